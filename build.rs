@@ -1,8 +1,21 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Compile the protocol buffer definitions
-    tonic_build::compile_protos("proto/node_service.proto")?;
+    // Compile the protocol buffer definitions. The descriptors of what the node
+    // serves are kept for server reflection.
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("node_descriptor.bin"))
+        .compile(&["proto/node_service.proto", "proto/health.proto", "proto/reflection.proto"], &["proto"])?;
+    tonic_build::compile_protos("proto/metrics_service.proto")?;
     
     println!("cargo:rerun-if-changed=proto/node_service.proto");
+    println!("cargo:rerun-if-changed=proto/health.proto");
+    println!("cargo:rerun-if-changed=proto/reflection.proto");
+    println!("cargo:rerun-if-changed=proto/metrics_service.proto");
+    // Release signing keys are embedded by src/updater/signature.rs
+    println!("cargo:rerun-if-env-changed=UPDATE_SIGNING_KEYS");
+    if std::env::var("PROFILE").as_deref() == Ok("release") && std::env::var_os("UPDATE_SIGNING_KEYS").is_none() {
+        println!("cargo:warning=UPDATE_SIGNING_KEYS is not set: this build will refuse every signed update");
+    }
     
     Ok(())
-} 
\ No newline at end of file
+} 
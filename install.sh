@@ -133,22 +133,6 @@ echo "Installing command-line tool..."
 cp ./node-monitor /usr/local/bin/
 chmod +x /usr/local/bin/node-monitor
 
-# Create default configuration
-echo "Creating default configuration..."
-cat > /Library/NodeController/config/config.json << EOF
-{
-  "log_level": "info",
-  "update_interval_seconds": 60,
-  "enable_performance_monitoring": true,
-  "enable_network_monitoring": true,
-  "enable_process_monitoring": true,
-  "max_log_size_mb": 100,
-  "max_log_files": 5,
-  "installation_date": "$(date -u +"%Y-%m-%dT%H:%M:%SZ")",
-  "installation_version": "0.1.0"
-}
-EOF
-
 # Get API configuration
 echo -e "${GREEN}Monitoring API Configuration${NC}"
 read -p "Enter the monitoring API endpoint [https://node-metrics.a14a.org]: " API_ENDPOINT
@@ -161,20 +145,25 @@ if [ -z "$API_KEY" ]; then
     exit 1
 fi
 
-# Create environment file with user update directory
-echo "Creating environment configuration..."
-cat > /Library/NodeController/config/.env << EOF
-# Monitoring API Configuration
-MONITORING_API_URL=${API_ENDPOINT}
-MONITORING_API_KEY=${API_KEY}
+# Create the configuration file with the user update directory
+echo "Creating configuration..."
+cat > /Library/NodeController/config/config.toml << EOF
+# Node Controller configuration, see config.example.toml for every setting
+# Installed $(date -u +"%Y-%m-%dT%H:%M:%SZ")
+
+[api]
+url = "${API_ENDPOINT}"
+key = "${API_KEY}"
 
-# Logging Configuration
-RUST_LOG=info
+[updater]
+dir = "${USER_UPDATE_DIR}"
+auto_update = true
 
-# Update Configuration
-UPDATE_DIR=$USER_UPDATE_DIR
-AUTO_UPDATE=true
+[logging]
+level = "info"
 EOF
+chmod 600 /Library/NodeController/config/config.toml
+cp ./config.example.toml /Library/NodeController/config/
 
 # Copy launch daemon
 echo "Installing launch daemon..."
@@ -210,7 +199,7 @@ fi
 echo -e "${GREEN}Installation complete!${NC}"
 echo "The Node Controller service has been installed and started."
 echo "Logs can be found in /Library/Logs/NodeController/"
-echo "Configuration is at /Library/NodeController/config/config.json"
+echo "Configuration is at /Library/NodeController/config/config.toml"
 echo "Updates will be stored in $USER_UPDATE_DIR"
 echo ""
 echo "You can now use the following commands:"
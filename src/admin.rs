@@ -0,0 +1,548 @@
+// src/admin.rs
+//
+// Local admin API
+// A small HTTP server for operators on the node itself. It listens on the
+// loopback interface (ADMIN_API_PORT) and/or on a Unix socket only the
+// agent's user can open (ADMIN_API_SOCKET), never on the network. Requests
+// whose Host or Origin header names another machine are refused, so neither
+// web pages nor names rebound to 127.0.0.1 reach it. Responses are JSON:
+//   GET  /status         everything below at once
+//   GET  /metrics        latest reading of each collector
+//   GET  /update         update state of the agent
+//   GET  /peers          nodes found by discovery and their membership state
+//   GET  /wake           nodes that can be woken, asleep or not
+//   GET  /transfers      file transfers in progress
+//   POST /transfers/<id>/pause, /resume or /cancel
+//   GET  /spool          delivery counters and offline spool depth
+//   GET  /content/<hash> received file with that SHA256 hash
+//   POST /update/check   queue an update check
+//   POST /spool/flush    replay the offline spool now
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::agent::LatestMetrics;
+use crate::api::ApiClient;
+use crate::networking::file_transfer::ProgressCallback;
+use crate::networking::{FileTransferManager, NodeDiscovery, TransferStatus, WakeTable};
+use crate::updater::UpdateHandle;
+
+/// What the admin API reports on and acts on
+pub struct AdminState {
+    pub node_id: String,
+    pub node_name: String,
+    pub metrics: watch::Receiver<LatestMetrics>,
+    pub updates: UpdateHandle,
+    pub discovery: Option<Arc<NodeDiscovery>>,
+    pub wake: WakeTable,
+    pub transfers: ActiveTransfers,
+    pub file_transfers: Option<Arc<FileTransferManager>>,
+    pub api_client: Option<Arc<ApiClient>>,
+}
+
+/// A file transfer in progress
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveTransfer {
+    pub file_id: String,
+    pub file_name: String,
+    pub bytes_transferred: u64,
+    pub total_bytes: u64,
+    pub percent_complete: f32,
+    pub paused: bool,
+    pub started_at: DateTime<Utc>,
+}
+
+/// File transfers in progress, kept from the transfer progress callbacks
+#[derive(Clone, Default)]
+pub struct ActiveTransfers {
+    transfers: Arc<Mutex<BTreeMap<String, ActiveTransfer>>>,
+}
+
+impl ActiveTransfers {
+    /// A progress callback recording into this list
+    pub fn callback(&self) -> ProgressCallback {
+        let transfers = self.clone();
+        Arc::new(move |status| transfers.record(status))
+    }
+
+    fn record(&self, status: TransferStatus) {
+        let mut transfers = self.transfers.lock().unwrap();
+        match status {
+            TransferStatus::Started { file_id, file_name, file_size } => {
+                let transfer = entry(&mut transfers, file_id);
+                transfer.file_name = file_name;
+                transfer.total_bytes = file_size;
+            }
+            TransferStatus::Progress { file_id, bytes_transferred, total_bytes, percent_complete } => {
+                let transfer = entry(&mut transfers, file_id);
+                transfer.bytes_transferred = bytes_transferred;
+                transfer.total_bytes = total_bytes;
+                transfer.percent_complete = percent_complete;
+            }
+            TransferStatus::Completed { file_id, .. }
+            | TransferStatus::Failed { file_id, .. }
+            | TransferStatus::Cancelled { file_id } => {
+                transfers.remove(&file_id);
+            }
+        }
+    }
+
+    fn set_paused(&self, file_id: &str, paused: bool) {
+        if let Some(transfer) = self.transfers.lock().unwrap().get_mut(file_id) {
+            transfer.paused = paused;
+        }
+    }
+
+    /// Transfers in progress, oldest first
+    pub fn list(&self) -> Vec<ActiveTransfer> {
+        let mut transfers: Vec<_> = self.transfers.lock().unwrap().values().cloned().collect();
+        transfers.sort_by_key(|transfer| transfer.started_at);
+        transfers
+    }
+}
+
+/// The transfer `file_id`, added when progress arrives before its start
+fn entry(transfers: &mut BTreeMap<String, ActiveTransfer>, file_id: String) -> &mut ActiveTransfer {
+    transfers.entry(file_id.clone()).or_insert_with(|| ActiveTransfer {
+        file_id,
+        file_name: String::new(),
+        bytes_transferred: 0,
+        total_bytes: 0,
+        percent_complete: 0.0,
+        paused: false,
+        started_at: Utc::now(),
+    })
+}
+
+/// The admin API listeners; stopped with [`AdminServer::stop`]
+pub struct AdminServer {
+    tasks: Vec<JoinHandle<()>>,
+    socket: Option<PathBuf>,
+}
+
+impl AdminServer {
+    /// Serve `state` on 127.0.0.1:`port` and/or the Unix socket at `socket`
+    pub fn bind(port: Option<u16>, socket: Option<PathBuf>, state: AdminState) -> Result<Self> {
+        let state = Arc::new(state);
+        let mut tasks = Vec::new();
+        if let Some(port) = port {
+            let addr = SocketAddr::from(([127, 0, 0, 1], port));
+            let listener = std::net::TcpListener::bind(addr)
+                .with_context(|| format!("Failed to bind {}", addr))?;
+            listener.set_nonblocking(true)?;
+            let listener = TcpListener::from_std(listener)?;
+            info!("Serving the admin API on http://{}", addr);
+            let state = state.clone();
+            tasks.push(tokio::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, _)) => {
+                            tokio::spawn(respond_logged(stream, state.clone()));
+                        }
+                        Err(e) => debug!("Admin API accept failed: {}", e),
+                    }
+                }
+            }));
+        }
+        #[cfg(unix)]
+        if let Some(path) = &socket {
+            let listener = bind_private_socket(path)?;
+            info!("Serving the admin API on {}", path.display());
+            tasks.push(tokio::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, _)) => {
+                            tokio::spawn(respond_logged(stream, state.clone()));
+                        }
+                        Err(e) => debug!("Admin API accept failed: {}", e),
+                    }
+                }
+            }));
+        }
+        Ok(Self { tasks, socket })
+    }
+
+    /// Stop listening and remove the Unix socket
+    pub fn stop(self) {
+        for task in self.tasks {
+            task.abort();
+        }
+        if let Some(path) = self.socket {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Bind a Unix socket at `path` that only this user can connect to. The socket
+/// is made in a 0700 directory next to `path` and renamed into place once it is
+/// 0600, so it is never reachable with looser permissions.
+#[cfg(unix)]
+fn bind_private_socket(path: &std::path::Path) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    // A socket left by an earlier run would fail the bind; anything else at
+    // the path is not ours to delete
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            anyhow::bail!("{} exists and is not a socket", path.display());
+        }
+        std::fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+    let private = parent.join(format!(".admin-socket-{}", std::process::id()));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&private)
+        .with_context(|| format!("Failed to create {}", private.display()))?;
+    let staged = private.join("admin.sock");
+    let bound = tokio::net::UnixListener::bind(&staged)
+        .with_context(|| format!("Failed to bind {}", path.display()))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+            std::fs::rename(&staged, path).with_context(|| format!("Failed to move the socket to {}", path.display()))?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&private);
+    bound
+}
+
+async fn respond_logged<S: AsyncRead + AsyncWrite + Unpin>(stream: S, state: Arc<AdminState>) {
+    if let Err(e) = respond(stream, &state).await {
+        debug!("Admin API request failed: {}", e);
+    }
+}
+
+async fn respond<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, state: &AdminState) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut lines = request.lines();
+    let mut parts = lines.next().unwrap_or_default().split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+    let (status, body) = match foreign_header(lines) {
+        Some(header) => ("403 Forbidden", json!({ "error": format!("{} is not this machine", header) })),
+        None => route(method, path, state).await,
+    };
+    let body = format!("{}\n", serde_json::to_string_pretty(&body)?);
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, body.len(), body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// The Host or Origin header of a request that names another machine: a web
+/// page's request (Origin) or one sent to a name rebound to 127.0.0.1 (Host)
+fn foreign_header<'a>(headers: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    headers
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find_map(|(name, value)| {
+            let value = value.trim();
+            let authority = if name.eq_ignore_ascii_case("host") {
+                value
+            } else if name.eq_ignore_ascii_case("origin") {
+                value.split_once("://").map_or("", |(_, rest)| rest.split('/').next().unwrap_or_default())
+            } else {
+                return None;
+            };
+            (!is_loopback(authority)).then_some(value)
+        })
+}
+
+/// Whether `authority`, a host with or without a port, is the loopback interface
+fn is_loopback(authority: &str) -> bool {
+    let host = match authority.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    host.eq_ignore_ascii_case("localhost") || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+const GET_PATHS: &[&str] = &["/status", "/metrics", "/update", "/peers", "/wake", "/transfers", "/spool"];
+const POST_PATHS: &[&str] = &["/update/check", "/spool/flush"];
+
+async fn route(method: &str, path: &str, state: &AdminState) -> (&'static str, Value) {
+    if let Some((file_id, action)) = path.strip_prefix("/transfers/").and_then(|rest| rest.split_once('/')) {
+        return match method {
+            "POST" => control_transfer(state, file_id, action),
+            _ => ("405 Method Not Allowed", json!({ "error": format!("{} is not allowed on {}", method, path) })),
+        };
+    }
+    if let Some(hash) = path.strip_prefix("/content/") {
+        return match method {
+            "GET" => content(state, hash).await,
+            _ => ("405 Method Not Allowed", json!({ "error": format!("{} is not allowed on {}", method, path) })),
+        };
+    }
+    match (method, path) {
+        ("GET", "/status") => ("200 OK", json!({
+            "node_id": state.node_id,
+            "node_name": state.node_name,
+            "metrics": metrics(state),
+            "update": update(state).await,
+            "peers": peers(state),
+            "transfers": state.transfers.list(),
+            "spool": spool(state),
+        })),
+        ("GET", "/metrics") => ("200 OK", metrics(state)),
+        ("GET", "/update") => ("200 OK", update(state).await),
+        ("GET", "/peers") => ("200 OK", peers(state)),
+        ("GET", "/wake") => ("200 OK", json!(state.wake.targets())),
+        ("GET", "/transfers") => ("200 OK", json!(state.transfers.list())),
+        ("GET", "/spool") => ("200 OK", spool(state)),
+        ("POST", "/update/check") => match state.updates.check_for_updates().await {
+            Ok(()) => ("202 Accepted", json!({ "queued": true })),
+            Err(e) => ("500 Internal Server Error", json!({ "error": e.to_string() })),
+        },
+        ("POST", "/spool/flush") => match &state.api_client {
+            Some(client) => match client.flush_spool().await {
+                Ok(remaining) => ("200 OK", json!({ "remaining": remaining })),
+                Err(e) => ("502 Bad Gateway", json!({ "error": e.to_string() })),
+            },
+            None => ("503 Service Unavailable", json!({ "error": "The monitoring API is not configured" })),
+        },
+        (_, path) if GET_PATHS.contains(&path) || POST_PATHS.contains(&path) => {
+            ("405 Method Not Allowed", json!({ "error": format!("{} is not allowed on {}", method, path) }))
+        }
+        _ => ("404 Not Found", json!({ "error": format!("No endpoint {}", path) })),
+    }
+}
+
+/// Pause, resume or cancel a transfer of the file transfer server
+fn control_transfer(state: &AdminState, file_id: &str, action: &str) -> (&'static str, Value) {
+    let Some(transfers) = &state.file_transfers else {
+        return ("503 Service Unavailable", json!({ "error": "The file transfer server is not running" }));
+    };
+    let result = match action {
+        "pause" => transfers.pause_transfer(file_id).map(|_| state.transfers.set_paused(file_id, true)),
+        "resume" => transfers.resume_transfer(file_id).map(|_| state.transfers.set_paused(file_id, false)),
+        "cancel" => transfers.cancel_transfer(file_id),
+        _ => return ("404 Not Found", json!({ "error": format!("No transfer action {}", action) })),
+    };
+    match result {
+        Ok(()) => ("200 OK", json!({ "file_id": file_id, "action": action })),
+        Err(e) => ("409 Conflict", json!({ "error": e.to_string() })),
+    }
+}
+
+/// The received file with SHA256 `hash`
+async fn content(state: &AdminState, hash: &str) -> (&'static str, Value) {
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return ("400 Bad Request", json!({ "error": "Expected a hex SHA256 hash" }));
+    }
+    let Some(transfers) = state.file_transfers.clone() else {
+        return ("503 Service Unavailable", json!({ "error": "The file transfer server is not running" }));
+    };
+    // Lookups hash the file again
+    let hash = hash.to_ascii_lowercase();
+    let found = {
+        let hash = hash.clone();
+        tokio::task::spawn_blocking(move || transfers.find_by_hash(&hash)).await.ok().flatten()
+    };
+    match found {
+        Some(path) => {
+            let size = std::fs::metadata(&path).map(|metadata| metadata.len()).ok();
+            ("200 OK", json!({ "sha256": hash, "path": path, "size": size }))
+        }
+        None => ("404 Not Found", json!({ "error": format!("No received file with hash {}", hash) })),
+    }
+}
+
+fn metrics(state: &AdminState) -> Value {
+    json!(*state.metrics.borrow())
+}
+
+async fn update(state: &AdminState) -> Value {
+    let status = state.updates.status().await;
+    json!({
+        "current_version": state.updates.current_version().to_string(),
+        "state": status.state(),
+        "version": status.version(),
+        "detail": status.detail(),
+    })
+}
+
+fn peers(state: &AdminState) -> Value {
+    match &state.discovery {
+        Some(discovery) => {
+            // Each node with what membership believes about it: alive, suspect or dead
+            let nodes: Vec<Value> = discovery.member_states().into_iter().map(|(node, member_state)| {
+                let mut node = json!(node);
+                node["state"] = json!(member_state);
+                node
+            }).collect();
+            json!({ "discovery": true, "nodes": nodes })
+        }
+        None => json!({ "discovery": false, "nodes": [] }),
+    }
+}
+
+fn spool(state: &AdminState) -> Value {
+    match &state.api_client {
+        Some(client) => json!(client.delivery_stats()),
+        None => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::FileTransferConfig;
+    use crate::updater::{UpdateConfig, UpdateManager};
+
+    fn state() -> AdminState {
+        let manager = UpdateManager::new(UpdateConfig::default(), "1.2.3".parse().unwrap());
+        let mut latest = LatestMetrics::new();
+        latest.insert("cpu", json!({ "current_load": 12.5 }));
+        AdminState {
+            node_id: "node-1".to_string(),
+            node_name: "render-1".to_string(),
+            metrics: watch::channel(latest).1,
+            updates: manager.handle(),
+            discovery: None,
+            wake: WakeTable::default(),
+            transfers: ActiveTransfers::default(),
+            file_transfers: None,
+            api_client: None,
+        }
+    }
+
+    async fn request(port: u16, request: &str) -> String {
+        let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[test]
+    fn test_active_transfers() {
+        let transfers = ActiveTransfers::default();
+        let callback = transfers.callback();
+        callback(TransferStatus::Started { file_id: "a".into(), file_name: "a.mov".into(), file_size: 100 });
+        callback(TransferStatus::Progress { file_id: "a".into(), bytes_transferred: 50, total_bytes: 100, percent_complete: 50.0 });
+        callback(TransferStatus::Started { file_id: "b".into(), file_name: "b.mov".into(), file_size: 10 });
+        let list = transfers.list();
+        assert_eq!(list.len(), 2);
+        assert_eq!((list[0].file_name.as_str(), list[0].bytes_transferred), ("a.mov", 50));
+
+        callback(TransferStatus::Failed { file_id: "a".into(), error: "reset".into() });
+        callback(TransferStatus::Cancelled { file_id: "b".into() });
+        assert!(transfers.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_control_transfer() {
+        let mut state = state();
+        assert_eq!(route("POST", "/transfers/t1/pause", &state).await.0, "503 Service Unavailable");
+        state.file_transfers = Some(Arc::new(FileTransferManager::new(FileTransferConfig::default())));
+        assert_eq!(route("POST", "/transfers/t1/pause", &state).await.0, "409 Conflict");
+        assert_eq!(route("POST", "/transfers/t1/rewind", &state).await.0, "404 Not Found");
+        assert_eq!(route("GET", "/transfers/t1/pause", &state).await.0, "405 Method Not Allowed");
+    }
+
+    #[tokio::test]
+    async fn test_content_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = state();
+        let config = FileTransferConfig { receive_dir: dir.path().to_path_buf(), ..FileTransferConfig::default() };
+        state.file_transfers = Some(Arc::new(FileTransferManager::new(config)));
+        assert_eq!(route("GET", "/content/xyz", &state).await.0, "400 Bad Request");
+        let hash = "0".repeat(64);
+        assert_eq!(route("GET", &format!("/content/{}", hash), &state).await.0, "404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let server = AdminServer::bind(Some(port), None, state()).unwrap();
+
+        let response = request(port, "GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let body: Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["node_name"], "render-1");
+        assert_eq!(body["metrics"]["cpu"]["current_load"], 12.5);
+        assert_eq!(body["update"]["current_version"], "1.2.3");
+        assert_eq!(body["peers"]["discovery"], false);
+        assert_eq!(body["spool"], Value::Null);
+
+        let response = request(port, "GET /wake HTTP/1.1\r\n\r\n").await;
+        assert!(response.ends_with("\r\n\r\n[]\n"), "{}", response);
+        let response = request(port, "POST /spool/flush HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 503"));
+        let response = request(port, "POST /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 405"));
+        let response = request(port, "GET /nothing HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+
+        // Requests of web pages and to rebound names
+        let response = request(port, "POST /update/check HTTP/1.1\r\nHost: 127.0.0.1\r\nOrigin: https://evil.example\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+        let response = request(port, "GET /status HTTP/1.1\r\nHost: rebound.example:9180\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+        let response = request(port, "GET /update HTTP/1.1\r\nHost: [::1]:9180\r\nOrigin: http://localhost:9180\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        server.stop();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("admin.sock");
+        let server = AdminServer::bind(None, Some(path.clone()), state()).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        stream.write_all(b"GET /update HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("\"state\": \"idle\""));
+
+        server.stop();
+        assert!(!path.exists());
+
+        // A stale socket is replaced, anything else is left alone
+        let stale = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        drop(stale);
+        let server = AdminServer::bind(None, Some(path.clone()), state()).unwrap();
+        tokio::net::UnixStream::connect(&path).await.unwrap();
+        server.stop();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        std::fs::write(&path, "not a socket").unwrap();
+        let err = AdminServer::bind(None, Some(path.clone()), state()).err().unwrap();
+        assert!(err.to_string().contains("is not a socket"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+    }
+}
@@ -0,0 +1,531 @@
+// src/agent.rs
+//
+// Collection tasks
+// Each collector runs in a task of its own, on its own interval, so a slow
+// one (a storage scan, a CPU sample waiting on powermetrics) doesn't hold up
+// the others. What they collect goes over a channel to the aggregator task,
+// which prints it, raises threshold alerts and events, keeps the metrics
+// summary peers ask for current, and on the server interval sends everything
+// collected since the last server update along with the system info.
+// Intervals, enabled collectors and thresholds follow remote configuration
+// through watch channels, and collectors are recreated on request. The latest
+// reading of every collector is kept for the local admin API.
+
+use anyhow::{anyhow, Result};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+use crate::api::events::peripheral_changes;
+use crate::api::remote_config::AlertThresholds;
+use crate::api::{MetricsBatch, MetricsSinks};
+use crate::metrics::cpu::types::CpuMetrics;
+use crate::metrics::network::types::NetworkSnapshot;
+use crate::metrics::storage::types::StorageMetrics;
+use crate::metrics::system::types::PeripheralDevice;
+use crate::metrics::{AgentCollector, SystemCollectorConfig, SystemInfoCollector};
+use crate::networking::MetricsSummary;
+
+/// Collected metrics waiting for the aggregator, per collector
+const COLLECTED_BACKLOG: usize = 16;
+
+pub fn print_separator() {
+    println!("\n{}\n", "-".repeat(80));
+}
+
+/// How often a collector runs, and whether it does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    pub interval: Duration,
+    pub enabled: bool,
+}
+
+/// Request to recreate a collector, answered once it is
+type RestartRequest = oneshot::Sender<Result<()>>;
+
+/// A collector running in its own task
+pub struct CollectorTask {
+    schedule: watch::Sender<Schedule>,
+    restarts: mpsc::Sender<RestartRequest>,
+    task: JoinHandle<()>,
+}
+
+impl CollectorTask {
+    /// Run on `schedule` from the next collection on
+    pub fn set_schedule(&self, schedule: Schedule) {
+        self.schedule.send_if_modified(|current| std::mem::replace(current, schedule) != schedule);
+    }
+
+    /// Recreate the collector, dropping its accumulated state
+    pub async fn restart(&self) -> Result<()> {
+        let (reply, outcome) = oneshot::channel();
+        self.restarts.send(reply).await.map_err(|_| anyhow!("Collector task stopped"))?;
+        outcome.await.map_err(|_| anyhow!("Collector task stopped"))?
+    }
+
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+}
+
+/// Run `collector` in a task, sending what `collect` returns to `collected`
+/// every `schedule.interval` while it is enabled; restarts replace it with
+/// what `create` makes. Collectors block, so they run with `block_in_place`
+/// on the multi-threaded runtime.
+pub fn spawn_collector<C, T, F, G>(
+    name: &'static str,
+    mut collector: C,
+    create: F,
+    collect: G,
+    schedule: Schedule,
+    collected: mpsc::Sender<T>,
+) -> CollectorTask
+where
+    C: Send + 'static,
+    T: Send + 'static,
+    F: Fn() -> Result<C> + Send + 'static,
+    G: Fn(&mut C) -> Result<T> + Send + 'static,
+{
+    let (schedule_tx, mut schedules) = watch::channel(schedule);
+    let (restarts_tx, mut restarts) = mpsc::channel::<RestartRequest>(1);
+    let task = tokio::spawn(async move {
+        let mut ticker = every(schedule.interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if !schedules.borrow().enabled {
+                        continue;
+                    }
+                    info!("{} collection interval reached", name);
+                    match tokio::task::block_in_place(|| collect(&mut collector)) {
+                        Ok(metrics) => {
+                            if collected.send(metrics).await.is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => error!("Failed to collect {} metrics: {}", name, e),
+                    }
+                }
+                Ok(()) = schedules.changed() => {
+                    let interval = schedules.borrow_and_update().interval;
+                    if interval != ticker.period() {
+                        ticker = every(interval);
+                    }
+                }
+                Some(reply) = restarts.recv() => {
+                    let _ = reply.send(create().map(|created| collector = created));
+                }
+            }
+        }
+    });
+    CollectorTask { schedule: schedule_tx, restarts: restarts_tx, task }
+}
+
+/// Ticks every `period`, the first one a period from now
+fn every(period: Duration) -> Interval {
+    let mut ticker = tokio::time::interval_at(Instant::now() + period, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ticker
+}
+
+/// Metrics a collector task sends the aggregator
+pub enum Collected {
+    Cpu(CpuMetrics),
+    Network(NetworkSnapshot),
+    Storage(StorageMetrics),
+}
+
+/// Requests to the aggregator, besides collected metrics
+pub enum AggregatorRequest {
+    /// Send the full system info with the next server update
+    FullSystemInfo,
+    /// Recreate the system info or agent collector
+    Restart(String, RestartRequest),
+}
+
+/// Settings of the aggregator that follow remote configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregatorSettings {
+    pub server_interval: Duration,
+    pub thresholds: AlertThresholds,
+}
+
+/// The latest reading of each collector (cpu, network, storage, system, agent)
+pub type LatestMetrics = BTreeMap<&'static str, Value>;
+
+/// Gathers what the collector tasks send and hands it to the sinks
+pub struct Aggregator {
+    sinks: Arc<MetricsSinks>,
+    metrics_summary: watch::Sender<MetricsSummary>,
+    latest: watch::Sender<LatestMetrics>,
+    /// Where free disk space in the summary is measured
+    data_dir: PathBuf,
+    system_config: SystemCollectorConfig,
+    system_collector: SystemInfoCollector,
+    agent_collector: AgentCollector,
+    pending_cpu_metrics: Option<CpuMetrics>,
+    pending_network_metrics: Option<NetworkSnapshot>,
+    pending_storage_metrics: Option<StorageMetrics>,
+    pending_system_changes: Vec<String>,
+    known_peripherals: Option<Vec<PeripheralDevice>>,
+}
+
+/// The running aggregator task and its inputs
+pub struct AggregatorTask {
+    pub collected: mpsc::Sender<Collected>,
+    pub requests: mpsc::Sender<AggregatorRequest>,
+    pub settings: watch::Sender<AggregatorSettings>,
+    pub latest: watch::Receiver<LatestMetrics>,
+    task: JoinHandle<()>,
+}
+
+impl AggregatorTask {
+    /// Let the aggregator finish what it is doing and wait for it to stop
+    pub async fn stop(self, timeout: Duration) {
+        drop(self.requests);
+        if tokio::time::timeout(timeout, self.task).await.is_err() {
+            warn!("Metrics aggregator did not stop in time");
+        }
+    }
+}
+
+impl Aggregator {
+    pub fn new(
+        sinks: Arc<MetricsSinks>,
+        metrics_summary: watch::Sender<MetricsSummary>,
+        data_dir: PathBuf,
+        system_collector: SystemInfoCollector,
+        system_config: SystemCollectorConfig,
+    ) -> Self {
+        Self {
+            sinks,
+            metrics_summary,
+            latest: watch::Sender::new(LatestMetrics::new()),
+            data_dir,
+            system_config,
+            system_collector,
+            agent_collector: AgentCollector::new(),
+            pending_cpu_metrics: None,
+            pending_network_metrics: None,
+            pending_storage_metrics: None,
+            pending_system_changes: Vec::new(),
+            known_peripherals: None,
+        }
+    }
+
+    /// Aggregate in a task until its requests sender is dropped
+    pub fn spawn(mut self, settings: AggregatorSettings) -> AggregatorTask {
+        let (collected_tx, mut collected) = mpsc::channel(COLLECTED_BACKLOG);
+        let (requests_tx, mut requests) = mpsc::channel(8);
+        let (settings_tx, mut settings_rx) = watch::channel(settings);
+        let latest = self.latest.subscribe();
+        let task = tokio::spawn(async move {
+            let mut ticker = every(settings_rx.borrow().server_interval);
+            loop {
+                tokio::select! {
+                    Some(metrics) = collected.recv() => {
+                        let thresholds = settings_rx.borrow().thresholds.clone();
+                        self.receive(metrics, &thresholds);
+                    }
+                    request = requests.recv() => match request {
+                        Some(AggregatorRequest::FullSystemInfo) => self.system_collector.request_full_update(),
+                        Some(AggregatorRequest::Restart(collector, reply)) => {
+                            let _ = reply.send(self.restart(&collector));
+                        }
+                        None => break,
+                    },
+                    _ = ticker.tick() => self.server_update().await,
+                    Ok(()) = settings_rx.changed() => {
+                        let interval = settings_rx.borrow_and_update().server_interval;
+                        if interval != ticker.period() {
+                            ticker = every(interval);
+                        }
+                    }
+                }
+            }
+        });
+        AggregatorTask { collected: collected_tx, requests: requests_tx, settings: settings_tx, latest, task }
+    }
+
+    fn restart(&mut self, collector: &str) -> Result<()> {
+        match collector {
+            "system" => self.system_collector = SystemInfoCollector::new(self.system_config.clone()),
+            "agent" => self.agent_collector = AgentCollector::new(),
+            other => return Err(anyhow!("Unknown collector '{}'", other)),
+        }
+        Ok(())
+    }
+
+    /// Print and check what a collector sent, keeping it for the next server update
+    fn receive(&mut self, collected: Collected, thresholds: &AlertThresholds) {
+        match collected {
+            Collected::Cpu(metrics) => {
+                // Print summary
+                println!("CPU Usage: {:.1}% (User: {:.1}%, System: {:.1}%)",
+                    metrics.current_load,
+                    metrics.user_load,
+                    metrics.system_load
+                );
+                println!("Temperature: {:.1}°C (Max: {:.1}°C)",
+                    metrics.temperature_main,
+                    metrics.temperature_max
+                );
+                if let Some(apple_data) = &metrics.apple_silicon_data {
+                    println!("Power: {:.2}W (CPU: {:.2}W, GPU: {:.2}W)",
+                        apple_data.power.package_watts,
+                        apple_data.power.cpu_watts,
+                        apple_data.power.gpu_watts
+                    );
+                }
+                if let Some(sched) = &metrics.scheduler {
+                    match (sched.context_switches_per_sec, sched.interrupts_per_sec) {
+                        (Some(csw), Some(intr)) => println!("Run queue: {} (Context switches: {:.0}/s, Interrupts: {:.0}/s)",
+                            sched.run_queue, csw, intr),
+                        _ => println!("Run queue: {}", sched.run_queue),
+                    }
+                }
+                for alert in thresholds.check_cpu(&metrics) {
+                    warn!("Threshold exceeded: {}", alert);
+                    self.sinks.stream_event("alert", &alert);
+                }
+                self.metrics_summary.send_modify(|summary| summary.record_cpu(&metrics));
+                self.record_latest("cpu", &metrics);
+                self.pending_cpu_metrics = Some(metrics);
+                info!("CPU metrics collected successfully");
+            }
+            Collected::Network(mut metrics) => {
+                print_separator();
+                println!("Network Interfaces:");
+                for metric in &metrics.interfaces {
+                    println!("{}", metric);
+                }
+                if !metrics.dns.is_empty() {
+                    println!("\nDNS:");
+                    for probe in &metrics.dns {
+                        println!("{}", probe);
+                    }
+                }
+                if !metrics.http_checks.is_empty() {
+                    println!("\nHTTP Checks:");
+                    for check in &metrics.http_checks {
+                        println!("{}", check);
+                    }
+                }
+                if let Some(gateway) = &metrics.default_gateway {
+                    println!("\nDefault Gateway: {}", gateway);
+                }
+                if metrics.vpn.active || !metrics.vpn.clients.is_empty() {
+                    println!("VPN: {}", metrics.vpn);
+                }
+                if let Some(proxy) = &metrics.proxy {
+                    println!("Proxy: {}", proxy);
+                }
+                if let Some(public_ip) = &metrics.public_ip {
+                    println!("Public IP: {}", public_ip.address);
+                }
+                for event in &metrics.link_events {
+                    warn!("Network link change: {}", event);
+                    self.sinks.stream_event("link", event);
+                }
+                for event in &metrics.route_events {
+                    warn!("Routing change: {}", event);
+                    self.sinks.stream_event("route", event);
+                }
+
+                // Keep events that have not been sent yet
+                if let Some(previous) = self.pending_network_metrics.take() {
+                    metrics.link_events.splice(0..0, previous.link_events);
+                    metrics.route_events.splice(0..0, previous.route_events);
+                    metrics.public_ip_changes.splice(0..0, previous.public_ip_changes);
+                }
+                self.metrics_summary.send_modify(|summary| summary.record_network(&metrics));
+                self.record_latest("network", &metrics);
+                self.pending_network_metrics = Some(metrics);
+                info!("Network metrics collected successfully");
+            }
+            Collected::Storage(mut metrics) => {
+                print_separator();
+                println!("Storage:");
+                println!("\nFilesystems:");
+                for fs in &metrics.filesystem_metrics {
+                    println!("{}", fs);
+                }
+                println!("\nDisk I/O:");
+                println!("{}", metrics.io_metrics);
+                for disk in &metrics.io_metrics.devices {
+                    println!("  {}", disk);
+                }
+                if let Some(backup) = &metrics.backup {
+                    println!("{}", backup);
+                }
+                for event in &metrics.mount_events {
+                    warn!("Storage change: {}", event);
+                    self.sinks.stream_event("mount", event);
+                }
+
+                for alert in thresholds.check_storage(&metrics) {
+                    warn!("Threshold exceeded: {}", alert);
+                    self.sinks.stream_event("alert", &alert);
+                }
+
+                // Keep mount events that have not been sent yet
+                if let Some(previous) = self.pending_storage_metrics.take() {
+                    metrics.mount_events.splice(0..0, previous.mount_events);
+                }
+                let data_dir = &self.data_dir;
+                self.metrics_summary.send_modify(|summary| summary.record_storage(&metrics, data_dir));
+                self.record_latest("storage", &metrics);
+                self.pending_storage_metrics = Some(metrics);
+                info!("Storage metrics collected successfully");
+            }
+        }
+    }
+
+    /// Keep `metrics` as the latest reading of `collector`
+    fn record_latest<T: Serialize>(&self, collector: &'static str, metrics: &T) {
+        match serde_json::to_value(metrics) {
+            Ok(value) => {
+                self.latest.send_modify(|latest| {
+                    latest.insert(collector, value);
+                });
+            }
+            Err(err) => debug!("Failed to keep the latest {} metrics: {}", collector, err),
+        }
+    }
+
+    /// Collect the system info and send it with everything pending
+    async fn server_update(&mut self) {
+        info!("Server update interval reached");
+
+        // Collect latest system info and check for changes
+        let system_info = match tokio::task::block_in_place(|| self.system_collector.collect()) {
+            Ok(system_info) => system_info,
+            Err(err) => {
+                error!("Failed to collect system info for server update: {}", err);
+                return;
+            }
+        };
+        info!("System info collected successfully for server update");
+        self.metrics_summary.send_modify(|summary| summary.record_system(&system_info));
+        self.record_latest("system", &system_info);
+        for event in &system_info.power_events {
+            warn!("Power change: {}", event);
+            self.sinks.stream_event("power", event);
+        }
+        for event in &system_info.display_events {
+            info!("{}", event);
+            self.sinks.stream_event("display", event);
+        }
+        if let Some(adapter) = system_info.power.adapter.as_ref().filter(|a| a.undersized) {
+            warn!("Power adapter ({}W) is undersized for current draw of {:.1}W",
+                  adapter.watts.unwrap_or_default(),
+                  adapter.system_power_watts.unwrap_or_default());
+        }
+
+        let changed = |field: &str| system_info.last_update.changed_fields.iter().any(|f| f == field);
+        if self.known_peripherals.is_none() || changed("peripherals") {
+            if let Some(previous) = &self.known_peripherals {
+                let (added, removed) = peripheral_changes(previous, &system_info.peripherals);
+                for device in added {
+                    info!("Peripheral added: {} ({})", device.name, device.connection_type);
+                    self.sinks.stream_event("peripheral_added", device);
+                }
+                for device in removed {
+                    info!("Peripheral removed: {} ({})", device.name, device.connection_type);
+                    self.sinks.stream_event("peripheral_removed", device);
+                }
+            }
+            self.known_peripherals = Some(system_info.peripherals.clone());
+        }
+
+        // If there are changes, add them to pending updates
+        if !system_info.last_update.changed_fields.is_empty() {
+            info!("System changes detected: {:?}", system_info.last_update.changed_fields);
+            self.pending_system_changes = system_info.last_update.changed_fields.clone();
+        } else {
+            info!("No system changes detected");
+        }
+
+        // Sample our own resource usage, including what is still waiting to be sent
+        let pending_network_events = self.pending_network_metrics.as_ref().map_or(0, |n| {
+            n.link_events.len() + n.route_events.len() + n.public_ip_changes.len()
+        });
+        let pending_storage_events = self.pending_storage_metrics.as_ref().map_or(0, |s| s.mount_events.len());
+        let agent_metrics = self.agent_collector.collect(&[
+            ("network_events", pending_network_events),
+            ("storage_events", pending_storage_events),
+            ("system_changes", self.pending_system_changes.len()),
+        ]);
+        debug!("Agent: rss={}MB cpu={:.1}% fds={:?} tasks={:?}",
+               agent_metrics.rss_bytes / 1024 / 1024,
+               agent_metrics.cpu_percent,
+               agent_metrics.open_fds,
+               agent_metrics.tokio_tasks);
+        self.record_latest("agent", &agent_metrics);
+
+        self.sinks.send(&MetricsBatch {
+            system: &system_info,
+            cpu: self.pending_cpu_metrics.as_ref(),
+            network: self.pending_network_metrics.as_ref(),
+            storage: self.pending_storage_metrics.as_ref(),
+            agent: Some(&agent_metrics),
+            system_changes: &self.pending_system_changes,
+        }).await;
+
+        // Clear pending updates
+        self.pending_cpu_metrics = None;
+        self.pending_network_metrics = None;
+        self.pending_storage_metrics = None;
+        self.pending_system_changes.clear();
+        info!("Server update completed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    async fn next(received: &mut mpsc::Receiver<u32>) -> Result<Option<u32>> {
+        Ok(tokio::time::timeout(Duration::from_secs(5), received.recv()).await?)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_collector_follows_its_schedule() -> Result<()> {
+        let created = Arc::new(AtomicU32::new(0));
+        let (collected, mut received) = mpsc::channel(16);
+        let counter = created.clone();
+        let task = spawn_collector(
+            "test",
+            created.fetch_add(1, Ordering::SeqCst),
+            move || Ok(counter.fetch_add(1, Ordering::SeqCst)),
+            |generation: &mut u32| Ok(*generation),
+            Schedule { interval: Duration::from_millis(100), enabled: true },
+            collected,
+        );
+        // Nothing before the first interval has passed
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(received.try_recv().is_err());
+        assert_eq!(next(&mut received).await?, Some(0));
+
+        // A restart recreates the collector
+        task.restart().await?;
+        while next(&mut received).await? != Some(1) {}
+
+        // Disabled, it stops collecting
+        task.set_schedule(Schedule { interval: Duration::from_millis(100), enabled: false });
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        while received.try_recv().is_ok() {}
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(received.try_recv().is_err());
+        task.stop();
+        Ok(())
+    }
+}
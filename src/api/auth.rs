@@ -0,0 +1,143 @@
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use log::{debug, info};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use crate::proxy;
+
+/// Refresh tokens this long before they expire
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// Assumed lifetime when neither `expires_in` nor a JWT `exp` claim is available
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(300);
+
+/// OAuth2 client credentials grant settings
+#[derive(Debug, Clone)]
+pub struct OAuthConfig {
+    pub token_url: Url,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+    pub audience: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+struct CachedToken {
+    access_token: String,
+    expires_at: Instant,
+}
+
+/// Fetches bearer tokens with the client credentials grant and refreshes them
+/// shortly before they expire.
+pub struct TokenProvider {
+    client: Client,
+    config: OAuthConfig,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+impl TokenProvider {
+    pub fn new(config: OAuthConfig) -> Result<Self> {
+        let client = proxy::client_builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to create HTTP client")?;
+        Ok(Self { client, config, cached: Mutex::new(None) })
+    }
+
+    /// A valid access token, fetching a new one if needed
+    pub async fn token(&self) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref() {
+            if Instant::now() + REFRESH_MARGIN < token.expires_at {
+                return Ok(token.access_token.clone());
+            }
+            debug!("Access token expires soon, refreshing");
+        }
+
+        let token = self.fetch().await?;
+        let access_token = token.access_token.clone();
+        *cached = Some(token);
+        Ok(access_token)
+    }
+
+    /// Drop the cached token, e.g. after the API answered 401
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+
+    async fn fetch(&self) -> Result<CachedToken> {
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", self.config.client_id.as_str()),
+            ("client_secret", self.config.client_secret.as_str()),
+        ];
+        if let Some(scope) = &self.config.scope {
+            form.push(("scope", scope));
+        }
+        if let Some(audience) = &self.config.audience {
+            form.push(("audience", audience));
+        }
+
+        let response = self
+            .client
+            .post(self.config.token_url.clone())
+            .form(&form)
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach token endpoint {}", self.config.token_url))?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow!("Token endpoint returned {}: {}", status.as_u16(), message));
+        }
+        let token: TokenResponse = response.json().await.context("Invalid token endpoint response")?;
+
+        let lifetime = token
+            .expires_in
+            .map(Duration::from_secs)
+            .or_else(|| jwt_lifetime(&token.access_token))
+            .unwrap_or(DEFAULT_LIFETIME);
+        info!("Obtained access token from {}, valid for {}s", self.config.token_url, lifetime.as_secs());
+
+        Ok(CachedToken {
+            access_token: token.access_token,
+            expires_at: Instant::now() + lifetime,
+        })
+    }
+}
+
+/// Remaining lifetime from the `exp` claim of a JWT, without verifying it
+fn jwt_lifetime(token: &str) -> Option<Duration> {
+    #[derive(Deserialize)]
+    struct Claims {
+        exp: i64,
+    }
+
+    let payload = token.split('.').nth(1)?;
+    let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    let remaining = claims.exp - chrono::Utc::now().timestamp();
+    Some(Duration::from_secs(remaining.max(0) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jwt_lifetime() {
+        let exp = chrono::Utc::now().timestamp() + 3600;
+        let payload = URL_SAFE_NO_PAD.encode(format!("{{\"sub\":\"node\",\"exp\":{}}}", exp));
+        let token = format!("eyJhbGciOiJIUzI1NiJ9.{}.signature", payload);
+
+        let lifetime = jwt_lifetime(&token).unwrap();
+        assert!(lifetime > Duration::from_secs(3590) && lifetime <= Duration::from_secs(3600));
+        assert!(jwt_lifetime("not-a-jwt").is_none());
+    }
+}
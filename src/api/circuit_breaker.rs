@@ -0,0 +1,195 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// When to stop calling the API and for how long
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failed sends that open the circuit
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a probe is let through
+    pub cool_down: Duration,
+    /// Successful probes needed to close the circuit again
+    pub probes_to_close: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cool_down: Duration::from_secs(60),
+            probes_to_close: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Sends go through
+    Closed,
+    /// Sends are short-circuited until the cool-down ends
+    Open,
+    /// One probe at a time is let through to test the API
+    HalfOpen,
+}
+
+impl std::fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half_open"),
+        }
+    }
+}
+
+/// A send was skipped because the circuit is open
+#[derive(Debug)]
+pub struct CircuitOpen {
+    pub retry_in: Duration,
+}
+
+impl std::fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "API circuit breaker open, next probe in {}s", self.retry_in.as_secs())
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+/// Stops sending to an API that keeps failing, so a down backend does not
+/// cause a tight loop of timeouts and retries.
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    state: CircuitState,
+    consecutive_failures: u32,
+    probe_successes: u32,
+    opened_at: Instant,
+    probe_in_flight: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(Inner {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                probe_successes: 0,
+                opened_at: Instant::now(),
+                probe_in_flight: false,
+            }),
+        }
+    }
+
+    pub fn cool_down(&self) -> Duration {
+        self.config.cool_down
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Ask to send; every granted call must be followed by `record_success` or `record_failure`
+    pub fn try_acquire(&self) -> Result<(), CircuitOpen> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == CircuitState::Open {
+            let elapsed = inner.opened_at.elapsed();
+            if elapsed < self.config.cool_down {
+                return Err(CircuitOpen { retry_in: self.config.cool_down - elapsed });
+            }
+            inner.state = CircuitState::HalfOpen;
+            inner.probe_successes = 0;
+            inner.probe_in_flight = false;
+        }
+        if inner.state == CircuitState::HalfOpen {
+            if inner.probe_in_flight {
+                return Err(CircuitOpen { retry_in: Duration::ZERO });
+            }
+            inner.probe_in_flight = true;
+        }
+        Ok(())
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = 0;
+        if inner.state == CircuitState::HalfOpen {
+            inner.probe_in_flight = false;
+            inner.probe_successes += 1;
+            if inner.probe_successes >= self.config.probes_to_close.max(1) {
+                inner.state = CircuitState::Closed;
+            }
+        }
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        let trip = match inner.state {
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => inner.consecutive_failures >= self.config.failure_threshold,
+            CircuitState::Open => false,
+        };
+        if trip {
+            inner.state = CircuitState::Open;
+            inner.opened_at = Instant::now();
+            inner.probe_in_flight = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(cool_down: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 2,
+            cool_down,
+            probes_to_close: 1,
+        })
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = breaker(Duration::from_secs(60));
+        breaker.try_acquire().unwrap();
+        breaker.record_failure();
+        breaker.try_acquire().unwrap();
+        breaker.record_success();
+        breaker.try_acquire().unwrap();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.try_acquire().unwrap();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.try_acquire().is_err());
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breaker = breaker(Duration::ZERO);
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // Cool-down over: one probe at a time
+        breaker.try_acquire().unwrap();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.try_acquire().is_err());
+
+        // A failed probe reopens, a successful one closes
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        breaker.try_acquire().unwrap();
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
@@ -1,31 +1,177 @@
 use anyhow::{Result, Context};
-use reqwest::{Client, header};
+use async_trait::async_trait;
+use reqwest::{Client, Url, header};
+use serde::Serialize;
 use log::{info, error, debug, warn};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use crate::metrics::agent::types::AgentMetrics;
+use crate::networking::NodeInfo;
+use crate::proxy;
 use crate::metrics::cpu::types::CpuMetrics;
-use crate::metrics::network::types::NetworkMetrics;
-use crate::metrics::storage::types::StorageMetrics;
-use crate::metrics::system::types::SystemInfo;
+use crate::metrics::network::types::{LinkEventKind, NetworkSnapshot, RouteEventKind};
+use crate::metrics::storage::types::{MountEventKind, StorageMetrics};
+use crate::metrics::system::types::{DisplayEventKind, DisplayInfo, SystemInfo};
+use super::auth::TokenProvider;
+use super::commands::{CommandResult, NodeCommand};
+use super::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitState};
+use super::compression::{self, CompressionMode, ContentEncoding, MIN_COMPRESS_BYTES};
+use super::delta::DeltaEncoder;
+use super::encryption::{PayloadEncryption, ENCRYPTION_HEADER_VALUE};
+use super::events::EventQueue;
+use super::field_policy::FieldPolicy;
+use super::grpc::{metrics::Command, GrpcTransport};
 use super::models;
+use super::remote_config::RemoteConfig;
+use super::sink::{MetricsBatch, MetricsSink};
+use super::retry::{DeliveryCounters, DeliveryStats, RetryPolicy};
+use super::sequence::SequenceCounter;
+use super::spool::MetricsSpool;
+use super::websocket::WebSocketTransport;
 use chrono::Utc;
 
 /// API client for sending metrics to the monitoring API
 pub struct ApiClient {
     client: Client,
     base_url: String,
+    node_id: String,
+    /// User-defined labels sent with every metrics payload
+    labels: BTreeMap<String, String>,
+    spool: Option<MetricsSpool>,
+    /// Held while the spool is replayed, so an upload and a flush don't send the same payload
+    replaying: tokio::sync::Mutex<()>,
+    retry_policy: RetryPolicy,
+    delivery: DeliveryCounters,
+    compression: CompressionMode,
+    available_encodings: Vec<ContentEncoding>,
+    encoding: Mutex<ContentEncoding>,
+    api_key: String,
+    websocket: Option<WebSocketTransport>,
+    grpc: Option<GrpcTransport>,
+    delta: Option<DeltaEncoder>,
+    field_policy: FieldPolicy,
+    circuit_breaker: Option<CircuitBreaker>,
+    token_provider: Option<TokenProvider>,
+    events: Option<Arc<EventQueue>>,
+    sequence: SequenceCounter,
+    encryption: Option<PayloadEncryption>,
+    /// Endpoint peers register with and are listed by, when not the monitoring API
+    rendezvous_url: Option<String>,
+    /// File touched after every acknowledged upload, e.g. for post-update health checks
+    delivery_marker: Option<PathBuf>,
+}
+
+/// Idempotency key and sequence number of a serialized metrics payload
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PayloadKeys {
+    payload_id: Option<String>,
+    sequence: Option<u64>,
+}
+
+/// The API answered 415 to a compressed body
+#[derive(Debug)]
+struct UnsupportedEncoding;
+
+impl std::fmt::Display for UnsupportedEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "API does not support the request content encoding")
+    }
+}
+
+impl std::error::Error for UnsupportedEncoding {}
+
+/// The API refused a payload; resending the same payload will not succeed
+#[derive(Debug)]
+pub struct ApiRejected {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ApiRejected {
+    /// 4xx responses other than timeouts and rate limiting
+    fn is_permanent(status: reqwest::StatusCode) -> bool {
+        status.is_client_error()
+            && status != reqwest::StatusCode::REQUEST_TIMEOUT
+            && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+    }
+}
+
+impl std::fmt::Display for ApiRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "API rejected payload ({}): {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for ApiRejected {}
+
+/// The API could not be reached in time or failed server-side; worth retrying
+#[derive(Debug)]
+pub struct ApiUnavailable {
+    pub status: Option<u16>,
+    pub message: String,
+}
+
+impl ApiUnavailable {
+    /// 5xx responses and 408 Request Timeout
+    fn is_transient(status: reqwest::StatusCode) -> bool {
+        status.is_server_error() || status == reqwest::StatusCode::REQUEST_TIMEOUT
+    }
+}
+
+impl std::fmt::Display for ApiUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.status {
+            Some(status) => write!(f, "API error ({}): {}", status, self.message),
+            None => write!(f, "API request timed out: {}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ApiUnavailable {}
+
+#[async_trait]
+impl MetricsSink for ApiClient {
+    fn name(&self) -> &str {
+        "monitoring API"
+    }
+
+    async fn send(&self, batch: &MetricsBatch<'_>) -> Result<()> {
+        let result = self.send_metrics(batch.system, batch.cpu, batch.network, batch.storage, batch.agent).await;
+        let delivery = self.delivery_stats();
+        debug!("Metric delivery: {} sends, {} retries, {} failures, {} spooled, circuit {}",
+               delivery.sends, delivery.retries, delivery.failures, delivery.spooled,
+               delivery.circuit.map_or("disabled".to_string(), |state| state.to_string()));
+        result
+    }
+
+    fn stream_event(&self, kind: &str, event: &serde_json::Value) -> bool {
+        ApiClient::stream_event(self, kind, event)
+    }
 }
 
 impl ApiClient {
     /// Create a new API client
-    pub fn new(base_url: String, api_key: String) -> Result<Self> {
+    pub fn new(base_url: String, api_key: String, node_id: String) -> Result<Self> {
         let mut headers = header::HeaderMap::new();
+        // Token-authenticated deployments run without a static key
+        if !api_key.is_empty() {
+            headers.insert(
+                "X-API-Key",
+                header::HeaderValue::from_str(&api_key)
+                    .context("Invalid API key format")?
+            );
+        }
         headers.insert(
-            "X-API-Key",
-            header::HeaderValue::from_str(&api_key)
-                .context("Invalid API key format")?
+            "X-Node-Id",
+            header::HeaderValue::from_str(&node_id)
+                .context("Invalid node ID format")?
         );
 
-        let client = Client::builder()
+        let client = proxy::client_builder()
             .default_headers(headers)
             .timeout(Duration::from_secs(10))
             .build()
@@ -34,31 +180,322 @@ impl ApiClient {
         Ok(Self {
             client,
             base_url,
+            node_id,
+            labels: BTreeMap::new(),
+            spool: None,
+            replaying: tokio::sync::Mutex::new(()),
+            retry_policy: RetryPolicy::default(),
+            delivery: DeliveryCounters::default(),
+            compression: CompressionMode::Fixed(ContentEncoding::Identity),
+            available_encodings: Vec::new(),
+            encoding: Mutex::new(ContentEncoding::Identity),
+            api_key,
+            websocket: None,
+            grpc: None,
+            delta: None,
+            field_policy: FieldPolicy::default(),
+            circuit_breaker: None,
+            token_provider: None,
+            events: None,
+            sequence: SequenceCounter::in_memory(),
+            delivery_marker: None,
+            encryption: None,
+            rendezvous_url: None,
         })
     }
 
+    /// Compress request bodies
+    pub fn with_compression(mut self, mode: CompressionMode) -> Self {
+        self.available_encodings = vec![ContentEncoding::Zstd, ContentEncoding::Gzip];
+        let initial = match mode {
+            CompressionMode::Fixed(encoding) => encoding,
+            CompressionMode::Auto => ContentEncoding::Identity,
+        };
+        self.compression = mode;
+        self.encoding = Mutex::new(initial);
+        self
+    }
+
+    /// Stream metrics and events over a persistent WebSocket at `url`, falling back
+    /// to HTTP POST whenever the socket is down. Messages the server pushes go to `incoming`.
+    pub fn with_websocket(mut self, url: Url, incoming: Option<mpsc::Sender<String>>) -> Result<Self> {
+        let mut headers = vec![("X-Node-Id".to_string(), self.node_id.clone())];
+        if !self.api_key.is_empty() {
+            headers.push(("X-API-Key".to_string(), self.api_key.clone()));
+        }
+        self.websocket = Some(WebSocketTransport::spawn(url, headers, incoming)?);
+        Ok(self)
+    }
+
+    /// Upload metrics over gRPC to `url`, falling back to HTTP POST when a call fails.
+    /// Commands the server streams back on the same connection go to `commands`.
+    pub fn with_grpc(mut self, url: Url, commands: Option<mpsc::Sender<Command>>) -> Result<Self> {
+        self.grpc = Some(GrpcTransport::connect(url, &self.api_key, &self.node_id, commands)?);
+        Ok(self)
+    }
+
+    /// Only send fields that changed since the last successful upload, with a full
+    /// snapshot at least every `full_interval`
+    pub fn with_delta(mut self, full_interval: Duration) -> Self {
+        self.delta = Some(DeltaEncoder::new(full_interval));
+        self
+    }
+
+    /// Redact payload fields according to `policy` before sending
+    pub fn with_field_policy(mut self, policy: FieldPolicy) -> Self {
+        self.field_policy = policy;
+        self
+    }
+
+    /// Stop calling the API after repeated failures and probe it again after a cool-down,
+    /// spooling payloads in the meantime
+    pub fn with_circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.circuit_breaker = Some(CircuitBreaker::new(config));
+        self
+    }
+
+    /// Authenticate with OAuth2 bearer tokens from `provider`, refreshed before they expire
+    pub fn with_oauth(mut self, provider: TokenProvider) -> Self {
+        self.token_provider = Some(provider);
+        self
+    }
+
+    /// Current bearer token when OAuth is configured. Token endpoint failures count as
+    /// the API being unavailable so the payload is retried and spooled.
+    async fn bearer_token(&self) -> Result<Option<String>> {
+        let Some(provider) = &self.token_provider else {
+            return Ok(None);
+        };
+        match provider.token().await {
+            Ok(token) => Ok(Some(token)),
+            Err(err) => Err(ApiUnavailable { status: None, message: format!("{:#}", err) }.into()),
+        }
+    }
+
+    /// Fetch this node's configuration. Returns None when it is unchanged since
+    /// `etag` or no configuration has been assigned to the node.
+    pub async fn fetch_config(&self, etag: Option<&str>) -> Result<Option<(RemoteConfig, Option<String>)>> {
+        let endpoint = format!("{}/api/v1/nodes/{}/config", self.base_url, self.node_id);
+        let mut request = self.client.get(&endpoint);
+        if let Some(token) = self.bearer_token().await? {
+            request = request.bearer_auth(token);
+        }
+        if let Some(etag) = etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+
+        let response = request.send().await.with_context(|| format!("Failed to reach {}", endpoint))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_MODIFIED || status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("API error ({}): {}", status, message));
+        }
+        let etag = response.headers().get(header::ETAG).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let config = response.json::<RemoteConfig>().await.context("Invalid node configuration")?;
+        Ok(Some((config, etag)))
+    }
+
+    /// Tell the API which configuration version is active
+    pub async fn report_config_version(&self, version: &str) -> Result<()> {
+        let endpoint = format!("{}/api/v1/nodes/{}/config/status", self.base_url, self.node_id);
+        let mut request = self.client.post(&endpoint).json(&models::ConfigStatus {
+            version: version.to_string(),
+            applied_at: Utc::now(),
+        });
+        if let Some(token) = self.bearer_token().await? {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.with_context(|| format!("Failed to reach {}", endpoint))?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("API error ({}): {}", status, message));
+        }
+        Ok(())
+    }
+
+    /// Report streamed events through `queue` and `POST /api/v1/events` instead of
+    /// the WebSocket, so they survive API outages and restarts
+    pub fn with_events(mut self, queue: Arc<EventQueue>) -> Self {
+        self.events = Some(queue);
+        self
+    }
+
+    pub fn event_queue(&self) -> Option<Arc<EventQueue>> {
+        self.events.clone()
+    }
+
+    /// Encrypt metric payloads end to end. Metrics then only go out over HTTP POST;
+    /// the gRPC and WebSocket transports carry them in plaintext.
+    pub fn with_encryption(mut self, encryption: PayloadEncryption) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+
+    /// Number payloads from `sequence`, e.g. one persisted across restarts
+    pub fn with_sequence(mut self, sequence: SequenceCounter) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Report `labels` with the system info of every payload
+    pub fn with_labels(mut self, labels: BTreeMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Record the time of every acknowledged upload in `path`
+    pub fn with_delivery_marker(mut self, path: PathBuf) -> Self {
+        self.delivery_marker = Some(path);
+        self
+    }
+
+    /// Count an acknowledged upload and update the delivery marker
+    fn record_delivery(&self, sequence: Option<u64>, duplicate: bool) {
+        self.delivery.record_ack(sequence, duplicate);
+        if let Some(path) = &self.delivery_marker {
+            if let Err(err) = std::fs::write(path, Utc::now().to_rfc3339()) {
+                debug!("Failed to update delivery marker {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Deliver a batch of agent events
+    pub async fn post_events(&self, events: &[serde_json::Value]) -> Result<()> {
+        let endpoint = format!("{}/api/v1/events", self.base_url);
+        let mut request = self.client.post(&endpoint).json(&serde_json::json!({ "events": events }));
+        if let Some(token) = self.bearer_token().await? {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.with_context(|| format!("Failed to reach {}", endpoint))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let message = response.text().await.unwrap_or_default();
+        if ApiRejected::is_permanent(status) && status != reqwest::StatusCode::UNAUTHORIZED {
+            return Err(ApiRejected { status: status.as_u16(), message }.into());
+        }
+        Err(ApiUnavailable { status: Some(status.as_u16()), message }.into())
+    }
+
+    /// Commands the API has queued for this node
+    pub async fn fetch_commands(&self) -> Result<Vec<NodeCommand>> {
+        let endpoint = format!("{}/api/v1/nodes/{}/commands", self.base_url, self.node_id);
+        let mut request = self.client.get(&endpoint);
+        if let Some(token) = self.bearer_token().await? {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.with_context(|| format!("Failed to reach {}", endpoint))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NO_CONTENT || status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("API error ({}): {}", status, message));
+        }
+        response.json::<Vec<NodeCommand>>().await.context("Invalid command list")
+    }
+
+    /// Report the outcome of a command
+    pub async fn report_command_result(&self, id: &str, result: &CommandResult) -> Result<()> {
+        let endpoint = format!("{}/api/v1/nodes/{}/commands/{}/result", self.base_url, self.node_id, id);
+        let mut request = self.client.post(&endpoint).json(result);
+        if let Some(token) = self.bearer_token().await? {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.with_context(|| format!("Failed to reach {}", endpoint))?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("API error ({}): {}", status, message));
+        }
+        Ok(())
+    }
+
+    /// Register with and list peers at a dedicated rendezvous endpoint instead
+    /// of the monitoring API, with the same credentials
+    pub fn with_rendezvous_url(mut self, url: String) -> Self {
+        self.rendezvous_url = Some(url);
+        self
+    }
+
+    /// Register this node's discovery record so nodes on other networks can find it
+    pub async fn register_peer(&self, node: &NodeInfo) -> Result<()> {
+        let base_url = self.rendezvous_url.as_deref().unwrap_or(&self.base_url);
+        let endpoint = format!("{}/api/v1/nodes/{}/peer", base_url, self.node_id);
+        let mut request = self.client.put(&endpoint).json(node);
+        if let Some(token) = self.bearer_token().await? {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.with_context(|| format!("Failed to reach {}", endpoint))?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("API error ({}): {}", status, message));
+        }
+        Ok(())
+    }
+
+    /// Discovery records of the nodes registered for this node's peers
+    pub async fn fetch_peers(&self) -> Result<Vec<NodeInfo>> {
+        let base_url = self.rendezvous_url.as_deref().unwrap_or(&self.base_url);
+        let endpoint = format!("{}/api/v1/nodes/{}/peers", base_url, self.node_id);
+        let mut request = self.client.get(&endpoint);
+        if let Some(token) = self.bearer_token().await? {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.with_context(|| format!("Failed to reach {}", endpoint))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NO_CONTENT || status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("API error ({}): {}", status, message));
+        }
+        response.json::<Vec<NodeInfo>>().await.context("Invalid peer list")
+    }
+
+    /// Replace the default retry policy
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Queue payloads that fail to send in `spool` and replay them once the API is reachable
+    pub fn with_spool(mut self, spool: MetricsSpool) -> Self {
+        self.spool = Some(spool);
+        self
+    }
+
     /// Send system metrics to the monitoring API
     pub async fn send_metrics(
         &self,
         system_info: &SystemInfo,
         cpu_metrics: Option<&CpuMetrics>,
-        network_metrics: Option<&Vec<NetworkMetrics>>,
+        network_metrics: Option<&NetworkSnapshot>,
         storage_metrics: Option<&StorageMetrics>,
+        agent_metrics: Option<&AgentMetrics>,
     ) -> Result<()> {
         let metrics = self.build_metrics_payload(
             system_info,
             cpu_metrics,
             network_metrics,
             storage_metrics,
+            agent_metrics,
         )?;
 
-        let endpoint = format!("{}/api/v1/metrics", self.base_url);
-        debug!("Sending metrics to API: {}", endpoint);
-        
-        // Log request summary
-        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
-        let start_time = Instant::now();
-        
         // Create a condensed version of the metrics for logging
         let body_summary = format!(
             "{{ system: {}, cpu: {:.1}%, memory: {:.1}MB free, metrics_count: {} }}", 
@@ -72,10 +509,299 @@ impl ApiClient {
                 .count()
         );
         
+        let payload = serde_json::to_value(&metrics).context("Failed to serialize metrics")?;
+        let Some(delta) = &self.delta else {
+            return self.deliver(&metrics, &payload, &body_summary).await;
+        };
+
+        let force_full = system_info.last_update.changed_fields.iter().any(|field| field == "full_update");
+        let encoded = delta.encode(&payload, force_full);
+        let result = self.deliver(&metrics, &encoded.body, &body_summary).await;
+        match &result {
+            Ok(()) => delta.acknowledge(payload, encoded.full),
+            Err(_) => delta.reset(),
+        }
+        result
+    }
+
+    /// Deliver one JSON payload (full or delta) over the first available transport
+    async fn deliver(&self, metrics: &models::SystemMetrics, payload: &serde_json::Value, body_summary: &str) -> Result<()> {
+        let body = serde_json::to_vec(payload).context("Failed to serialize metrics")?;
+        let Some(breaker) = &self.circuit_breaker else {
+            return self.transmit(metrics, payload, body, body_summary).await;
+        };
+
+        if let Err(open) = breaker.try_acquire() {
+            if let Some(spool) = &self.spool {
+                spool.enqueue(metrics.timestamp, &body)?;
+                return Err(anyhow::Error::new(open).context("Metrics spooled for replay"));
+            }
+            return Err(open.into());
+        }
+
+        let before = breaker.state();
+        let result = self.transmit(metrics, payload, body, body_summary).await;
+        match &result {
+            // A rejected payload still means the API is up
+            Err(err) if err.downcast_ref::<ApiRejected>().is_none() => breaker.record_failure(),
+            _ => breaker.record_success(),
+        }
+        match (before, breaker.state()) {
+            (_, CircuitState::Open) => warn!("Monitoring API keeps failing, pausing sends for {}s",
+                                            breaker.cool_down().as_secs()),
+            (CircuitState::HalfOpen, CircuitState::Closed) => info!("Monitoring API recovered, resuming sends"),
+            _ => {}
+        }
+        result
+    }
+
+    /// Send over gRPC, WebSocket or HTTP, replaying the spool first
+    async fn transmit(&self, metrics: &models::SystemMetrics, payload: &serde_json::Value, body: Vec<u8>, body_summary: &str) -> Result<()> {
+
+        // Prefer the binary gRPC upload, unless older payloads still wait in the spool
+        if let Some(grpc) = self.grpc.as_ref().filter(|_| self.encryption.is_none()) {
+            if self.spool.as_ref().is_none_or(|spool| spool.is_empty()) {
+                let result = match self.bearer_token().await {
+                    Ok(bearer) => grpc.submit(metrics, bearer).await,
+                    Err(err) => Err(err),
+                };
+                match result {
+                    Ok(response) => {
+                        self.delivery.record_send();
+                        self.record_delivery(response.acked_sequence.or(metrics.sequence), response.duplicate);
+                        debug!("Sent metrics over gRPC: {}", body_summary);
+                        return Ok(());
+                    }
+                    Err(err) => debug!("gRPC upload failed, using HTTP: {}", err),
+                }
+            }
+        }
+
+        // Stream over the WebSocket while it is up, unless older payloads still wait in the spool
+        if let Some(websocket) = self.websocket.as_ref().filter(|_| self.encryption.is_none()) {
+            if websocket.is_connected() && self.spool.as_ref().is_none_or(|spool| spool.is_empty()) {
+                let frame = serde_json::json!({ "type": "metrics", "data": payload }).to_string();
+                match websocket.try_send(frame) {
+                    Ok(()) => {
+                        self.delivery.record_send();
+                        debug!("Streamed metrics over WebSocket: {}", body_summary);
+                        return Ok(());
+                    }
+                    Err(err) => debug!("WebSocket send failed, using HTTP: {}", err),
+                }
+            }
+        }
+
+        let Some(spool) = &self.spool else {
+            return self.post_with_retry(body, body_summary).await;
+        };
+
+        // Deliver anything queued while offline first so the API sees payloads in order
+        if let Err(err) = self.replay_spool(spool).await {
+            spool.enqueue(metrics.timestamp, &body)?;
+            return Err(err.context(format!("API unreachable, {} payload(s) spooled", spool.len())));
+        }
+
+        match self.post_with_retry(body.clone(), body_summary).await {
+            Err(err) if err.downcast_ref::<ApiRejected>().is_none() => {
+                spool.enqueue(metrics.timestamp, &body)?;
+                Err(err.context("Metrics spooled for replay"))
+            }
+            result => result,
+        }
+    }
+
+    /// Queue an event for the events API when enabled, otherwise push it over the
+    /// WebSocket if connected. Returns false if neither happened; the event still
+    /// goes out with the next metrics payload.
+    pub fn stream_event<T: Serialize>(&self, kind: &str, event: &T) -> bool {
+        if let Some(events) = &self.events {
+            let queued = serde_json::to_value(event)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| events.publish(kind, &data));
+            return match queued {
+                Ok(_) => true,
+                Err(err) => {
+                    warn!("Failed to queue {} event: {:#}", kind, err);
+                    false
+                }
+            };
+        }
+        let Some(websocket) = &self.websocket else {
+            return false;
+        };
+        let frame = serde_json::json!({
+            "type": "event",
+            "kind": kind,
+            "nodeId": self.node_id,
+            "data": event,
+        });
+        websocket.try_send(frame.to_string()).is_ok()
+    }
+
+    /// POST a payload, retrying 5xx responses and timeouts according to the retry policy
+    async fn post_with_retry(&self, body: Vec<u8>, body_summary: &str) -> Result<()> {
+        self.delivery.record_send();
+        let mut attempt = 1;
+        loop {
+            match self.post_metrics(body.clone(), body_summary).await {
+                Err(err) if err.downcast_ref::<ApiUnavailable>().is_some()
+                    && attempt < self.retry_policy.max_attempts => {
+                    let delay = self.retry_policy.backoff(attempt);
+                    warn!("Metrics post attempt {}/{} failed ({}), retrying in {}ms",
+                          attempt, self.retry_policy.max_attempts, err, delay.as_millis());
+                    self.delivery.record_retry();
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => {
+                    self.delivery.record_failure();
+                    if attempt > 1 {
+                        return Err(err.context(format!("Giving up after {} attempts", attempt)));
+                    }
+                    return Err(err);
+                }
+                Ok(()) => {
+                    if attempt > 1 {
+                        info!("Metrics delivered on attempt {}", attempt);
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn current_encoding(&self) -> ContentEncoding {
+        *self.encoding.lock().unwrap()
+    }
+
+    fn set_encoding(&self, encoding: ContentEncoding) {
+        *self.encoding.lock().unwrap() = encoding;
+    }
+
+    /// In auto mode, follow the encodings the API advertises in `Accept-Encoding`
+    fn negotiate_encoding(&self, accept_encoding: &str) {
+        if self.compression != CompressionMode::Auto {
+            return;
+        }
+        let negotiated = compression::negotiate(accept_encoding, &self.available_encodings);
+        let mut current = self.encoding.lock().unwrap();
+        if *current != negotiated {
+            info!("API accepts '{}', switching request encoding to {}", accept_encoding, negotiated.header_value());
+            *current = negotiated;
+        }
+    }
+
+    /// Delivery counters since startup, including the current spool depth
+    pub fn delivery_stats(&self) -> DeliveryStats {
+        let mut stats = self.delivery.snapshot(self.spool.as_ref().map_or(0, |spool| spool.len()));
+        stats.circuit = self.circuit_breaker.as_ref().map(CircuitBreaker::state);
+        stats
+    }
+
+    /// Replay the spool now rather than before the next upload; how many
+    /// payloads are still waiting
+    pub async fn flush_spool(&self) -> Result<usize> {
+        let Some(spool) = &self.spool else {
+            return Ok(0);
+        };
+        self.replay_spool(spool).await?;
+        Ok(spool.len())
+    }
+
+    /// Send spooled payloads oldest-first, stopping at the first one that cannot be delivered
+    async fn replay_spool(&self, spool: &MetricsSpool) -> Result<()> {
+        let _replaying = self.replaying.lock().await;
+        let mut replayed = 0;
+        while let Some(payload) = spool.peek()? {
+            let summary = format!("{{ spooled: {} }}", payload.path.display());
+            match self.post_metrics(payload.body.clone(), &summary).await {
+                Ok(()) => replayed += 1,
+                Err(err) if err.downcast_ref::<ApiRejected>().is_some() => {
+                    warn!("Dropping spooled payload {} rejected by API: {}", payload.path.display(), err);
+                }
+                Err(err) => {
+                    if replayed > 0 {
+                        info!("Replayed {} spooled payload(s) before losing connectivity", replayed);
+                    }
+                    return Err(err);
+                }
+            }
+            spool.remove(&payload)?;
+        }
+        if replayed > 0 {
+            info!("Replayed {} spooled payload(s)", replayed);
+        }
+        Ok(())
+    }
+
+    /// POST a serialized metrics payload
+    async fn post_metrics(&self, body: Vec<u8>, body_summary: &str) -> Result<()> {
+        // Spooled payloads carry the keys they were built with, so replays reuse them
+        let keys: PayloadKeys = serde_json::from_slice(&body).unwrap_or_default();
+        let keys = &keys;
+        let encoding = self.current_encoding();
+        if encoding == ContentEncoding::Identity || body.len() < MIN_COMPRESS_BYTES {
+            return self.post_body(body, ContentEncoding::Identity, keys, body_summary).await;
+        }
+
+        let compressed = match encoding.encode(&body) {
+            Ok(compressed) => compressed,
+            Err(err) => {
+                warn!("{} compression failed, sending uncompressed: {}", encoding.header_value(), err);
+                return self.post_body(body, ContentEncoding::Identity, keys, body_summary).await;
+            }
+        };
+        debug!("Compressed metrics with {}: {} -> {} bytes", encoding.header_value(), body.len(), compressed.len());
+
+        match self.post_body(compressed, encoding, keys, body_summary).await {
+            Err(err) if err.downcast_ref::<UnsupportedEncoding>().is_some() => {
+                warn!("API does not accept {} bodies, disabling compression", encoding.header_value());
+                self.set_encoding(ContentEncoding::Identity);
+                self.post_body(body, ContentEncoding::Identity, keys, body_summary).await
+            }
+            result => result,
+        }
+    }
+
+    /// POST an already encoded payload
+    async fn post_body(&self, body: Vec<u8>, encoding: ContentEncoding, keys: &PayloadKeys, body_summary: &str) -> Result<()> {
+        let endpoint = format!("{}/api/v1/metrics", self.base_url);
+        debug!("Sending metrics to API: {}", endpoint);
+        
+        // Log request summary
+        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+        let start_time = Instant::now();
+        
         // Send the request
-        let response_result = self.client
-            .post(&endpoint)
-            .json(&metrics)
+        let mut request = self.client.post(&endpoint);
+        if let Some(token) = self.bearer_token().await? {
+            request = request.bearer_auth(token);
+        }
+        let body = match &self.encryption {
+            // The compression is part of the plaintext, so it travels in its own header
+            Some(encryption) => {
+                request = request
+                    .header(header::CONTENT_TYPE, "application/octet-stream")
+                    .header("X-Payload-Encryption", ENCRYPTION_HEADER_VALUE);
+                if encoding != ContentEncoding::Identity {
+                    request = request.header("X-Payload-Content-Encoding", encoding.header_value());
+                }
+                encryption.encrypt(&body).context("Failed to encrypt metrics payload")?
+            }
+            None => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                if encoding != ContentEncoding::Identity {
+                    request = request.header(header::CONTENT_ENCODING, encoding.header_value());
+                }
+                body
+            }
+        };
+        if let Some(payload_id) = &keys.payload_id {
+            request = request.header("Idempotency-Key", payload_id);
+        }
+        let response_result = request
+            .body(body)
             .send()
             .await;
             
@@ -84,14 +810,38 @@ impl ApiClient {
         match response_result {
             Ok(response) => {
                 let status = response.status();
+
+                if status == reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE && encoding != ContentEncoding::Identity {
+                    return Err(UnsupportedEncoding.into());
+                }
+                // The token may have been revoked early; fetch a new one on the next attempt
+                if status == reqwest::StatusCode::UNAUTHORIZED {
+                    if let Some(provider) = &self.token_provider {
+                        provider.invalidate().await;
+                        return Err(ApiUnavailable {
+                            status: Some(status.as_u16()),
+                            message: "Access token rejected".to_string(),
+                        }.into());
+                    }
+                }
+                if let Some(accept) = response.headers().get(header::ACCEPT_ENCODING).and_then(|v| v.to_str().ok()) {
+                    self.negotiate_encoding(accept);
+                }
                 
                 if status.is_success() {
                     // Try to parse the response
                     match response.json::<models::ApiResponse>().await {
                         Ok(api_response) => {
-                            info!("[{}] POST - {}ms - {} - {} - {} OK (node: {})", 
-                                  timestamp, duration, endpoint, body_summary, 
-                                  status.as_u16(), api_response.node);
+                            if api_response.duplicate {
+                                info!("[{}] POST - {}ms - {} - {} - {} DUPLICATE (node: {}, already stored)",
+                                      timestamp, duration, endpoint, body_summary,
+                                      status.as_u16(), api_response.node);
+                            } else {
+                                info!("[{}] POST - {}ms - {} - {} - {} OK (node: {})", 
+                                      timestamp, duration, endpoint, body_summary, 
+                                      status.as_u16(), api_response.node);
+                            }
+                            self.record_delivery(api_response.acked_sequence.or(keys.sequence), api_response.duplicate);
                             Ok(())
                         },
                         Err(err) => {
@@ -108,12 +858,24 @@ impl ApiClient {
                             error!("[{}] POST - {}ms - {} - {} - {} ERROR ({})", 
                                    timestamp, duration, endpoint, body_summary, 
                                    status.as_u16(), error_text);
+                            if ApiRejected::is_permanent(status) {
+                                return Err(ApiRejected { status: status.as_u16(), message: error_text }.into());
+                            }
+                            if ApiUnavailable::is_transient(status) {
+                                return Err(ApiUnavailable { status: Some(status.as_u16()), message: error_text }.into());
+                            }
                             Err(anyhow::anyhow!("API error ({}): {}", status, error_text))
                         },
                         Err(err) => {
                             error!("[{}] POST - {}ms - {} - {} - {} ERROR (Failed to get error text: {})", 
                                    timestamp, duration, endpoint, body_summary, 
                                    status.as_u16(), err);
+                            if ApiUnavailable::is_transient(status) {
+                                return Err(ApiUnavailable {
+                                    status: Some(status.as_u16()),
+                                    message: "Failed to get error details".to_string(),
+                                }.into());
+                            }
                             Err(anyhow::anyhow!("API error ({}): Failed to get error details", status))
                         }
                     }
@@ -122,6 +884,9 @@ impl ApiClient {
             Err(err) => {
                 error!("[{}] POST - {}ms - {} - {} - REQUEST FAILED ({})", 
                        timestamp, duration, endpoint, body_summary, err);
+                if err.is_timeout() {
+                    return Err(ApiUnavailable { status: None, message: err.to_string() }.into());
+                }
                 Err(anyhow::anyhow!("Failed to send metrics to API: {}", err))
             }
         }
@@ -132,13 +897,17 @@ impl ApiClient {
         &self,
         system_info: &SystemInfo,
         cpu_metrics: Option<&CpuMetrics>,
-        network_metrics: Option<&Vec<NetworkMetrics>>,
+        network_metrics: Option<&NetworkSnapshot>,
         storage_metrics: Option<&StorageMetrics>,
+        agent_metrics: Option<&AgentMetrics>,
     ) -> Result<models::SystemMetrics> {
         // Create the base system metrics
         let mut metrics = models::SystemMetrics {
             timestamp: chrono::Utc::now(),
+            payload_id: Some(uuid::Uuid::new_v4().to_string()),
+            sequence: Some(self.sequence.next()),
             system: models::SystemInfo {
+                node_id: self.node_id.clone(),
                 hostname: system_info.hostname.clone(),
                 platform: system_info.platform.os_type.clone(),
                 release: system_info.platform.os_version.clone(),
@@ -151,6 +920,14 @@ impl ApiClient {
                 is_apple_silicon: system_info.hardware.model_identifier.contains("Mac") && 
                                 system_info.hardware.processor_name.contains("Apple"),
                 model: system_info.hardware.model_name.clone(),
+                clock: system_info.clock.as_ref().map(|clock| models::ClockInfo {
+                    ntp_server: clock.server.clone(),
+                    offset_ms: clock.offset_ms,
+                    round_trip_ms: clock.round_trip_ms,
+                    stratum: clock.stratum,
+                    checked_at: clock.checked_at,
+                }),
+                labels: self.labels.clone(),
             },
             // Initialize with empty values, will be filled in below if available
             cpu: models::CpuInfo {
@@ -174,6 +951,7 @@ impl ApiClient {
                     cores: None,
                 },
                 temperature: None,
+                scheduler: None,
             },
             memory: models::MemoryInfo {
                 total: system_info.platform.total_memory,
@@ -185,9 +963,36 @@ impl ApiClient {
             gpu: None,
             network: None,
             thermal: None,
+            power: None,
             storage: None,
             peripherals: None,
             apple_silicon: None,
+            agent: agent_metrics.map(|agent| models::AgentInfo {
+                pid: agent.pid,
+                version: agent.version.clone(),
+                uptime_seconds: agent.uptime_seconds,
+                rss_bytes: agent.rss_bytes,
+                virtual_memory_bytes: agent.virtual_memory_bytes,
+                cpu_percent: agent.cpu_percent,
+                open_fds: agent.open_fds,
+                tokio_tasks: agent.tokio_tasks,
+                tokio_workers: agent.tokio_workers,
+                backlogs: agent.backlogs.iter()
+                    .map(|b| models::QueueBacklogInfo { name: b.name.clone(), depth: b.depth })
+                    .collect(),
+                delivery: {
+                    let stats = self.delivery_stats();
+                    models::DeliveryInfo {
+                        sends: stats.sends,
+                        retries: stats.retries,
+                        failures: stats.failures,
+                        spooled: stats.spooled,
+                        circuit_state: stats.circuit.map(|state| state.to_string()),
+                        last_acked_sequence: stats.last_acked_sequence,
+                        duplicates: stats.duplicates,
+                    }
+                },
+            }),
         };
 
         // Add CPU metrics if available
@@ -229,6 +1034,12 @@ impl ApiClient {
                     cores: None, // We need to add per-core temperatures
                     max: cpu.temperature_max,
                 }),
+                scheduler: cpu.scheduler.as_ref().map(|sched| models::CpuSchedulerInfo {
+                    run_queue: sched.run_queue,
+                    blocked: sched.blocked,
+                    context_switches_per_sec: sched.context_switches_per_sec,
+                    interrupts_per_sec: sched.interrupts_per_sec,
+                }),
             };
 
             // Add Apple Silicon data if available
@@ -260,7 +1071,7 @@ impl ApiClient {
 
         // Add network metrics if available
         if let Some(network) = network_metrics {
-            let interfaces = network.iter().map(|net| {
+            let interfaces = network.interfaces.iter().map(|net| {
                 models::NetworkInterface {
                     name: net.interface_name.clone(),
                     r#type: net.interface_info.interface_type.clone(),
@@ -272,7 +1083,7 @@ impl ApiClient {
                 }
             }).collect();
 
-            let stats = network.iter().map(|net| {
+            let stats = network.interfaces.iter().map(|net| {
                 models::NetworkStats {
                     interface: net.interface_name.clone(),
                     rx_sec: net.rx_bytes_per_sec,
@@ -283,9 +1094,118 @@ impl ApiClient {
                 }
             }).collect();
 
+            let dns = network.dns.iter().map(|probe| {
+                models::DnsProbeInfo {
+                    hostname: probe.hostname.clone(),
+                    resolved: probe.resolved,
+                    latency_ms: probe.latency_ms,
+                    addresses: probe.addresses.clone(),
+                    error: probe.error.clone(),
+                }
+            }).collect::<Vec<_>>();
+
+            let http_checks = network.http_checks.iter().map(|check| {
+                models::HttpCheckInfo {
+                    url: check.url.clone(),
+                    success: check.success,
+                    status: check.status,
+                    expected_status: check.expected_status,
+                    latency_ms: check.latency_ms,
+                    cert_expires_at: check.cert_expires_at,
+                    cert_days_remaining: check.cert_days_remaining,
+                    error: check.error.clone(),
+                }
+            }).collect::<Vec<_>>();
+
+            let link_events = network.link_events.iter().map(|event| {
+                models::LinkEventInfo {
+                    interface: event.interface_name.clone(),
+                    r#type: event.interface_type.clone(),
+                    event: match event.kind {
+                        LinkEventKind::LinkUp => "link_up".to_string(),
+                        LinkEventKind::LinkDown => "link_down".to_string(),
+                    },
+                    previous_status: event.previous_status.clone(),
+                    status: event.status.clone(),
+                    timestamp: event.timestamp,
+                }
+            }).collect::<Vec<_>>();
+
+            let routes = network.routes.iter().map(|route| {
+                models::RouteInfo {
+                    destination: route.destination.clone(),
+                    gateway: route.gateway.clone(),
+                    flags: route.flags.clone(),
+                    interface: route.interface.clone(),
+                }
+            }).collect::<Vec<_>>();
+
+            let route_events = network.route_events.iter().map(|event| {
+                models::RouteEventInfo {
+                    event: match event.kind {
+                        RouteEventKind::DefaultGatewayChanged => "default_gateway_changed".to_string(),
+                        RouteEventKind::RouteAdded => "route_added".to_string(),
+                        RouteEventKind::RouteRemoved => "route_removed".to_string(),
+                    },
+                    destination: event.destination.clone(),
+                    previous_gateway: event.previous_gateway.clone(),
+                    gateway: event.gateway.clone(),
+                    interface: event.interface.clone(),
+                    timestamp: event.timestamp,
+                }
+            }).collect::<Vec<_>>();
+
             metrics.network = Some(models::NetworkInfo {
                 interfaces: Some(interfaces),
                 stats: Some(stats),
+                dns: if dns.is_empty() { None } else { Some(dns) },
+                http_checks: if http_checks.is_empty() { None } else { Some(http_checks) },
+                link_events: if link_events.is_empty() { None } else { Some(link_events) },
+                routes: if routes.is_empty() { None } else { Some(routes) },
+                default_gateway: network.default_gateway.as_ref().map(|gw| models::GatewayInfo {
+                    address: gw.address.clone(),
+                    interface: gw.interface.clone(),
+                    mac: gw.mac.clone(),
+                }),
+                route_events: if route_events.is_empty() { None } else { Some(route_events) },
+                // Only report VPN state when there is something to say
+                vpn: if network.vpn.active || !network.vpn.clients.is_empty() {
+                    Some(models::VpnInfo {
+                        active: network.vpn.active,
+                        interfaces: network.vpn.interfaces.iter().map(|iface| models::VpnInterfaceInfo {
+                            name: iface.name.clone(),
+                            address: iface.address.clone(),
+                            rx_bytes: iface.rx_bytes,
+                            tx_bytes: iface.tx_bytes,
+                        }).collect(),
+                        clients: network.vpn.clients.clone(),
+                    })
+                } else {
+                    None
+                },
+                proxy: network.proxy.as_ref().map(|proxy| models::ProxyInfo {
+                    source: proxy.source.clone(),
+                    http: proxy.http.clone(),
+                    https: proxy.https.clone(),
+                    socks: proxy.socks.clone(),
+                    pac_url: proxy.pac_url.clone(),
+                    auto_discovery: proxy.auto_discovery,
+                    exceptions: proxy.exceptions.clone(),
+                }),
+                public_ip: network.public_ip.as_ref().map(|ip| models::PublicIpInfo {
+                    address: ip.address.clone(),
+                    changed_at: ip.changed_at,
+                    checked_at: ip.checked_at,
+                }),
+                public_ip_changes: if network.public_ip_changes.is_empty() {
+                    None
+                } else {
+                    Some(network.public_ip_changes.iter().map(|change| models::PublicIpChangeInfo {
+                        previous_address: change.previous_address.clone(),
+                        address: change.address.clone(),
+                        timestamp: change.timestamp,
+                    }).collect())
+                },
             });
         }
 
@@ -294,11 +1214,13 @@ impl ApiClient {
             let filesystems = storage.filesystem_metrics.iter().map(|fs| {
                 models::FilesystemInfo {
                     fs: fs.fs.clone(),
-                    r#type: "unknown".to_string(), // We need to add filesystem type
+                    r#type: fs.fs_type.clone(),
                     size: fs.size,
                     used: fs.used,
                     available: fs.available,
                     mount: fs.mount.clone(),
+                    encrypted: fs.encrypted,
+                    encryption_type: fs.encryption_type.clone(),
                 }
             }).collect();
 
@@ -309,7 +1231,45 @@ impl ApiClient {
                     total_write: storage.io_metrics.total_write,
                     read_bytes_per_sec: storage.io_metrics.read_bytes_per_sec,
                     write_bytes_per_sec: storage.io_metrics.write_bytes_per_sec,
+                    devices: if storage.io_metrics.devices.is_empty() {
+                        None
+                    } else {
+                        Some(storage.io_metrics.devices.iter().map(|disk| models::DiskIoInfo {
+                            device: disk.device.clone(),
+                            reads_per_sec: disk.reads_per_sec,
+                            writes_per_sec: disk.writes_per_sec,
+                            read_bytes_per_sec: disk.read_bytes_per_sec,
+                            write_bytes_per_sec: disk.write_bytes_per_sec,
+                            read_latency_ms: disk.read_latency_ms,
+                            write_latency_ms: disk.write_latency_ms,
+                            queue_depth: disk.queue_depth,
+                        }).collect())
+                    },
                 }),
+                backup: storage.backup.as_ref().map(|backup| models::BackupInfo {
+                    local_snapshot_count: backup.local_snapshot_count,
+                    latest_local_snapshot: backup.latest_local_snapshot,
+                    time_machine_configured: backup.time_machine_configured,
+                    backup_running: backup.backup_running,
+                    backup_phase: backup.backup_phase.clone(),
+                    backup_progress: backup.backup_progress,
+                    last_backup_at: backup.last_backup_at,
+                }),
+                mount_events: if storage.mount_events.is_empty() {
+                    None
+                } else {
+                    Some(storage.mount_events.iter().map(|event| models::MountEventInfo {
+                        event: match event.kind {
+                            MountEventKind::Mounted => "mounted".to_string(),
+                            MountEventKind::Unmounted => "unmounted".to_string(),
+                        },
+                        fs: event.device.clone(),
+                        mount: event.mount.clone(),
+                        r#type: event.fs_type.clone(),
+                        size: event.size,
+                        timestamp: event.timestamp,
+                    }).collect())
+                },
             });
         }
 
@@ -330,6 +1290,77 @@ impl ApiClient {
             metrics.gpu = Some(gpus);
         }
 
+        // Report displays only when one was plugged in or removed
+        if !system_info.display_events.is_empty() {
+            metrics.peripherals = Some(models::PeripheralsInfo {
+                changes: None,
+                displays: Some(system_info.displays.iter().map(display_device_info).collect()),
+                display_events: Some(system_info.display_events.iter().map(|e| models::DisplayEventInfo {
+                    event: match e.kind {
+                        DisplayEventKind::Connected => "connected",
+                        DisplayEventKind::Disconnected => "disconnected",
+                    }.to_string(),
+                    display: display_device_info(&e.display),
+                    timestamp: e.timestamp,
+                }).collect()),
+            });
+        }
+
+        // Add power state; only assertions that actually keep the node awake are reported
+        metrics.power = Some(models::PowerStatusInfo {
+            source: system_info.power.power_source.clone(),
+            sleep_blockers: system_info.power.assertions.iter()
+                .filter(|a| a.prevents_sleep)
+                .map(|a| models::PowerAssertionInfo {
+                    pid: a.pid,
+                    process: a.process.clone(),
+                    r#type: a.assertion_type.clone(),
+                    name: a.name.clone(),
+                    age_secs: a.age_secs,
+                })
+                .collect(),
+            scheduled_events: system_info.power.scheduled_events.iter()
+                .map(|e| models::ScheduledPowerEventInfo {
+                    r#type: e.event_type.clone(),
+                    time: e.time.clone(),
+                    repeating: e.repeating,
+                    owner: e.owner.clone(),
+                })
+                .collect(),
+            ups: if system_info.power.ups.is_empty() {
+                None
+            } else {
+                Some(system_info.power.ups.iter().map(|ups| models::UpsInfo {
+                    name: ups.name.clone(),
+                    id: ups.id.clone(),
+                    charge_percent: ups.charge_percent,
+                    runtime_minutes: ups.runtime_minutes,
+                    on_battery: ups.on_battery,
+                    state: ups.state.clone(),
+                }).collect())
+            },
+            adapter: system_info.power.adapter.as_ref().map(|adapter| models::AdapterInfo {
+                adapter_id: adapter.adapter_id.clone(),
+                name: adapter.name.clone(),
+                watts: adapter.watts,
+                system_power_watts: adapter.system_power_watts,
+                undersized: adapter.undersized,
+            }),
+            events: if system_info.power_events.is_empty() {
+                None
+            } else {
+                Some(system_info.power_events.iter().map(|event| models::PowerEventInfo {
+                    event: serde_json::to_value(event.kind)
+                        .ok()
+                        .and_then(|v| v.as_str().map(str::to_string))
+                        .unwrap_or_default(),
+                    source: event.source.clone(),
+                    detail: event.detail.clone(),
+                    timestamp: event.timestamp,
+                }).collect())
+            },
+        });
+
         // Add thermal info if available
         if system_info.power.battery_present {
             let battery = models::BatteryThermal {
@@ -351,6 +1382,26 @@ impl ApiClient {
             });
         }
 
-        Ok(metrics)
+        if self.field_policy.is_empty() {
+            return Ok(metrics);
+        }
+        let mut payload = serde_json::to_value(&metrics).context("Failed to serialize metrics")?;
+        self.field_policy.apply(&mut payload);
+        serde_json::from_value(payload).context("Field policy produced an invalid payload")
+    }
+} 
+
+fn display_device_info(display: &DisplayInfo) -> models::DisplayDeviceInfo {
+    models::DisplayDeviceInfo {
+        name: display.name.clone(),
+        width: display.resolution.0,
+        height: display.resolution.1,
+        refresh_rate: display.refresh_rate,
+        is_builtin: display.is_builtin,
+        serial_number: display.serial_number.clone(),
+        manufacturer: display.manufacturer.clone(),
+        manufacture_year: display.manufacture_year,
+        width_mm: display.width_mm,
+        height_mm: display.height_mm,
     }
-} 
\ No newline at end of file
+}
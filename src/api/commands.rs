@@ -0,0 +1,508 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use crate::metrics::storage::ScanOptions;
+use crate::networking::jobs::{JobSpec, JobTarget};
+use crate::networking::BenchmarkPlan;
+use crate::updater::{UpdateChannel, Version};
+use super::grpc::metrics::Command;
+use super::ApiClient;
+
+/// Commands remembered to skip ones the API hands out again before their result arrived
+const SEEN_COMMANDS: usize = 256;
+
+/// Update attempts returned by `update_history` unless the payload asks for a different number
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+
+/// A command queued for this node by the monitoring API
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeCommand {
+    pub id: String,
+    /// Command name, e.g. `scan_directory`
+    pub kind: String,
+    #[serde(default)]
+    pub payload: Value,
+    #[serde(default)]
+    pub issued_at: Option<DateTime<Utc>>,
+}
+
+impl From<Command> for NodeCommand {
+    fn from(command: Command) -> Self {
+        let payload = if command.payload_json.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(&command.payload_json).unwrap_or_else(|err| {
+                warn!("Ignoring invalid payload of command {}: {}", command.id, err);
+                Value::Null
+            })
+        };
+        Self {
+            id: command.id,
+            kind: command.kind,
+            payload,
+            issued_at: Utc.timestamp_millis_opt(command.issued_at).single(),
+        }
+    }
+}
+
+/// What a command asks the agent to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandAction {
+    CheckForUpdates,
+    /// Restore the newest update backup
+    RollbackUpdate,
+    /// Return the most recent update attempts
+    UpdateHistory { limit: usize },
+    /// A release was published; check for updates now if it is on this node's channel
+    ReleasePublished { tag_name: Option<String> },
+    /// Follow a different update channel
+    SetUpdateChannel(UpdateChannel),
+    /// Install at most `max_version` (None removes the pin); `force` allows downgrades
+    PinUpdateVersion { max_version: Option<Version>, force: bool },
+    /// Send the complete system info with the next update
+    FullSystemInfo,
+    ScanDirectory { path: PathBuf, options: ScanOptions },
+    /// Recreate a collector, dropping its accumulated state
+    RestartCollector(String),
+    /// Run a job on the discovered nodes `target` matches and report every node's outcome
+    SubmitJob { job: JobSpec, target: JobTarget },
+    /// Return the latency and clock offset of every node pinged
+    PeerLatency,
+    /// Send Wake-on-LAN packets for a node (ID, ID prefix or name), from this
+    /// node or through the discovered node `via` on the sleeping node's subnet
+    WakeNode { node: String, via: Option<String> },
+    /// Measure round trips and throughput to a discovered node (ID, ID prefix
+    /// or name) over its transfer server and return the report
+    BenchmarkLink { node: String, plan: BenchmarkPlan },
+    /// Return where the sync of FOLDER_SYNC_DIR to each of its peers stands
+    FolderSyncStatus,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScanPayload {
+    path: PathBuf,
+    top_n: Option<usize>,
+    time_limit_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct HistoryPayload {
+    limit: Option<usize>,
+}
+
+/// `release_published` payload: the tag, or a GitHub `release` webhook event relayed as is
+#[derive(Debug, Default, Deserialize)]
+struct ReleasePayload {
+    #[serde(default, rename = "tagName")]
+    tag_name: Option<String>,
+    #[serde(default)]
+    action: Option<String>,
+    #[serde(default)]
+    release: Option<GithubRelease>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GithubRelease {
+    tag_name: String,
+}
+
+/// Message pushed by the server over the WebSocket
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PushedMessage {
+    Command { command: NodeCommand },
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelPayload {
+    channel: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PinPayload {
+    /// Omitted or null to remove the pin
+    max_version: Option<String>,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestartPayload {
+    collector: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WakePayload {
+    node: String,
+    #[serde(default)]
+    via: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BenchmarkPayload {
+    node: String,
+    pings: Option<u32>,
+    /// Stream counts measured, e.g. [1, 2, 4, 8]
+    streams: Option<Vec<usize>>,
+    /// MiB sent in each run
+    run_mb: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JobPayload {
+    job: JobSpec,
+    /// Omitted to run on every discovered node
+    #[serde(default)]
+    target: JobTarget,
+}
+
+impl NodeCommand {
+    pub fn action(&self) -> Result<CommandAction> {
+        match self.kind.as_str() {
+            "check_updates" => Ok(CommandAction::CheckForUpdates),
+            "rollback_update" => Ok(CommandAction::RollbackUpdate),
+            "update_history" => {
+                let history: HistoryPayload = match &self.payload {
+                    Value::Null => HistoryPayload::default(),
+                    payload => serde_json::from_value(payload.clone()).context("Invalid update_history payload")?,
+                };
+                Ok(CommandAction::UpdateHistory { limit: history.limit.unwrap_or(DEFAULT_HISTORY_LIMIT) })
+            }
+            "release_published" => {
+                let release: ReleasePayload = match &self.payload {
+                    Value::Null => ReleasePayload::default(),
+                    payload => serde_json::from_value(payload.clone()).context("Invalid release_published payload")?,
+                };
+                // GitHub sends release events for drafts and deletions too
+                if let Some(action) = release.action.as_deref() {
+                    if !matches!(action, "published" | "released" | "prereleased") {
+                        return Err(anyhow!("Release event '{}' does not announce a release", action));
+                    }
+                }
+                let tag_name = release.tag_name.or(release.release.map(|release| release.tag_name));
+                Ok(CommandAction::ReleasePublished { tag_name })
+            }
+            "set_update_channel" => {
+                let payload: ChannelPayload =
+                    serde_json::from_value(self.payload.clone()).context("Invalid set_update_channel payload")?;
+                Ok(CommandAction::SetUpdateChannel(payload.channel.parse()?))
+            }
+            "pin_update_version" => {
+                let payload: PinPayload =
+                    serde_json::from_value(self.payload.clone()).context("Invalid pin_update_version payload")?;
+                let max_version = payload.max_version.as_deref().map(str::parse).transpose()
+                    .context("Invalid pin_update_version version")?;
+                Ok(CommandAction::PinUpdateVersion { max_version, force: payload.force })
+            }
+            "full_system_info" => Ok(CommandAction::FullSystemInfo),
+            "scan_directory" => {
+                let scan: ScanPayload = serde_json::from_value(self.payload.clone()).context("Invalid scan_directory payload")?;
+                let defaults = ScanOptions::default();
+                Ok(CommandAction::ScanDirectory {
+                    path: scan.path,
+                    options: ScanOptions {
+                        top_n: scan.top_n.unwrap_or(defaults.top_n),
+                        time_limit: scan.time_limit_secs.map(Duration::from_secs).unwrap_or(defaults.time_limit),
+                        ..defaults
+                    },
+                })
+            }
+            "restart_collector" => {
+                let restart: RestartPayload =
+                    serde_json::from_value(self.payload.clone()).context("Invalid restart_collector payload")?;
+                Ok(CommandAction::RestartCollector(restart.collector))
+            }
+            "submit_job" => {
+                let payload: JobPayload = serde_json::from_value(self.payload.clone()).context("Invalid submit_job payload")?;
+                Ok(CommandAction::SubmitJob { job: payload.job, target: payload.target })
+            }
+            "peer_latency" => Ok(CommandAction::PeerLatency),
+            "wake_node" => {
+                let wake: WakePayload = serde_json::from_value(self.payload.clone()).context("Invalid wake_node payload")?;
+                Ok(CommandAction::WakeNode { node: wake.node, via: wake.via })
+            }
+            "benchmark_link" => {
+                let payload: BenchmarkPayload =
+                    serde_json::from_value(self.payload.clone()).context("Invalid benchmark_link payload")?;
+                let defaults = BenchmarkPlan::default();
+                Ok(CommandAction::BenchmarkLink {
+                    node: payload.node,
+                    plan: BenchmarkPlan {
+                        pings: payload.pings.unwrap_or(defaults.pings),
+                        stream_counts: payload.streams.filter(|counts| !counts.is_empty()).unwrap_or(defaults.stream_counts),
+                        run_bytes: payload.run_mb.map(|mb| mb * 1024 * 1024).unwrap_or(defaults.run_bytes),
+                    },
+                })
+            }
+            "folder_sync_status" => Ok(CommandAction::FolderSyncStatus),
+            other => Err(anyhow!("Unsupported command '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandStatus {
+    Succeeded,
+    Failed,
+}
+
+/// Outcome reported to `POST /api/v1/nodes/{nodeId}/commands/{id}/result`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandResult {
+    pub status: CommandStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub completed_at: DateTime<Utc>,
+}
+
+impl CommandResult {
+    pub fn succeeded(output: Option<Value>) -> Self {
+        Self { status: CommandStatus::Succeeded, output, error: None, completed_at: Utc::now() }
+    }
+
+    pub fn failed(error: &anyhow::Error) -> Self {
+        Self {
+            status: CommandStatus::Failed,
+            output: None,
+            error: Some(format!("{:#}", error)),
+            completed_at: Utc::now(),
+        }
+    }
+
+    pub fn from_result(result: Result<Option<Value>>) -> Self {
+        match result {
+            Ok(output) => Self::succeeded(output),
+            Err(err) => Self::failed(&err),
+        }
+    }
+}
+
+/// Report `result` in the background; without an API client it is only logged
+pub fn report_result(client: Option<Arc<ApiClient>>, id: String, result: CommandResult) {
+    match result.status {
+        CommandStatus::Succeeded => info!("Command {} succeeded", id),
+        CommandStatus::Failed => warn!("Command {} failed: {}", id, result.error.as_deref().unwrap_or_default()),
+    }
+    let Some(client) = client else {
+        return;
+    };
+    tokio::spawn(async move {
+        if let Err(err) = client.report_command_result(&id, &result).await {
+            warn!("Failed to report result of command {}: {:#}", id, err);
+        }
+    });
+}
+
+/// Poll the API for queued commands every `interval` and forward new ones to `commands`
+pub fn spawn_command_poller(client: Arc<ApiClient>, interval: Duration, commands: mpsc::Sender<NodeCommand>) {
+    tokio::spawn(async move {
+        let mut seen = SeenCommands::default();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match client.fetch_commands().await {
+                Ok(pending) => {
+                    for command in pending.into_iter().filter(|c| seen.insert(&c.id)) {
+                        info!("Received command '{}' ({}) from monitoring API", command.kind, command.id);
+                        if commands.send(command).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(err) => debug!("Failed to poll commands: {:#}", err),
+            }
+        }
+    });
+}
+
+/// Forward commands the server pushes over the WebSocket to `commands`. Other
+/// messages are ignored.
+pub fn spawn_pushed_commands(mut messages: mpsc::Receiver<String>, commands: mpsc::Sender<NodeCommand>) {
+    tokio::spawn(async move {
+        let mut seen = SeenCommands::default();
+        while let Some(text) = messages.recv().await {
+            let command = match serde_json::from_str::<PushedMessage>(&text) {
+                Ok(PushedMessage::Command { command }) => command,
+                Err(err) => {
+                    debug!("Ignoring WebSocket message from server: {}", err);
+                    continue;
+                }
+            };
+            if !seen.insert(&command.id) {
+                continue;
+            }
+            info!("Received command '{}' ({}) over WebSocket", command.kind, command.id);
+            if commands.send(command).await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// Bounded set of recently received command IDs
+#[derive(Default)]
+struct SeenCommands {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl SeenCommands {
+    /// Returns false if `id` was already seen
+    fn insert(&mut self, id: &str) -> bool {
+        if !self.ids.insert(id.to_string()) {
+            return false;
+        }
+        self.order.push_back(id.to_string());
+        if self.order.len() > SEEN_COMMANDS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_command_actions() {
+        let command = |kind: &str, payload: Value| NodeCommand {
+            id: "1".to_string(),
+            kind: kind.to_string(),
+            payload,
+            issued_at: None,
+        };
+
+        assert_eq!(command("check_updates", Value::Null).action().unwrap(), CommandAction::CheckForUpdates);
+        assert_eq!(command("rollback_update", Value::Null).action().unwrap(), CommandAction::RollbackUpdate);
+        assert_eq!(
+            command("update_history", Value::Null).action().unwrap(),
+            CommandAction::UpdateHistory { limit: DEFAULT_HISTORY_LIMIT }
+        );
+        assert_eq!(
+            command("update_history", json!({ "limit": 5 })).action().unwrap(),
+            CommandAction::UpdateHistory { limit: 5 }
+        );
+        assert_eq!(
+            command("release_published", json!({ "tagName": "stable-0.3.0" })).action().unwrap(),
+            CommandAction::ReleasePublished { tag_name: Some("stable-0.3.0".to_string()) }
+        );
+        let webhook = json!({ "action": "published", "release": { "tag_name": "beta-0.4.0", "prerelease": true } });
+        assert_eq!(
+            command("release_published", webhook).action().unwrap(),
+            CommandAction::ReleasePublished { tag_name: Some("beta-0.4.0".to_string()) }
+        );
+        assert_eq!(
+            command("release_published", Value::Null).action().unwrap(),
+            CommandAction::ReleasePublished { tag_name: None }
+        );
+        assert!(command("release_published", json!({ "action": "deleted", "release": { "tag_name": "beta-0.4.0" } })).action().is_err());
+        assert_eq!(
+            command("set_update_channel", json!({ "channel": "beta" })).action().unwrap(),
+            CommandAction::SetUpdateChannel(UpdateChannel::Beta)
+        );
+        assert_eq!(
+            command("pin_update_version", json!({ "maxVersion": "0.3.1", "force": true })).action().unwrap(),
+            CommandAction::PinUpdateVersion { max_version: Some(Version::new(0, 3, 1, None, None)), force: true }
+        );
+        assert_eq!(
+            command("pin_update_version", json!({})).action().unwrap(),
+            CommandAction::PinUpdateVersion { max_version: None, force: false }
+        );
+        assert!(command("pin_update_version", json!({ "maxVersion": "latest" })).action().is_err());
+        assert_eq!(
+            command("scan_directory", json!({ "path": "/var/log", "topN": 5 })).action().unwrap(),
+            CommandAction::ScanDirectory {
+                path: PathBuf::from("/var/log"),
+                options: ScanOptions { top_n: 5, ..ScanOptions::default() },
+            }
+        );
+        assert_eq!(
+            command("restart_collector", json!({ "collector": "network" })).action().unwrap(),
+            CommandAction::RestartCollector("network".to_string())
+        );
+        assert_eq!(
+            command("submit_job", json!({ "job": { "kind": "shell", "command": "uptime" }, "target": { "capabilities": ["gpu"] } })).action().unwrap(),
+            CommandAction::SubmitJob {
+                job: JobSpec::Shell { command: "uptime".to_string(), timeout_secs: None },
+                target: JobTarget { capabilities: vec!["gpu".to_string()], nodes: vec![], ..Default::default() },
+            }
+        );
+        assert!(command("submit_job", json!({ "job": { "kind": "reboot" } })).action().is_err());
+        assert_eq!(command("peer_latency", Value::Null).action().unwrap(), CommandAction::PeerLatency);
+        assert_eq!(
+            command("wake_node", json!({ "node": "render-1", "via": "render-2" })).action().unwrap(),
+            CommandAction::WakeNode { node: "render-1".to_string(), via: Some("render-2".to_string()) }
+        );
+        assert!(command("wake_node", Value::Null).action().is_err());
+        assert_eq!(
+            command("benchmark_link", json!({ "node": "render-2", "streams": [1, 4], "runMb": 64 })).action().unwrap(),
+            CommandAction::BenchmarkLink {
+                node: "render-2".to_string(),
+                plan: BenchmarkPlan { stream_counts: vec![1, 4], run_bytes: 64 * 1024 * 1024, ..BenchmarkPlan::default() },
+            }
+        );
+        assert_eq!(command("folder_sync_status", Value::Null).action().unwrap(), CommandAction::FolderSyncStatus);
+        assert!(command("scan_directory", Value::Null).action().is_err());
+        assert!(command("reboot", Value::Null).action().is_err());
+    }
+
+    #[test]
+    fn test_grpc_command_conversion() {
+        let command = NodeCommand::from(Command {
+            id: "c1".to_string(),
+            kind: "scan_directory".to_string(),
+            payload_json: r#"{"path":"/tmp"}"#.to_string(),
+            issued_at: 1_700_000_000_000,
+        });
+        assert_eq!(command.payload, json!({ "path": "/tmp" }));
+        assert_eq!(command.issued_at.unwrap().timestamp(), 1_700_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_pushed_commands() {
+        let (messages_tx, messages_rx) = mpsc::channel(4);
+        let (commands_tx, mut commands_rx) = mpsc::channel(4);
+        spawn_pushed_commands(messages_rx, commands_tx);
+
+        let pushed = json!({ "type": "command", "command": { "id": "c1", "kind": "release_published" } }).to_string();
+        messages_tx.send(json!({ "type": "config_changed" }).to_string()).await.unwrap();
+        messages_tx.send(pushed.clone()).await.unwrap();
+        // Redelivered after a reconnect
+        messages_tx.send(pushed).await.unwrap();
+        drop(messages_tx);
+
+        let command = commands_rx.recv().await.unwrap();
+        assert_eq!((command.id.as_str(), command.kind.as_str()), ("c1", "release_published"));
+        assert!(commands_rx.recv().await.is_none());
+    }
+
+    #[test]
+    fn test_seen_commands_are_bounded() {
+        let mut seen = SeenCommands::default();
+        assert!(seen.insert("a"));
+        assert!(!seen.insert("a"));
+        for i in 0..SEEN_COMMANDS {
+            seen.insert(&i.to_string());
+        }
+        assert!(seen.insert("a"));
+    }
+}
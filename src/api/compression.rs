@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Payloads smaller than this are sent uncompressed; the savings would not cover the overhead
+pub const MIN_COMPRESS_BYTES: usize = 1024;
+
+/// Request body encodings, in order of preference when the API accepts several
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Zstd,
+    Gzip,
+    Identity,
+}
+
+/// Compression setting from configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMode {
+    /// Send uncompressed until the API advertises an encoding via `Accept-Encoding`
+    Auto,
+    /// Always use this encoding, unless the API answers 415 Unsupported Media Type
+    Fixed(ContentEncoding),
+}
+
+impl ContentEncoding {
+    /// Value for the `Content-Encoding` header
+    pub fn header_value(&self) -> &'static str {
+        match self {
+            ContentEncoding::Zstd => "zstd",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Identity => "identity",
+        }
+    }
+
+    /// Compress `body`
+    pub fn encode(&self, body: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            ContentEncoding::Identity => Ok(body.to_vec()),
+            ContentEncoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 4), Compression::new(6));
+                encoder.write_all(body)?;
+                Ok(encoder.finish()?)
+            }
+            ContentEncoding::Zstd => Ok(zstd::bulk::compress(body, 3)?),
+        }
+    }
+}
+
+impl FromStr for CompressionMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(CompressionMode::Auto),
+            "gzip" => Ok(CompressionMode::Fixed(ContentEncoding::Gzip)),
+            "zstd" => Ok(CompressionMode::Fixed(ContentEncoding::Zstd)),
+            "none" | "identity" | "off" => Ok(CompressionMode::Fixed(ContentEncoding::Identity)),
+            other => Err(anyhow!("Unknown compression mode '{}' (expected auto, gzip, zstd or none)", other)),
+        }
+    }
+}
+
+/// Pick the preferred encoding the API lists in an `Accept-Encoding` header
+/// (e.g. `zstd, gzip;q=0.8`), ignoring entries with `q=0`
+pub fn negotiate(accept_encoding: &str, available: &[ContentEncoding]) -> ContentEncoding {
+    let accepted: Vec<&str> = accept_encoding.split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';').map(str::trim);
+            let name = parts.next()?;
+            let rejected = parts.any(|param| {
+                param.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0)
+            });
+            (!rejected).then_some(name)
+        })
+        .collect();
+
+    [ContentEncoding::Zstd, ContentEncoding::Gzip]
+        .into_iter()
+        .find(|encoding| {
+            available.contains(encoding)
+                && accepted.iter().any(|name| name.eq_ignore_ascii_case(encoding.header_value()))
+        })
+        .unwrap_or(ContentEncoding::Identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_encoding() {
+        let both = [ContentEncoding::Zstd, ContentEncoding::Gzip];
+        assert_eq!(negotiate("gzip, zstd", &both), ContentEncoding::Zstd);
+        assert_eq!(negotiate("gzip, zstd", &[ContentEncoding::Gzip]), ContentEncoding::Gzip);
+        assert_eq!(negotiate("zstd;q=0, gzip;q=0.5", &both), ContentEncoding::Gzip);
+        assert_eq!(negotiate("br", &both), ContentEncoding::Identity);
+        assert_eq!("ZSTD".parse::<CompressionMode>().unwrap(), CompressionMode::Fixed(ContentEncoding::Zstd));
+        assert!("lz4".parse::<CompressionMode>().is_err());
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let body = br#"{"metrics":"payload"}"#.repeat(100);
+        let compressed = ContentEncoding::Gzip.encode(&body).unwrap();
+        assert!(compressed.len() < body.len());
+        assert_eq!(&compressed[..2], &[0x1f, 0x8b]);
+        let mut decoded = Vec::new();
+        std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(&compressed[..]), &mut decoded).unwrap();
+        assert_eq!(decoded, body);
+    }
+
+    #[test]
+    fn test_zstd_round_trip() {
+        let body = br#"{"metrics":"payload"}"#.repeat(100);
+        let compressed = ContentEncoding::Zstd.encode(&body).unwrap();
+        assert!(compressed.len() < body.len());
+        assert_eq!(zstd::bulk::decompress(&compressed, body.len()).unwrap(), body);
+    }
+}
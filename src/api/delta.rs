@@ -0,0 +1,152 @@
+use serde_json::{Map, Value};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Keys sent in every delta so the API can place it, as (object path, key)
+const ALWAYS_SENT: &[(&[&str], &str)] = &[(&[], "timestamp"), (&["system"], "nodeId")];
+
+/// Sends only what changed since the last successful upload, with a full
+/// snapshot every `full_interval` and after any failed upload.
+///
+/// Deltas carry `"delta": true`; objects are diffed recursively, arrays and
+/// scalars are replaced whole and removed keys are sent as `null`.
+pub struct DeltaEncoder {
+    full_interval: Duration,
+    baseline: Mutex<Option<Baseline>>,
+}
+
+struct Baseline {
+    payload: Value,
+    last_full: Instant,
+}
+
+/// A payload ready to send
+pub struct EncodedPayload {
+    pub body: Value,
+    pub full: bool,
+}
+
+impl DeltaEncoder {
+    pub fn new(full_interval: Duration) -> Self {
+        Self { full_interval, baseline: Mutex::new(None) }
+    }
+
+    /// Encode `payload` against the last acknowledged one; `force_full` skips the diff
+    pub fn encode(&self, payload: &Value, force_full: bool) -> EncodedPayload {
+        let baseline = self.baseline.lock().unwrap();
+        let previous = match baseline.as_ref() {
+            Some(b) if !force_full && b.last_full.elapsed() < self.full_interval => &b.payload,
+            _ => return EncodedPayload { body: payload.clone(), full: true },
+        };
+
+        let mut body = diff(previous, payload).unwrap_or_else(|| Value::Object(Map::new()));
+        for (path, key) in ALWAYS_SENT {
+            if let Some(value) = lookup(payload, path).and_then(|obj| obj.get(*key)) {
+                insert_at(&mut body, path, key, value.clone());
+            }
+        }
+        if let Value::Object(map) = &mut body {
+            map.insert("delta".to_string(), Value::Bool(true));
+        }
+        EncodedPayload { body, full: false }
+    }
+
+    /// The API accepted `payload` (the full version, not the delta)
+    pub fn acknowledge(&self, payload: Value, full: bool) {
+        let mut baseline = self.baseline.lock().unwrap();
+        let last_full = match baseline.as_ref() {
+            Some(b) if !full => b.last_full,
+            _ => Instant::now(),
+        };
+        *baseline = Some(Baseline { payload, last_full });
+    }
+
+    /// Delivery failed; the next upload is a full snapshot
+    pub fn reset(&self) {
+        *self.baseline.lock().unwrap() = None;
+    }
+}
+
+/// The parts of `current` that differ from `previous`, or None if nothing changed
+pub fn diff(previous: &Value, current: &Value) -> Option<Value> {
+    match (previous, current) {
+        (Value::Object(prev), Value::Object(curr)) => {
+            let mut changed = Map::new();
+            for (key, value) in curr {
+                match prev.get(key) {
+                    Some(old) => {
+                        if let Some(delta) = diff(old, value) {
+                            changed.insert(key.clone(), delta);
+                        }
+                    }
+                    None => {
+                        changed.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            for key in prev.keys().filter(|key| !curr.contains_key(*key)) {
+                changed.insert(key.clone(), Value::Null);
+            }
+            (!changed.is_empty()).then_some(Value::Object(changed))
+        }
+        _ if previous == current => None,
+        _ => Some(current.clone()),
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Map<String, Value>> {
+    path.iter()
+        .try_fold(value, |value, key| value.get(*key))?
+        .as_object()
+}
+
+fn insert_at(body: &mut Value, path: &[&str], key: &str, value: Value) {
+    let mut target = body;
+    for segment in path {
+        let Value::Object(map) = target else { return };
+        target = map.entry(segment.to_string()).or_insert_with(|| Value::Object(Map::new()));
+    }
+    if let Value::Object(map) = target {
+        map.insert(key.to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff() {
+        let previous = json!({ "cpu": { "load": 10, "cores": [1, 2] }, "gpu": [1], "power": { "source": "ac" } });
+        let current = json!({ "cpu": { "load": 12, "cores": [1, 2] }, "power": { "source": "ac" }, "agent": {} });
+
+        assert_eq!(
+            diff(&previous, &current),
+            Some(json!({ "cpu": { "load": 12 }, "gpu": null, "agent": {} }))
+        );
+        assert_eq!(diff(&current, &current), None);
+    }
+
+    #[test]
+    fn test_encode_sends_full_until_acknowledged() {
+        let encoder = DeltaEncoder::new(Duration::from_secs(300));
+        let first = json!({ "timestamp": "t1", "system": { "nodeId": "n", "uptime": 1, "model": "m" } });
+        let second = json!({ "timestamp": "t2", "system": { "nodeId": "n", "uptime": 2, "model": "m" } });
+
+        let encoded = encoder.encode(&first, false);
+        assert!(encoded.full);
+        encoder.acknowledge(first, true);
+
+        let encoded = encoder.encode(&second, false);
+        assert!(!encoded.full);
+        assert_eq!(
+            encoded.body,
+            json!({ "timestamp": "t2", "system": { "nodeId": "n", "uptime": 2 }, "delta": true })
+        );
+
+        assert!(encoder.encode(&second, true).full);
+        encoder.reset();
+        assert!(encoder.encode(&second, false).full);
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Value of the `X-Payload-Encryption` header on encrypted bodies
+pub const ENCRYPTION_HEADER_VALUE: &str = "age";
+
+/// Application-layer encryption of metric payloads to the backend's public key(s),
+/// so relays and TLS-terminating proxies only see ciphertext
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadEncryption {
+    recipients: Vec<String>,
+}
+
+impl PayloadEncryption {
+    /// Encrypt to the given age recipients (`age1...` X25519 public keys)
+    pub fn new(recipients: Vec<String>) -> Result<Self> {
+        if recipients.is_empty() {
+            return Err(anyhow!("No encryption recipients configured"));
+        }
+        if let Some(invalid) = recipients.iter().find(|r| !is_age_recipient(r)) {
+            return Err(anyhow!("Invalid age recipient '{}'", invalid));
+        }
+        Ok(Self { recipients })
+    }
+
+    /// Parse a comma-separated recipient list
+    pub fn from_list(list: &str) -> Result<Self> {
+        Self::new(list.split(',').map(str::trim).filter(|r| !r.is_empty()).map(str::to_string).collect())
+    }
+
+    /// Encrypt `body` with the system `age` tool
+    pub fn encrypt(&self, body: &[u8]) -> Result<Vec<u8>> {
+        let mut command = Command::new("age");
+        for recipient in &self.recipients {
+            command.args(["-r", recipient]);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run age")?;
+
+        // Feed stdin from a thread so a full stdout pipe cannot deadlock us
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("age stdin unavailable"))?;
+        let input = body.to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+        let output = child.wait_with_output()?;
+        writer.join().map_err(|_| anyhow!("age writer panicked"))??;
+        if !output.status.success() {
+            return Err(anyhow!("age exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(output.stdout)
+    }
+
+    /// Whether the `age` tool is installed and accepts the recipients
+    pub fn is_available(&self) -> bool {
+        self.encrypt(b"probe").is_ok()
+    }
+}
+
+/// Bech32 X25519 recipient as printed by `age-keygen`
+fn is_age_recipient(recipient: &str) -> bool {
+    const BECH32: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    recipient
+        .strip_prefix("age1")
+        .is_some_and(|data| data.len() == 58 && data.chars().all(|c| BECH32.contains(c)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECIPIENT: &str = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+
+    #[test]
+    fn test_recipients() {
+        let encryption = PayloadEncryption::from_list(&format!(" {}, ", RECIPIENT)).unwrap();
+        assert_eq!(encryption.recipients, vec![RECIPIENT.to_string()]);
+        assert!(PayloadEncryption::from_list("").is_err());
+        assert!(PayloadEncryption::from_list("ssh-ed25519 AAAA").is_err());
+        assert!(PayloadEncryption::from_list(&RECIPIENT.to_uppercase()).is_err());
+    }
+
+    #[test]
+    fn test_encrypt() {
+        let encryption = PayloadEncryption::from_list(RECIPIENT).unwrap();
+        if !encryption.is_available() {
+            return;
+        }
+        let ciphertext = encryption.encrypt(br#"{"metrics":"payload"}"#).unwrap();
+        assert!(ciphertext.starts_with(b"age-encryption.org/v1\n"));
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use sysinfo::System;
+
+use crate::node_identity::NodeIdentity;
+use crate::proxy;
+
+/// Registration sent to `POST /api/v1/nodes/enroll` on first boot
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnrollmentRequest {
+    pub node_id: String,
+    pub hostname: String,
+    pub platform: String,
+    pub arch: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    pub cpu_brand: String,
+    pub cpu_cores: u32,
+    pub memory_total: u64,
+    pub agent_version: String,
+}
+
+impl EnrollmentRequest {
+    /// Describe this machine for the monitoring API
+    pub fn collect(identity: &NodeIdentity) -> Self {
+        let mut sys = System::new();
+        sys.refresh_cpu();
+        sys.refresh_memory();
+
+        Self {
+            node_id: identity.node_id.clone(),
+            hostname: identity.node_name.clone(),
+            platform: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            os_version: System::long_os_version(),
+            cpu_brand: sys.cpus().first().map(|cpu| cpu.brand().to_string()).unwrap_or_default(),
+            cpu_cores: sys.cpus().len() as u32,
+            memory_total: sys.total_memory(),
+            agent_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// Node-specific credentials issued by the monitoring API at enrollment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeCredentials {
+    /// Node the credentials were issued for
+    #[serde(default)]
+    pub node_id: String,
+    /// Replaces MONITORING_API_KEY for metric uploads
+    pub api_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enrolled_at: Option<String>,
+}
+
+impl NodeCredentials {
+    /// Load stored credentials, ignoring ones issued to a different node ID
+    pub fn load(path: &Path, node_id: &str) -> Result<Option<Self>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let credentials: Self = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid node credentials in {}", path.display()))?;
+        if credentials.node_id != node_id {
+            warn!("Ignoring node credentials in {} issued for node {}", path.display(), credentials.node_id);
+            return Ok(None);
+        }
+        Ok(Some(credentials))
+    }
+
+    /// Write via a temporary file readable by the owner only
+    pub fn store(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let tmp = path.with_extension("tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path).with_context(|| format!("Failed to store node credentials in {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Default location of the node credentials, alongside the node ID
+pub fn default_credentials_path() -> PathBuf {
+    dirs::home_dir()
+        .map(|home| home.join("Library/Application Support/NodeController/credentials.json"))
+        .unwrap_or_else(|| PathBuf::from("./credentials.json"))
+}
+
+/// Register with the monitoring API using a one-time enrollment token
+pub async fn enroll(api_url: &str, token: &str, request: &EnrollmentRequest) -> Result<NodeCredentials> {
+    let client = proxy::client_builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .context("Failed to create HTTP client")?;
+    let url = format!("{}/api/v1/nodes/enroll", api_url.trim_end_matches('/'));
+
+    let response = client
+        .post(&url)
+        .bearer_auth(token)
+        .json(request)
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {}", url))?;
+    let status = response.status();
+    if !status.is_success() {
+        let message = response.text().await.unwrap_or_default();
+        return Err(anyhow!("Enrollment rejected with status {}: {}", status.as_u16(), message));
+    }
+
+    let mut credentials: NodeCredentials = response.json().await.context("Invalid enrollment response")?;
+    if credentials.api_key.is_empty() {
+        return Err(anyhow!("Enrollment response did not contain an API key"));
+    }
+    credentials.node_id = request.node_id.clone();
+    if credentials.enrolled_at.is_none() {
+        credentials.enrolled_at = Some(chrono::Utc::now().to_rfc3339());
+    }
+    Ok(credentials)
+}
+
+/// Stored credentials for this node, enrolling first if there are none and an
+/// enrollment token is available
+pub async fn load_or_enroll(
+    path: &Path,
+    api_url: &str,
+    token: Option<&str>,
+    identity: &NodeIdentity,
+) -> Result<Option<NodeCredentials>> {
+    if let Some(credentials) = NodeCredentials::load(path, &identity.node_id)? {
+        return Ok(Some(credentials));
+    }
+    let Some(token) = token else {
+        return Ok(None);
+    };
+
+    info!("Enrolling node {} with the monitoring API", identity.node_id);
+    let credentials = enroll(api_url, token, &EnrollmentRequest::collect(identity)).await?;
+    credentials.store(path)?;
+    info!("Node enrolled, credentials stored in {}", path.display());
+    Ok(Some(credentials))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_credentials_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state/credentials.json");
+        let credentials = NodeCredentials {
+            node_id: "node-1".to_string(),
+            api_key: "secret".to_string(),
+            enrolled_at: None,
+        };
+        credentials.store(&path).unwrap();
+
+        assert_eq!(NodeCredentials::load(&path, "node-1").unwrap(), Some(credentials));
+        assert_eq!(NodeCredentials::load(&path, "node-2").unwrap(), None);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+}
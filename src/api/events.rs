@@ -0,0 +1,207 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
+use crate::metrics::system::types::PeripheralDevice;
+use super::client::ApiRejected;
+use super::spool::MetricsSpool;
+use super::ApiClient;
+
+/// Events sent per request to `POST /api/v1/events`
+const BATCH_SIZE: usize = 50;
+/// Redelivery attempts back off up to this delay
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Spooled events are also retried on this schedule without new events arriving
+const IDLE_RETRY: Duration = Duration::from_secs(30);
+
+/// A discrete agent event, reported separately from periodic metrics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentEvent {
+    /// Unique per event so the API can drop redelivered duplicates
+    pub id: String,
+    /// Event name, e.g. `link`, `alert` or `crash_detected`
+    pub kind: String,
+    pub node_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub data: Value,
+}
+
+/// Disk-backed outbox for agent events. Events stay queued until the API
+/// accepted them, so each one is delivered at least once.
+pub struct EventQueue {
+    spool: MetricsSpool,
+    node_id: String,
+    notify: Notify,
+}
+
+impl EventQueue {
+    pub fn new(dir: PathBuf, max_bytes: u64, node_id: String) -> Result<Self> {
+        Ok(Self {
+            spool: MetricsSpool::new(dir, max_bytes)?,
+            node_id,
+            notify: Notify::new(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.spool.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spool.is_empty()
+    }
+
+    /// Queue an event for delivery
+    pub fn publish(&self, kind: &str, data: &Value) -> Result<AgentEvent> {
+        let event = AgentEvent {
+            id: Uuid::new_v4().to_string(),
+            kind: kind.to_string(),
+            node_id: self.node_id.clone(),
+            timestamp: Utc::now(),
+            data: data.clone(),
+        };
+        let body = serde_json::to_vec(&event)?;
+        // Spool files are named by timestamp; step past events queued in the same instant
+        let mut at = event.timestamp;
+        while !self.spool.enqueue(at, &body)? {
+            at += ChronoDuration::nanoseconds(1);
+        }
+        self.notify.notify_one();
+        Ok(event)
+    }
+
+    /// Deliver queued events oldest-first until the queue is empty or the API fails
+    async fn flush(&self, client: &ApiClient) -> Result<()> {
+        loop {
+            let batch = self.spool.peek_batch(BATCH_SIZE)?;
+            if batch.is_empty() {
+                return Ok(());
+            }
+            let events: Vec<Value> = batch
+                .iter()
+                .filter_map(|payload| serde_json::from_slice(&payload.body).ok())
+                .collect();
+
+            match client.post_events(&events).await {
+                Ok(()) => debug!("Delivered {} event(s)", events.len()),
+                Err(err) if err.downcast_ref::<ApiRejected>().is_some() => {
+                    warn!("Dropping {} event(s) rejected by the API: {:#}", batch.len(), err);
+                }
+                Err(err) => return Err(err),
+            }
+            for payload in &batch {
+                self.spool.remove(payload)?;
+            }
+        }
+    }
+}
+
+/// Deliver events from `queue` to the API in the background, retrying with backoff
+pub fn spawn_event_delivery(client: Arc<ApiClient>, queue: Arc<EventQueue>) {
+    tokio::spawn(async move {
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            match queue.flush(&client).await {
+                Ok(()) => {
+                    backoff = Duration::from_secs(1);
+                    let _ = tokio::time::timeout(IDLE_RETRY, queue.notify.notified()).await;
+                }
+                Err(err) => {
+                    warn!("Failed to deliver events ({} queued), retrying in {}s: {:#}",
+                          queue.len(), backoff.as_secs(), err);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    });
+}
+
+/// Events about how the previous run ended, based on marker files in `state_dir`:
+/// `crash_detected` if it did not shut down cleanly and `update_applied` if the
+/// agent version changed since.
+pub fn startup_events(state_dir: &Path, version: &str) -> Result<Vec<(&'static str, Value)>> {
+    fs::create_dir_all(state_dir).with_context(|| format!("Failed to create {}", state_dir.display()))?;
+    let mut events = Vec::new();
+
+    let marker = state_dir.join("running");
+    if let Ok(previous) = fs::read_to_string(&marker) {
+        warn!("Previous run did not shut down cleanly");
+        let previous: Value = serde_json::from_str(&previous).unwrap_or(Value::Null);
+        events.push(("crash_detected", json!({ "previousRun": previous })));
+    }
+    let run = json!({ "pid": std::process::id(), "version": version, "startedAt": Utc::now() });
+    fs::write(&marker, run.to_string()).with_context(|| format!("Failed to write {}", marker.display()))?;
+
+    let version_file = state_dir.join("last_version");
+    match fs::read_to_string(&version_file) {
+        Ok(previous) if previous.trim() != version => {
+            info!("Agent updated from {} to {}", previous.trim(), version);
+            events.push(("update_applied", json!({ "previousVersion": previous.trim(), "version": version })));
+        }
+        _ => {}
+    }
+    fs::write(&version_file, version).with_context(|| format!("Failed to write {}", version_file.display()))?;
+
+    Ok(events)
+}
+
+/// Remove the run marker so the next start does not report a crash
+pub fn mark_clean_shutdown(state_dir: &Path) {
+    if let Err(err) = fs::remove_file(state_dir.join("running")) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            warn!("Failed to remove run marker: {}", err);
+        }
+    }
+}
+
+/// Devices in `current` but not `previous`, and the other way around, matched by ID
+pub fn peripheral_changes<'a>(
+    previous: &'a [PeripheralDevice],
+    current: &'a [PeripheralDevice],
+) -> (Vec<&'a PeripheralDevice>, Vec<&'a PeripheralDevice>) {
+    let added = current.iter().filter(|d| !previous.iter().any(|p| p.id == d.id)).collect();
+    let removed = previous.iter().filter(|p| !current.iter().any(|d| d.id == p.id)).collect();
+    (added, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_events_queue_in_order() {
+        let dir = tempdir().unwrap();
+        let queue = EventQueue::new(dir.path().join("events"), 1024 * 1024, "node-1".to_string()).unwrap();
+        for i in 0..3 {
+            queue.publish("alert", &json!({ "n": i })).unwrap();
+        }
+
+        let batch = queue.spool.peek_batch(BATCH_SIZE).unwrap();
+        let events: Vec<AgentEvent> = batch.iter().map(|p| serde_json::from_slice(&p.body).unwrap()).collect();
+        assert_eq!(events.iter().map(|e| e.data["n"].as_i64().unwrap()).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert!(events.iter().all(|e| e.kind == "alert" && e.node_id == "node-1"));
+        assert_ne!(events[0].id, events[1].id);
+    }
+
+    #[test]
+    fn test_startup_events() {
+        let dir = tempdir().unwrap();
+        assert!(startup_events(dir.path(), "0.2.0").unwrap().is_empty());
+        mark_clean_shutdown(dir.path());
+        assert!(startup_events(dir.path(), "0.2.0").unwrap().is_empty());
+
+        // Not shut down cleanly, then restarted with a new version
+        let kinds: Vec<_> = startup_events(dir.path(), "0.3.0").unwrap().into_iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, vec!["crash_detected", "update_applied"]);
+    }
+}
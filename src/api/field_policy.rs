@@ -0,0 +1,149 @@
+use serde_json::Value;
+
+/// Replacement for redacted strings
+pub const REDACTED: &str = "[redacted]";
+
+/// Allow/deny list for payload fields, for deployments that must not report
+/// identifiers such as serial numbers or MAC addresses.
+///
+/// Patterns are dot-separated JSON keys as sent to the API, e.g.
+/// `peripherals.displays.serialNumber`. `*` matches any single key and `**` any
+/// number of keys; arrays are transparent, so a pattern applies to every element.
+///
+/// Filtered fields keep their type so the payload still matches the schema:
+/// strings become `[redacted]` (timestamps the epoch), numbers `0`, booleans
+/// `false`, arrays empty and objects are blanked field by field. Deny wins over
+/// allow; an empty allow list allows everything.
+#[derive(Debug, Clone, Default)]
+pub struct FieldPolicy {
+    allow: Vec<Vec<String>>,
+    deny: Vec<Vec<String>>,
+}
+
+impl FieldPolicy {
+    pub fn new(allow: &[String], deny: &[String]) -> Self {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| p.trim())
+                .filter(|p| !p.is_empty())
+                .map(|p| p.split('.').map(str::to_string).collect())
+                .collect()
+        };
+        Self { allow: parse(allow), deny: parse(deny) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Filter `payload` in place
+    pub fn apply(&self, payload: &mut Value) {
+        self.walk(payload, &mut Vec::new(), self.allow.is_empty());
+    }
+
+    fn walk(&self, value: &mut Value, path: &mut Vec<String>, allowed: bool) {
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map.iter_mut() {
+                    path.push(key.clone());
+                    if self.deny.iter().any(|p| matches(p, path)) {
+                        blank(child);
+                    } else if allowed || self.allow.iter().any(|p| matches(p, path)) {
+                        self.walk(child, path, true);
+                    } else if self.allow.iter().any(|p| could_match(p, path)) {
+                        self.walk(child, path, false);
+                    } else {
+                        blank(child);
+                    }
+                    path.pop();
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    self.walk(item, path, allowed);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether `pattern` matches all of `path`
+fn matches(pattern: &[String], path: &[String]) -> bool {
+    match (pattern.first().map(String::as_str), path.first()) {
+        (None, None) => true,
+        (Some("**"), _) => {
+            matches(&pattern[1..], path) || (!path.is_empty() && matches(pattern, &path[1..]))
+        }
+        (Some(segment), Some(key)) => (segment == "*" || segment == key) && matches(&pattern[1..], &path[1..]),
+        _ => false,
+    }
+}
+
+/// Whether `pattern` could match some descendant of `path`
+fn could_match(pattern: &[String], path: &[String]) -> bool {
+    match (pattern.first().map(String::as_str), path.first()) {
+        (Some(_), None) => true,
+        (Some("**"), _) => true,
+        (Some(segment), Some(key)) => (segment == "*" || segment == key) && could_match(&pattern[1..], &path[1..]),
+        (None, _) => false,
+    }
+}
+
+/// Replace a value with an empty one of the same type
+fn blank(value: &mut Value) {
+    match value {
+        // Keep timestamps parseable
+        Value::String(s) if chrono::DateTime::parse_from_rfc3339(s).is_ok() => {
+            *s = "1970-01-01T00:00:00Z".to_string()
+        }
+        Value::String(s) => *s = REDACTED.to_string(),
+        Value::Number(n) => *n = 0.into(),
+        Value::Bool(b) => *b = false,
+        Value::Array(items) => items.clear(),
+        Value::Object(map) => map.values_mut().for_each(blank),
+        Value::Null => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> FieldPolicy {
+        let owned = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        FieldPolicy::new(&owned(allow), &owned(deny))
+    }
+
+    #[test]
+    fn test_deny_redacts_through_arrays() {
+        let mut payload = json!({
+            "peripherals": { "displays": [{ "name": "Studio Display", "serialNumber": "ABC123" }] },
+            "network": { "interfaces": [{ "name": "en0", "mac": "aa:bb", "speed": 1000 }] },
+        });
+        policy(&[], &["**.serialNumber", "network.interfaces.mac"]).apply(&mut payload);
+
+        assert_eq!(payload["peripherals"]["displays"][0]["serialNumber"], REDACTED);
+        assert_eq!(payload["peripherals"]["displays"][0]["name"], "Studio Display");
+        assert_eq!(payload["network"]["interfaces"][0]["mac"], REDACTED);
+        assert_eq!(payload["network"]["interfaces"][0]["speed"], 1000);
+    }
+
+    #[test]
+    fn test_allow_list_blanks_everything_else() {
+        let mut payload = json!({
+            "timestamp": "2024-01-01T00:00:00Z",
+            "system": { "hostname": "studio", "uptime": 10, "loadavg": [1.0], "clock": { "checkedAt": "2024-01-01T00:00:00Z" } },
+            "cpu": { "load": { "current": 12.5 } },
+        });
+        policy(&["timestamp", "system.uptime", "cpu"], &["cpu.load.current"]).apply(&mut payload);
+
+        assert_eq!(payload, json!({
+            "timestamp": "2024-01-01T00:00:00Z",
+            "system": { "hostname": REDACTED, "uptime": 10, "loadavg": [], "clock": { "checkedAt": "1970-01-01T00:00:00Z" } },
+            "cpu": { "load": { "current": 0 } },
+        }));
+    }
+}
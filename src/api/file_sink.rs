@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use log::info;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use super::sink::{MetricsBatch, MetricsSink};
+
+/// Appends each batch as one JSON line to a local file.
+///
+/// Once the file grows past `max_bytes` it is renamed to `<file>.1`,
+/// replacing the previous rotation.
+pub struct FileSink {
+    path: PathBuf,
+    max_bytes: u64,
+    lock: Mutex<()>,
+}
+
+impl FileSink {
+    pub fn new(path: PathBuf, max_bytes: u64) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        }
+        Ok(Self { path, max_bytes, lock: Mutex::new(()) })
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(".1");
+        PathBuf::from(name)
+    }
+
+    fn append(&self, line: &[u8]) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+
+        let size = fs::metadata(&self.path).map_or(0, |meta| meta.len());
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            let rotated = self.rotated_path();
+            fs::rename(&self.path, &rotated)
+                .with_context(|| format!("Failed to rotate {}", self.path.display()))?;
+            info!("Rotated metrics file to {}", rotated.display());
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        file.write_all(line)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+#[async_trait]
+impl MetricsSink for FileSink {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn send(&self, batch: &MetricsBatch<'_>) -> Result<()> {
+        let mut line = serde_json::to_vec(&serde_json::json!({
+            "timestamp": Utc::now(),
+            "metrics": batch,
+        }))?;
+        line.push(b'\n');
+        self.append(&line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_and_rotate() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FileSink::new(dir.path().join("metrics.jsonl"), 10).unwrap();
+
+        sink.append(b"first\n").unwrap();
+        sink.append(b"second\n").unwrap();
+        sink.append(b"third\n").unwrap();
+
+        assert_eq!(fs::read_to_string(dir.path().join("metrics.jsonl")).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(dir.path().join("metrics.jsonl.1")).unwrap(), "second\n");
+    }
+}
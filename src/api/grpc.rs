@@ -0,0 +1,792 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use reqwest::Url;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::transport::{Channel, Endpoint};
+
+use super::models;
+
+// Import generated protobuf code
+pub mod metrics {
+    tonic::include_proto!("metrics");
+}
+
+use metrics::metrics_service_client::MetricsServiceClient;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(60);
+
+/// gRPC connection to the monitoring service.
+///
+/// Metric uploads and the server's command stream share one HTTP/2 channel;
+/// a background task keeps the command stream open and resubscribes with
+/// exponential backoff when it drops.
+pub struct GrpcTransport {
+    client: MetricsServiceClient<Channel>,
+    api_key: MetadataValue<Ascii>,
+    node_id: MetadataValue<Ascii>,
+}
+
+impl GrpcTransport {
+    /// Set up a lazily connected channel to `url` (`http://` only, TLS is not
+    /// built in). Commands streamed by the server are forwarded to `commands` if given.
+    pub fn connect(
+        url: Url,
+        api_key: &str,
+        node_id: &str,
+        commands: Option<mpsc::Sender<metrics::Command>>,
+    ) -> Result<Self> {
+        match url.scheme() {
+            "http" => {}
+            "https" => return Err(anyhow!("gRPC over TLS is not supported, use an http:// endpoint")),
+            other => return Err(anyhow!("Unsupported gRPC scheme '{}'", other)),
+        }
+
+        let channel = Endpoint::from_shared(url.to_string())
+            .context("Invalid gRPC endpoint")?
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .connect_lazy();
+
+        let transport = Self {
+            client: MetricsServiceClient::new(channel),
+            api_key: api_key.parse().context("Invalid API key format")?,
+            node_id: node_id.parse().context("Invalid node ID format")?,
+        };
+
+        if let Some(commands) = commands {
+            tokio::spawn(stream_commands(transport.clone_handle(), url, commands));
+        }
+
+        Ok(transport)
+    }
+
+    /// Another handle on the same channel
+    fn clone_handle(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            api_key: self.api_key.clone(),
+            node_id: self.node_id.clone(),
+        }
+    }
+
+    fn request<T>(&self, message: T) -> tonic::Request<T> {
+        let mut request = tonic::Request::new(message);
+        if !self.api_key.is_empty() {
+            request.metadata_mut().insert("x-api-key", self.api_key.clone());
+        }
+        request.metadata_mut().insert("x-node-id", self.node_id.clone());
+        request
+    }
+
+    /// Upload one metrics sample, authenticated with `bearer` if given
+    pub async fn submit(&self, metrics: &models::SystemMetrics, bearer: Option<String>) -> Result<metrics::SubmitResponse> {
+        let mut request = self.request(metrics::SystemMetrics::from(metrics));
+        if let Some(token) = bearer {
+            let value = format!("Bearer {}", token).parse().context("Invalid access token format")?;
+            request.metadata_mut().insert("authorization", value);
+        }
+        let response = self
+            .client
+            .clone()
+            .submit_metrics(request)
+            .await
+            .map_err(|status| anyhow!("gRPC SubmitMetrics failed ({:?}): {}", status.code(), status.message()))?
+            .into_inner();
+
+        if !response.success {
+            return Err(anyhow!("Monitoring service did not accept metrics for node {}", response.node));
+        }
+        Ok(response)
+    }
+}
+
+async fn stream_commands(transport: GrpcTransport, url: Url, commands: mpsc::Sender<metrics::Command>) {
+    let mut delay = RECONNECT_MIN;
+    loop {
+        let subscription = metrics::CommandSubscription {
+            node_id: transport.node_id.to_str().unwrap_or_default().to_string(),
+        };
+        match transport.client.clone().stream_commands(transport.request(subscription)).await {
+            Ok(response) => {
+                info!("Subscribed to commands from {}", url);
+                delay = RECONNECT_MIN;
+                let mut stream = response.into_inner();
+                loop {
+                    match stream.message().await {
+                        Ok(Some(command)) => {
+                            if commands.send(command).await.is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => {
+                            info!("Command stream from {} closed", url);
+                            break;
+                        }
+                        Err(status) => {
+                            warn!("Command stream from {} dropped: {}", url, status.message());
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(status) if status.code() == tonic::Code::Unimplemented => {
+                info!("{} does not stream commands", url);
+                return;
+            }
+            Err(status) => debug!("Command subscription to {} failed: {}", url, status.message()),
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(RECONNECT_MAX);
+    }
+}
+
+fn millis(timestamp: &DateTime<Utc>) -> i64 {
+    timestamp.timestamp_millis()
+}
+
+impl From<&models::SystemMetrics> for metrics::SystemMetrics {
+    fn from(m: &models::SystemMetrics) -> Self {
+        Self {
+            timestamp: millis(&m.timestamp),
+            system: Some((&m.system).into()),
+            cpu: Some((&m.cpu).into()),
+            memory: Some((&m.memory).into()),
+            gpu: m.gpu.iter().flatten().map(Into::into).collect(),
+            network: m.network.as_ref().map(Into::into),
+            thermal: m.thermal.as_ref().map(Into::into),
+            power: m.power.as_ref().map(Into::into),
+            storage: m.storage.as_ref().map(Into::into),
+            peripherals: m.peripherals.as_ref().map(Into::into),
+            apple_silicon: m.apple_silicon.as_ref().map(Into::into),
+            agent: m.agent.as_ref().map(Into::into),
+            payload_id: m.payload_id.clone().unwrap_or_default(),
+            sequence: m.sequence.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<&models::SystemInfo> for metrics::SystemInfo {
+    fn from(s: &models::SystemInfo) -> Self {
+        Self {
+            node_id: s.node_id.clone(),
+            hostname: s.hostname.clone(),
+            platform: s.platform.clone(),
+            release: s.release.clone(),
+            uptime: s.uptime,
+            loadavg: s.loadavg.clone(),
+            is_apple_silicon: s.is_apple_silicon,
+            model: s.model.clone(),
+            clock: s.clock.as_ref().map(|c| metrics::ClockInfo {
+                ntp_server: c.ntp_server.clone(),
+                offset_ms: c.offset_ms,
+                round_trip_ms: c.round_trip_ms,
+                stratum: c.stratum.into(),
+                checked_at: millis(&c.checked_at),
+            }),
+            labels: s.labels.clone().into_iter().collect(),
+        }
+    }
+}
+
+impl From<&models::CpuInfo> for metrics::CpuInfo {
+    fn from(c: &models::CpuInfo) -> Self {
+        Self {
+            info: Some(metrics::CpuHardwareInfo {
+                manufacturer: c.info.manufacturer.clone(),
+                brand: c.info.brand.clone(),
+                physical_cores: c.info.cores.physical,
+                logical_cores: c.info.cores.logical,
+                base_speed: c.info.speed.base,
+                max_speed: c.info.speed.max,
+                current_speed: c.info.speed.current.clone().unwrap_or_default(),
+            }),
+            load: Some(metrics::CpuLoadInfo {
+                current: c.load.current,
+                user: c.load.user,
+                system: c.load.system,
+                cores: c
+                    .load
+                    .cores
+                    .iter()
+                    .flatten()
+                    .map(|core| metrics::CoreLoadInfo {
+                        number: core.number,
+                        load: core.load,
+                        user: core.user,
+                        system: core.system,
+                    })
+                    .collect(),
+            }),
+            temperature: c.temperature.as_ref().map(|t| metrics::CpuTemperatureInfo {
+                main: t.main,
+                cores: t.cores.clone().unwrap_or_default(),
+                max: t.max,
+            }),
+            scheduler: c.scheduler.as_ref().map(|s| metrics::CpuSchedulerInfo {
+                run_queue: s.run_queue,
+                blocked: s.blocked,
+                context_switches_per_sec: s.context_switches_per_sec,
+                interrupts_per_sec: s.interrupts_per_sec,
+            }),
+        }
+    }
+}
+
+impl From<&models::MemoryInfo> for metrics::MemoryInfo {
+    fn from(m: &models::MemoryInfo) -> Self {
+        Self {
+            total: m.total,
+            used: m.used,
+            active: m.active,
+            available: m.available,
+            swap: m.swap.as_ref().map(|s| metrics::SwapInfo { total: s.total, used: s.used }),
+        }
+    }
+}
+
+impl From<&models::GpuInfo> for metrics::GpuInfo {
+    fn from(g: &models::GpuInfo) -> Self {
+        Self {
+            model: g.model.clone(),
+            vendor: g.vendor.clone(),
+            vram: g.vram.as_ref().map(|v| metrics::GpuVramInfo {
+                total: v.total,
+                used: v.used,
+                free: v.free,
+            }),
+        }
+    }
+}
+
+impl From<&models::NetworkInfo> for metrics::NetworkInfo {
+    fn from(n: &models::NetworkInfo) -> Self {
+        Self {
+            interfaces: n
+                .interfaces
+                .iter()
+                .flatten()
+                .map(|i| metrics::NetworkInterface {
+                    name: i.name.clone(),
+                    r#type: i.r#type.clone(),
+                    mac: i.mac.clone(),
+                    ipv4: i.ipv4.clone(),
+                    ipv6: i.ipv6.clone(),
+                    speed: i.speed,
+                    status: i.status.clone(),
+                })
+                .collect(),
+            stats: n
+                .stats
+                .iter()
+                .flatten()
+                .map(|s| metrics::NetworkStats {
+                    interface: s.interface.clone(),
+                    rx_sec: s.rx_sec,
+                    tx_sec: s.tx_sec,
+                    rx_bytes: s.rx_bytes,
+                    tx_bytes: s.tx_bytes,
+                    errors: s.errors,
+                })
+                .collect(),
+            dns: n
+                .dns
+                .iter()
+                .flatten()
+                .map(|d| metrics::DnsProbeInfo {
+                    hostname: d.hostname.clone(),
+                    resolved: d.resolved,
+                    latency_ms: d.latency_ms,
+                    addresses: d.addresses.clone(),
+                    error: d.error.clone(),
+                })
+                .collect(),
+            http_checks: n
+                .http_checks
+                .iter()
+                .flatten()
+                .map(|h| metrics::HttpCheckInfo {
+                    url: h.url.clone(),
+                    success: h.success,
+                    status: h.status.map(Into::into),
+                    expected_status: h.expected_status.into(),
+                    latency_ms: h.latency_ms,
+                    cert_expires_at: h.cert_expires_at.as_ref().map(millis),
+                    cert_days_remaining: h.cert_days_remaining,
+                    error: h.error.clone(),
+                })
+                .collect(),
+            link_events: n
+                .link_events
+                .iter()
+                .flatten()
+                .map(|e| metrics::LinkEventInfo {
+                    interface: e.interface.clone(),
+                    r#type: e.r#type.clone(),
+                    event: e.event.clone(),
+                    previous_status: e.previous_status.clone(),
+                    status: e.status.clone(),
+                    timestamp: millis(&e.timestamp),
+                })
+                .collect(),
+            routes: n
+                .routes
+                .iter()
+                .flatten()
+                .map(|r| metrics::RouteInfo {
+                    destination: r.destination.clone(),
+                    gateway: r.gateway.clone(),
+                    flags: r.flags.clone(),
+                    interface: r.interface.clone(),
+                })
+                .collect(),
+            default_gateway: n.default_gateway.as_ref().map(|g| metrics::GatewayInfo {
+                address: g.address.clone(),
+                interface: g.interface.clone(),
+                mac: g.mac.clone(),
+            }),
+            route_events: n
+                .route_events
+                .iter()
+                .flatten()
+                .map(|e| metrics::RouteEventInfo {
+                    event: e.event.clone(),
+                    destination: e.destination.clone(),
+                    previous_gateway: e.previous_gateway.clone(),
+                    gateway: e.gateway.clone(),
+                    interface: e.interface.clone(),
+                    timestamp: millis(&e.timestamp),
+                })
+                .collect(),
+            vpn: n.vpn.as_ref().map(|v| metrics::VpnInfo {
+                active: v.active,
+                interfaces: v
+                    .interfaces
+                    .iter()
+                    .map(|i| metrics::VpnInterfaceInfo {
+                        name: i.name.clone(),
+                        address: i.address.clone(),
+                        rx_bytes: i.rx_bytes,
+                        tx_bytes: i.tx_bytes,
+                    })
+                    .collect(),
+                clients: v.clients.clone(),
+            }),
+            proxy: n.proxy.as_ref().map(|p| metrics::ProxyInfo {
+                source: p.source.clone(),
+                http: p.http.clone(),
+                https: p.https.clone(),
+                socks: p.socks.clone(),
+                pac_url: p.pac_url.clone(),
+                auto_discovery: p.auto_discovery,
+                exceptions: p.exceptions.clone(),
+            }),
+            public_ip: n.public_ip.as_ref().map(|p| metrics::PublicIpInfo {
+                address: p.address.clone(),
+                changed_at: p.changed_at.as_ref().map(millis),
+                checked_at: millis(&p.checked_at),
+            }),
+            public_ip_changes: n
+                .public_ip_changes
+                .iter()
+                .flatten()
+                .map(|c| metrics::PublicIpChangeInfo {
+                    previous_address: c.previous_address.clone(),
+                    address: c.address.clone(),
+                    timestamp: millis(&c.timestamp),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&models::ThermalInfo> for metrics::ThermalInfo {
+    fn from(t: &models::ThermalInfo) -> Self {
+        Self {
+            chassis_temperature: t.chassis.as_ref().map(|c| c.temperature),
+            battery: t.battery.as_ref().map(|b| metrics::BatteryThermal {
+                temperature: b.temperature,
+                health: b.health,
+                cycle_count: b.cycle_count,
+                is_charging: b.is_charging,
+                voltage: b.voltage,
+                percent: b.percent,
+            }),
+            fan_speed: t.fan.as_ref().map(|f| f.speed),
+            pressure: t.pressure.clone(),
+        }
+    }
+}
+
+impl From<&models::PowerStatusInfo> for metrics::PowerStatusInfo {
+    fn from(p: &models::PowerStatusInfo) -> Self {
+        Self {
+            source: p.source.clone(),
+            sleep_blockers: p
+                .sleep_blockers
+                .iter()
+                .map(|a| metrics::PowerAssertionInfo {
+                    pid: a.pid,
+                    process: a.process.clone(),
+                    r#type: a.r#type.clone(),
+                    name: a.name.clone(),
+                    age_secs: a.age_secs,
+                })
+                .collect(),
+            scheduled_events: p
+                .scheduled_events
+                .iter()
+                .map(|e| metrics::ScheduledPowerEventInfo {
+                    r#type: e.r#type.clone(),
+                    time: e.time.clone(),
+                    repeating: e.repeating,
+                    owner: e.owner.clone(),
+                })
+                .collect(),
+            ups: p
+                .ups
+                .iter()
+                .flatten()
+                .map(|u| metrics::UpsInfo {
+                    name: u.name.clone(),
+                    id: u.id.clone(),
+                    charge_percent: u.charge_percent,
+                    runtime_minutes: u.runtime_minutes,
+                    on_battery: u.on_battery,
+                    state: u.state.clone(),
+                })
+                .collect(),
+            adapter: p.adapter.as_ref().map(|a| metrics::AdapterInfo {
+                adapter_id: a.adapter_id.clone(),
+                name: a.name.clone(),
+                watts: a.watts,
+                system_power_watts: a.system_power_watts,
+                undersized: a.undersized,
+            }),
+            events: p
+                .events
+                .iter()
+                .flatten()
+                .map(|e| metrics::PowerEventInfo {
+                    event: e.event.clone(),
+                    source: e.source.clone(),
+                    detail: e.detail.clone(),
+                    timestamp: millis(&e.timestamp),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&models::StorageInfo> for metrics::StorageInfo {
+    fn from(s: &models::StorageInfo) -> Self {
+        Self {
+            filesystems: s
+                .filesystems
+                .iter()
+                .flatten()
+                .map(|f| metrics::FilesystemInfo {
+                    fs: f.fs.clone(),
+                    r#type: f.r#type.clone(),
+                    size: f.size,
+                    used: f.used,
+                    available: f.available,
+                    mount: f.mount.clone(),
+                    encrypted: f.encrypted,
+                    encryption_type: f.encryption_type.clone(),
+                })
+                .collect(),
+            io: s.io.as_ref().map(|io| metrics::IoInfo {
+                total_read: io.total_read,
+                total_write: io.total_write,
+                read_bytes_per_sec: io.read_bytes_per_sec,
+                write_bytes_per_sec: io.write_bytes_per_sec,
+                devices: io
+                    .devices
+                    .iter()
+                    .flatten()
+                    .map(|d| metrics::DiskIoInfo {
+                        device: d.device.clone(),
+                        reads_per_sec: d.reads_per_sec,
+                        writes_per_sec: d.writes_per_sec,
+                        read_bytes_per_sec: d.read_bytes_per_sec,
+                        write_bytes_per_sec: d.write_bytes_per_sec,
+                        read_latency_ms: d.read_latency_ms,
+                        write_latency_ms: d.write_latency_ms,
+                        queue_depth: d.queue_depth,
+                    })
+                    .collect(),
+            }),
+            backup: s.backup.as_ref().map(|b| metrics::BackupInfo {
+                local_snapshot_count: b.local_snapshot_count as u64,
+                latest_local_snapshot: b.latest_local_snapshot.as_ref().map(millis),
+                time_machine_configured: b.time_machine_configured,
+                backup_running: b.backup_running,
+                backup_phase: b.backup_phase.clone(),
+                backup_progress: b.backup_progress,
+                last_backup_at: b.last_backup_at.as_ref().map(millis),
+            }),
+            mount_events: s
+                .mount_events
+                .iter()
+                .flatten()
+                .map(|e| metrics::MountEventInfo {
+                    event: e.event.clone(),
+                    fs: e.fs.clone(),
+                    mount: e.mount.clone(),
+                    r#type: e.r#type.clone(),
+                    size: e.size,
+                    timestamp: millis(&e.timestamp),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&models::PeripheralsInfo> for metrics::PeripheralsInfo {
+    fn from(p: &models::PeripheralsInfo) -> Self {
+        Self {
+            changes: p.changes.as_ref().map(|c| metrics::PeripheralChanges {
+                added: c.added.as_ref().map(Into::into),
+                removed: c.removed.as_ref().map(Into::into),
+                changed: c.changed.as_ref().map(Into::into),
+            }),
+            displays: p.displays.iter().flatten().map(Into::into).collect(),
+            display_events: p
+                .display_events
+                .iter()
+                .flatten()
+                .map(|e| metrics::DisplayEventInfo {
+                    event: e.event.clone(),
+                    display: Some((&e.display).into()),
+                    timestamp: millis(&e.timestamp),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&models::PeripheralChangesByType> for metrics::PeripheralChangesByType {
+    fn from(c: &models::PeripheralChangesByType) -> Self {
+        let json = |devices: &Option<Vec<serde_json::Value>>| -> Vec<String> {
+            devices.iter().flatten().map(|device| device.to_string()).collect()
+        };
+        Self {
+            usb: json(&c.usb),
+            bluetooth: json(&c.bluetooth),
+            audio: json(&c.audio),
+        }
+    }
+}
+
+impl From<&models::DisplayDeviceInfo> for metrics::DisplayDeviceInfo {
+    fn from(d: &models::DisplayDeviceInfo) -> Self {
+        Self {
+            name: d.name.clone(),
+            width: d.width,
+            height: d.height,
+            refresh_rate: d.refresh_rate,
+            is_builtin: d.is_builtin,
+            serial_number: d.serial_number.clone(),
+            manufacturer: d.manufacturer.clone(),
+            manufacture_year: d.manufacture_year.map(Into::into),
+            width_mm: d.width_mm,
+            height_mm: d.height_mm,
+        }
+    }
+}
+
+impl From<&models::AppleSiliconInfo> for metrics::AppleSiliconInfo {
+    fn from(a: &models::AppleSiliconInfo) -> Self {
+        Self {
+            chip_model: a.chip.model.clone(),
+            cpu_cores: a.chip.cores.cpu,
+            gpu_cores: a.chip.cores.gpu,
+            neural_engine_cores: a.chip.cores.neural_engine,
+            cpu_power: a.power.cpu_power,
+            gpu_power: a.power.gpu_power,
+            package_power: a.power.package_power,
+            cpu_thermal_level: a.thermal.levels.cpu,
+            gpu_thermal_level: a.thermal.levels.gpu,
+            io_thermal_level: a.thermal.levels.io,
+        }
+    }
+}
+
+impl From<&models::AgentInfo> for metrics::AgentInfo {
+    fn from(a: &models::AgentInfo) -> Self {
+        Self {
+            pid: a.pid,
+            version: a.version.clone(),
+            uptime_seconds: a.uptime_seconds,
+            rss_bytes: a.rss_bytes,
+            virtual_memory_bytes: a.virtual_memory_bytes,
+            cpu_percent: a.cpu_percent,
+            open_fds: a.open_fds.map(|n| n as u64),
+            tokio_tasks: a.tokio_tasks.map(|n| n as u64),
+            tokio_workers: a.tokio_workers.map(|n| n as u64),
+            backlogs: a
+                .backlogs
+                .iter()
+                .map(|b| metrics::QueueBacklogInfo {
+                    name: b.name.clone(),
+                    depth: b.depth as u64,
+                })
+                .collect(),
+            delivery: Some(metrics::DeliveryInfo {
+                sends: a.delivery.sends,
+                retries: a.delivery.retries,
+                failures: a.delivery.failures,
+                spooled: a.delivery.spooled as u64,
+                circuit_state: a.delivery.circuit_state.clone(),
+                last_acked_sequence: a.delivery.last_acked_sequence,
+                duplicates: a.delivery.duplicates,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::metrics_service_server::{MetricsService, MetricsServiceServer};
+    use std::sync::{Arc, Mutex};
+    use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+
+    fn sample_metrics() -> models::SystemMetrics {
+        models::SystemMetrics {
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            payload_id: Some("0b7c6f1e-5d2a-4c1b-9f3e-2a6d8e4b1c70".to_string()),
+            sequence: Some(42),
+            system: models::SystemInfo {
+                node_id: "node-1".to_string(),
+                hostname: "studio".to_string(),
+                platform: "darwin".to_string(),
+                release: "14.0".to_string(),
+                uptime: 3600,
+                loadavg: vec![1.0, 0.5, 0.25],
+                is_apple_silicon: true,
+                model: "Mac14,13".to_string(),
+                clock: None,
+                labels: Default::default(),
+            },
+            cpu: models::CpuInfo {
+                info: models::CpuHardwareInfo {
+                    manufacturer: "Apple".to_string(),
+                    brand: "M2 Max".to_string(),
+                    cores: models::CpuCoreCount { physical: 12, logical: 12 },
+                    speed: models::CpuSpeed { base: 3.5, max: 3.5, current: None },
+                },
+                load: models::CpuLoadInfo { current: 12.5, user: 10.0, system: 2.5, cores: None },
+                temperature: None,
+                scheduler: None,
+            },
+            memory: models::MemoryInfo { total: 32, used: 16, active: 8, available: 16, swap: None },
+            gpu: None,
+            network: None,
+            thermal: None,
+            power: None,
+            storage: Some(models::StorageInfo {
+                filesystems: None,
+                io: None,
+                backup: None,
+                mount_events: Some(vec![models::MountEventInfo {
+                    event: "mounted".to_string(),
+                    fs: "/dev/disk4s1".to_string(),
+                    mount: "/Volumes/Backup".to_string(),
+                    r#type: "apfs".to_string(),
+                    size: 1024,
+                    timestamp: DateTime::from_timestamp(1_700_000_001, 0).unwrap(),
+                }]),
+            }),
+            peripherals: None,
+            apple_silicon: None,
+            agent: None,
+        }
+    }
+
+    #[test]
+    fn test_convert_system_metrics() {
+        let proto = metrics::SystemMetrics::from(&sample_metrics());
+
+        assert_eq!(proto.timestamp, 1_700_000_000_000);
+        assert_eq!(proto.sequence, 42);
+        assert_eq!(proto.system.as_ref().unwrap().node_id, "node-1");
+        assert_eq!(proto.cpu.as_ref().unwrap().info.as_ref().unwrap().physical_cores, 12);
+        assert!(proto.network.is_none());
+        let mounts = &proto.storage.as_ref().unwrap().mount_events;
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(mounts[0].timestamp, 1_700_000_001_000);
+    }
+
+    #[derive(Default)]
+    struct Backend {
+        received: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    #[tonic::async_trait]
+    impl MetricsService for Backend {
+        async fn submit_metrics(
+            &self,
+            request: tonic::Request<metrics::SystemMetrics>,
+        ) -> Result<tonic::Response<metrics::SubmitResponse>, tonic::Status> {
+            let api_key = request.metadata().get("x-api-key").unwrap().to_str().unwrap().to_string();
+            let node = request.into_inner().system.unwrap().node_id;
+            self.received.lock().unwrap().push((api_key, node.clone()));
+            Ok(tonic::Response::new(metrics::SubmitResponse { success: true, node, ..Default::default() }))
+        }
+
+        type StreamCommandsStream = ReceiverStream<Result<metrics::Command, tonic::Status>>;
+
+        async fn stream_commands(
+            &self,
+            request: tonic::Request<metrics::CommandSubscription>,
+        ) -> Result<tonic::Response<Self::StreamCommandsStream>, tonic::Status> {
+            let (tx, rx) = mpsc::channel(1);
+            let command = metrics::Command {
+                id: "cmd-1".to_string(),
+                kind: "ping".to_string(),
+                payload_json: format!("{{\"node\":\"{}\"}}", request.into_inner().node_id),
+                issued_at: 0,
+            };
+            tx.send(Ok(command)).await.unwrap();
+            Ok(tonic::Response::new(ReceiverStream::new(rx)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_loopback_submit_and_commands() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let backend = Backend::default();
+        let received = backend.received.clone();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(MetricsServiceServer::new(backend))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let url: Url = format!("http://127.0.0.1:{}", port).parse().unwrap();
+        let (tx, mut commands) = mpsc::channel(4);
+        let transport = GrpcTransport::connect(url, "secret", "node-1", Some(tx)).unwrap();
+
+        transport.submit(&sample_metrics(), None).await.unwrap();
+        assert_eq!(*received.lock().unwrap(), vec![("secret".to_string(), "node-1".to_string())]);
+
+        let command = tokio::time::timeout(Duration::from_secs(5), commands.recv()).await.unwrap().unwrap();
+        assert_eq!(command.kind, "ping");
+        assert_eq!(command.payload_json, "{\"node\":\"node-1\"}");
+    }
+
+    #[test]
+    fn test_rejects_tls_endpoint() {
+        let url: Url = "https://metrics.example.com".parse().unwrap();
+        assert!(GrpcTransport::connect(url, "key", "node", None).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::debug;
+use reqwest::{header, Client, Url};
+use std::fmt::Write as _;
+use std::time::Duration;
+use crate::proxy;
+use super::sink::{MetricsBatch, MetricsSink};
+
+/// Settings for writing to an InfluxDB v2 compatible `/api/v2/write` endpoint
+#[derive(Debug, Clone)]
+pub struct InfluxConfig {
+    /// Base URL, e.g. `http://localhost:8086`
+    pub url: Url,
+    pub org: String,
+    pub bucket: String,
+    /// API token; sent as `Authorization: Token <token>` when set
+    pub token: Option<String>,
+}
+
+/// Writes metrics as Influx line protocol, e.g. straight into InfluxDB or VictoriaMetrics
+pub struct InfluxSink {
+    client: Client,
+    write_url: Url,
+    node_id: String,
+}
+
+/// A field value in line protocol
+enum Field {
+    Float(f64),
+    Int(i64),
+    Bool(bool),
+}
+
+impl InfluxSink {
+    pub fn new(config: InfluxConfig, node_id: String) -> Result<Self> {
+        let mut write_url = config.url.join("api/v2/write").context("Invalid InfluxDB URL")?;
+        write_url
+            .query_pairs_mut()
+            .append_pair("org", &config.org)
+            .append_pair("bucket", &config.bucket)
+            .append_pair("precision", "ms");
+
+        let mut headers = header::HeaderMap::new();
+        if let Some(token) = &config.token {
+            headers.insert(
+                header::AUTHORIZATION,
+                header::HeaderValue::from_str(&format!("Token {}", token))
+                    .context("Invalid InfluxDB token format")?,
+            );
+        }
+        let client = proxy::client_builder()
+            .default_headers(headers)
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        Ok(Self { client, write_url, node_id })
+    }
+
+    /// Render `batch` as line protocol, one line per measurement and tag set
+    fn lines(&self, batch: &MetricsBatch<'_>) -> String {
+        let system = batch.system;
+        let host = system.hostname.as_str();
+        let tags = |extra: &[(&str, &str)]| -> Vec<(String, String)> {
+            [("node_id", self.node_id.as_str()), ("host", host)]
+                .iter()
+                .chain(extra)
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        let mut out = String::new();
+        let (load1, load5, load15) = system.platform.load_average;
+        write_line(&mut out, "system", &tags(&[]), &[
+            ("uptime_seconds", Field::Int(system.platform.uptime_seconds as i64)),
+            ("load1", Field::Float(load1)),
+            ("load5", Field::Float(load5)),
+            ("load15", Field::Float(load15)),
+            ("memory_total", Field::Int(system.platform.total_memory as i64)),
+            ("memory_available", Field::Int(system.platform.available_memory as i64)),
+        ], &system.collected_at);
+
+        if let Some(cpu) = batch.cpu {
+            write_line(&mut out, "cpu", &tags(&[]), &[
+                ("load", Field::Float(cpu.current_load)),
+                ("user", Field::Float(cpu.user_load)),
+                ("system", Field::Float(cpu.system_load)),
+                ("temperature", Field::Float(cpu.temperature_main)),
+            ], &cpu.collected_at);
+        }
+
+        if let Some(network) = batch.network {
+            for interface in &network.interfaces {
+                write_line(&mut out, "net", &tags(&[("interface", &interface.interface_name)]), &[
+                    ("rx_bytes", Field::Int(interface.rx_bytes as i64)),
+                    ("tx_bytes", Field::Int(interface.tx_bytes as i64)),
+                    ("rx_errors", Field::Int(interface.rx_errors as i64)),
+                    ("tx_errors", Field::Int(interface.tx_errors as i64)),
+                    ("rx_bytes_per_sec", Field::Float(interface.rx_bytes_per_sec)),
+                    ("tx_bytes_per_sec", Field::Float(interface.tx_bytes_per_sec)),
+                ], &interface.collected_at);
+            }
+        }
+
+        if let Some(storage) = batch.storage {
+            for fs in &storage.filesystem_metrics {
+                write_line(&mut out, "disk", &tags(&[("mount", &fs.mount), ("fstype", &fs.fs_type)]), &[
+                    ("size", Field::Int(fs.size as i64)),
+                    ("used", Field::Int(fs.used as i64)),
+                    ("available", Field::Int(fs.available as i64)),
+                    ("encrypted", Field::Bool(fs.encrypted)),
+                ], &storage.collected_at);
+            }
+            let io = &storage.io_metrics;
+            write_line(&mut out, "diskio", &tags(&[]), &[
+                ("read_bytes", Field::Int(io.total_read as i64)),
+                ("write_bytes", Field::Int(io.total_write as i64)),
+                ("read_bytes_per_sec", Field::Float(io.read_bytes_per_sec)),
+                ("write_bytes_per_sec", Field::Float(io.write_bytes_per_sec)),
+            ], &storage.collected_at);
+        }
+
+        if let Some(agent) = batch.agent {
+            let mut fields = vec![
+                ("rss_bytes", Field::Int(agent.rss_bytes as i64)),
+                ("cpu_percent", Field::Float(agent.cpu_percent as f64)),
+            ];
+            if let Some(tasks) = agent.tokio_tasks {
+                fields.push(("tokio_tasks", Field::Int(tasks as i64)));
+            }
+            write_line(&mut out, "agent", &tags(&[]), &fields, &agent.collected_at);
+        }
+
+        out
+    }
+}
+
+#[async_trait]
+impl MetricsSink for InfluxSink {
+    fn name(&self) -> &str {
+        "InfluxDB"
+    }
+
+    async fn send(&self, batch: &MetricsBatch<'_>) -> Result<()> {
+        let body = self.lines(batch);
+        debug!("Writing {} line(s) to {}", body.lines().count(), self.write_url);
+
+        let response = self
+            .client
+            .post(self.write_url.clone())
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(body)
+            .send()
+            .await
+            .context("Failed to write to InfluxDB")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(anyhow!("InfluxDB write failed ({}): {}", status.as_u16(), message));
+        }
+        Ok(())
+    }
+}
+
+fn write_line(
+    out: &mut String,
+    measurement: &str,
+    tags: &[(String, String)],
+    fields: &[(&str, Field)],
+    timestamp: &DateTime<Utc>,
+) {
+    out.push_str(&escape(measurement, &[',', ' ']));
+    for (key, value) in tags.iter().filter(|(_, value)| !value.is_empty()) {
+        let _ = write!(out, ",{}={}", escape(key, &[',', '=', ' ']), escape(value, &[',', '=', ' ']));
+    }
+    for (i, (key, value)) in fields.iter().enumerate() {
+        out.push(if i == 0 { ' ' } else { ',' });
+        out.push_str(&escape(key, &[',', '=', ' ']));
+        let _ = match value {
+            Field::Float(v) => write!(out, "={}", v),
+            Field::Int(v) => write!(out, "={}i", v),
+            Field::Bool(v) => write!(out, "={}", v),
+        };
+    }
+    let _ = writeln!(out, " {}", timestamp.timestamp_millis());
+}
+
+/// Backslash-escape `special` characters (and backslashes) in names and tag values
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_line() {
+        let mut out = String::new();
+        let tags = vec![
+            ("host".to_string(), "mac studio".to_string()),
+            ("mount".to_string(), "/Volumes/a,b".to_string()),
+            ("fstype".to_string(), String::new()),
+        ];
+        let timestamp = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        write_line(&mut out, "disk", &tags, &[
+            ("used", Field::Int(42)),
+            ("ratio", Field::Float(0.5)),
+            ("encrypted", Field::Bool(true)),
+        ], &timestamp);
+
+        assert_eq!(
+            out,
+            "disk,host=mac\\ studio,mount=/Volumes/a\\,b used=42i,ratio=0.5,encrypted=true 1700000000000\n"
+        );
+    }
+
+    #[test]
+    fn test_write_url() {
+        let sink = InfluxSink::new(
+            InfluxConfig {
+                url: "http://localhost:8086".parse().unwrap(),
+                org: "a14a".to_string(),
+                bucket: "nodes".to_string(),
+                token: Some("secret".to_string()),
+            },
+            "node-1".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            sink.write_url.as_str(),
+            "http://localhost:8086/api/v2/write?org=a14a&bucket=nodes&precision=ms"
+        );
+    }
+}
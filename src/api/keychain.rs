@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// Service name the secrets are stored under
+const SERVICE: &str = "org.a14a.node-controller";
+
+/// `security` exits with this status when no matching item exists
+const ERR_SEC_ITEM_NOT_FOUND: i32 = 44;
+
+/// Where API secrets are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretStore {
+    /// macOS Keychain through the `security` tool
+    Keychain,
+    /// Secret Service (GNOME Keyring, KWallet) through libsecret's `secret-tool`
+    SecretService,
+    /// Environment variables only
+    Env,
+}
+
+impl FromStr for SecretStore {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(Self::detect()),
+            "keychain" => Ok(Self::Keychain),
+            "secret-service" | "libsecret" => Ok(Self::SecretService),
+            "env" | "none" => Ok(Self::Env),
+            other => Err(anyhow!("Unknown credential store '{}' (expected auto, keychain, secret-service or env)", other)),
+        }
+    }
+}
+
+impl SecretStore {
+    /// The platform's store if its tool is installed, otherwise environment variables
+    pub fn detect() -> Self {
+        let (store, program) = if cfg!(target_os = "macos") {
+            (Self::Keychain, "security")
+        } else {
+            (Self::SecretService, "secret-tool")
+        };
+        let installed = Command::new("which")
+            .arg(program)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if installed { store } else { Self::Env }
+    }
+
+    /// CREDENTIAL_STORE, defaulting to auto-detection
+    pub fn from_env() -> Self {
+        match env::var("CREDENTIAL_STORE") {
+            Ok(value) => value.parse().unwrap_or_else(|err| {
+                warn!("{}, reading credentials from the environment", err);
+                Self::Env
+            }),
+            Err(_) => Self::detect(),
+        }
+    }
+
+    /// Look up the secret stored for `account`
+    pub fn get(&self, account: &str) -> Result<Option<String>> {
+        let output = match self {
+            Self::Env => return Ok(None),
+            Self::Keychain => Command::new("security")
+                .args(["find-generic-password", "-s", SERVICE, "-a", account, "-w"])
+                .output()
+                .context("Failed to run security")?,
+            Self::SecretService => Command::new("secret-tool")
+                .args(["lookup", "service", SERVICE, "account", account])
+                .output()
+                .context("Failed to run secret-tool")?,
+        };
+        if !output.status.success() {
+            // secret-tool exits with 1 and no output when nothing matches
+            if *self == Self::Keychain && output.status.code() != Some(ERR_SEC_ITEM_NOT_FOUND) {
+                return Err(anyhow!("Keychain lookup failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+            }
+            return Ok(None);
+        }
+        let secret = String::from_utf8(output.stdout).context("Stored secret is not valid UTF-8")?;
+        let secret = secret.trim_end_matches('\n');
+        Ok(Some(secret.to_string()).filter(|s| !s.is_empty()))
+    }
+
+    /// Store `secret` for `account`, replacing any previous value
+    pub fn set(&self, account: &str, secret: &str) -> Result<()> {
+        match self {
+            Self::Env => Err(anyhow!("No credential store available")),
+            Self::Keychain => {
+                // -U updates an existing item instead of failing
+                let output = Command::new("security")
+                    .args(["add-generic-password", "-U", "-s", SERVICE, "-a", account, "-w", secret])
+                    .output()
+                    .context("Failed to run security")?;
+                if !output.status.success() {
+                    return Err(anyhow!("Failed to store {} in the Keychain: {}",
+                                       account, String::from_utf8_lossy(&output.stderr).trim()));
+                }
+                Ok(())
+            }
+            Self::SecretService => {
+                // secret-tool reads the secret from stdin, keeping it out of the process list
+                let mut child = Command::new("secret-tool")
+                    .args(["store", &format!("--label=Node controller {}", account), "service", SERVICE, "account", account])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::piped())
+                    .spawn()
+                    .context("Failed to run secret-tool")?;
+                child.stdin.take().ok_or_else(|| anyhow!("secret-tool stdin unavailable"))?
+                    .write_all(secret.as_bytes())?;
+                let output = child.wait_with_output()?;
+                if !output.status.success() {
+                    return Err(anyhow!("Failed to store {} with secret-tool: {}",
+                                       account, String::from_utf8_lossy(&output.stderr).trim()));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Read secret `name` from the store, falling back to the environment variable of
+    /// the same name. A value still set in the environment is moved into the store so
+    /// it can be removed from `.env`.
+    pub fn resolve(&self, name: &str) -> Option<String> {
+        let from_env = env::var(name).ok().filter(|v| !v.is_empty());
+        if *self == Self::Env {
+            return from_env;
+        }
+
+        let stored = self.get(name).unwrap_or_else(|err| {
+            warn!("Failed to read {} from the credential store: {:#}", name, err);
+            None
+        });
+        let Some(value) = from_env else {
+            if stored.is_some() {
+                debug!("Using {} from the credential store", name);
+            }
+            return stored;
+        };
+        if stored.as_deref() != Some(value.as_str()) {
+            match self.set(name, &value) {
+                Ok(()) => info!("Stored {} in the credential store; it can now be removed from the environment", name),
+                Err(err) => warn!("Failed to migrate {} to the credential store: {:#}", name, err),
+            }
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_store() {
+        assert_eq!("keychain".parse::<SecretStore>().unwrap(), SecretStore::Keychain);
+        assert_eq!("libsecret".parse::<SecretStore>().unwrap(), SecretStore::SecretService);
+        assert_eq!(" ENV ".parse::<SecretStore>().unwrap(), SecretStore::Env);
+        assert!("vault".parse::<SecretStore>().is_err());
+        assert_eq!(SecretStore::Env.get("MONITORING_API_KEY").unwrap(), None);
+    }
+}
@@ -1,4 +1,34 @@
+pub mod auth;
+pub mod circuit_breaker;
 pub mod client;
+pub mod commands;
+pub mod compression;
+pub mod delta;
+pub mod encryption;
+pub mod enrollment;
+pub mod events;
+pub mod field_policy;
+pub mod file_sink;
+pub mod grpc;
+pub mod influx;
+pub mod keychain;
 pub mod models;
+pub mod mqtt;
+pub mod prometheus;
+pub mod remote_config;
+pub mod retry;
+pub mod sequence;
+pub mod sink;
+pub mod spool;
+pub mod websocket;
 
-pub use client::ApiClient; 
\ No newline at end of file
+pub use client::ApiClient;
+pub use compression::CompressionMode;
+pub use file_sink::FileSink;
+pub use influx::{InfluxConfig, InfluxSink};
+pub use mqtt::{MqttConfig, MqttSink};
+pub use prometheus::PrometheusSink;
+pub use retry::RetryPolicy;
+pub use sink::{MetricsBatch, MetricsSinks};
+pub use spool::MetricsSpool;
+ 
@@ -1,10 +1,17 @@
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
 
 /// Main system metrics structure that matches the OpenAPI schema
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SystemMetrics {
     pub timestamp: DateTime<Utc>,
+    /// Unique per payload; also sent as `Idempotency-Key` so replays are not counted twice
+    #[serde(rename = "payloadId", skip_serializing_if = "Option::is_none")]
+    pub payload_id: Option<String>,
+    /// Increases by one per payload so the backend can detect gaps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
     pub system: SystemInfo,
     pub cpu: CpuInfo,
     pub memory: MemoryInfo,
@@ -15,16 +22,22 @@ pub struct SystemMetrics {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub thermal: Option<ThermalInfo>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerStatusInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub storage: Option<StorageInfo>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub peripherals: Option<PeripheralsInfo>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "appleSilicon")]
     pub apple_silicon: Option<AppleSiliconInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent: Option<AgentInfo>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SystemInfo {
+    #[serde(rename = "nodeId")]
+    pub node_id: String,
     pub hostname: String,
     pub platform: String,
     pub release: String,
@@ -33,6 +46,24 @@ pub struct SystemInfo {
     #[serde(rename = "isAppleSilicon")]
     pub is_apple_silicon: bool,
     pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock: Option<ClockInfo>,
+    /// User-defined node labels, e.g. rack and role
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClockInfo {
+    #[serde(rename = "ntpServer")]
+    pub ntp_server: String,
+    #[serde(rename = "offsetMs")]
+    pub offset_ms: f64,
+    #[serde(rename = "roundTripMs")]
+    pub round_trip_ms: f64,
+    pub stratum: u8,
+    #[serde(rename = "checkedAt")]
+    pub checked_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -41,6 +72,22 @@ pub struct CpuInfo {
     pub load: CpuLoadInfo,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<CpuTemperatureInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduler: Option<CpuSchedulerInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CpuSchedulerInfo {
+    #[serde(rename = "runQueue")]
+    pub run_queue: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "contextSwitchesPerSec")]
+    pub context_switches_per_sec: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "interruptsPerSec")]
+    pub interrupts_per_sec: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -127,6 +174,32 @@ pub struct NetworkInfo {
     pub interfaces: Option<Vec<NetworkInterface>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stats: Option<Vec<NetworkStats>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns: Option<Vec<DnsProbeInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "httpChecks")]
+    pub http_checks: Option<Vec<HttpCheckInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "linkEvents")]
+    pub link_events: Option<Vec<LinkEventInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routes: Option<Vec<RouteInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "defaultGateway")]
+    pub default_gateway: Option<GatewayInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "routeEvents")]
+    pub route_events: Option<Vec<RouteEventInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vpn: Option<VpnInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<ProxyInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "publicIp")]
+    pub public_ip: Option<PublicIpInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "publicIpChanges")]
+    pub public_ip_changes: Option<Vec<PublicIpChangeInfo>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -150,6 +223,132 @@ pub struct NetworkStats {
     pub errors: u64,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LinkEventInfo {
+    pub interface: String,
+    pub r#type: String,
+    /// Either `link_up` or `link_down`
+    pub event: String,
+    #[serde(rename = "previousStatus")]
+    pub previous_status: String,
+    pub status: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RouteInfo {
+    pub destination: String,
+    pub gateway: String,
+    pub flags: String,
+    pub interface: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GatewayInfo {
+    pub address: String,
+    pub interface: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RouteEventInfo {
+    /// One of `default_gateway_changed`, `route_added` or `route_removed`
+    pub event: String,
+    pub destination: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "previousGateway")]
+    pub previous_gateway: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<String>,
+    pub interface: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VpnInfo {
+    pub active: bool,
+    pub interfaces: Vec<VpnInterfaceInfo>,
+    pub clients: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VpnInterfaceInfo {
+    pub name: String,
+    pub address: String,
+    #[serde(rename = "rxBytes")]
+    pub rx_bytes: u64,
+    #[serde(rename = "txBytes")]
+    pub tx_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProxyInfo {
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub https: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socks: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "pacUrl")]
+    pub pac_url: Option<String>,
+    #[serde(rename = "autoDiscovery")]
+    pub auto_discovery: bool,
+    pub exceptions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicIpInfo {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "changedAt")]
+    pub changed_at: Option<DateTime<Utc>>,
+    #[serde(rename = "checkedAt")]
+    pub checked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicIpChangeInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "previousAddress")]
+    pub previous_address: Option<String>,
+    pub address: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DnsProbeInfo {
+    pub hostname: String,
+    pub resolved: bool,
+    #[serde(rename = "latencyMs")]
+    pub latency_ms: f64,
+    pub addresses: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HttpCheckInfo {
+    pub url: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(rename = "expectedStatus")]
+    pub expected_status: u16,
+    #[serde(rename = "latencyMs")]
+    pub latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "certExpiresAt")]
+    pub cert_expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "certDaysRemaining")]
+    pub cert_days_remaining: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ThermalInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -162,6 +361,79 @@ pub struct ThermalInfo {
     pub pressure: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PowerStatusInfo {
+    pub source: String,
+    #[serde(rename = "sleepBlockers")]
+    pub sleep_blockers: Vec<PowerAssertionInfo>,
+    #[serde(rename = "scheduledEvents")]
+    pub scheduled_events: Vec<ScheduledPowerEventInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ups: Option<Vec<UpsInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adapter: Option<AdapterInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub events: Option<Vec<PowerEventInfo>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdapterInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "adapterId")]
+    pub adapter_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watts: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "systemPowerWatts")]
+    pub system_power_watts: Option<f64>,
+    pub undersized: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpsInfo {
+    pub name: String,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "chargePercent")]
+    pub charge_percent: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "runtimeMinutes")]
+    pub runtime_minutes: Option<u32>,
+    #[serde(rename = "onBattery")]
+    pub on_battery: bool,
+    pub state: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PowerEventInfo {
+    /// e.g. `ups_on_battery`, `ups_online`, `adapter_changed`
+    pub event: String,
+    pub source: String,
+    pub detail: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PowerAssertionInfo {
+    pub pid: u32,
+    pub process: String,
+    pub r#type: String,
+    pub name: String,
+    #[serde(rename = "ageSecs")]
+    pub age_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScheduledPowerEventInfo {
+    pub r#type: String,
+    pub time: String,
+    pub repeating: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChassisTemperature {
     pub temperature: f64,
@@ -190,6 +462,44 @@ pub struct StorageInfo {
     pub filesystems: Option<Vec<FilesystemInfo>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub io: Option<IoInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<BackupInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "mountEvents")]
+    pub mount_events: Option<Vec<MountEventInfo>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MountEventInfo {
+    /// Either `mounted` or `unmounted`
+    pub event: String,
+    pub fs: String,
+    pub mount: String,
+    pub r#type: String,
+    pub size: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupInfo {
+    #[serde(rename = "localSnapshotCount")]
+    pub local_snapshot_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "latestLocalSnapshot")]
+    pub latest_local_snapshot: Option<DateTime<Utc>>,
+    #[serde(rename = "timeMachineConfigured")]
+    pub time_machine_configured: bool,
+    #[serde(rename = "backupRunning")]
+    pub backup_running: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "backupPhase")]
+    pub backup_phase: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "backupProgress")]
+    pub backup_progress: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "lastBackupAt")]
+    pub last_backup_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -200,6 +510,10 @@ pub struct FilesystemInfo {
     pub used: u64,
     pub available: u64,
     pub mount: String,
+    pub encrypted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "encryptionType")]
+    pub encryption_type: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -212,12 +526,71 @@ pub struct IoInfo {
     pub read_bytes_per_sec: f64,
     #[serde(rename = "writeBytesPerSec")]
     pub write_bytes_per_sec: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub devices: Option<Vec<DiskIoInfo>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiskIoInfo {
+    pub device: String,
+    #[serde(rename = "readsPerSec")]
+    pub reads_per_sec: f64,
+    #[serde(rename = "writesPerSec")]
+    pub writes_per_sec: f64,
+    #[serde(rename = "readBytesPerSec")]
+    pub read_bytes_per_sec: f64,
+    #[serde(rename = "writeBytesPerSec")]
+    pub write_bytes_per_sec: f64,
+    #[serde(rename = "readLatencyMs")]
+    pub read_latency_ms: f64,
+    #[serde(rename = "writeLatencyMs")]
+    pub write_latency_ms: f64,
+    #[serde(rename = "queueDepth")]
+    pub queue_depth: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PeripheralsInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub changes: Option<PeripheralChanges>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub displays: Option<Vec<DisplayDeviceInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "displayEvents")]
+    pub display_events: Option<Vec<DisplayEventInfo>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisplayDeviceInfo {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    #[serde(rename = "refreshRate")]
+    pub refresh_rate: f32,
+    #[serde(rename = "isBuiltin")]
+    pub is_builtin: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "serialNumber")]
+    pub serial_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "manufactureYear")]
+    pub manufacture_year: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "widthMm")]
+    pub width_mm: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "heightMm")]
+    pub height_mm: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisplayEventInfo {
+    /// `connected` or `disconnected`
+    pub event: String,
+    pub display: DisplayDeviceInfo,
+    pub timestamp: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -279,11 +652,71 @@ pub struct AppleSiliconThermalLevels {
     pub io: u32,
 }
 
+/// Resource usage of the node-controller process itself
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentInfo {
+    pub pid: u32,
+    pub version: String,
+    #[serde(rename = "uptimeSeconds")]
+    pub uptime_seconds: u64,
+    #[serde(rename = "rssBytes")]
+    pub rss_bytes: u64,
+    #[serde(rename = "virtualMemoryBytes")]
+    pub virtual_memory_bytes: u64,
+    #[serde(rename = "cpuPercent")]
+    pub cpu_percent: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "openFds")]
+    pub open_fds: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "tokioTasks")]
+    pub tokio_tasks: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "tokioWorkers")]
+    pub tokio_workers: Option<usize>,
+    pub backlogs: Vec<QueueBacklogInfo>,
+    pub delivery: DeliveryInfo,
+}
+
+/// Metric delivery counters since the agent started
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeliveryInfo {
+    pub sends: u64,
+    pub retries: u64,
+    pub failures: u64,
+    pub spooled: usize,
+    #[serde(rename = "circuitState", skip_serializing_if = "Option::is_none")]
+    pub circuit_state: Option<String>,
+    #[serde(rename = "lastAckedSequence", skip_serializing_if = "Option::is_none")]
+    pub last_acked_sequence: Option<u64>,
+    pub duplicates: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueueBacklogInfo {
+    pub name: String,
+    pub depth: usize,
+}
+
+/// Configuration version reported after applying remote configuration
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigStatus {
+    pub version: String,
+    pub applied_at: DateTime<Utc>,
+}
+
 // API response models
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiResponse {
     pub success: bool,
     pub node: String,
+    /// Highest sequence number the API has stored for this node
+    #[serde(rename = "ackedSequence", default)]
+    pub acked_sequence: Option<u64>,
+    /// The payload ID was seen before and the payload was not stored again
+    #[serde(default)]
+    pub duplicate: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -0,0 +1,463 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, WriteHalf};
+use tokio::sync::mpsc;
+use super::sink::{MetricsBatch, MetricsSink};
+use crate::proxy;
+
+const OUTGOING_QUEUE: usize = 256;
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(60);
+/// Unacknowledged QoS 1 messages kept for redelivery after a reconnect
+const MAX_INFLIGHT: usize = 100;
+/// Packets read ahead of the session loop
+const INCOMING_PACKETS: usize = 16;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PINGREQ: u8 = 0xC0;
+const PINGRESP: u8 = 0xD0;
+const DISCONNECT: u8 = 0xE0;
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// Settings for publishing metrics to an MQTT broker
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    /// `mqtt://host:1883` or `mqtts://host:8883`
+    pub broker: Url,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topic per metric type; `{node_id}`, `{hostname}` and `{metric}` are substituted
+    pub topic_template: String,
+    /// 0 (at most once) or 1 (at least once)
+    pub qos: u8,
+    pub keep_alive: Duration,
+}
+
+struct Message {
+    topic: String,
+    payload: Vec<u8>,
+}
+
+/// Publishes metric payloads to an MQTT broker (MQTT 3.1.1).
+///
+/// A background task owns the connection, reconnects with backoff and
+/// redelivers unacknowledged QoS 1 messages.
+pub struct MqttSink {
+    outgoing: mpsc::Sender<Message>,
+    connected: Arc<AtomicBool>,
+    topic_template: String,
+    node_id: String,
+    hostname: String,
+}
+
+impl MqttSink {
+    pub fn spawn(config: MqttConfig, node_id: String, hostname: String) -> Result<Self> {
+        match config.broker.scheme() {
+            "mqtt" | "mqtts" | "tcp" | "ssl" => {}
+            other => return Err(anyhow!("Unsupported MQTT scheme '{}'", other)),
+        }
+        if config.qos > 1 {
+            return Err(anyhow!("MQTT QoS {} is not supported (use 0 or 1)", config.qos));
+        }
+
+        let (outgoing, rx) = mpsc::channel(OUTGOING_QUEUE);
+        let connected = Arc::new(AtomicBool::new(false));
+        let topic_template = config.topic_template.clone();
+        tokio::spawn(run(config, rx, connected.clone()));
+
+        Ok(Self { outgoing, connected, topic_template, node_id, hostname })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Topic for a metric type, e.g. `node-controller/<node_id>/cpu`
+    pub fn topic(&self, metric: &str) -> String {
+        self.topic_template
+            .replace("{node_id}", &self.node_id)
+            .replace("{hostname}", &self.hostname)
+            .replace("{metric}", metric)
+    }
+
+    /// Queue `payload` as JSON on the topic for `metric`
+    pub fn publish<T: Serialize>(&self, metric: &str, payload: &T) -> Result<()> {
+        let message = Message {
+            topic: self.topic(metric),
+            payload: serde_json::to_vec(payload)?,
+        };
+        self.outgoing.try_send(message).map_err(|e| anyhow!("MQTT queue unavailable: {}", e))
+    }
+}
+
+#[async_trait]
+impl MetricsSink for MqttSink {
+    fn name(&self) -> &str {
+        "MQTT"
+    }
+
+    async fn send(&self, batch: &MetricsBatch<'_>) -> Result<()> {
+        if !self.is_connected() {
+            debug!("MQTT broker not connected, queueing metrics");
+        }
+        self.publish("system", batch.system)?;
+        if let Some(agent) = batch.agent {
+            self.publish("agent", agent)?;
+        }
+        if let Some(cpu) = batch.cpu {
+            self.publish("cpu", cpu)?;
+        }
+        if let Some(network) = batch.network {
+            self.publish("network", network)?;
+        }
+        if let Some(storage) = batch.storage {
+            self.publish("storage", storage)?;
+        }
+        Ok(())
+    }
+
+    /// Events go out on their own topic, e.g. `node-controller/<node_id>/events/link`
+    fn stream_event(&self, kind: &str, event: &serde_json::Value) -> bool {
+        self.publish(&format!("events/{}", kind), event).is_ok()
+    }
+}
+
+async fn run(config: MqttConfig, mut outgoing: mpsc::Receiver<Message>, connected: Arc<AtomicBool>) {
+    let mut delay = RECONNECT_MIN;
+    let mut inflight: BTreeMap<u16, Message> = BTreeMap::new();
+    let mut next_packet_id: u16 = 1;
+
+    loop {
+        match connect(&config).await {
+            Ok(stream) => {
+                info!("MQTT connected to {}", config.broker);
+                connected.store(true, Ordering::SeqCst);
+                delay = RECONNECT_MIN;
+
+                let result = session(stream, &config, &mut outgoing, &mut inflight, &mut next_packet_id).await;
+                connected.store(false, Ordering::SeqCst);
+                match result {
+                    Ok(()) => {
+                        info!("MQTT connection to {} closed", config.broker);
+                        return;
+                    }
+                    Err(e) => warn!("MQTT connection to {} dropped: {}", config.broker, e),
+                }
+            }
+            Err(e) => debug!("MQTT connect to {} failed: {}", config.broker, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(RECONNECT_MAX);
+    }
+}
+
+async fn connect(config: &MqttConfig) -> Result<Box<dyn Connection>> {
+    let host = config.broker.host_str().ok_or_else(|| anyhow!("MQTT broker URL has no host"))?;
+    let tls = matches!(config.broker.scheme(), "mqtts" | "ssl");
+    let port = config.broker.port().unwrap_or(if tls { 8883 } else { 1883 });
+
+    let tcp = tokio::time::timeout(Duration::from_secs(10), proxy::connect_tcp(&config.broker, host, port))
+        .await
+        .context("Connection timed out")??;
+    tcp.set_nodelay(true)?;
+    let mut stream: Box<dyn Connection> = if tls {
+        let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+        Box::new(connector.connect(host, tcp).await?)
+    } else {
+        Box::new(tcp)
+    };
+
+    stream.write_all(&encode_connect(config)).await?;
+    let (header, body) = tokio::time::timeout(Duration::from_secs(10), read_packet(&mut stream))
+        .await
+        .context("No CONNACK from broker")??;
+    if header & 0xF0 != CONNACK || body.len() < 2 {
+        return Err(anyhow!("Unexpected packet {:#x} instead of CONNACK", header));
+    }
+    if body[1] != 0 {
+        return Err(anyhow!("Broker refused connection (return code {})", body[1]));
+    }
+    Ok(stream)
+}
+
+async fn session(
+    stream: Box<dyn Connection>,
+    config: &MqttConfig,
+    outgoing: &mut mpsc::Receiver<Message>,
+    inflight: &mut BTreeMap<u16, Message>,
+    next_packet_id: &mut u16,
+) -> Result<()> {
+    let (mut reader, writer) = tokio::io::split(stream);
+
+    // Reading a packet takes several awaits, so it can't be raced against the
+    // keepalive and the outgoing queue without losing half-read packets; a
+    // task reads whole ones
+    let (packet_tx, mut packets) = mpsc::channel(INCOMING_PACKETS);
+    let reading = tokio::spawn(async move {
+        loop {
+            let packet = read_packet(&mut reader).await;
+            let failed = packet.is_err();
+            if packet_tx.send(packet).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+    let result = exchange(writer, &mut packets, config, outgoing, inflight, next_packet_id).await;
+    reading.abort();
+    result
+}
+
+async fn exchange(
+    mut writer: WriteHalf<Box<dyn Connection>>,
+    packets: &mut mpsc::Receiver<Result<(u8, Vec<u8>)>>,
+    config: &MqttConfig,
+    outgoing: &mut mpsc::Receiver<Message>,
+    inflight: &mut BTreeMap<u16, Message>,
+    next_packet_id: &mut u16,
+) -> Result<()> {
+    // Redeliver anything the broker never acknowledged
+    for (packet_id, message) in inflight.iter() {
+        writer.write_all(&encode_publish(&message.topic, &message.payload, 1, Some(*packet_id), true)).await?;
+    }
+
+    let mut ping = tokio::time::interval(config.keep_alive.max(Duration::from_secs(5)) / 2);
+    ping.tick().await;
+
+    loop {
+        tokio::select! {
+            message = outgoing.recv() => {
+                let Some(message) = message else {
+                    writer.write_all(&[DISCONNECT, 0]).await?;
+                    return Ok(());
+                };
+                if config.qos == 0 {
+                    writer.write_all(&encode_publish(&message.topic, &message.payload, 0, None, false)).await?;
+                    continue;
+                }
+                let packet_id = take_packet_id(next_packet_id, inflight);
+                writer.write_all(&encode_publish(&message.topic, &message.payload, 1, Some(packet_id), false)).await?;
+                inflight.insert(packet_id, message);
+                if inflight.len() > MAX_INFLIGHT {
+                    if let Some((dropped, _)) = inflight.pop_first() {
+                        warn!("MQTT inflight window full, dropping unacknowledged message {}", dropped);
+                    }
+                }
+            }
+            packet = packets.recv() => {
+                let (header, body) = packet.ok_or_else(|| anyhow!("MQTT reader stopped"))??;
+                match header & 0xF0 {
+                    PUBACK if body.len() >= 2 => {
+                        inflight.remove(&u16::from_be_bytes([body[0], body[1]]));
+                    }
+                    PINGRESP => {}
+                    other => debug!("Ignoring MQTT packet {:#x}", other),
+                }
+            }
+            _ = ping.tick() => {
+                writer.write_all(&[PINGREQ, 0]).await?;
+            }
+        }
+    }
+}
+
+/// The next packet ID from `next`, skipping 0 and IDs of messages still inflight
+fn take_packet_id(next: &mut u16, inflight: &BTreeMap<u16, Message>) -> u16 {
+    loop {
+        let packet_id = *next;
+        *next = next.checked_add(1).unwrap_or(1);
+        if packet_id != 0 && !inflight.contains_key(&packet_id) {
+            return packet_id;
+        }
+    }
+}
+
+fn encode_remaining_length(mut len: usize, out: &mut Vec<u8>) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn push_str(value: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn packet(header: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut packet = vec![header];
+    encode_remaining_length(body.len(), &mut packet);
+    packet.extend(body);
+    packet
+}
+
+fn encode_connect(config: &MqttConfig) -> Vec<u8> {
+    let mut flags = 0x02; // clean session
+    if config.username.is_some() {
+        flags |= 0x80;
+    }
+    if config.password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut body = Vec::new();
+    push_str("MQTT", &mut body);
+    body.push(4); // protocol level 3.1.1
+    body.push(flags);
+    body.extend_from_slice(&(config.keep_alive.as_secs().min(u16::MAX as u64) as u16).to_be_bytes());
+    push_str(&config.client_id, &mut body);
+    if let Some(username) = &config.username {
+        push_str(username, &mut body);
+    }
+    if let Some(password) = &config.password {
+        push_str(password, &mut body);
+    }
+    packet(CONNECT, body)
+}
+
+fn encode_publish(topic: &str, payload: &[u8], qos: u8, packet_id: Option<u16>, dup: bool) -> Vec<u8> {
+    let mut body = Vec::with_capacity(topic.len() + payload.len() + 4);
+    push_str(topic, &mut body);
+    if let Some(id) = packet_id {
+        body.extend_from_slice(&id.to_be_bytes());
+    }
+    body.extend_from_slice(payload);
+    let header = PUBLISH | if dup { 0x08 } else { 0 } | (qos << 1);
+    packet(header, body)
+}
+
+/// Read one control packet, returning its first header byte and body
+async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(u8, Vec<u8>)> {
+    let header = reader.read_u8().await?;
+    let mut len = 0usize;
+    let mut multiplier = 1usize;
+    for _ in 0..4 {
+        let byte = reader.read_u8().await?;
+        len += (byte & 0x7f) as usize * multiplier;
+        if byte & 0x80 == 0 {
+            let mut body = vec![0u8; len];
+            reader.read_exact(&mut body).await?;
+            return Ok((header, body));
+        }
+        multiplier *= 128;
+    }
+    Err(anyhow!("Malformed MQTT remaining length"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_packet_encoding() {
+        let mut len = Vec::new();
+        encode_remaining_length(321, &mut len);
+        assert_eq!(len, vec![0xC1, 0x02]);
+
+        let publish = encode_publish("a/b", b"{}", 1, Some(7), false);
+        assert_eq!(publish, vec![0x32, 9, 0, 3, b'a', b'/', b'b', 0, 7, b'{', b'}']);
+    }
+
+    #[test]
+    fn test_packet_ids_skip_inflight() {
+        let message = || Message { topic: String::new(), payload: Vec::new() };
+        let inflight = BTreeMap::from([(1, message()), (2, message()), (u16::MAX, message())]);
+        let mut next = u16::MAX - 1;
+        assert_eq!(take_packet_id(&mut next, &inflight), u16::MAX - 1);
+        // Wraps past the inflight IDs and 0
+        assert_eq!(take_packet_id(&mut next, &inflight), 3);
+        assert_eq!(next, 4);
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_during_partial_packet() {
+        let (client, mut broker) = tokio::io::duplex(1 << 16);
+        let config = MqttConfig {
+            broker: "mqtt://127.0.0.1".parse().unwrap(),
+            client_id: "test-node".to_string(),
+            username: None,
+            password: None,
+            topic_template: "nodes/{node_id}/{metric}".to_string(),
+            qos: 1,
+            keep_alive: Duration::from_secs(5),
+        };
+        let (out_tx, mut out_rx) = mpsc::channel(OUTGOING_QUEUE);
+        let session = tokio::spawn(async move {
+            let mut inflight = BTreeMap::new();
+            let mut next_packet_id = 1;
+            let result = session(Box::new(client), &config, &mut out_rx, &mut inflight, &mut next_packet_id).await;
+            result.map(|()| inflight.len())
+        });
+        out_tx.send(Message { topic: "a".to_string(), payload: b"1".to_vec() }).await.unwrap();
+        let (header, _) = read_packet(&mut broker).await.unwrap();
+        assert_eq!(header, PUBLISH | 0x02);
+
+        // Half of the PUBACK arrives, then a keepalive goes out before the rest
+        broker.write_all(&[PUBACK, 2]).await.unwrap();
+        let (header, _) = read_packet(&mut broker).await.unwrap();
+        assert_eq!(header, PINGREQ);
+        broker.write_all(&[0, 1]).await.unwrap();
+        broker.write_all(&[PINGRESP, 0]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        drop(out_tx);
+        let (header, _) = read_packet(&mut broker).await.unwrap();
+        assert_eq!(header, DISCONNECT);
+        assert_eq!(session.await.unwrap().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_publish_qos1_to_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let broker = format!("mqtt://{}", listener.local_addr().unwrap()).parse().unwrap();
+
+        let sink = MqttSink::spawn(MqttConfig {
+            broker,
+            client_id: "test-node".to_string(),
+            username: None,
+            password: None,
+            topic_template: "nodes/{node_id}/{metric}".to_string(),
+            qos: 1,
+            keep_alive: Duration::from_secs(30),
+        }, "node-1".to_string(), "host".to_string()).unwrap();
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let (header, body) = read_packet(&mut socket).await.unwrap();
+        assert_eq!(header, CONNECT);
+        assert!(body.windows(9).any(|w| w == b"test-node"));
+        socket.write_all(&[CONNACK, 2, 0, 0]).await.unwrap();
+
+        while !sink.is_connected() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        sink.publish("cpu", &serde_json::json!({ "load": 12.5 })).unwrap();
+
+        let (header, body) = read_packet(&mut socket).await.unwrap();
+        assert_eq!(header, PUBLISH | 0x02);
+        let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+        assert_eq!(&body[2..2 + topic_len], b"nodes/node-1/cpu");
+        let packet_id = &body[2 + topic_len..4 + topic_len];
+        assert_eq!(&body[4 + topic_len..], br#"{"load":12.5}"#);
+        socket.write_all(&[PUBACK, 2, packet_id[0], packet_id[1]]).await.unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use super::sink::{MetricsBatch, MetricsSink};
+
+/// Exposes the latest metrics at `GET /metrics` in the Prometheus text format.
+///
+/// Sections are only replaced when a batch carries them, so metrics collected
+/// on a slower interval stay visible between collections.
+pub struct PrometheusSink {
+    sections: Arc<Mutex<BTreeMap<&'static str, String>>>,
+}
+
+impl PrometheusSink {
+    /// Listen on `addr` and serve scrapes from a background task
+    pub fn bind(addr: SocketAddr) -> Result<Self> {
+        let listener = std::net::TcpListener::bind(addr)
+            .with_context(|| format!("Failed to bind {}", addr))?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        info!("Serving Prometheus metrics on http://{}/metrics", addr);
+
+        let sections = Arc::new(Mutex::new(BTreeMap::new()));
+        tokio::spawn(serve(listener, sections.clone()));
+        Ok(Self { sections })
+    }
+}
+
+#[async_trait]
+impl MetricsSink for PrometheusSink {
+    fn name(&self) -> &str {
+        "prometheus"
+    }
+
+    async fn send(&self, batch: &MetricsBatch<'_>) -> Result<()> {
+        let mut sections = self.sections.lock().unwrap();
+        sections.insert("system", render_system(batch));
+        if let Some(cpu) = batch.cpu {
+            let mut out = String::new();
+            family(&mut out, "cpu_load_percent", "gauge", "CPU load by mode", [
+                (labels(&[("mode", "total")]), cpu.current_load),
+                (labels(&[("mode", "user")]), cpu.user_load),
+                (labels(&[("mode", "system")]), cpu.system_load),
+            ]);
+            sections.insert("cpu", out);
+        }
+        if let Some(network) = batch.network {
+            let mut out = String::new();
+            family(&mut out, "network_receive_bytes_total", "counter", "Bytes received per interface",
+                network.interfaces.iter().map(|i| (labels(&[("interface", &i.interface_name)]), i.rx_bytes as f64)));
+            family(&mut out, "network_transmit_bytes_total", "counter", "Bytes sent per interface",
+                network.interfaces.iter().map(|i| (labels(&[("interface", &i.interface_name)]), i.tx_bytes as f64)));
+            family(&mut out, "network_errors_total", "counter", "Receive and transmit errors per interface",
+                network.interfaces.iter().map(|i| (labels(&[("interface", &i.interface_name)]), (i.rx_errors + i.tx_errors) as f64)));
+            sections.insert("network", out);
+        }
+        if let Some(storage) = batch.storage {
+            let mut out = String::new();
+            let fs_labels = |fs: &crate::metrics::storage::types::FilesystemMetric| {
+                labels(&[("mount", &fs.mount), ("fstype", &fs.fs_type)])
+            };
+            family(&mut out, "filesystem_size_bytes", "gauge", "Filesystem size",
+                storage.filesystem_metrics.iter().map(|fs| (fs_labels(fs), fs.size as f64)));
+            family(&mut out, "filesystem_used_bytes", "gauge", "Filesystem space in use",
+                storage.filesystem_metrics.iter().map(|fs| (fs_labels(fs), fs.used as f64)));
+            family(&mut out, "filesystem_available_bytes", "gauge", "Filesystem space available",
+                storage.filesystem_metrics.iter().map(|fs| (fs_labels(fs), fs.available as f64)));
+            family(&mut out, "disk_read_bytes_total", "counter", "Bytes read from all disks",
+                [(String::new(), storage.io_metrics.total_read as f64)]);
+            family(&mut out, "disk_written_bytes_total", "counter", "Bytes written to all disks",
+                [(String::new(), storage.io_metrics.total_write as f64)]);
+            sections.insert("storage", out);
+        }
+        if let Some(agent) = batch.agent {
+            let mut out = String::new();
+            family(&mut out, "agent_resident_memory_bytes", "gauge", "Resident memory of the node controller",
+                [(String::new(), agent.rss_bytes as f64)]);
+            family(&mut out, "agent_cpu_percent", "gauge", "CPU usage of the node controller, 100 = one core",
+                [(String::new(), agent.cpu_percent as f64)]);
+            family(&mut out, "agent_queue_depth", "gauge", "Items waiting to be delivered",
+                agent.backlogs.iter().map(|b| (labels(&[("queue", &b.name)]), b.depth as f64)));
+            sections.insert("agent", out);
+        }
+        Ok(())
+    }
+}
+
+fn render_system(batch: &MetricsBatch<'_>) -> String {
+    let platform = &batch.system.platform;
+    let (load1, load5, load15) = platform.load_average;
+    let mut out = String::new();
+    family(&mut out, "uptime_seconds", "gauge", "Seconds since boot",
+        [(String::new(), platform.uptime_seconds as f64)]);
+    family(&mut out, "load_average", "gauge", "System load average", [
+        (labels(&[("period", "1m")]), load1),
+        (labels(&[("period", "5m")]), load5),
+        (labels(&[("period", "15m")]), load15),
+    ]);
+    family(&mut out, "memory_total_bytes", "gauge", "Physical memory",
+        [(String::new(), platform.total_memory as f64)]);
+    family(&mut out, "memory_available_bytes", "gauge", "Memory available to new processes",
+        [(String::new(), platform.available_memory as f64)]);
+    out
+}
+
+/// Write one metric family with its HELP and TYPE lines
+fn family(out: &mut String, name: &str, kind: &str, help: &str, samples: impl IntoIterator<Item = (String, f64)>) {
+    let _ = writeln!(out, "# HELP node_controller_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE node_controller_{} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "node_controller_{}{} {}", name, labels, value);
+    }
+}
+
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+fn render(sections: &Mutex<BTreeMap<&'static str, String>>) -> String {
+    sections.lock().unwrap().values().map(String::as_str).collect()
+}
+
+async fn serve(listener: TcpListener, sections: Arc<Mutex<BTreeMap<&'static str, String>>>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let sections = sections.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = respond(stream, &sections).await {
+                        debug!("Prometheus scrape from {} failed: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => debug!("Prometheus listener accept failed: {}", e),
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream, sections: &Mutex<BTreeMap<&'static str, String>>) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request_line = String::from_utf8_lossy(&request);
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let (status, content_type, body) = if path == "/metrics" {
+        ("200 OK", "text/plain; version=0.0.4", render(sections))
+    } else {
+        ("404 Not Found", "text/plain", "Not Found\n".to_string())
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_are_escaped() {
+        assert_eq!(labels(&[("mount", "/Volumes/\"Backup\"")]), "{mount=\"/Volumes/\\\"Backup\\\"\"}");
+    }
+
+    #[test]
+    fn test_family_format() {
+        let mut out = String::new();
+        family(&mut out, "uptime_seconds", "gauge", "Seconds since boot", [(String::new(), 42.0)]);
+        assert_eq!(
+            out,
+            "# HELP node_controller_uptime_seconds Seconds since boot\n\
+             # TYPE node_controller_uptime_seconds gauge\n\
+             node_controller_uptime_seconds 42\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scrape() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let sink = PrometheusSink::bind(SocketAddr::from(([127, 0, 0, 1], port))).unwrap();
+        sink.sections.lock().unwrap().insert("system", "node_controller_uptime_seconds 1\n".to_string());
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("node_controller_uptime_seconds 1\n"));
+    }
+}
@@ -0,0 +1,190 @@
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use crate::metrics::cpu::types::CpuMetrics;
+use crate::metrics::storage::types::StorageMetrics;
+use super::ApiClient;
+
+/// Node configuration managed from the monitoring API
+/// (`GET /api/v1/nodes/{nodeId}/config`). Omitted settings keep their local value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteConfig {
+    /// Opaque version, reported back once applied
+    pub version: String,
+    #[serde(default)]
+    pub intervals: CollectionIntervals,
+    #[serde(default)]
+    pub collectors: EnabledCollectors,
+    #[serde(default)]
+    pub thresholds: AlertThresholds,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_channel: Option<String>,
+    /// Highest update version to install; an empty string removes the pin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_update_version: Option<String>,
+    /// Let `max_update_version` take the node below its installed version
+    #[serde(default)]
+    pub allow_downgrade: bool,
+}
+
+/// Collection intervals in seconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionIntervals {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnabledCollectors {
+    #[serde(default = "enabled")]
+    pub cpu: bool,
+    #[serde(default = "enabled")]
+    pub network: bool,
+    #[serde(default = "enabled")]
+    pub storage: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for EnabledCollectors {
+    fn default() -> Self {
+        Self { cpu: true, network: true, storage: true }
+    }
+}
+
+/// Limits that raise an alert event when exceeded
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertThresholds {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_percent: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature_celsius: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_percent: Option<f64>,
+}
+
+/// A threshold that was exceeded
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    pub metric: String,
+    /// Mount point or other instance the value belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    pub value: f64,
+    pub threshold: f64,
+}
+
+impl std::fmt::Display for Alert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.metric)?;
+        if let Some(subject) = &self.subject {
+            write!(f, " ({})", subject)?;
+        }
+        write!(f, " at {:.1}, threshold {:.1}", self.value, self.threshold)
+    }
+}
+
+impl AlertThresholds {
+    pub fn check_cpu(&self, cpu: &CpuMetrics) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        if let Some(threshold) = self.cpu_percent.filter(|t| cpu.current_load > *t) {
+            alerts.push(Alert { metric: "cpu_percent".to_string(), subject: None, value: cpu.current_load, threshold });
+        }
+        if let Some(threshold) = self.temperature_celsius.filter(|t| cpu.temperature_max > *t) {
+            alerts.push(Alert {
+                metric: "temperature_celsius".to_string(),
+                subject: None,
+                value: cpu.temperature_max,
+                threshold,
+            });
+        }
+        alerts
+    }
+
+    pub fn check_storage(&self, storage: &StorageMetrics) -> Vec<Alert> {
+        let Some(threshold) = self.disk_percent else {
+            return Vec::new();
+        };
+        storage
+            .filesystem_metrics
+            .iter()
+            .filter(|fs| fs.used_percent > threshold)
+            .map(|fs| Alert {
+                metric: "disk_percent".to_string(),
+                subject: Some(fs.mount.clone()),
+                value: fs.used_percent,
+                threshold,
+            })
+            .collect()
+    }
+}
+
+/// Poll the API for configuration changes every `interval`. The receiver holds
+/// the latest configuration; each new version is reported back to the API.
+pub fn spawn_config_sync(client: Arc<ApiClient>, interval: Duration) -> watch::Receiver<Option<RemoteConfig>> {
+    let (tx, rx) = watch::channel::<Option<RemoteConfig>>(None);
+    tokio::spawn(async move {
+        let mut etag: Option<String> = None;
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if tx.is_closed() {
+                break;
+            }
+            match client.fetch_config(etag.as_deref()).await {
+                Ok(Some((config, new_etag))) => {
+                    etag = new_etag;
+                    if tx.borrow().as_ref().map(|c| &c.version) == Some(&config.version) {
+                        continue;
+                    }
+                    info!("Received node configuration version {}", config.version);
+                    let version = config.version.clone();
+                    tx.send_replace(Some(config));
+                    match client.report_config_version(&version).await {
+                        Ok(()) => debug!("Reported active configuration version {}", version),
+                        Err(err) => warn!("Failed to report configuration version {}: {:#}", version, err),
+                    }
+                }
+                Ok(None) => debug!("Node configuration unchanged"),
+                Err(err) => warn!("Failed to fetch node configuration: {:#}", err),
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config_keeps_defaults() {
+        let config: RemoteConfig = serde_json::from_str(
+            r#"{"version":"7","intervals":{"cpuSecs":10},"collectors":{"storage":false},"thresholds":{"diskPercent":90}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.intervals.cpu_secs, Some(10));
+        assert_eq!(config.intervals.network_secs, None);
+        assert!(config.collectors.cpu && config.collectors.network && !config.collectors.storage);
+        assert_eq!(config.thresholds.disk_percent, Some(90.0));
+        assert_eq!(config.update_channel, None);
+        assert_eq!(config.max_update_version, None);
+        assert!(!config.allow_downgrade);
+    }
+}
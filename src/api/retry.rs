@@ -0,0 +1,137 @@
+use rand::Rng;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use super::circuit_breaker::CircuitState;
+
+/// How failed metric posts are retried within a single send
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts per send, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each further retry
+    pub base_delay: Duration,
+    /// Upper bound for a single backoff delay
+    pub max_delay: Duration,
+    /// Random spread applied to each delay, as a fraction (0.2 = ±20%)
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff before retry number `retry` (1 = first retry), without jitter
+    pub fn base_backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Backoff with jitter applied, never exceeding `max_delay`
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let base = self.base_backoff(retry);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return base;
+        }
+        let factor = rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter);
+        base.mul_f64(factor).min(self.max_delay)
+    }
+}
+
+/// Running totals of metric deliveries since startup
+#[derive(Debug, Default)]
+pub struct DeliveryCounters {
+    sends: AtomicU64,
+    retries: AtomicU64,
+    failures: AtomicU64,
+    acked_sequence: AtomicU64,
+    duplicates: AtomicU64,
+}
+
+impl DeliveryCounters {
+    pub fn record_send(&self) {
+        self.sends.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a server acknowledgment for everything up to `sequence`
+    pub fn record_ack(&self, sequence: Option<u64>, duplicate: bool) {
+        if let Some(sequence) = sequence {
+            self.acked_sequence.fetch_max(sequence, Ordering::Relaxed);
+        }
+        if duplicate {
+            self.duplicates.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self, spooled: usize) -> DeliveryStats {
+        DeliveryStats {
+            sends: self.sends.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            spooled,
+            circuit: None,
+            last_acked_sequence: Some(self.acked_sequence.load(Ordering::Relaxed)).filter(|&seq| seq > 0),
+            duplicates: self.duplicates.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryStats {
+    pub sends: u64,
+    /// Extra attempts made after a retryable failure
+    pub retries: u64,
+    /// Sends that failed after all attempts
+    pub failures: u64,
+    /// Payloads currently waiting in the offline spool
+    pub spooled: usize,
+    /// Circuit breaker state, if one guards the API
+    pub circuit: Option<CircuitState>,
+    /// Highest sequence number the API acknowledged
+    pub last_acked_sequence: Option<u64>,
+    /// Payloads the API had already stored, e.g. replays after a lost response
+    pub duplicates: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_capped_and_jittered() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(3),
+            jitter: 0.2,
+        };
+        assert_eq!(policy.base_backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.base_backoff(2), Duration::from_secs(1));
+        assert_eq!(policy.base_backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.base_backoff(4), Duration::from_secs(3));
+        assert_eq!(policy.base_backoff(40), Duration::from_secs(3));
+
+        for _ in 0..100 {
+            let delay = policy.backoff(2);
+            assert!(delay >= Duration::from_millis(800) && delay <= Duration::from_millis(1200));
+            assert!(policy.backoff(10) <= Duration::from_secs(3));
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{Context, Result};
+use log::warn;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Monotonic upload sequence numbers, so the backend can spot missing payloads.
+///
+/// With a state file the sequence continues across restarts; the file is
+/// rewritten for every number handed out.
+pub struct SequenceCounter {
+    last: Mutex<u64>,
+    path: Option<PathBuf>,
+}
+
+impl SequenceCounter {
+    pub fn in_memory() -> Self {
+        Self { last: Mutex::new(0), path: None }
+    }
+
+    /// Continue from the value stored in `path`, starting at 1 if there is none
+    pub fn persistent(path: PathBuf) -> Result<Self> {
+        let last = match fs::read_to_string(&path) {
+            Ok(contents) => contents.trim().parse().unwrap_or_else(|_| {
+                warn!("Ignoring invalid sequence number in {}", path.display());
+                0
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        Ok(Self { last: Mutex::new(last), path: Some(path) })
+    }
+
+    /// Hand out the next sequence number
+    pub fn next(&self) -> u64 {
+        let mut last = self.last.lock().unwrap();
+        *last += 1;
+        if let Some(path) = &self.path {
+            let tmp = path.with_extension("tmp");
+            if let Err(err) = fs::write(&tmp, last.to_string()).and_then(|_| fs::rename(&tmp, path)) {
+                warn!("Failed to persist sequence number to {}: {}", path.display(), err);
+            }
+        }
+        *last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sequence_survives_restart() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state/sequence");
+
+        let counter = SequenceCounter::persistent(path.clone()).unwrap();
+        assert_eq!(counter.next(), 1);
+        assert_eq!(counter.next(), 2);
+
+        let restarted = SequenceCounter::persistent(path).unwrap();
+        assert_eq!(restarted.next(), 3);
+        assert_eq!(SequenceCounter::in_memory().next(), 1);
+    }
+}
@@ -0,0 +1,568 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::json;
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use crate::metrics::agent::types::AgentMetrics;
+use crate::metrics::cpu::types::CpuMetrics;
+use crate::metrics::network::types::NetworkSnapshot;
+use crate::metrics::storage::types::StorageMetrics;
+use crate::metrics::system::types::SystemInfo;
+use crate::node_identity::NodeIdentity;
+use crate::proxy;
+use super::auth::{OAuthConfig, TokenProvider};
+use super::circuit_breaker::CircuitBreakerConfig;
+use super::commands::{spawn_pushed_commands, NodeCommand};
+use super::encryption::PayloadEncryption;
+use super::enrollment;
+use super::keychain::SecretStore;
+use super::events::{spawn_event_delivery, EventQueue};
+use super::sequence::SequenceCounter;
+use super::field_policy::FieldPolicy;
+use super::{ApiClient, CompressionMode, FileSink, InfluxConfig, InfluxSink, MetricsSpool, MqttConfig, MqttSink, PrometheusSink, RetryPolicy};
+
+/// Size limit of the on-disk event queue
+const EVENTS_SPOOL_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Everything collected since the previous server update
+#[derive(Debug, Serialize)]
+pub struct MetricsBatch<'a> {
+    pub system: &'a SystemInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<&'a CpuMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<&'a NetworkSnapshot>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<&'a StorageMetrics>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent: Option<&'a AgentMetrics>,
+    /// System fields that changed since the previous update
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub system_changes: &'a [String],
+}
+
+/// A destination for collected metrics
+#[async_trait]
+pub trait MetricsSink: Send + Sync {
+    /// Short name used in logs
+    fn name(&self) -> &str;
+
+    /// Deliver one batch. Errors are reported per sink and never affect other sinks.
+    async fn send(&self, batch: &MetricsBatch<'_>) -> Result<()>;
+
+    /// Push a single event right away; returns false if the sink cannot do that
+    /// and the event should wait for the next batch
+    fn stream_event(&self, _kind: &str, _event: &serde_json::Value) -> bool {
+        false
+    }
+}
+
+#[async_trait]
+impl<T: MetricsSink + ?Sized> MetricsSink for Arc<T> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    async fn send(&self, batch: &MetricsBatch<'_>) -> Result<()> {
+        (**self).send(batch).await
+    }
+
+    fn stream_event(&self, kind: &str, event: &serde_json::Value) -> bool {
+        (**self).stream_event(kind, event)
+    }
+}
+
+/// Fans metrics out to every configured sink
+#[derive(Default)]
+pub struct MetricsSinks {
+    sinks: Vec<Box<dyn MetricsSink>>,
+    api: Option<Arc<ApiClient>>,
+}
+
+impl MetricsSinks {
+    pub fn push(&mut self, sink: Box<dyn MetricsSink>) {
+        info!("Metrics sink enabled: {}", sink.name());
+        self.sinks.push(sink);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// The monitoring API client, for tasks that talk to the API beyond metric uploads
+    pub fn api_client(&self) -> Option<Arc<ApiClient>> {
+        self.api.clone()
+    }
+
+    /// Send `batch` to all sinks concurrently, logging failures per sink
+    pub async fn send(&self, batch: &MetricsBatch<'_>) {
+        let results = join_all(self.sinks.iter().map(|sink| sink.send(batch))).await;
+        for (sink, result) in self.sinks.iter().zip(results) {
+            match result {
+                Ok(()) => info!("Successfully sent metrics to {}", sink.name()),
+                Err(err) => warn!("Failed to send metrics to {}: {:#}", sink.name(), err),
+            }
+        }
+    }
+
+    /// Offer an event to every sink that can stream it
+    pub fn stream_event<T: Serialize>(&self, kind: &str, event: &T) {
+        let Ok(event) = serde_json::to_value(event) else {
+            return;
+        };
+        for sink in &self.sinks {
+            sink.stream_event(kind, &event);
+        }
+    }
+
+    /// Build the sinks configured through environment variables; sinks that fail
+    /// to initialize are logged and skipped. Commands streamed by the API are
+    /// forwarded to `commands`.
+    pub async fn from_env(identity: &NodeIdentity, commands: mpsc::Sender<NodeCommand>) -> Result<Self> {
+        let mut sinks = Self::default();
+
+        if let Some(client) = api_client_from_env(identity, commands).await? {
+            let client = Arc::new(client);
+            if let Some(queue) = client.event_queue() {
+                spawn_event_delivery(client.clone(), queue);
+            }
+            sinks.push(Box::new(client.clone()));
+            sinks.api = Some(client);
+        }
+
+        // Optionally publish metrics to an MQTT broker as well
+        if let Some(broker) = env::var("MQTT_BROKER").ok().filter(|b| !b.is_empty()) {
+            let config = broker.parse().map_err(anyhow::Error::from).map(|broker| MqttConfig {
+                broker,
+                client_id: env::var("MQTT_CLIENT_ID").unwrap_or_else(|_| format!("node-controller-{}", identity.node_id)),
+                username: env::var("MQTT_USERNAME").ok(),
+                password: env::var("MQTT_PASSWORD").ok(),
+                topic_template: env::var("MQTT_TOPIC_TEMPLATE")
+                    .unwrap_or_else(|_| "node-controller/{node_id}/{metric}".to_string()),
+                qos: env::var("MQTT_QOS").ok().and_then(|v| v.parse().ok()).unwrap_or(1),
+                keep_alive: Duration::from_secs(
+                    env::var("MQTT_KEEP_ALIVE_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(60)
+                ),
+            });
+            match config.and_then(|config| MqttSink::spawn(config, identity.node_id.clone(), identity.node_name.clone())) {
+                Ok(sink) => {
+                    info!("Publishing metrics to MQTT broker {}", broker);
+                    sinks.push(Box::new(sink));
+                }
+                Err(err) => error!("Failed to configure MQTT sink: {}", err),
+            }
+        }
+
+        // Write line protocol to InfluxDB (or anything speaking its v2 write API)
+        if let Some(url) = env::var("INFLUX_URL").ok().filter(|u| !u.is_empty()) {
+            let config = url.parse().map_err(anyhow::Error::from).map(|url| InfluxConfig {
+                url,
+                org: env::var("INFLUX_ORG").unwrap_or_default(),
+                bucket: env::var("INFLUX_BUCKET").unwrap_or_else(|_| "node-controller".to_string()),
+                token: env::var("INFLUX_TOKEN").ok().filter(|t| !t.is_empty()),
+            });
+            match config.and_then(|config| InfluxSink::new(config, identity.node_id.clone())) {
+                Ok(sink) => {
+                    info!("Writing metrics to InfluxDB at {}", url);
+                    sinks.push(Box::new(sink));
+                }
+                Err(err) => error!("Failed to configure InfluxDB sink: {:#}", err),
+            }
+        }
+
+        // Append every batch to a local JSON lines file
+        if let Some(path) = env::var("METRICS_FILE").ok().filter(|p| !p.is_empty()) {
+            let max_mb = env::var("METRICS_FILE_MAX_MB")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(100);
+            match FileSink::new(PathBuf::from(&path), max_mb * 1024 * 1024) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(err) => error!("Failed to configure metrics file {}: {:#}", path, err),
+            }
+        }
+
+        // Serve the latest values for Prometheus to scrape
+        if let Some(addr) = env::var("PROMETHEUS_LISTEN_ADDR").ok().filter(|a| !a.is_empty()) {
+            match addr.parse().map_err(anyhow::Error::from).and_then(PrometheusSink::bind) {
+                Ok(sink) => sinks.push(Box::new(sink)),
+                Err(err) => error!("Failed to start Prometheus exporter on {}: {:#}", addr, err),
+            }
+        }
+
+        if sinks.is_empty() {
+            warn!("No metrics sinks available, printing server updates to stdout");
+            sinks.push(Box::new(StdoutSink { node_id: identity.node_id.clone() }));
+        }
+
+        Ok(sinks)
+    }
+}
+
+async fn api_client_from_env(identity: &NodeIdentity, commands: mpsc::Sender<NodeCommand>) -> Result<Option<ApiClient>> {
+    let api_url = env::var("MONITORING_API_URL")
+        .unwrap_or_else(|_| "http://localhost:3000".to_string());
+    if api_url.is_empty() {
+        info!("MONITORING_API_URL is empty, not sending metrics to the monitoring API");
+        return Ok(None);
+    }
+    // Secrets come from the Keychain / Secret Service when available; values still in
+    // the environment are migrated there
+    let secrets = SecretStore::from_env();
+    if secrets != SecretStore::Env {
+        info!("Reading API credentials from {:?} store", secrets);
+    }
+
+    // OAuth2 client credentials replace the static API key when configured
+    let oauth = match env::var("OAUTH_TOKEN_URL").ok().filter(|url| !url.is_empty()) {
+        Some(url) => Some(OAuthConfig {
+            token_url: url.parse().with_context(|| format!("Invalid OAUTH_TOKEN_URL '{}'", url))?,
+            client_id: env::var("OAUTH_CLIENT_ID").context("OAUTH_CLIENT_ID is required with OAUTH_TOKEN_URL")?,
+            client_secret: secrets.resolve("OAUTH_CLIENT_SECRET")
+                .context("OAUTH_CLIENT_SECRET is required with OAUTH_TOKEN_URL")?,
+            scope: env::var("OAUTH_SCOPE").ok().filter(|s| !s.is_empty()),
+            audience: env::var("OAUTH_AUDIENCE").ok().filter(|a| !a.is_empty()),
+        }),
+        None => None,
+    };
+    let api_key = secrets.resolve("MONITORING_API_KEY").unwrap_or_else(|| {
+        if oauth.is_some() { String::new() } else { "dev-api-key".to_string() }
+    });
+
+    // Node-specific credentials from enrollment take precedence over the shared key
+    let credentials_path = env::var("NODE_CREDENTIALS_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| enrollment::default_credentials_path());
+    let enrollment_token = env::var("ENROLLMENT_TOKEN").ok().filter(|t| !t.is_empty());
+    let api_key = match enrollment::load_or_enroll(&credentials_path, &api_url, enrollment_token.as_deref(), identity).await {
+        Ok(Some(credentials)) => credentials.api_key,
+        Ok(None) => api_key,
+        Err(err) => {
+            error!("Node enrollment failed, using the configured API key: {:#}", err);
+            api_key
+        }
+    };
+
+    info!("Starting node controller with monitoring API at: {}", api_url);
+
+    let client = match ApiClient::new(api_url, api_key, identity.node_id.clone()) {
+        Ok(client) => client.with_labels(identity.labels.clone()),
+        Err(err) => {
+            error!("Failed to initialize API client: {}", err);
+            return Ok(None);
+        }
+    };
+    info!("API client initialized successfully");
+
+    let client = match oauth {
+        Some(config) => {
+            info!("Authenticating with OAuth2 client credentials from {}", config.token_url);
+            if env::var("API_WS_URL").is_ok_and(|url| !url.is_empty()) {
+                warn!("The WebSocket transport only supports X-API-Key authentication");
+            }
+            client.with_oauth(TokenProvider::new(config)?)
+        }
+        None => client,
+    };
+
+    let compression = match env::var("API_COMPRESSION") {
+        Ok(mode) => mode.parse::<CompressionMode>().unwrap_or_else(|err| {
+            warn!("{}, falling back to auto", err);
+            CompressionMode::Auto
+        }),
+        Err(_) => CompressionMode::Auto,
+    };
+    let client = client.with_compression(compression);
+
+    // End-to-end encryption to the backend's age key(s); never falls back to plaintext
+    let client = match env::var("API_ENCRYPTION_RECIPIENTS").ok().filter(|r| !r.trim().is_empty()) {
+        Some(recipients) => {
+            let encryption = PayloadEncryption::from_list(&recipients).context("Invalid API_ENCRYPTION_RECIPIENTS")?;
+            if !encryption.is_available() {
+                error!("Payload encryption is configured but age is not installed; metrics will be spooled, not sent");
+            }
+            if ["API_GRPC_URL", "API_WS_URL"].iter().any(|var| env::var(var).is_ok_and(|url| !url.is_empty())) {
+                warn!("Encrypted metrics are only sent over HTTP POST, not gRPC or the WebSocket");
+            }
+            info!("Encrypting metric payloads end to end");
+            client.with_encryption(encryption)
+        }
+        None => client,
+    };
+
+    let retry_defaults = RetryPolicy::default();
+    let client = client.with_retry_policy(RetryPolicy {
+        max_attempts: env::var("API_RETRY_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .map(|attempts| attempts.max(1))
+            .unwrap_or(retry_defaults.max_attempts),
+        base_delay: env::var("API_RETRY_BASE_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(retry_defaults.base_delay),
+        max_delay: env::var("API_RETRY_MAX_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(retry_defaults.max_delay),
+        jitter: env::var("API_RETRY_JITTER")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(retry_defaults.jitter),
+    });
+
+    // Redact privacy-sensitive fields (comma-separated patterns such as `**.serialNumber`)
+    let field_list = |name: &str| -> Vec<String> {
+        env::var(name)
+            .map(|list| list.split(',').map(|p| p.trim().to_string()).collect())
+            .unwrap_or_default()
+    };
+    let field_policy = FieldPolicy::new(&field_list("API_FIELD_ALLOW"), &field_list("API_FIELD_DENY"));
+    let client = if field_policy.is_empty() {
+        client
+    } else {
+        info!("Applying payload field policy: {:?}", field_policy);
+        client.with_field_policy(field_policy)
+    };
+
+    // Peer discovery across networks may use its own rendezvous endpoint
+    let client = match env::var("DISCOVERY_RENDEZVOUS_URL").ok().filter(|url| !url.is_empty()) {
+        Some(url) => client.with_rendezvous_url(url),
+        None => client,
+    };
+
+    // Send only changed fields, with a periodic full snapshot
+    let delta_mode = env::var("API_DELTA_MODE")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let client = if delta_mode {
+        let full_interval = env::var("API_DELTA_FULL_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(300);
+        client.with_delta(Duration::from_secs(full_interval))
+    } else {
+        client
+    };
+
+    // Pause sends after repeated failures (API_CIRCUIT_FAILURE_THRESHOLD=0 disables)
+    let breaker_defaults = CircuitBreakerConfig::default();
+    let failure_threshold = env::var("API_CIRCUIT_FAILURE_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(breaker_defaults.failure_threshold);
+    let client = if failure_threshold == 0 {
+        client
+    } else {
+        client.with_circuit_breaker(CircuitBreakerConfig {
+            failure_threshold,
+            cool_down: env::var("API_CIRCUIT_COOLDOWN_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(breaker_defaults.cool_down),
+            probes_to_close: env::var("API_CIRCUIT_PROBES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(breaker_defaults.probes_to_close),
+        })
+    };
+
+    // Spool payloads to disk while the API is unreachable (METRICS_SPOOL_MAX_MB=0 disables)
+    let spool_max_mb = env::var("METRICS_SPOOL_MAX_MB")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(50);
+    let spool_dir = env::var("METRICS_SPOOL_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            dirs::home_dir()
+                .map(|home| home.join("Library/Application Support/NodeController/spool"))
+                .unwrap_or_else(|| PathBuf::from("./spool"))
+        });
+    let client = match env::var("API_WS_URL").ok().filter(|url| !url.is_empty()) {
+        Some(url) => match url.parse::<reqwest::Url>() {
+            Ok(url) => {
+                let (messages_tx, messages_rx) = mpsc::channel(16);
+                spawn_pushed_commands(messages_rx, commands.clone());
+                client.with_websocket(url, Some(messages_tx))?
+            }
+            Err(err) => {
+                warn!("Ignoring invalid API_WS_URL '{}': {}", url, err);
+                client
+            }
+        },
+        None => client,
+    };
+    let client = match env::var("API_GRPC_URL").ok().filter(|url| !url.is_empty()) {
+        Some(url) => match url.parse::<reqwest::Url>() {
+            Ok(url) => {
+                if proxy::global().proxy_for(&url).is_some() {
+                    warn!("The gRPC transport does not support proxies, connecting to {} directly", url);
+                }
+                let (commands_tx, mut commands_rx) = mpsc::channel::<super::grpc::metrics::Command>(16);
+                tokio::spawn(async move {
+                    while let Some(command) = commands_rx.recv().await {
+                        info!("Received command '{}' ({}) from monitoring service", command.kind, command.id);
+                        if commands.send(command.into()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+                client.with_grpc(url, Some(commands_tx))?
+            }
+            Err(err) => {
+                warn!("Ignoring invalid API_GRPC_URL '{}': {}", url, err);
+                client
+            }
+        },
+        None => client,
+    };
+
+    // Queue discrete events for POST /api/v1/events (API_EVENTS=false streams them over the WebSocket)
+    let events_enabled = env::var("API_EVENTS")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(true);
+    let client = if events_enabled {
+        let events_dir = env::var("EVENTS_SPOOL_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| {
+                dirs::home_dir()
+                    .map(|home| home.join("Library/Application Support/NodeController/events"))
+                    .unwrap_or_else(|| PathBuf::from("./events"))
+            });
+        match EventQueue::new(events_dir, EVENTS_SPOOL_MAX_BYTES, identity.node_id.clone()) {
+            Ok(queue) => {
+                if !queue.is_empty() {
+                    info!("{} queued event(s) waiting for delivery", queue.len());
+                }
+                client.with_events(Arc::new(queue))
+            }
+            Err(err) => {
+                warn!("Event queue disabled: {}", err);
+                client
+            }
+        }
+    } else {
+        client
+    };
+
+    // Continue payload sequence numbers across restarts so the API can detect gaps
+    let sequence_file = env::var("API_SEQUENCE_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            dirs::home_dir()
+                .map(|home| home.join("Library/Application Support/NodeController/sequence"))
+                .unwrap_or_else(|| PathBuf::from("./sequence"))
+        });
+    let client = match SequenceCounter::persistent(sequence_file) {
+        Ok(sequence) => client.with_sequence(sequence),
+        Err(err) => {
+            warn!("Payload sequence numbers restart at 1 on every run: {}", err);
+            client
+        }
+    };
+
+    // Timestamp of the last acknowledged upload, checked after updates
+    let client = client.with_delivery_marker(delivery_marker_path());
+
+    if spool_max_mb == 0 {
+        return Ok(Some(client));
+    }
+    match MetricsSpool::new(spool_dir, spool_max_mb * 1024 * 1024) {
+        Ok(spool) => {
+            if !spool.is_empty() {
+                info!("{} spooled metric payload(s) waiting for replay", spool.len());
+            }
+            Ok(Some(client.with_spool(spool)))
+        }
+        Err(err) => {
+            warn!("Metrics spool disabled: {}", err);
+            Ok(Some(client))
+        }
+    }
+}
+
+/// File the API client updates after every acknowledged upload (API_DELIVERY_MARKER)
+pub fn delivery_marker_path() -> PathBuf {
+    env::var("API_DELIVERY_MARKER")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            dirs::home_dir()
+                .map(|home| home.join("Library/Application Support/NodeController/last_delivery"))
+                .unwrap_or_else(|| PathBuf::from("./last_delivery"))
+        })
+}
+
+/// Prints the prepared server update when no other sink is available
+struct StdoutSink {
+    node_id: String,
+}
+
+#[async_trait]
+impl MetricsSink for StdoutSink {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    async fn send(&self, batch: &MetricsBatch<'_>) -> Result<()> {
+        let system_info = batch.system;
+        let mut update_payload = json!({
+            "timestamp": chrono::Utc::now(),
+            "node_id": self.node_id,
+            "hostname": system_info.hostname,
+        });
+
+        if let Some(cpu) = batch.cpu {
+            update_payload["cpu"] = json!(cpu);
+        }
+        if let Some(network) = batch.network {
+            update_payload["network"] = json!(network);
+        }
+        if let Some(storage) = batch.storage {
+            update_payload["storage"] = json!(storage);
+        }
+        if let Some(agent) = batch.agent {
+            update_payload["agent"] = json!(agent);
+        }
+
+        // Add system changes if any
+        if !batch.system_changes.is_empty() {
+            let mut system_update = json!({});
+            for field in batch.system_changes {
+                match field.as_str() {
+                    "peripherals" => { system_update["peripherals"] = json!(system_info.peripherals); }
+                    "displays" => {
+                        system_update["displays"] = json!(system_info.displays);
+                        system_update["display_events"] = json!(system_info.display_events);
+                    }
+                    "power" => { system_update["power"] = json!(system_info.power); }
+                    "clock" => { system_update["clock"] = json!(system_info.clock); }
+                    "platform" => {
+                        system_update["platform"] = json!({
+                            "available_memory": system_info.platform.available_memory,
+                            "load_average": system_info.platform.load_average,
+                            "uptime_seconds": system_info.platform.uptime_seconds,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            update_payload["system_changes"] = system_update;
+        }
+
+        println!("\nPrepared server update (no metrics sink configured):");
+        println!("{}", serde_json::to_string_pretty(&update_payload)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Disk-backed queue of metric payloads that could not be delivered.
+///
+/// Each payload is stored as one JSON file named after its collection timestamp,
+/// so file names sort oldest-first and a payload is never queued twice.
+pub struct MetricsSpool {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+/// A queued payload
+pub struct SpooledPayload {
+    pub path: PathBuf,
+    pub body: Vec<u8>,
+}
+
+impl MetricsSpool {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create spool directory {}", dir.display()))?;
+        Ok(Self { dir, max_bytes })
+    }
+
+    /// Queue a payload; returns false if one with the same timestamp is already queued.
+    /// Evicts the oldest payloads once the spool exceeds its size limit.
+    pub fn enqueue(&self, timestamp: DateTime<Utc>, body: &[u8]) -> Result<bool> {
+        let path = self.dir.join(format!("{:020}.json", timestamp.timestamp_nanos_opt().unwrap_or_default()));
+        if path.exists() {
+            debug!("Payload {} already spooled", path.display());
+            return Ok(false);
+        }
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, body).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to spool {}", path.display()))?;
+
+        self.evict()?;
+        Ok(true)
+    }
+
+    /// Queued payload files, oldest first
+    pub fn pending(&self) -> Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending().map(|files| files.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read the oldest queued payload without removing it
+    pub fn peek(&self) -> Result<Option<SpooledPayload>> {
+        match self.pending()?.into_iter().next() {
+            Some(path) => {
+                let body = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+                Ok(Some(SpooledPayload { path, body }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Read up to `max` of the oldest queued payloads without removing them
+    pub fn peek_batch(&self, max: usize) -> Result<Vec<SpooledPayload>> {
+        self.pending()?
+            .into_iter()
+            .take(max)
+            .map(|path| {
+                let body = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+                Ok(SpooledPayload { path, body })
+            })
+            .collect()
+    }
+
+    /// Remove a payload after it was delivered (or permanently rejected)
+    pub fn remove(&self, payload: &SpooledPayload) -> Result<()> {
+        remove_file(&payload.path)
+    }
+
+    fn evict(&self) -> Result<()> {
+        let files = self.pending()?;
+        let sizes: Vec<u64> = files.iter()
+            .map(|path| fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+            .collect();
+        let mut total: u64 = sizes.iter().sum();
+
+        for (path, size) in files.iter().zip(sizes) {
+            // Always keep the newest payload, even if it alone exceeds the limit
+            if total <= self.max_bytes || Some(path) == files.last() {
+                break;
+            }
+            warn!("Metrics spool over {} bytes, dropping oldest payload {}", self.max_bytes, path.display());
+            remove_file(path)?;
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_spool_order_dedup_and_eviction() {
+        let dir = tempdir().unwrap();
+        let spool = MetricsSpool::new(dir.path().join("spool"), 25).unwrap();
+        let at = |secs: i64| Utc.timestamp_opt(1_714_550_000 + secs, 0).unwrap();
+
+        assert!(spool.enqueue(at(2), b"{\"n\":2}").unwrap());
+        assert!(spool.enqueue(at(1), b"{\"n\":1}").unwrap());
+        assert!(!spool.enqueue(at(1), b"{\"n\":1}").unwrap());
+        assert_eq!(spool.len(), 2);
+
+        // Replay is oldest first regardless of enqueue order
+        let first = spool.peek().unwrap().unwrap();
+        assert_eq!(first.body, b"{\"n\":1}");
+        spool.remove(&first).unwrap();
+        assert_eq!(spool.peek().unwrap().unwrap().body, b"{\"n\":2}");
+
+        // 3 x 7 bytes exceeds the 25 byte limit once a fourth arrives
+        spool.enqueue(at(3), b"{\"n\":3}").unwrap();
+        spool.enqueue(at(4), b"{\"n\":4}").unwrap();
+        spool.enqueue(at(5), b"{\"n\":5}").unwrap();
+        assert_eq!(spool.len(), 3);
+        assert_eq!(spool.peek().unwrap().unwrap().body, b"{\"n\":3}");
+    }
+}
@@ -0,0 +1,413 @@
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{debug, info, warn};
+use rand::Rng;
+use reqwest::Url;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, WriteHalf};
+use tokio::sync::mpsc;
+use crate::proxy;
+
+/// GUID appended to the client key when computing `Sec-WebSocket-Accept` (RFC 6455 §1.3)
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const OUTGOING_QUEUE: usize = 64;
+const MAX_FRAME_BYTES: u64 = 16 * 1024 * 1024;
+/// Largest message reassembled from fragments
+const MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+/// Frames read ahead of the session loop
+const INCOMING_FRAMES: usize = 16;
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(60);
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// Persistent WebSocket connection to the monitoring service.
+///
+/// A background task owns the socket and reconnects with exponential backoff;
+/// callers only enqueue text frames and fall back to HTTP while it is down.
+pub struct WebSocketTransport {
+    outgoing: mpsc::Sender<String>,
+    connected: Arc<AtomicBool>,
+}
+
+impl WebSocketTransport {
+    /// Start connecting to `url` (`ws://` or `wss://`) in the background.
+    /// Text frames received from the server are forwarded to `incoming` if given.
+    pub fn spawn(
+        url: Url,
+        headers: Vec<(String, String)>,
+        incoming: Option<mpsc::Sender<String>>,
+    ) -> Result<Self> {
+        match url.scheme() {
+            "ws" | "wss" => {}
+            other => return Err(anyhow!("Unsupported WebSocket scheme '{}'", other)),
+        }
+
+        let (outgoing, rx) = mpsc::channel(OUTGOING_QUEUE);
+        let connected = Arc::new(AtomicBool::new(false));
+        tokio::spawn(run(url, headers, rx, incoming, connected.clone()));
+
+        Ok(Self { outgoing, connected })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Queue a text frame; fails if the socket is down or the queue is full
+    pub fn try_send(&self, text: String) -> Result<()> {
+        if !self.is_connected() {
+            return Err(anyhow!("WebSocket not connected"));
+        }
+        self.outgoing.try_send(text).map_err(|e| anyhow!("WebSocket queue unavailable: {}", e))
+    }
+}
+
+async fn run(
+    url: Url,
+    headers: Vec<(String, String)>,
+    mut outgoing: mpsc::Receiver<String>,
+    incoming: Option<mpsc::Sender<String>>,
+    connected: Arc<AtomicBool>,
+) {
+    let mut delay = RECONNECT_MIN;
+    loop {
+        match connect(&url, &headers).await {
+            Ok(stream) => {
+                info!("WebSocket connected to {}", url);
+                connected.store(true, Ordering::SeqCst);
+                delay = RECONNECT_MIN;
+
+                let result = session(stream, &mut outgoing, incoming.as_ref()).await;
+                connected.store(false, Ordering::SeqCst);
+                match result {
+                    Ok(()) => info!("WebSocket to {} closed", url),
+                    Err(e) => warn!("WebSocket to {} dropped: {}", url, e),
+                }
+                if outgoing.is_closed() {
+                    return;
+                }
+            }
+            Err(e) => debug!("WebSocket connect to {} failed: {}", url, e),
+        }
+
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(RECONNECT_MAX);
+        // Frames queued while disconnected are stale; HTTP already delivered them
+        while outgoing.try_recv().is_ok() {}
+    }
+}
+
+async fn connect(url: &Url, headers: &[(String, String)]) -> Result<Box<dyn Connection>> {
+    let host = url.host_str().ok_or_else(|| anyhow!("WebSocket URL has no host"))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let tcp = tokio::time::timeout(Duration::from_secs(10), proxy::connect_tcp(url, host, port))
+        .await
+        .context("Connection timed out")??;
+    tcp.set_nodelay(true)?;
+
+    let mut stream: Box<dyn Connection> = if url.scheme() == "wss" {
+        let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+        Box::new(connector.connect(host, tcp).await?)
+    } else {
+        Box::new(tcp)
+    };
+
+    handshake(&mut stream, url, host, port, headers).await?;
+    Ok(stream)
+}
+
+async fn handshake(
+    stream: &mut Box<dyn Connection>,
+    url: &Url,
+    host: &str,
+    port: u16,
+    headers: &[(String, String)],
+) -> Result<()> {
+    let key = BASE64.encode(rand::thread_rng().gen::<[u8; 16]>());
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+
+    let mut request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n",
+        path, host, port, key
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read the response head byte by byte so no frame data is consumed
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > 8192 {
+            return Err(anyhow!("WebSocket handshake response too large"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let response = String::from_utf8_lossy(&response);
+
+    let status = response.lines().next().unwrap_or_default();
+    if !status.contains(" 101 ") {
+        return Err(anyhow!("WebSocket upgrade refused: {}", status));
+    }
+    let accept = response.lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim().eq_ignore_ascii_case("sec-websocket-accept").then(|| value.trim().to_string())
+        })
+        .ok_or_else(|| anyhow!("WebSocket upgrade missing Sec-WebSocket-Accept"))?;
+    if accept != accept_key(&key) {
+        return Err(anyhow!("WebSocket upgrade returned an invalid Sec-WebSocket-Accept"));
+    }
+    Ok(())
+}
+
+async fn session(
+    stream: Box<dyn Connection>,
+    outgoing: &mut mpsc::Receiver<String>,
+    incoming: Option<&mpsc::Sender<String>>,
+) -> Result<()> {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut message = Vec::new();
+
+    // Reading a frame takes several awaits, so it can't be raced against the
+    // outgoing queue without losing half-read frames; a task reads whole ones
+    let (frame_tx, mut frames) = mpsc::channel(INCOMING_FRAMES);
+    let reading = tokio::spawn(async move {
+        loop {
+            let frame = read_frame(&mut reader).await;
+            let failed = frame.is_err();
+            if frame_tx.send(frame).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+    let _reading = AbortOnDrop(reading);
+
+    loop {
+        tokio::select! {
+            text = outgoing.recv() => {
+                match text {
+                    Some(text) => write_frame(&mut writer, OP_TEXT, text.as_bytes()).await?,
+                    None => {
+                        write_frame(&mut writer, OP_CLOSE, &1000u16.to_be_bytes()).await?;
+                        return Ok(());
+                    }
+                }
+            }
+            frame = frames.recv() => {
+                let (fin, opcode, payload) = frame.ok_or_else(|| anyhow!("WebSocket reader stopped"))??;
+                match opcode {
+                    OP_PING => write_frame(&mut writer, OP_PONG, &payload).await?,
+                    OP_PONG => {}
+                    OP_CLOSE => {
+                        let _ = write_frame(&mut writer, OP_CLOSE, &payload).await;
+                        return Ok(());
+                    }
+                    OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                        if message.len() + payload.len() > MAX_MESSAGE_BYTES {
+                            return Err(anyhow!("WebSocket message exceeds {} bytes", MAX_MESSAGE_BYTES));
+                        }
+                        message.extend_from_slice(&payload);
+                        if fin {
+                            let text = String::from_utf8_lossy(&message).to_string();
+                            message.clear();
+                            match incoming {
+                                Some(tx) => { let _ = tx.send(text).await; }
+                                None => debug!("WebSocket message from server: {}", text),
+                            }
+                        }
+                    }
+                    other => return Err(anyhow!("Unknown WebSocket opcode {:#x}", other)),
+                }
+            }
+        }
+    }
+}
+
+/// Stops the task when the session ends
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Write a single masked frame (clients must mask, RFC 6455 §5.3)
+async fn write_frame(writer: &mut WriteHalf<Box<dyn Connection>>, opcode: u8, payload: &[u8]) -> Result<()> {
+    let mask: [u8; 4] = rand::thread_rng().gen();
+    writer.write_all(&encode_frame(opcode, payload, mask)).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+fn encode_frame(opcode: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => frame.push(0x80 | len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    frame
+}
+
+/// Read one frame, returning (fin, opcode, unmasked payload)
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).await?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0f;
+    let masked = head[1] & 0x80 != 0;
+
+    let len = match head[1] & 0x7f {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    if len > MAX_FRAME_BYTES {
+        return Err(anyhow!("WebSocket frame of {} bytes exceeds limit", len));
+    }
+
+    let mut mask = [0u8; 4];
+    if masked {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    if masked {
+        payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= mask[i % 4]);
+    }
+    Ok((fin, opcode, payload))
+}
+
+fn accept_key(key: &str) -> String {
+    BASE64.encode(sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()))
+}
+
+/// SHA-1 (RFC 3174), only used for the handshake accept key
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_accept_key() {
+        // Example from RFC 6455 §1.3
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        let digest: String = sha1(b"abc").iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(digest, "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[tokio::test]
+    async fn test_frame_round_trip() {
+        for len in [5usize, 300, 70_000] {
+            let payload = vec![b'x'; len];
+            let frame = encode_frame(OP_TEXT, &payload, [1, 2, 3, 4]);
+            let (fin, opcode, decoded) = read_frame(&mut frame.as_slice()).await.unwrap();
+            assert!(fin);
+            assert_eq!(opcode, OP_TEXT);
+            assert_eq!(decoded, payload);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_during_partial_frame() {
+        let (client, mut server) = tokio::io::duplex(1 << 20);
+        let (out_tx, mut out_rx) = mpsc::channel(OUTGOING_QUEUE);
+        let (in_tx, mut in_rx) = mpsc::channel(OUTGOING_QUEUE);
+        let session = tokio::spawn(async move {
+            session(Box::new(client), &mut out_rx, Some(&in_tx)).await
+        });
+
+        // A message sent while only half of a server frame has arrived
+        let frame = encode_frame(OP_TEXT, b"hello from the server", [9, 8, 7, 6]);
+        server.write_all(&frame[..5]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        out_tx.send("hi".to_string()).await.unwrap();
+        let (_, opcode, payload) = read_frame(&mut server).await.unwrap();
+        assert_eq!((opcode, payload.as_slice()), (OP_TEXT, b"hi".as_slice()));
+        server.write_all(&frame[5..]).await.unwrap();
+        assert_eq!(in_rx.recv().await.unwrap(), "hello from the server");
+
+        // Fragments adding up to more than a message may hold
+        let fragment = vec![b'x'; MAX_FRAME_BYTES as usize];
+        for _ in 0..2 {
+            let mut frame = encode_frame(OP_CONTINUATION, &fragment, [0; 4]);
+            frame[0] &= 0x7f;
+            server.write_all(&frame).await.unwrap();
+        }
+        let err = session.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("exceeds"), "{}", err);
+    }
+}
@@ -0,0 +1,658 @@
+// src/cli.rs
+//
+// Command line
+// Without arguments, or with `run`, the binary runs the agent. The other
+// subcommands do one thing with the agent's configuration and exit: check
+// for, install or roll back an update (here or, over gRPC, on a peer), send, sync, broadcast or fetch files,
+// browse a peer's exports, show transfer statistics, benchmark the link to a
+// peer, list the nodes on the network, print a metrics snapshot, or check
+// the configuration file. The arguments are parsed with clap, which also
+// prints the help. The commands replace the interactive test binaries.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::{find_config_file, AgentConfig, DEFAULT_CONFIG_DIR};
+use crate::metrics::{CpuCollector, NetworkCollector, NetworkCollectorConfig, StorageCollector, SystemCollectorConfig, SystemInfoCollector};
+use crate::networking::broadcast::DEFAULT_FAN_OUT;
+use crate::networking::file_transfer::TransferDirection;
+use crate::networking::communication::node::UpdateStatusResponse;
+use crate::networking::{BenchmarkPlan, FileTransferManager, NodeClient, NodeDiscovery, NodeInfo, UdpMode};
+use crate::node_identity::NodeIdentity;
+use crate::updater::{UpdateManager, UpdateStatus};
+
+/// How long `nodes` and `send-file` listen for peers by default
+const DEFAULT_DISCOVERY_WAIT: Duration = Duration::from_secs(5);
+/// How long `update --node` and `rollback --node` follow the node
+const REMOTE_UPDATE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+/// Update states a node stays in until something else happens
+const SETTLED_STATES: [&str; 6] = ["update_success", "restart_pending", "update_failed", "rolled_back", "preflight_failed", "error"];
+
+/// Runs the agent, or does one thing with its configuration and exits.
+/// Settings come from the configuration file and the environment, as for the agent.
+#[derive(Debug, Parser)]
+#[command(name = "node-controller-rust", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+impl Cli {
+    /// The command asked for, running the agent without one
+    pub fn command(self) -> Command {
+        self.command.unwrap_or(Command::Run)
+    }
+}
+
+/// What the command line asks for
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Run the agent (the default)
+    Run,
+    /// Check for an update and print what was found
+    CheckUpdate {
+        /// Check on this node (name or ID prefix) instead, over gRPC
+        #[arg(long, value_name = "PEER")]
+        node: Option<String>,
+    },
+    /// Install the available update
+    Update {
+        /// Install exactly this version
+        #[arg(long, value_name = "VERSION")]
+        to: Option<String>,
+        /// Allow installing a lower version
+        #[arg(long, conflicts_with = "node")]
+        force: bool,
+        /// Update this node (name or ID prefix) instead, and follow it until it is done
+        #[arg(long, value_name = "PEER")]
+        node: Option<String>,
+    },
+    /// Restore the version installed before the last update
+    Rollback {
+        /// Roll back this node (name or ID prefix) instead, and follow it until it is done
+        #[arg(long, value_name = "PEER")]
+        node: Option<String>,
+    },
+    /// Send a file or directory to a node
+    SendFile {
+        /// Name, ID prefix or host:port of the node's file transfer server
+        peer: String,
+        path: PathBuf,
+        /// Send only the blocks that differ from the node's copy
+        #[arg(long)]
+        sync: bool,
+    },
+    /// Send a file or directory to every node on the network
+    Broadcast {
+        path: PathBuf,
+        /// Seconds to listen for nodes
+        #[arg(long, value_name = "SECS", default_value = "5", value_parser = seconds)]
+        wait: Duration,
+    },
+    /// Have a node send a file or directory it exports to the agent on this node
+    Fetch {
+        /// Name or ID prefix of the node
+        peer: String,
+        /// Path on the node, relative ones in its export roots
+        path: String,
+    },
+    /// List the export roots of a node, or a directory in them
+    Browse {
+        /// Name or ID prefix of the node
+        peer: String,
+        path: Option<String>,
+    },
+    /// Per-peer transfer statistics of this node, or of another one
+    History {
+        /// Name or ID prefix of the node
+        peer: Option<String>,
+    },
+    /// Measure round trips and MB/s per stream count to a node
+    Benchmark {
+        /// Name or ID prefix of the node
+        peer: String,
+        /// MB sent from memory per run
+        #[arg(long, value_name = "MB")]
+        run_mb: Option<u64>,
+    },
+    /// List the nodes on the network
+    Nodes {
+        /// Seconds to listen for nodes
+        #[arg(long, value_name = "SECS", default_value = "5", value_parser = seconds)]
+        wait: Duration,
+    },
+    /// Print a metrics snapshot as JSON
+    Metrics {
+        /// Print one snapshot and exit
+        #[arg(long)]
+        once: bool,
+        /// Seconds between snapshots
+        #[arg(long, value_name = "SECS", default_value = "5", value_parser = seconds)]
+        interval: Duration,
+        /// Print the metrics summary this node (name or ID prefix) shares instead
+        #[arg(long, value_name = "PEER")]
+        node: Option<String>,
+    },
+    /// Work with the configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+/// `config` subcommands
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum ConfigCommand {
+    /// Check the configuration file, or the one at PATH
+    Validate { path: Option<PathBuf> },
+}
+
+/// A whole number of seconds
+fn seconds(value: &str) -> Result<Duration, String> {
+    value.parse().map(Duration::from_secs).map_err(|_| format!("expected whole seconds, not '{}'", value))
+}
+
+/// `state version: detail`, as much of it as the status has
+fn describe(status: &UpdateStatus) -> String {
+    let mut text = status.state().to_string();
+    if let Some(version) = status.version() {
+        text = format!("{} {}", text, version);
+    }
+    if let Some(detail) = status.detail() {
+        text = format!("{}: {}", text, detail);
+    }
+    text
+}
+
+/// Check for an update without installing it
+pub async fn check_update(manager: &UpdateManager) -> Result<()> {
+    println!("Current version: {}", manager.handle().current_version());
+    println!("{}", describe(&manager.check_now().await?));
+    Ok(())
+}
+
+/// Install the update a check finds; with `version`, only that version
+pub async fn update(manager: &UpdateManager, version: Option<&str>) -> Result<()> {
+    println!("Current version: {}", manager.handle().current_version());
+    match manager.check_now().await? {
+        UpdateStatus::UpdateAvailable(_) | UpdateStatus::UpdateDeferred(_) => {}
+        status => return Err(anyhow!("Nothing to install ({})", describe(&status))),
+    }
+    let status = manager.apply_now(version).await?;
+    println!("{}", describe(&status));
+    if let UpdateStatus::RestartPending { version } = status {
+        println!("Restart the agent to run version {}", version);
+    }
+    Ok(())
+}
+
+/// Restore the newest backup
+pub async fn rollback(manager: &UpdateManager) -> Result<()> {
+    println!("{}", describe(&manager.rollback_now().await?));
+    Ok(())
+}
+
+/// `state version: detail` of a node's update status, with the download progress
+fn describe_remote(status: &UpdateStatusResponse) -> String {
+    let mut text = status.state.clone();
+    if !status.version.is_empty() {
+        text = format!("{} {}", text, status.version);
+    }
+    if status.state == "downloading" {
+        text = format!("{} ({}%)", text, status.progress);
+    }
+    if !status.detail.is_empty() {
+        text = format!("{}: {}", text, status.detail);
+    }
+    text
+}
+
+/// Have `peer` check for an update
+pub async fn check_update_on(peers: &Peers, peer: &str) -> Result<()> {
+    let session = Session::start(peers).await?;
+    let result = async {
+        let node = session.find(peer).await?;
+        let status = peers.client.check_for_updates(&node, &session.local_node).await?;
+        println!("{} runs version {}", node.name, status.current_version);
+        println!("{}", describe_remote(&status));
+        Ok(())
+    }.await;
+    session.finish(result)
+}
+
+/// What `update --node` and `rollback --node` ask of the node
+#[derive(Clone, Copy)]
+pub enum VersionChange<'a> {
+    /// The update its check finds, or only this version
+    Update(Option<&'a str>),
+    Rollback,
+}
+
+/// Have `peer` install its update or roll back, and print its update status
+/// as it changes until it settles or the node runs another version
+pub async fn change_version_on(peers: &Peers, peer: &str, change: VersionChange<'_>) -> Result<()> {
+    let session = Session::start(peers).await?;
+    let result = async {
+        let node = session.find(peer).await?;
+        let local_node = &session.local_node;
+        let before = match change {
+            VersionChange::Update(version) => {
+                let status = peers.client.check_for_updates(&node, local_node).await?;
+                println!("{} runs version {}", node.name, status.current_version);
+                if !matches!(status.state.as_str(), "update_available" | "update_deferred") {
+                    bail!("Nothing to install on {} ({})", node.name, describe_remote(&status));
+                }
+                peers.client.apply_update(&node, local_node, version).await?;
+                status
+            }
+            VersionChange::Rollback => {
+                let status = peers.client.update_status(&node, local_node).await?;
+                println!("{} runs version {}", node.name, status.current_version);
+                peers.client.rollback(&node, local_node).await?;
+                status
+            }
+        };
+
+        // The node works through the request in the background; it may
+        // restart on the new version, so errors only end the wait at the deadline
+        let deadline = Instant::now() + REMOTE_UPDATE_TIMEOUT;
+        let mut last = describe_remote(&before);
+        while Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            let status = match peers.client.update_status(&node, local_node).await {
+                Ok(status) => status,
+                Err(_) => continue,
+            };
+            if status.current_version != before.current_version {
+                println!("{} now runs version {}", node.name, status.current_version);
+                return Ok(());
+            }
+            let text = describe_remote(&status);
+            if text == last {
+                continue;
+            }
+            println!("{}", text);
+            last = text;
+            if SETTLED_STATES.contains(&status.state.as_str()) {
+                let failed = match change {
+                    VersionChange::Update(_) => status.state != "update_success" && status.state != "restart_pending",
+                    VersionChange::Rollback => status.state != "rolled_back" && status.state != "restart_pending",
+                };
+                if failed {
+                    bail!("{} did not change version ({})", node.name, last);
+                }
+                if status.state == "restart_pending" {
+                    println!("Restart the agent on {} to run version {}", node.name, status.version);
+                }
+                return Ok(());
+            }
+        }
+        bail!("{} has not settled after {} minutes ({})", node.name, REMOTE_UPDATE_TIMEOUT.as_secs() / 60, last)
+    }.await;
+    session.finish(result)
+}
+
+/// Discovery for the commands that look for peers. It advertises under an ID
+/// of its own, so the agent's entry on the other nodes stays as it is.
+fn discovery(identity: &NodeIdentity, udp: (UdpMode, u16)) -> Result<NodeDiscovery> {
+    let (mode, port) = udp;
+    Ok(NodeDiscovery::new(&format!("{}-cli", identity.node_name), None)?.with_udp(mode, port))
+}
+
+/// List the nodes discovered within `wait`
+pub async fn nodes(identity: &NodeIdentity, udp: (UdpMode, u16), wait: Duration) -> Result<()> {
+    let discovery = discovery(identity, udp)?;
+    discovery.start().await?;
+    tokio::time::sleep(wait).await;
+    let mut nodes = discovery.get_discovered_nodes();
+    discovery.shutdown()?;
+
+    if nodes.is_empty() {
+        println!("No nodes found in {}s", wait.as_secs());
+        return Ok(());
+    }
+    nodes.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    println!("{:<24} {:<36} {:<24} {:<12} {:<8} CAPABILITIES", "NAME", "ID", "ADDRESS", "INTERFACE", "VERSION");
+    for node in nodes {
+        let address = format!("{}:{}", node.ip, node.port);
+        let capabilities = node.capabilities.join(",");
+        println!("{:<24} {:<36} {:<24} {:<12} {:<8} {}", node.name, node.id, address, node.interface_type, node.version, capabilities);
+    }
+    Ok(())
+}
+
+/// What the commands that talk to peers work with
+pub struct Peers {
+    pub identity: NodeIdentity,
+    pub udp: (UdpMode, u16),
+    pub client: NodeClient,
+    pub transfers: FileTransferManager,
+    /// Port of the agent's file transfer server, which fetched files go to
+    pub transfer_port: u16,
+}
+
+/// Discovery while a command looks for peers, and this node as it
+/// introduces itself to them
+struct Session {
+    discovery: NodeDiscovery,
+    local_node: NodeInfo,
+}
+
+impl Session {
+    async fn start(peers: &Peers) -> Result<Self> {
+        let discovery = discovery(&peers.identity, peers.udp)?;
+        discovery.start().await?;
+        // Requests name the node's identity, not the short-lived discovery entry
+        let mut local_node = discovery.get_local_node();
+        local_node.id = peers.identity.node_id.clone();
+        local_node.name = peers.identity.node_name.clone();
+        Ok(Self { discovery, local_node })
+    }
+
+    /// The node named `peer`, or with an ID starting with it
+    async fn find(&self, peer: &str) -> Result<NodeInfo> {
+        find_node(&self.discovery, peer, DEFAULT_DISCOVERY_WAIT).await
+            .ok_or_else(|| anyhow!("Node {} not found within {}s", peer, DEFAULT_DISCOVERY_WAIT.as_secs()))
+    }
+
+    /// Stop discovering and pass on the command's result
+    fn finish<T>(self, result: Result<T>) -> Result<T> {
+        self.discovery.shutdown()?;
+        result
+    }
+}
+
+/// Send `path` to `peer`: a node found by name or ID prefix, offered the
+/// transfer over gRPC first, or the host:port of a file transfer server.
+/// With `sync`, only the blocks the node's copy lacks are sent.
+pub async fn send_file(peers: &Peers, peer: &str, path: &Path, sync: bool) -> Result<()> {
+    if let Ok(addr) = peer.parse::<SocketAddr>() {
+        if sync {
+            bail!("--sync needs a node, not the address of a file transfer server");
+        }
+        let transfer_id = peers.transfers.send_file(path, addr).await?;
+        println!("Sent {} to {} (transfer {})", path.display(), addr, transfer_id);
+        return Ok(());
+    }
+
+    let session = Session::start(peers).await?;
+    let result = async {
+        let node = session.find(peer).await?;
+        if sync {
+            let stats = peers.transfers.sync_to_node(&peers.client, &node, &session.local_node, path).await?;
+            println!("Synchronized {} with {} ({}): {} of {} bytes sent", path.display(), node.name, node.id, stats.literal_bytes, stats.file_size);
+        } else {
+            let transfer_id = peers.transfers.send_to_node(&peers.client, &node, &session.local_node, path).await?;
+            println!("Sent {} to {} ({}, transfer {})", path.display(), node.name, node.id, transfer_id);
+        }
+        Ok(())
+    }.await;
+    session.finish(result)
+}
+
+/// Send `path` to every node discovered within `wait`
+pub async fn broadcast(peers: &Peers, path: &Path, wait: Duration) -> Result<()> {
+    let session = Session::start(peers).await?;
+    tokio::time::sleep(wait).await;
+    let targets = session.discovery.get_discovered_nodes();
+    let report = peers.transfers
+        .broadcast_to_nodes(&peers.client, &targets, &session.local_node, path, DEFAULT_FAN_OUT)
+        .await;
+    session.finish(Ok(()))?;
+
+    if targets.is_empty() {
+        bail!("No nodes found in {}s", wait.as_secs());
+    }
+    println!("Sent {} to {} of {} nodes", path.display(), report.delivered.len(), targets.len());
+    for (node_id, reason) in &report.failed {
+        println!("  {}: {}", node_id, reason);
+    }
+    if !report.is_complete() {
+        bail!("{} node(s) did not receive {}", report.failed.len(), path.display());
+    }
+    Ok(())
+}
+
+/// Ask `peer` to send a file or directory it exports to the agent's file
+/// transfer server on this node
+pub async fn fetch(peers: &Peers, peer: &str, path: &str) -> Result<()> {
+    let session = Session::start(peers).await?;
+    let result = async {
+        let node = session.find(peer).await?;
+        let response = peers.client.fetch_file(&node, &session.local_node, path, peers.transfer_port).await?;
+        println!("{} is sending {} to port {}", node.name, response.name, peers.transfer_port);
+        Ok(())
+    }.await;
+    session.finish(result)
+}
+
+/// List the export roots of `peer`, or a directory in them
+pub async fn browse(peers: &Peers, peer: &str, path: Option<&str>) -> Result<()> {
+    let session = Session::start(peers).await?;
+    let result = async {
+        let node = session.find(peer).await?;
+        let listing = peers.client.list_files(&node, &session.local_node, path.unwrap_or_default(), false, false).await?;
+        for entry in &listing.entries {
+            if entry.directory {
+                println!("{:>14}  {}/", "", entry.path);
+            } else {
+                println!("{:>14}  {}", entry.size, entry.path);
+            }
+        }
+        if listing.truncated {
+            println!("... (listing truncated)");
+        }
+        Ok(())
+    }.await;
+    session.finish(result)
+}
+
+/// Per-peer transfer statistics of this node, or of `peer` over gRPC
+pub async fn history(peers: &Peers, peer: Option<&str>) -> Result<()> {
+    let rows: Vec<_> = match peer {
+        None => peers.transfers.history().stats().into_iter()
+            .map(|stats| {
+                let sent = stats.direction == TransferDirection::Send;
+                (sent, stats.peer, stats.transfers, stats.failures, stats.bytes, stats.mean_throughput_mbps, stats.last_throughput_mbps)
+            })
+            .collect(),
+        Some(peer) => {
+            let session = Session::start(peers).await?;
+            let result = async {
+                let node = session.find(peer).await?;
+                Ok(peers.client.transfer_history(&node, &session.local_node, "", 0).await?.stats)
+            }.await;
+            session.finish(result)?.into_iter()
+                .map(|stats| (stats.sent, stats.peer, stats.transfers, stats.failures, stats.bytes, stats.mean_throughput_mbps, stats.last_throughput_mbps))
+                .collect()
+        }
+    };
+
+    if rows.is_empty() {
+        println!("No transfers recorded");
+        return Ok(());
+    }
+    println!("{:<9} {:<36} {:>9} {:>7} {:>16} {:>10} LAST MB/S", "DIRECTION", "PEER", "TRANSFERS", "FAILED", "BYTES", "MEAN MB/S");
+    for (sent, peer, transfers, failures, bytes, mean, last) in rows {
+        let direction = if sent { "send" } else { "receive" };
+        println!("{:<9} {:<36} {:>9} {:>7} {:>16} {:>10.2} {:.2}", direction, peer, transfers, failures, bytes, mean, last);
+    }
+    Ok(())
+}
+
+/// Measure round trips and MB/s per stream count to `peer`, sending
+/// `run_mb` from memory per run
+pub async fn benchmark(peers: &Peers, peer: &str, run_mb: Option<u64>) -> Result<()> {
+    let mut plan = BenchmarkPlan::default();
+    if let Some(run_mb) = run_mb {
+        plan.run_bytes = run_mb * 1024 * 1024;
+    }
+    let session = Session::start(peers).await?;
+    let result = async {
+        let node = session.find(peer).await?;
+        peers.transfers.benchmark_node(&peers.client, &node, &session.local_node, &plan).await
+    }.await;
+    let report = session.finish(result)?;
+
+    if let Some(latency) = &report.latency {
+        println!("Round trip: {:.3} ms min, {:.3} ms median, {:.3} ms max", latency.min_ms, latency.median_ms, latency.max_ms);
+    }
+    for run in &report.runs {
+        println!("{} stream(s): {:.2} MB/s", run.streams, run.throughput_mbps);
+    }
+    Ok(())
+}
+
+/// The node named `peer`, or with an ID starting with it, once discovered
+async fn find_node(discovery: &NodeDiscovery, peer: &str, wait: Duration) -> Option<NodeInfo> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let node = discovery.get_discovered_nodes().into_iter()
+            .find(|node| node.name == peer || node.id.starts_with(peer));
+        if node.is_some() || Instant::now() >= deadline {
+            return node;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Print what every collector reads as JSON, once (pretty) or every
+/// `interval` (a line per snapshot)
+pub async fn metrics(
+    node_id: &str,
+    network_config: NetworkCollectorConfig,
+    system_config: SystemCollectorConfig,
+    once: bool,
+    interval: Duration,
+) -> Result<()> {
+    let mut cpu = CpuCollector::new(node_id.to_string());
+    let mut network = NetworkCollector::new(node_id.to_string(), network_config);
+    let mut storage = StorageCollector::new(node_id.to_string());
+    let mut system = SystemInfoCollector::new(system_config);
+    // HTTP checks and the public IP probe have no result before their first run
+    if !once {
+        network.start_background_probes()?;
+    }
+
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        // Collectors block, e.g. the CPU one while it samples
+        let snapshot = tokio::task::block_in_place(|| json!({
+            "node_id": node_id,
+            "timestamp": chrono::Utc::now(),
+            "cpu": reading(cpu.collect()),
+            "network": reading(network.collect()),
+            "storage": reading(storage.collect()),
+            "system": reading(system.collect()),
+        }));
+        if once {
+            println!("{}", serde_json::to_string_pretty(&snapshot)?);
+            return Ok(());
+        }
+        println!("{}", snapshot);
+    }
+}
+
+/// Print the metrics summary `peer` shares as JSON, once (pretty) or every
+/// `interval` (a line per summary)
+pub async fn metrics_on(peers: &Peers, peer: &str, once: bool, interval: Duration) -> Result<()> {
+    let session = Session::start(peers).await?;
+    let result = async {
+        let node = session.find(peer).await?;
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let summary = peers.client.get_metrics_summary(&node, &session.local_node).await?;
+            let snapshot = json!({ "node_id": node.id, "node_name": node.name, "summary": summary });
+            if once {
+                println!("{}", serde_json::to_string_pretty(&snapshot)?);
+                return Ok(());
+            }
+            println!("{}", snapshot);
+        }
+    }.await;
+    session.finish(result)
+}
+
+/// A collector's result, or its error as `{"error": ...}`
+fn reading<T: Serialize>(result: Result<T>) -> Value {
+    match result.and_then(|metrics| Ok(serde_json::to_value(metrics)?)) {
+        Ok(value) => value,
+        Err(e) => json!({ "error": format!("{:#}", e) }),
+    }
+}
+
+/// Check the configuration file at `path`, or the one the agent would read,
+/// and name the settings the environment overrides
+pub fn validate_config(path: Option<PathBuf>) -> Result<()> {
+    let path = find_config_file(path)?
+        .with_context(|| format!("No configuration file in {}", DEFAULT_CONFIG_DIR))?;
+    let config = AgentConfig::load(&path)?;
+    println!("{} is valid", path.display());
+    let overridden: Vec<_> = config.env_vars().into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| std::env::var_os(name).is_some())
+        .collect();
+    if !overridden.is_empty() {
+        println!("Overridden by the environment: {}", overridden.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(line: &str) -> Result<Command, clap::Error> {
+        Cli::try_parse_from(std::iter::once("node-controller-rust").chain(line.split_whitespace())).map(Cli::command)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_line("").unwrap(), Command::Run);
+        assert_eq!(parse_line("run").unwrap(), Command::Run);
+        assert_eq!(parse_line("update").unwrap(), Command::Update { to: None, force: false, node: None });
+        assert_eq!(parse_line("update --force --to 0.3.0").unwrap(), Command::Update { to: Some("0.3.0".into()), force: true, node: None });
+        assert_eq!(parse_line("update --to=0.2.1").unwrap(), Command::Update { to: Some("0.2.1".into()), force: false, node: None });
+        assert_eq!(
+            parse_line("update --node render-2 --to 0.3.0").unwrap(),
+            Command::Update { to: Some("0.3.0".into()), force: false, node: Some("render-2".into()) },
+        );
+        assert_eq!(parse_line("rollback --node render-2").unwrap(), Command::Rollback { node: Some("render-2".into()) });
+        assert_eq!(
+            parse_line("send-file render-2 /tmp/a.bin").unwrap(),
+            Command::SendFile { peer: "render-2".into(), path: "/tmp/a.bin".into(), sync: false },
+        );
+        assert_eq!(parse_line("send-file --sync render-2 m.bin").unwrap(), Command::SendFile { peer: "render-2".into(), path: "m.bin".into(), sync: true });
+        assert_eq!(parse_line("browse render-2").unwrap(), Command::Browse { peer: "render-2".into(), path: None });
+        assert_eq!(parse_line("benchmark render-2 --run-mb 64").unwrap(), Command::Benchmark { peer: "render-2".into(), run_mb: Some(64) });
+        assert_eq!(parse_line("nodes --wait 10").unwrap(), Command::Nodes { wait: Duration::from_secs(10) });
+        assert_eq!(parse_line("metrics --once").unwrap(), Command::Metrics { once: true, interval: Duration::from_secs(5), node: None });
+        assert_eq!(parse_line("config validate").unwrap(), Command::Config { command: ConfigCommand::Validate { path: None } });
+        assert_eq!(
+            parse_line("config validate ./c.yaml").unwrap(),
+            Command::Config { command: ConfigCommand::Validate { path: Some("./c.yaml".into()) } },
+        );
+        assert_eq!(parse_line("nodes --help").unwrap_err().kind(), clap::error::ErrorKind::DisplayHelp);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        use clap::error::ErrorKind;
+        let error = |line: &str| parse_line(line).unwrap_err();
+        assert_eq!(error("upgrade").kind(), ErrorKind::InvalidSubcommand);
+        assert_eq!(error("update --to").kind(), ErrorKind::InvalidValue);
+        assert_eq!(error("send-file render-2").kind(), ErrorKind::MissingRequiredArgument);
+        assert!(error("nodes --wait soon").to_string().contains("expected whole seconds, not 'soon'"));
+        assert_eq!(error("rollback now").kind(), ErrorKind::UnknownArgument);
+        assert_eq!(error("update --force --node render-2").kind(), ErrorKind::ArgumentConflict);
+        assert_eq!(error("config check").kind(), ErrorKind::InvalidSubcommand);
+    }
+}
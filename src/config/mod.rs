@@ -0,0 +1,910 @@
+// src/config/mod.rs
+//
+// Configuration file
+// The agent reads its settings from a TOML or YAML file, by default
+// /Library/NodeController/config/config.toml (or config.yaml), in sections
+// for the API, updater, collectors, networking, other metric outputs, admin
+// API and logging. Each setting
+// stands for one of the environment variables the agent has always read, and
+// is only applied where that variable is not set, so the environment (and
+// .env) overrides the file. The file is checked as a whole before anything
+// is applied: unknown sections and keys, values of the wrong type and values
+// the agent would not accept fail with the key and what is expected. The
+// settings are applied before the async runtime starts, while the process
+// still has a single thread.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use crate::api::keychain::SecretStore;
+use crate::api::CompressionMode;
+use crate::metrics::HttpCheckConfig;
+use crate::networking::udp_discovery::UdpMode;
+use crate::updater::{ApplyMode, MaintenanceWindow, ServiceManager, UpdateChannel, Version};
+
+/// Directory the configuration file is looked for in
+pub const DEFAULT_CONFIG_DIR: &str = "/Library/NodeController/config";
+/// File names looked for in `DEFAULT_CONFIG_DIR`, in order
+pub const DEFAULT_CONFIG_FILES: [&str; 3] = ["config.toml", "config.yaml", "config.yml"];
+
+/// Every setting of the configuration file; None leaves the variable alone
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentConfig {
+    pub api: ApiConfig,
+    pub updater: UpdaterConfig,
+    pub collectors: CollectorsConfig,
+    pub networking: NetworkingConfig,
+    pub outputs: OutputsConfig,
+    pub admin: AdminConfig,
+    pub logging: LoggingConfig,
+}
+
+/// Monitoring API connection
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    /// MONITORING_API_URL
+    pub url: Option<String>,
+    /// MONITORING_API_KEY, moved into the credential store when there is one
+    pub key: Option<String>,
+    /// API_WS_URL
+    pub ws_url: Option<String>,
+    /// API_GRPC_URL
+    pub grpc_url: Option<String>,
+    /// API_COMPRESSION
+    pub compression: Option<String>,
+    /// API_EVENTS
+    pub events: Option<bool>,
+    /// CREDENTIAL_STORE
+    pub credential_store: Option<String>,
+    /// CONFIG_SYNC_INTERVAL_SECS
+    pub config_sync_interval_secs: Option<u64>,
+    /// COMMAND_POLL_INTERVAL_SECS
+    pub command_poll_interval_secs: Option<u64>,
+    /// METRICS_SPOOL_DIR
+    pub spool_dir: Option<PathBuf>,
+    /// METRICS_SPOOL_MAX_MB
+    pub spool_max_mb: Option<u64>,
+    /// EVENTS_SPOOL_DIR
+    pub events_spool_dir: Option<PathBuf>,
+    /// API_SEQUENCE_FILE
+    pub sequence_file: Option<PathBuf>,
+    /// API_DELIVERY_MARKER
+    pub delivery_marker: Option<PathBuf>,
+    /// ENROLLMENT_TOKEN, exchanged for node credentials on first start
+    pub enrollment_token: Option<String>,
+    /// NODE_CREDENTIALS_FILE
+    pub credentials_file: Option<PathBuf>,
+    /// API_DELTA_MODE
+    pub delta_mode: Option<bool>,
+    /// API_DELTA_FULL_INTERVAL_SECS
+    pub delta_full_interval_secs: Option<u64>,
+    /// API_ENCRYPTION_RECIPIENTS, age recipients
+    pub encryption_recipients: Option<Vec<String>>,
+    /// API_FIELD_ALLOW
+    pub field_allow: Option<Vec<String>>,
+    /// API_FIELD_DENY
+    pub field_deny: Option<Vec<String>>,
+    pub retry: RetrySection,
+    pub circuit: CircuitSection,
+    pub oauth: OAuthSection,
+}
+
+/// `[api.retry]`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetrySection {
+    /// API_RETRY_MAX_ATTEMPTS
+    pub max_attempts: Option<u32>,
+    /// API_RETRY_BASE_MS
+    pub base_ms: Option<u64>,
+    /// API_RETRY_MAX_MS
+    pub max_ms: Option<u64>,
+    /// API_RETRY_JITTER
+    pub jitter: Option<f64>,
+}
+
+/// `[api.circuit]`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CircuitSection {
+    /// API_CIRCUIT_FAILURE_THRESHOLD
+    pub failure_threshold: Option<u32>,
+    /// API_CIRCUIT_COOLDOWN_SECS
+    pub cooldown_secs: Option<u64>,
+    /// API_CIRCUIT_PROBES
+    pub probes: Option<u32>,
+}
+
+/// `[api.oauth]`, client credentials in place of the API key
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OAuthSection {
+    /// OAUTH_TOKEN_URL
+    pub token_url: Option<String>,
+    /// OAUTH_CLIENT_ID
+    pub client_id: Option<String>,
+    /// OAUTH_CLIENT_SECRET, moved into the credential store when there is one
+    pub client_secret: Option<String>,
+    /// OAUTH_SCOPE
+    pub scope: Option<String>,
+    /// OAUTH_AUDIENCE
+    pub audience: Option<String>,
+}
+
+/// Self-updates
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdaterConfig {
+    /// AUTO_UPDATE
+    pub auto_update: Option<bool>,
+    /// UPDATE_CHANNEL
+    pub channel: Option<String>,
+    /// UPDATE_MAX_VERSION
+    pub max_version: Option<String>,
+    /// UPDATE_ALLOW_DOWNGRADE
+    pub allow_downgrade: Option<bool>,
+    /// UPDATE_CHECK_INTERVAL_MINS
+    pub check_interval_mins: Option<u64>,
+    /// UPDATE_REPOSITORY
+    pub repository: Option<String>,
+    /// UPDATE_GITHUB_TOKEN, moved into the credential store when there is one
+    pub github_token: Option<String>,
+    /// UPDATE_MANIFEST_URL, releases from a manifest instead of GitHub
+    pub manifest_url: Option<String>,
+    /// UPDATE_LOCAL_DIR, releases from a directory instead of GitHub
+    pub local_dir: Option<PathBuf>,
+    /// UPDATE_DIR
+    pub dir: Option<PathBuf>,
+    /// UPDATE_SERVICE_MANAGER
+    pub service_manager: Option<String>,
+    /// UPDATE_BINARY_PATH
+    pub binary_path: Option<PathBuf>,
+    /// UPDATE_CONFIG_DIR
+    pub config_dir: Option<PathBuf>,
+    /// UPDATE_RESTORE_SCRIPT
+    pub restore_script: Option<PathBuf>,
+    /// UPDATE_APPLY_MODE
+    pub apply_mode: Option<String>,
+    /// UPDATE_SLOTS_DIR
+    pub slots_dir: Option<PathBuf>,
+    /// UPDATE_WINDOW
+    pub window: Option<String>,
+    /// MAX_BACKUPS
+    pub max_backups: Option<usize>,
+    /// UPDATE_DELTA
+    pub delta: Option<bool>,
+    /// UPDATE_REQUIRE_SIGNATURE
+    pub require_signature: Option<bool>,
+    /// UPDATE_REQUIRE_CHECKSUMS, channels whose releases must carry checksums
+    pub require_checksums: Option<Vec<String>>,
+    /// UPDATE_REQUIRE_CODESIGN
+    pub require_codesign: Option<bool>,
+    /// UPDATE_CODESIGN_TEAM_ID
+    pub codesign_team_id: Option<String>,
+    /// UPDATE_REQUIRE_NOTARIZATION
+    pub require_notarization: Option<bool>,
+    /// UPDATE_DOWNLOAD_LIMIT_KBPS
+    pub download_limit_kbps: Option<u64>,
+    /// UPDATE_DOWNLOAD_BURST_KB
+    pub download_burst_kb: Option<u64>,
+    /// POST_UPDATE_COMMANDS, run in order after an update
+    pub post_update_commands: Option<Vec<String>>,
+    pub health: HealthSection,
+    pub s3: S3Section,
+}
+
+/// `[updater.s3]`, releases from an S3 bucket instead of GitHub
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Section {
+    /// UPDATE_S3_BUCKET
+    pub bucket: Option<String>,
+    /// UPDATE_S3_ENDPOINT
+    pub endpoint: Option<String>,
+    /// UPDATE_S3_REGION
+    pub region: Option<String>,
+    /// UPDATE_S3_PREFIX
+    pub prefix: Option<String>,
+    /// UPDATE_S3_ACCESS_KEY_ID
+    pub access_key_id: Option<String>,
+    /// UPDATE_S3_SECRET_ACCESS_KEY
+    pub secret_access_key: Option<String>,
+}
+
+/// `[updater.health]`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthSection {
+    /// UPDATE_HEALTH_URL
+    pub url: Option<String>,
+    /// UPDATE_HEALTH_GRPC_ADDR
+    pub grpc_addr: Option<String>,
+    /// UPDATE_HEALTH_REQUIRE_DELIVERY
+    pub require_delivery: Option<bool>,
+    /// UPDATE_HEALTH_MIN_UPTIME_SECS
+    pub min_uptime_secs: Option<u64>,
+    /// HEALTH_CHECK_TIMEOUT_SECS
+    pub timeout_secs: Option<u64>,
+}
+
+/// What is collected and how often
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollectorsConfig {
+    /// COLLECT_CPU
+    pub cpu: Option<bool>,
+    /// COLLECT_NETWORK
+    pub network: Option<bool>,
+    /// COLLECT_STORAGE
+    pub storage: Option<bool>,
+    /// CPU_INTERVAL_SECS
+    pub cpu_interval_secs: Option<u64>,
+    /// NETWORK_INTERVAL_SECS
+    pub network_interval_secs: Option<u64>,
+    /// STORAGE_INTERVAL_SECS
+    pub storage_interval_secs: Option<u64>,
+    /// SERVER_UPDATE_INTERVAL_SECS
+    pub server_interval_secs: Option<u64>,
+    /// DNS_PROBE_HOSTS
+    pub dns_probe_hosts: Option<Vec<String>>,
+    /// DNS_PROBE_TIMEOUT_MS
+    pub dns_probe_timeout_ms: Option<u64>,
+    /// HTTP_CHECKS, one `URL [STATUS] [TIMEOUT_MS]` per check
+    pub http_checks: Option<Vec<String>>,
+    /// HTTP_CHECK_INTERVAL_SECS
+    pub http_check_interval_secs: Option<u64>,
+    /// PUBLIC_IP_ENDPOINT
+    pub public_ip_endpoint: Option<String>,
+    /// PUBLIC_IP_INTERVAL_SECS
+    pub public_ip_interval_secs: Option<u64>,
+    /// NTP_SERVER, empty to turn the clock check off
+    pub ntp_server: Option<String>,
+    /// NTP_TIMEOUT_MS
+    pub ntp_timeout_ms: Option<u64>,
+    /// CLOCK_CHECK_INTERVAL_SECS
+    pub clock_check_interval_secs: Option<u64>,
+}
+
+/// Node identity, discovery and the peer services
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkingConfig {
+    /// NODE_NAME
+    pub node_name: Option<String>,
+    /// NODE_LABELS
+    pub labels: Option<BTreeMap<String, String>>,
+    /// NODE_ID_FILE
+    pub id_file: Option<PathBuf>,
+    /// NODE_KEY_FILE
+    pub key_file: Option<PathBuf>,
+    pub discovery: DiscoverySection,
+    pub grpc: GrpcSection,
+    pub file_transfer: FileTransferSection,
+    pub folder_sync: FolderSyncSection,
+}
+
+/// `[networking.discovery]`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiscoverySection {
+    /// NODE_DISCOVERY
+    pub enabled: Option<bool>,
+    /// DISCOVERY_PORT
+    pub port: Option<u16>,
+    /// DISCOVERY_UDP
+    pub udp: Option<String>,
+    /// DISCOVERY_UDP_PORT
+    pub udp_port: Option<u16>,
+    /// DISCOVERY_GOSSIP
+    pub gossip: Option<bool>,
+    /// DISCOVERY_GOSSIP_PORT
+    pub gossip_port: Option<u16>,
+    /// DISCOVERY_RENDEZVOUS
+    pub rendezvous: Option<bool>,
+    /// DISCOVERY_RENDEZVOUS_URL
+    pub rendezvous_url: Option<String>,
+    /// DISCOVERY_RENDEZVOUS_INTERVAL_SECS
+    pub rendezvous_interval_secs: Option<u64>,
+}
+
+/// `[networking.grpc]`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GrpcSection {
+    /// NODE_GRPC_SERVER
+    pub server: Option<bool>,
+    /// NODE_PEER_AUTH
+    pub peer_auth: Option<bool>,
+    /// NODE_JOBS
+    pub jobs: Option<bool>,
+    /// NODE_JOBS_SHELL
+    pub jobs_shell: Option<bool>,
+    /// NODE_JOBS_SHELL_SENDERS
+    pub jobs_shell_senders: Option<Vec<String>>,
+    /// NODE_UPDATE_CONTROLLERS
+    pub update_controllers: Option<Vec<String>>,
+    /// PEER_LATENCY_INTERVAL_SECS
+    pub latency_interval_secs: Option<u64>,
+    /// NODE_GRPC_TLS_CERT
+    pub tls_cert: Option<PathBuf>,
+    /// NODE_GRPC_TLS_KEY
+    pub tls_key: Option<PathBuf>,
+    /// NODE_GRPC_TLS_CA
+    pub tls_ca: Option<PathBuf>,
+}
+
+/// `[networking.file_transfer]`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileTransferSection {
+    /// FILE_TRANSFER_SERVER
+    pub server: Option<bool>,
+    /// FILE_TRANSFER_PORT
+    pub port: Option<u16>,
+    /// FILE_TRANSFER_RECEIVE_DIR
+    pub receive_dir: Option<PathBuf>,
+    /// FILE_TRANSFER_EXPORTS
+    pub exports: Option<Vec<PathBuf>>,
+    /// FILE_TRANSFER_REQUIRE_OFFER
+    pub require_offer: Option<bool>,
+    /// FILE_TRANSFER_SECRET
+    pub secret: Option<String>,
+    /// FILE_TRANSFER_ALLOWED_SENDERS
+    pub allowed_senders: Option<Vec<String>>,
+    /// FILE_TRANSFER_ALLOWED_EXTENSIONS
+    pub allowed_extensions: Option<Vec<String>>,
+    /// FILE_TRANSFER_MAX_FILE_MB
+    pub max_file_mb: Option<u64>,
+    /// FILE_TRANSFER_QUOTA_MB
+    pub quota_mb: Option<u64>,
+    /// FILE_TRANSFER_EVICT_WHEN_FULL
+    pub evict_when_full: Option<bool>,
+    /// FILE_TRANSFER_LIMIT_KBPS
+    pub limit_kbps: Option<u64>,
+    /// FILE_TRANSFER_TOTAL_LIMIT_KBPS
+    pub total_limit_kbps: Option<u64>,
+    /// FILE_TRANSFER_HISTORY_FILE
+    pub history_file: Option<PathBuf>,
+}
+
+/// `[networking.folder_sync]`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FolderSyncSection {
+    /// FOLDER_SYNC_DIR
+    pub dir: Option<PathBuf>,
+    /// FOLDER_SYNC_PEERS
+    pub peers: Option<Vec<String>>,
+    /// FOLDER_SYNC_RESCAN_SECS
+    pub rescan_secs: Option<u64>,
+}
+
+/// Where metrics go besides the monitoring API
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputsConfig {
+    /// PROMETHEUS_LISTEN_ADDR
+    pub prometheus_listen_addr: Option<String>,
+    /// METRICS_FILE
+    pub metrics_file: Option<PathBuf>,
+    /// METRICS_FILE_MAX_MB
+    pub metrics_file_max_mb: Option<u64>,
+    pub mqtt: MqttSection,
+    pub influx: InfluxSection,
+}
+
+/// `[outputs.mqtt]`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttSection {
+    /// MQTT_BROKER
+    pub broker: Option<String>,
+    /// MQTT_CLIENT_ID
+    pub client_id: Option<String>,
+    /// MQTT_USERNAME
+    pub username: Option<String>,
+    /// MQTT_PASSWORD
+    pub password: Option<String>,
+    /// MQTT_TOPIC_TEMPLATE
+    pub topic_template: Option<String>,
+    /// MQTT_QOS
+    pub qos: Option<u8>,
+    /// MQTT_KEEP_ALIVE_SECS
+    pub keep_alive_secs: Option<u64>,
+}
+
+/// `[outputs.influx]`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InfluxSection {
+    /// INFLUX_URL
+    pub url: Option<String>,
+    /// INFLUX_ORG
+    pub org: Option<String>,
+    /// INFLUX_BUCKET
+    pub bucket: Option<String>,
+    /// INFLUX_TOKEN
+    pub token: Option<String>,
+}
+
+/// Local admin API, off unless it has a port or socket
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// ADMIN_API_PORT, on the loopback interface
+    pub port: Option<u16>,
+    /// ADMIN_API_SOCKET
+    pub socket: Option<PathBuf>,
+}
+
+/// Log output
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// RUST_LOG: a level, or an env_logger filter such as `info,mdns_sd=warn`
+    pub level: Option<String>,
+}
+
+/// Environment variables set by a configuration, in the order of the file's sections
+#[derive(Default)]
+struct EnvVars(Vec<(&'static str, String)>);
+
+impl EnvVars {
+    fn set<T: ToString>(&mut self, name: &'static str, value: &Option<T>) {
+        if let Some(value) = value {
+            self.0.push((name, value.to_string()));
+        }
+    }
+
+    fn path(&mut self, name: &'static str, value: &Option<PathBuf>) {
+        self.set(name, &value.as_ref().map(|path| path.display()));
+    }
+
+    fn list<T: ToString>(&mut self, name: &'static str, values: &Option<Vec<T>>, separator: &str) {
+        self.set(name, &values.as_ref().map(|values| {
+            values.iter().map(ToString::to_string).collect::<Vec<_>>().join(separator)
+        }));
+    }
+}
+
+impl AgentConfig {
+    /// The configuration file at `path`, TOML unless it ends in .yaml or .yml
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read configuration file {}", path.display()))?;
+        let yaml = path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml");
+        let config = if yaml { Self::from_yaml(&text) } else { Self::from_toml(&text) };
+        config.with_context(|| format!("Invalid configuration file {}", path.display()))
+    }
+
+    /// Check a TOML file against the configuration's sections and values
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let config: Self = toml::from_str(text).map_err(|e| anyhow!("{}", e.to_string().trim_end()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check a YAML file against the configuration's sections and values
+    pub fn from_yaml(text: &str) -> Result<Self> {
+        let config: Self = serde_yaml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Values serde can't check: URLs, enumerations and schedules
+    fn validate(&self) -> Result<()> {
+        fn check<T>(key: &str, value: &Option<String>, parse: impl Fn(&str) -> Result<T>) -> Result<()> {
+            match value {
+                Some(value) => parse(value).map(|_| ()).with_context(|| format!("{} = \"{}\"", key, value)),
+                None => Ok(()),
+            }
+        }
+        let url = |value: &str| -> Result<()> {
+            if value.starts_with("http://") || value.starts_with("https://") {
+                Ok(())
+            } else {
+                Err(anyhow!("expected an http:// or https:// URL"))
+            }
+        };
+        let ws_url = |value: &str| -> Result<()> {
+            if value.starts_with("ws://") || value.starts_with("wss://") {
+                Ok(())
+            } else {
+                Err(anyhow!("expected a ws:// or wss:// URL"))
+            }
+        };
+
+        let api = &self.api;
+        check("api.url", &api.url, url)?;
+        check("api.ws_url", &api.ws_url, ws_url)?;
+        check("api.grpc_url", &api.grpc_url, url)?;
+        check("api.compression", &api.compression, str::parse::<CompressionMode>)?;
+        check("api.credential_store", &api.credential_store, str::parse::<SecretStore>)?;
+        check("api.oauth.token_url", &api.oauth.token_url, url)?;
+        if api.oauth.token_url.is_some() && (api.oauth.client_id.is_none() || api.oauth.client_secret.is_none()) {
+            bail!("api.oauth: token_url needs client_id and client_secret");
+        }
+        if api.retry.jitter.is_some_and(|jitter| !(0.0..=1.0).contains(&jitter)) {
+            bail!("api.retry.jitter: expected a fraction between 0 and 1");
+        }
+
+        let updater = &self.updater;
+        check("updater.channel", &updater.channel, str::parse::<UpdateChannel>)?;
+        check("updater.max_version", &updater.max_version, str::parse::<Version>)?;
+        check("updater.service_manager", &updater.service_manager, str::parse::<ServiceManager>)?;
+        check("updater.apply_mode", &updater.apply_mode, str::parse::<ApplyMode>)?;
+        check("updater.window", &updater.window, str::parse::<MaintenanceWindow>)?;
+        check("updater.health.url", &updater.health.url, url)?;
+        check("updater.manifest_url", &updater.manifest_url, url)?;
+        check("updater.s3.endpoint", &updater.s3.endpoint, url)?;
+        let sources = [updater.manifest_url.is_some(), updater.local_dir.is_some(), updater.s3.bucket.is_some()];
+        if sources.into_iter().filter(|&source| source).count() > 1 {
+            bail!("updater: manifest_url, local_dir and s3.bucket are alternative release sources, set one");
+        }
+        for channel in updater.require_checksums.iter().flatten() {
+            check("updater.require_checksums", &Some(channel.clone()), str::parse::<UpdateChannel>)?;
+        }
+        if updater.check_interval_mins == Some(0) {
+            bail!("updater.check_interval_mins: expected at least 1 minute");
+        }
+
+        let collectors = &self.collectors;
+        for (key, interval) in [
+            ("collectors.cpu_interval_secs", collectors.cpu_interval_secs),
+            ("collectors.network_interval_secs", collectors.network_interval_secs),
+            ("collectors.storage_interval_secs", collectors.storage_interval_secs),
+            ("collectors.server_interval_secs", collectors.server_interval_secs),
+        ] {
+            if interval == Some(0) {
+                bail!("{}: expected at least 1 second", key);
+            }
+        }
+        for spec in collectors.http_checks.iter().flatten() {
+            check("collectors.http_checks", &Some(spec.clone()), HttpCheckConfig::parse_list)?;
+        }
+        check("collectors.public_ip_endpoint", &collectors.public_ip_endpoint, url)?;
+
+        let networking = &self.networking;
+        for (key, value) in networking.labels.iter().flatten() {
+            if key.is_empty() || key.contains(['=', ',']) || value.contains(['=', ',']) {
+                bail!("networking.labels.{}: labels can't be empty or contain `=` or `,`", key);
+            }
+        }
+        check("networking.discovery.udp", &networking.discovery.udp, |mode| {
+            UdpMode::parse(mode).ok_or_else(|| anyhow!("expected fallback, always or off"))
+        })?;
+        check("networking.discovery.rendezvous_url", &networking.discovery.rendezvous_url, url)?;
+        if networking.folder_sync.dir.is_some() && networking.folder_sync.peers.as_ref().is_none_or(Vec::is_empty) {
+            bail!("networking.folder_sync.peers: a synced folder needs at least one peer");
+        }
+
+        let outputs = &self.outputs;
+        check("outputs.prometheus_listen_addr", &outputs.prometheus_listen_addr, |addr| {
+            addr.parse::<SocketAddr>().map_err(|_| anyhow!("expected an address such as 127.0.0.1:9184"))
+        })?;
+        check("outputs.mqtt.broker", &outputs.mqtt.broker, |value| {
+            match value.split_once("://") {
+                Some(("mqtt" | "mqtts" | "tcp" | "ssl", _)) => Ok(()),
+                _ => Err(anyhow!("expected an mqtt:// or mqtts:// URL")),
+            }
+        })?;
+        if outputs.mqtt.qos.is_some_and(|qos| qos > 1) {
+            bail!("outputs.mqtt.qos: expected 0 or 1");
+        }
+        check("outputs.influx.url", &outputs.influx.url, url)?;
+
+        check("logging.level", &self.logging.level, |level| {
+            let bare = !level.contains(['=', ',', '/']);
+            let levels = ["off", "error", "warn", "info", "debug", "trace"];
+            if bare && !levels.contains(&level.to_lowercase().as_str()) {
+                bail!("expected off, error, warn, info, debug, trace or an env_logger filter");
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// The environment variable of each setting in the file, with its value
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = EnvVars::default();
+
+        let api = &self.api;
+        vars.set("MONITORING_API_URL", &api.url);
+        vars.set("MONITORING_API_KEY", &api.key);
+        vars.set("API_WS_URL", &api.ws_url);
+        vars.set("API_GRPC_URL", &api.grpc_url);
+        vars.set("API_COMPRESSION", &api.compression);
+        vars.set("API_EVENTS", &api.events);
+        vars.set("CREDENTIAL_STORE", &api.credential_store);
+        vars.set("CONFIG_SYNC_INTERVAL_SECS", &api.config_sync_interval_secs);
+        vars.set("COMMAND_POLL_INTERVAL_SECS", &api.command_poll_interval_secs);
+        vars.path("METRICS_SPOOL_DIR", &api.spool_dir);
+        vars.set("METRICS_SPOOL_MAX_MB", &api.spool_max_mb);
+        vars.path("EVENTS_SPOOL_DIR", &api.events_spool_dir);
+        vars.path("API_SEQUENCE_FILE", &api.sequence_file);
+        vars.path("API_DELIVERY_MARKER", &api.delivery_marker);
+        vars.set("ENROLLMENT_TOKEN", &api.enrollment_token);
+        vars.path("NODE_CREDENTIALS_FILE", &api.credentials_file);
+        vars.set("API_DELTA_MODE", &api.delta_mode);
+        vars.set("API_DELTA_FULL_INTERVAL_SECS", &api.delta_full_interval_secs);
+        vars.list("API_ENCRYPTION_RECIPIENTS", &api.encryption_recipients, ",");
+        vars.list("API_FIELD_ALLOW", &api.field_allow, ",");
+        vars.list("API_FIELD_DENY", &api.field_deny, ",");
+        vars.set("API_RETRY_MAX_ATTEMPTS", &api.retry.max_attempts);
+        vars.set("API_RETRY_BASE_MS", &api.retry.base_ms);
+        vars.set("API_RETRY_MAX_MS", &api.retry.max_ms);
+        vars.set("API_RETRY_JITTER", &api.retry.jitter);
+        vars.set("API_CIRCUIT_FAILURE_THRESHOLD", &api.circuit.failure_threshold);
+        vars.set("API_CIRCUIT_COOLDOWN_SECS", &api.circuit.cooldown_secs);
+        vars.set("API_CIRCUIT_PROBES", &api.circuit.probes);
+        vars.set("OAUTH_TOKEN_URL", &api.oauth.token_url);
+        vars.set("OAUTH_CLIENT_ID", &api.oauth.client_id);
+        vars.set("OAUTH_CLIENT_SECRET", &api.oauth.client_secret);
+        vars.set("OAUTH_SCOPE", &api.oauth.scope);
+        vars.set("OAUTH_AUDIENCE", &api.oauth.audience);
+
+        let updater = &self.updater;
+        vars.set("AUTO_UPDATE", &updater.auto_update);
+        vars.set("UPDATE_CHANNEL", &updater.channel);
+        vars.set("UPDATE_MAX_VERSION", &updater.max_version);
+        vars.set("UPDATE_ALLOW_DOWNGRADE", &updater.allow_downgrade);
+        vars.set("UPDATE_CHECK_INTERVAL_MINS", &updater.check_interval_mins);
+        vars.set("UPDATE_REPOSITORY", &updater.repository);
+        vars.set("UPDATE_GITHUB_TOKEN", &updater.github_token);
+        vars.set("UPDATE_MANIFEST_URL", &updater.manifest_url);
+        vars.path("UPDATE_LOCAL_DIR", &updater.local_dir);
+        vars.path("UPDATE_DIR", &updater.dir);
+        vars.set("UPDATE_SERVICE_MANAGER", &updater.service_manager);
+        vars.path("UPDATE_BINARY_PATH", &updater.binary_path);
+        vars.path("UPDATE_CONFIG_DIR", &updater.config_dir);
+        vars.path("UPDATE_RESTORE_SCRIPT", &updater.restore_script);
+        vars.set("UPDATE_APPLY_MODE", &updater.apply_mode);
+        vars.path("UPDATE_SLOTS_DIR", &updater.slots_dir);
+        vars.set("UPDATE_WINDOW", &updater.window);
+        vars.set("MAX_BACKUPS", &updater.max_backups);
+        vars.set("UPDATE_DELTA", &updater.delta);
+        vars.set("UPDATE_REQUIRE_SIGNATURE", &updater.require_signature);
+        vars.list("UPDATE_REQUIRE_CHECKSUMS", &updater.require_checksums, ",");
+        vars.set("UPDATE_REQUIRE_CODESIGN", &updater.require_codesign);
+        vars.set("UPDATE_CODESIGN_TEAM_ID", &updater.codesign_team_id);
+        vars.set("UPDATE_REQUIRE_NOTARIZATION", &updater.require_notarization);
+        vars.set("UPDATE_DOWNLOAD_LIMIT_KBPS", &updater.download_limit_kbps);
+        vars.set("UPDATE_DOWNLOAD_BURST_KB", &updater.download_burst_kb);
+        vars.list("POST_UPDATE_COMMANDS", &updater.post_update_commands, ";");
+        vars.set("UPDATE_HEALTH_URL", &updater.health.url);
+        vars.set("UPDATE_HEALTH_GRPC_ADDR", &updater.health.grpc_addr);
+        vars.set("UPDATE_HEALTH_REQUIRE_DELIVERY", &updater.health.require_delivery);
+        vars.set("UPDATE_HEALTH_MIN_UPTIME_SECS", &updater.health.min_uptime_secs);
+        vars.set("HEALTH_CHECK_TIMEOUT_SECS", &updater.health.timeout_secs);
+        vars.set("UPDATE_S3_BUCKET", &updater.s3.bucket);
+        vars.set("UPDATE_S3_ENDPOINT", &updater.s3.endpoint);
+        vars.set("UPDATE_S3_REGION", &updater.s3.region);
+        vars.set("UPDATE_S3_PREFIX", &updater.s3.prefix);
+        vars.set("UPDATE_S3_ACCESS_KEY_ID", &updater.s3.access_key_id);
+        vars.set("UPDATE_S3_SECRET_ACCESS_KEY", &updater.s3.secret_access_key);
+
+        let collectors = &self.collectors;
+        vars.set("COLLECT_CPU", &collectors.cpu);
+        vars.set("COLLECT_NETWORK", &collectors.network);
+        vars.set("COLLECT_STORAGE", &collectors.storage);
+        vars.set("CPU_INTERVAL_SECS", &collectors.cpu_interval_secs);
+        vars.set("NETWORK_INTERVAL_SECS", &collectors.network_interval_secs);
+        vars.set("STORAGE_INTERVAL_SECS", &collectors.storage_interval_secs);
+        vars.set("SERVER_UPDATE_INTERVAL_SECS", &collectors.server_interval_secs);
+        vars.list("DNS_PROBE_HOSTS", &collectors.dns_probe_hosts, ",");
+        vars.set("DNS_PROBE_TIMEOUT_MS", &collectors.dns_probe_timeout_ms);
+        vars.list("HTTP_CHECKS", &collectors.http_checks, ";");
+        vars.set("HTTP_CHECK_INTERVAL_SECS", &collectors.http_check_interval_secs);
+        vars.set("PUBLIC_IP_ENDPOINT", &collectors.public_ip_endpoint);
+        vars.set("PUBLIC_IP_INTERVAL_SECS", &collectors.public_ip_interval_secs);
+        vars.set("NTP_SERVER", &collectors.ntp_server);
+        vars.set("NTP_TIMEOUT_MS", &collectors.ntp_timeout_ms);
+        vars.set("CLOCK_CHECK_INTERVAL_SECS", &collectors.clock_check_interval_secs);
+
+        let networking = &self.networking;
+        vars.set("NODE_NAME", &networking.node_name);
+        vars.set("NODE_LABELS", &networking.labels.as_ref().map(|labels| {
+            labels.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(",")
+        }));
+        vars.path("NODE_ID_FILE", &networking.id_file);
+        vars.path("NODE_KEY_FILE", &networking.key_file);
+        let discovery = &networking.discovery;
+        vars.set("NODE_DISCOVERY", &discovery.enabled);
+        vars.set("DISCOVERY_PORT", &discovery.port);
+        vars.set("DISCOVERY_UDP", &discovery.udp);
+        vars.set("DISCOVERY_UDP_PORT", &discovery.udp_port);
+        vars.set("DISCOVERY_GOSSIP", &discovery.gossip);
+        vars.set("DISCOVERY_GOSSIP_PORT", &discovery.gossip_port);
+        vars.set("DISCOVERY_RENDEZVOUS", &discovery.rendezvous);
+        vars.set("DISCOVERY_RENDEZVOUS_URL", &discovery.rendezvous_url);
+        vars.set("DISCOVERY_RENDEZVOUS_INTERVAL_SECS", &discovery.rendezvous_interval_secs);
+        let grpc = &networking.grpc;
+        vars.set("NODE_GRPC_SERVER", &grpc.server);
+        vars.set("NODE_PEER_AUTH", &grpc.peer_auth);
+        vars.set("NODE_JOBS", &grpc.jobs);
+        vars.set("NODE_JOBS_SHELL", &grpc.jobs_shell);
+        vars.list("NODE_JOBS_SHELL_SENDERS", &grpc.jobs_shell_senders, ",");
+        vars.list("NODE_UPDATE_CONTROLLERS", &grpc.update_controllers, ",");
+        vars.set("PEER_LATENCY_INTERVAL_SECS", &grpc.latency_interval_secs);
+        vars.path("NODE_GRPC_TLS_CERT", &grpc.tls_cert);
+        vars.path("NODE_GRPC_TLS_KEY", &grpc.tls_key);
+        vars.path("NODE_GRPC_TLS_CA", &grpc.tls_ca);
+        let transfer = &networking.file_transfer;
+        vars.set("FILE_TRANSFER_SERVER", &transfer.server);
+        vars.set("FILE_TRANSFER_PORT", &transfer.port);
+        vars.path("FILE_TRANSFER_RECEIVE_DIR", &transfer.receive_dir);
+        vars.list("FILE_TRANSFER_EXPORTS", &transfer.exports.as_ref().map(|exports| {
+            exports.iter().map(|root| root.display().to_string()).collect()
+        }), ",");
+        vars.set("FILE_TRANSFER_REQUIRE_OFFER", &transfer.require_offer);
+        vars.set("FILE_TRANSFER_SECRET", &transfer.secret);
+        vars.list("FILE_TRANSFER_ALLOWED_SENDERS", &transfer.allowed_senders, ",");
+        vars.list("FILE_TRANSFER_ALLOWED_EXTENSIONS", &transfer.allowed_extensions, ",");
+        vars.set("FILE_TRANSFER_MAX_FILE_MB", &transfer.max_file_mb);
+        vars.set("FILE_TRANSFER_QUOTA_MB", &transfer.quota_mb);
+        vars.set("FILE_TRANSFER_EVICT_WHEN_FULL", &transfer.evict_when_full);
+        vars.set("FILE_TRANSFER_LIMIT_KBPS", &transfer.limit_kbps);
+        vars.set("FILE_TRANSFER_TOTAL_LIMIT_KBPS", &transfer.total_limit_kbps);
+        vars.path("FILE_TRANSFER_HISTORY_FILE", &transfer.history_file);
+        let folder_sync = &networking.folder_sync;
+        vars.path("FOLDER_SYNC_DIR", &folder_sync.dir);
+        vars.list("FOLDER_SYNC_PEERS", &folder_sync.peers, ",");
+        vars.set("FOLDER_SYNC_RESCAN_SECS", &folder_sync.rescan_secs);
+
+        let outputs = &self.outputs;
+        vars.set("PROMETHEUS_LISTEN_ADDR", &outputs.prometheus_listen_addr);
+        vars.path("METRICS_FILE", &outputs.metrics_file);
+        vars.set("METRICS_FILE_MAX_MB", &outputs.metrics_file_max_mb);
+        vars.set("MQTT_BROKER", &outputs.mqtt.broker);
+        vars.set("MQTT_CLIENT_ID", &outputs.mqtt.client_id);
+        vars.set("MQTT_USERNAME", &outputs.mqtt.username);
+        vars.set("MQTT_PASSWORD", &outputs.mqtt.password);
+        vars.set("MQTT_TOPIC_TEMPLATE", &outputs.mqtt.topic_template);
+        vars.set("MQTT_QOS", &outputs.mqtt.qos);
+        vars.set("MQTT_KEEP_ALIVE_SECS", &outputs.mqtt.keep_alive_secs);
+        vars.set("INFLUX_URL", &outputs.influx.url);
+        vars.set("INFLUX_ORG", &outputs.influx.org);
+        vars.set("INFLUX_BUCKET", &outputs.influx.bucket);
+        vars.set("INFLUX_TOKEN", &outputs.influx.token);
+
+        vars.set("ADMIN_API_PORT", &self.admin.port);
+        vars.path("ADMIN_API_SOCKET", &self.admin.socket);
+
+        vars.set("RUST_LOG", &self.logging.level);
+        vars.0
+    }
+
+    /// Set the variables of the file's settings that the environment doesn't;
+    /// returns the names of those it left alone. Call it before any thread
+    /// starts: setting variables races with threads reading them
+    pub fn apply_to_env(&self) -> Vec<&'static str> {
+        let mut overridden = Vec::new();
+        for (name, value) in self.env_vars() {
+            if env::var_os(name).is_some() {
+                overridden.push(name);
+            } else {
+                env::set_var(name, value);
+            }
+        }
+        overridden
+    }
+}
+
+/// The configuration file to read: `explicit` (NODE_CONFIG_FILE), which must
+/// exist, or the first of `DEFAULT_CONFIG_FILES` in `DEFAULT_CONFIG_DIR`
+pub fn find_config_file(explicit: Option<PathBuf>) -> Result<Option<PathBuf>> {
+    match explicit {
+        Some(path) if path.is_file() => Ok(Some(path)),
+        Some(path) => Err(anyhow!("Configuration file {} does not exist", path.display())),
+        None => Ok(DEFAULT_CONFIG_FILES.iter()
+            .map(|name| Path::new(DEFAULT_CONFIG_DIR).join(name))
+            .find(|path| path.is_file())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+[api]
+url = "https://metrics.example.com"
+retry = { max_attempts = 8, jitter = 0.1 }
+
+[updater]
+channel = "beta"
+require_checksums = ["stable", "beta"]
+
+[collectors]
+cpu_interval_secs = 5
+storage = false
+http_checks = ["https://example.com 200", "https://intranet.local"]
+
+[networking]
+labels = { rack = "r1", role = "worker" }
+
+[networking.file_transfer]
+server = true
+exports = ["/srv/share", "/Volumes/Media"]
+allowed_extensions = ["mov", "exr"]
+quota_mb = 2048
+
+[outputs]
+prometheus_listen_addr = "127.0.0.1:9100"
+mqtt = { broker = "mqtts://broker.local:8883", qos = 0 }
+
+[admin]
+port = 9180
+
+[logging]
+level = "debug"
+"#;
+
+    fn env_vars(text: &str) -> Result<BTreeMap<&'static str, String>> {
+        Ok(AgentConfig::from_toml(text)?.env_vars().into_iter().collect())
+    }
+
+    #[test]
+    fn test_settings_become_env_vars() {
+        let vars = env_vars(EXAMPLE).unwrap();
+        assert_eq!(vars["MONITORING_API_URL"], "https://metrics.example.com");
+        assert_eq!(vars["API_RETRY_MAX_ATTEMPTS"], "8");
+        assert_eq!(vars["UPDATE_REQUIRE_CHECKSUMS"], "stable,beta");
+        assert_eq!(vars["CPU_INTERVAL_SECS"], "5");
+        assert_eq!(vars["COLLECT_STORAGE"], "false");
+        assert_eq!(vars["HTTP_CHECKS"], "https://example.com 200;https://intranet.local");
+        assert_eq!(vars["NODE_LABELS"], "rack=r1,role=worker");
+        assert_eq!(vars["FILE_TRANSFER_EXPORTS"], "/srv/share,/Volumes/Media");
+        assert_eq!(vars["FILE_TRANSFER_ALLOWED_EXTENSIONS"], "mov,exr");
+        assert_eq!(vars["FILE_TRANSFER_QUOTA_MB"], "2048");
+        assert_eq!(vars["PROMETHEUS_LISTEN_ADDR"], "127.0.0.1:9100");
+        assert_eq!(vars["MQTT_BROKER"], "mqtts://broker.local:8883");
+        assert_eq!(vars["MQTT_QOS"], "0");
+        assert_eq!(vars["ADMIN_API_PORT"], "9180");
+        assert_eq!(vars["RUST_LOG"], "debug");
+        assert!(!vars.contains_key("UPDATE_DIR"));
+
+        // The example shipped with the agent stays valid
+        let example = AgentConfig::from_toml(include_str!("../../config.example.toml")).unwrap();
+        assert_eq!(example.collectors.server_interval_secs, Some(5));
+
+        // The same settings in YAML
+        let yaml = "api:\n  url: https://metrics.example.com\ncollectors:\n  cpu_interval_secs: 5\n";
+        let config = AgentConfig::from_yaml(yaml).unwrap();
+        assert_eq!(config.collectors.cpu_interval_secs, Some(5));
+
+        assert_eq!(AgentConfig::from_yaml("# nothing set\n").unwrap(), AgentConfig::default());
+
+        // Multi-line strings, and YAML anchors and flow style
+        let config = AgentConfig::from_toml("[api]\nkey = '''\nsecret\n'''\n").unwrap();
+        assert_eq!(config.api.key.as_deref(), Some("secret\n"));
+        let yaml = "networking:\n  grpc: {jobs: true, jobs_shell_senders: &peers [render-2, render-3]}\n  file_transfer:\n    allowed_senders: *peers\n";
+        let vars = AgentConfig::from_yaml(yaml).unwrap().env_vars().into_iter().collect::<BTreeMap<_, _>>();
+        assert_eq!(vars["NODE_JOBS_SHELL_SENDERS"], "render-2,render-3");
+        assert_eq!(vars["FILE_TRANSFER_ALLOWED_SENDERS"], "render-2,render-3");
+    }
+
+    #[test]
+    fn test_errors_name_the_setting() {
+        let error = |text: &str| format!("{:#}", env_vars(text).unwrap_err());
+        let unknown = error("[api]\nurll = \"x\"");
+        assert!(unknown.contains("line 2") && unknown.contains("unknown field `urll`"), "{}", unknown);
+        assert!(error("[collectors]\ncpu_interval_secs = \"5\"").contains("invalid type: string \"5\", expected u64"));
+        assert!(error("[metrics]\n").contains("unknown field `metrics`, expected one of `api`"));
+        assert_eq!(error("[updater]\napply_mode = \"later\""), "updater.apply_mode = \"later\": Unknown update apply mode 'later'");
+        assert!(error("[api]\nurl = \"metrics.example.com\"").contains("expected an http:// or https:// URL"));
+        assert!(error("[logging]\nlevel = \"loud\"").starts_with("logging.level"));
+        assert!(error("[networking.folder_sync]\ndir = \"/srv/sync\"").contains("at least one peer"));
+        assert!(error("[outputs.mqtt]\nqos = 2").contains("expected 0 or 1"));
+        assert!(error("[outputs]\nprometheus_listen_addr = \"9100\"").starts_with("outputs.prometheus_listen_addr"));
+        assert!(error("[api.oauth]\ntoken_url = \"https://auth.example.com/token\"").contains("needs client_id"));
+        assert!(error("[updater]\nlocal_dir = \"/srv/releases\"\ns3 = { bucket = \"releases\" }").contains("set one"));
+    }
+}
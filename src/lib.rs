@@ -1,4 +1,7 @@
 pub mod metrics;
 pub mod api;
 pub mod updater;
-pub mod networking; 
\ No newline at end of file
+pub mod networking;
+pub mod node_identity;
+pub mod proxy;
+pub mod config;
@@ -2,120 +2,207 @@ mod metrics;
 mod api;
 mod updater;
 mod networking;
+mod node_identity;
+mod proxy;
+mod agent;
+mod admin;
+mod cli;
+mod config;
 
-use anyhow::Result;
-use metrics::{CpuCollector, NetworkCollector, StorageCollector, SystemInfoCollector};
-use std::time::{Duration, Instant};
-use std::thread;
-use std::sync::atomic::{AtomicBool, Ordering};
+use anyhow::{Context, Result};
+use admin::{ActiveTransfers, AdminServer, AdminState};
+use clap::Parser;
+use cli::{Cli, Command, ConfigCommand};
+use config::{find_config_file, AgentConfig};
+use agent::{print_separator, spawn_collector, Aggregator, AggregatorRequest, AggregatorSettings, Collected, Schedule};
+use metrics::storage::DirectoryScanner;
+use metrics::{CpuCollector, HttpCheckConfig, NetworkCollector, NetworkCollectorConfig, StorageCollector, SystemCollectorConfig, SystemInfoCollector};
+use std::time::Duration;
 use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot, watch};
 use ctrlc;
 use serde_json::json;
-use api::ApiClient;
-use log::{info, error, warn, debug};
+use api::MetricsSinks;
+use api::commands::{report_result, spawn_command_poller, CommandAction, CommandResult, NodeCommand};
+use api::keychain::SecretStore;
+use api::events::{mark_clean_shutdown, startup_events};
+use api::remote_config::{spawn_config_sync, AlertThresholds, EnabledCollectors};
+use log::{info, warn, debug};
 use std::env;
 use std::str::FromStr;
 use dotenv::dotenv;
-use std::path::PathBuf;
-use updater::{UpdateManager, UpdateConfig, UpdateChannel, Version};
+use std::path::{Path, PathBuf};
+use updater::{ApplyMode, CodesignPolicy, GithubToken, HealthChecks, InstallLayout, RateLimit, S3Config, SourceConfig, ServiceManager, SlotLayout, UpdateManager, UpdateConfig, UpdateChannel, Version, VersionPin};
 use dirs;
-use networking::NodeDiscovery;
+use networking::{capabilities, latency, membership, peer_auth, rendezvous, start_grpc_server_until, udp_discovery, FileExports, FileTransferConfig, FileTransferManager, FolderSync, FolderSyncConfig, GrpcTls, JobRunner, JobScheduler, LatencyTracker, MetricsSummary, NodeClient, NodeCommunicationService, NodeDiscovery, NodeKey, PeerAuth, ReceivePolicy, UdpMode, WakeTable, WhenFull};
+use node_identity::NodeIdentity;
 
 const SERVER_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_CPU_INTERVAL: Duration = Duration::from_secs(2);
+const DEFAULT_NETWORK_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_STORAGE_INTERVAL: Duration = Duration::from_secs(10);
 
-fn print_separator() {
-    println!("\n{}\n", "-".repeat(80));
-}
+fn main() -> Result<()> {
+    let command = Cli::parse().command();
 
-#[tokio::main]
-async fn main() -> Result<()> {
     // Load .env file if it exists
     dotenv().ok();
+    let explicit_config = env::var("NODE_CONFIG_FILE").ok().map(PathBuf::from);
+    // Checked as written, before the environment is mixed in
+    if let Command::Config { command: ConfigCommand::Validate { path } } = command {
+        return cli::validate_config(path.or(explicit_config));
+    }
+
+    // Read the configuration file; the environment and .env override its settings.
+    // They are set here, before the runtime starts any threads
+    let config_file = find_config_file(explicit_config)?;
+    let overridden = match &config_file {
+        Some(path) => AgentConfig::load(path)?.apply_to_env(),
+        None => Vec::new(),
+    };
     
-    // Initialize logging
-    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
-
-    // Get API configuration from environment variables
-    let api_url = env::var("MONITORING_API_URL")
-        .unwrap_or_else(|_| "http://localhost:3000".to_string());
-    let api_key = env::var("MONITORING_API_KEY")
-        .unwrap_or_else(|_| "dev-api-key".to_string());
-
-    info!("Starting node controller with monitoring API at: {}", api_url);
-
-    // Initialize API client
-    let api_client = match ApiClient::new(api_url, api_key) {
-        Ok(client) => {
-            info!("API client initialized successfully");
-            Some(client)
-        },
-        Err(err) => {
-            error!("Failed to initialize API client: {}", err);
-            None
+    // Initialize logging; the other commands print their results and only log warnings
+    let default_filter = if command == Command::Run { "info" } else { "warn" };
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or(default_filter));
+    if let Some(path) = &config_file {
+        info!("Configuration read from {}", path.display());
+        if !overridden.is_empty() {
+            info!("Environment overrides configuration settings: {}", overridden.join(", "));
         }
-    };
+    }
 
-    // Initialize the update manager
+    tokio::runtime::Runtime::new()
+        .context("Failed to start the async runtime")?
+        .block_on(start(command))
+}
+
+/// Run `command` once the configuration is in the environment
+async fn start(command: Command) -> Result<()> {
+    let (identity, identity_path) = load_identity()?;
     let current_version = updater::Version::from_cargo_toml()
         .unwrap_or_else(|_| {
             warn!("Could not determine current version from Cargo.toml, using 0.1.0");
             Version::from_str("0.1.0").unwrap()
         });
-    
+
+    match command {
+        Command::Run => run(identity, identity_path, current_version).await,
+        Command::CheckUpdate { node: Some(node) } => cli::check_update_on(&cli_peers(identity, &identity_path)?, &node).await,
+        Command::CheckUpdate { node: None } => cli::check_update(&cli_update_manager(&identity, current_version, None).await).await,
+        Command::Update { to, node: Some(node), .. } => {
+            let change = cli::VersionChange::Update(to.as_deref());
+            cli::change_version_on(&cli_peers(identity, &identity_path)?, &node, change).await
+        }
+        Command::Update { to, force, node: None } => {
+            let pin = to.as_deref()
+                .map(|to| to.parse().and_then(|to| VersionPin::new(to, &current_version, force)))
+                .transpose()?;
+            cli::update(&cli_update_manager(&identity, current_version, pin).await, to.as_deref()).await
+        }
+        Command::Rollback { node: Some(node) } => {
+            cli::change_version_on(&cli_peers(identity, &identity_path)?, &node, cli::VersionChange::Rollback).await
+        }
+        Command::Rollback { node: None } => cli::rollback(&cli_update_manager(&identity, current_version, None).await).await,
+        Command::SendFile { peer, path, sync } => cli::send_file(&cli_peers(identity, &identity_path)?, &peer, &path, sync).await,
+        Command::Broadcast { path, wait } => cli::broadcast(&cli_peers(identity, &identity_path)?, &path, wait).await,
+        Command::Fetch { peer, path } => cli::fetch(&cli_peers(identity, &identity_path)?, &peer, &path).await,
+        Command::Browse { peer, path } => cli::browse(&cli_peers(identity, &identity_path)?, &peer, path.as_deref()).await,
+        Command::History { peer } => cli::history(&cli_peers(identity, &identity_path)?, peer.as_deref()).await,
+        Command::Benchmark { peer, run_mb } => cli::benchmark(&cli_peers(identity, &identity_path)?, &peer, run_mb).await,
+        Command::Nodes { wait } => cli::nodes(&identity, udp_settings(), wait).await,
+        Command::Metrics { once, interval, node: Some(node) } => {
+            cli::metrics_on(&cli_peers(identity, &identity_path)?, &node, once, interval).await
+        }
+        Command::Metrics { once, interval, node: None } => {
+            cli::metrics(&identity.node_id, network_collector_config(), system_collector_config(), once, interval).await
+        }
+        // Handled before reading the configuration
+        Command::Config { .. } => Ok(()),
+    }
+}
+
+/// The node's identity, gRPC client and file transfer settings, for the
+/// commands that talk to peers
+fn cli_peers(identity: NodeIdentity, identity_path: &Path) -> Result<cli::Peers> {
+    let (_, peer_auth) = load_peer_auth(&identity, identity_path)?;
+    let mut client = NodeClient::new().with_auth(peer_auth.clone());
+    if let Some(tls) = load_grpc_tls(&identity)? {
+        client = client.with_tls(tls);
+    }
+    let state_dir = identity_path.parent().unwrap_or(Path::new("."));
+    let config = file_transfer_config(&identity, state_dir, &peer_auth);
+    Ok(cli::Peers {
+        transfer_port: config.port,
+        transfers: FileTransferManager::new(config),
+        client,
+        udp: udp_settings(),
+        identity,
+    })
+}
+
+/// The node name (hostname or a default) with the persistent node ID and
+/// labels, and the file the ID is kept in
+fn load_identity() -> Result<(NodeIdentity, PathBuf)> {
+    let hostname = env::var("NODE_NAME").ok().unwrap_or_else(|| {
+        hostname::get()
+            .ok()
+            .and_then(|h| h.into_string().ok())
+            .unwrap_or_else(|| "node-controller".to_string())
+    });
+    let identity_path = env::var("NODE_ID_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| node_identity::default_identity_path());
+    let labels = match env::var("NODE_LABELS") {
+        Ok(spec) => node_identity::parse_labels(&spec).context("Invalid NODE_LABELS")?,
+        Err(_) => Default::default(),
+    };
+    let identity = NodeIdentity::load_or_create(&identity_path, hostname)?.with_labels(labels);
+    Ok((identity, identity_path))
+}
+
+/// Update manager for the update commands, which install only what they are told to
+async fn cli_update_manager(identity: &NodeIdentity, current_version: Version, pin: Option<VersionPin>) -> UpdateManager {
+    let mut config = update_config(identity, &current_version).await;
+    config.auto_update = false;
+    if pin.is_some() {
+        config.version_pin = pin;
+    }
+    UpdateManager::new(config, current_version)
+}
+
+/// Run the agent
+async fn run(identity: NodeIdentity, identity_path: PathBuf, current_version: Version) -> Result<()> {
+    info!("Node identifier: {} ({})", identity.node_name, identity.node_id);
+
+    // Set up metrics destinations (monitoring API, MQTT, file, Prometheus)
+    // Commands from the monitoring API arrive over the gRPC stream or by polling
+    let (command_tx, mut command_rx) = mpsc::channel::<NodeCommand>(32);
+    let sinks = MetricsSinks::from_env(&identity, command_tx.clone()).await?;
+
     info!("Current version: {}", current_version);
+
+    // Report a crash of the previous run and updates applied since
+    let state_dir = identity_path.parent().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
+    match startup_events(&state_dir, &current_version.to_string()) {
+        Ok(events) => {
+            for (kind, event) in events {
+                sinks.stream_event(kind, &event);
+            }
+        }
+        Err(e) => warn!("Failed to check how the previous run ended: {}", e),
+    }
     
     // Configure the update manager
-    let update_config = UpdateConfig {
-        check_interval_mins: env::var("UPDATE_CHECK_INTERVAL_MINS")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(60), // Default: check every hour
-        
-        channel: match env::var("UPDATE_CHANNEL").as_deref() {
-            Ok("beta") => UpdateChannel::Beta,
-            Ok("nightly") => UpdateChannel::Nightly,
-            Ok(custom) if !custom.is_empty() => UpdateChannel::Custom(custom.to_string()),
-            _ => UpdateChannel::Stable, // Default to stable
-        },
-        
-        auto_update: env::var("AUTO_UPDATE")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(false), // Default: notify only
-        
-        repository: env::var("UPDATE_REPOSITORY")
-            .unwrap_or_else(|_| "a14a-org/node-controller-rust".to_string()),
-            
-        update_dir: env::var("UPDATE_DIR")
-            .map(PathBuf::from)
-            .unwrap_or_else(|_| {
-                // Use Application Support directory by default
-                dirs::home_dir()
-                    .map(|home| home.join("Library/Application Support/NodeController/updates"))
-                    .unwrap_or_else(|| PathBuf::from("./temp-updates"))
-            }),
-            
-        max_backups: env::var("MAX_BACKUPS")
-            .ok()
-            .and_then(|v| v.parse().ok())
-            .unwrap_or(3), // Default: keep 3 backups
-            
-        post_update_commands: env::var("POST_UPDATE_COMMANDS")
-            .map(|cmds| cmds.split(';').map(ToString::to_string).collect())
-            .unwrap_or_default(),
-            
-        health_check_timeout: Duration::from_secs(
-            env::var("HEALTH_CHECK_TIMEOUT_SECS")
-                .ok()
-                .and_then(|v| v.parse().ok())
-                .unwrap_or(30) // Default: 30 seconds
-        ),
-    };
+    let update_config = update_config(&identity, &current_version).await;
     
-    info!("Update configuration: channel={:?}, auto_update={}, check_interval={}min",
+    info!("Update configuration: source={:?}, channel={:?}, auto_update={}, check_interval={}min, service={:?}, apply_mode={:?}, binary={}",
+          update_config.source,
           update_config.channel,
           update_config.auto_update,
-          update_config.check_interval_mins);
+          update_config.check_interval_mins,
+          update_config.install.service,
+          update_config.install.apply_mode,
+          update_config.install.binary_path.display());
     
     // Create and start the update manager
     let mut update_manager = UpdateManager::new(update_config, current_version);
@@ -124,28 +211,258 @@ async fn main() -> Result<()> {
         Err(e) => warn!("Failed to start update manager: {}", e),
     }
 
-    // Initialize node discovery - get hostname or use a default
-    let hostname = env::var("NODE_NAME").ok().unwrap_or_else(|| {
-        hostname::get()
-            .ok()
-            .and_then(|h| h.into_string().ok())
-            .unwrap_or_else(|| "node-controller".to_string())
-    });
-    
-    info!("Node identifier: {}", hostname);
-    
+    // Pull node configuration (intervals, collectors, thresholds, update channel) from the API
+    let config_sync_interval = env::var("CONFIG_SYNC_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(300);
+    let mut remote_config = match sinks.api_client() {
+        Some(client) if config_sync_interval > 0 => {
+            Some(spawn_config_sync(client, Duration::from_secs(config_sync_interval)))
+        }
+        _ => None,
+    };
+    let command_poll_interval = env::var("COMMAND_POLL_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+    if let Some(client) = sinks.api_client().filter(|_| command_poll_interval > 0) {
+        spawn_command_poller(client, Duration::from_secs(command_poll_interval), command_tx);
+    }
+
+    // Initialize node discovery
+    let node_discovery = env::var("NODE_DISCOVERY")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(true); // Default: find and advertise to peers
     // Allow custom port from environment variable
     let discovery_port = env::var("DISCOVERY_PORT")
         .ok()
         .and_then(|p| p.parse::<u16>().ok());
+    // Serve NodeService (ping, health check and update RPCs) on the announced port
+    let grpc_server = env::var("NODE_GRPC_SERVER")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false); // Default: discovery only
+    
+    // UDP broadcast discovery stands in when mDNS can't start, or runs alongside it
+    let (udp_mode, udp_port) = udp_settings();
+    
+    // Probe discovered nodes so failures are noticed in seconds, not when
+    // their advertisements expire
+    let gossip = env::var("DISCOVERY_GOSSIP")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(true);
+    let gossip_port = env::var("DISCOVERY_GOSSIP_PORT")
+        .ok()
+        .and_then(|p| p.parse::<u16>().ok())
+        .unwrap_or(membership::DEFAULT_GOSSIP_PORT);
+    
+    // Register with the monitoring API to find nodes on other subnets and sites
+    let rendezvous = env::var("DISCOVERY_RENDEZVOUS")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let rendezvous_interval = env::var("DISCOVERY_RENDEZVOUS_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(rendezvous::DEFAULT_INTERVAL);
+    let rendezvous_client = sinks.api_client().filter(|_| rendezvous);
+    if rendezvous && rendezvous_client.is_none() {
+        warn!("DISCOVERY_RENDEZVOUS needs the monitoring API client; rendezvous discovery is off");
+    }
+    
+    // Identity key advertised in discovery and proven to peers
+    let (node_key, peer_auth) = load_peer_auth(&identity, &identity_path)?;
+    info!("Node key fingerprint: {}", node_key.fingerprint());
+    
+    // Serve and call gRPC over TLS
+    let grpc_tls = load_grpc_tls(&identity)?;
+    
+    // Run the shell commands and benchmarks other nodes schedule on this one
+    let run_jobs = env::var("NODE_JOBS")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let shell_jobs = env::var("NODE_JOBS_SHELL")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    if run_jobs && shell_jobs && !peer_auth.is_required() {
+        return Err(anyhow::anyhow!("NODE_JOBS_SHELL needs NODE_PEER_AUTH=true, or any caller could run commands"));
+    }
+    let shell_senders: Vec<String> = env::var("NODE_JOBS_SHELL_SENDERS").unwrap_or_default()
+        .split(',').map(|id| id.trim().to_string()).filter(|id| !id.is_empty()).collect();
+    if run_jobs && shell_jobs && shell_senders.is_empty() {
+        warn!("NODE_JOBS_SHELL is on but NODE_JOBS_SHELL_SENDERS lists no nodes; shell jobs are refused");
+    }
+    let job_runner = Some(JobRunner::new(shell_jobs).with_shell_senders(shell_senders)).filter(|_| run_jobs);
+
+    // Nodes that may drive this node's updates over gRPC, once they proved their key
+    let update_controllers: Vec<String> = env::var("NODE_UPDATE_CONTROLLERS").unwrap_or_default()
+        .split(',').map(|id| id.trim().to_string()).filter(|id| !id.is_empty()).collect();
+    
+    // Latest metrics, summarized for peers that ask over gRPC; free disk space
+    // is that of the filesystem holding the node ID
+    let metrics_summary = watch::Sender::new(MetricsSummary::default());
+    let data_dir = identity_path.parent()
+        .and_then(|dir| dir.canonicalize().ok())
+        .unwrap_or_else(|| PathBuf::from("/"));
+    
+    // Discovered nodes and the scheduler `submit_job` commands run jobs on them with
+    let mut job_scheduling: Option<(Arc<NodeDiscovery>, JobScheduler)> = None;
+    
+    // Round trips and clock offsets of the discovered nodes, pinged every
+    // PEER_LATENCY_INTERVAL_SECS (0 turns it off) and reported by `peer_latency`
+    let latency_interval = env::var("PEER_LATENCY_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(latency::DEFAULT_INTERVAL);
+    let mut peer_latency: Option<LatencyTracker> = None;
+    
+    // Receive files from peers, and send them for jobs, fetches and folder sync
+    let file_transfer_server = env::var("FILE_TRANSFER_SERVER")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false); // Default: no file transfers
+    let mut file_transfers: Option<Arc<FileTransferManager>> = None;
+    // Transfers in progress, for the admin API
+    let active_transfers = ActiveTransfers::default();
+    if file_transfer_server {
+        let mut config = file_transfer_config(&identity, &state_dir, &peer_auth);
+        config.progress_callback = Some(active_transfers.callback());
+        let mut manager = FileTransferManager::new(config);
+        match manager.start_server().await {
+            Ok(addr) => {
+                info!("File transfer server listening on {}, receiving into {}", addr, manager.receive_directory().display());
+                file_transfers = Some(Arc::new(manager));
+            }
+            Err(e) => warn!("Failed to start the file transfer server: {}", e),
+        }
+    }
+    // Directories peers may fetch from over gRPC
+    let exports = env::var("FILE_TRANSFER_EXPORTS").ok().map(|exports| {
+        exports.split(',').map(str::trim).filter(|root| !root.is_empty()).map(PathBuf::from).collect::<Vec<_>>()
+    });
+    // Keep FOLDER_SYNC_DIR in sync on the comma-separated FOLDER_SYNC_PEERS
+    let folder_sync_config = env::var("FOLDER_SYNC_DIR").ok().filter(|dir| !dir.is_empty()).map(|dir| {
+        let peers = env::var("FOLDER_SYNC_PEERS").unwrap_or_default()
+            .split(',').map(|peer| peer.trim().to_string()).filter(|peer| !peer.is_empty()).collect();
+        let mut config = FolderSyncConfig::new(dir, peers);
+        if let Some(secs) = env::var("FOLDER_SYNC_RESCAN_SECS").ok().and_then(|v| v.parse().ok()) {
+            config.rescan_interval = Duration::from_secs(secs);
+        }
+        config
+    });
+    let mut folder_sync: Option<FolderSync> = None;
+    
+    // Stopped on shutdown: discovery unregisters so peers drop this node right
+    // away, and the gRPC server finishes the calls in progress
+    let mut running_discovery: Option<Arc<NodeDiscovery>> = None;
+    let (grpc_shutdown, grpc_shutdown_rx) = watch::channel(false);
+    let mut grpc_task = None;
+    
+    // Hardware addresses of every node seen, kept next to the node ID so
+    // sleeping nodes can be woken by `wake_node` after a restart too
+    let wake_table = WakeTable::open(Some(&identity_path.with_file_name("wake_targets.json")));
+    let mut waking: Option<(Arc<NodeDiscovery>, Arc<NodeClient>)> = None;
     
-    match NodeDiscovery::new(&hostname, discovery_port) {
-        Ok(discovery) => {
+    let discovery = node_discovery.then(|| {
+        NodeDiscovery::with_node_id(&identity.node_id, &identity.node_name, discovery_port)
+            .map(|discovery| discovery.with_key(&node_key))
+            .map(|discovery| discovery.with_labels(identity.labels.clone()))
+            .map(|discovery| discovery.with_udp(udp_mode, udp_port))
+            .map(|discovery| if gossip { discovery.with_membership(gossip_port) } else { discovery })
+            .map(|discovery| match rendezvous_client {
+                Some(client) => discovery.with_rendezvous(client, rendezvous_interval),
+                None => discovery,
+            })
+    });
+    match discovery {
+        Some(Ok(discovery)) => {
             // Start the discovery service
             match discovery.start().await {
                 Ok(_) => {
                     info!("Node discovery service started successfully");
                     
+                    let mut client = NodeClient::new().with_auth(peer_auth.clone());
+                    if let Some(tls) = &grpc_tls {
+                        client = client.with_tls(tls.clone());
+                    }
+                    let client = Arc::new(client);
+                    let discovery = Arc::new(discovery);
+                    if !latency_interval.is_zero() {
+                        let tracker = LatencyTracker::new();
+                        tracker.spawn(discovery.clone(), client.clone(), latency_interval);
+                        peer_latency = Some(tracker);
+                    }
+                    wake_table.spawn(discovery.clone());
+                    waking = Some((discovery.clone(), client.clone()));
+                    running_discovery = Some(discovery.clone());
+                    
+                    // Peers find the transfer server's port in discovery
+                    let mut scheduler = JobScheduler::new(discovery.get_local_node(), client.clone());
+                    if let Some(transfers) = &file_transfers {
+                        if let Some(addr) = transfers.server_address().await {
+                            discovery.set_capability(capabilities::FILE_TRANSFER, Some(&addr.port().to_string()));
+                            discovery.set_capability(capabilities::BENCHMARK, None);
+                            discovery.set_capability(capabilities::FOLDER_SYNC, None);
+                        }
+                        scheduler = scheduler.with_transfers(transfers.clone());
+                        
+                        if let Some(config) = folder_sync_config {
+                            match FolderSync::new(config, transfers.clone(), client.clone(), discovery.clone()) {
+                                Ok(sync) => {
+                                    sync.spawn();
+                                    folder_sync = Some(sync);
+                                }
+                                Err(e) => warn!("Not syncing FOLDER_SYNC_DIR: {}", e),
+                            }
+                        }
+                    } else if folder_sync_config.is_some() {
+                        warn!("FOLDER_SYNC_DIR needs FILE_TRANSFER_SERVER=true; folder sync is off");
+                    }
+                    job_scheduling = Some((discovery.clone(), scheduler));
+                    
+                    if grpc_server {
+                        let local_node = discovery.get_local_node();
+                        let port = local_node.port;
+                        let addr = std::net::SocketAddr::from((std::net::Ipv6Addr::UNSPECIFIED, port));
+                        let mut service = NodeCommunicationService::for_node(&local_node)
+                            .with_updates(update_manager.handle())
+                            .with_update_controllers(update_controllers)
+                            .with_metrics(metrics_summary.subscribe())
+                            .with_wake(wake_table.clone())
+                            .with_auth(peer_auth);
+                        if let Some(jobs) = job_runner {
+                            service = service.with_jobs(jobs);
+                        }
+                        if let Some(transfers) = &file_transfers {
+                            service = service.with_transfers(transfers.clone());
+                            if let Some(roots) = exports {
+                                info!("Exporting for fetches: {:?}", roots);
+                                service = service.with_exports(FileExports::new(roots, transfers.clone()));
+                            }
+                        }
+                        if let Some(tls) = grpc_tls {
+                            service = service.with_tls(tls);
+                        }
+                        let mut shutdown = grpc_shutdown_rx;
+                        let stopped = async move {
+                            let _ = shutdown.wait_for(|stop| *stop).await;
+                        };
+                        match start_grpc_server_until(service, addr, stopped).await {
+                            Ok(task) => {
+                                discovery.set_capability(capabilities::GRPC, Some(&port.to_string()));
+                                grpc_task = Some(task);
+                            }
+                            Err(e) => warn!("Failed to start the node gRPC server: {}", e),
+                        }
+                    }
+                    
                     // Start a background task to periodically log discovered nodes
                     let discovery_clone = discovery;
                     tokio::spawn(async move {
@@ -168,20 +485,24 @@ async fn main() -> Result<()> {
                 Err(e) => warn!("Failed to start node discovery service: {}", e),
             }
         },
-        Err(e) => warn!("Failed to initialize node discovery: {}", e),
+        Some(Err(e)) => warn!("Failed to initialize node discovery: {}", e),
+        None => info!("Node discovery is turned off"),
     }
 
-    let running = Arc::new(AtomicBool::new(true));
-    let r = running.clone();
-
+    let (stop, mut stopped) = watch::channel(false);
     ctrlc::set_handler(move || {
-        r.store(false, Ordering::SeqCst);
+        stop.send_replace(true);
     })?;
 
-    let mut cpu_collector = CpuCollector::new();
-    let mut network_collector = NetworkCollector::new();
-    let mut storage_collector = StorageCollector::new();
-    let mut system_collector = SystemInfoCollector::new();
+    let cpu_collector = CpuCollector::new(identity.node_id.clone());
+    let network_config = network_collector_config();
+    let network_collector = NetworkCollector::new(identity.node_id.clone(), network_config.clone());
+    if let Err(e) = network_collector.start_background_probes() {
+        warn!("Failed to start background network probes: {}", e);
+    }
+    let storage_collector = StorageCollector::new(identity.node_id.clone());
+    let system_config = system_collector_config();
+    let mut system_collector = SystemInfoCollector::new(system_config.clone());
 
     // Collect and display initial system information
     if let Ok(system_info) = system_collector.collect() {
@@ -197,246 +518,645 @@ async fn main() -> Result<()> {
         // TODO: Send initial_payload to server
     }
 
-    // Collection intervals
-    let cpu_interval = Duration::from_secs(2);     // CPU every 2 seconds
-    let network_interval = Duration::from_secs(5);  // Network every 5 seconds
-    let storage_interval = Duration::from_secs(10); // Storage every 10 seconds
-    
-    let mut last_cpu = Instant::now();
-    let mut last_network = Instant::now();
-    let mut last_storage = Instant::now();
-    let mut last_server_update = Instant::now();
+    // Collection intervals and collectors, adjustable through remote configuration
+    let env_interval = |name: &str, default: Duration| {
+        env::var(name).ok().and_then(|v| v.parse().ok()).filter(|secs| *secs > 0).map(Duration::from_secs).unwrap_or(default)
+    };
+    let local_cpu_interval = env_interval("CPU_INTERVAL_SECS", DEFAULT_CPU_INTERVAL);
+    let local_network_interval = env_interval("NETWORK_INTERVAL_SECS", DEFAULT_NETWORK_INTERVAL);
+    let local_storage_interval = env_interval("STORAGE_INTERVAL_SECS", DEFAULT_STORAGE_INTERVAL);
+    let local_server_interval = env_interval("SERVER_UPDATE_INTERVAL_SECS", SERVER_UPDATE_INTERVAL);
+    let env_enabled = |name: &str| env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(true);
+    let collectors = EnabledCollectors {
+        cpu: env_enabled("COLLECT_CPU"),
+        network: env_enabled("COLLECT_NETWORK"),
+        storage: env_enabled("COLLECT_STORAGE"),
+    };
+    let sinks = Arc::new(sinks);
+    let aggregator = Aggregator::new(sinks.clone(), metrics_summary.clone(), data_dir.clone(), system_collector, system_config)
+        .spawn(AggregatorSettings { server_interval: local_server_interval, thresholds: AlertThresholds::default() });
 
-    // Keep track of metrics for server updates
-    let mut pending_cpu_metrics = None;
-    let mut pending_network_metrics = None;
-    let mut pending_storage_metrics = None;
-    let mut pending_system_changes = Vec::new();
+    // Local admin API on ADMIN_API_PORT (loopback only) and/or ADMIN_API_SOCKET
+    let admin_port = env::var("ADMIN_API_PORT")
+        .ok()
+        .and_then(|p| p.parse::<u16>().ok());
+    let admin_socket = env::var("ADMIN_API_SOCKET").ok().filter(|path| !path.is_empty()).map(PathBuf::from);
+    let admin_server = if admin_port.is_some() || admin_socket.is_some() {
+        let state = AdminState {
+            node_id: identity.node_id.clone(),
+            node_name: identity.node_name.clone(),
+            metrics: aggregator.latest.clone(),
+            updates: update_manager.handle(),
+            discovery: running_discovery.clone(),
+            wake: wake_table.clone(),
+            transfers: active_transfers,
+            file_transfers: file_transfers.clone(),
+            api_client: sinks.api_client(),
+        };
+        match AdminServer::bind(admin_port, admin_socket, state) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                warn!("Failed to start the admin API: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let node_id = identity.node_id.clone();
+    let cpu_task = spawn_collector(
+        "CPU",
+        cpu_collector,
+        move || Ok(CpuCollector::new(node_id.clone())),
+        |collector: &mut CpuCollector| collector.collect().map(Collected::Cpu),
+        Schedule { interval: local_cpu_interval, enabled: collectors.cpu },
+        aggregator.collected.clone(),
+    );
+    let node_id = identity.node_id.clone();
+    let network_task = spawn_collector(
+        "Network",
+        network_collector,
+        move || {
+            let collector = NetworkCollector::new(node_id.clone(), network_config.clone());
+            collector.start_background_probes().map(|_| collector)
+        },
+        |collector: &mut NetworkCollector| collector.collect().map(Collected::Network),
+        Schedule { interval: local_network_interval, enabled: collectors.network },
+        aggregator.collected.clone(),
+    );
+    let node_id = identity.node_id.clone();
+    let storage_task = spawn_collector(
+        "Storage",
+        storage_collector,
+        move || Ok(StorageCollector::new(node_id.clone())),
+        |collector: &mut StorageCollector| collector.collect().map(Collected::Storage),
+        Schedule { interval: local_storage_interval, enabled: collectors.storage },
+        aggregator.collected.clone(),
+    );
 
     println!("Starting metrics collection (Press Ctrl+C to stop)...");
     print_separator();
 
-    while running.load(Ordering::SeqCst) {
-        let now = Instant::now();
-        let mut updated_any = false;
-
-        // Log the start of each iteration
-        debug!("Main loop iteration starting");
-
-        // Collect CPU metrics if interval has elapsed
-        if now.duration_since(last_cpu) >= cpu_interval {
-            info!("CPU collection interval reached");
-            match cpu_collector.collect() {
-                Ok(metrics) => {
-                    // Print summary
-                    println!("CPU Usage: {:.1}% (User: {:.1}%, System: {:.1}%)",
-                        metrics.current_load,
-                        metrics.user_load,
-                        metrics.system_load
-                    );
-                    println!("Temperature: {:.1}°C (Max: {:.1}°C)",
-                        metrics.temperature_main,
-                        metrics.temperature_max
-                    );
-                    if let Some(apple_data) = &metrics.apple_silicon_data {
-                        println!("Power: {:.2}W (CPU: {:.2}W, GPU: {:.2}W)",
-                            apple_data.power.package_watts,
-                            apple_data.power.cpu_watts,
-                            apple_data.power.gpu_watts
-                        );
-                    }
-                    pending_cpu_metrics = Some(metrics);
-                    last_cpu = now;
-                    updated_any = true;
-                    info!("CPU metrics collected successfully");
-                },
-                Err(err) => {
-                    error!("Failed to collect CPU metrics: {}", err);
+    // Collection runs in its own tasks; here we follow configuration and run commands
+    let mut restart_check = tokio::time::interval(Duration::from_secs(1));
+    let mut restart_for_update = false;
+    loop {
+        let config_changed = async {
+            match remote_config.as_mut() {
+                Some(rx) => rx.changed().await.is_ok(),
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = stopped.wait_for(|stop| *stop) => break,
+            _ = restart_check.tick() => {
+                // An update installed in place runs once the agent has shut down cleanly
+                if update_manager.restart_pending().await {
+                    info!("Update installed, restarting");
+                    restart_for_update = true;
+                    break;
                 }
             }
-        }
-
-        // Collect Network metrics if interval has elapsed
-        if now.duration_since(last_network) >= network_interval {
-            info!("Network collection interval reached");
-            match network_collector.collect() {
-                Ok(metrics) => {
-                    print_separator();
-                    println!("Network Interfaces:");
-                    for metric in &metrics {
-                        println!("{}", metric);
+            changed = config_changed => {
+                // Apply configuration changes pushed from the monitoring API
+                let Some(rx) = remote_config.as_mut().filter(|_| changed) else {
+                    remote_config = None;
+                    continue;
+                };
+                let Some(config) = rx.borrow_and_update().clone() else {
+                    continue;
+                };
+                let seconds = |secs: Option<u64>, default: Duration| {
+                    secs.filter(|s| *s > 0).map(Duration::from_secs).unwrap_or(default)
+                };
+                let cpu_interval = seconds(config.intervals.cpu_secs, local_cpu_interval);
+                let network_interval = seconds(config.intervals.network_secs, local_network_interval);
+                let storage_interval = seconds(config.intervals.storage_secs, local_storage_interval);
+                let server_interval = seconds(config.intervals.server_secs, local_server_interval);
+                let collectors = config.collectors;
+                cpu_task.set_schedule(Schedule { interval: cpu_interval, enabled: collectors.cpu });
+                network_task.set_schedule(Schedule { interval: network_interval, enabled: collectors.network });
+                storage_task.set_schedule(Schedule { interval: storage_interval, enabled: collectors.storage });
+                aggregator.settings.send_replace(AggregatorSettings { server_interval, thresholds: config.thresholds });
+                if let Some(channel) = config.update_channel.and_then(|c| c.parse::<UpdateChannel>().ok()) {
+                    if let Err(e) = update_manager.set_channel(channel).await {
+                        warn!("Failed to switch update channel: {}", e);
                     }
-                    pending_network_metrics = Some(metrics);
-                    last_network = now;
-                    updated_any = true;
-                    info!("Network metrics collected successfully");
-                },
-                Err(err) => {
-                    error!("Failed to collect Network metrics: {}", err);
                 }
-            }
-        }
-
-        // Collect Storage metrics if interval has elapsed
-        if now.duration_since(last_storage) >= storage_interval {
-            info!("Storage collection interval reached");
-            match storage_collector.collect() {
-                Ok(metrics) => {
-                    print_separator();
-                    println!("Storage:");
-                    println!("\nFilesystems:");
-                    for fs in &metrics.filesystem_metrics {
-                        println!("{}", fs);
+                if let Some(max_version) = config.max_update_version {
+                    let max_version = Some(max_version).filter(|v| !v.is_empty()).map(|v| v.parse()).transpose();
+                    let result = match max_version {
+                        Ok(max_version) => update_manager.pin_version(max_version, config.allow_downgrade).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        warn!("Failed to pin update version: {}", e);
                     }
-                    println!("\nDisk I/O:");
-                    println!("{}", metrics.io_metrics);
-                    pending_storage_metrics = Some(metrics);
-                    last_storage = now;
-                    updated_any = true;
-                    info!("Storage metrics collected successfully");
-                },
-                Err(err) => {
-                    error!("Failed to collect Storage metrics: {}", err);
                 }
+                info!("Applied node configuration {}: intervals cpu={}s network={}s storage={}s server={}s, collectors {:?}",
+                      config.version, cpu_interval.as_secs(), network_interval.as_secs(),
+                      storage_interval.as_secs(), server_interval.as_secs(), collectors);
             }
-        }
-
-        // Check for system changes and prepare server update
-        if now.duration_since(last_server_update) >= SERVER_UPDATE_INTERVAL {
-            // Log the server update check - added for debugging
-            info!("SERVER UPDATE INTERVAL REACHED: {} seconds elapsed since last update", 
-                  now.duration_since(last_server_update).as_secs());
-            
-            // Collect latest system info and check for changes
-            match system_collector.collect() {
-                Ok(system_info) => {
-                    info!("System info collected successfully for server update");
-                    
-                    // If there are changes, add them to pending updates
-                    if !system_info.last_update.changed_fields.is_empty() {
-                        info!("System changes detected: {:?}", system_info.last_update.changed_fields);
-                        pending_system_changes = system_info.last_update.changed_fields.clone();
-                    } else {
-                        info!("No system changes detected");
+            Some(command) = command_rx.recv() => {
+                // Run commands queued by the monitoring API
+                let id = command.id.clone();
+                let action = match command.action() {
+                    Ok(action) => action,
+                    Err(e) => {
+                        report_result(sinks.api_client(), id, CommandResult::failed(&e));
+                        continue;
                     }
-
-                    // Send metrics to the monitoring API if client is available
-                    if let Some(client) = &api_client {
-                        info!("Sending metrics to monitoring API...");
-                        
-                        let send_result = client.send_metrics(
-                            &system_info,
-                            pending_cpu_metrics.as_ref(),
-                            pending_network_metrics.as_ref(),
-                            pending_storage_metrics.as_ref(),
-                        ).await;
-                        
-                        match send_result {
-                            Ok(_) => info!("Successfully sent metrics to monitoring API"),
-                            Err(err) => warn!("Failed to send metrics to monitoring API: {}", err),
-                        }
-                    } else {
-                        // Log if API client is not available - added for debugging
-                        warn!("API client is not available for sending metrics");
-                        
-                        // Prepare the update payload for display
-                        let mut update_payload = json!({
-                            "timestamp": chrono::Utc::now(),
-                            "node_id": system_info.hostname, // Use hostname as node ID
+                };
+                info!("Running command {}: {:?}", id, action);
+                let result = match action {
+                    CommandAction::CheckForUpdates => update_manager.check_for_updates().await.map(|_| None),
+                    CommandAction::RollbackUpdate => update_manager.rollback_to_previous().await.map(|_| None),
+                    CommandAction::ReleasePublished { tag_name } => update_manager.release_published(tag_name).await.map(|_| None),
+                    CommandAction::SetUpdateChannel(channel) => update_manager.set_channel(channel).await.map(|_| None),
+                    CommandAction::PinUpdateVersion { max_version, force } => {
+                        update_manager.pin_version(max_version, force).await.map(|_| None)
+                    }
+                    CommandAction::UpdateHistory { limit } => update_manager.history(limit)
+                        .and_then(|history| Ok(Some(serde_json::to_value(history)?))),
+                    CommandAction::FullSystemInfo => {
+                        // Sent with the next server update
+                        let _ = aggregator.requests.send(AggregatorRequest::FullSystemInfo).await;
+                        Ok(None)
+                    }
+                    CommandAction::ScanDirectory { path, options } => {
+                        // Large trees take a while; report from the blocking pool when done
+                        let client = sinks.api_client();
+                        tokio::task::spawn_blocking(move || {
+                            let result = DirectoryScanner::new(options)
+                                .scan(&path)
+                                .and_then(|report| Ok(Some(serde_json::to_value(report)?)));
+                            report_result(client, id, CommandResult::from_result(result));
                         });
-
-                        // Add CPU metrics if available
-                        if let Some(cpu) = &pending_cpu_metrics {
-                            update_payload["cpu"] = json!(cpu);
+                        continue;
+                    }
+                    CommandAction::RestartCollector(collector) => match collector.as_str() {
+                        "cpu" => cpu_task.restart().await.map(|_| None),
+                        "network" => network_task.restart().await.map(|_| None),
+                        "storage" => storage_task.restart().await.map(|_| None),
+                        collector @ ("system" | "agent") => {
+                            let (reply, outcome) = oneshot::channel();
+                            let _ = aggregator.requests.send(AggregatorRequest::Restart(collector.to_string(), reply)).await;
+                            outcome.await.unwrap_or_else(|_| Err(anyhow::anyhow!("Metrics aggregator stopped"))).map(|_| None)
                         }
-
-                        // Add network metrics if available
-                        if let Some(network) = &pending_network_metrics {
-                            update_payload["network"] = json!(network);
+                        other => Err(anyhow::anyhow!("Unknown collector '{}'", other)),
+                    },
+                    CommandAction::SubmitJob { job, target } => match &job_scheduling {
+                        Some((discovery, scheduler)) => {
+                            // Jobs run as long as their slowest node; report when all finished
+                            let client = sinks.api_client();
+                            let nodes = discovery.get_discovered_nodes();
+                            let scheduler = scheduler.clone();
+                            tokio::spawn(async move {
+                                let result = scheduler.run(job, target, &nodes).await
+                                    .and_then(|report| Ok(Some(serde_json::to_value(report)?)));
+                                report_result(client, id, CommandResult::from_result(result));
+                            });
+                            continue;
                         }
-
-                        // Add storage metrics if available
-                        if let Some(storage) = &pending_storage_metrics {
-                            update_payload["storage"] = json!(storage);
+                        None => Err(anyhow::anyhow!("Jobs need node discovery, which is not running")),
+                    },
+                    CommandAction::PeerLatency => match &peer_latency {
+                        Some(tracker) => serde_json::to_value(tracker.report(&identity.node_id))
+                            .map(Some)
+                            .map_err(Into::into),
+                        None => Err(anyhow::anyhow!("Latency is not measured without node discovery or with PEER_LATENCY_INTERVAL_SECS=0")),
+                    },
+                    CommandAction::WakeNode { node, via: None } => wake_table.wake(&node).await
+                        .and_then(|report| Ok(Some(serde_json::to_value(report)?))),
+                    CommandAction::WakeNode { node, via: Some(via) } => match &waking {
+                        Some((discovery, client)) => {
+                            let relay = discovery.get_discovered_nodes().into_iter()
+                                .find(|peer| peer.id.starts_with(&via) || peer.name == via);
+                            match (relay, wake_table.find(&node)) {
+                                (Some(relay), Some(target)) => client.wake_node(&relay, &discovery.get_local_node(), &target).await
+                                    .and_then(|response| Ok(Some(serde_json::to_value(response.sent_to)?))),
+                                (None, _) => Err(anyhow::anyhow!("Node {} to wake through is not discovered", via)),
+                                (_, None) => Err(anyhow::anyhow!("No hardware address known for node {}", node)),
+                            }
                         }
-
-                        // Add system changes if any
-                        if !pending_system_changes.is_empty() {
-                            let mut system_update = json!({});
-                            for field in &pending_system_changes {
-                                match field.as_str() {
-                                    "peripherals" => { system_update["peripherals"] = json!(system_info.peripherals); }
-                                    "power" => { system_update["power"] = json!(system_info.power); }
-                                    "platform" => { 
-                                        system_update["platform"] = json!({
-                                            "available_memory": system_info.platform.available_memory,
-                                            "load_average": system_info.platform.load_average,
-                                            "uptime_seconds": system_info.platform.uptime_seconds,
-                                        });
-                                    }
-                                    _ => {}
+                        None => Err(anyhow::anyhow!("Waking through another node needs node discovery, which is not running")),
+                    },
+                    CommandAction::BenchmarkLink { node, plan } => match (&waking, &file_transfers) {
+                        (Some((discovery, client)), Some(transfers)) => {
+                            let peer = discovery.get_discovered_nodes().into_iter()
+                                .find(|peer| peer.id.starts_with(&node) || peer.name == node);
+                            match peer {
+                                Some(peer) => {
+                                    // Runs stream for seconds each; report once all are done
+                                    let api_client = sinks.api_client();
+                                    let (client, transfers, local_node) = (client.clone(), transfers.clone(), discovery.get_local_node());
+                                    tokio::spawn(async move {
+                                        let result = transfers.benchmark_node(&client, &peer, &local_node, &plan).await
+                                            .and_then(|report| Ok(Some(serde_json::to_value(report)?)));
+                                        report_result(api_client, id, CommandResult::from_result(result));
+                                    });
+                                    continue;
                                 }
+                                None => Err(anyhow::anyhow!("Node {} is not discovered", node)),
                             }
-                            update_payload["system_changes"] = system_update;
                         }
+                        (None, _) => Err(anyhow::anyhow!("Benchmarks need node discovery, which is not running")),
+                        (_, None) => Err(anyhow::anyhow!("Benchmarks need the file transfer server, set FILE_TRANSFER_SERVER=true")),
+                    },
+                    CommandAction::FolderSyncStatus => match &folder_sync {
+                        Some(sync) => serde_json::to_value(sync.status()).map(Some).map_err(Into::into),
+                        None => Err(anyhow::anyhow!("No folder is synced, set FOLDER_SYNC_DIR and FOLDER_SYNC_PEERS")),
+                    },
+                };
+                report_result(sinks.api_client(), id, CommandResult::from_result(result));
+            }
+        }
+    }
 
-                        println!("\nPrepared server update (API client not available):");
-                        println!("{}", serde_json::to_string_pretty(&update_payload)?);
-                    }
+    // Stop collecting, sending what the aggregator is busy with
+    for task in [&cpu_task, &network_task, &storage_task] {
+        task.stop();
+    }
+    aggregator.stop(Duration::from_secs(5)).await;
+    if let Some(server) = admin_server {
+        server.stop();
+    }
 
-                    // Clear pending updates
-                    pending_cpu_metrics = None;
-                    pending_network_metrics = None;
-                    pending_storage_metrics = None;
-                    pending_system_changes.clear();
-                    last_server_update = now;
-                    updated_any = true;
-                    info!("Server update completed");
-                },
-                Err(err) => {
-                    error!("Failed to collect system info for server update: {}", err);
-                }
-            }
+    // Stop serving peers before the process goes, or restarts into an update,
+    // withdrawing the services first so peers stop sending us work meanwhile
+    if let Some(discovery) = &running_discovery {
+        for service in [capabilities::GRPC, capabilities::FILE_TRANSFER, capabilities::BENCHMARK, capabilities::FOLDER_SYNC] {
+            discovery.remove_capability(service);
         }
+    }
+    let _ = grpc_shutdown.send(true);
+    if let Some(task) = grpc_task {
+        if tokio::time::timeout(Duration::from_secs(5), task).await.is_err() {
+            warn!("gRPC server did not finish its calls in time");
+        }
+    }
+    if let Some(transfers) = &file_transfers {
+        transfers.stop_server().await;
+    }
+    if let Some(discovery) = &running_discovery {
+        if let Err(e) = discovery.shutdown() {
+            warn!("Failed to stop node discovery: {}", e);
+        }
+    }
+
+    mark_clean_shutdown(&state_dir);
+    println!("\nStopping metrics collection...");
+    if restart_for_update {
+        update_manager.restart_into_update()?;
+    }
+    Ok(())
+} 
 
-        // Sleep for a short duration to prevent busy waiting
-        // Use the shortest of the remaining intervals
-        let next_cpu = cpu_interval.saturating_sub(now.duration_since(last_cpu));
-        let next_network = network_interval.saturating_sub(now.duration_since(last_network));
-        let next_storage = storage_interval.saturating_sub(now.duration_since(last_storage));
-        let next_server = SERVER_UPDATE_INTERVAL.saturating_sub(now.duration_since(last_server_update));
+/// Update settings from the environment
+async fn update_config(identity: &NodeIdentity, current_version: &Version) -> UpdateConfig {
+    let update_dir = env::var("UPDATE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            // Use Application Support directory by default
+            dirs::home_dir()
+                .map(|home| home.join("Library/Application Support/NodeController/updates"))
+                .unwrap_or_else(|| PathBuf::from("./temp-updates"))
+        });
+    
+    // launchd or systemd when running under one, paths from the platform or systemd unit
+    let service = match env::var("UPDATE_SERVICE_MANAGER").map(|manager| manager.parse::<ServiceManager>()) {
+        Ok(Ok(service)) => service,
+        Ok(Err(e)) => {
+            warn!("Ignoring UPDATE_SERVICE_MANAGER: {}", e);
+            ServiceManager::detect()
+        }
+        Err(_) => ServiceManager::detect(),
+    };
+    let mut install = InstallLayout::for_service(service, &update_dir).await;
+    if let Ok(path) = env::var("UPDATE_BINARY_PATH") {
+        install.binary_path = PathBuf::from(path);
+    }
+    if let Ok(dir) = env::var("UPDATE_CONFIG_DIR") {
+        install.config_dir = PathBuf::from(dir);
+    }
+    if let Ok(script) = env::var("UPDATE_RESTORE_SCRIPT") {
+        install.restore_script = PathBuf::from(script);
+    }
+    // A/B installs: the service runs <UPDATE_SLOTS_DIR>/current/node-controller
+    if let Ok(dir) = env::var("UPDATE_SLOTS_DIR") {
+        let slots = SlotLayout::new(dir);
+        install.binary_path = slots.binary_path();
+        install.slots = Some(slots);
+    }
+    match env::var("UPDATE_APPLY_MODE").map(|mode| mode.parse::<ApplyMode>()) {
+        Ok(Ok(mode)) => install.apply_mode = mode,
+        Ok(Err(e)) => warn!("Ignoring UPDATE_APPLY_MODE: {}", e),
+        Err(_) => {} // Default: through the service manager, re-exec without one
+    }
+    
+    let download_burst_kib = env::var("UPDATE_DOWNLOAD_BURST_KB").ok().and_then(|v| v.parse().ok());
+    UpdateConfig {
+        check_interval_mins: env::var("UPDATE_CHECK_INTERVAL_MINS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60), // Default: check every hour
         
-        // Add debug logging for timing
-        debug!(
-            "Time to next intervals - CPU: {}s, Network: {}s, Storage: {}s, Server: {}s", 
-            next_cpu.as_secs_f32(), 
-            next_network.as_secs_f32(), 
-            next_storage.as_secs_f32(), 
-            next_server.as_secs_f32()
-        );
+        channel: env::var("UPDATE_CHANNEL")
+            .ok()
+            .and_then(|channel| channel.parse().ok())
+            .unwrap_or(UpdateChannel::Stable), // Default to stable
         
-        let sleep_duration = next_cpu
-            .min(next_network)
-            .min(next_storage)
-            .min(next_server)
-            .min(Duration::from_millis(100));
+        // A pin below the running version needs UPDATE_ALLOW_DOWNGRADE=true
+        version_pin: env::var("UPDATE_MAX_VERSION").ok().filter(|v| !v.is_empty()).and_then(|max_version| {
+            let force = env::var("UPDATE_ALLOW_DOWNGRADE").ok().and_then(|v| v.parse().ok()).unwrap_or(false);
+            match max_version.parse().and_then(|max_version| VersionPin::new(max_version, current_version, force)) {
+                Ok(pin) => Some(pin),
+                Err(e) => {
+                    warn!("Ignoring UPDATE_MAX_VERSION: {}", e);
+                    None
+                }
+            }
+        }), // Default: newest release
         
-        debug!("Sleeping for {}s", sleep_duration.as_secs_f32());
+        auto_update: env::var("AUTO_UPDATE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(false), // Default: notify only
         
-        // Add a safety check to prevent sleeping for 0 duration
-        if sleep_duration.as_nanos() == 0 {
-            thread::sleep(Duration::from_millis(10));
-            warn!("Sleep duration was 0, using 10ms fallback");
+        // A manifest URL or S3 bucket replaces GitHub releases, e.g. for air-gapped sites
+        source: if let Ok(url) = env::var("UPDATE_MANIFEST_URL").map(|url| url.parse()) {
+            match url {
+                Ok(url) => SourceConfig::Manifest(url),
+                Err(e) => {
+                    warn!("Ignoring invalid UPDATE_MANIFEST_URL: {}", e);
+                    SourceConfig::GitHub
+                }
+            }
+        } else if let Ok(dir) = env::var("UPDATE_LOCAL_DIR") {
+            SourceConfig::LocalDir(PathBuf::from(dir))
+        } else if let Ok(bucket) = env::var("UPDATE_S3_BUCKET") {
+            SourceConfig::S3(S3Config {
+                endpoint: env::var("UPDATE_S3_ENDPOINT").ok().and_then(|url| url.parse().ok()),
+                bucket,
+                region: env::var("UPDATE_S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                prefix: env::var("UPDATE_S3_PREFIX").unwrap_or_default(),
+                credentials: env::var("UPDATE_S3_ACCESS_KEY_ID").ok()
+                    .zip(env::var("UPDATE_S3_SECRET_ACCESS_KEY").ok()),
+            })
         } else {
-            thread::sleep(sleep_duration);
-        }
+            SourceConfig::GitHub
+        },
         
-        // Log if no updates were made in this iteration
-        if !updated_any {
-            debug!("No metrics were updated in this iteration");
-        }
+        repository: env::var("UPDATE_REPOSITORY")
+            .unwrap_or_else(|_| "a14a-org/node-controller-rust".to_string()),
+        
+        // Needed for private repositories; also raises the GitHub API rate limit
+        github_token: SecretStore::from_env().resolve("UPDATE_GITHUB_TOKEN").map(GithubToken),
+            
+        update_dir,
+        
+        install,
+            
+        max_backups: env::var("MAX_BACKUPS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(3), // Default: keep 3 backups
+            
+        post_update_commands: env::var("POST_UPDATE_COMMANDS")
+            .map(|cmds| cmds.split(';').map(ToString::to_string).collect())
+            .unwrap_or_default(),
+            
+        health_check_timeout: Duration::from_secs(
+            env::var("HEALTH_CHECK_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30) // Default: 30 seconds
+        ),
+        
+        // Probes of the new version; none by default
+        health_checks: HealthChecks {
+            http_url: env::var("UPDATE_HEALTH_URL").ok().filter(|url| !url.is_empty()),
+            grpc_addr: env::var("UPDATE_HEALTH_GRPC_ADDR").ok().filter(|addr| !addr.is_empty()),
+            delivery_marker: env::var("UPDATE_HEALTH_REQUIRE_DELIVERY")
+                .ok()
+                .and_then(|v| v.parse::<bool>().ok())
+                .unwrap_or(false) // Default: don't wait for a metrics upload
+                .then(api::sink::delivery_marker_path),
+            min_uptime: Duration::from_secs(
+                env::var("UPDATE_HEALTH_MIN_UPTIME_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0) // Default: no soak period
+            ),
+        },
+        
+        require_signature: env::var("UPDATE_REQUIRE_SIGNATURE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(true), // Default: refuse unsigned releases
+        
+        // Comma-separated channels that fail closed without a SHA256SUMS asset or manifest checksum
+        checksum_channels: env::var("UPDATE_REQUIRE_CHECKSUMS")
+            .map(|channels| channels.split(',').map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect())
+            .unwrap_or_else(|_| vec!["stable".to_string()]), // Default: stable only
+        
+        // Developer ID signature (and optionally notarization) of new binaries on macOS
+        codesign: env::var("UPDATE_REQUIRE_CODESIGN")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(cfg!(target_os = "macos")) // Default: required on macOS
+            .then(|| CodesignPolicy {
+                team_id: env::var("UPDATE_CODESIGN_TEAM_ID").ok().filter(|id| !id.is_empty()),
+                require_notarization: env::var("UPDATE_REQUIRE_NOTARIZATION")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(false), // Default: signature only
+            }),
+        
+        delta_updates: env::var("UPDATE_DELTA")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(true), // Default: prefer binary patches
+        
+        download_limit: RateLimit::from_kib(
+            env::var("UPDATE_DOWNLOAD_LIMIT_KBPS").ok().and_then(|v| v.parse().ok()).unwrap_or(0),
+            download_burst_kib,
+        ), // Default: unlimited
+        
+        // UPDATE_DOWNLOAD_LIMIT_KBPS_<CHANNEL>, e.g. _NIGHTLY=0 for unlimited nightly downloads
+        channel_download_limits: env::vars()
+            .filter_map(|(name, value)| {
+                let channel = name.strip_prefix("UPDATE_DOWNLOAD_LIMIT_KBPS_")?.to_lowercase();
+                let kib = value.parse().ok()?;
+                Some((channel, RateLimit::from_kib(kib, download_burst_kib)))
+            })
+            .collect(),
+        
+        node_id: Some(identity.node_id.clone()),
+        
+        maintenance_window: env::var("UPDATE_WINDOW").ok().and_then(|window| match window.parse() {
+            Ok(window) => Some(window),
+            Err(e) => {
+                warn!("Ignoring UPDATE_WINDOW: {}", e);
+                None
+            }
+        }), // Default: install any time
     }
+}
 
-    println!("\nStopping metrics collection...");
-    Ok(())
-} 
\ No newline at end of file
+/// UDP broadcast discovery: when it runs (standing in when mDNS can't start,
+/// or alongside it) and on which port
+fn udp_settings() -> (UdpMode, u16) {
+    let udp_mode = env::var("DISCOVERY_UDP")
+        .ok()
+        .and_then(|v| UdpMode::parse(&v))
+        .unwrap_or_default();
+    let udp_port = env::var("DISCOVERY_UDP_PORT")
+        .ok()
+        .and_then(|p| p.parse::<u16>().ok())
+        .unwrap_or(udp_discovery::DEFAULT_UDP_PORT);
+    (udp_mode, udp_port)
+}
+
+/// The identity key advertised in discovery and proven to peers, and the
+/// authentication that proves it; NODE_PEER_AUTH refuses peers that don't
+/// prove theirs
+fn load_peer_auth(identity: &NodeIdentity, identity_path: &Path) -> Result<(NodeKey, PeerAuth)> {
+    let key_path = env::var("NODE_KEY_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| peer_auth::default_key_path(identity_path));
+    let node_key = NodeKey::load_or_create(&key_path)?;
+    let peer_auth_required = env::var("NODE_PEER_AUTH")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let peer_auth = PeerAuth::new(&identity.node_id, node_key.clone(), peer_auth_required)
+        .with_known_peers(&identity_path.with_file_name("known_peers.json"));
+    Ok((node_key, peer_auth))
+}
+
+/// TLS for serving and calling gRPC, with a certificate naming the node ID and
+/// trusting a cluster CA or the nodes' own certificates; None without NODE_GRPC_TLS_CERT
+fn load_grpc_tls(identity: &NodeIdentity) -> Result<Option<GrpcTls>> {
+    let Ok(cert) = env::var("NODE_GRPC_TLS_CERT") else {
+        return Ok(None);
+    };
+    let key = env::var("NODE_GRPC_TLS_KEY").context("NODE_GRPC_TLS_CERT needs NODE_GRPC_TLS_KEY")?;
+    let trusted = env::var("NODE_GRPC_TLS_CA").context("NODE_GRPC_TLS_CERT needs NODE_GRPC_TLS_CA")?;
+    let tls = GrpcTls::load(&identity.node_id, Path::new(&cert), Path::new(&key), Path::new(&trusted))
+        .context("Invalid gRPC TLS configuration")?;
+    info!("Node gRPC calls use TLS with certificate {}", cert);
+    Ok(Some(tls))
+}
+
+/// File transfer settings from the environment, receiving into the state directory by default
+fn file_transfer_config(identity: &NodeIdentity, state_dir: &Path, peer_auth: &PeerAuth) -> FileTransferConfig {
+    let env_mib = |name: &str| env::var(name).ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|mib| *mib > 0)
+        .map(|mib| mib * 1024 * 1024);
+    let env_kib = |name: &str| env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(0);
+    let env_list = |name: &str| env::var(name).ok().map(|list| {
+        list.split(',').map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect::<Vec<_>>()
+    });
+    FileTransferConfig {
+        port: env::var("FILE_TRANSFER_PORT")
+            .ok()
+            .and_then(|p| p.parse::<u16>().ok())
+            .unwrap_or(FileTransferConfig::default().port),
+        receive_dir: env::var("FILE_TRANSFER_RECEIVE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| state_dir.join("received_files")),
+        node_id: Some(identity.node_id.clone()),
+        shared_secret: env::var("FILE_TRANSFER_SECRET").ok().filter(|s| !s.is_empty()),
+        allowed_senders: env_list("FILE_TRANSFER_ALLOWED_SENDERS"),
+        peer_auth: Some(peer_auth.clone()),
+        require_offer: env::var("FILE_TRANSFER_REQUIRE_OFFER")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(false),
+        receive_policy: ReceivePolicy {
+            max_file_size: env_mib("FILE_TRANSFER_MAX_FILE_MB"),
+            allowed_extensions: env_list("FILE_TRANSFER_ALLOWED_EXTENSIONS"),
+            quota: env_mib("FILE_TRANSFER_QUOTA_MB"),
+            when_full: match env::var("FILE_TRANSFER_EVICT_WHEN_FULL").ok().and_then(|v| v.parse().ok()) {
+                Some(true) => WhenFull::EvictOldest,
+                _ => WhenFull::Reject,
+            },
+            accept_callback: None,
+        },
+        transfer_limit: RateLimit::from_kib(env_kib("FILE_TRANSFER_LIMIT_KBPS"), None),
+        total_limit: RateLimit::from_kib(env_kib("FILE_TRANSFER_TOTAL_LIMIT_KBPS"), None),
+        history_file: env::var("FILE_TRANSFER_HISTORY_FILE").ok().filter(|s| !s.is_empty()).map(PathBuf::from),
+        ..FileTransferConfig::default()
+    }
+}
+
+/// The network collector's probes
+fn network_collector_config() -> NetworkCollectorConfig {
+    let network_defaults = NetworkCollectorConfig::default();
+    NetworkCollectorConfig {
+        dns_probe_hosts: env::var("DNS_PROBE_HOSTS")
+            .map(|hosts| hosts.split(',')
+                .map(|h| h.trim().to_string())
+                .filter(|h| !h.is_empty())
+                .collect())
+            .unwrap_or(network_defaults.dns_probe_hosts),
+
+        dns_probe_timeout: env::var("DNS_PROBE_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(network_defaults.dns_probe_timeout),
+
+        http_checks: match env::var("HTTP_CHECKS") {
+            Ok(spec) => HttpCheckConfig::parse_list(&spec).unwrap_or_else(|e| {
+                warn!("Ignoring invalid HTTP_CHECKS: {}", e);
+                Vec::new()
+            }),
+            Err(_) => network_defaults.http_checks,
+        },
+
+        http_check_interval: env::var("HTTP_CHECK_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(network_defaults.http_check_interval),
+
+        public_ip_endpoint: env::var("PUBLIC_IP_ENDPOINT")
+            .ok()
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .or(network_defaults.public_ip_endpoint),
+
+        public_ip_interval: env::var("PUBLIC_IP_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(network_defaults.public_ip_interval),
+    }
+}
+
+/// The system collector's clock sync check
+fn system_collector_config() -> SystemCollectorConfig {
+    let system_defaults = SystemCollectorConfig::default();
+    SystemCollectorConfig {
+        ntp_server: match env::var("NTP_SERVER") {
+            Ok(server) if server.trim().is_empty() => None,
+            Ok(server) => Some(server.trim().to_string()),
+            Err(_) => system_defaults.ntp_server,
+        },
+
+        ntp_timeout: env::var("NTP_TIMEOUT_MS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(system_defaults.ntp_timeout),
+
+        clock_check_interval: env::var("CLOCK_CHECK_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(system_defaults.clock_check_interval),
+    }
+}
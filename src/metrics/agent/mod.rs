@@ -0,0 +1,4 @@
+pub mod types;
+mod collector;
+
+pub use collector::AgentCollector;
@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+
+/// Resource usage of the node-controller process itself
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AgentMetrics {
+    pub pid: u32,
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub rss_bytes: u64,
+    pub virtual_memory_bytes: u64,
+    /// CPU usage since the previous collection, 100% = one full core
+    pub cpu_percent: f32,
+    pub open_fds: Option<usize>,
+    /// Tasks alive on the tokio runtime, `None` when collected outside of it
+    pub tokio_tasks: Option<usize>,
+    pub tokio_workers: Option<usize>,
+    /// Items queued internally and not yet delivered (pending events, buffers)
+    pub backlogs: Vec<QueueBacklog>,
+    pub collected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct QueueBacklog {
+    pub name: String,
+    pub depth: usize,
+}
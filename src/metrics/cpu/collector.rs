@@ -3,24 +3,26 @@ use chrono::Utc;
 use sysinfo::System;
 use std::collections::HashMap;
 use std::process::Command;
-use uuid::Uuid;
 
+use super::scheduler::SchedulerSampler;
 use super::types::{CpuMetrics, CoreMetrics, AppleSiliconData, PowerMetrics, ThermalMetrics};
 
 pub struct CpuCollector {
     sys: System,
     node_id: String,
+    scheduler: SchedulerSampler,
 }
 
 impl CpuCollector {
-    pub fn new() -> Self {
+    pub fn new(node_id: String) -> Self {
         let mut sys = System::new();
         sys.refresh_cpu(); // Initial refresh to get baseline CPU metrics
         std::thread::sleep(std::time::Duration::from_millis(100)); // Wait for initial sample
         
         Self {
             sys,
-            node_id: Uuid::new_v4().to_string(),
+            node_id,
+            scheduler: SchedulerSampler::new(),
         }
     }
 
@@ -73,6 +75,7 @@ impl CpuCollector {
             temperature_max: temp_max,
             core_metrics,
             apple_silicon_data,
+            scheduler: self.scheduler.sample(),
         })
     }
 
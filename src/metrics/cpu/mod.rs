@@ -1,4 +1,5 @@
 pub mod types;
 mod collector;
+mod scheduler;
 
 pub use collector::CpuCollector; 
\ No newline at end of file
@@ -0,0 +1,134 @@
+use std::fs;
+use std::process::Command;
+use std::time::Instant;
+
+use super::types::SchedulerStats;
+
+/// Cumulative kernel counters from `/proc/stat`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchedulerCounters {
+    pub context_switches: u64,
+    pub interrupts: u64,
+    pub procs_running: u32,
+    pub procs_blocked: u32,
+}
+
+/// Samples run queue depth and turns cumulative context switch / interrupt
+/// counters into per-second rates between calls
+pub struct SchedulerSampler {
+    last: Option<(Instant, SchedulerCounters)>,
+}
+
+impl SchedulerSampler {
+    pub fn new() -> Self {
+        Self { last: None }
+    }
+
+    pub fn sample(&mut self) -> Option<SchedulerStats> {
+        match fs::read_to_string("/proc/stat") {
+            Ok(stat) => {
+                let counters = parse_proc_stat(&stat)?;
+                let now = Instant::now();
+                let stats = rates(self.last, now, counters);
+                self.last = Some((now, counters));
+                Some(stats)
+            }
+            // macOS exposes no system-wide switch/interrupt counters without private
+            // APIs, so only the run queue is reported there
+            Err(_) => {
+                let output = Command::new("ps").args(["-A", "-o", "state="]).output().ok()?;
+                let (running, blocked) = count_process_states(&String::from_utf8_lossy(&output.stdout));
+                Some(SchedulerStats {
+                    run_queue: running,
+                    blocked: Some(blocked),
+                    context_switches_per_sec: None,
+                    interrupts_per_sec: None,
+                })
+            }
+        }
+    }
+}
+
+fn rates(last: Option<(Instant, SchedulerCounters)>, now: Instant, counters: SchedulerCounters) -> SchedulerStats {
+    let per_sec = |current: u64, previous: u64, elapsed: f64| {
+        (elapsed > 0.0).then(|| current.saturating_sub(previous) as f64 / elapsed)
+    };
+    let (context_switches_per_sec, interrupts_per_sec) = match last {
+        Some((at, previous)) => {
+            let elapsed = now.duration_since(at).as_secs_f64();
+            (
+                per_sec(counters.context_switches, previous.context_switches, elapsed),
+                per_sec(counters.interrupts, previous.interrupts, elapsed),
+            )
+        }
+        None => (None, None),
+    };
+
+    SchedulerStats {
+        // procs_running includes the reading process itself
+        run_queue: counters.procs_running.saturating_sub(1),
+        blocked: Some(counters.procs_blocked),
+        context_switches_per_sec,
+        interrupts_per_sec,
+    }
+}
+
+fn parse_proc_stat(stat: &str) -> Option<SchedulerCounters> {
+    let field = |name: &str| {
+        stat.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+
+    Some(SchedulerCounters {
+        context_switches: field("ctxt ")?,
+        // The first number on the intr line is the total across all sources
+        interrupts: field("intr ")?,
+        procs_running: field("procs_running ").unwrap_or(0) as u32,
+        procs_blocked: field("procs_blocked ").unwrap_or(0) as u32,
+    })
+}
+
+/// Count runnable (`R`) and uninterruptible (`D`/`U`) processes from `ps -o state=`
+fn count_process_states(output: &str) -> (u32, u32) {
+    output.lines()
+        .filter_map(|line| line.trim().chars().next())
+        .fold((0, 0), |(running, blocked), state| match state {
+            'R' => (running + 1, blocked),
+            'D' | 'U' => (running, blocked + 1),
+            _ => (running, blocked),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_scheduler_rates() {
+        let stat = "cpu  10 0 10 100 0 0 0 0 0 0
+intr 5000 20 0 9
+ctxt 120000
+btime 1714550000
+procs_running 4
+procs_blocked 1
+";
+        let first = parse_proc_stat(stat).unwrap();
+        assert_eq!(first.context_switches, 120000);
+        assert_eq!(first.interrupts, 5000);
+
+        let start = Instant::now();
+        let initial = rates(None, start, first);
+        assert_eq!(initial.run_queue, 3);
+        assert_eq!(initial.context_switches_per_sec, None);
+
+        let second = SchedulerCounters { context_switches: 124000, interrupts: 7000, ..first };
+        let stats = rates(Some((start, first)), start + Duration::from_secs(2), second);
+        assert_eq!(stats.context_switches_per_sec, Some(2000.0));
+        assert_eq!(stats.interrupts_per_sec, Some(1000.0));
+
+        assert_eq!(count_process_states("Ss\nR+\nR\nU\nS\n"), (2, 1));
+    }
+}
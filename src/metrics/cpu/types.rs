@@ -20,6 +20,19 @@ pub struct CpuMetrics {
     pub core_metrics: HashMap<String, CoreMetrics>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub apple_silicon_data: Option<AppleSiliconData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduler: Option<SchedulerStats>,
+}
+
+/// Scheduler saturation indicators; rates are `None` until two samples have been taken
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SchedulerStats {
+    /// Runnable processes waiting for or using a CPU
+    pub run_queue: u32,
+    /// Processes in uninterruptible (usually I/O) wait
+    pub blocked: Option<u32>,
+    pub context_switches_per_sec: Option<f64>,
+    pub interrupts_per_sec: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
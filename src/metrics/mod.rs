@@ -1,9 +1,11 @@
+pub mod agent;
 pub mod cpu;
 pub mod network;
 pub mod storage;
 pub mod system;
 
+pub use agent::AgentCollector;
 pub use cpu::CpuCollector;
-pub use network::NetworkCollector;
+pub use network::{HttpCheckConfig, NetworkCollector, NetworkCollectorConfig};
 pub use storage::StorageCollector;
-pub use system::SystemInfoCollector; 
\ No newline at end of file
+pub use system::{SystemCollectorConfig, SystemInfoCollector}; 
\ No newline at end of file
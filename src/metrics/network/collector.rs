@@ -1,48 +1,136 @@
 use anyhow::Result;
 use chrono::Utc;
 use std::process::Command;
-use uuid::Uuid;
 use std::collections::HashMap;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use super::types::{NetworkMetrics, InterfaceInfo, WifiInfo};
+use super::dns::DnsProbe;
+use super::http_check::{HttpCheckConfig, HttpChecker};
+use super::public_ip::PublicIpProbe;
+use super::routes::RouteMonitor;
+use super::vpn;
+use super::types::{NetworkMetrics, NetworkSnapshot, InterfaceInfo, LinkEvent, LinkEventKind, WifiInfo};
 
 const RATE_SMOOTHING_FACTOR: f64 = 0.3; // Lower = more smoothing
 
+/// Configuration for the network collector and its probes
+#[derive(Debug, Clone)]
+pub struct NetworkCollectorConfig {
+    /// Hostnames resolved on every collection to measure DNS health
+    pub dns_probe_hosts: Vec<String>,
+
+    /// Maximum time to wait for all DNS lookups to finish
+    pub dns_probe_timeout: Duration,
+
+    /// Synthetic HTTP(S) endpoint checks
+    pub http_checks: Vec<HttpCheckConfig>,
+
+    /// How often the HTTP checks run
+    pub http_check_interval: Duration,
+
+    /// Endpoint returning the node's public IP; the probe is disabled when unset
+    pub public_ip_endpoint: Option<String>,
+
+    /// How often the public IP is looked up
+    pub public_ip_interval: Duration,
+}
+
+impl Default for NetworkCollectorConfig {
+    fn default() -> Self {
+        Self {
+            dns_probe_hosts: vec!["apple.com".to_string(), "github.com".to_string()],
+            dns_probe_timeout: Duration::from_secs(2),
+            http_checks: Vec::new(),
+            http_check_interval: Duration::from_secs(60),
+            public_ip_endpoint: None,
+            public_ip_interval: Duration::from_secs(600), // 10 minutes
+        }
+    }
+}
+
 pub struct NetworkCollector {
     node_id: String,
     last_bytes: HashMap<String, (u64, u64, Instant)>, // (rx_bytes, tx_bytes, timestamp)
     smoothed_rates: HashMap<String, (f64, f64)>, // (rx_rate, tx_rate)
+    last_status: Option<HashMap<String, (String, String)>>, // (interface_type, status)
+    dns_probe: DnsProbe,
+    http_checker: HttpChecker,
+    route_monitor: RouteMonitor,
+    public_ip_probe: PublicIpProbe,
 }
 
 impl NetworkCollector {
-    pub fn new() -> Self {
+    pub fn new(node_id: String, config: NetworkCollectorConfig) -> Self {
         Self {
-            node_id: Uuid::new_v4().to_string(),
+            node_id,
             last_bytes: HashMap::new(),
             smoothed_rates: HashMap::new(),
+            last_status: None,
+            dns_probe: DnsProbe::new(config.dns_probe_hosts, config.dns_probe_timeout),
+            http_checker: HttpChecker::new(config.http_checks, config.http_check_interval),
+            route_monitor: RouteMonitor::new(),
+            public_ip_probe: PublicIpProbe::new(config.public_ip_endpoint, config.public_ip_interval),
         }
     }
 
-    pub fn collect(&mut self) -> Result<Vec<NetworkMetrics>> {
+    /// Start the probes that run on their own schedule (must be called within a tokio runtime)
+    pub fn start_background_probes(&self) -> Result<()> {
+        self.http_checker.start()?;
+        self.public_ip_probe.start()
+    }
+
+    pub fn collect(&mut self) -> Result<NetworkSnapshot> {
+        // Get network usage from netstat
+        let usage = self.get_network_usage()?;
+        let (interfaces, link_events) = self.collect_interfaces(&usage)?;
+        let dns = self.dns_probe.probe();
+        let http_checks = self.http_checker.latest_results();
+        let (routes, default_gateway, route_events) = self.route_monitor.poll();
+        let vpn = vpn::detect_vpn(&usage);
+        let proxy = vpn::detect_proxy();
+
+        Ok(NetworkSnapshot {
+            interfaces,
+            dns,
+            http_checks,
+            link_events,
+            routes,
+            default_gateway,
+            route_events,
+            vpn,
+            proxy,
+            public_ip: self.public_ip_probe.latest(),
+            public_ip_changes: self.public_ip_probe.take_changes(),
+        })
+    }
+
+    fn collect_interfaces(&mut self, usage: &HashMap<String, (u64, u64, u64, u64)>) -> Result<(Vec<NetworkMetrics>, Vec<LinkEvent>)> {
         let mut metrics = Vec::new();
         
         // Get interface details using networksetup
         let interfaces = self.get_interface_details()?;
+
+        // Compare against the previous pass before inactive interfaces are filtered out
+        let current_status: HashMap<String, (String, String)> = interfaces.iter()
+            .map(|(name, info)| (name.clone(), (info.interface_type.clone(), info.status.clone())))
+            .collect();
+        let link_events = match &self.last_status {
+            Some(previous) => detect_link_events(previous, &current_status),
+            None => Vec::new(),
+        };
+        self.last_status = Some(current_status);
         
-        // Get network usage from netstat
-        let usage = self.get_network_usage()?;
         let now = Instant::now();
         
         for (name, stats) in usage {
-            if let Some(interface_info) = interfaces.get(&name) {
+            if let Some(interface_info) = interfaces.get(name) {
                 // Skip inactive interfaces
                 if interface_info.status != "active" {
                     continue;
                 }
 
                 // Calculate rates
-                let (rx_rate, tx_rate) = if let Some((last_rx, last_tx, last_time)) = self.last_bytes.get(&name) {
+                let (rx_rate, tx_rate) = if let Some((last_rx, last_tx, last_time)) = self.last_bytes.get(name) {
                     let time_diff = now.duration_since(*last_time).as_secs_f64();
                     if time_diff > 0.0 {
                         let rx_diff = stats.0.saturating_sub(*last_rx) as f64;
@@ -70,7 +158,7 @@ impl NetworkCollector {
                 let mut metric = NetworkMetrics {
                     node_id: self.node_id.clone(),
                     collected_at: Utc::now(),
-                    interface_name: name,
+                    interface_name: name.clone(),
                     rx_bytes: stats.0,
                     tx_bytes: stats.1,
                     rx_errors: stats.2,
@@ -95,7 +183,7 @@ impl NetworkCollector {
             b_is_wifi.cmp(&a_is_wifi)
         });
 
-        Ok(metrics)
+        Ok((metrics, link_events))
     }
 
     fn get_network_usage(&self) -> Result<HashMap<String, (u64, u64, u64, u64)>> {
@@ -317,4 +405,79 @@ impl NetworkCollector {
             wifi_info,
         })
     }
-} 
\ No newline at end of file
+} 
+
+/// Compare two status maps (interface -> (type, status)) and produce up/down transitions.
+/// Interfaces that disappear entirely are reported as going down.
+fn detect_link_events(
+    previous: &HashMap<String, (String, String)>,
+    current: &HashMap<String, (String, String)>,
+) -> Vec<LinkEvent> {
+    let now = Utc::now();
+    let mut events = Vec::new();
+
+    for (name, (interface_type, status)) in current {
+        let previous_status = previous.get(name)
+            .map(|(_, status)| status.as_str())
+            .unwrap_or("absent");
+        let was_active = previous_status == "active";
+        let is_active = status == "active";
+
+        if was_active != is_active {
+            events.push(LinkEvent {
+                interface_name: name.clone(),
+                interface_type: interface_type.clone(),
+                kind: if is_active { LinkEventKind::LinkUp } else { LinkEventKind::LinkDown },
+                previous_status: previous_status.to_string(),
+                status: status.clone(),
+                timestamp: now,
+            });
+        }
+    }
+
+    for (name, (interface_type, status)) in previous {
+        if status == "active" && !current.contains_key(name) {
+            events.push(LinkEvent {
+                interface_name: name.clone(),
+                interface_type: interface_type.clone(),
+                kind: LinkEventKind::LinkDown,
+                previous_status: status.clone(),
+                status: "absent".to_string(),
+                timestamp: now,
+            });
+        }
+    }
+
+    events.sort_by(|a, b| a.interface_name.cmp(&b.interface_name));
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_map(entries: &[(&str, &str)]) -> HashMap<String, (String, String)> {
+        entries.iter()
+            .map(|(name, status)| (name.to_string(), ("Ethernet".to_string(), status.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_link_events() {
+        let previous = status_map(&[("en0", "active"), ("en1", "inactive"), ("en2", "active"), ("en3", "active")]);
+        let current = status_map(&[("en0", "inactive"), ("en1", "active"), ("en3", "active")]);
+
+        let events = detect_link_events(&previous, &current);
+        let summary: Vec<(&str, LinkEventKind)> = events.iter()
+            .map(|e| (e.interface_name.as_str(), e.kind))
+            .collect();
+
+        assert_eq!(summary, vec![
+            ("en0", LinkEventKind::LinkDown),
+            ("en1", LinkEventKind::LinkUp),
+            ("en2", LinkEventKind::LinkDown),
+        ]);
+        assert_eq!(events[2].status, "absent");
+        assert!(detect_link_events(&current, &current).is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::Utc;
+use log::debug;
+use std::net::ToSocketAddrs;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::types::DnsProbeResult;
+
+/// Measures name resolution latency against the system resolvers.
+///
+/// Lookups go through `getaddrinfo` (via `ToSocketAddrs`), so they exercise the
+/// same resolver configuration every other process on the node uses.
+pub struct DnsProbe {
+    hostnames: Vec<String>,
+    timeout: Duration,
+}
+
+impl DnsProbe {
+    pub fn new(hostnames: Vec<String>, timeout: Duration) -> Self {
+        Self { hostnames, timeout }
+    }
+
+    /// Resolve every configured hostname in parallel, waiting at most `timeout` overall
+    pub fn probe(&self) -> Vec<DnsProbeResult> {
+        let deadline = Instant::now() + self.timeout;
+
+        // getaddrinfo has no timeout of its own, so each lookup runs on a
+        // helper thread and we stop waiting once the deadline has passed
+        let pending: Vec<(String, mpsc::Receiver<DnsProbeResult>)> = self.hostnames
+            .iter()
+            .map(|hostname| {
+                let (tx, rx) = mpsc::channel();
+                let host = hostname.clone();
+                thread::spawn(move || {
+                    let _ = tx.send(resolve(&host));
+                });
+                (hostname.clone(), rx)
+            })
+            .collect();
+
+        pending
+            .into_iter()
+            .map(|(hostname, rx)| {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                rx.recv_timeout(remaining).unwrap_or_else(|_| {
+                    debug!("DNS lookup for {} timed out", hostname);
+                    DnsProbeResult {
+                        hostname,
+                        resolved: false,
+                        latency_ms: self.timeout.as_secs_f64() * 1000.0,
+                        addresses: Vec::new(),
+                        error: Some("timed out".to_string()),
+                        checked_at: Utc::now(),
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+fn resolve(hostname: &str) -> DnsProbeResult {
+    let start = Instant::now();
+    // Port is irrelevant for the lookup but required by ToSocketAddrs
+    let result = (hostname, 0).to_socket_addrs();
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    match result {
+        Ok(addrs) => {
+            let mut addresses: Vec<String> = addrs.map(|addr| addr.ip().to_string()).collect();
+            addresses.dedup();
+            DnsProbeResult {
+                hostname: hostname.to_string(),
+                resolved: !addresses.is_empty(),
+                latency_ms,
+                addresses,
+                error: None,
+                checked_at: Utc::now(),
+            }
+        }
+        Err(e) => DnsProbeResult {
+            hostname: hostname.to_string(),
+            resolved: false,
+            latency_ms,
+            addresses: Vec::new(),
+            error: Some(e.to_string()),
+            checked_at: Utc::now(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_localhost() {
+        let probe = DnsProbe::new(vec!["localhost".to_string()], Duration::from_secs(2));
+        let results = probe.probe();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].hostname, "localhost");
+        assert!(results[0].resolved);
+        assert!(results[0].error.is_none());
+    }
+}
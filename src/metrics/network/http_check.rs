@@ -0,0 +1,307 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use futures_util::future::join_all;
+use log::{debug, warn};
+use reqwest::{redirect, Client};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::proxy;
+use super::types::HttpCheckResult;
+
+const DEFAULT_EXPECTED_STATUS: u16 = 200;
+const DEFAULT_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A single HTTP(S) endpoint to probe
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpCheckConfig {
+    pub url: String,
+    pub expected_status: u16,
+    pub timeout: Duration,
+}
+
+impl HttpCheckConfig {
+    /// Parse a `;`-separated list of checks, each written as `URL [STATUS] [TIMEOUT_MS]`
+    pub fn parse_list(spec: &str) -> Result<Vec<Self>> {
+        spec.split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let mut parts = entry.split_whitespace();
+                let url = parts.next()
+                    .ok_or_else(|| anyhow!("Empty HTTP check entry"))?
+                    .to_string();
+
+                let expected_status = match parts.next() {
+                    Some(status) => status.parse::<u16>()
+                        .map_err(|_| anyhow!("Invalid expected status '{}' for {}", status, url))?,
+                    None => DEFAULT_EXPECTED_STATUS,
+                };
+
+                let timeout = match parts.next() {
+                    Some(ms) => Duration::from_millis(ms.parse::<u64>()
+                        .map_err(|_| anyhow!("Invalid timeout '{}' for {}", ms, url))?),
+                    None => DEFAULT_CHECK_TIMEOUT,
+                };
+
+                Ok(Self { url, expected_status, timeout })
+            })
+            .collect()
+    }
+}
+
+/// Periodically probes configured HTTP endpoints in the background
+pub struct HttpChecker {
+    checks: Vec<HttpCheckConfig>,
+    interval: Duration,
+    results: Arc<Mutex<Vec<HttpCheckResult>>>,
+}
+
+impl HttpChecker {
+    pub fn new(checks: Vec<HttpCheckConfig>, interval: Duration) -> Self {
+        Self {
+            checks,
+            interval,
+            results: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Spawn the background task that runs all checks on the configured interval
+    pub fn start(&self) -> Result<()> {
+        if self.checks.is_empty() {
+            debug!("No HTTP checks configured");
+            return Ok(());
+        }
+
+        // Redirects are not followed so that the expected status applies to the
+        // configured URL itself
+        let client = proxy::client_builder()
+            .user_agent("node-controller-http-check")
+            .redirect(redirect::Policy::none())
+            .tls_info(true)
+            .build()?;
+
+        let checks = self.checks.clone();
+        let interval = self.interval;
+        let results = self.results.clone();
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                // Stop once the collector owning the results was dropped, e.g. on restart
+                if Arc::strong_count(&results) == 1 {
+                    break;
+                }
+                let latest = join_all(checks.iter().map(|check| run_check(&client, check))).await;
+                *results.lock().unwrap() = latest;
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Results of the most recent round of checks
+    pub fn latest_results(&self) -> Vec<HttpCheckResult> {
+        self.results.lock().unwrap().clone()
+    }
+}
+
+async fn run_check(client: &Client, check: &HttpCheckConfig) -> HttpCheckResult {
+    let start = Instant::now();
+    let response = client.get(&check.url)
+        .timeout(check.timeout)
+        .send()
+        .await;
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    let mut result = HttpCheckResult {
+        url: check.url.clone(),
+        success: false,
+        status: None,
+        expected_status: check.expected_status,
+        latency_ms,
+        cert_expires_at: None,
+        cert_days_remaining: None,
+        error: None,
+        checked_at: Utc::now(),
+    };
+
+    match response {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            result.status = Some(status);
+            result.success = status == check.expected_status;
+            if !result.success {
+                result.error = Some(format!("expected status {}, got {}", check.expected_status, status));
+            }
+
+            if let Some(expires_at) = response.extensions()
+                .get::<reqwest::tls::TlsInfo>()
+                .and_then(|info| info.peer_certificate())
+                .and_then(certificate_not_after)
+            {
+                result.cert_expires_at = Some(expires_at);
+                result.cert_days_remaining = Some((expires_at - Utc::now()).num_days());
+            }
+        }
+        Err(e) => {
+            warn!("HTTP check for {} failed: {}", check.url, e);
+            result.error = Some(if e.is_timeout() { "timed out".to_string() } else { e.to_string() });
+        }
+    }
+
+    result
+}
+
+/// Read one DER TLV, returning (tag, contents, rest)
+fn der_read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+
+    let (len, rest) = if first & 0x80 == 0 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        (len, &rest[count..])
+    };
+
+    if rest.len() < len {
+        return None;
+    }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+/// Extract the `notAfter` validity bound from a DER-encoded X.509 certificate
+fn certificate_not_after(der: &[u8]) -> Option<DateTime<Utc>> {
+    const SEQUENCE: u8 = 0x30;
+    const EXPLICIT_VERSION: u8 = 0xa0;
+
+    let (tag, certificate, _) = der_read(der)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+
+    let (tag, mut tbs, _) = der_read(certificate)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+
+    // Skip the optional version, then serial number, signature algorithm and issuer
+    let (tag, _, rest) = der_read(tbs)?;
+    if tag == EXPLICIT_VERSION {
+        tbs = rest;
+        let (_, _, rest) = der_read(tbs)?;
+        tbs = rest;
+    } else {
+        tbs = rest;
+    }
+    for _ in 0..2 {
+        let (_, _, rest) = der_read(tbs)?;
+        tbs = rest;
+    }
+
+    let (tag, validity, _) = der_read(tbs)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+
+    let (_, _, rest) = der_read(validity)?; // notBefore
+    let (tag, not_after, _) = der_read(rest)?;
+    parse_asn1_time(tag, not_after)
+}
+
+/// Parse an ASN.1 UTCTime (0x17) or GeneralizedTime (0x18) value
+fn parse_asn1_time(tag: u8, value: &[u8]) -> Option<DateTime<Utc>> {
+    let text = std::str::from_utf8(value).ok()?.trim_end_matches('Z');
+
+    let full = match tag {
+        // UTCTime uses a two-digit year: 50-99 => 19xx, 00-49 => 20xx
+        0x17 => {
+            let year: u32 = text.get(..2)?.parse().ok()?;
+            let century = if year >= 50 { "19" } else { "20" };
+            format!("{}{}", century, text)
+        }
+        0x18 => text.to_string(),
+        _ => return None,
+    };
+
+    let naive = NaiveDateTime::parse_from_str(&full, "%Y%m%d%H%M%S").ok()?;
+    Some(Utc.from_utc_datetime(&naive))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_check_list() {
+        let checks = HttpCheckConfig::parse_list(
+            "https://example.com; http://10.0.0.1:8080/health 204 1500 ;"
+        ).unwrap();
+
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].url, "https://example.com");
+        assert_eq!(checks[0].expected_status, 200);
+        assert_eq!(checks[0].timeout, DEFAULT_CHECK_TIMEOUT);
+        assert_eq!(checks[1].url, "http://10.0.0.1:8080/health");
+        assert_eq!(checks[1].expected_status, 204);
+        assert_eq!(checks[1].timeout, Duration::from_millis(1500));
+
+        assert!(HttpCheckConfig::parse_list("https://example.com abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_asn1_time() {
+        let utc = parse_asn1_time(0x17, b"250301120000Z").unwrap();
+        assert_eq!(utc.to_rfc3339(), "2025-03-01T12:00:00+00:00");
+
+        let generalized = parse_asn1_time(0x18, b"20510101000000Z").unwrap();
+        assert_eq!(generalized.to_rfc3339(), "2051-01-01T00:00:00+00:00");
+
+        assert!(parse_asn1_time(0x04, b"250301120000Z").is_none());
+    }
+
+    /// A self-signed DER certificate expiring at `not_after` (Unix seconds)
+    fn certificate(not_after: i64) -> Vec<u8> {
+        use openssl::asn1::Asn1Time;
+        use openssl::hash::MessageDigest;
+        use openssl::pkey::PKey;
+        use openssl::x509::{X509Builder, X509NameBuilder};
+
+        let key = PKey::generate_ed25519().unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "example.com").unwrap();
+        let name = name.build();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&Asn1Time::from_unix(1_700_000_000).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::from_unix(not_after).unwrap()).unwrap();
+        builder.sign(&key, MessageDigest::null()).unwrap();
+        builder.build().to_der().unwrap()
+    }
+
+    #[test]
+    fn test_certificate_not_after() {
+        // Years before 2050 are encoded as UTCTime, later ones as GeneralizedTime
+        let utc = certificate(1_900_000_000);
+        assert!(utc.windows(15).any(|w| w == b"\x17\x0d300317174640Z"));
+        assert_eq!(certificate_not_after(&utc).unwrap().to_rfc3339(), "2030-03-17T17:46:40+00:00");
+
+        let generalized = certificate(2_600_000_000);
+        assert!(generalized.windows(17).any(|w| w == b"\x18\x0f20520522141320Z"));
+        assert_eq!(certificate_not_after(&generalized).unwrap().to_rfc3339(), "2052-05-22T14:13:20+00:00");
+
+        for len in 0..utc.len() {
+            assert!(certificate_not_after(&utc[..len]).is_none());
+        }
+        assert!(certificate_not_after(&[0x30, 0x84, 0xff, 0xff, 0xff, 0xff]).is_none());
+    }
+}
@@ -1,4 +1,10 @@
 pub mod types;
 mod collector;
+mod dns;
+mod http_check;
+mod public_ip;
+mod routes;
+mod vpn;
 
-pub use collector::NetworkCollector; 
\ No newline at end of file
+pub use collector::{NetworkCollector, NetworkCollectorConfig};
+pub use http_check::HttpCheckConfig;
\ No newline at end of file
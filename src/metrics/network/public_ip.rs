@@ -0,0 +1,152 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use log::{debug, info, warn};
+use reqwest::Client;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::proxy;
+use super::types::{PublicIpChange, PublicIpInfo};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct PublicIpState {
+    latest: Option<PublicIpInfo>,
+    changes: Vec<PublicIpChange>,
+}
+
+/// Periodically asks an external endpoint which address the node egresses from.
+///
+/// Disabled unless an endpoint is configured, since it contacts a third party.
+pub struct PublicIpProbe {
+    endpoint: Option<String>,
+    interval: Duration,
+    state: Arc<Mutex<PublicIpState>>,
+}
+
+impl PublicIpProbe {
+    pub fn new(endpoint: Option<String>, interval: Duration) -> Self {
+        Self {
+            endpoint,
+            interval,
+            state: Arc::new(Mutex::new(PublicIpState::default())),
+        }
+    }
+
+    /// Spawn the background lookup task (no-op when no endpoint is configured)
+    pub fn start(&self) -> Result<()> {
+        let Some(endpoint) = self.endpoint.clone() else {
+            debug!("Public IP probe disabled");
+            return Ok(());
+        };
+
+        let client = proxy::client_builder()
+            .user_agent("node-controller-public-ip")
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        let interval = self.interval;
+        let state = self.state.clone();
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                // Stop once the collector owning the state was dropped, e.g. on restart
+                if Arc::strong_count(&state) == 1 {
+                    break;
+                }
+                match lookup(&client, &endpoint).await {
+                    Ok(address) => record(&state, &endpoint, address),
+                    Err(e) => warn!("Public IP lookup via {} failed: {}", endpoint, e),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Most recent lookup result
+    pub fn latest(&self) -> Option<PublicIpInfo> {
+        self.state.lock().unwrap().latest.clone()
+    }
+
+    /// Address changes seen since the last call
+    pub fn take_changes(&self) -> Vec<PublicIpChange> {
+        std::mem::take(&mut self.state.lock().unwrap().changes)
+    }
+}
+
+async fn lookup(client: &Client, endpoint: &str) -> Result<IpAddr> {
+    let response = client.get(endpoint).send().await?.error_for_status()?;
+    parse_address(&response.text().await?)
+}
+
+fn record(state: &Mutex<PublicIpState>, endpoint: &str, address: IpAddr) {
+    let now = Utc::now();
+    let address = address.to_string();
+    let mut state = state.lock().unwrap();
+
+    let previous = state.latest.as_ref().map(|info| info.address.clone());
+    let changed_at = match &previous {
+        Some(prev) if *prev == address => state.latest.as_ref().and_then(|info| info.changed_at),
+        Some(prev) => {
+            info!("Public IP changed from {} to {}", prev, address);
+            state.changes.push(PublicIpChange {
+                previous_address: Some(prev.clone()),
+                address: address.clone(),
+                timestamp: now,
+            });
+            Some(now)
+        }
+        None => None,
+    };
+
+    state.latest = Some(PublicIpInfo {
+        address,
+        endpoint: endpoint.to_string(),
+        changed_at,
+        checked_at: now,
+    });
+}
+
+/// Accept either a bare address (ipify, icanhazip, ifconfig.me) or a JSON object with an `ip` field
+fn parse_address(body: &str) -> Result<IpAddr> {
+    let body = body.trim();
+    if let Ok(address) = body.parse::<IpAddr>() {
+        return Ok(address);
+    }
+
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|json| json.get("ip")?.as_str()?.parse::<IpAddr>().ok())
+        .ok_or_else(|| anyhow!("Unrecognised public IP response: {:.64}", body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(parse_address("203.0.113.7\n").unwrap().to_string(), "203.0.113.7");
+        assert_eq!(parse_address(r#"{"ip":"2001:db8::1"}"#).unwrap().to_string(), "2001:db8::1");
+        assert!(parse_address("<html>blocked</html>").is_err());
+    }
+
+    #[test]
+    fn test_record_changes() {
+        let state = Mutex::new(PublicIpState::default());
+        record(&state, "https://api.ipify.org", "203.0.113.7".parse().unwrap());
+        record(&state, "https://api.ipify.org", "203.0.113.7".parse().unwrap());
+        assert!(state.lock().unwrap().changes.is_empty());
+
+        record(&state, "https://api.ipify.org", "198.51.100.2".parse().unwrap());
+        let state = state.lock().unwrap();
+        assert_eq!(state.changes.len(), 1);
+        assert_eq!(state.changes[0].previous_address.as_deref(), Some("203.0.113.7"));
+        assert_eq!(state.latest.as_ref().unwrap().address, "198.51.100.2");
+        assert!(state.latest.as_ref().unwrap().changed_at.is_some());
+    }
+}
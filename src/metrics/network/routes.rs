@@ -0,0 +1,216 @@
+use chrono::Utc;
+use log::debug;
+use std::collections::HashSet;
+use std::process::Command;
+
+use super::types::{GatewayInfo, RouteEntry, RouteEvent, RouteEventKind};
+
+/// Tracks the IPv4 routing table and default gateway between collections
+pub struct RouteMonitor {
+    last: Option<(Vec<RouteEntry>, Option<GatewayInfo>)>,
+}
+
+impl RouteMonitor {
+    pub fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// Read the current routes and gateway, returning them along with any
+    /// changes since the previous poll (none on the first poll)
+    pub fn poll(&mut self) -> (Vec<RouteEntry>, Option<GatewayInfo>, Vec<RouteEvent>) {
+        let routes = match Command::new("netstat").args(["-rn", "-f", "inet"]).output() {
+            Ok(output) if output.status.success() => parse_routes(&String::from_utf8_lossy(&output.stdout)),
+            Ok(_) | Err(_) => {
+                debug!("Failed to read routing table with netstat");
+                Vec::new()
+            }
+        };
+
+        let gateway = routes.iter()
+            .find(|route| route.is_default())
+            .map(|route| GatewayInfo {
+                address: route.gateway.clone(),
+                interface: route.interface.clone(),
+                mac: lookup_mac(&route.gateway),
+            });
+
+        let events = match &self.last {
+            Some((previous_routes, previous_gateway)) => {
+                diff_routes(previous_routes, previous_gateway.as_ref(), &routes, gateway.as_ref())
+            }
+            None => Vec::new(),
+        };
+
+        self.last = Some((routes.clone(), gateway.clone()));
+        (routes, gateway, events)
+    }
+}
+
+/// Parse `netstat -rn` output (macOS or Linux layout), locating columns from the header.
+/// Cloned host routes and link-layer entries are skipped since they churn constantly.
+fn parse_routes(output: &str) -> Vec<RouteEntry> {
+    let mut routes = Vec::new();
+    let mut columns: Option<(usize, usize, usize)> = None; // (gateway, flags, interface)
+
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.first() == Some(&"Destination") {
+            let find = |names: &[&str]| parts.iter().position(|p| names.contains(p));
+            columns = match (find(&["Gateway"]), find(&["Flags"]), find(&["Netif", "Iface"])) {
+                (Some(g), Some(f), Some(i)) => Some((g, f, i)),
+                _ => None,
+            };
+            continue;
+        }
+
+        let Some((gateway_col, flags_col, interface_col)) = columns else {
+            continue;
+        };
+        if parts.len() <= gateway_col.max(flags_col).max(interface_col) {
+            // A blank line or new section ends the current table
+            if parts.is_empty() {
+                columns = None;
+            }
+            continue;
+        }
+
+        let flags = parts[flags_col];
+        if flags.contains('W') || parts[gateway_col].starts_with("link#") {
+            continue;
+        }
+
+        routes.push(RouteEntry {
+            destination: parts[0].to_string(),
+            gateway: parts[gateway_col].to_string(),
+            flags: flags.to_string(),
+            interface: parts[interface_col].to_string(),
+        });
+    }
+
+    routes
+}
+
+/// Look up the hardware address of a neighbour in the ARP cache
+fn lookup_mac(address: &str) -> Option<String> {
+    let output = Command::new("arp").args(["-n", address]).output().ok()?;
+    parse_arp_mac(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_arp_mac(output: &str) -> Option<String> {
+    output.split_whitespace()
+        .find(|token| token.len() <= 17 && token.matches(':').count() == 5)
+        .map(|mac| mac.to_lowercase())
+}
+
+fn diff_routes(
+    previous: &[RouteEntry],
+    previous_gateway: Option<&GatewayInfo>,
+    current: &[RouteEntry],
+    current_gateway: Option<&GatewayInfo>,
+) -> Vec<RouteEvent> {
+    let now = Utc::now();
+    let mut events = Vec::new();
+
+    let gateway_key = |gw: Option<&GatewayInfo>| gw.map(|gw| (gw.address.clone(), gw.interface.clone()));
+    if gateway_key(previous_gateway) != gateway_key(current_gateway) {
+        events.push(RouteEvent {
+            kind: RouteEventKind::DefaultGatewayChanged,
+            destination: "default".to_string(),
+            previous_gateway: previous_gateway.map(|gw| gw.address.clone()),
+            gateway: current_gateway.map(|gw| gw.address.clone()),
+            interface: current_gateway.or(previous_gateway).map(|gw| gw.interface.clone()).unwrap_or_default(),
+            timestamp: now,
+        });
+    }
+
+    let key = |route: &RouteEntry| (route.destination.clone(), route.gateway.clone(), route.interface.clone());
+    let previous_keys: HashSet<_> = previous.iter().map(key).collect();
+    let current_keys: HashSet<_> = current.iter().map(key).collect();
+
+    for route in current.iter().filter(|r| !r.is_default() && !previous_keys.contains(&key(r))) {
+        events.push(RouteEvent {
+            kind: RouteEventKind::RouteAdded,
+            destination: route.destination.clone(),
+            previous_gateway: None,
+            gateway: Some(route.gateway.clone()),
+            interface: route.interface.clone(),
+            timestamp: now,
+        });
+    }
+    for route in previous.iter().filter(|r| !r.is_default() && !current_keys.contains(&key(r))) {
+        events.push(RouteEvent {
+            kind: RouteEventKind::RouteRemoved,
+            destination: route.destination.clone(),
+            previous_gateway: Some(route.gateway.clone()),
+            gateway: None,
+            interface: route.interface.clone(),
+            timestamp: now,
+        });
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MACOS_NETSTAT: &str = "Routing tables
+
+Internet:
+Destination        Gateway            Flags               Netif Expire
+default            192.168.1.1        UGScg                 en0
+10.8.0/24          utun3              USc                 utun3
+127                127.0.0.1          UCS                   lo0
+192.168.1          link#6             UCS                   en0      !
+192.168.1.1/32     link#6             UCS                   en0      !
+192.168.1.20       3c:22:fb:1:2:3     UHLWIi                en0   1180
+";
+
+    const LINUX_NETSTAT: &str = "Kernel IP routing table
+Destination     Gateway         Genmask         Flags   MSS Window  irtt Iface
+0.0.0.0         10.0.0.1        0.0.0.0         UG        0 0          0 eth0
+10.0.0.0        0.0.0.0         255.255.255.0   U         0 0          0 eth0
+";
+
+    #[test]
+    fn test_parse_routes() {
+        let routes = parse_routes(MACOS_NETSTAT);
+        assert_eq!(routes.len(), 3);
+        assert!(routes[0].is_default());
+        assert_eq!(routes[0].gateway, "192.168.1.1");
+        assert_eq!(routes[0].interface, "en0");
+        assert_eq!(routes[1].destination, "10.8.0/24");
+
+        let routes = parse_routes(LINUX_NETSTAT);
+        assert_eq!(routes.len(), 2);
+        assert!(routes[0].is_default());
+        assert_eq!(routes[0].interface, "eth0");
+    }
+
+    #[test]
+    fn test_parse_arp_mac() {
+        let macos = "? (192.168.1.1) at A4:91:B1:0:2:FF on en0 ifscope [ethernet]";
+        assert_eq!(parse_arp_mac(macos).as_deref(), Some("a4:91:b1:0:2:ff"));
+        assert_eq!(parse_arp_mac("192.168.1.1 (192.168.1.1) -- no entry"), None);
+    }
+
+    #[test]
+    fn test_diff_routes() {
+        let before = parse_routes(MACOS_NETSTAT);
+        let mut after = before.clone();
+        after.retain(|r| r.interface != "utun3");
+        after[0].gateway = "10.0.0.1".to_string();
+
+        let gw = |routes: &[RouteEntry]| GatewayInfo {
+            address: routes[0].gateway.clone(),
+            interface: routes[0].interface.clone(),
+            mac: None,
+        };
+        let events = diff_routes(&before, Some(&gw(&before)), &after, Some(&gw(&after)));
+        let kinds: Vec<RouteEventKind> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, vec![RouteEventKind::DefaultGatewayChanged, RouteEventKind::RouteRemoved]);
+        assert_eq!(events[0].gateway.as_deref(), Some("10.0.0.1"));
+        assert_eq!(events[1].destination, "10.8.0/24");
+    }
+}
@@ -2,6 +2,27 @@ use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 use std::fmt;
 
+/// Everything gathered during one network collection pass
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetworkSnapshot {
+    pub interfaces: Vec<NetworkMetrics>,
+    pub dns: Vec<DnsProbeResult>,
+    pub http_checks: Vec<HttpCheckResult>,
+    /// Interface status transitions observed since the previous collection
+    pub link_events: Vec<LinkEvent>,
+    pub routes: Vec<RouteEntry>,
+    pub default_gateway: Option<GatewayInfo>,
+    /// Routing changes observed since the previous collection
+    pub route_events: Vec<RouteEvent>,
+    pub vpn: VpnStatus,
+    /// System proxy settings, if any proxy is configured
+    pub proxy: Option<ProxyConfig>,
+    /// Latest public egress address, when the opt-in probe is enabled
+    pub public_ip: Option<PublicIpInfo>,
+    /// Public address changes observed since the previous collection
+    pub public_ip_changes: Vec<PublicIpChange>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NetworkMetrics {
     pub node_id: String,
@@ -106,4 +127,274 @@ impl WifiInfo {
     pub fn snr(&self) -> i32 {
         self.rssi - self.noise
     }
-} 
\ No newline at end of file
+} 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkEventKind {
+    LinkUp,
+    LinkDown,
+}
+
+/// An interface changing status between two collections
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LinkEvent {
+    pub interface_name: String,
+    pub interface_type: String,
+    pub kind: LinkEventKind,
+    pub previous_status: String,
+    pub status: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A single IPv4 routing table entry
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RouteEntry {
+    pub destination: String,
+    pub gateway: String,
+    pub flags: String,
+    pub interface: String,
+}
+
+impl RouteEntry {
+    pub fn is_default(&self) -> bool {
+        self.destination == "default" || self.destination == "0.0.0.0"
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GatewayInfo {
+    pub address: String,
+    pub interface: String,
+    pub mac: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteEventKind {
+    DefaultGatewayChanged,
+    RouteAdded,
+    RouteRemoved,
+}
+
+/// A change in the routing table between two collections
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RouteEvent {
+    pub kind: RouteEventKind,
+    pub destination: String,
+    pub previous_gateway: Option<String>,
+    pub gateway: Option<String>,
+    pub interface: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Active VPN tunnels and VPN client processes on the node
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct VpnStatus {
+    pub active: bool,
+    pub interfaces: Vec<VpnInterface>,
+    /// Recognised VPN clients that are currently running
+    pub clients: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VpnInterface {
+    pub name: String,
+    pub address: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProxyConfig {
+    /// Where the settings came from: `system` or `environment`
+    pub source: String,
+    pub http: Option<String>,
+    pub https: Option<String>,
+    pub socks: Option<String>,
+    pub pac_url: Option<String>,
+    pub auto_discovery: bool,
+    pub exceptions: Vec<String>,
+}
+
+impl ProxyConfig {
+    pub fn is_configured(&self) -> bool {
+        self.http.is_some() || self.https.is_some() || self.socks.is_some()
+            || self.pac_url.is_some() || self.auto_discovery
+    }
+}
+
+/// The address this node egresses from, as seen by an external endpoint
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublicIpInfo {
+    pub address: String,
+    pub endpoint: String,
+    /// When the address last changed, if a change has been observed
+    pub changed_at: Option<DateTime<Utc>>,
+    pub checked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublicIpChange {
+    pub previous_address: Option<String>,
+    pub address: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Outcome of resolving a single hostname with the system resolver
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DnsProbeResult {
+    pub hostname: String,
+    pub resolved: bool,
+    pub latency_ms: f64,
+    pub addresses: Vec<String>,
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Outcome of the most recent synthetic check against an HTTP(S) endpoint
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HttpCheckResult {
+    pub url: String,
+    pub success: bool,
+    pub status: Option<u16>,
+    pub expected_status: u16,
+    pub latency_ms: f64,
+    pub cert_expires_at: Option<DateTime<Utc>>,
+    pub cert_days_remaining: Option<i64>,
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl fmt::Display for DnsProbeResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.resolved {
+            write!(f, "{} - {:.1} ms ({})", self.hostname, self.latency_ms, self.addresses.join(", "))
+        } else {
+            write!(
+                f,
+                "{} - FAILED after {:.1} ms ({})",
+                self.hostname,
+                self.latency_ms,
+                self.error.as_deref().unwrap_or("no addresses")
+            )
+        }
+    }
+}
+
+impl fmt::Display for HttpCheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = self.status.map_or("-".to_string(), |s| s.to_string());
+        write!(
+            f,
+            "{} - {} ({:.0} ms, status {})",
+            self.url,
+            if self.success { "OK" } else { "FAILED" },
+            self.latency_ms,
+            status
+        )?;
+        if let Some(days) = self.cert_days_remaining {
+            write!(f, " - certificate expires in {} days", days)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, " - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for LinkEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            LinkEventKind::LinkUp => "link up",
+            LinkEventKind::LinkDown => "link down",
+        };
+        write!(
+            f,
+            "{} ({}) - {} ({} -> {}) at {}",
+            self.interface_name,
+            self.interface_type,
+            kind,
+            self.previous_status,
+            self.status,
+            self.timestamp.format("%H:%M:%S")
+        )
+    }
+}
+
+impl fmt::Display for GatewayInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} via {}", self.address, self.interface)?;
+        if let Some(mac) = &self.mac {
+            write!(f, " ({})", mac)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RouteEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let none = "none";
+        match self.kind {
+            RouteEventKind::DefaultGatewayChanged => write!(
+                f,
+                "default gateway changed: {} -> {} ({})",
+                self.previous_gateway.as_deref().unwrap_or(none),
+                self.gateway.as_deref().unwrap_or(none),
+                self.interface
+            ),
+            RouteEventKind::RouteAdded => write!(
+                f,
+                "route added: {} via {} ({})",
+                self.destination,
+                self.gateway.as_deref().unwrap_or(none),
+                self.interface
+            ),
+            RouteEventKind::RouteRemoved => write!(
+                f,
+                "route removed: {} via {} ({})",
+                self.destination,
+                self.previous_gateway.as_deref().unwrap_or(none),
+                self.interface
+            ),
+        }
+    }
+}
+
+impl fmt::Display for VpnStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.active {
+            let names: Vec<String> = self.interfaces.iter()
+                .map(|iface| format!("{} ({})", iface.name, iface.address))
+                .collect();
+            write!(f, "active via {}", names.join(", "))?;
+        } else {
+            write!(f, "inactive")?;
+        }
+        if !self.clients.is_empty() {
+            write!(f, " - clients: {}", self.clients.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(http) = &self.http {
+            parts.push(format!("HTTP {}", http));
+        }
+        if let Some(https) = &self.https {
+            parts.push(format!("HTTPS {}", https));
+        }
+        if let Some(socks) = &self.socks {
+            parts.push(format!("SOCKS {}", socks));
+        }
+        if let Some(pac) = &self.pac_url {
+            parts.push(format!("PAC {}", pac));
+        }
+        if self.auto_discovery {
+            parts.push("auto-discovery".to_string());
+        }
+        write!(f, "{} ({})", parts.join(", "), self.source)
+    }
+}
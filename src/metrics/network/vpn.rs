@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::env;
+use std::process::Command;
+
+use super::types::{ProxyConfig, VpnInterface, VpnStatus};
+
+/// Interface name prefixes used by tunnel drivers (macOS utun/ipsec/ppp, Linux tun/tap/wg)
+const TUNNEL_PREFIXES: &[&str] = &["utun", "ipsec", "ppp", "tun", "tap", "wg"];
+
+/// Process names of common VPN clients, matched case-insensitively against the executable name
+const KNOWN_VPN_PROCESSES: &[(&str, &str)] = &[
+    ("openvpn", "OpenVPN"),
+    ("tunnelblick", "Tunnelblick"),
+    ("wireguard-go", "WireGuard"),
+    ("wireguard", "WireGuard"),
+    ("tailscaled", "Tailscale"),
+    ("zerotier-one", "ZeroTier"),
+    ("vpnagentd", "Cisco AnyConnect"),
+    ("pangps", "GlobalProtect"),
+    ("forticlient", "FortiClient"),
+    ("nordvpn", "NordVPN"),
+    ("expressvpn", "ExpressVPN"),
+    ("protonvpn", "ProtonVPN"),
+    ("mullvad-daemon", "Mullvad"),
+    ("pritunl", "Pritunl"),
+];
+
+/// Detect active VPN tunnels from interface statistics (name -> rx/tx bytes and errors)
+/// and running VPN client processes.
+///
+/// A tunnel interface only counts as a VPN when it has an IPv4 address and has carried
+/// traffic; macOS keeps several idle utun interfaces around for system services.
+pub fn detect_vpn(usage: &HashMap<String, (u64, u64, u64, u64)>) -> VpnStatus {
+    let mut interfaces: Vec<VpnInterface> = usage.iter()
+        .filter(|(name, _)| is_tunnel_interface(name))
+        .filter(|(_, stats)| stats.0 + stats.1 > 0)
+        .filter_map(|(name, stats)| {
+            let address = interface_ipv4(name)?;
+            Some(VpnInterface {
+                name: name.clone(),
+                address,
+                rx_bytes: stats.0,
+                tx_bytes: stats.1,
+            })
+        })
+        .collect();
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let clients = match Command::new("ps").args(["-axo", "comm"]).output() {
+        Ok(output) => match_vpn_processes(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    };
+
+    VpnStatus {
+        active: !interfaces.is_empty(),
+        interfaces,
+        clients,
+    }
+}
+
+/// Read the system proxy configuration (`scutil --proxy` on macOS), falling back to
+/// the standard proxy environment variables. Returns `None` when no proxy is configured.
+pub fn detect_proxy() -> Option<ProxyConfig> {
+    let system = Command::new("scutil")
+        .arg("--proxy")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| parse_scutil_proxy(&String::from_utf8_lossy(&output.stdout)));
+
+    system.or_else(proxy_from_env)
+}
+
+fn is_tunnel_interface(name: &str) -> bool {
+    TUNNEL_PREFIXES.iter().any(|prefix| {
+        name.strip_prefix(prefix)
+            .is_some_and(|rest| rest.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+fn interface_ipv4(name: &str) -> Option<String> {
+    let output = Command::new("ifconfig").arg(name).output().ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("inet "))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .map(|addr| addr.trim_start_matches("addr:").to_string())
+}
+
+fn match_vpn_processes(ps_output: &str) -> Vec<String> {
+    let mut clients: Vec<String> = ps_output.lines()
+        .filter_map(|line| {
+            let executable = line.trim().rsplit('/').next()?.to_lowercase();
+            KNOWN_VPN_PROCESSES.iter()
+                .find(|(process, _)| executable.starts_with(process))
+                .map(|(_, client)| client.to_string())
+        })
+        .collect();
+    clients.sort();
+    clients.dedup();
+    clients
+}
+
+fn parse_scutil_proxy(output: &str) -> Option<ProxyConfig> {
+    let mut values: HashMap<&str, &str> = HashMap::new();
+    let mut exceptions = Vec::new();
+    let mut in_exceptions = false;
+
+    for line in output.lines().map(str::trim) {
+        if in_exceptions {
+            if line == "}" {
+                in_exceptions = false;
+            } else if let Some((_, host)) = line.split_once(" : ") {
+                exceptions.push(host.trim().to_string());
+            }
+            continue;
+        }
+        if line.starts_with("ExceptionsList") {
+            in_exceptions = true;
+            continue;
+        }
+        if let Some((key, value)) = line.split_once(" : ") {
+            values.insert(key.trim(), value.trim());
+        }
+    }
+
+    let enabled = |key: &str| values.get(key) == Some(&"1");
+    let endpoint = |prefix: &str| {
+        if !enabled(&format!("{}Enable", prefix)) {
+            return None;
+        }
+        let host = values.get(format!("{}Proxy", prefix).as_str())?;
+        Some(match values.get(format!("{}Port", prefix).as_str()) {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        })
+    };
+
+    let config = ProxyConfig {
+        source: "system".to_string(),
+        http: endpoint("HTTP"),
+        https: endpoint("HTTPS"),
+        socks: endpoint("SOCKS"),
+        pac_url: if enabled("ProxyAutoConfigEnable") {
+            values.get("ProxyAutoConfigURLString").map(|url| url.to_string())
+        } else {
+            None
+        },
+        auto_discovery: enabled("ProxyAutoDiscoveryEnable"),
+        exceptions,
+    };
+
+    config.is_configured().then_some(config)
+}
+
+fn proxy_from_env() -> Option<ProxyConfig> {
+    let var = |names: &[&str]| names.iter().find_map(|name| env::var(name).ok().filter(|v| !v.is_empty()));
+
+    let config = ProxyConfig {
+        source: "environment".to_string(),
+        http: var(&["HTTP_PROXY", "http_proxy"]),
+        https: var(&["HTTPS_PROXY", "https_proxy"]),
+        socks: var(&["ALL_PROXY", "all_proxy"]),
+        pac_url: None,
+        auto_discovery: false,
+        exceptions: var(&["NO_PROXY", "no_proxy"])
+            .map(|list| list.split(',').map(|h| h.trim().to_string()).filter(|h| !h.is_empty()).collect())
+            .unwrap_or_default(),
+    };
+
+    config.is_configured().then_some(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scutil_proxy() {
+        let output = "<dictionary> {
+  ExceptionsList : <array> {
+    0 : *.local
+    1 : 169.254/16
+  }
+  FTPPassive : 1
+  HTTPEnable : 1
+  HTTPPort : 3128
+  HTTPProxy : proxy.internal
+  HTTPSEnable : 0
+  ProxyAutoConfigEnable : 1
+  ProxyAutoConfigURLString : http://wpad.internal/proxy.pac
+}";
+        let config = parse_scutil_proxy(output).unwrap();
+        assert_eq!(config.http.as_deref(), Some("proxy.internal:3128"));
+        assert_eq!(config.https, None);
+        assert_eq!(config.pac_url.as_deref(), Some("http://wpad.internal/proxy.pac"));
+        assert_eq!(config.exceptions, vec!["*.local", "169.254/16"]);
+
+        let disabled = "<dictionary> {\n  HTTPEnable : 0\n  HTTPSEnable : 0\n}";
+        assert!(parse_scutil_proxy(disabled).is_none());
+    }
+
+    #[test]
+    fn test_vpn_matching() {
+        assert!(is_tunnel_interface("utun4"));
+        assert!(is_tunnel_interface("wg0"));
+        assert!(!is_tunnel_interface("en0"));
+        assert!(!is_tunnel_interface("tunnel-foo"));
+
+        let ps = "COMM\n/usr/sbin/mDNSResponder\n/Applications/Tailscale.app/Contents/MacOS/tailscaled\n/usr/local/sbin/openvpn\nopenvpn\n";
+        assert_eq!(match_vpn_processes(ps), vec!["OpenVPN", "Tailscale"]);
+    }
+}
@@ -1,42 +1,108 @@
 use anyhow::Result;
 use chrono::Utc;
 use std::process::Command;
-use uuid::Uuid;
-use std::time::Instant;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-use super::types::{StorageMetrics, FilesystemMetric, IoMetrics};
+use super::disk_io::{self, DiskCounters};
+use super::encryption;
+use super::time_machine;
+use super::types::{BackupStatus, StorageMetrics, FilesystemMetric, IoMetrics, MountEvent, MountEventKind};
 
 const RATE_SMOOTHING_FACTOR: f64 = 0.3; // Lower = more smoothing
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(300); // 5 minutes
+const ENCRYPTION_CHECK_INTERVAL: Duration = Duration::from_secs(300); // 5 minutes
 
 pub struct StorageCollector {
     node_id: String,
     last_io: Option<(u64, u64, Instant)>, // (total_read, total_write, timestamp)
     smoothed_rates: (f64, f64), // (read_rate, write_rate)
+    last_disk_counters: Option<(HashMap<String, DiskCounters>, Instant)>,
+    last_backup: Option<(Option<BackupStatus>, Instant)>,
+    encryption_cache: HashMap<String, (Option<String>, Instant)>, // device -> (encryption type, checked)
+    last_mounts: Option<HashMap<String, (String, String, u64)>>, // mount -> (device, fs_type, size)
 }
 
 impl StorageCollector {
-    pub fn new() -> Self {
+    pub fn new(node_id: String) -> Self {
         Self {
-            node_id: Uuid::new_v4().to_string(),
+            node_id,
             last_io: None,
             smoothed_rates: (0.0, 0.0),
+            last_disk_counters: None,
+            last_backup: None,
+            encryption_cache: HashMap::new(),
+            last_mounts: None,
         }
     }
 
     pub fn collect(&mut self) -> Result<StorageMetrics> {
         let filesystem_metrics = self.collect_filesystem_metrics()?;
+        let mount_events = self.track_mounts(&filesystem_metrics);
         let io_metrics = self.collect_io_metrics()?;
+        let backup = self.collect_backup_status();
         
         Ok(StorageMetrics {
             node_id: self.node_id.clone(),
             collected_at: Utc::now(),
             filesystem_metrics,
             io_metrics,
+            backup,
+            mount_events,
         })
     }
 
-    fn collect_filesystem_metrics(&self) -> Result<Vec<FilesystemMetric>> {
+    /// Compare the mounted filesystems against the previous collection
+    fn track_mounts(&mut self, filesystems: &[FilesystemMetric]) -> Vec<MountEvent> {
+        let current: HashMap<String, (String, String, u64)> = filesystems.iter()
+            .map(|fs| (fs.mount.clone(), (fs.fs.clone(), fs.fs_type.clone(), fs.size)))
+            .collect();
+
+        let events = match &self.last_mounts {
+            Some(previous) => detect_mount_events(previous, &current),
+            None => Vec::new(),
+        };
+        self.last_mounts = Some(current);
+        events
+    }
+
+    /// tmutil is slow, so backup status is refreshed far less often than disk usage
+    fn collect_backup_status(&mut self) -> Option<BackupStatus> {
+        if let Some((status, checked)) = &self.last_backup {
+            if checked.elapsed() < BACKUP_CHECK_INTERVAL {
+                return status.clone();
+            }
+        }
+
+        let status = time_machine::collect_backup_status();
+        self.last_backup = Some((status.clone(), Instant::now()));
+        status
+    }
+
+    /// Encryption status rarely changes, so each device is only re-checked periodically
+    fn encryption_for(&mut self, device: &str) -> Option<String> {
+        if let Some((kind, checked)) = self.encryption_cache.get(device) {
+            if checked.elapsed() < ENCRYPTION_CHECK_INTERVAL {
+                return kind.clone();
+            }
+        }
+
+        let kind = encryption::detect_encryption(device);
+        self.encryption_cache.insert(device.to_string(), (kind.clone(), Instant::now()));
+        kind
+    }
+
+    /// Map each mounted device to its filesystem type using `mount`
+    fn get_filesystem_types(&self) -> HashMap<String, String> {
+        match Command::new("mount").output() {
+            Ok(output) if output.status.success() => parse_mount_types(&String::from_utf8_lossy(&output.stdout)),
+            _ => HashMap::new(),
+        }
+    }
+
+    fn collect_filesystem_metrics(&mut self) -> Result<Vec<FilesystemMetric>> {
         let mut metrics = Vec::new();
+        let fs_types = self.get_filesystem_types();
         
         // Use df to get filesystem information
         let output = Command::new("df")
@@ -47,12 +113,15 @@ impl StorageCollector {
             let output_str = String::from_utf8_lossy(&output.stdout);
             let mut lines = output_str.lines();
             
-            // Skip header line
-            lines.next();
+            // The mount point is the last column: index 5 on Linux, 8 on macOS
+            // (which adds inode columns)
+            let mount_col = lines.next()
+                .and_then(|header| header.split_whitespace().position(|col| col == "Mounted"))
+                .unwrap_or(5);
             
             for line in lines {
                 let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 6 {
+                if parts.len() > mount_col {
                     let size = parts[1].parse::<u64>().unwrap_or(0) * 1024; // Convert KB to bytes
                     let used = parts[2].parse::<u64>().unwrap_or(0) * 1024;
                     let available = parts[3].parse::<u64>().unwrap_or(0) * 1024;
@@ -62,13 +131,18 @@ impl StorageCollector {
                         0.0
                     };
 
+                    let encryption_type = self.encryption_for(parts[0]);
+
                     metrics.push(FilesystemMetric {
                         fs: parts[0].to_string(),
-                        mount: parts[5].to_string(),
+                        mount: parts[mount_col..].join(" "),
+                        fs_type: fs_types.get(parts[0]).cloned().unwrap_or_else(|| "unknown".to_string()),
                         size,
                         used,
                         available,
                         used_percent,
+                        encrypted: encryption_type.is_some(),
+                        encryption_type,
                     });
                 }
             }
@@ -133,6 +207,20 @@ impl StorageCollector {
         // Update last I/O values
         self.last_io = Some((total_read, total_write, now));
 
+        // Per-device statistics, computed from the change in kernel counters
+        let counters = disk_io::read_counters();
+        let mut devices = Vec::new();
+        if let Some((last_counters, last_time)) = &self.last_disk_counters {
+            let elapsed = now.duration_since(*last_time).as_secs_f64();
+            for (device, current) in &counters {
+                if let Some(previous) = last_counters.get(device) {
+                    devices.push(disk_io::compute_rates(device, previous, current, elapsed));
+                }
+            }
+            devices.sort_by(|a, b| a.device.cmp(&b.device));
+        }
+        self.last_disk_counters = Some((counters, now));
+
         let mut metrics = IoMetrics {
             total_read,
             total_write,
@@ -140,6 +228,7 @@ impl StorageCollector {
             write_bytes_per_sec: self.smoothed_rates.1,
             read_rate_human: String::new(),
             write_rate_human: String::new(),
+            devices,
         };
 
         // Update human-readable rates
@@ -148,4 +237,90 @@ impl StorageCollector {
 
         Ok(metrics)
     }
-} 
\ No newline at end of file
+} 
+
+/// A mount point whose backing device changed counts as an unmount followed by a mount
+fn detect_mount_events(
+    previous: &HashMap<String, (String, String, u64)>,
+    current: &HashMap<String, (String, String, u64)>,
+) -> Vec<MountEvent> {
+    let now = Utc::now();
+    let event = |kind, mount: &str, (device, fs_type, size): &(String, String, u64)| MountEvent {
+        kind,
+        device: device.clone(),
+        mount: mount.to_string(),
+        fs_type: fs_type.clone(),
+        size: *size,
+        timestamp: now,
+    };
+
+    let mut events = Vec::new();
+    for (mount, info) in previous {
+        if current.get(mount).map(|(device, _, _)| device) != Some(&info.0) {
+            events.push(event(MountEventKind::Unmounted, mount, info));
+        }
+    }
+    for (mount, info) in current {
+        if previous.get(mount).map(|(device, _, _)| device) != Some(&info.0) {
+            events.push(event(MountEventKind::Mounted, mount, info));
+        }
+    }
+
+    events.sort_by(|a, b| (a.kind != MountEventKind::Unmounted, &a.mount).cmp(&(b.kind != MountEventKind::Unmounted, &b.mount)));
+    events
+}
+
+/// Parse `mount` output in either the macOS (`dev on /path (apfs, local, ...)`)
+/// or Linux (`dev on /path type ext4 (rw, ...)`) format
+fn parse_mount_types(output: &str) -> HashMap<String, String> {
+    output.lines()
+        .filter_map(|line| {
+            let (device, rest) = line.split_once(" on ")?;
+            let fs_type = if let Some((_, after)) = rest.split_once(" type ") {
+                after.split_whitespace().next()?
+            } else {
+                let (_, options) = rest.rsplit_once(" (")?;
+                options.split(',').next()?.trim().trim_end_matches(')')
+            };
+            Some((device.to_string(), fs_type.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mount_types() {
+        let macos = "/dev/disk3s1s1 on / (apfs, sealed, local, read-only, journaled)\nmap auto_home on /System/Volumes/Data/home (autofs, automounted, nobrowse)\n";
+        let types = parse_mount_types(macos);
+        assert_eq!(types.get("/dev/disk3s1s1").map(String::as_str), Some("apfs"));
+        assert_eq!(types.get("map auto_home").map(String::as_str), Some("autofs"));
+
+        let linux = "/dev/mapper/cryptroot on / type ext4 (rw,relatime)\n";
+        let types = parse_mount_types(linux);
+        assert_eq!(types.get("/dev/mapper/cryptroot").map(String::as_str), Some("ext4"));
+    }
+
+    #[test]
+    fn test_detect_mount_events() {
+        let entry = |device: &str| (device.to_string(), "apfs".to_string(), 1024);
+        let previous: HashMap<_, _> = [
+            ("/".to_string(), entry("/dev/disk3s1s1")),
+            ("/Volumes/Backup".to_string(), entry("/dev/disk4s1")),
+        ].into_iter().collect();
+        let current: HashMap<_, _> = [
+            ("/".to_string(), entry("/dev/disk3s1s1")),
+            ("/Volumes/Scratch SSD".to_string(), entry("/dev/disk5s2")),
+        ].into_iter().collect();
+
+        let events = detect_mount_events(&previous, &current);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, MountEventKind::Unmounted);
+        assert_eq!(events[0].device, "/dev/disk4s1");
+        assert_eq!(events[1].kind, MountEventKind::Mounted);
+        assert_eq!(events[1].mount, "/Volumes/Scratch SSD");
+        assert!(detect_mount_events(&current, &current).is_empty());
+    }
+}
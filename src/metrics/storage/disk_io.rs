@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use super::types::DiskIoMetrics;
+
+const SECTOR_SIZE: u64 = 512; // /proc/diskstats always counts 512-byte sectors
+
+/// Cumulative per-device I/O counters as exposed by the kernel
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DiskCounters {
+    pub reads: u64,
+    pub writes: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub read_time_ns: u64,
+    pub write_time_ns: u64,
+    /// Sum over all requests of the time they spent queued or in service
+    pub weighted_time_ns: u64,
+}
+
+/// Read counters for every physical disk, from `/proc/diskstats` on Linux or the
+/// IOBlockStorageDriver statistics in the IORegistry on macOS
+pub fn read_counters() -> HashMap<String, DiskCounters> {
+    if let Ok(diskstats) = fs::read_to_string("/proc/diskstats") {
+        return parse_diskstats(&diskstats)
+            .into_iter()
+            .filter(|(name, _)| Path::new("/sys/block").join(name).exists())
+            .collect();
+    }
+
+    match Command::new("ioreg").args(["-c", "IOBlockStorageDriver", "-r", "-l", "-w", "0"]).output() {
+        Ok(output) if output.status.success() => parse_ioreg(&String::from_utf8_lossy(&output.stdout)),
+        _ => HashMap::new(),
+    }
+}
+
+/// Turn two counter samples taken `elapsed_secs` apart into rates, latencies and queue depth
+pub fn compute_rates(device: &str, previous: &DiskCounters, current: &DiskCounters, elapsed_secs: f64) -> DiskIoMetrics {
+    let delta = |cur: u64, prev: u64| cur.saturating_sub(prev) as f64;
+    let reads = delta(current.reads, previous.reads);
+    let writes = delta(current.writes, previous.writes);
+    let per_sec = |value: f64| if elapsed_secs > 0.0 { value / elapsed_secs } else { 0.0 };
+    let latency_ms = |time_ns: f64, ops: f64| if ops > 0.0 { time_ns / ops / 1_000_000.0 } else { 0.0 };
+
+    DiskIoMetrics {
+        device: device.to_string(),
+        reads_per_sec: per_sec(reads),
+        writes_per_sec: per_sec(writes),
+        read_bytes_per_sec: per_sec(delta(current.read_bytes, previous.read_bytes)),
+        write_bytes_per_sec: per_sec(delta(current.write_bytes, previous.write_bytes)),
+        read_latency_ms: latency_ms(delta(current.read_time_ns, previous.read_time_ns), reads),
+        write_latency_ms: latency_ms(delta(current.write_time_ns, previous.write_time_ns), writes),
+        // Little's law: average number of requests in flight over the interval
+        queue_depth: per_sec(delta(current.weighted_time_ns, previous.weighted_time_ns)) / 1_000_000_000.0,
+    }
+}
+
+fn parse_diskstats(content: &str) -> HashMap<String, DiskCounters> {
+    content.lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 14 {
+                return None;
+            }
+            let field = |i: usize| parts[i].parse::<u64>().unwrap_or(0);
+            let ms = 1_000_000;
+            Some((parts[2].to_string(), DiskCounters {
+                reads: field(3),
+                read_bytes: field(5) * SECTOR_SIZE,
+                read_time_ns: field(6) * ms,
+                writes: field(7),
+                write_bytes: field(9) * SECTOR_SIZE,
+                write_time_ns: field(10) * ms,
+                weighted_time_ns: field(13) * ms,
+            }))
+        })
+        .collect()
+}
+
+/// Each IOBlockStorageDriver carries a `"Statistics"` dictionary; the first `"BSD Name"`
+/// that follows it in the subtree is the whole-disk media node (e.g. `disk0`)
+fn parse_ioreg(output: &str) -> HashMap<String, DiskCounters> {
+    let mut devices = HashMap::new();
+    let mut pending: Option<DiskCounters> = None;
+
+    for line in output.lines() {
+        if let Some(start) = line.find("\"Statistics\" = {") {
+            let body = &line[start + "\"Statistics\" = {".len()..];
+            let body = body.trim_end().trim_end_matches('}');
+            let stats: HashMap<&str, u64> = body.split(',')
+                .filter_map(|entry| {
+                    let (key, value) = entry.split_once('=')?;
+                    Some((key.trim().trim_matches('"'), value.trim().parse().ok()?))
+                })
+                .collect();
+            let stat = |key: &str| stats.get(key).copied().unwrap_or(0);
+
+            pending = Some(DiskCounters {
+                reads: stat("Operations (Read)"),
+                writes: stat("Operations (Write)"),
+                read_bytes: stat("Bytes (Read)"),
+                write_bytes: stat("Bytes (Write)"),
+                read_time_ns: stat("Total Time (Read)"),
+                write_time_ns: stat("Total Time (Write)"),
+                weighted_time_ns: stat("Total Time (Read)") + stat("Total Time (Write)"),
+            });
+        } else if let Some(start) = line.find("\"BSD Name\" = \"") {
+            if let Some(counters) = pending.take() {
+                let name = line[start + "\"BSD Name\" = \"".len()..].trim_end().trim_end_matches('"');
+                devices.insert(name.to_string(), counters);
+            }
+        }
+    }
+
+    devices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ioreg() {
+        let output = r#"+-o IOBlockStorageDriver  <class IOBlockStorageDriver, id 0x100000494>
+    {
+      "Statistics" = {"Operations (Write)"=200,"Latency Time (Write)"=0,"Bytes (Read)"=4096000,"Errors (Write)"=0,"Total Time (Read)"=50000000,"Latency Time (Read)"=0,"Retries (Read)"=0,"Errors (Read)"=0,"Total Time (Write)"=80000000,"Bytes (Write)"=819200,"Operations (Read)"=1000,"Retries (Write)"=0}
+    }
+    +-o APPLE SSD AP0512Q Media  <class IOMedia, id 0x100000495>
+      {
+        "BSD Name" = "disk0"
+      }
+      +-o IOGUIDPartitionScheme
+        +-o iBootSystemContainer@1  <class IOMedia>
+            "BSD Name" = "disk0s1"
+"#;
+        let devices = parse_ioreg(output);
+        assert_eq!(devices.len(), 1);
+        let disk = devices["disk0"];
+        assert_eq!(disk.reads, 1000);
+        assert_eq!(disk.write_bytes, 819200);
+        assert_eq!(disk.weighted_time_ns, 130_000_000);
+    }
+
+    #[test]
+    fn test_parse_diskstats_and_rates() {
+        let before = parse_diskstats("   8       0 sda 100 0 800 50 40 0 400 80 0 90 130 0 0 0 0\n");
+        let after = parse_diskstats("   8       0 sda 300 0 2400 250 140 0 1200 380 1 500 630 0 0 0 0\n");
+
+        let metrics = compute_rates("sda", &before["sda"], &after["sda"], 2.0);
+        assert_eq!(metrics.reads_per_sec, 100.0);
+        assert_eq!(metrics.writes_per_sec, 50.0);
+        assert_eq!(metrics.read_bytes_per_sec, 1600.0 * 512.0 / 2.0);
+        assert_eq!(metrics.read_latency_ms, 1.0);
+        assert_eq!(metrics.write_latency_ms, 3.0);
+        assert!((metrics.queue_depth - 0.25).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Determine whether the block device backing a filesystem is encrypted,
+/// returning the encryption type (`FileVault`, `LUKS`, `dm-crypt`) if it is.
+///
+/// Returns `None` for unencrypted volumes and for pseudo filesystems without a
+/// backing device.
+pub fn detect_encryption(device: &str) -> Option<String> {
+    if !device.starts_with("/dev/") {
+        return None;
+    }
+
+    if Path::new("/sys/block").exists() {
+        return linux_encryption(device);
+    }
+
+    let output = Command::new("diskutil").args(["info", device]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_diskutil_encryption(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_diskutil_encryption(output: &str) -> Option<String> {
+    let value = |key: &str| {
+        output.lines()
+            .map(str::trim)
+            .find_map(|line| line.strip_prefix(key).map(|rest| rest.trim_start_matches(':').trim().to_string()))
+    };
+
+    if value("FileVault").is_some_and(|v| v.starts_with("Yes")) {
+        return Some("FileVault".to_string());
+    }
+    // Non-FileVault APFS encryption and legacy CoreStorage volumes
+    if value("Encrypted").is_some_and(|v| v.starts_with("Yes")) {
+        return Some(value("Encryption Type").unwrap_or_else(|| "APFS".to_string()));
+    }
+    None
+}
+
+/// Walk the device-mapper stack (e.g. LVM on LUKS) looking for a crypt target
+fn linux_encryption(device: &str) -> Option<String> {
+    let name = fs::canonicalize(device).ok()?
+        .file_name()?
+        .to_string_lossy()
+        .to_string();
+    dm_encryption(&name, 0)
+}
+
+fn dm_encryption(name: &str, depth: usize) -> Option<String> {
+    if depth > 4 {
+        return None;
+    }
+
+    let block = Path::new("/sys/class/block").join(name);
+    if let Ok(uuid) = fs::read_to_string(block.join("dm/uuid")) {
+        if let Some(kind) = crypt_type_from_dm_uuid(uuid.trim()) {
+            return Some(kind);
+        }
+    }
+
+    fs::read_dir(block.join("slaves")).ok()?
+        .filter_map(|entry| entry.ok())
+        .find_map(|entry| dm_encryption(&entry.file_name().to_string_lossy(), depth + 1))
+}
+
+fn crypt_type_from_dm_uuid(uuid: &str) -> Option<String> {
+    if uuid.starts_with("CRYPT-LUKS") {
+        Some("LUKS".to_string())
+    } else if uuid.starts_with("CRYPT-") {
+        Some("dm-crypt".to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diskutil_encryption() {
+        let filevault = "   Volume Name:               Macintosh HD - Data\n   FileVault:                 Yes (Unlocked)\n";
+        assert_eq!(parse_diskutil_encryption(filevault).as_deref(), Some("FileVault"));
+
+        let plain = "   Volume Name:               External\n   FileVault:                 No\n";
+        assert_eq!(parse_diskutil_encryption(plain), None);
+
+        assert_eq!(crypt_type_from_dm_uuid("CRYPT-LUKS2-0f3e-cryptroot").as_deref(), Some("LUKS"));
+        assert_eq!(crypt_type_from_dm_uuid("LVM-abcdef"), None);
+    }
+}
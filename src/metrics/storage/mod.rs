@@ -1,4 +1,10 @@
 pub mod types;
 mod collector;
+mod disk_io;
+mod encryption;
+// Scans only run on demand, not from the collection loop
+mod scanner;
+mod time_machine;
 
-pub use collector::StorageCollector; 
\ No newline at end of file
+pub use collector::StorageCollector;
+pub use scanner::{DirectoryScanner, ScanOptions}; 
\ No newline at end of file
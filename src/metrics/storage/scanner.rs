@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use log::debug;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::types::{DirectoryScanReport, ScanEntry};
+
+/// Limits for an on-demand directory scan
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanOptions {
+    /// Number of largest files and directories to report
+    pub top_n: usize,
+    /// Do not descend into other mounted filesystems
+    pub same_filesystem: bool,
+    /// Stop scanning after this long and report what was found so far
+    pub time_limit: Duration,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            top_n: 20,
+            same_filesystem: true,
+            time_limit: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Walks a directory tree to find what is using the most space.
+///
+/// Sizes are allocated bytes (like `du`), so sparse files and APFS clones are not
+/// over-counted. Symlinks are never followed.
+pub struct DirectoryScanner {
+    options: ScanOptions,
+}
+
+struct ScanState {
+    root_dev: u64,
+    deadline: Instant,
+    files: BinaryHeap<Reverse<(u64, PathBuf)>>,
+    directories: BinaryHeap<Reverse<(u64, PathBuf)>>,
+    file_count: u64,
+    dir_count: u64,
+    errors: u64,
+    truncated: bool,
+}
+
+impl DirectoryScanner {
+    pub fn new(options: ScanOptions) -> Self {
+        Self { options }
+    }
+
+    /// Scan `path` and report its total size and the top-N largest files and directories
+    pub fn scan(&self, path: &Path) -> Result<DirectoryScanReport> {
+        let started = Instant::now();
+        let metadata = fs::symlink_metadata(path)
+            .map_err(|e| anyhow!("Cannot scan {}: {}", path.display(), e))?;
+        if !metadata.is_dir() {
+            return Err(anyhow!("{} is not a directory", path.display()));
+        }
+
+        let mut state = ScanState {
+            root_dev: metadata.dev(),
+            deadline: started + self.options.time_limit,
+            files: BinaryHeap::new(),
+            directories: BinaryHeap::new(),
+            file_count: 0,
+            dir_count: 0,
+            errors: 0,
+            truncated: false,
+        };
+
+        let total_bytes = self.scan_dir(path, &mut state);
+        debug!("Scanned {} in {:?}", path.display(), started.elapsed());
+
+        Ok(DirectoryScanReport {
+            path: path.display().to_string(),
+            total_bytes,
+            file_count: state.file_count,
+            dir_count: state.dir_count,
+            largest_files: into_sorted_entries(state.files),
+            largest_directories: into_sorted_entries(state.directories),
+            errors: state.errors,
+            truncated: state.truncated,
+            duration_ms: started.elapsed().as_millis() as u64,
+            scanned_at: Utc::now(),
+        })
+    }
+
+    /// Returns the allocated size of everything below `dir`
+    fn scan_dir(&self, dir: &Path, state: &mut ScanState) -> u64 {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => {
+                state.errors += 1;
+                return 0;
+            }
+        };
+
+        let mut total = 0;
+        for entry in entries {
+            if Instant::now() >= state.deadline {
+                state.truncated = true;
+                break;
+            }
+
+            let Ok(entry) = entry else {
+                state.errors += 1;
+                continue;
+            };
+            let Ok(metadata) = entry.metadata() else {
+                state.errors += 1;
+                continue;
+            };
+
+            let path = entry.path();
+            if metadata.is_dir() {
+                if self.options.same_filesystem && metadata.dev() != state.root_dev {
+                    continue;
+                }
+                state.dir_count += 1;
+                let size = self.scan_dir(&path, state);
+                total += size;
+                push_bounded(&mut state.directories, self.options.top_n, size, path);
+            } else if metadata.is_file() {
+                state.file_count += 1;
+                let size = metadata.blocks() * 512;
+                total += size;
+                push_bounded(&mut state.files, self.options.top_n, size, path);
+            }
+        }
+
+        total
+    }
+}
+
+/// Keep only the `limit` largest entries in a min-heap
+fn push_bounded(heap: &mut BinaryHeap<Reverse<(u64, PathBuf)>>, limit: usize, size: u64, path: PathBuf) {
+    if limit == 0 {
+        return;
+    }
+    if heap.len() < limit {
+        heap.push(Reverse((size, path)));
+    } else if heap.peek().is_some_and(|Reverse((smallest, _))| size > *smallest) {
+        heap.pop();
+        heap.push(Reverse((size, path)));
+    }
+}
+
+fn into_sorted_entries(heap: BinaryHeap<Reverse<(u64, PathBuf)>>) -> Vec<ScanEntry> {
+    // Ascending order of Reverse is descending order of size
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((size, path))| ScanEntry {
+            path: path.display().to_string(),
+            size,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_scan_reports_largest_entries() {
+        let root = tempdir().unwrap();
+        let big = root.path().join("big");
+        let small = root.path().join("small");
+        fs::create_dir_all(big.join("nested")).unwrap();
+        fs::create_dir_all(&small).unwrap();
+        fs::write(big.join("nested/blob.bin"), vec![1u8; 256 * 1024]).unwrap();
+        fs::write(big.join("medium.bin"), vec![1u8; 64 * 1024]).unwrap();
+        fs::write(small.join("tiny.txt"), b"hello").unwrap();
+
+        let scanner = DirectoryScanner::new(ScanOptions { top_n: 2, ..ScanOptions::default() });
+        let report = scanner.scan(root.path()).unwrap();
+
+        assert_eq!(report.file_count, 3);
+        assert_eq!(report.dir_count, 3);
+        assert!(!report.truncated);
+        assert_eq!(report.largest_files.len(), 2);
+        assert!(report.largest_files[0].path.ends_with("blob.bin"));
+        assert!(report.largest_files[1].path.ends_with("medium.bin"));
+        assert!(report.largest_directories[0].path.ends_with("big"));
+        assert!(report.largest_directories[0].size >= report.largest_directories[1].size);
+        assert!(report.total_bytes >= 320 * 1024);
+
+        assert!(scanner.scan(&big.join("medium.bin")).is_err());
+    }
+}
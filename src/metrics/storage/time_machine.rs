@@ -0,0 +1,101 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use std::collections::HashMap;
+use std::process::Command;
+
+use super::types::BackupStatus;
+
+/// Collect local APFS snapshot and Time Machine status via `tmutil`.
+/// Returns `None` on systems without Time Machine.
+pub fn collect_backup_status() -> Option<BackupStatus> {
+    let snapshots = run_tmutil(&["listlocalsnapshots", "/"])?;
+    let local_snapshots = parse_local_snapshots(&snapshots);
+
+    let configured = run_tmutil(&["destinationinfo"])
+        .map(|output| output.contains("Name"))
+        .unwrap_or(false);
+
+    let status = run_tmutil(&["status"]).map(|output| parse_status(&output)).unwrap_or_default();
+    let running = status.get("Running").is_some_and(|v| v == "1");
+
+    // Reading the latest backup requires Full Disk Access; treat failure as unknown
+    let last_backup_at = run_tmutil(&["latestbackup"])
+        .and_then(|output| parse_backup_timestamp(output.trim()));
+
+    Some(BackupStatus {
+        local_snapshot_count: local_snapshots.len(),
+        latest_local_snapshot: local_snapshots.iter().filter_map(|name| parse_backup_timestamp(name)).max(),
+        local_snapshots,
+        time_machine_configured: configured,
+        backup_running: running,
+        backup_phase: if running { status.get("BackupPhase").cloned() } else { None },
+        backup_progress: if running {
+            status.get("Percent").and_then(|p| p.parse::<f64>().ok()).map(|p| p * 100.0)
+        } else {
+            None
+        },
+        last_backup_at,
+    })
+}
+
+fn run_tmutil(args: &[&str]) -> Option<String> {
+    let output = Command::new("tmutil").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn parse_local_snapshots(output: &str) -> Vec<String> {
+    output.lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("com.apple."))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parse the `Key = value;` pairs printed by `tmutil status`
+fn parse_status(output: &str) -> HashMap<String, String> {
+    output.lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().trim_end_matches(';').split_once(" = ")?;
+            Some((key.trim().to_string(), value.trim().trim_matches('"').to_string()))
+        })
+        .collect()
+}
+
+/// Extract the `YYYY-MM-DD-HHMMSS` local timestamp that Time Machine embeds in backup
+/// paths and snapshot names (e.g. `com.apple.TimeMachine.2024-05-01-101500.local`)
+fn parse_backup_timestamp(name: &str) -> Option<DateTime<Utc>> {
+    let name = name.rsplit('/').next()?;
+    name.split('.')
+        .find_map(|part| NaiveDateTime::parse_from_str(part, "%Y-%m-%d-%H%M%S").ok())
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(|local| local.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tmutil_output() {
+        let snapshots = parse_local_snapshots(
+            "Snapshots for disk /:\ncom.apple.TimeMachine.2024-05-01-101500.local\ncom.apple.os.update-ABC123\n"
+        );
+        assert_eq!(snapshots.len(), 2);
+
+        let status = parse_status(
+            "Backup session status:\n{\n    BackupPhase = Copying;\n    Percent = \"0.25\";\n    Running = 1;\n}\n"
+        );
+        assert_eq!(status.get("BackupPhase").map(String::as_str), Some("Copying"));
+        assert_eq!(status.get("Percent").map(String::as_str), Some("0.25"));
+    }
+
+    #[test]
+    fn test_parse_backup_timestamp() {
+        let expected = Local.with_ymd_and_hms(2024, 5, 1, 10, 15, 0).unwrap().with_timezone(&Utc);
+        assert_eq!(parse_backup_timestamp("com.apple.TimeMachine.2024-05-01-101500.local"), Some(expected));
+        assert_eq!(parse_backup_timestamp("/Volumes/Backups/2024-05-01-101500.backup"), Some(expected));
+        assert_eq!(parse_backup_timestamp("com.apple.os.update-ABC123"), None);
+    }
+}
@@ -8,17 +8,42 @@ pub struct StorageMetrics {
     pub collected_at: DateTime<Utc>,
     pub filesystem_metrics: Vec<FilesystemMetric>,
     pub io_metrics: IoMetrics,
+    /// Local snapshot and Time Machine state, on systems that have it
+    pub backup: Option<BackupStatus>,
+    /// Filesystems that appeared or disappeared since the previous collection
+    pub mount_events: Vec<MountEvent>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MountEventKind {
+    Mounted,
+    Unmounted,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MountEvent {
+    pub kind: MountEventKind,
+    pub device: String,
+    pub mount: String,
+    pub fs_type: String,
+    pub size: u64,
+    pub timestamp: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FilesystemMetric {
     pub fs: String,
     pub mount: String,
+    pub fs_type: String,
     pub size: u64,
     pub used: u64,
     pub available: u64,
     #[serde(skip_serializing)]
     pub used_percent: f64,
+    pub encrypted: bool,
+    /// `FileVault`, `LUKS`, `dm-crypt`, ... when the volume is encrypted
+    pub encryption_type: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,6 +56,62 @@ pub struct IoMetrics {
     pub read_rate_human: String,
     #[serde(skip_serializing)]
     pub write_rate_human: String,
+    pub devices: Vec<DiskIoMetrics>,
+}
+
+/// Per-device throughput, latency and queue depth over the last collection interval
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiskIoMetrics {
+    pub device: String,
+    pub reads_per_sec: f64,
+    pub writes_per_sec: f64,
+    pub read_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    pub read_latency_ms: f64,
+    pub write_latency_ms: f64,
+    /// Average number of requests in flight
+    pub queue_depth: f64,
+}
+
+/// Local APFS snapshots and Time Machine backup health.
+///
+/// APFS does not report how much space an individual snapshot pins, so only the
+/// snapshot count and names are collected; reclaimable space shows up as purgeable
+/// space in the filesystem metrics.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupStatus {
+    pub local_snapshot_count: usize,
+    pub local_snapshots: Vec<String>,
+    pub latest_local_snapshot: Option<DateTime<Utc>>,
+    pub time_machine_configured: bool,
+    pub backup_running: bool,
+    pub backup_phase: Option<String>,
+    /// Progress of the running backup (0-100)
+    pub backup_progress: Option<f64>,
+    pub last_backup_at: Option<DateTime<Utc>>,
+}
+
+/// Result of an on-demand scan for what is filling a directory tree
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DirectoryScanReport {
+    pub path: String,
+    pub total_bytes: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+    pub largest_files: Vec<ScanEntry>,
+    pub largest_directories: Vec<ScanEntry>,
+    /// Entries that could not be read (usually permission errors)
+    pub errors: u64,
+    /// Set when the time limit was hit before the whole tree was walked
+    pub truncated: bool,
+    pub duration_ms: u64,
+    pub scanned_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScanEntry {
+    pub path: String,
+    pub size: u64,
 }
 
 impl StorageMetrics {
@@ -76,7 +157,11 @@ impl fmt::Display for FilesystemMetric {
             StorageMetrics::format_size(self.size),
             self.used_percent,
             StorageMetrics::format_size(self.available)
-        )
+        )?;
+        if let Some(encryption) = &self.encryption_type {
+            write!(f, " [{}]", encryption)?;
+        }
+        Ok(())
     }
 }
 
@@ -89,4 +174,83 @@ impl fmt::Display for IoMetrics {
             self.write_rate_human
         )
     }
-} 
\ No newline at end of file
+} 
+
+impl fmt::Display for DiskIoMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:.0} r/s ({:.2} ms), {:.0} w/s ({:.2} ms), queue {:.2} - Read {} - Write {}",
+            self.device,
+            self.reads_per_sec,
+            self.read_latency_ms,
+            self.writes_per_sec,
+            self.write_latency_ms,
+            self.queue_depth,
+            StorageMetrics::format_rate(self.read_bytes_per_sec),
+            StorageMetrics::format_rate(self.write_bytes_per_sec)
+        )
+    }
+}
+
+impl fmt::Display for BackupStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Backups: {} local snapshots", self.local_snapshot_count)?;
+        if !self.time_machine_configured {
+            return write!(f, " - Time Machine not configured");
+        }
+        match self.last_backup_at {
+            Some(last) => write!(f, " - last backup {}", last.format("%Y-%m-%d %H:%M UTC"))?,
+            None => write!(f, " - last backup unknown")?,
+        }
+        if self.backup_running {
+            write!(f, " - backup running ({})", self.backup_phase.as_deref().unwrap_or("starting"))?;
+            if let Some(progress) = self.backup_progress {
+                write!(f, " {:.0}%", progress)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MountEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            MountEventKind::Mounted => "mounted",
+            MountEventKind::Unmounted => "unmounted",
+        };
+        write!(
+            f,
+            "{} {} on {} ({}, {})",
+            self.device,
+            kind,
+            self.mount,
+            self.fs_type,
+            StorageMetrics::format_size(self.size)
+        )
+    }
+}
+
+impl fmt::Display for DirectoryScanReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} in {} files, {} directories ({} ms{})",
+            self.path,
+            StorageMetrics::format_size(self.total_bytes),
+            self.file_count,
+            self.dir_count,
+            self.duration_ms,
+            if self.truncated { ", truncated" } else { "" }
+        )?;
+        writeln!(f, "Largest directories:")?;
+        for entry in &self.largest_directories {
+            writeln!(f, "  {:>10}  {}", StorageMetrics::format_size(entry.size), entry.path)?;
+        }
+        writeln!(f, "Largest files:")?;
+        for entry in &self.largest_files {
+            writeln!(f, "  {:>10}  {}", StorageMetrics::format_size(entry.size), entry.path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::types::ClockSyncInfo;
+
+const NTP_PORT: u16 = 123;
+const NTP_PACKET_SIZE: usize = 48;
+const NTP_UNIX_EPOCH_DELTA: f64 = 2_208_988_800.0; // Seconds between 1900-01-01 and 1970-01-01
+
+/// Query an NTP server with a single SNTP request and compute the local clock offset
+pub fn query_clock_offset(server: &str, timeout: Duration) -> Result<ClockSyncInfo> {
+    let addrs = (server, NTP_PORT).to_socket_addrs()
+        .with_context(|| format!("Failed to resolve NTP server {}", server))?;
+
+    let mut last_error = None;
+    for addr in addrs {
+        match query_address(server, addr, timeout) {
+            Ok(info) => return Ok(info),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| anyhow!("NTP server {} has no addresses", server)))
+}
+
+/// Query one resolved address of `server`, from a socket of the same address family
+fn query_address(server: &str, addr: SocketAddr, timeout: Duration) -> Result<ClockSyncInfo> {
+    let local: SocketAddr = if addr.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" }.parse()?;
+    let socket = UdpSocket::bind(local).context("Failed to bind UDP socket for NTP query")?;
+    socket.connect(addr)
+        .with_context(|| format!("Failed to reach NTP server {} at {}", server, addr))?;
+
+    // LI = 0, VN = 4, Mode = 3 (client). The server echoes our transmit
+    // timestamp as the originate timestamp of its reply.
+    let mut request = [0u8; NTP_PACKET_SIZE];
+    request[0] = 0x23;
+    let originate = unix_now();
+    let transmitted = unix_to_ntp_timestamp(originate);
+    request[40..48].copy_from_slice(&transmitted);
+    socket.send(&request).context("Failed to send NTP request")?;
+
+    // Replies that are not a server's answer to this request are dropped
+    let deadline = Instant::now() + timeout;
+    let mut response = [0u8; NTP_PACKET_SIZE];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(anyhow!("No NTP response from {}", server));
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let len = socket.recv(&mut response)
+            .with_context(|| format!("No NTP response from {}", server))?;
+        if len < NTP_PACKET_SIZE {
+            return Err(anyhow!("Short NTP response ({} bytes)", len));
+        }
+        if is_reply_to(&response, &transmitted) {
+            break;
+        }
+    }
+    let destination = unix_now();
+
+    let stratum = response[1];
+    if stratum == 0 {
+        return Err(anyhow!("NTP server {} sent a kiss-of-death response", server));
+    }
+
+    let receive = ntp_timestamp_to_unix(&response[32..40]);
+    let transmit = ntp_timestamp_to_unix(&response[40..48]);
+    let (offset, delay) = compute_offset(originate, receive, transmit, destination);
+
+    Ok(ClockSyncInfo {
+        server: server.to_string(),
+        offset_ms: offset * 1000.0,
+        round_trip_ms: delay * 1000.0,
+        stratum,
+        checked_at: Utc::now(),
+    })
+}
+
+/// Whether `response` is a server-mode reply whose originate timestamp is our `transmitted` one
+fn is_reply_to(response: &[u8; NTP_PACKET_SIZE], transmitted: &[u8; 8]) -> bool {
+    const MODE_SERVER: u8 = 4;
+    response[0] & 0x07 == MODE_SERVER && response[24..32] == transmitted[..]
+}
+
+/// Standard SNTP offset and round-trip delay from the four exchange timestamps (seconds)
+fn compute_offset(originate: f64, receive: f64, transmit: f64, destination: f64) -> (f64, f64) {
+    let offset = ((receive - originate) + (transmit - destination)) / 2.0;
+    let delay = (destination - originate) - (transmit - receive);
+    (offset, delay)
+}
+
+fn ntp_timestamp_to_unix(bytes: &[u8]) -> f64 {
+    let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64;
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as f64;
+    seconds - NTP_UNIX_EPOCH_DELTA + fraction / 4_294_967_296.0
+}
+
+fn unix_to_ntp_timestamp(unix: f64) -> [u8; 8] {
+    let ntp = unix + NTP_UNIX_EPOCH_DELTA;
+    let seconds = ntp.trunc() as u32;
+    let fraction = (ntp.fract() * 4_294_967_296.0) as u32;
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+    bytes[4..].copy_from_slice(&fraction.to_be_bytes());
+    bytes
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_offset() {
+        // Local clock 50ms behind the server, 20ms symmetric network delay
+        let (offset, delay) = compute_offset(100.000, 100.060, 100.061, 100.021);
+        assert!((offset - 0.050).abs() < 1e-9);
+        assert!((delay - 0.020).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ntp_timestamp_to_unix() {
+        // 2208988800 seconds after the NTP epoch is the Unix epoch; 0x80000000 is half a second
+        let bytes = [0x83, 0xaa, 0x7e, 0x80, 0x80, 0x00, 0x00, 0x00];
+        assert!((ntp_timestamp_to_unix(&bytes) - 0.5).abs() < 1e-9);
+        assert_eq!(unix_to_ntp_timestamp(0.5), bytes);
+    }
+
+    #[test]
+    fn test_query_drops_unrelated_replies() {
+        // An IPv6-only server that first answers with a stale reply and a client-mode packet
+        let server = UdpSocket::bind("[::1]:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let responder = std::thread::spawn(move || {
+            let mut request = [0u8; NTP_PACKET_SIZE];
+            let (_, client) = server.recv_from(&mut request).unwrap();
+
+            let mut reply = [0u8; NTP_PACKET_SIZE];
+            reply[0] = 0x24; // VN = 4, Mode = 4 (server)
+            reply[1] = 2;
+            reply[24..32].copy_from_slice(&unix_to_ntp_timestamp(1.0));
+            server.send_to(&reply, client).unwrap();
+
+            reply[0] = 0x23;
+            reply[24..32].copy_from_slice(&request[40..48]);
+            server.send_to(&reply, client).unwrap();
+
+            reply[0] = 0x24;
+            let now = unix_to_ntp_timestamp(unix_now() + 5.0);
+            reply[32..40].copy_from_slice(&now);
+            reply[40..48].copy_from_slice(&now);
+            server.send_to(&reply, client).unwrap();
+        });
+
+        let info = query_address("localhost", addr, Duration::from_secs(5)).unwrap();
+        responder.join().unwrap();
+        assert_eq!(info.stratum, 2);
+        assert!((info.offset_ms - 5000.0).abs() < 500.0);
+
+        // Without a matching reply the query times out
+        let silent = UdpSocket::bind("[::1]:0").unwrap();
+        let err = query_address("localhost", silent.local_addr().unwrap(), Duration::from_millis(200)).unwrap_err();
+        assert!(err.to_string().contains("No NTP response"));
+    }
+}
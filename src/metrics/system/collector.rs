@@ -1,41 +1,78 @@
 use anyhow::Result;
 use chrono::Utc;
+use log::warn;
 use std::process::Command;
 use std::str;
 use std::time::Duration;
 
-use super::types::{SystemInfo, PlatformInfo, HardwareInfo, PeripheralDevice, DisplayInfo, PowerInfo, GpuInfo, UpdateTracker};
+use super::clock;
+use super::edid;
+use super::power;
+use super::types::{SystemInfo, PlatformInfo, HardwareInfo, PeripheralDevice, DisplayInfo, DisplayEvent, DisplayEventKind, PowerInfo, GpuInfo, UpdateTracker};
 
 const FULL_UPDATE_INTERVAL: Duration = Duration::from_secs(300); // 5 minutes
 const PERIPHERAL_CHECK_INTERVAL: Duration = Duration::from_secs(5); // 5 seconds
 const POWER_CHECK_INTERVAL: Duration = Duration::from_secs(30); // 30 seconds
 
+/// Configuration for the system collector's clock sync check
+#[derive(Debug, Clone)]
+pub struct SystemCollectorConfig {
+    /// NTP server used to measure clock offset; `None` disables the check
+    pub ntp_server: Option<String>,
+    pub ntp_timeout: Duration,
+    pub clock_check_interval: Duration,
+}
+
+impl Default for SystemCollectorConfig {
+    fn default() -> Self {
+        Self {
+            ntp_server: Some("time.apple.com".to_string()),
+            ntp_timeout: Duration::from_secs(2),
+            clock_check_interval: Duration::from_secs(300), // 5 minutes
+        }
+    }
+}
+
 pub struct SystemInfoCollector {
+    config: SystemCollectorConfig,
     last_info: Option<SystemInfo>,
+    force_full: bool,
 }
 
 impl SystemInfoCollector {
-    pub fn new() -> Self {
+    pub fn new(config: SystemCollectorConfig) -> Self {
         Self {
+            config,
             last_info: None,
+            force_full: false,
         }
     }
 
+    /// Collect full system info on the next call instead of only the changes
+    pub fn request_full_update(&mut self) {
+        self.force_full = true;
+    }
+
     pub fn collect(&mut self) -> Result<SystemInfo> {
         let now = Utc::now();
         let mut info = if let Some(last_info) = &self.last_info {
             // Check if we need a full update
-            if now.signed_duration_since(last_info.last_update.last_full_update) >= chrono::Duration::from_std(FULL_UPDATE_INTERVAL)? {
+            if self.force_full || now.signed_duration_since(last_info.last_update.last_full_update) >= chrono::Duration::from_std(FULL_UPDATE_INTERVAL)? {
                 self.collect_full_info()?
             } else {
-                last_info.clone()
+                let mut info = last_info.clone();
+                info.last_update.changed_fields.clear();
+                info
             }
         } else {
             self.collect_full_info()?
         };
+        self.force_full = false;
 
         // Update timestamps
         info.collected_at = now;
+        info.power_events.clear();
+        info.display_events.clear();
         
         // Check for peripheral changes if needed
         if now.signed_duration_since(info.last_update.last_peripheral_check) >= chrono::Duration::from_std(PERIPHERAL_CHECK_INTERVAL)? {
@@ -44,12 +81,25 @@ impl SystemInfoCollector {
                 info.last_update.changed_fields.push("peripherals".to_string());
                 info.peripherals = new_peripherals;
             }
+
+            let new_displays = self.collect_displays()?;
+            if info.displays != new_displays {
+                info.last_update.changed_fields.push("displays".to_string());
+                info.displays = new_displays;
+            }
             info.last_update.last_peripheral_check = now;
         }
 
+        // Diff against the previous collection so hotplugs picked up by a full refresh are reported too
+        if let Some(last_info) = &self.last_info {
+            info.display_events = display_changes(&last_info.displays, &info.displays);
+        }
+
         // Check power status if needed
         if now.signed_duration_since(info.last_update.last_power_check) >= chrono::Duration::from_std(POWER_CHECK_INTERVAL)? {
             let new_power = self.collect_power_info()?;
+            info.power_events.extend(power::ups_transitions(&info.power.ups, &new_power.ups));
+            info.power_events.extend(power::adapter_transition(info.power.adapter.as_ref(), new_power.adapter.as_ref()));
             if info.power != new_power {
                 info.last_update.changed_fields.push("power".to_string());
                 info.power = new_power;
@@ -57,6 +107,24 @@ impl SystemInfoCollector {
             info.last_update.last_power_check = now;
         }
 
+        // Measure clock offset against NTP if needed
+        if let Some(server) = &self.config.ntp_server {
+            let due = match info.last_update.last_clock_check {
+                Some(last) => now.signed_duration_since(last) >= chrono::Duration::from_std(self.config.clock_check_interval)?,
+                None => true,
+            };
+            if due {
+                match clock::query_clock_offset(server, self.config.ntp_timeout) {
+                    Ok(sync) => {
+                        info.clock = Some(sync);
+                        info.last_update.changed_fields.push("clock".to_string());
+                    }
+                    Err(e) => warn!("Clock offset check against {} failed: {}", server, e),
+                }
+                info.last_update.last_clock_check = Some(now);
+            }
+        }
+
         // Update dynamic platform info
         let new_platform = self.collect_platform_info()?;
         if info.platform.available_memory != new_platform.available_memory ||
@@ -79,11 +147,15 @@ impl SystemInfoCollector {
             hardware: self.collect_hardware_info()?,
             peripherals: self.collect_peripherals()?,
             displays: self.collect_displays()?,
+            display_events: Vec::new(),
             power: self.collect_power_info()?,
+            power_events: Vec::new(),
+            clock: self.last_info.as_ref().and_then(|info| info.clock.clone()),
             last_update: UpdateTracker {
                 last_full_update: Utc::now(),
                 last_peripheral_check: Utc::now(),
                 last_power_check: Utc::now(),
+                last_clock_check: self.last_info.as_ref().and_then(|info| info.last_update.last_clock_check),
                 changed_fields: vec!["full_update".to_string()],
             },
         })
@@ -294,6 +366,11 @@ impl SystemInfoCollector {
                     is_builtin: line.contains("Built-in"),
                     serial_number: None,
                     technology: String::new(),
+                    manufacturer: None,
+                    product_code: None,
+                    manufacture_year: None,
+                    width_mm: None,
+                    height_mm: None,
                 });
             } else if let Some(display) = &mut current_display {
                 if line.contains("Resolution:") {
@@ -324,6 +401,17 @@ impl SystemInfoCollector {
             displays.push(display);
         }
 
+        let edids = edid::collect_edids();
+        for display in &mut displays {
+            if let Some(info) = match_edid(display, &edids) {
+                display.manufacturer = Some(edid::manufacturer_name(&info.manufacturer_id));
+                display.product_code = Some(info.product_code);
+                display.manufacture_year = info.manufacture_year;
+                display.width_mm = info.width_mm;
+                display.height_mm = info.height_mm;
+            }
+        }
+
         Ok(displays)
     }
 
@@ -423,7 +511,8 @@ impl SystemInfoCollector {
                 } else {
                     "Unknown".to_string()
                 };
-            } else if line.contains("%") {
+            } else if line.contains("%") && line.contains("InternalBattery") {
+                // Other power sources listed here (UPS devices) are collected separately
                 power_info.battery_present = true;
                 if let Some(pct) = line.split('%').next() {
                     if let Ok(capacity) = pct.trim().parse::<u32>() {
@@ -465,6 +554,72 @@ impl SystemInfoCollector {
             }
         }
 
+        power_info.ups = power::collect_ups();
+        power_info.adapter = power::collect_adapter();
+        power_info.assertions = power::collect_assertions();
+        power_info.scheduled_events = power::collect_scheduled_events();
+
         Ok(power_info)
     }
-} 
\ No newline at end of file
+} 
+/// Pair a display from system_profiler with its EDID by serial number, then by name
+fn match_edid<'a>(display: &DisplayInfo, edids: &'a [edid::EdidInfo]) -> Option<&'a edid::EdidInfo> {
+    edids.iter()
+        .find(|e| e.serial_number.is_some() && e.serial_number == display.serial_number)
+        .or_else(|| edids.iter().find(|e| {
+            e.name.as_deref().is_some_and(|name| name.eq_ignore_ascii_case(&display.name))
+        }))
+}
+
+/// Displays are identified by name and serial number; mode changes are not hotplugs
+fn display_changes(previous: &[DisplayInfo], current: &[DisplayInfo]) -> Vec<DisplayEvent> {
+    let same = |a: &DisplayInfo, b: &DisplayInfo| a.name == b.name && a.serial_number == b.serial_number;
+    let now = Utc::now();
+
+    let connected = current.iter()
+        .filter(|display| !previous.iter().any(|p| same(p, display)))
+        .map(|display| DisplayEvent { kind: DisplayEventKind::Connected, display: display.clone(), timestamp: now });
+    let disconnected = previous.iter()
+        .filter(|display| !current.iter().any(|c| same(c, display)))
+        .map(|display| DisplayEvent { kind: DisplayEventKind::Disconnected, display: display.clone(), timestamp: now });
+
+    connected.chain(disconnected).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(name: &str, resolution: (u32, u32)) -> DisplayInfo {
+        DisplayInfo {
+            name: name.to_string(),
+            resolution,
+            refresh_rate: 60.0,
+            is_builtin: false,
+            serial_number: None,
+            technology: String::new(),
+            manufacturer: None,
+            product_code: None,
+            manufacture_year: None,
+            width_mm: None,
+            height_mm: None,
+        }
+    }
+
+    #[test]
+    fn test_display_changes() {
+        let builtin = display("Color LCD", (3024, 1964));
+        let external = display("LG UltraFine", (3840, 2160));
+
+        let events = display_changes(std::slice::from_ref(&builtin), &[builtin.clone(), external.clone()]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, DisplayEventKind::Connected);
+        assert_eq!(events[0].display.name, "LG UltraFine");
+
+        let events = display_changes(&[builtin.clone(), external], std::slice::from_ref(&builtin));
+        assert_eq!(events[0].kind, DisplayEventKind::Disconnected);
+
+        // A resolution change is not a hotplug
+        assert!(display_changes(std::slice::from_ref(&builtin), &[display("Color LCD", (1512, 982))]).is_empty());
+    }
+}
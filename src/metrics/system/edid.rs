@@ -0,0 +1,177 @@
+use std::fs;
+use std::process::Command;
+
+/// Fields decoded from a display's EDID base block
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdidInfo {
+    /// Three-letter PNP manufacturer ID, e.g. `GSM` (LG) or `DEL` (Dell)
+    pub manufacturer_id: String,
+    pub product_code: u16,
+    pub name: Option<String>,
+    pub serial_number: Option<String>,
+    pub manufacture_year: Option<u16>,
+    pub width_mm: Option<u32>,
+    pub height_mm: Option<u32>,
+}
+
+/// EDID blocks of all connected displays, from ioreg on macOS or DRM connectors on Linux
+pub fn collect_edids() -> Vec<EdidInfo> {
+    if let Ok(connectors) = fs::read_dir("/sys/class/drm") {
+        return connectors
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| fs::read(entry.path().join("edid")).ok())
+            .filter_map(|bytes| parse_edid(&bytes))
+            .collect();
+    }
+
+    // Intel Macs publish `IODisplayEDID`, Apple silicon publishes `EDID` on the framebuffer
+    match Command::new("ioreg").args(["-l", "-w0", "-r", "-d1", "-c", "IOMobileFramebuffer"]).output() {
+        Ok(output) => {
+            let mut edids = parse_ioreg_edids(&String::from_utf8_lossy(&output.stdout));
+            if edids.is_empty() {
+                if let Ok(output) = Command::new("ioreg").args(["-l", "-w0", "-r", "-c", "IODisplayConnect"]).output() {
+                    edids = parse_ioreg_edids(&String::from_utf8_lossy(&output.stdout));
+                }
+            }
+            edids
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Find `"EDID" = <00ffffffffffff00...>` or `"IODisplayEDID" = <...>` properties
+fn parse_ioreg_edids(output: &str) -> Vec<EdidInfo> {
+    output.lines()
+        .filter(|line| line.contains("\"EDID\" = <") || line.contains("\"IODisplayEDID\" = <"))
+        .filter_map(|line| {
+            let (_, hex) = line.split_once("= <")?;
+            let hex = hex.split('>').next()?;
+            parse_edid(&decode_hex(hex)?)
+        })
+        .collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Decode the 128-byte EDID base block (VESA E-EDID 1.3/1.4)
+pub fn parse_edid(bytes: &[u8]) -> Option<EdidInfo> {
+    const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+    if bytes.len() < 128 || bytes[..8] != HEADER {
+        return None;
+    }
+
+    // Manufacturer ID is three 5-bit letters, big-endian, 1 = 'A'
+    let id = u16::from_be_bytes([bytes[8], bytes[9]]);
+    let manufacturer_id: String = [10, 5, 0]
+        .iter()
+        .map(|shift| (b'A' - 1 + ((id >> shift) & 0x1f) as u8) as char)
+        .collect();
+
+    // Year 0 with week 0xff would be a model year, still useful; 0 means unspecified
+    let manufacture_year = (bytes[17] != 0).then(|| 1990 + bytes[17] as u16);
+    let width_mm = (bytes[21] != 0).then(|| bytes[21] as u32 * 10);
+    let height_mm = (bytes[22] != 0).then(|| bytes[22] as u32 * 10);
+
+    let mut name = None;
+    let mut serial_number = None;
+    for offset in [54, 72, 90, 108] {
+        let descriptor = &bytes[offset..offset + 18];
+        // Display descriptors start with a zero pixel clock
+        if descriptor[0] != 0 || descriptor[1] != 0 {
+            continue;
+        }
+        let text = descriptor_text(&descriptor[5..]);
+        match descriptor[3] {
+            0xfc => name = text,
+            0xff => serial_number = text,
+            _ => {}
+        }
+    }
+
+    Some(EdidInfo {
+        manufacturer_id,
+        product_code: u16::from_le_bytes([bytes[10], bytes[11]]),
+        name,
+        serial_number,
+        manufacture_year,
+        width_mm,
+        height_mm,
+    })
+}
+
+fn descriptor_text(data: &[u8]) -> Option<String> {
+    let end = data.iter().position(|&b| b == 0x0a).unwrap_or(data.len());
+    let text = String::from_utf8_lossy(&data[..end]).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Vendor name for common PNP IDs, falling back to the raw ID
+pub fn manufacturer_name(id: &str) -> String {
+    match id {
+        "APP" => "Apple",
+        "GSM" => "LG",
+        "DEL" => "Dell",
+        "SAM" | "SEC" => "Samsung",
+        "ACI" | "AUS" => "ASUS",
+        "BNQ" => "BenQ",
+        "HWP" | "HPN" => "HP",
+        "LEN" => "Lenovo",
+        "PHL" => "Philips",
+        "ACR" => "Acer",
+        "VSC" => "ViewSonic",
+        "EIZ" | "ENC" => "EIZO",
+        "NEC" => "NEC",
+        "SNY" => "Sony",
+        "AOC" => "AOC",
+        _ => id,
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_edid() -> Vec<u8> {
+        let mut edid = vec![0u8; 128];
+        edid[..8].copy_from_slice(&[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00]);
+        // "GSM" = 00111 10011 01101
+        edid[8] = 0x1e;
+        edid[9] = 0x6d;
+        edid[10] = 0x7f;
+        edid[11] = 0x5b;
+        edid[17] = 31; // 2021
+        edid[21] = 60;
+        edid[22] = 34;
+        // Monitor name descriptor
+        edid[54 + 3] = 0xfc;
+        edid[54 + 5..54 + 18].copy_from_slice(b"LG UltraFine\n");
+        edid
+    }
+
+    #[test]
+    fn test_parse_edid() {
+        let info = parse_edid(&sample_edid()).unwrap();
+        assert_eq!(info.manufacturer_id, "GSM");
+        assert_eq!(manufacturer_name(&info.manufacturer_id), "LG");
+        assert_eq!(info.product_code, 0x5b7f);
+        assert_eq!(info.manufacture_year, Some(2021));
+        assert_eq!(info.width_mm, Some(600));
+        assert_eq!(info.height_mm, Some(340));
+        assert_eq!(info.name.as_deref(), Some("LG UltraFine"));
+        assert_eq!(info.serial_number, None);
+
+        let hex: String = sample_edid().iter().map(|b| format!("{:02x}", b)).collect();
+        let ioreg = format!("    | \"EDID\" = <{}>\n", hex);
+        assert_eq!(parse_ioreg_edids(&ioreg), vec![info]);
+        assert!(parse_edid(&[0u8; 64]).is_none());
+    }
+}
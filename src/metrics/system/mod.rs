@@ -1,5 +1,8 @@
 pub mod collector;
 pub mod types;
+mod clock;
+mod edid;
+mod power;
 
-pub use collector::SystemInfoCollector;
+pub use collector::{SystemCollectorConfig, SystemInfoCollector};
  
\ No newline at end of file
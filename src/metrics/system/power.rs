@@ -0,0 +1,367 @@
+use chrono::Utc;
+use std::process::Command;
+
+use super::types::{AdapterInfo, PowerAssertion, PowerEvent, PowerEventKind, ScheduledPowerEvent, UpsInfo};
+
+/// Assertion types that keep the whole system awake (display-only assertions do not)
+const SLEEP_PREVENTING_ASSERTIONS: &[&str] = &[
+    "PreventUserIdleSystemSleep",
+    "PreventSystemSleep",
+    "NoIdleSleepAssertion",
+];
+
+/// Per-process power assertions from `pmset -g assertions`
+pub fn collect_assertions() -> Vec<PowerAssertion> {
+    match Command::new("pmset").args(["-g", "assertions"]).output() {
+        Ok(output) => parse_assertions(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Scheduled and repeating sleep/wake events from `pmset -g sched`
+pub fn collect_scheduled_events() -> Vec<ScheduledPowerEvent> {
+    match Command::new("pmset").args(["-g", "sched"]).output() {
+        Ok(output) => parse_scheduled_events(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// UPS devices reported through IOPowerSources (`pmset -g ps`), excluding internal batteries
+pub fn collect_ups() -> Vec<UpsInfo> {
+    match Command::new("pmset").args(["-g", "ps"]).output() {
+        Ok(output) => parse_ups(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The connected power adapter, with current system draw from `AppleSmartBattery`
+/// used to flag adapters too small to run the machine
+pub fn collect_adapter() -> Option<AdapterInfo> {
+    let output = Command::new("pmset").args(["-g", "adapter"]).output().ok()?;
+    let mut adapter = parse_adapter(&String::from_utf8_lossy(&output.stdout))?;
+
+    adapter.system_power_watts = Command::new("ioreg")
+        .args(["-rn", "AppleSmartBattery"])
+        .output()
+        .ok()
+        .and_then(|output| parse_system_power_in(&String::from_utf8_lossy(&output.stdout)));
+    adapter.undersized = match (adapter.watts, adapter.system_power_watts) {
+        (Some(watts), Some(draw)) => draw > watts as f64,
+        _ => false,
+    };
+
+    Some(adapter)
+}
+
+/// Emit an event when the adapter is plugged in, unplugged or swapped for a different one
+pub fn adapter_transition(previous: Option<&AdapterInfo>, current: Option<&AdapterInfo>) -> Option<PowerEvent> {
+    let (kind, adapter) = match (previous, current) {
+        (None, Some(current)) => (PowerEventKind::AdapterConnected, current),
+        (Some(previous), None) => (PowerEventKind::AdapterDisconnected, previous),
+        (Some(previous), Some(current))
+            if previous.adapter_id != current.adapter_id
+                || previous.serial_number != current.serial_number
+                || previous.watts != current.watts => (PowerEventKind::AdapterChanged, current),
+        _ => return None,
+    };
+
+    Some(PowerEvent {
+        kind,
+        source: adapter.name.clone()
+            .or_else(|| adapter.adapter_id.clone())
+            .unwrap_or_else(|| "AC adapter".to_string()),
+        detail: match adapter.watts {
+            Some(watts) => format!("{}W", watts),
+            None => "unknown wattage".to_string(),
+        },
+        timestamp: Utc::now(),
+    })
+}
+
+/// Emit events for UPS devices that switched between line and battery power
+pub fn ups_transitions(previous: &[UpsInfo], current: &[UpsInfo]) -> Vec<PowerEvent> {
+    current.iter()
+        .filter_map(|ups| {
+            let before = previous.iter().find(|p| p.name == ups.name)?;
+            if before.on_battery == ups.on_battery {
+                return None;
+            }
+            let charge = ups.charge_percent.map_or("unknown".to_string(), |c| format!("{}%", c));
+            Some(PowerEvent {
+                kind: if ups.on_battery { PowerEventKind::UpsOnBattery } else { PowerEventKind::UpsOnline },
+                source: ups.name.clone(),
+                detail: match ups.runtime_minutes {
+                    Some(runtime) => format!("charge {}, {} minutes remaining", charge, runtime),
+                    None => format!("charge {}", charge),
+                },
+                timestamp: Utc::now(),
+            })
+        })
+        .collect()
+}
+
+/// Power source lines look like `-CP1500PFCLCD (id=5678) 100%; AC attached; not charging present: true`
+fn parse_ups(output: &str) -> Vec<UpsInfo> {
+    output.lines()
+        .filter_map(|line| {
+            let entry = line.trim().strip_prefix('-')?;
+            if entry.starts_with("InternalBattery") {
+                return None;
+            }
+
+            let (name, rest) = entry.split_once(" (id=")?;
+            let (id, status) = rest.split_once(')')?;
+            let fields: Vec<&str> = status.split(';').map(str::trim).collect();
+
+            let charge_percent = fields.first()
+                .and_then(|f| f.trim_end_matches('%').parse::<u32>().ok());
+            let state = fields.get(1).copied().unwrap_or("unknown").to_string();
+            let runtime_minutes = fields.get(2)
+                .and_then(|f| f.split_whitespace().next())
+                .and_then(|time| {
+                    let (h, m) = time.split_once(':')?;
+                    Some(h.parse::<u32>().ok()? * 60 + m.parse::<u32>().ok()?)
+                });
+
+            Some(UpsInfo {
+                name: name.trim().to_string(),
+                id: id.to_string(),
+                charge_percent,
+                runtime_minutes,
+                on_battery: state == "discharging",
+                state,
+            })
+        })
+        .collect()
+}
+
+/// `pmset -g adapter` prints `Key = value` lines, or "No adapter attached."
+fn parse_adapter(output: &str) -> Option<AdapterInfo> {
+    let mut adapter = AdapterInfo {
+        adapter_id: None,
+        name: None,
+        manufacturer: None,
+        serial_number: None,
+        watts: None,
+        system_power_watts: None,
+        undersized: false,
+    };
+    let mut found = false;
+
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        found = true;
+        match key.trim() {
+            "AdapterID" => adapter.adapter_id = Some(value),
+            "Name" => adapter.name = Some(value),
+            "Manufacturer" => adapter.manufacturer = Some(value),
+            "SerialString" => adapter.serial_number = Some(value),
+            "Watts" => adapter.watts = value.trim_end_matches('W').parse().ok(),
+            _ => {}
+        }
+    }
+
+    found.then_some(adapter)
+}
+
+/// `SystemPowerIn` (milliwatts) lives in the `PowerTelemetryData` dictionary on Apple silicon
+fn parse_system_power_in(output: &str) -> Option<f64> {
+    let (_, rest) = output.split_once("\"SystemPowerIn\"=")?;
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse::<u64>().ok().map(|mw| mw as f64 / 1000.0)
+}
+
+/// Parse the "Listed by owning process" section, whose entries look like
+/// `pid 456(caffeinate): [0x00001a2b00018c41] 01:02:03 PreventUserIdleSystemSleep named: "caffeinate command-line tool"`
+fn parse_assertions(output: &str) -> Vec<PowerAssertion> {
+    let mut assertions = Vec::new();
+    let mut in_process_section = false;
+
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("Listed by owning process") {
+            in_process_section = true;
+            continue;
+        }
+        if !in_process_section {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) && !trimmed.is_empty() {
+            // Next top-level section (e.g. "Kernel Assertions")
+            break;
+        }
+        if let Some(assertion) = parse_assertion_line(trimmed) {
+            assertions.push(assertion);
+        }
+    }
+
+    assertions
+}
+
+fn parse_assertion_line(line: &str) -> Option<PowerAssertion> {
+    let rest = line.strip_prefix("pid ")?;
+    let (pid, rest) = rest.split_once('(')?;
+    let (process, rest) = rest.split_once("): ")?;
+    let (_, rest) = rest.split_once("] ")?;
+
+    let mut parts = rest.splitn(3, ' ');
+    let age = parts.next()?;
+    let assertion_type = parts.next()?.to_string();
+    let name = parts.next()
+        .and_then(|named| named.trim().strip_prefix("named: "))
+        .map(|name| name.trim().trim_matches('"').to_string())
+        .unwrap_or_default();
+
+    Some(PowerAssertion {
+        pid: pid.trim().parse().ok()?,
+        process: process.to_string(),
+        prevents_sleep: SLEEP_PREVENTING_ASSERTIONS.contains(&assertion_type.as_str()),
+        assertion_type,
+        name,
+        age_secs: parse_hms(age).unwrap_or(0),
+    })
+}
+
+fn parse_hms(value: &str) -> Option<u64> {
+    value.split(':')
+        .try_fold(0u64, |acc, part| Some(acc * 60 + part.parse::<u64>().ok()?))
+}
+
+fn parse_scheduled_events(output: &str) -> Vec<ScheduledPowerEvent> {
+    let mut events = Vec::new();
+    let mut repeating = false;
+
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("Repeating power events") {
+            repeating = true;
+            continue;
+        }
+        if trimmed.starts_with("Scheduled power events") {
+            repeating = false;
+            continue;
+        }
+
+        // Scheduled entries are prefixed with an index: " [0]  wake at 05/02/2024 08:00:00 by 'owner'"
+        let entry = match trimmed.strip_prefix('[') {
+            Some(rest) => rest.split_once(']').map(|(_, entry)| entry.trim()).unwrap_or(trimmed),
+            None => trimmed,
+        };
+        let Some((event_type, rest)) = entry.split_once(" at ") else {
+            continue;
+        };
+
+        let (time, owner) = match rest.split_once(" by ") {
+            Some((time, owner)) => (time, Some(owner.trim_matches('\'').to_string())),
+            None => (rest, None),
+        };
+
+        events.push(ScheduledPowerEvent {
+            event_type: event_type.trim().to_string(),
+            time: time.trim().to_string(),
+            repeating,
+            owner,
+        });
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_assertions() {
+        let output = "2024-05-01 10:00:00 +0200
+Assertion status system-wide:
+   PreventUserIdleDisplaySleep    0
+   PreventUserIdleSystemSleep     1
+Listed by owning process:
+   pid 123(coreaudiod): [0x0000a1b200019c3f] 00:10:05 PreventUserIdleSystemSleep named: \"com.apple.audio.context\"
+\tTimeout will fire in 3600 secs Action=TimeoutActionRelease
+   pid 456(Google Chrome): [0x0000a1b200019c40] 01:00:00 PreventUserIdleDisplaySleep named: \"Video Wake Lock\"
+Kernel Assertions: 0x4=USB
+   id=500  level=255 0x4=USB description=com.apple.usb.externaldevice
+";
+        let assertions = parse_assertions(output);
+        assert_eq!(assertions.len(), 2);
+        assert_eq!(assertions[0].pid, 123);
+        assert_eq!(assertions[0].process, "coreaudiod");
+        assert_eq!(assertions[0].age_secs, 605);
+        assert!(assertions[0].prevents_sleep);
+        assert_eq!(assertions[1].process, "Google Chrome");
+        assert_eq!(assertions[1].name, "Video Wake Lock");
+        assert!(!assertions[1].prevents_sleep);
+    }
+
+    #[test]
+    fn test_parse_ups_and_transitions() {
+        let online = "Now drawing from 'AC Power'
+ -InternalBattery-0 (id=4653155)\t95%; charging; 0:45 remaining present: true
+ -CP1500PFCLCD (id=5678)\t100%; AC attached; not charging present: true
+";
+        let outage = "Now drawing from 'UPS Power'
+ -CP1500PFCLCD (id=5678)\t87%; discharging; 0:32 remaining present: true
+";
+        let before = parse_ups(online);
+        assert_eq!(before.len(), 1);
+        assert_eq!(before[0].name, "CP1500PFCLCD");
+        assert!(!before[0].on_battery);
+
+        let after = parse_ups(outage);
+        assert_eq!(after[0].charge_percent, Some(87));
+        assert_eq!(after[0].runtime_minutes, Some(32));
+        assert!(after[0].on_battery);
+
+        let events = ups_transitions(&before, &after);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, PowerEventKind::UpsOnBattery);
+        assert!(ups_transitions(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn test_parse_adapter_and_transitions() {
+        let output = " AdapterID = 0x7001
+ Watts = 30W
+ FamilyCode = 0xe000400a
+ Name = 30W USB-C Power Adapter
+ Manufacturer = Apple Inc.
+ SerialString = C4H1234ABCD
+";
+        let adapter = parse_adapter(output).unwrap();
+        assert_eq!(adapter.watts, Some(30));
+        assert_eq!(adapter.name.as_deref(), Some("30W USB-C Power Adapter"));
+        assert!(parse_adapter("No adapter attached.\n").is_none());
+
+        let ioreg = r#"  "PowerTelemetryData" = {"SystemLoad"=3241,"SystemPowerIn"=42150,"BatteryPower"=0}"#;
+        assert_eq!(parse_system_power_in(ioreg), Some(42.15));
+
+        let connected = adapter_transition(None, Some(&adapter)).unwrap();
+        assert_eq!(connected.kind, PowerEventKind::AdapterConnected);
+        assert_eq!(adapter_transition(Some(&adapter), None).unwrap().kind, PowerEventKind::AdapterDisconnected);
+        assert!(adapter_transition(Some(&adapter), Some(&adapter)).is_none());
+
+        let larger = AdapterInfo { watts: Some(96), ..adapter.clone() };
+        assert_eq!(adapter_transition(Some(&adapter), Some(&larger)).unwrap().kind, PowerEventKind::AdapterChanged);
+    }
+
+    #[test]
+    fn test_parse_scheduled_events() {
+        let output = "Repeating power events:
+  wakepoweron at 8:00AM weekdays only
+Scheduled power events:
+ [0]  wake at 05/02/2024 08:00:00 by 'com.apple.alarm.user-visible-Weekly Wake'
+";
+        let events = parse_scheduled_events(output);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_type, "wakepoweron");
+        assert!(events[0].repeating);
+        assert_eq!(events[1].event_type, "wake");
+        assert_eq!(events[1].time, "05/02/2024 08:00:00");
+        assert_eq!(events[1].owner.as_deref(), Some("com.apple.alarm.user-visible-Weekly Wake"));
+    }
+}
@@ -10,7 +10,12 @@ pub struct SystemInfo {
     pub hardware: HardwareInfo,
     pub peripherals: Vec<PeripheralDevice>,
     pub displays: Vec<DisplayInfo>,
+    /// Displays connected or disconnected since the previous collection
+    pub display_events: Vec<DisplayEvent>,
     pub power: PowerInfo,
+    /// Power transitions detected during this collection
+    pub power_events: Vec<PowerEvent>,
+    pub clock: Option<ClockSyncInfo>,
     #[serde(skip)]
     pub last_update: UpdateTracker,
 }
@@ -20,6 +25,7 @@ pub struct UpdateTracker {
     pub last_full_update: DateTime<Utc>,
     pub last_peripheral_check: DateTime<Utc>,
     pub last_power_check: DateTime<Utc>,
+    pub last_clock_check: Option<DateTime<Utc>>,
     pub changed_fields: Vec<String>,
 }
 
@@ -79,6 +85,26 @@ pub struct DisplayInfo {
     pub is_builtin: bool,
     pub serial_number: Option<String>,
     pub technology: String,
+    /// Manufacturer name decoded from EDID
+    pub manufacturer: Option<String>,
+    pub product_code: Option<u16>,
+    pub manufacture_year: Option<u16>,
+    pub width_mm: Option<u32>,
+    pub height_mm: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayEventKind {
+    Connected,
+    Disconnected,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DisplayEvent {
+    pub kind: DisplayEventKind,
+    pub display: DisplayInfo,
+    pub timestamp: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -90,6 +116,98 @@ pub struct PowerInfo {
     pub battery_health: Option<String>,
     pub time_remaining: Option<u32>,
     pub charging: bool,
+    pub assertions: Vec<PowerAssertion>,
+    pub scheduled_events: Vec<ScheduledPowerEvent>,
+    pub ups: Vec<UpsInfo>,
+    pub adapter: Option<AdapterInfo>,
+}
+
+/// The connected AC power adapter (`pmset -g adapter`)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AdapterInfo {
+    pub adapter_id: Option<String>,
+    pub name: Option<String>,
+    pub manufacturer: Option<String>,
+    pub serial_number: Option<String>,
+    pub watts: Option<u32>,
+    /// Current system power draw from the battery controller, if available
+    pub system_power_watts: Option<f64>,
+    /// Adapter cannot cover the current system draw
+    pub undersized: bool,
+}
+
+/// An uninterruptible power supply attached over USB/HID
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UpsInfo {
+    pub name: String,
+    pub id: String,
+    pub charge_percent: Option<u32>,
+    pub runtime_minutes: Option<u32>,
+    pub on_battery: bool,
+    /// State as reported by IOPowerSources, e.g. `AC attached` or `discharging`
+    pub state: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerEventKind {
+    UpsOnBattery,
+    UpsOnline,
+    AdapterConnected,
+    AdapterDisconnected,
+    AdapterChanged,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PowerEvent {
+    pub kind: PowerEventKind,
+    /// Device the event relates to
+    pub source: String,
+    pub detail: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A process-held power assertion (`pmset -g assertions`)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PowerAssertion {
+    pub pid: u32,
+    pub process: String,
+    pub assertion_type: String,
+    pub name: String,
+    /// Whether this assertion keeps the system from idle sleeping
+    pub prevents_sleep: bool,
+    pub age_secs: u64,
+}
+
+// The age ticks up on every check, so it is left out of change detection
+impl PartialEq for PowerAssertion {
+    fn eq(&self, other: &Self) -> bool {
+        self.pid == other.pid
+            && self.process == other.process
+            && self.assertion_type == other.assertion_type
+            && self.name == other.name
+    }
+}
+
+/// A scheduled or repeating sleep/wake event (`pmset -g sched`)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ScheduledPowerEvent {
+    pub event_type: String,
+    /// As printed by pmset, e.g. `05/02/2024 08:00:00` or `8:00AM weekdays only`
+    pub time: String,
+    pub repeating: bool,
+    pub owner: Option<String>,
+}
+
+/// Local clock offset measured against an NTP server
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ClockSyncInfo {
+    pub server: String,
+    /// Positive when the local clock is behind the server
+    pub offset_ms: f64,
+    pub round_trip_ms: f64,
+    pub stratum: u8,
+    pub checked_at: DateTime<Utc>,
 }
 
 impl SystemInfo {
@@ -104,11 +222,15 @@ impl SystemInfo {
             hardware: HardwareInfo::default(),
             peripherals: Vec::new(),
             displays: Vec::new(),
+            display_events: Vec::new(),
             power: PowerInfo::default(),
+            power_events: Vec::new(),
+            clock: None,
             last_update: UpdateTracker {
                 last_full_update: Utc::now(),
                 last_peripheral_check: Utc::now(),
                 last_power_check: Utc::now(),
+                last_clock_check: None,
                 changed_fields: Vec::new(),
             },
         }
@@ -177,6 +299,10 @@ impl Default for PowerInfo {
             battery_health: None,
             time_remaining: None,
             charging: false,
+            assertions: Vec::new(),
+            scheduled_events: Vec::new(),
+            ups: Vec::new(),
+            adapter: None,
         }
     }
 }
@@ -228,6 +354,9 @@ impl std::fmt::Display for SystemInfo {
                     display.resolution.0,
                     display.resolution.1,
                     display.refresh_rate)?;
+                if let (Some(manufacturer), Some(year)) = (&display.manufacturer, display.manufacture_year) {
+                    writeln!(f, "    {} ({})", manufacturer, year)?;
+                }
             }
         }
 
@@ -244,6 +373,32 @@ impl std::fmt::Display for SystemInfo {
                 writeln!(f, "  Time Remaining: {} minutes", time)?;
             }
         }
+        for ups in &self.power.ups {
+            write!(f, "  UPS: {} - {}", ups.name, ups.state)?;
+            if let Some(charge) = ups.charge_percent {
+                write!(f, ", {}%", charge)?;
+            }
+            if let Some(runtime) = ups.runtime_minutes {
+                write!(f, ", {} minutes remaining", runtime)?;
+            }
+            writeln!(f)?;
+        }
+        for assertion in self.power.assertions.iter().filter(|a| a.prevents_sleep) {
+            writeln!(f, "  Sleep blocked by: {} (pid {}) - {}", assertion.process, assertion.pid, assertion.name)?;
+        }
+        for event in &self.power.scheduled_events {
+            writeln!(f, "  Scheduled: {} at {}{}", event.event_type, event.time,
+                if event.repeating { " (repeating)" } else { "" })?;
+        }
+
+        if let Some(clock) = &self.clock {
+            writeln!(f, "\nClock:")?;
+            writeln!(f, "  Offset: {:+.1} ms vs {} (stratum {}, RTT {:.1} ms)",
+                clock.offset_ms,
+                clock.server,
+                clock.stratum,
+                clock.round_trip_ms)?;
+        }
 
         if !self.peripherals.is_empty() {
             writeln!(f, "\nPeripherals:")?;
@@ -258,4 +413,31 @@ impl std::fmt::Display for SystemInfo {
 
         Ok(())
     }
-} 
\ No newline at end of file
+} 
+
+impl std::fmt::Display for DisplayEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            DisplayEventKind::Connected => "connected",
+            DisplayEventKind::Disconnected => "disconnected",
+        };
+        write!(f, "Display {} {}", self.display.name, kind)?;
+        if self.kind == DisplayEventKind::Connected {
+            write!(f, " ({}x{} @ {}Hz)", self.display.resolution.0, self.display.resolution.1, self.display.refresh_rate)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for PowerEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            PowerEventKind::UpsOnBattery => "UPS switched to battery",
+            PowerEventKind::UpsOnline => "UPS back on line power",
+            PowerEventKind::AdapterConnected => "power adapter connected",
+            PowerEventKind::AdapterDisconnected => "power adapter disconnected",
+            PowerEventKind::AdapterChanged => "power adapter changed",
+        };
+        write!(f, "{}: {} ({})", self.source, kind, self.detail)
+    }
+}
@@ -0,0 +1,119 @@
+// src/networking/bandwidth.rs
+//
+// File transfer bandwidth limits
+// Bulk transfers can fill the link and starve the gRPC control plane and the
+// metrics uploads. Each direction gets a token bucket per transfer, shared by
+// that transfer's parallel streams, and one shared by all transfers; a stream
+// waits on both after every chunk.
+
+use crate::updater::{RateLimit, Throttle};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::sync::Mutex;
+
+type Bucket = Arc<Mutex<Throttle>>;
+
+/// Bandwidth caps for one direction (sending or receiving)
+#[derive(Clone, Default)]
+pub struct Throttles {
+    per_transfer: Option<RateLimit>,
+    total: Option<Bucket>,
+    /// Buckets of the transfers in flight, keyed by file ID
+    transfers: Arc<StdMutex<HashMap<String, Bucket>>>,
+}
+
+impl Throttles {
+    pub fn new(per_transfer: Option<RateLimit>, total: Option<RateLimit>) -> Self {
+        Self {
+            per_transfer,
+            total: total.map(|limit| Arc::new(Mutex::new(Throttle::new(limit)))),
+            transfers: Arc::default(),
+        }
+    }
+
+    /// Buckets for one stream of transfer `file_id`
+    pub fn stream(&self, file_id: &str) -> StreamThrottle {
+        let transfer = self.per_transfer.map(|limit| {
+            let mut transfers = self.transfers.lock().unwrap();
+            transfers
+                .entry(file_id.to_string())
+                .or_insert_with(|| Arc::new(Mutex::new(Throttle::new(limit))))
+                .clone()
+        });
+        StreamThrottle {
+            file_id: file_id.to_string(),
+            transfer,
+            total: self.total.clone(),
+            transfers: self.transfers.clone(),
+        }
+    }
+}
+
+/// The buckets one stream waits on; the transfer's bucket is dropped with its last stream
+pub struct StreamThrottle {
+    file_id: String,
+    transfer: Option<Bucket>,
+    total: Option<Bucket>,
+    transfers: Arc<StdMutex<HashMap<String, Bucket>>>,
+}
+
+impl StreamThrottle {
+    /// Wait as long as both caps require after moving `bytes`
+    pub async fn consume(&self, bytes: usize) {
+        for bucket in [&self.transfer, &self.total].into_iter().flatten() {
+            bucket.lock().await.consume(bytes).await;
+        }
+    }
+}
+
+impl Drop for StreamThrottle {
+    fn drop(&mut self) {
+        if let Some(transfer) = self.transfer.take() {
+            let mut transfers = self.transfers.lock().unwrap();
+            // Only the map and this stream still hold it
+            if Arc::strong_count(&transfer) == 2 {
+                transfers.remove(&self.file_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_streams_share_transfer_bucket() {
+        // 100 KiB/s per transfer with a 100 KiB burst, no total cap
+        let throttles = Throttles::new(RateLimit::from_kib(100, None), None);
+        let a = throttles.stream("t1");
+        let b = throttles.stream("t1");
+        let other = throttles.stream("t2");
+        assert_eq!(throttles.transfers.lock().unwrap().len(), 2);
+
+        // Two streams of one transfer drain the same burst...
+        let start = Instant::now();
+        a.consume(100 * 1024).await;
+        other.consume(100 * 1024).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+        b.consume(25 * 1024).await;
+        assert!(start.elapsed() >= Duration::from_millis(240));
+
+        // ...and the bucket goes away with the transfer's last stream
+        drop(a);
+        assert_eq!(throttles.transfers.lock().unwrap().len(), 2);
+        drop(b);
+        assert_eq!(throttles.transfers.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_total_cap_spans_transfers() {
+        let throttles = Throttles::new(None, RateLimit::from_kib(100, None));
+        let start = Instant::now();
+        throttles.stream("t1").consume(100 * 1024).await;
+        throttles.stream("t2").consume(25 * 1024).await;
+        assert!(start.elapsed() >= Duration::from_millis(240));
+        assert!(throttles.transfers.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+// src/networking/benchmark.rs
+//
+// Link benchmarks
+// A benchmark runs over the file transfer protocol between two nodes without
+// touching either disk: the sender first measures round trips with one-byte
+// pings echoed by the receiver, then streams bytes from memory over 1, 2, 4
+// ... parallel connections, which the receiver reads and discards. The MB/s
+// reached with each stream count show whether a link, say a new Thunderbolt
+// cable, delivers what it should before real transfers go over it.
+
+use serde::Serialize;
+use std::time::Duration;
+
+/// Pings sent before the throughput runs
+pub const DEFAULT_PINGS: u32 = 20;
+/// Stream counts the throughput is measured with
+pub const DEFAULT_STREAM_COUNTS: [usize; 4] = [1, 2, 4, 8];
+/// Bytes sent in each run, split over its streams
+pub const DEFAULT_RUN_BYTES: u64 = 256 * 1024 * 1024;
+/// Most bytes a receiver reads from one benchmark stream
+pub const MAX_STREAM_BYTES: u64 = 64 * 1024 * 1024 * 1024;
+
+/// What to measure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchmarkPlan {
+    pub pings: u32,
+    pub stream_counts: Vec<usize>,
+    pub run_bytes: u64,
+}
+
+impl Default for BenchmarkPlan {
+    fn default() -> Self {
+        Self {
+            pings: DEFAULT_PINGS,
+            stream_counts: DEFAULT_STREAM_COUNTS.to_vec(),
+            run_bytes: DEFAULT_RUN_BYTES,
+        }
+    }
+}
+
+/// Round trips of the pings
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchmarkLatency {
+    pub samples: usize,
+    pub min_ms: f64,
+    pub median_ms: f64,
+    pub max_ms: f64,
+}
+
+impl BenchmarkLatency {
+    /// Statistics of `round_trips`; None without any
+    pub fn of(round_trips: &[Duration]) -> Option<Self> {
+        let mut ms: Vec<f64> = round_trips.iter().map(|rtt| rtt.as_secs_f64() * 1000.0).collect();
+        ms.sort_by(f64::total_cmp);
+        Some(Self {
+            samples: ms.len(),
+            min_ms: *ms.first()?,
+            median_ms: ms[ms.len() / 2],
+            max_ms: *ms.last()?,
+        })
+    }
+}
+
+/// Throughput reached with one stream count
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchmarkRun {
+    pub streams: usize,
+    pub bytes: u64,
+    pub duration_secs: f64,
+    /// MiB per second over all streams
+    pub throughput_mbps: f64,
+}
+
+impl BenchmarkRun {
+    pub fn new(streams: usize, bytes: u64, duration: Duration) -> Self {
+        let duration_secs = duration.as_secs_f64();
+        Self {
+            streams,
+            bytes,
+            duration_secs,
+            throughput_mbps: if duration_secs > 0.0 { bytes as f64 / duration_secs / (1024.0 * 1024.0) } else { 0.0 },
+        }
+    }
+}
+
+/// Outcome of a benchmark against one node
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchmarkReport {
+    /// Node ID of the receiver, or its address when it has none
+    pub peer: String,
+    pub latency: Option<BenchmarkLatency>,
+    pub runs: Vec<BenchmarkRun>,
+}
+
+impl BenchmarkReport {
+    /// The run with the highest throughput
+    pub fn best(&self) -> Option<&BenchmarkRun> {
+        self.runs.iter().max_by(|a, b| a.throughput_mbps.total_cmp(&b.throughput_mbps))
+    }
+}
+
+/// `bytes` split over `streams` streams, the remainder going to the first
+pub fn split(bytes: u64, streams: usize) -> Vec<u64> {
+    let streams = streams.max(1) as u64;
+    (0..streams)
+        .map(|stream| bytes / streams + if stream == 0 { bytes % streams } else { 0 })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_statistics() {
+        let rtts = [3, 1, 2, 9].map(Duration::from_millis);
+        let latency = BenchmarkLatency::of(&rtts).unwrap();
+        assert_eq!((latency.samples, latency.min_ms, latency.median_ms, latency.max_ms), (4, 1.0, 3.0, 9.0));
+        assert_eq!(BenchmarkLatency::of(&[]), None);
+
+        let report = BenchmarkReport {
+            peer: "node".to_string(),
+            latency: Some(latency),
+            runs: vec![
+                BenchmarkRun::new(1, 512 * 1024 * 1024, Duration::from_secs(2)),
+                BenchmarkRun::new(4, 512 * 1024 * 1024, Duration::from_secs(1)),
+            ],
+        };
+        assert_eq!(report.runs[0].throughput_mbps, 256.0);
+        assert_eq!(report.best().unwrap().streams, 4);
+
+        assert_eq!(split(10, 3), [4, 3, 3]);
+        assert_eq!(split(10, 0), [10]);
+    }
+}
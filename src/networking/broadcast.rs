@@ -0,0 +1,211 @@
+// src/networking/broadcast.rs
+//
+// Broadcast distribution
+// Pushing a dataset to a whole cluster offers it to every node and sends it to
+// several of them at once over one stream each. Every chunk is read from disk
+// and hashed once, then written to all of those streams, so the sender's disk
+// and CPU work doesn't grow with the number of nodes.
+
+use log::{info, warn};
+use std::path::Path;
+
+use super::communication::NodeClient;
+use super::discovery::NodeInfo;
+use super::file_transfer::{offer_to, FileTransferManager};
+
+/// Nodes sent to at once unless the caller chooses
+pub const DEFAULT_FAN_OUT: usize = 4;
+
+/// Outcome of a broadcast, by node ID
+#[derive(Debug, Default)]
+pub struct BroadcastReport {
+    /// Nodes that received the file or directory, with the transfer ID
+    pub delivered: Vec<(String, String)>,
+    /// Nodes that did not, with the reason
+    pub failed: Vec<(String, String)>,
+}
+
+impl BroadcastReport {
+    /// Whether every node received it
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Record the outcome of sending `path` to `node`
+    fn settle(&mut self, node: &NodeInfo, path: &Path, outcome: anyhow::Result<String>) {
+        match outcome {
+            Ok(transfer_id) => {
+                info!("{} received {}", node.name, path.display());
+                self.delivered.push((node.id.clone(), transfer_id));
+            }
+            Err(e) => {
+                warn!("Broadcast of {} to {} failed: {:#}", path.display(), node.name, e);
+                self.failed.push((node.id.clone(), format!("{:#}", e)));
+            }
+        }
+    }
+}
+
+impl FileTransferManager {
+    /// Send a file or directory to every node in `nodes`, offering it to each
+    /// first and sending to up to `fan_out` nodes at a time from the same
+    /// reads. A node that refuses or fails does not stop the others.
+    pub async fn broadcast_to_nodes<P: AsRef<Path>>(
+        &self,
+        client: &NodeClient,
+        nodes: &[NodeInfo],
+        local_node: &NodeInfo,
+        path: P,
+        fan_out: usize,
+    ) -> BroadcastReport {
+        let path = path.as_ref();
+        let targets: Vec<&NodeInfo> = nodes.iter().filter(|node| node.id != local_node.id).collect();
+        info!("Broadcasting {} to {} nodes, {} at a time", path.display(), targets.len(), fan_out.max(1));
+
+        let mut report = BroadcastReport::default();
+        // Offered group by group so no token expires while earlier groups are sent
+        for group in targets.chunks(fan_out.max(1)) {
+            let offers = futures_util::future::join_all(
+                group.iter().map(|node| offer_to(client, node, local_node, path))
+            ).await;
+            let mut accepted = Vec::new();
+            let mut destinations = Vec::new();
+            for (node, offer) in group.iter().zip(offers) {
+                match offer {
+                    Ok(destination) => {
+                        accepted.push(*node);
+                        destinations.push(destination);
+                    }
+                    Err(e) => report.settle(node, path, Err(e)),
+                }
+            }
+            if destinations.is_empty() {
+                continue;
+            }
+
+            match self.send_to_all(path, &destinations).await {
+                Ok(outcomes) => {
+                    for (node, outcome) in accepted.into_iter().zip(outcomes) {
+                        report.settle(node, path, outcome);
+                    }
+                }
+                Err(e) => {
+                    for node in accepted {
+                        report.settle(node, path, Err(anyhow::anyhow!("{:#}", e)));
+                    }
+                }
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::communication::node::node_service_server::NodeServiceServer;
+    use crate::networking::communication::NodeCommunicationService;
+    use crate::networking::FileTransferConfig;
+    use std::sync::Arc;
+    use tonic::transport::Server;
+
+    fn node(id: &str, port: u16) -> NodeInfo {
+        NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port,
+            interface_type: "Loopback".to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        }
+    }
+
+    /// A receiving node with gRPC and a transfer server
+    async fn receiving_node(id: &str, receive_dir: &Path) -> NodeInfo {
+        let mut receiver = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.to_path_buf(),
+            require_offer: true,
+            ..FileTransferConfig::default()
+        });
+        receiver.start_server().await.unwrap();
+        let service = NodeCommunicationService::new(id.to_string(), id.to_string())
+            .with_transfers(Arc::new(receiver));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(Server::builder()
+            .add_service(NodeServiceServer::new(service))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)));
+        node(id, port)
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_to_nodes() {
+        let send_dir = tempfile::tempdir().unwrap();
+        let payload = send_dir.path().join("dataset.bin");
+        let data: Vec<u8> = (0..3_000_000u32).map(|i| (i % 253) as u8).collect();
+        std::fs::write(&payload, &data).unwrap();
+
+        let receive_dirs: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        let mut nodes = Vec::new();
+        for (i, dir) in receive_dirs.iter().enumerate() {
+            nodes.push(receiving_node(&format!("node-{}", i), dir.path()).await);
+        }
+        // One node has gone away
+        let closed_port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        nodes.push(node("node-x", closed_port));
+        let local_node = node("node-a", 0);
+        nodes.push(local_node.clone());
+
+        let sender = FileTransferManager::new(FileTransferConfig {
+            node_id: Some("node-a".to_string()),
+            ..FileTransferConfig::default()
+        });
+        let report = sender.broadcast_to_nodes(&NodeClient::new(), &nodes, &local_node, &payload, 2).await;
+
+        assert!(!report.is_complete());
+        let mut delivered: Vec<_> = report.delivered.iter().map(|(id, _)| id.as_str()).collect();
+        delivered.sort();
+        assert_eq!(delivered, ["node-0", "node-1", "node-2"]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, "node-x");
+        for dir in &receive_dirs {
+            assert_eq!(std::fs::read(dir.path().join("dataset.bin")).unwrap(), data);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_directory() {
+        let send_dir = tempfile::tempdir().unwrap();
+        let root = send_dir.path().join("dataset");
+        std::fs::create_dir_all(root.join("shards")).unwrap();
+        std::fs::write(root.join("index.json"), b"{}").unwrap();
+        std::fs::write(root.join("shards/0.bin"), vec![7u8; 1_500_000]).unwrap();
+
+        let receive_dirs: Vec<_> = (0..2).map(|_| tempfile::tempdir().unwrap()).collect();
+        let mut nodes = Vec::new();
+        for (i, dir) in receive_dirs.iter().enumerate() {
+            nodes.push(receiving_node(&format!("node-{}", i), dir.path()).await);
+        }
+        let local_node = node("node-a", 0);
+
+        let sender = FileTransferManager::new(FileTransferConfig {
+            node_id: Some("node-a".to_string()),
+            ..FileTransferConfig::default()
+        });
+        let report = sender.broadcast_to_nodes(&NodeClient::new(), &nodes, &local_node, &root, 4).await;
+
+        assert!(report.is_complete(), "{:?}", report.failed);
+        assert_eq!(report.delivered.len(), 2);
+        for dir in &receive_dirs {
+            assert_eq!(std::fs::read(dir.path().join("dataset/index.json")).unwrap(), b"{}");
+            assert_eq!(std::fs::read(dir.path().join("dataset/shards/0.bin")).unwrap(), vec![7u8; 1_500_000]);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+// src/networking/capabilities.rs
+//
+// Capability advertisement
+// Peers place jobs and transfers by what a node advertises: the services it
+// runs, with their ports, and what its hardware offers (RDMA devices, GPU
+// cores, free disk space). Each capability is a `name` or `name:value` entry
+// of NodeInfo.capabilities. The hardware is probed periodically and the node
+// re-advertised whenever an entry changes; free disk space is rounded so it
+// doesn't change with every file written.
+
+use log::{debug, info};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use super::discovery::NodeInfo;
+use super::rdma;
+use crate::updater::free_space;
+
+/// Time between hardware probes
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Every node can be discovered
+pub const DISCOVERY: &str = "discovery";
+/// Serves the node gRPC service, on the port given
+pub const GRPC: &str = "grpc";
+/// Receives file transfers on the port given
+pub const FILE_TRANSFER: &str = "file_transfer";
+/// Its file transfer server answers link benchmarks
+pub const BENCHMARK: &str = "benchmark";
+/// Its file transfer server keeps newest-wins copies of synchronized folders
+pub const FOLDER_SYNC: &str = "folder_sync";
+/// Has RDMA devices
+pub const RDMA: &str = "rdma";
+/// GPU cores, summed over all GPUs
+pub const GPU_CORES: &str = "gpu_cores";
+/// Free space in GiB, to two significant digits
+pub const FREE_DISK_GB: &str = "free_disk_gb";
+
+/// Name of a `name` or `name:value` capability
+pub fn name_of(capability: &str) -> &str {
+    capability.split_once(':').map_or(capability, |(name, _)| name)
+}
+
+/// Value of capability `name` in `capabilities`: empty for one without a
+/// value, None if it is missing
+pub fn value_of<'a>(capabilities: &'a [String], name: &str) -> Option<&'a str> {
+    capabilities.iter()
+        .find(|capability| name_of(capability) == name)
+        .map(|capability| capability.split_once(':').map_or("", |(_, value)| value))
+}
+
+/// Set capability `name`, with `value` if given; whether that changed anything
+pub fn set(capabilities: &mut Vec<String>, name: &str, value: Option<&str>) -> bool {
+    let entry = match value {
+        Some(value) => format!("{}:{}", name, value),
+        None => name.to_string(),
+    };
+    match capabilities.iter_mut().find(|capability| name_of(capability) == name) {
+        Some(capability) if *capability == entry => false,
+        Some(capability) => {
+            *capability = entry;
+            true
+        }
+        None => {
+            capabilities.push(entry);
+            true
+        }
+    }
+}
+
+/// Remove capability `name`; whether it was there
+pub fn remove(capabilities: &mut Vec<String>, name: &str) -> bool {
+    let before = capabilities.len();
+    capabilities.retain(|capability| name_of(capability) != name);
+    capabilities.len() != before
+}
+
+/// Capabilities that come from probing the hardware
+const HARDWARE: [&str; 3] = [RDMA, GPU_CORES, FREE_DISK_GB];
+
+/// The hardware capabilities of this node, with free space measured where
+/// `disk_path` is
+pub async fn probe_hardware(disk_path: &Path) -> Vec<String> {
+    let mut hardware = Vec::new();
+    if has_rdma().await {
+        hardware.push(RDMA.to_string());
+    }
+    if let Some(cores) = gpu_cores().await {
+        hardware.push(format!("{}:{}", GPU_CORES, cores));
+    }
+    if let Ok((_, bytes)) = free_space(disk_path).await {
+        hardware.push(format!("{}:{}", FREE_DISK_GB, round_down(bytes / (1 << 30))));
+    }
+    hardware
+}
+
+/// Replace the hardware capabilities in `capabilities` with `hardware`;
+/// whether anything changed
+fn apply(capabilities: &mut Vec<String>, hardware: &[String]) -> bool {
+    let updated: Vec<String> = capabilities.iter()
+        .filter(|capability| !HARDWARE.contains(&name_of(capability)))
+        .chain(hardware)
+        .cloned()
+        .collect();
+    if updated == *capabilities {
+        return false;
+    }
+    *capabilities = updated;
+    true
+}
+
+/// Probe the hardware every `PROBE_INTERVAL` and update the capabilities of
+/// `local_node`, which re-advertises the node when they change
+pub fn spawn(local_node: Arc<watch::Sender<NodeInfo>>, disk_path: PathBuf) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PROBE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let hardware = probe_hardware(&disk_path).await;
+            let changed = local_node.send_if_modified(|node| apply(&mut node.capabilities, &hardware));
+            if changed {
+                info!("Advertising capabilities {}", local_node.borrow().capabilities.join(", "));
+            } else {
+                debug!("Capabilities unchanged");
+            }
+        }
+    })
+}
+
+/// Whether the node has an RDMA device
+async fn has_rdma() -> bool {
+    // With RDMA built in, the devices transfers would open
+    if rdma::SUPPORTED {
+        return tokio::task::spawn_blocking(rdma::devices).await
+            .is_ok_and(|devices| devices.is_ok_and(|devices| !devices.is_empty()));
+    }
+    if cfg!(target_os = "linux") {
+        return std::fs::read_dir("/sys/class/infiniband").is_ok_and(|mut devices| devices.next().is_some());
+    }
+    // Where libibverbs is installed, it lists the devices
+    match Command::new("ibv_devices").output().await {
+        Ok(output) if output.status.success() => count_ibv_devices(&String::from_utf8_lossy(&output.stdout)) > 0,
+        _ => false,
+    }
+}
+
+/// Devices in `ibv_devices` output, below its two header lines
+fn count_ibv_devices(output: &str) -> usize {
+    output.lines().skip(2).filter(|line| !line.trim().is_empty()).count()
+}
+
+/// GPU cores, as reported by `system_profiler` on macOS
+async fn gpu_cores() -> Option<u64> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+    let output = Command::new("system_profiler").arg("SPDisplaysDataType").output().await.ok()?;
+    parse_gpu_cores(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_gpu_cores(output: &str) -> Option<u64> {
+    let cores: Vec<u64> = output.lines()
+        .filter_map(|line| line.trim().strip_prefix("Total Number of Cores:"))
+        .filter_map(|cores| cores.trim().parse().ok())
+        .collect();
+    (!cores.is_empty()).then(|| cores.iter().sum())
+}
+
+/// `value` rounded down to two significant digits
+fn round_down(value: u64) -> u64 {
+    let mut scale = 1;
+    while value / scale >= 100 {
+        scale *= 10;
+    }
+    value / scale * scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capability_entries() {
+        let mut capabilities = vec![DISCOVERY.to_string()];
+        assert!(set(&mut capabilities, GRPC, Some("54321")));
+        assert!(!set(&mut capabilities, GRPC, Some("54321")));
+        assert!(set(&mut capabilities, GRPC, Some("50051")));
+        assert_eq!(capabilities, ["discovery", "grpc:50051"]);
+        assert_eq!(value_of(&capabilities, GRPC), Some("50051"));
+        assert_eq!(value_of(&capabilities, DISCOVERY), Some(""));
+        assert_eq!(value_of(&capabilities, RDMA), None);
+
+        let hardware = ["rdma".to_string(), "free_disk_gb:410".to_string()];
+        assert!(apply(&mut capabilities, &hardware));
+        assert!(!apply(&mut capabilities, &hardware));
+        assert_eq!(capabilities, ["discovery", "grpc:50051", "rdma", "free_disk_gb:410"]);
+        assert!(apply(&mut capabilities, &["free_disk_gb:400".to_string()]));
+        assert!(remove(&mut capabilities, GRPC));
+        assert_eq!(capabilities, ["discovery", "free_disk_gb:400"]);
+    }
+
+    #[test]
+    fn test_hardware_parsing() {
+        let profiler = "Graphics/Displays:\n\n    Apple M2 Max:\n\n      Chipset Model: Apple M2 Max\n      Type: GPU\n      Total Number of Cores: 38\n      Vendor: Apple (0x106b)\n";
+        assert_eq!(parse_gpu_cores(profiler), Some(38));
+        assert_eq!(parse_gpu_cores("Graphics/Displays:\n"), None);
+
+        let ibv = "    device          \t   node GUID\n    ------          \t----------------\n    mlx5_0          \t0c42a10300a1b2c4\n";
+        assert_eq!(count_ibv_devices(ibv), 1);
+
+        assert_eq!(round_down(7), 7);
+        assert_eq!(round_down(437), 430);
+        assert_eq!(round_down(1861), 1800);
+    }
+}
@@ -1,12 +1,17 @@
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::future::Future;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use anyhow::{Result, anyhow};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result, anyhow};
 use log::{debug, info, warn, error};
-use tokio::sync::Mutex;
-use tonic::{Request, Response, Status};
-use tonic::transport::{Channel, Server};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tonic::{Code, Request, Response, Status};
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::{InterceptedService, Interceptor};
+use tonic::transport::{Channel, Endpoint, Server};
 
 // Import generated protobuf code
 pub mod node {
@@ -16,15 +21,63 @@ pub mod node {
 use node::node_service_server::{NodeService, NodeServiceServer};
 use node::node_service_client::NodeServiceClient;
 use node::{PingRequest, PongResponse, HealthCheckRequest, HealthCheckResponse};
+use node::{UpdateRequest, ApplyUpdateRequest, UpdateStatusResponse};
+use node::{FetchFileRequest, FetchFileResponse, FileEntry, ListFilesRequest, ListFilesResponse, TransferOffer, TransferOfferResponse};
+use node::{TransferHistoryRequest, TransferHistoryResponse, PeerTransferStats};
+use node::{ChallengeRequest, ChallengeResponse, AuthenticateRequest, AuthenticateResponse};
+use node::{JobRequest, JobResponse, MetricsSummaryRequest, MetricsSummaryResponse};
+use node::{WakeNodeRequest, WakeNodeResponse};
 
+use super::connections::{ConnectionPolicy, Connections};
 use super::discovery::NodeInfo;
+use super::interface;
+use super::fetch::{received_name, FileExports};
+use super::file_transfer::{FileTransferManager, TransferDirection};
+use super::grpc_health::proto::health_server::HealthServer;
+use super::grpc_health::HealthService;
+use super::grpc_tls::{self, GrpcTls};
+use super::history::HISTORY_LIMIT;
+use super::jobs::{JobRunner, JobSpec};
+use super::paths::PathSelector;
+use super::peer_metrics::MetricsSummary;
+use super::peer_auth::{self, PeerAuth, CHALLENGE_LEN, GRPC_CLIENT_PROOF, GRPC_SERVER_PROOF, SESSION_HEADER};
+use super::reflection::proto::server_reflection_server::ServerReflectionServer;
+use super::reflection::ReflectionService;
+use super::wake::{self, WakeTable};
+use crate::updater::{UpdateHandle, UpdateStatus};
 
 /// Node communication service implementing the gRPC interface
 pub struct NodeCommunicationService {
     node_id: String,
     node_name: String,
-    health_status: Mutex<node::health_check_response::Status>,
+    /// Also served by the standard health service
+    health_status: watch::Sender<node::health_check_response::Status>,
     health_metrics: Mutex<HashMap<String, String>>,
+    /// User-defined labels reported with the health status
+    labels: HashMap<String, String>,
+    /// Update manager driven by the update RPCs; they are refused without one
+    updates: Option<UpdateHandle>,
+    /// Nodes that may check for, apply and roll back updates, once they proved
+    /// their key
+    update_controllers: Vec<String>,
+    /// Paths served by FetchFile; it is refused without any
+    exports: Option<FileExports>,
+    /// Transfer server OfferTransfer hands out and GetTransferHistory reports
+    /// on; offers are rejected without one
+    transfers: Option<Arc<FileTransferManager>>,
+    /// Runs the jobs of RunJob; they are refused without one
+    jobs: Option<JobRunner>,
+    /// Latest metrics served by GetMetricsSummary; it is refused without them
+    metrics: Option<watch::Receiver<MetricsSummary>>,
+    /// Hardware addresses WakeNode looks nodes up in; it needs them in the
+    /// request without one
+    wake: Option<WakeTable>,
+    /// Identity key proven to clients, and the sessions of the clients that
+    /// proved theirs; any caller is served without one
+    auth: Option<PeerAuth>,
+    /// Certificate the service is served over mutual TLS with; plaintext
+    /// without one
+    tls: Option<GrpcTls>,
 }
 
 impl NodeCommunicationService {
@@ -32,15 +85,165 @@ impl NodeCommunicationService {
         Self {
             node_id,
             node_name,
-            health_status: Mutex::new(node::health_check_response::Status::Healthy),
+            health_status: watch::Sender::new(node::health_check_response::Status::Healthy),
             health_metrics: Mutex::new(HashMap::new()),
+            labels: HashMap::new(),
+            updates: None,
+            update_controllers: Vec::new(),
+            exports: None,
+            transfers: None,
+            jobs: None,
+            metrics: None,
+            wake: None,
+            auth: None,
+            tls: None,
+        }
+    }
+
+    /// Service for `node`, reporting its labels in health checks
+    pub fn for_node(node: &NodeInfo) -> Self {
+        Self::new(node.id.clone(), node.name.clone()).with_labels(node.labels.clone().into_iter().collect())
+    }
+
+    /// Report `labels` in health check responses
+    pub fn with_labels(mut self, labels: HashMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Serve the update RPCs from `updates`
+    pub fn with_updates(mut self, updates: UpdateHandle) -> Self {
+        self.updates = Some(updates);
+        self
+    }
+
+    /// Accept update checks, updates and rollbacks from the nodes with these
+    /// IDs only
+    pub fn with_update_controllers(mut self, controllers: Vec<String>) -> Self {
+        self.update_controllers = controllers;
+        self
+    }
+
+    /// Serve FetchFile from `exports`
+    pub fn with_exports(mut self, exports: FileExports) -> Self {
+        self.exports = Some(exports);
+        self
+    }
+
+    /// Answer OfferTransfer and GetTransferHistory for `transfers`
+    pub fn with_transfers(mut self, transfers: Arc<FileTransferManager>) -> Self {
+        self.transfers = Some(transfers);
+        self
+    }
+
+    /// Run the jobs of RunJob with `jobs`
+    pub fn with_jobs(mut self, jobs: JobRunner) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Serve GetMetricsSummary from the summaries sent on `metrics`
+    pub fn with_metrics(mut self, metrics: watch::Receiver<MetricsSummary>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Look up the nodes WakeNode wakes in `wake`
+    pub fn with_wake(mut self, wake: WakeTable) -> Self {
+        self.wake = Some(wake);
+        self
+    }
+
+    /// Prove our identity key to clients and check theirs
+    pub fn with_auth(mut self, auth: PeerAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Serve over TLS only, with the certificate of `tls`, to clients whose
+    /// certificate names the node their requests come from
+    pub fn with_tls(mut self, tls: GrpcTls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Why a request claiming to come from `sender_id` is refused, checking its
+    /// session. A request without one passes unless authentication is required.
+    fn refusal<T>(&self, request: &Request<T>, sender_id: &str) -> Option<Status> {
+        if let Some(status) = self.certificate_refusal(request, sender_id) {
+            return Some(status);
+        }
+        let auth = self.auth.as_ref()?;
+        let session = request.metadata().get(SESSION_HEADER).and_then(|session| session.to_str().ok());
+        match session {
+            Some(session) => match auth.session_peer(session) {
+                Some(peer) if peer == sender_id => None,
+                Some(peer) => Some(Status::permission_denied(format!("Session belongs to node {}, not {}", peer, sender_id))),
+                None => Some(Status::unauthenticated("Unknown or expired session")),
+            },
+            None if auth.is_required() => Some(Status::unauthenticated("Authenticate with the node key first")),
+            None => None,
+        }
+    }
+
+    /// Why a request claiming to come from `sender_id` is refused over TLS:
+    /// the client's certificate must name that node
+    fn certificate_refusal<T>(&self, request: &Request<T>, sender_id: &str) -> Option<Status> {
+        self.tls.as_ref()?;
+        let names = grpc_tls::client_names(request);
+        if names.iter().any(|name| name == sender_id) {
+            return None;
+        }
+        Some(Status::permission_denied(format!("Client certificate is for {:?}, not node {}", names, sender_id)))
+    }
+
+    /// The node whose session `request` carries, once it proved its key
+    fn session_peer<T>(&self, request: &Request<T>) -> Option<String> {
+        let session = request.metadata().get(SESSION_HEADER)?.to_str().ok()?;
+        self.auth.as_ref()?.session_peer(session)
+    }
+
+    fn updates(&self) -> Option<&UpdateHandle> {
+        self.updates.as_ref()
+    }
+
+    /// Why a request to change what this node runs is refused: it must come
+    /// over a session of an update controller, whether or not authentication
+    /// is required otherwise
+    fn update_refusal<T>(&self, request: &Request<T>) -> Option<Status> {
+        match self.session_peer(request) {
+            None => Some(Status::unauthenticated("Updates need a session authenticated with the node key")),
+            Some(peer) if !self.update_controllers.contains(&peer) => {
+                Some(Status::permission_denied(format!("Node {} may not control updates", peer)))
+            }
+            Some(_) => None,
+        }
+    }
+
+    /// Current update status of this node
+    async fn update_status(&self, updates: &UpdateHandle) -> UpdateStatusResponse {
+        let status = updates.status().await;
+        UpdateStatusResponse {
+            responder_id: self.node_id.clone(),
+            current_version: updates.current_version().to_string(),
+            state: status.state().to_string(),
+            version: status.version().unwrap_or_default().to_string(),
+            detail: status.detail().unwrap_or_default().to_string(),
+            progress: match status {
+                UpdateStatus::Downloading { progress, .. } => progress.into(),
+                _ => 0,
+            },
         }
     }
 
     /// Update the health status of this node
     pub async fn update_health_status(&self, status: node::health_check_response::Status) {
-        let mut current_status = self.health_status.lock().await;
-        *current_status = status;
+        self.health_status.send_replace(status);
+    }
+
+    /// Follow the health status of this node
+    pub fn health(&self) -> watch::Receiver<node::health_check_response::Status> {
+        self.health_status.subscribe()
     }
 
     /// Update health metrics
@@ -60,12 +263,16 @@ impl NodeCommunicationService {
 impl NodeService for NodeCommunicationService {
     /// Handle ping requests from other nodes
     async fn ping(&self, request: Request<PingRequest>) -> Result<Response<PongResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
         let ping_req = request.into_inner();
         let now = SystemTime::now().duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_millis() as i64;
         
-        info!("📨 Received ping from '{}' with message: {}", ping_req.sender_name, ping_req.message);
+        // Peers measuring latency ping every few seconds
+        debug!("📨 Received ping from '{}' with message: {}", ping_req.sender_name, ping_req.message);
         
         // Construct the pong response
         let response = PongResponse {
@@ -84,12 +291,15 @@ impl NodeService for NodeCommunicationService {
         &self,
         request: Request<HealthCheckRequest>,
     ) -> Result<Response<HealthCheckResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
         let health_req = request.into_inner();
         
         debug!("Received health check from {}", health_req.sender_id);
         
         // Get current health status and metrics
-        let status = *self.health_status.lock().await;
+        let status = *self.health_status.borrow();
         let metrics = self.health_metrics.lock().await.clone();
         
         // Construct the health check response
@@ -98,43 +308,501 @@ impl NodeService for NodeCommunicationService {
             responder_name: self.node_name.clone(),
             status: status as i32,
             metrics,
+            labels: self.labels.clone(),
         };
         
         Ok(Response::new(response))
     }
+
+    /// Queue an update check
+    async fn check_for_updates(&self, request: Request<UpdateRequest>) -> Result<Response<UpdateStatusResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        if let Some(status) = self.update_refusal(&request) {
+            return Err(status);
+        }
+        let updates = self.updates().ok_or_else(updates_not_managed)?;
+        info!("Update check requested by {}", request.into_inner().sender_id);
+        updates.check_for_updates().await.map_err(|e| Status::unavailable(e.to_string()))?;
+        Ok(Response::new(self.update_status(updates).await))
+    }
+
+    async fn get_update_status(&self, request: Request<UpdateRequest>) -> Result<Response<UpdateStatusResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        let updates = self.updates().ok_or_else(updates_not_managed)?;
+        Ok(Response::new(self.update_status(updates).await))
+    }
+
+    /// Install the update found by the last check
+    async fn apply_update(&self, request: Request<ApplyUpdateRequest>) -> Result<Response<UpdateStatusResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        if let Some(status) = self.update_refusal(&request) {
+            return Err(status);
+        }
+        let updates = self.updates().ok_or_else(updates_not_managed)?;
+        let request = request.into_inner();
+        info!("Update requested by {}", request.sender_id);
+        let version = Some(request.version.as_str()).filter(|version| !version.is_empty());
+        updates.apply_available(version).await.map_err(|e| Status::failed_precondition(e.to_string()))?;
+        Ok(Response::new(self.update_status(updates).await))
+    }
+
+    /// Queue a rollback to the previous version
+    async fn rollback(&self, request: Request<UpdateRequest>) -> Result<Response<UpdateStatusResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        if let Some(status) = self.update_refusal(&request) {
+            return Err(status);
+        }
+        let updates = self.updates().ok_or_else(updates_not_managed)?;
+        info!("Rollback requested by {}", request.into_inner().sender_id);
+        updates.rollback().await.map_err(|e| Status::unavailable(e.to_string()))?;
+        Ok(Response::new(self.update_status(updates).await))
+    }
+
+    /// Send an exported path back to the requester's file transfer server
+    async fn fetch_file(&self, request: Request<FetchFileRequest>) -> Result<Response<FetchFileResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        let exports = self.exports.as_ref()
+            .ok_or_else(|| Status::unimplemented("No files are exported by this node"))?;
+        let requester = request.remote_addr()
+            .ok_or_else(|| Status::internal("Requester address unknown"))?;
+        let request = request.into_inner();
+        let port = u16::try_from(request.transfer_port)
+            .ok().filter(|port| *port != 0)
+            .ok_or_else(|| Status::invalid_argument("Invalid transfer port"))?;
+
+        let path = exports.resolve(&request.path).map_err(|e| {
+            warn!("Refused fetch of {} by {}: {}", request.path, request.sender_id, e);
+            Status::permission_denied(e.to_string())
+        })?;
+        let metadata = std::fs::metadata(&path).map_err(|e| Status::not_found(e.to_string()))?;
+        let name = received_name(&path).map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        // Only addresses on our own Thunderbolt links, so a fetch can't send
+        // the file anywhere else
+        let receive_addresses: Vec<IpAddr> = request.receive_addresses.iter().filter_map(|ip| ip.parse().ok()).collect();
+        let receiver = interface::links_to(&receive_addresses).first()
+            .map_or(requester.ip().to_canonical(), |(_, ip)| *ip);
+        info!("Node {} fetches {} to {}", request.sender_id, path.display(), receiver);
+        exports.send(path, SocketAddr::new(receiver, port));
+        Ok(Response::new(FetchFileResponse {
+            responder_id: self.node_id.clone(),
+            name,
+            directory: metadata.is_dir(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+        }))
+    }
+
+    /// List a directory inside the export roots
+    async fn list_files(&self, request: Request<ListFilesRequest>) -> Result<Response<ListFilesResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        let exports = self.exports.clone()
+            .ok_or_else(|| Status::unimplemented("No files are exported by this node"))?;
+        let request = request.into_inner();
+
+        debug!("Node {} lists {:?}", request.sender_id, request.path);
+        let path = request.path.clone();
+        let listing = tokio::task::spawn_blocking(move || exports.list(&path, request.recursive, request.hashes))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let (files, truncated) = listing.map_err(|e| {
+            warn!("Refused listing of {} by {}: {}", request.path, request.sender_id, e);
+            Status::permission_denied(e.to_string())
+        })?;
+        Ok(Response::new(ListFilesResponse {
+            responder_id: self.node_id.clone(),
+            entries: files.into_iter().map(|file| FileEntry {
+                path: file.path,
+                directory: file.directory,
+                size: file.size,
+                hash: file.hash.unwrap_or_default(),
+                modified: file.modified,
+            }).collect(),
+            truncated,
+        }))
+    }
+
+    /// Accept or reject a transfer another node wants to send
+    async fn offer_transfer(&self, request: Request<TransferOffer>) -> Result<Response<TransferOfferResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        let transfers = self.transfers.as_ref()
+            .ok_or_else(|| Status::unimplemented("This node does not receive file transfers"))?;
+        let offer = request.into_inner();
+
+        let mut response = TransferOfferResponse {
+            responder_id: self.node_id.clone(),
+            ..Default::default()
+        };
+        match transfers.accept_offer(&offer.sender_id, offer.size).await {
+            Ok((port, token)) => {
+                info!("Accepted transfer of {} ({} bytes) from {}", offer.name, offer.size, offer.sender_id);
+                response.accepted = true;
+                response.port = port.into();
+                response.token = token;
+            }
+            Err(e) => {
+                warn!("Rejected transfer of {} from {}: {}", offer.name, offer.sender_id, e);
+                response.reason = e.to_string();
+            }
+        }
+        Ok(Response::new(response))
+    }
+
+    async fn get_transfer_history(&self, request: Request<TransferHistoryRequest>) -> Result<Response<TransferHistoryResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        let transfers = self.transfers.as_ref()
+            .ok_or_else(|| Status::unimplemented("This node does not transfer files"))?;
+        let request = request.into_inner();
+        let limit = if request.limit == 0 { HISTORY_LIMIT } else { request.limit as usize };
+
+        let history = transfers.history();
+        let records = history.records(&request.peer, limit).into_iter()
+            .map(|record| node::TransferRecord {
+                file_id: record.file_id,
+                file_name: record.file_name,
+                sent: record.direction == TransferDirection::Send,
+                peer: record.peer,
+                size: record.size,
+                duration_secs: record.duration_secs,
+                throughput_mbps: record.throughput_mbps,
+                hash: record.hash,
+                error: record.error.unwrap_or_default(),
+                finished_at: record.finished_at,
+            })
+            .collect();
+        let stats = history.stats().into_iter()
+            .filter(|stats| request.peer.is_empty() || stats.peer == request.peer)
+            .map(|stats| PeerTransferStats {
+                peer: stats.peer,
+                sent: stats.direction == TransferDirection::Send,
+                transfers: stats.transfers,
+                failures: stats.failures,
+                bytes: stats.bytes,
+                mean_throughput_mbps: stats.mean_throughput_mbps,
+                last_throughput_mbps: stats.last_throughput_mbps,
+            })
+            .collect();
+        Ok(Response::new(TransferHistoryResponse { responder_id: self.node_id.clone(), records, stats }))
+    }
+
+    /// Prove our key by signing the client's nonce, and challenge the client
+    async fn run_job(&self, request: Request<JobRequest>) -> Result<Response<JobResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        let jobs = self.jobs.as_ref()
+            .ok_or_else(|| Status::unimplemented("This node does not run jobs"))?;
+        let caller = self.session_peer(&request);
+        let request = request.into_inner();
+        let spec: JobSpec = serde_json::from_str(&request.spec_json)
+            .map_err(|e| Status::invalid_argument(format!("Invalid job: {}", e)))?;
+        // Shell commands only come from nodes that proved their key and are
+        // allowed to send them; without shell jobs the runner refuses them itself
+        if matches!(spec, JobSpec::Shell { .. }) && jobs.runs_shell() {
+            match caller {
+                None => return Err(Status::unauthenticated("Shell jobs need a session authenticated with the node key")),
+                Some(peer) if !jobs.allows_shell_from(&peer) => {
+                    return Err(Status::permission_denied(format!("Node {} may not send shell jobs", peer)));
+                }
+                Some(_) => {}
+            }
+        }
+
+        info!("Running job {} from {}: {:?}", request.job_id, request.sender_id, spec);
+        let started = Instant::now();
+        let result = jobs.run(&spec).await;
+        let mut response = JobResponse {
+            responder_id: self.node_id.clone(),
+            job_id: request.job_id,
+            duration_ms: started.elapsed().as_millis() as u64,
+            ..Default::default()
+        };
+        match result {
+            Ok(output) => {
+                response.succeeded = true;
+                response.output = output;
+            }
+            Err(e) => {
+                warn!("Job {} from {} failed: {:#}", response.job_id, request.sender_id, e);
+                response.error = format!("{:#}", e);
+            }
+        }
+        Ok(Response::new(response))
+    }
+
+    async fn get_metrics_summary(&self, request: Request<MetricsSummaryRequest>) -> Result<Response<MetricsSummaryResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        let metrics = self.metrics.as_ref()
+            .ok_or_else(|| Status::unimplemented("This node does not share its metrics"))?;
+        debug!("Metrics summary requested by {}", request.get_ref().sender_id);
+        let summary = metrics.borrow().to_response(self.node_id.clone());
+        Ok(Response::new(summary))
+    }
+
+    /// Broadcast magic packets for a node, with the MACs in the request or
+    /// those recorded for it
+    async fn wake_node(&self, request: Request<WakeNodeRequest>) -> Result<Response<WakeNodeResponse>, Status> {
+        if let Some(status) = self.refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        let request = request.into_inner();
+        let sent_to = if request.mac_addresses.is_empty() {
+            let table = self.wake.as_ref()
+                .ok_or_else(|| Status::failed_precondition("No hardware addresses given and none recorded"))?;
+            table.wake(&request.node_id).await.map(|report| report.sent_to)
+        } else {
+            wake::send_magic_packets(&request.mac_addresses.into_iter().collect()).await
+        }
+        .map_err(|e| Status::failed_precondition(e.to_string()))?;
+        info!("Woke node {} for {}", request.node_id, request.sender_id);
+        Ok(Response::new(WakeNodeResponse { responder_id: self.node_id.clone(), sent_to }))
+    }
+
+    async fn challenge(&self, request: Request<ChallengeRequest>) -> Result<Response<ChallengeResponse>, Status> {
+        let auth = self.auth.as_ref().ok_or_else(auth_not_configured)?;
+        let request = request.into_inner();
+        if request.nonce.len() != CHALLENGE_LEN {
+            return Err(Status::invalid_argument("Invalid nonce"));
+        }
+        debug!("Identity challenge from {}", request.sender_id);
+        Ok(Response::new(ChallengeResponse {
+            responder_id: self.node_id.clone(),
+            public_key: auth.key().public_key(),
+            signature: auth.prove(GRPC_SERVER_PROOF, &request.nonce),
+            challenge: auth.issue_challenge(),
+        }))
+    }
+
+    /// Open a session for a client that signed our challenge
+    async fn authenticate(&self, request: Request<AuthenticateRequest>) -> Result<Response<AuthenticateResponse>, Status> {
+        if let Some(status) = self.certificate_refusal(&request, &request.get_ref().sender_id) {
+            return Err(status);
+        }
+        let auth = self.auth.as_ref().ok_or_else(auth_not_configured)?;
+        let request = request.into_inner();
+        let session = auth.authenticate(&request.sender_id, &request.public_key, &request.challenge, &request.signature)
+            .map_err(|e| {
+                warn!("Refused authentication of {}: {}", request.sender_id, e);
+                Status::unauthenticated(e.to_string())
+            })?;
+        info!("Node {} authenticated with key {}", request.sender_id, peer_auth::fingerprint(&request.public_key));
+        Ok(Response::new(AuthenticateResponse { responder_id: self.node_id.clone(), session }))
+    }
+}
+
+fn auth_not_configured() -> Status {
+    Status::unimplemented("Peer authentication is not configured on this node")
+}
+
+fn updates_not_managed() -> Status {
+    Status::unimplemented("Updates are not managed on this node")
 }
 
+/// Adds the session a client authenticated to every request
+#[derive(Clone)]
+struct SessionHeader(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for SessionHeader {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(session) = &self.0 {
+            request.metadata_mut().insert(SESSION_HEADER, session.clone());
+        }
+        Ok(request)
+    }
+}
+
+type Client = NodeServiceClient<InterceptedService<Channel, SessionHeader>>;
+
 /// Client for communicating with other nodes
 pub struct NodeClient {
-    clients: Mutex<HashMap<String, NodeServiceClient<Channel>>>,
+    /// Connected clients by node ID, with the address each reached its node
+    /// at, and the failures of each node
+    clients: Mutex<Connections<(Client, IpAddr)>>,
+    /// Which addresses of each node answer, best path first
+    paths: PathSelector,
+    /// Identity key proven to nodes before calling them; calls are
+    /// unauthenticated without one
+    auth: Option<PeerAuth>,
+    /// Trusted certificates nodes are called over TLS with; plaintext without
+    tls: Option<GrpcTls>,
 }
 
 impl NodeClient {
     pub fn new() -> Self {
         Self {
-            clients: Mutex::new(HashMap::new()),
+            clients: Mutex::new(Connections::new(ConnectionPolicy::default())),
+            paths: PathSelector::default(),
+            auth: None,
+            tls: None,
         }
     }
+
+    /// Connect, close idle connections and back off from failing nodes
+    /// following `policy`
+    pub fn with_connection_policy(mut self, policy: ConnectionPolicy) -> Self {
+        self.clients = Mutex::new(Connections::new(policy));
+        self
+    }
+
+    /// Whether calls to `node` currently fail right away, because it failed
+    /// too often recently
+    pub async fn is_backing_off(&self, node: &NodeInfo) -> bool {
+        self.clients.lock().await.is_open(&node.id, Instant::now())
+    }
+
+    /// Authenticate with `auth` to every node, and check each node proves the
+    /// key it advertises
+    pub fn with_auth(mut self, auth: PeerAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Call every node over TLS, checking its certificate is trusted by `tls`
+    /// and names its node ID
+    pub fn with_tls(mut self, tls: GrpcTls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
     
     /// Get or create a client for a specific node
-    async fn get_client(&self, node: &NodeInfo) -> Result<NodeServiceClient<Channel>> {
-        let mut clients = self.clients.lock().await;
-        
-        if let Some(client) = clients.get(&node.id) {
-            Ok(client.clone())
-        } else {
-            let addr = format!("http://{}:{}", node.ip, node.port);
+    async fn get_client(&self, node: &NodeInfo) -> Result<Client> {
+        Ok(self.connect(node).await?.0)
+    }
+
+    /// The address of `node` its gRPC service answered on, connecting first
+    /// if needed; transfers to the node go to the same address
+    pub async fn address_of(&self, node: &NodeInfo) -> Result<IpAddr> {
+        Ok(self.connect(node).await?.1)
+    }
+
+    /// Connect to the first of the node's addresses that answers, best path
+    /// first, unless we still have a connection to it
+    async fn connect(&self, node: &NodeInfo) -> Result<(Client, IpAddr)> {
+        let policy = {
+            let mut clients = self.clients.lock().await;
+            if let Some(client) = clients.get(&node.id, Instant::now())? {
+                return Ok(client);
+            }
+            clients.policy().clone()
+        };
+
+        let candidates = self.paths.candidates(node).await;
+        if candidates.is_empty() {
+            return Err(anyhow!("Node {} has no valid address: {}", node.name, node.ip));
+        }
+        let mut errors = Vec::new();
+        for ip in candidates {
+            let scheme = if self.tls.is_some() { "https" } else { "http" };
+            let addr = format!("{}://{}", scheme, SocketAddr::new(ip, node.port));
             debug!("Creating new client for node {} at {}", node.name, addr);
             
-            match NodeServiceClient::connect(addr.clone()).await {
-                Ok(client) => {
-                    let client_clone = client.clone();
-                    clients.insert(node.id.clone(), client);
-                    Ok(client_clone)
+            let mut endpoint = Endpoint::from_shared(addr.clone())?
+                .connect_timeout(policy.connect_timeout)
+                .tcp_keepalive(Some(policy.keep_alive_interval))
+                .http2_keep_alive_interval(policy.keep_alive_interval)
+                .keep_alive_timeout(policy.connect_timeout);
+            if let Some(tls) = &self.tls {
+                endpoint = endpoint.tls_config(tls.client_config(&node.id))?;
+            }
+            let channel = endpoint.connect().await;
+            match channel {
+                Ok(channel) => {
+                    let Ok(session) = tokio::time::timeout(policy.connect_timeout, self.authenticate(node, channel.clone())).await else {
+                        debug!("Node {} did not authenticate at {} in time", node.name, addr);
+                        errors.push(format!("{}: authentication timed out", addr));
+                        continue;
+                    };
+                    let client = (NodeServiceClient::with_interceptor(channel, SessionHeader(session?)), ip);
+                    let mut clients = self.clients.lock().await;
+                    clients.connected(&node.id, client.clone(), Instant::now());
+                    clients.succeeded(&node.id);
+                    return Ok(client);
                 },
-                Err(e) => Err(anyhow!("Failed to connect to node at {}: {}", addr, e)),
+                Err(e) => {
+                    debug!("Node {} not reachable at {}: {}", node.name, addr, e);
+                    errors.push(format!("{}: {}", addr, e));
+                },
+            }
+        }
+        self.clients.lock().await.failed(&node.id, Instant::now());
+        self.paths.forget(&node.id);
+        Err(anyhow!("Failed to connect to node {} at {}", node.name, errors.join(", ")))
+    }
+
+    /// Check that `node` holds the key it advertises and prove ours, returning
+    /// the session to present. Nodes without peer authentication are called
+    /// without a session unless we require it or they advertise a key.
+    async fn authenticate(&self, node: &NodeInfo, channel: Channel) -> Result<Option<MetadataValue<Ascii>>> {
+        let Some(auth) = &self.auth else { return Ok(None) };
+        let mut client = NodeServiceClient::new(channel);
+
+        let nonce = rand::random::<[u8; CHALLENGE_LEN]>().to_vec();
+        let request = ChallengeRequest { sender_id: auth.node_id().to_string(), nonce: nonce.clone() };
+        let challenge = match client.challenge(request).await {
+            Ok(response) => response.into_inner(),
+            Err(e) if e.code() == Code::Unimplemented && node.public_key.is_empty() && !auth.is_required() => {
+                debug!("Node {} does not authenticate peers", node.name);
+                return Ok(None);
+            }
+            Err(e) => return Err(anyhow!("Node {} did not prove its identity: {}", node.name, e.message())),
+        };
+        if challenge.responder_id != node.id {
+            return Err(anyhow!("Node {} answered as node {}", node.id, challenge.responder_id));
+        }
+        if !node.public_key.is_empty() && challenge.public_key != node.public_key {
+            return Err(anyhow!(
+                "Node {} proved key {} but advertises key {}",
+                node.name, peer_auth::fingerprint(&challenge.public_key), peer_auth::fingerprint(&node.public_key)
+            ));
+        }
+        auth.check_proof(GRPC_SERVER_PROOF, &node.id, &challenge.public_key, &nonce, &challenge.signature)?;
+
+        let request = AuthenticateRequest {
+            sender_id: auth.node_id().to_string(),
+            public_key: auth.key().public_key(),
+            signature: auth.prove(GRPC_CLIENT_PROOF, &challenge.challenge),
+            challenge: challenge.challenge,
+        };
+        let response = client.authenticate(request).await
+            .map_err(|e| anyhow!("Node {} refused our key: {}", node.name, e.message()))?
+            .into_inner();
+        debug!("Authenticated to node {} with key {}", node.name, auth.key().fingerprint());
+        Ok(Some(response.session.parse().context("Invalid session")?))
+    }
+
+    /// The message of a call to `node`, dropping its client when the node no
+    /// longer accepts our session so the next call authenticates again, or
+    /// when the call didn't reach the node so the next call reconnects
+    async fn finish<T>(&self, node: &NodeInfo, result: Result<Response<T>, Status>) -> Result<T, Box<Status>> {
+        let mut clients = self.clients.lock().await;
+        match &result {
+            Err(e) if e.code() == Code::Unauthenticated => clients.remove(&node.id),
+            // Statuses the node sent carry no source; transport errors do
+            Err(e) if e.source().is_some() => {
+                clients.failed(&node.id, Instant::now());
+                self.paths.forget(&node.id);
             }
+            _ => clients.succeeded(&node.id),
         }
+        drop(clients);
+        result.map(Response::into_inner).map_err(Box::new)
     }
     
     /// Send a ping to a specific node
@@ -152,9 +820,8 @@ impl NodeClient {
             timestamp: now,
         };
         
-        match client.ping(request).await {
-            Ok(response) => {
-                let resp = response.into_inner();
+        match self.finish(node, client.ping(request).await).await {
+            Ok(resp) => {
                 debug!("Received pong from {} ({}): {}",
                       resp.responder_name, resp.responder_id, resp.message);
                 Ok(resp)
@@ -171,9 +838,8 @@ impl NodeClient {
             sender_id: local_node.id.clone(),
         };
         
-        match client.health_check(request).await {
-            Ok(response) => {
-                let resp = response.into_inner();
+        match self.finish(node, client.health_check(request).await).await {
+            Ok(resp) => {
                 debug!("Health check response from {} ({}): status={:?}",
                       resp.responder_name, resp.responder_id, resp.status);
                 Ok(resp)
@@ -181,35 +847,531 @@ impl NodeClient {
             Err(e) => Err(anyhow!("Health check failed: {}", e)),
         }
     }
+    
+    /// Ask a node to check for updates
+    pub async fn check_for_updates(&self, node: &NodeInfo, local_node: &NodeInfo) -> Result<UpdateStatusResponse> {
+        let mut client = self.get_client(node).await?;
+        let request = UpdateRequest { sender_id: local_node.id.clone() };
+        self.finish(node, client.check_for_updates(request).await).await
+            .map_err(|e| anyhow!("Update check on {} failed: {}", node.name, e.message()))
+    }
+    
+    /// Get the update status of a node
+    pub async fn update_status(&self, node: &NodeInfo, local_node: &NodeInfo) -> Result<UpdateStatusResponse> {
+        let mut client = self.get_client(node).await?;
+        let request = UpdateRequest { sender_id: local_node.id.clone() };
+        self.finish(node, client.get_update_status(request).await).await
+            .map_err(|e| anyhow!("Getting the update status of {} failed: {}", node.name, e.message()))
+    }
+    
+    /// Ask a node to install its available update, optionally only if it is `version`
+    pub async fn apply_update(&self, node: &NodeInfo, local_node: &NodeInfo, version: Option<&str>) -> Result<UpdateStatusResponse> {
+        let mut client = self.get_client(node).await?;
+        let request = ApplyUpdateRequest {
+            sender_id: local_node.id.clone(),
+            version: version.unwrap_or_default().to_string(),
+        };
+        self.finish(node, client.apply_update(request).await).await
+            .map_err(|e| anyhow!("Update of {} failed: {}", node.name, e.message()))
+    }
+    
+    /// Ask a node to roll back to its previous version
+    pub async fn rollback(&self, node: &NodeInfo, local_node: &NodeInfo) -> Result<UpdateStatusResponse> {
+        let mut client = self.get_client(node).await?;
+        let request = UpdateRequest { sender_id: local_node.id.clone() };
+        self.finish(node, client.rollback(request).await).await
+            .map_err(|e| anyhow!("Rollback of {} failed: {}", node.name, e.message()))
+    }
+    
+    /// Ask a node to send `path` from its exports to our file transfer server on `transfer_port`
+    pub async fn fetch_file(&self, node: &NodeInfo, local_node: &NodeInfo, path: &str, transfer_port: u16) -> Result<FetchFileResponse> {
+        let mut client = self.get_client(node).await?;
+        let request = FetchFileRequest {
+            sender_id: local_node.id.clone(),
+            path: path.to_string(),
+            transfer_port: transfer_port.into(),
+            receive_addresses: node.thunderbolt_links().iter().map(|(local, _)| local.to_string()).collect(),
+        };
+        self.finish(node, client.fetch_file(request).await).await
+            .map_err(|e| anyhow!("Fetching {} from {} failed: {}", path, node.name, e.message()))
+    }
+    
+    /// List `path` on a node, absolute or relative to one of its export roots;
+    /// an empty path lists the roots
+    pub async fn list_files(&self, node: &NodeInfo, local_node: &NodeInfo, path: &str, recursive: bool, hashes: bool) -> Result<ListFilesResponse> {
+        let mut client = self.get_client(node).await?;
+        let request = ListFilesRequest {
+            sender_id: local_node.id.clone(),
+            path: path.to_string(),
+            recursive,
+            hashes,
+        };
+        self.finish(node, client.list_files(request).await).await
+            .map_err(|e| anyhow!("Listing {} on {} failed: {}", path, node.name, e.message()))
+    }
+    
+    /// Offer to send `name` (`size` bytes in total) to a node
+    pub async fn offer_transfer(&self, node: &NodeInfo, local_node: &NodeInfo, name: &str, size: u64, directory: bool) -> Result<TransferOfferResponse> {
+        let mut client = self.get_client(node).await?;
+        let request = TransferOffer {
+            sender_id: local_node.id.clone(),
+            name: name.to_string(),
+            size,
+            directory,
+        };
+        self.finish(node, client.offer_transfer(request).await).await
+            .map_err(|e| anyhow!("Offering {} to {} failed: {}", name, node.name, e.message()))
+    }
+
+    /// Get `node`'s most recent transfers with `peer` (all peers when empty)
+    /// and its per-peer statistics
+    pub async fn transfer_history(&self, node: &NodeInfo, local_node: &NodeInfo, peer: &str, limit: u32) -> Result<TransferHistoryResponse> {
+        let mut client = self.get_client(node).await?;
+        let request = TransferHistoryRequest {
+            sender_id: local_node.id.clone(),
+            peer: peer.to_string(),
+            limit,
+        };
+        self.finish(node, client.get_transfer_history(request).await).await
+            .map_err(|e| anyhow!("Getting the transfer history of {} failed: {}", node.name, e.message()))
+    }
+
+    /// Run job `job_id` on a node and wait for its outcome
+    pub async fn run_job(&self, node: &NodeInfo, local_node: &NodeInfo, job_id: &str, spec: &JobSpec) -> Result<JobResponse> {
+        let mut client = self.get_client(node).await?;
+        let request = JobRequest {
+            sender_id: local_node.id.clone(),
+            job_id: job_id.to_string(),
+            spec_json: serde_json::to_string(spec)?,
+        };
+        self.finish(node, client.run_job(request).await).await
+            .map_err(|e| anyhow!("Running job {} on {} failed: {}", job_id, node.name, e.message()))
+    }
+
+    /// Latest metrics of a node
+    pub async fn get_metrics_summary(&self, node: &NodeInfo, local_node: &NodeInfo) -> Result<MetricsSummary> {
+        let mut client = self.get_client(node).await?;
+        let request = MetricsSummaryRequest { sender_id: local_node.id.clone() };
+        self.finish(node, client.get_metrics_summary(request).await).await
+            .map(MetricsSummary::from)
+            .map_err(|e| anyhow!("Metrics summary of {} failed: {}", node.name, e.message()))
+    }
+
+    /// Ask a node to broadcast magic packets for `target` on its subnets
+    pub async fn wake_node(&self, node: &NodeInfo, local_node: &NodeInfo, target: &wake::WakeTarget) -> Result<WakeNodeResponse> {
+        let mut client = self.get_client(node).await?;
+        let request = WakeNodeRequest {
+            sender_id: local_node.id.clone(),
+            node_id: target.node_id.clone(),
+            mac_addresses: target.mac_addresses.clone().into_iter().collect(),
+        };
+        self.finish(node, client.wake_node(request).await).await
+            .map_err(|e| anyhow!("Waking {} through {} failed: {}", target.name, node.name, e.message()))
+    }
 }
 
-/// Starts the gRPC server for node communication
-pub async fn start_grpc_server(
-    node_info: NodeInfo,
+/// Starts the gRPC server for node communication with `service`, which serves
+/// the optional RPCs it was given the means for (`with_updates`,
+/// `with_exports`, `with_transfers`, `with_jobs`, `with_metrics`, `with_wake`), checks
+/// the keys of its clients `with_auth` and only takes TLS connections `with_tls`.
+/// The standard health and reflection services are served alongside it.
+/// Once `shutdown` resolves it finishes the calls in progress, and the
+/// returned task ends once it has.
+pub async fn start_grpc_server_until(
+    service: NodeCommunicationService,
     addr: SocketAddr,
-) -> Result<()> {
-    // Create the service
-    let service = NodeCommunicationService::new(
-        node_info.id.clone(),
-        node_info.name.clone(),
-    );
-    
-    info!("Starting gRPC server for node {} on {}...", node_info.name, addr);
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<JoinHandle<()>> {
+    let node_name = service.node_name.clone();
+    let tls = service.tls.clone();
+    info!("Starting gRPC server for node {} on {}{}...", node_name, addr, if tls.is_some() { " with TLS" } else { "" });
     
+    // Bind ourselves so an IPv6 address takes IPv4 connections too
+    let listener = super::interface::bind_tcp(addr)?.listen(1024)?;
+    let addr = listener.local_addr()?;
+
     // Create the server
-    let server = Server::builder()
+    let health = HealthServer::new(HealthService::new(service.health()));
+    let mut builder = Server::builder();
+    if let Some(tls) = &tls {
+        builder = builder.tls_config(tls.server_config())?;
+    }
+    let server = builder
         .add_service(NodeServiceServer::new(service))
-        .serve(addr);
+        .add_service(health)
+        .add_service(ServerReflectionServer::new(ReflectionService::new()))
+        .serve_with_incoming_shutdown(tokio_stream::wrappers::TcpListenerStream::new(listener), shutdown);
     
     // Start the server in the background
-    tokio::spawn(async move {
+    let task = tokio::spawn(async move {
         match server.await {
             Ok(_) => info!("gRPC server shutdown gracefully"),
             Err(e) => error!("gRPC server error: {}", e),
         }
     });
     
-    info!("gRPC server for node '{}' listening on {}", node_info.name, addr);
+    info!("gRPC server for node '{}' listening on {}", node_name, addr);
     
-    Ok(())
-} 
\ No newline at end of file
+    Ok(task)
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::updater::{UpdateConfig, UpdateManager, Version};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_update_rpcs() {
+        let request = || Request::new(UpdateRequest { sender_id: "controller".to_string() });
+        let service = NodeCommunicationService::new("node-1".to_string(), "node".to_string());
+        let err = service.get_update_status(request()).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unimplemented);
+
+        let manager = UpdateManager::new(UpdateConfig::default(), Version::new(0, 2, 0, None, None));
+        let service = service.with_updates(manager.handle());
+        let status = service.get_update_status(request()).await.unwrap().into_inner();
+        assert_eq!(status.responder_id, "node-1");
+        assert_eq!(status.current_version, "0.2.0");
+        assert_eq!(status.state, "idle");
+
+        // Changing what runs needs a session of an update controller, even
+        // when authentication isn't required otherwise
+        let auth = PeerAuth::new("node-1", crate::networking::NodeKey::generate().unwrap(), false);
+        let service = service.with_auth(auth.clone()).with_update_controllers(vec!["controller".to_string()]);
+        let apply = |sender_id: &str, session: Option<&str>| {
+            let mut request = Request::new(ApplyUpdateRequest { sender_id: sender_id.to_string(), version: String::new() });
+            if let Some(session) = session {
+                request.metadata_mut().insert(SESSION_HEADER, session.parse().unwrap());
+            }
+            request
+        };
+        let err = service.apply_update(apply("controller", None)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        let err = service.rollback(request()).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        let err = service.check_for_updates(request()).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+        let session = |node_id: &str| {
+            let peer = PeerAuth::new(node_id, crate::networking::NodeKey::generate().unwrap(), false);
+            let challenge = auth.issue_challenge();
+            auth.authenticate(node_id, &peer.key().public_key(), &challenge, &peer.prove(GRPC_CLIENT_PROOF, &challenge)).unwrap()
+        };
+        let other = session("worker");
+        let err = service.apply_update(apply("worker", Some(&other))).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+
+        // Nothing was found by a check yet
+        let controller = session("controller");
+        let err = service.apply_update(apply("controller", Some(&controller))).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_grpc_tls() {
+        use crate::networking::grpc_tls::test_certs;
+
+        let ca = test_certs::ca();
+        let tls = |node_id: &str| {
+            let (cert, key) = test_certs::node(node_id, &ca);
+            GrpcTls::from_pem(node_id, &cert, &key, &ca.0).unwrap()
+        };
+        let service = NodeCommunicationService::new("node-b".to_string(), "b".to_string()).with_tls(tls("node-b"));
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        start_grpc_server_until(service, SocketAddr::from(([127, 0, 0, 1], port)), std::future::pending()).await.unwrap();
+
+        let node = |id: &str| NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port,
+            interface_type: "Loopback".to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        };
+        let node_a = node("node-a");
+        let client = NodeClient::new().with_tls(tls("node-a"));
+        let health = client.health_check(&node("node-b"), &node_a).await.unwrap();
+        assert_eq!(health.responder_id, "node-b");
+
+        // A server whose certificate names another node, and a plaintext client
+        assert!(NodeClient::new().with_tls(tls("node-a")).health_check(&node("node-c"), &node_a).await.is_err());
+        assert!(NodeClient::new().health_check(&node("node-b"), &node_a).await.is_err());
+
+        // A client that doesn't trust the cluster CA
+        let other_ca = test_certs::ca();
+        let (cert, key) = test_certs::node("node-a", &other_ca);
+        let untrusting = GrpcTls::from_pem("node-a", &cert, &key, &other_ca.0).unwrap();
+        assert!(NodeClient::new().with_tls(untrusting).health_check(&node("node-b"), &node_a).await.is_err());
+
+        // Clients with a certificate for another node, one the server doesn't
+        // trust, or none at all
+        let err = NodeClient::new().with_tls(tls("node-c")).health_check(&node("node-b"), &node_a).await.unwrap_err();
+        assert!(err.to_string().contains("not node node-a"), "{}", err);
+        let (cert, key) = test_certs::node("node-a", &other_ca);
+        let untrusted = GrpcTls::from_pem("node-a", &cert, &key, &[other_ca.0.as_slice(), ca.0.as_slice()].concat()).unwrap();
+        assert!(NodeClient::new().with_tls(untrusted).health_check(&node("node-b"), &node_a).await.is_err());
+        let anonymous = Endpoint::from_shared(format!("https://127.0.0.1:{}", port)).unwrap()
+            .tls_config(tonic::transport::ClientTlsConfig::new()
+                .ca_certificate(tonic::transport::Certificate::from_pem(&ca.0))
+                .domain_name("node-b"))
+            .unwrap();
+        let health = match anonymous.connect().await {
+            Ok(channel) => NodeServiceClient::new(channel)
+                .health_check(HealthCheckRequest { sender_id: "node-a".to_string() }).await.map(|_| ()).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        assert!(health.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_health_check_labels() {
+        let labels = HashMap::from([("rack".to_string(), "r1".to_string())]);
+        let service = NodeCommunicationService::new("node-1".to_string(), "node".to_string()).with_labels(labels.clone());
+        let request = Request::new(HealthCheckRequest { sender_id: "controller".to_string() });
+        let health = service.health_check(request).await.unwrap().into_inner();
+        assert_eq!(health.labels, labels);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_file() {
+        use crate::networking::{FileTransferConfig, FileTransferManager};
+
+        let exported = tempfile::tempdir().unwrap();
+        let receive_dir = tempfile::tempdir().unwrap();
+        std::fs::write(exported.path().join("weights.bin"), vec![3u8; 100 * 1024]).unwrap();
+
+        // Node A receives into its receive directory
+        let mut receiver = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.path().to_path_buf(),
+            ..FileTransferConfig::default()
+        });
+        let transfer_port = receiver.start_server().await.unwrap().port();
+
+        // Node B exports a directory over gRPC
+        let sender = Arc::new(FileTransferManager::new(FileTransferConfig::default()));
+        let service = NodeCommunicationService::new("node-b".to_string(), "b".to_string())
+            .with_exports(FileExports::new(vec![exported.path().to_path_buf()], sender));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let grpc_port = listener.local_addr().unwrap().port();
+        tokio::spawn(Server::builder()
+            .add_service(NodeServiceServer::new(service))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)));
+
+        let node = |id: &str, port| NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port,
+            interface_type: "Loopback".to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        };
+        let client = NodeClient::new();
+        let (node_b, node_a) = (node("node-b", grpc_port), node("node-a", 0));
+
+        let err = client.fetch_file(&node_b, &node_a, "/etc/hosts", transfer_port).await.unwrap_err();
+        assert!(err.to_string().ends_with("/etc/hosts is not exported"), "{}", err);
+
+        let roots = client.list_files(&node_b, &node_a, "", false, false).await.unwrap();
+        assert_eq!(roots.entries.len(), 1);
+        let listing = client.list_files(&node_b, &node_a, &roots.entries[0].path, false, true).await.unwrap();
+        assert_eq!(listing.entries.len(), 1);
+        let entry = &listing.entries[0];
+        assert_eq!((entry.path.as_str(), entry.size, entry.hash.len()), ("weights.bin", 100 * 1024, 64));
+        assert!(client.list_files(&node_b, &node_a, "/etc", false, false).await.is_err());
+
+        let response = client.fetch_file(&node_b, &node_a, "weights.bin", transfer_port).await.unwrap();
+        assert_eq!((response.name.as_str(), response.directory, response.size), ("weights.bin", false, 100 * 1024));
+
+        let fetched = receive_dir.path().join("weights.bin");
+        for _ in 0..50 {
+            if std::fs::read(&fetched).is_ok_and(|data| data == vec![3u8; 100 * 1024]) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Fetched file never arrived");
+    }
+
+    #[tokio::test]
+    async fn test_offer_transfer() {
+        use crate::networking::FileTransferConfig;
+
+        let send_dir = tempfile::tempdir().unwrap();
+        let receive_dir = tempfile::tempdir().unwrap();
+        let payload = send_dir.path().join("dataset.bin");
+        std::fs::write(&payload, vec![5u8; 200 * 1024]).unwrap();
+
+        // Node B only takes transfers it accepted over gRPC
+        let mut receiver = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.path().to_path_buf(),
+            require_offer: true,
+            ..FileTransferConfig::default()
+        });
+        let transfer_port = receiver.start_server().await.unwrap().port();
+        let service = NodeCommunicationService::new("node-b".to_string(), "b".to_string())
+            .with_transfers(Arc::new(receiver));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let grpc_port = listener.local_addr().unwrap().port();
+        tokio::spawn(Server::builder()
+            .add_service(NodeServiceServer::new(service))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)));
+
+        let node = |id: &str, port| NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port,
+            interface_type: "Loopback".to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        };
+        let (node_b, node_a) = (node("node-b", grpc_port), node("node-a", 0));
+        let sender = FileTransferManager::new(FileTransferConfig {
+            node_id: Some("node-a".to_string()),
+            ..FileTransferConfig::default()
+        });
+
+        // Assuming the port is not enough
+        let direct = SocketAddr::from(([127, 0, 0, 1], transfer_port));
+        assert!(sender.send_file(&payload, direct).await.is_err());
+
+        let client = NodeClient::new();
+        let offer = client.offer_transfer(&node_b, &node_a, "dataset.bin", 200 * 1024, false).await.unwrap();
+        assert!(offer.accepted);
+        assert_eq!(offer.port, u32::from(transfer_port));
+
+        sender.send_to_node(&client, &node_b, &node_a, &payload).await.unwrap();
+        assert_eq!(std::fs::read(receive_dir.path().join("dataset.bin")).unwrap(), vec![5u8; 200 * 1024]);
+
+        // Both sides record the transfer; the receiver reports it over gRPC
+        let sent = sender.history().records("node-b", 10);
+        assert_eq!(sent.len(), 1);
+        assert_eq!((sent[0].size, sent[0].error.as_deref()), (200 * 1024, None));
+        let history = client.transfer_history(&node_b, &node_a, "", 0).await.unwrap();
+        assert_eq!(history.records.len(), 1);
+        assert!(!history.records[0].sent);
+        assert_eq!(history.records[0].peer, "node-a");
+        assert_eq!(history.records[0].hash, FileTransferManager::calculate_file_hash(&payload).unwrap());
+        assert_eq!((history.stats[0].transfers, history.stats[0].bytes), (1, 200 * 1024));
+
+        // More than the receiving disk holds is rejected
+        let offer = client.offer_transfer(&node_b, &node_a, "huge.bin", u64::MAX, false).await.unwrap();
+        assert!(!offer.accepted);
+        assert!(offer.reason.starts_with("Not enough free space"), "{}", offer.reason);
+    }
+
+    #[tokio::test]
+    async fn test_connection_backoff() {
+        // A port nothing listens on, until the node comes up
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let node = |id: &str| NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port: addr.port(),
+            interface_type: "Loopback".to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        };
+        let (node_a, node_b) = (node("node-a"), node("node-b"));
+        let client = NodeClient::new().with_connection_policy(ConnectionPolicy {
+            connect_timeout: Duration::from_millis(500),
+            failure_threshold: 2,
+            initial_backoff: Duration::from_millis(300),
+            ..Default::default()
+        });
+
+        for _ in 0..2 {
+            let err = client.ping(&node_b, "hi", &node_a).await.unwrap_err();
+            assert!(err.to_string().contains("Failed to connect"), "{}", err);
+        }
+        assert!(client.is_backing_off(&node_b).await);
+        let err = client.ping(&node_b, "hi", &node_a).await.unwrap_err();
+        assert!(err.to_string().contains("not retrying"), "{}", err);
+
+        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        tokio::spawn(Server::builder()
+            .add_service(NodeServiceServer::new(NodeCommunicationService::new("node-b".to_string(), "b".to_string())))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        client.ping(&node_b, "hi", &node_a).await.unwrap();
+        assert!(!client.is_backing_off(&node_b).await);
+    }
+
+    #[tokio::test]
+    async fn test_peer_authentication() {
+        use crate::networking::NodeKey;
+
+        let key_b = NodeKey::generate().unwrap();
+        let service = NodeCommunicationService::new("node-b".to_string(), "b".to_string())
+            .with_auth(PeerAuth::new("node-b", key_b.clone(), true));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let grpc_port = listener.local_addr().unwrap().port();
+        tokio::spawn(Server::builder()
+            .add_service(NodeServiceServer::new(service))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)));
+
+        let node = |id: &str, public_key: &str| NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port: grpc_port,
+            interface_type: "Loopback".to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: vec![],
+            public_key: public_key.to_string(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        };
+        let (node_b, node_a) = (node("node-b", &key_b.public_key()), node("node-a", ""));
+        let auth_a = PeerAuth::new("node-a", NodeKey::generate().unwrap(), true);
+
+        // Without a key, and claiming another ID with a session
+        let err = NodeClient::new().ping(&node_b, "hi", &node_a).await.unwrap_err();
+        assert!(err.to_string().contains("Authenticate with the node key first"), "{}", err);
+        let client = NodeClient::new().with_auth(auth_a.clone());
+        let err = client.ping(&node_b, "hi", &node("node-c", "")).await.unwrap_err();
+        assert!(err.to_string().contains("Session belongs to node node-a"), "{}", err);
+
+        let pong = client.ping(&node_b, "hi", &node_a).await.unwrap();
+        assert_eq!(pong.responder_id, "node-b");
+
+        // A node must prove the key it advertises
+        let other_key = NodeKey::generate().unwrap().public_key();
+        let err = NodeClient::new().with_auth(auth_a).ping(&node("node-b", &other_key), "hi", &node_a).await.unwrap_err();
+        assert!(err.to_string().contains("advertises key"), "{}", err);
+
+        // Node A's key is pinned by node B
+        let impostor = PeerAuth::new("node-a", NodeKey::generate().unwrap(), true);
+        let err = NodeClient::new().with_auth(impostor).ping(&node_b, "hi", &node_a).await.unwrap_err();
+        assert!(err.to_string().contains("refused our key"), "{}", err);
+    }
+}
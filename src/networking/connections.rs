@@ -0,0 +1,203 @@
+// src/networking/connections.rs
+//
+// Lifecycle of the connections NodeClient keeps to its peers
+// Connections are dropped once idle for a while, and when a peer stops
+// answering, so the next call reconnects. A peer that keeps failing trips its
+// circuit breaker: calls to it fail immediately instead of waiting on connect
+// timeouts, until a backoff that doubles with every further failure has
+// passed. Then a single call is let through to probe the peer; its success
+// closes the circuit again.
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Timeouts and failure handling of the connections to peers
+#[derive(Debug, Clone)]
+pub struct ConnectionPolicy {
+    /// Time allowed to connect to one address of a peer, and to authenticate
+    pub connect_timeout: Duration,
+    /// Connections unused for this long are closed
+    pub idle_timeout: Duration,
+    /// Interval of the HTTP/2 pings that detect dead connections while calls
+    /// are running; a connection whose ping isn't answered within the
+    /// connect timeout is closed
+    pub keep_alive_interval: Duration,
+    /// Consecutive failures after which calls to a peer fail fast
+    pub failure_threshold: u32,
+    /// Time calls fail fast after the circuit opens, doubled with every
+    /// failure after that up to `max_backoff`
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ConnectionPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(3),
+            idle_timeout: Duration::from_secs(300),
+            keep_alive_interval: Duration::from_secs(30),
+            failure_threshold: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ConnectionPolicy {
+    /// Time calls fail fast after `failures` consecutive failures
+    fn backoff(&self, failures: u32) -> Duration {
+        let doublings = failures.saturating_sub(self.failure_threshold).min(16);
+        self.initial_backoff.saturating_mul(1 << doublings).min(self.max_backoff)
+    }
+}
+
+struct Peer<C> {
+    /// Connection and when it was last used
+    connection: Option<(C, Instant)>,
+    /// Consecutive failed connects and calls
+    failures: u32,
+    /// While the circuit is open, when the next call may probe the peer
+    retry_at: Option<Instant>,
+}
+
+/// Connections of type `C` by node ID, with the failure state of each node
+pub(crate) struct Connections<C> {
+    policy: ConnectionPolicy,
+    peers: HashMap<String, Peer<C>>,
+}
+
+impl<C: Clone> Connections<C> {
+    pub fn new(policy: ConnectionPolicy) -> Self {
+        Self { policy, peers: HashMap::new() }
+    }
+
+    pub fn policy(&self) -> &ConnectionPolicy {
+        &self.policy
+    }
+
+    /// Connection to use for a call to `node_id`, or None if the caller should
+    /// connect. Fails while the node's circuit is open; once the backoff has
+    /// passed the caller gets to probe the node and the circuit stays open
+    /// for other callers until it reports back.
+    pub fn get(&mut self, node_id: &str, now: Instant) -> Result<Option<C>> {
+        self.evict_idle(now);
+        let Some(peer) = self.peers.get_mut(node_id) else { return Ok(None) };
+        if let Some(retry_at) = peer.retry_at {
+            if now < retry_at {
+                bail!(
+                    "Node {} failed {} times in a row, not retrying for {:.1}s",
+                    node_id, peer.failures, (retry_at - now).as_secs_f64()
+                );
+            }
+            peer.retry_at = Some(now + self.policy.backoff(peer.failures));
+        }
+        Ok(peer.connection.as_mut().map(|(connection, used)| {
+            *used = now;
+            connection.clone()
+        }))
+    }
+
+    /// Keep the connection to `node_id`
+    pub fn connected(&mut self, node_id: &str, connection: C, now: Instant) {
+        self.peer(node_id).connection = Some((connection, now));
+    }
+
+    /// `node_id` answered, so calls to it go through again
+    pub fn succeeded(&mut self, node_id: &str) {
+        if let Some(peer) = self.peers.get_mut(node_id) {
+            peer.failures = 0;
+            peer.retry_at = None;
+        }
+    }
+
+    /// `node_id` couldn't be reached: drop its connection so the next call
+    /// reconnects, and open its circuit once it failed too often
+    pub fn failed(&mut self, node_id: &str, now: Instant) {
+        let policy = self.policy.clone();
+        let peer = self.peer(node_id);
+        peer.connection = None;
+        peer.failures += 1;
+        if peer.failures >= policy.failure_threshold {
+            peer.retry_at = Some(now + policy.backoff(peer.failures));
+        }
+    }
+
+    /// Drop the connection to `node_id`, keeping its failure state
+    pub fn remove(&mut self, node_id: &str) {
+        if let Some(peer) = self.peers.get_mut(node_id) {
+            peer.connection = None;
+        }
+    }
+
+    /// Whether calls to `node_id` currently fail fast
+    pub fn is_open(&self, node_id: &str, now: Instant) -> bool {
+        self.peers.get(node_id).and_then(|peer| peer.retry_at).is_some_and(|retry_at| now < retry_at)
+    }
+
+    fn peer(&mut self, node_id: &str) -> &mut Peer<C> {
+        self.peers.entry(node_id.to_string())
+            .or_insert_with(|| Peer { connection: None, failures: 0, retry_at: None })
+    }
+
+    /// Close idle connections, and forget peers with neither a connection
+    /// nor failures
+    fn evict_idle(&mut self, now: Instant) {
+        let idle_timeout = self.policy.idle_timeout;
+        self.peers.retain(|_, peer| {
+            if peer.connection.as_ref().is_some_and(|(_, used)| now.duration_since(*used) >= idle_timeout) {
+                peer.connection = None;
+            }
+            peer.connection.is_some() || peer.failures > 0
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_lifecycle() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut connections = Connections::new(ConnectionPolicy::default());
+
+        assert_eq!(connections.get("a", at(0)).unwrap(), None);
+        connections.connected("a", 1, at(0));
+        assert_eq!(connections.get("a", at(100)).unwrap(), Some(1));
+        // Idle for the idle timeout since the last use
+        assert_eq!(connections.get("a", at(399)).unwrap(), Some(1));
+        assert_eq!(connections.get("a", at(699)).unwrap(), None);
+
+        // Failures below the threshold reconnect right away
+        connections.connected("a", 2, at(700));
+        connections.failed("a", at(700));
+        assert_eq!(connections.get("a", at(700)).unwrap(), None);
+        connections.failed("a", at(700));
+        assert!(!connections.is_open("a", at(700)));
+
+        // Then the circuit opens for a backoff that doubles with each failure
+        connections.failed("a", at(700));
+        assert!(connections.get("a", at(700)).is_err());
+        assert!(connections.get("b", at(700)).is_ok());
+        assert_eq!(connections.get("a", at(701)).unwrap(), None);
+        assert!(connections.get("a", at(701)).is_err(), "only one call probes the node");
+        connections.failed("a", at(701));
+        assert!(connections.is_open("a", at(702)));
+        assert!(!connections.is_open("a", at(703)));
+        for _ in 0..20 {
+            connections.failed("a", at(703));
+        }
+        assert!(connections.is_open("a", at(762)));
+        assert!(!connections.is_open("a", at(763)));
+
+        // An answer closes it
+        assert_eq!(connections.get("a", at(763)).unwrap(), None);
+        connections.connected("a", 3, at(763));
+        connections.succeeded("a");
+        assert_eq!(connections.get("a", at(763)).unwrap(), Some(3));
+        connections.failed("a", at(764));
+        assert!(!connections.is_open("a", at(764)));
+    }
+}
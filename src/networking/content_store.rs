@@ -0,0 +1,183 @@
+// src/networking/content_store.rs
+//
+// Content-addressed received files
+// Every verified file is indexed by its SHA256 hash. When a file arrives whose
+// content is already on disk under another name, the new name becomes a hard
+// link to the existing copy, so receiving the same model from several peers
+// costs its size once. Linked names share one inode, so a transfer into an
+// existing linked name detaches it first instead of writing through to the
+// other names.
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use uuid::Uuid;
+
+use super::file_transfer::FileTransferManager;
+
+/// Index of the receive directory, mapping hashes to the names holding them
+pub const INDEX_FILE: &str = ".content-index.json";
+
+/// Suffix of the temporary link swapped in over a duplicate
+pub const LINK_SUFFIX: &str = ".link";
+
+/// Received files by content hash
+#[derive(Clone)]
+pub struct ContentStore {
+    root: PathBuf,
+    /// Hash to paths relative to `root`
+    index: Arc<StdMutex<HashMap<String, Vec<String>>>>,
+}
+
+impl ContentStore {
+    /// Open the index of `root`, starting empty if there is none
+    pub fn open(root: &Path) -> Self {
+        let index = match fs::read(root.join(INDEX_FILE)) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable content index in {}: {}", root.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        Self { root: root.to_path_buf(), index: Arc::new(StdMutex::new(index)) }
+    }
+
+    /// Index the received file at `path` with SHA256 `hash`. If the same
+    /// content is already stored under another name with the same
+    /// permissions, `path` is replaced by a hard link to it; returns whether
+    /// it was.
+    pub fn add(&self, path: &Path, hash: &str) -> Result<bool> {
+        let name = self.relative(path)?;
+        let metadata = fs::metadata(path)?;
+        let mut index = self.index.lock().unwrap();
+        // The name held other content before
+        for (_, names) in index.iter_mut().filter(|(other, _)| *other != hash) {
+            names.retain(|other| *other != name);
+        }
+        index.retain(|_, names| !names.is_empty());
+        let names = index.entry(hash.to_string()).or_default();
+        names.retain(|other| *other == name || self.root.join(other).is_file());
+
+        let original = names.iter().filter(|other| **other != name).find_map(|other| {
+            let other = self.root.join(other);
+            let existing = fs::metadata(&other).ok()?;
+            let same_content = existing.ino() != metadata.ino()
+                && existing.len() == metadata.len()
+                && existing.mode() == metadata.mode()
+                // The copy may have been edited since it was indexed
+                && FileTransferManager::calculate_file_hash(&other).ok()? == hash;
+            same_content.then_some(other)
+        });
+        if let Some(original) = &original {
+            let link = path.with_file_name(format!("{}{}", Uuid::new_v4(), LINK_SUFFIX));
+            fs::hard_link(original, &link)
+                .with_context(|| format!("Failed to link {} to {}", path.display(), original.display()))?;
+            if let Err(e) = fs::rename(&link, path) {
+                let _ = fs::remove_file(&link);
+                return Err(e.into());
+            }
+            info!("{} has the content of {}, stored once", path.display(), original.display());
+        }
+
+        if !names.contains(&name) {
+            names.push(name);
+        }
+        self.save(&index);
+        Ok(original.is_some())
+    }
+
+    /// A received file with SHA256 `hash`, if any. The file is hashed again in
+    /// case it was changed since it was received.
+    pub fn lookup(&self, hash: &str) -> Option<PathBuf> {
+        let mut index = self.index.lock().unwrap();
+        let names = index.get_mut(hash)?;
+        let before = names.len();
+        let mut found = None;
+        names.retain(|name| {
+            let path = self.root.join(name);
+            if found.is_some() {
+                return path.is_file();
+            }
+            let matches = FileTransferManager::calculate_file_hash(&path).is_ok_and(|actual| actual == hash);
+            if matches {
+                found = Some(path);
+            }
+            matches
+        });
+        if names.len() != before {
+            if names.is_empty() {
+                index.remove(hash);
+            }
+            self.save(&index);
+        }
+        found
+    }
+
+    fn relative(&self, path: &Path) -> Result<String> {
+        path.strip_prefix(&self.root)
+            .map(|name| name.to_string_lossy().to_string())
+            .map_err(|_| anyhow!("{} is outside the receive directory", path.display()))
+    }
+
+    fn save(&self, index: &HashMap<String, Vec<String>>) {
+        let result = serde_json::to_vec(index)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(fs::write(self.root.join(INDEX_FILE), data)?));
+        if let Err(e) = result {
+            warn!("Failed to save the content index: {}", e);
+        }
+    }
+}
+
+/// Unlink `path` if other names share its content, so writing to it leaves them intact
+pub fn detach(path: &Path) -> Result<()> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.nlink() > 1 => Ok(fs::remove_file(path)?),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_duplicates_are_stored_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("peer-b")).unwrap();
+        for name in ["model.bin", "peer-b/model.bin", "other.bin"] {
+            fs::write(root.join(name), vec![9u8; 4096]).unwrap();
+        }
+        let hash = FileTransferManager::calculate_file_hash(&root.join("model.bin")).unwrap();
+
+        let store = ContentStore::open(root);
+        assert!(!store.add(&root.join("model.bin"), &hash).unwrap());
+        assert!(store.add(&root.join("peer-b/model.bin"), &hash).unwrap());
+        assert_eq!(fs::metadata(root.join("model.bin")).unwrap().nlink(), 2);
+        assert_eq!(store.lookup(&hash), Some(root.join("model.bin")));
+
+        // Other permissions keep their own copy
+        fs::set_permissions(root.join("other.bin"), fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(!store.add(&root.join("other.bin"), &hash).unwrap());
+
+        // The index survives a restart and forgets removed names
+        fs::remove_file(root.join("model.bin")).unwrap();
+        let store = ContentStore::open(root);
+        assert_eq!(store.lookup(&hash), Some(root.join("peer-b/model.bin")));
+        assert_eq!(store.lookup("unknown"), None);
+
+        // Detaching leaves the other names alone
+        let shared = fs::metadata(root.join("peer-b/model.bin")).unwrap().permissions();
+        fs::set_permissions(root.join("other.bin"), shared).unwrap();
+        assert!(store.add(&root.join("other.bin"), &hash).unwrap());
+        detach(&root.join("other.bin")).unwrap();
+        assert!(!root.join("other.bin").exists());
+        assert_eq!(fs::read(root.join("peer-b/model.bin")).unwrap(), vec![9u8; 4096]);
+    }
+}
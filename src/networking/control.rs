@@ -0,0 +1,130 @@
+// src/networking/control.rs
+//
+// Pausing and cancelling transfers
+// Every stream of a transfer, sending or receiving, watches the transfer's
+// requested state and checks it between chunks: paused streams wait, cancelled
+// ones stop. A transfer is known from its first stream until its last ends.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::sync::watch;
+
+/// Requested state of a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferState {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+/// The transfers in flight, keyed by file ID
+#[derive(Clone, Default)]
+pub struct TransferControls {
+    transfers: Arc<StdMutex<HashMap<String, watch::Sender<TransferState>>>>,
+}
+
+impl TransferControls {
+    /// Watch transfer `file_id`, registering it if this is its first stream
+    pub fn watch(&self, file_id: &str) -> TransferWatch {
+        let mut transfers = self.transfers.lock().unwrap();
+        let state = transfers
+            .entry(file_id.to_string())
+            .or_insert_with(|| watch::channel(TransferState::Running).0)
+            .subscribe();
+        TransferWatch {
+            file_id: file_id.to_string(),
+            state,
+            transfers: self.transfers.clone(),
+        }
+    }
+
+    /// Request a new state for a running transfer
+    pub fn set(&self, file_id: &str, requested: TransferState) -> Result<()> {
+        let transfers = self.transfers.lock().unwrap();
+        let state = transfers
+            .get(file_id)
+            .ok_or_else(|| anyhow!("No transfer in progress with ID {}", file_id))?;
+        if *state.borrow() == TransferState::Cancelled {
+            return Err(anyhow!("Transfer {} is already cancelled", file_id));
+        }
+        state.send_replace(requested);
+        Ok(())
+    }
+}
+
+/// A stream's view of its transfer's state
+pub struct TransferWatch {
+    file_id: String,
+    state: watch::Receiver<TransferState>,
+    transfers: Arc<StdMutex<HashMap<String, watch::Sender<TransferState>>>>,
+}
+
+impl TransferWatch {
+    /// Wait while the transfer is paused; fail once it is cancelled
+    pub async fn proceed(&mut self) -> Result<()> {
+        loop {
+            let state = *self.state.borrow_and_update();
+            match state {
+                TransferState::Running => return Ok(()),
+                TransferState::Cancelled => return Err(anyhow!("Transfer {} was cancelled", self.file_id)),
+                TransferState::Paused => {
+                    if self.state.changed().await.is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.state.borrow() == TransferState::Cancelled
+    }
+}
+
+impl Drop for TransferWatch {
+    fn drop(&mut self) {
+        let mut transfers = self.transfers.lock().unwrap();
+        // Forget the transfer with its last stream
+        if transfers.get(&self.file_id).is_some_and(|state| state.receiver_count() <= 1) {
+            transfers.remove(&self.file_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_pause_resume_cancel() {
+        let controls = TransferControls::default();
+        assert!(controls.set("t1", TransferState::Paused).is_err());
+
+        let mut stream = controls.watch("t1");
+        let other = controls.watch("t1");
+        stream.proceed().await.unwrap();
+
+        // Paused streams wait until resumed
+        controls.set("t1", TransferState::Paused).unwrap();
+        let waiting = tokio::spawn(async move {
+            stream.proceed().await.map(|_| stream)
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        controls.set("t1", TransferState::Running).unwrap();
+        let mut stream = waiting.await.unwrap().unwrap();
+
+        controls.set("t1", TransferState::Cancelled).unwrap();
+        assert!(stream.proceed().await.is_err());
+        assert!(other.is_cancelled());
+        assert!(controls.set("t1", TransferState::Running).is_err());
+
+        // The transfer is forgotten with its last stream
+        drop(stream);
+        assert!(controls.transfers.lock().unwrap().contains_key("t1"));
+        drop(other);
+        assert!(!controls.transfers.lock().unwrap().contains_key("t1"));
+    }
+}
@@ -0,0 +1,328 @@
+// src/networking/delta_sync.rs
+//
+// rsync-style delta synchronization
+// When the receiver already has an older copy of a file it sends a signature
+// of every block (a rolling weak checksum and a strong hash). The sender slides
+// a window over its copy, and wherever the window matches a block it only
+// names the block; everything else goes over as literal data. Large model and
+// data files that change slightly then cost little more than their changes.
+
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Bounds of the block size the receiver picks for its copy
+const MIN_BLOCK_SIZE: usize = 2 * 1024;
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+/// Bytes of SHA-256 kept as the strong hash of a block
+pub const STRONG_HASH_LEN: usize = 16;
+
+/// Block size for a file of `file_size` bytes: about the square root, so the
+/// signature and the number of blocks both stay small
+pub fn block_size_for(file_size: u64) -> usize {
+    let root = (file_size as f64).sqrt() as usize;
+    (root.div_ceil(1024) * 1024).clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE)
+}
+
+/// Checksum of one block of the receiver's copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSignature {
+    pub weak: u32,
+    pub strong: [u8; STRONG_HASH_LEN],
+}
+
+/// Signatures of every block of the receiver's copy; the last block may be short
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Signatures {
+    pub block_size: usize,
+    pub blocks: Vec<BlockSignature>,
+}
+
+impl Signatures {
+    /// Signatures of the file at `path`
+    pub fn of_file(path: &Path, block_size: usize) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?);
+        let mut blocks = Vec::new();
+        let mut block = vec![0u8; block_size];
+        loop {
+            let n = read_full(&mut reader, &mut block)?;
+            if n == 0 {
+                break;
+            }
+            blocks.push(BlockSignature {
+                weak: RollingChecksum::new(&block[..n]).value(),
+                strong: strong_hash(&block[..n]),
+            });
+            if n < block_size {
+                break;
+            }
+        }
+        Ok(Self { block_size, blocks })
+    }
+}
+
+/// One step of rebuilding the sender's file on the receiver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaOp {
+    /// Copy block `index` of the receiver's copy
+    Copy(u32),
+    /// Send `len` bytes of the sender's file starting at `offset`
+    Literal { offset: u64, len: u64 },
+}
+
+/// Ops rebuilding the file at `path` from the blocks described by `signatures`,
+/// and the SHA256 hash of the file, computed in the same pass
+pub fn compute_delta(path: &Path, signatures: &Signatures) -> Result<(Vec<DeltaOp>, String)> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let file_size = file.metadata()?.len();
+    let mut reader = BufReader::new(HashingReader { inner: file, hasher: Sha256::new() });
+    let mut ops = Vec::new();
+
+    // Without blocks to match the whole file is literal
+    let block_size = signatures.block_size;
+    if signatures.blocks.is_empty() || block_size == 0 {
+        if file_size > 0 {
+            ops.push(DeltaOp::Literal { offset: 0, len: file_size });
+        }
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        return Ok((ops, reader.into_inner().finish()));
+    }
+    let mut input = (&mut reader).bytes();
+
+    let mut by_weak: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (index, block) in signatures.blocks.iter().enumerate() {
+        by_weak.entry(block.weak).or_default().push(index as u32);
+    }
+
+    let mut window: VecDeque<u8> = VecDeque::with_capacity(block_size);
+    let mut window_start = 0u64;
+    let mut literal_start = 0u64;
+
+    fill(&mut window, &mut input, block_size)?;
+    let mut checksum = RollingChecksum::new(window.make_contiguous());
+
+    while !window.is_empty() {
+        let matched = by_weak.get(&checksum.value()).and_then(|candidates| {
+            let strong = strong_hash(window.make_contiguous());
+            candidates.iter().copied().find(|&index| {
+                let block = &signatures.blocks[index as usize];
+                block.strong == strong && block_len(signatures, index, window.len())
+            })
+        });
+
+        if let Some(index) = matched {
+            if window_start > literal_start {
+                ops.push(DeltaOp::Literal { offset: literal_start, len: window_start - literal_start });
+            }
+            ops.push(DeltaOp::Copy(index));
+            window_start += window.len() as u64;
+            literal_start = window_start;
+            window.clear();
+            fill(&mut window, &mut input, block_size)?;
+            checksum = RollingChecksum::new(window.make_contiguous());
+            continue;
+        }
+
+        // Slide by one byte; past the end the window shrinks to match a short last block
+        let out = window.pop_front().expect("window is not empty");
+        window_start += 1;
+        match input.next().transpose()? {
+            Some(byte) => {
+                window.push_back(byte);
+                checksum.roll(out, byte);
+            }
+            None => checksum.shrink(out),
+        }
+    }
+
+    if file_size > literal_start {
+        ops.push(DeltaOp::Literal { offset: literal_start, len: file_size - literal_start });
+    }
+    Ok((ops, reader.into_inner().finish()))
+}
+
+/// Hashes everything read through it
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R> HashingReader<R> {
+    fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Whether block `index` has the window's length; only the last block may be short
+fn block_len(signatures: &Signatures, index: u32, len: usize) -> bool {
+    if (index as usize) + 1 < signatures.blocks.len() {
+        len == signatures.block_size
+    } else {
+        len <= signatures.block_size
+    }
+}
+
+fn fill(window: &mut VecDeque<u8>, input: &mut impl Iterator<Item = std::io::Result<u8>>, block_size: usize) -> Result<()> {
+    while window.len() < block_size {
+        match input.next().transpose()? {
+            Some(byte) => window.push_back(byte),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = reader.read(&mut buf[filled..])?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
+pub fn strong_hash(data: &[u8]) -> [u8; STRONG_HASH_LEN] {
+    let mut hash = [0u8; STRONG_HASH_LEN];
+    hash.copy_from_slice(&Sha256::digest(data)[..STRONG_HASH_LEN]);
+    hash
+}
+
+/// rsync's weak checksum, which can be rolled one byte at a time
+#[derive(Debug, Clone, Copy)]
+struct RollingChecksum {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl RollingChecksum {
+    fn new(data: &[u8]) -> Self {
+        let len = data.len() as u32;
+        let (mut a, mut b) = (0u32, 0u32);
+        for (i, &byte) in data.iter().enumerate() {
+            a = a.wrapping_add(byte as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(byte as u32));
+        }
+        Self { a, b, len }
+    }
+
+    fn value(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+
+    /// Drop `out` from the front and append `next`
+    fn roll(&mut self, out: u8, next: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(next as u32);
+        self.b = self.b.wrapping_sub(self.len.wrapping_mul(out as u32)).wrapping_add(self.a);
+    }
+
+    /// Drop `out` from the front without appending
+    fn shrink(&mut self, out: u8) {
+        self.a = self.a.wrapping_sub(out as u32);
+        self.b = self.b.wrapping_sub(self.len.wrapping_mul(out as u32));
+        self.len -= 1;
+    }
+}
+
+/// Refuse block sizes outside what `block_size_for` picks
+pub fn check_block_size(block_size: usize) -> Result<()> {
+    if !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size) {
+        return Err(anyhow!("Invalid delta sync block size {}", block_size));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rebuild the sender's file the way the receiver does
+    fn apply(old: &[u8], new: &[u8], signatures: &Signatures, ops: &[DeltaOp]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for op in ops {
+            match *op {
+                DeltaOp::Copy(index) => {
+                    let start = index as usize * signatures.block_size;
+                    out.extend_from_slice(&old[start..(start + signatures.block_size).min(old.len())]);
+                }
+                DeltaOp::Literal { offset, len } => {
+                    out.extend_from_slice(&new[offset as usize..(offset + len) as usize]);
+                }
+            }
+        }
+        out
+    }
+
+    fn literal_bytes(ops: &[DeltaOp]) -> u64 {
+        ops.iter().map(|op| match op { DeltaOp::Literal { len, .. } => *len, DeltaOp::Copy(_) => 0 }).sum()
+    }
+
+    #[test]
+    fn test_rolling_checksum() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut checksum = RollingChecksum::new(&data[..2048]);
+        for start in 1..=100 {
+            checksum.roll(data[start - 1], data[start + 2047]);
+            assert_eq!(checksum.value(), RollingChecksum::new(&data[start..start + 2048]).value());
+        }
+        checksum.shrink(data[100]);
+        assert_eq!(checksum.value(), RollingChecksum::new(&data[101..2148]).value());
+    }
+
+    #[test]
+    fn test_delta_sends_only_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let old: Vec<u8> = (0..200_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8).collect();
+        // Insert, modify and truncate: later blocks shift by the insertion
+        let mut new = old[..50_000].to_vec();
+        new.extend_from_slice(b"inserted bytes");
+        new.extend_from_slice(&old[50_000..120_000]);
+        new.extend_from_slice(&[0xEE; 3000]);
+        new.extend_from_slice(&old[123_000..199_000]);
+        fs_write(&dir, "old", &old);
+        fs_write(&dir, "new", &new);
+
+        let signatures = Signatures::of_file(&dir.path().join("old"), 2048).unwrap();
+        assert_eq!(signatures.blocks.len(), 98);
+        let (ops, hash) = compute_delta(&dir.path().join("new"), &signatures).unwrap();
+        assert_eq!(apply(&old, &new, &signatures, &ops), new);
+        assert_eq!(hash, format!("{:x}", Sha256::digest(&new)));
+        assert!(literal_bytes(&ops) < 12 * 2048, "sent {} literal bytes", literal_bytes(&ops));
+
+        // Identical files are all copies, including the short last block
+        let (ops, _) = compute_delta(&dir.path().join("old"), &signatures).unwrap();
+        assert_eq!(literal_bytes(&ops), 0);
+        assert_eq!(apply(&old, &old, &signatures, &ops), old);
+
+        // Nothing to match against
+        let (ops, hash) = compute_delta(&dir.path().join("new"), &Signatures::default()).unwrap();
+        assert_eq!(ops, [DeltaOp::Literal { offset: 0, len: new.len() as u64 }]);
+        assert_eq!(hash, format!("{:x}", Sha256::digest(&new)));
+    }
+
+    #[test]
+    fn test_block_size_for() {
+        assert_eq!(block_size_for(0), MIN_BLOCK_SIZE);
+        assert_eq!(block_size_for(1 << 30), 32 * 1024);
+        assert_eq!(block_size_for(1 << 40), MAX_BLOCK_SIZE);
+    }
+
+    fn fs_write(dir: &tempfile::TempDir, name: &str, data: &[u8]) {
+        std::fs::write(dir.path().join(name), data).unwrap();
+    }
+}
@@ -2,20 +2,33 @@ use anyhow::{Result, anyhow};
 use log::{debug, info, warn, error};
 use mdns_sd::{ServiceDaemon, ServiceInfo, ServiceEvent};
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
-use std::net::{IpAddr, Ipv4Addr};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::time::sleep;
 use uuid::Uuid;
 use std::str::FromStr;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 
-use super::interface::{self, NetworkInterface};
+use super::capabilities;
+use super::interface::{self, InterfaceType, NetworkInterface};
+use super::membership::{MemberState, Membership, MembershipConfig};
+use super::peer_auth::{self, NodeKey};
+use super::rendezvous;
+use super::udp_discovery::{self, UdpMode};
+use crate::api::client::ApiClient;
 
 const SERVICE_TYPE: &str = "_node-controller._tcp.local.";
 const DISCOVERY_PORT: u16 = 54321; // Default port for node discovery
 const ADVERTISE_TTL: u32 = 60; // TTL for service advertisements in seconds
 const REFRESH_INTERVAL: Duration = Duration::from_secs(55); // Re-advertise before TTL expires
+const LABEL_PREFIX: &str = "label."; // TXT record key prefix of node labels
+const ADDRESS_TYPE_PREFIX: &str = "iftype."; // TXT record key prefix of address interface types
+const MAC_PREFIX: &str = "mac."; // TXT record key prefix of hardware addresses
+const INTERFACE_REFRESH_INTERVAL: Duration = Duration::from_secs(30); // Re-check local interfaces
 
 /// Node information shared during discovery
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,27 +40,113 @@ pub struct NodeInfo {
     pub interface_type: String,
     pub capabilities: Vec<String>,
     pub version: String,
+    /// Every address the node can be reached at, IPv4 and IPv6; empty when
+    /// only `ip` is known
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// Hex Ed25519 key the node proves it holds when peers connect; empty for
+    /// nodes without one
+    #[serde(default)]
+    pub public_key: String,
+    /// User-defined labels such as rack, role or environment
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Interface type of each of `addresses` (Ethernet, Thunderbolt, Wifi);
+    /// empty for nodes that don't advertise them
+    #[serde(default)]
+    pub address_types: BTreeMap<String, String>,
+    /// Hardware address of the interface of each IPv4 address in
+    /// `addresses`, for Wake-on-LAN; empty for nodes that don't advertise them
+    #[serde(default)]
+    pub mac_addresses: BTreeMap<String, String>,
 }
 
 impl NodeInfo {
-    pub fn new(name: String, interface: &NetworkInterface, port: u16) -> Self {
-        // Generate a UUID for this node
-        let uuid = Uuid::new_v4();
-        
+    pub fn new(id: String, name: String, interface: &NetworkInterface, port: u16) -> Self {
         Self {
-            id: uuid.to_string(),
+            id,
             name,
             ip: interface.ip.to_string(),
             port,
             interface_type: format!("{:?}", interface.interface_type),
-            capabilities: vec!["discovery".to_string()], // Add more capabilities as they're implemented
+            capabilities: vec![capabilities::DISCOVERY.to_string()],
             version: env!("CARGO_PKG_VERSION").to_string(),
+            addresses: vec![interface.ip.to_string()],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: [(interface.ip.to_string(), format!("{:?}", interface.interface_type))].into(),
+            mac_addresses: Default::default(),
+        }
+    }
+
+    /// Advertise the best of `interfaces` as the primary address and every
+    /// routable one with its type, and IPv4 ones with the hardware address in
+    /// `macs` of their interface; whether anything changed
+    fn set_interfaces(&mut self, interfaces: &[NetworkInterface], macs: &HashMap<String, String>) -> Result<bool> {
+        let best = interface::best_interface(interfaces)?;
+        let advertised = interface::advertised_interfaces(&best, interfaces);
+        let addresses: Vec<String> = advertised.iter().map(|interface| interface.ip.to_string()).collect();
+        let address_types: BTreeMap<String, String> = advertised.iter()
+            .map(|interface| (interface.ip.to_string(), format!("{:?}", interface.interface_type)))
+            .collect();
+        let mac_addresses: BTreeMap<String, String> = advertised.iter()
+            .filter(|interface| interface.ip.is_ipv4())
+            .filter_map(|interface| Some((interface.ip.to_string(), macs.get(&interface.name)?.clone())))
+            .collect();
+        let (ip, interface_type) = (best.ip.to_string(), format!("{:?}", best.interface_type));
+        if self.ip == ip
+            && self.interface_type == interface_type
+            && self.addresses == addresses
+            && self.address_types == address_types
+            && self.mac_addresses == mac_addresses
+        {
+            return Ok(false);
+        }
+        (self.ip, self.interface_type, self.addresses, self.address_types, self.mac_addresses) =
+            (ip, interface_type, addresses, address_types, mac_addresses);
+        Ok(true)
+    }
+
+    /// Interface type of the node's address `ip`, when it advertises one
+    pub fn address_type(&self, ip: &IpAddr) -> Option<InterfaceType> {
+        match self.address_types.get(&ip.to_string()) {
+            Some(interface_type) => interface_type.parse().ok(),
+            None if self.ip.parse() == Ok(*ip) => self.interface_type.parse().ok(),
+            None => None,
         }
     }
+
+    /// Value of capability `name`: empty for one without a value, None if the
+    /// node doesn't advertise it
+    pub fn capability(&self, name: &str) -> Option<&str> {
+        capabilities::value_of(&self.capabilities, name)
+    }
+
+    /// Addresses to try connecting to, best first: `ip`, then the other
+    /// addresses with IPv4 before IPv6. Link-local IPv6 addresses are left
+    /// out since they can't be used without an interface scope.
+    pub fn candidate_ips(&self) -> Vec<IpAddr> {
+        let primary: Option<IpAddr> = self.ip.parse().ok();
+        let mut others: Vec<IpAddr> = self.addresses.iter()
+            .filter_map(|address| address.parse().ok())
+            .filter(|ip| Some(*ip) != primary && interface::is_routable(ip))
+            .collect();
+        others.sort_by_key(IpAddr::is_ipv6);
+        others.dedup();
+        primary.into_iter().chain(others).collect()
+    }
+    
+    /// Direct Thunderbolt links from this host to the node: pairs of our
+    /// address and the node's on the same Thunderbolt subnet
+    pub fn thunderbolt_links(&self) -> Vec<(IpAddr, IpAddr)> {
+        interface::links_to(&self.candidate_ips())
+    }
     
     /// Attempt to parse NodeInfo from TXT records
     fn from_service_info(info: &ServiceInfo) -> Option<Self> {
-        let ip_addr = info.get_addresses().iter().next().copied()?;
+        let mut addresses: Vec<IpAddr> = info.get_addresses().iter().copied().collect();
+        addresses.sort_by_key(|ip| (!interface::is_routable(ip), ip.is_ipv6(), *ip));
+        let ip_addr = addresses.first().copied()?;
         
         // Extract TXT records
         let mut txt_records = HashMap::new();
@@ -59,6 +158,13 @@ impl NodeInfo {
             }
         }
         
+        // A fingerprint that doesn't match the key means a mangled record
+        let public_key = txt_records.get("public_key").cloned().unwrap_or_default();
+        if txt_records.get("fingerprint").is_some_and(|fingerprint| *fingerprint != peer_auth::fingerprint(&public_key)) {
+            warn!("Ignoring service {} with a key not matching its fingerprint", info.get_fullname());
+            return None;
+        }
+        
         Some(Self {
             id: txt_records.get("id")?.clone(),
             name: txt_records.get("name")?.clone(),
@@ -67,30 +173,127 @@ impl NodeInfo {
             interface_type: txt_records.get("interface_type")?.clone(),
             capabilities: txt_records.get("capabilities")?.split(',').map(String::from).collect(),
             version: txt_records.get("version")?.clone(),
+            addresses: addresses.iter().map(IpAddr::to_string).collect(),
+            public_key,
+            labels: txt_records.iter()
+                .filter_map(|(key, value)| Some((key.strip_prefix(LABEL_PREFIX)?.to_string(), value.clone())))
+                .collect(),
+            address_types: txt_records.iter()
+                .filter_map(|(key, value)| Some((key.strip_prefix(ADDRESS_TYPE_PREFIX)?.to_string(), value.clone())))
+                .collect(),
+            mac_addresses: txt_records.iter()
+                .filter_map(|(key, value)| Some((key.strip_prefix(MAC_PREFIX)?.to_string(), value.clone())))
+                .collect(),
         })
     }
+
+    /// TXT records advertising this node
+    fn txt_properties(&self) -> HashMap<String, String> {
+        let mut properties = HashMap::new();
+        properties.insert("id".to_string(), self.id.clone());
+        properties.insert("name".to_string(), self.name.clone());
+        properties.insert("interface_type".to_string(), self.interface_type.clone());
+        properties.insert("capabilities".to_string(), self.capabilities.join(","));
+        properties.insert("version".to_string(), self.version.clone());
+        if !self.public_key.is_empty() {
+            properties.insert("public_key".to_string(), self.public_key.clone());
+            properties.insert("fingerprint".to_string(), peer_auth::fingerprint(&self.public_key));
+        }
+        for (key, value) in &self.labels {
+            properties.insert(format!("{}{}", LABEL_PREFIX, key), value.clone());
+        }
+        for (address, interface_type) in &self.address_types {
+            properties.insert(format!("{}{}", ADDRESS_TYPE_PREFIX, address), interface_type.clone());
+        }
+        for (address, mac) in &self.mac_addresses {
+            properties.insert(format!("{}{}", MAC_PREFIX, address), mac.clone());
+        }
+        properties
+    }
+
+    /// Addresses for the mDNS A and AAAA records, comma-separated
+    fn advertised_ips(&self) -> String {
+        if self.addresses.is_empty() { self.ip.clone() } else { self.addresses.join(",") }
+    }
+
+    /// mDNS host name, which can't hold the colons of an IPv6 address
+    fn hostname(&self) -> String {
+        format!("{}.local.", self.ip.replace(':', "-"))
+    }
+}
+
+/// Re-check the interfaces of this host every `INTERFACE_REFRESH_INTERVAL`
+/// and update the addresses `local_node` advertises
+fn spawn_interface_refresh(local_node: Arc<watch::Sender<NodeInfo>>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(INTERFACE_REFRESH_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let interfaces = match interface::list_interfaces() {
+                Ok(interfaces) => interfaces,
+                Err(e) => {
+                    debug!("Failed to list interfaces: {}", e);
+                    continue;
+                }
+            };
+            let mut result = Ok(false);
+            local_node.send_if_modified(|node| {
+                result = node.set_interfaces(&interfaces, &interface::mac_addresses());
+                matches!(result, Ok(true))
+            });
+            match result {
+                Ok(true) => {
+                    let node = local_node.borrow();
+                    info!("Interfaces changed, advertising {} on {} ({})", node.addresses.join(", "), node.ip, node.interface_type);
+                }
+                Ok(false) => {}
+                Err(e) => debug!("Keeping the advertised addresses: {}", e),
+            }
+        }
+    })
 }
 
 /// Main node discovery service
 pub struct NodeDiscovery {
-    mdns: ServiceDaemon,
-    local_node: NodeInfo,
+    /// None when the mDNS daemon could not be started
+    mdns: Option<ServiceDaemon>,
+    /// This node as advertised; every advertisement is renewed when it changes
+    local_node: Arc<watch::Sender<NodeInfo>>,
     discovered_nodes: Arc<Mutex<HashMap<String, (NodeInfo, Instant)>>>,
     service_name: String,
+    udp_mode: UdpMode,
+    udp_port: u16,
+    /// API peers register with and are listed by, and how often
+    rendezvous: Option<(Arc<ApiClient>, Duration)>,
+    /// Port SWIM membership runs on, when enabled
+    gossip_port: Option<u16>,
+    /// Running membership, once started
+    membership: Mutex<Option<Membership>>,
+    /// UDP, rendezvous and membership tasks, stopped on shutdown
+    tasks: Mutex<Vec<JoinHandle<()>>>,
 }
 
 impl NodeDiscovery {
-    /// Create a new node discovery service
+    /// Create a new node discovery service with a freshly generated node ID
     pub fn new(node_name: &str, port: Option<u16>) -> Result<Self> {
+        Self::with_node_id(&Uuid::new_v4().to_string(), node_name, port)
+    }
+
+    /// Create a node discovery service advertising a persistent node ID
+    pub fn with_node_id(node_id: &str, node_name: &str, port: Option<u16>) -> Result<Self> {
         // Get the best network interface for node communication
-        let interface = interface::get_best_interface()?;
+        let interfaces = interface::discover_interfaces()?;
+        let interface = interface::best_interface(&interfaces)?;
         
-        // Create local node info
-        let local_node = NodeInfo::new(
+        // Create local node info, advertising the addresses of every interface
+        let mut local_node = NodeInfo::new(
+            node_id.to_string(),
             node_name.to_string(),
             &interface,
             port.unwrap_or(DISCOVERY_PORT),
         );
+        local_node.set_interfaces(&interfaces, &interface::mac_addresses())?;
         
         info!("Initializing node discovery for node {} on {:?} interface ({})...",
              local_node.name, interface.interface_type, interface.ip);
@@ -98,41 +301,116 @@ impl NodeDiscovery {
         // Create unique service name
         let service_name = format!("{}_{}", node_name, Uuid::new_v4().to_string());
         
-        // Initialize mDNS service daemon
-        let mdns = ServiceDaemon::new()?;
+        // Initialize mDNS service daemon; UDP discovery stands in without one
+        let mdns = ServiceDaemon::new()
+            .map_err(|e| warn!("Failed to start the mDNS daemon: {}", e))
+            .ok();
         
         Ok(Self {
             mdns,
-            local_node,
+            local_node: Arc::new(watch::channel(local_node).0),
             discovered_nodes: Arc::new(Mutex::new(HashMap::new())),
             service_name,
+            udp_mode: UdpMode::default(),
+            udp_port: udp_discovery::DEFAULT_UDP_PORT,
+            rendezvous: None,
+            gossip_port: None,
+            membership: Mutex::new(None),
+            tasks: Mutex::new(Vec::new()),
         })
     }
+
+    /// Advertise `key` as this node's identity key. Set it before starting.
+    pub fn with_key(self, key: &NodeKey) -> Self {
+        self.local_node.send_modify(|node| node.public_key = key.public_key());
+        self
+    }
+
+    /// Advertise user-defined `labels` with the node
+    pub fn with_labels(self, labels: BTreeMap<String, String>) -> Self {
+        self.local_node.send_modify(|node| node.labels = labels);
+        self
+    }
+
+    /// Choose when UDP broadcast discovery runs, and on which port
+    pub fn with_udp(mut self, mode: UdpMode, port: u16) -> Self {
+        self.udp_mode = mode;
+        self.udp_port = port;
+        self
+    }
+
+    /// Also find nodes on other networks by registering with `client` and
+    /// fetching the peers registered there every `interval`
+    pub fn with_rendezvous(mut self, client: Arc<ApiClient>, interval: Duration) -> Self {
+        self.rendezvous = Some((client, interval));
+        self
+    }
+
+    /// Track which discovered nodes are alive by probing them over UDP
+    /// `port`, which every node has to use, instead of waiting for their
+    /// advertisements to expire
+    pub fn with_membership(mut self, port: u16) -> Self {
+        self.gossip_port = Some(port);
+        self
+    }
     
     /// Start the discovery service
     pub async fn start(&self) -> Result<()> {
-        // Start advertising our service
-        self.advertise_service()?;
-        
-        // Browse for other services
-        self.browse_services().await?;
-        
+        // Probe what the hardware offers, re-advertising when it changes
+        let disk_path = dirs::home_dir().unwrap_or_else(|| PathBuf::from("/"));
+        self.tasks.lock().unwrap().push(capabilities::spawn(self.local_node.clone(), disk_path));
+        // Follow interfaces coming and going, re-advertising when they change
+        self.tasks.lock().unwrap().push(spawn_interface_refresh(self.local_node.clone()));
+
+        if let Some(port) = self.gossip_port {
+            let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+            let membership = Membership::bind(self.local_node.subscribe(), addr, MembershipConfig::default()).await?;
+            let mut tasks = membership.spawn();
+            tasks.push(membership.follow(self.discovered_nodes.clone(), port));
+            self.tasks.lock().unwrap().extend(tasks);
+            *self.membership.lock().unwrap() = Some(membership);
+        }
+
+        let mdns_result = match &self.mdns {
+            // Advertise our service and browse for other services
+            Some(mdns) => match self.advertise_service(mdns) {
+                Ok(()) => self.browse_services(mdns).await,
+                Err(e) => Err(e),
+            },
+            None => Err(anyhow!("mDNS is unavailable")),
+        };
+
+        if let Some((client, interval)) = &self.rendezvous {
+            let task = rendezvous::spawn(client.clone(), self.local_node.subscribe(), self.discovered_nodes.clone(), *interval);
+            self.tasks.lock().unwrap().push(task);
+        }
+
+        let use_udp = match self.udp_mode {
+            UdpMode::Always => true,
+            UdpMode::Fallback => mdns_result.is_err(),
+            UdpMode::Off => false,
+        };
+        if !use_udp {
+            // Rendezvous discovery alone is enough to start
+            return mdns_result.or_else(|e| if self.rendezvous.is_some() { Ok(()) } else { Err(e) });
+        }
+        if let Err(e) = &mdns_result {
+            warn!("mDNS discovery failed ({}), falling back to UDP broadcast discovery", e);
+        }
+        let tasks = udp_discovery::start(self.local_node.subscribe(), self.udp_port, self.discovered_nodes.clone()).await?;
+        self.tasks.lock().unwrap().extend(tasks);
         Ok(())
     }
     
     /// Advertise this node as an available service
-    fn advertise_service(&self) -> Result<()> {
-        let ip_addr = self.local_node.ip.clone();
-        let port = self.local_node.port;
-        let hostname = format!("{}.local.", self.local_node.ip);
+    fn advertise_service(&self, mdns: &ServiceDaemon) -> Result<()> {
+        let local_node = self.get_local_node();
+        let ip_addr = local_node.advertised_ips();
+        let port = local_node.port;
+        let hostname = local_node.hostname();
         
         // Create properties as a HashMap
-        let mut properties = HashMap::new();
-        properties.insert("id".to_string(), self.local_node.id.clone());
-        properties.insert("name".to_string(), self.local_node.name.clone());
-        properties.insert("interface_type".to_string(), self.local_node.interface_type.clone());
-        properties.insert("capabilities".to_string(), self.local_node.capabilities.join(","));
-        properties.insert("version".to_string(), self.local_node.version.clone());
+        let properties = local_node.txt_properties();
         
         // Create the service info
         let service_info = ServiceInfo::new(
@@ -145,29 +423,28 @@ impl NodeDiscovery {
         )?;
         
         // Register the service
-        self.mdns.register(service_info)?;
+        mdns.register(service_info)?;
         info!("Node '{}' ready and advertising on {} port {}", 
-             self.local_node.name, self.local_node.ip, port);
+             local_node.name, local_node.ip, port);
         
-        // Setup periodic re-advertising
-        let mdns = self.mdns.clone();
+        // Setup periodic re-advertising, and right away when the node changes
+        let mdns = mdns.clone();
         let service_name = self.service_name.clone();
-        let local_node = self.local_node.clone();
+        let mut advertised = self.local_node.subscribe();
         
         tokio::spawn(async move {
             loop {
-                sleep(REFRESH_INTERVAL).await;
+                tokio::select! {
+                    _ = sleep(REFRESH_INTERVAL) => {}
+                    Ok(()) = advertised.changed() => {}
+                }
+                let local_node = advertised.borrow_and_update().clone();
                 
-                let ip_addr = local_node.ip.clone();
-                let hostname = format!("{}.local.", local_node.ip);
+                let ip_addr = local_node.advertised_ips();
+                let hostname = local_node.hostname();
                 
                 // Create properties as a HashMap for refresh
-                let mut properties = HashMap::new();
-                properties.insert("id".to_string(), local_node.id.clone());
-                properties.insert("name".to_string(), local_node.name.clone());
-                properties.insert("interface_type".to_string(), local_node.interface_type.clone());
-                properties.insert("capabilities".to_string(), local_node.capabilities.join(","));
-                properties.insert("version".to_string(), local_node.version.clone());
+                let properties = local_node.txt_properties();
                 
                 match ServiceInfo::new(
                     SERVICE_TYPE,
@@ -194,13 +471,13 @@ impl NodeDiscovery {
     }
     
     /// Browse for other node controller services
-    async fn browse_services(&self) -> Result<()> {
+    async fn browse_services(&self, mdns: &ServiceDaemon) -> Result<()> {
         // Browse for services
-        let receiver = self.mdns.browse(SERVICE_TYPE)?;
+        let receiver = mdns.browse(SERVICE_TYPE)?;
         
         // Store the discovered nodes
         let discovered_nodes = self.discovered_nodes.clone();
-        let local_id = self.local_node.id.clone();
+        let local_id = self.get_local_node().id;
         
         // Process events in background
         tokio::spawn(async move {
@@ -245,8 +522,16 @@ impl NodeDiscovery {
         Ok(())
     }
     
-    /// Get a copy of all currently discovered nodes
+    /// Get a copy of all currently discovered nodes; with membership, those
+    /// not known to have failed
     pub fn get_discovered_nodes(&self) -> Vec<NodeInfo> {
+        if let Some(membership) = self.membership.lock().unwrap().as_ref() {
+            return membership.members().into_iter()
+                .filter(|member| member.state != MemberState::Dead)
+                .map(|member| member.node)
+                .collect();
+        }
+
         let now = Instant::now();
         let mut result = Vec::new();
         
@@ -272,18 +557,114 @@ impl NodeDiscovery {
         result
     }
     
+    /// Every node with what is known of its health. Without membership all
+    /// discovered nodes count as alive.
+    pub fn member_states(&self) -> Vec<(NodeInfo, MemberState)> {
+        match self.membership.lock().unwrap().as_ref() {
+            Some(membership) => membership.members().into_iter().map(|member| (member.node, member.state)).collect(),
+            None => self.get_discovered_nodes().into_iter().map(|node| (node, MemberState::Alive)).collect(),
+        }
+    }
+
     /// Get information about the local node
     pub fn get_local_node(&self) -> NodeInfo {
-        self.local_node.clone()
+        self.local_node.borrow().clone()
+    }
+
+    /// Advertise capability `name`, with `value` if given (e.g. the port of a
+    /// service), replacing an earlier value
+    pub fn set_capability(&self, name: &str, value: Option<&str>) {
+        self.local_node.send_if_modified(|node| capabilities::set(&mut node.capabilities, name, value));
+    }
+
+    /// Stop advertising capability `name`
+    pub fn remove_capability(&self, name: &str) {
+        self.local_node.send_if_modified(|node| capabilities::remove(&mut node.capabilities, name));
     }
     
     /// Stop the discovery service
     pub fn shutdown(&self) -> Result<()> {
         // Unregister our service
-        if let Err(e) = self.mdns.unregister(&self.service_name) {
-            warn!("Failed to unregister service: {}", e);
+        if let Some(mdns) = &self.mdns {
+            if let Err(e) = mdns.unregister(&self.service_name) {
+                warn!("Failed to unregister service: {}", e);
+            }
+        }
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
         }
         
         Ok(())
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_ips() {
+        let mut node = NodeInfo {
+            id: "node-a".to_string(),
+            name: "node-a".to_string(),
+            ip: "2001:db8::5".to_string(),
+            port: 54321,
+            interface_type: "Ethernet".to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: ["fe80::1", "2001:db8::5", "fd00::7", "10.0.0.5"].map(String::from).to_vec(),
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        };
+        let candidates: Vec<String> = node.candidate_ips().iter().map(IpAddr::to_string).collect();
+        assert_eq!(candidates, ["2001:db8::5", "10.0.0.5", "fd00::7"]);
+        assert_eq!(node.hostname(), "2001-db8--5.local.");
+
+        // Records from nodes that only announce one address
+        node.addresses.clear();
+        let node: NodeInfo = serde_json::from_str(&serde_json::to_string(&node).unwrap().replace(",\"addresses\":[]", "")).unwrap();
+        assert_eq!(node.candidate_ips(), ["2001:db8::5".parse::<IpAddr>().unwrap()]);
+        assert_eq!(node.advertised_ips(), "2001:db8::5");
+    }
+
+    #[test]
+    fn test_labels_in_txt_records() {
+        let mut node = NodeInfo::new("node-a".to_string(), "node-a".to_string(), &NetworkInterface::new(
+            "lo0".to_string(), IpAddr::V4(Ipv4Addr::LOCALHOST), interface::InterfaceType::Loopback,
+        ), DISCOVERY_PORT);
+        node.labels = [("rack", "r1"), ("role", "worker")].map(|(k, v)| (k.to_string(), v.to_string())).into();
+        let info = ServiceInfo::new(SERVICE_TYPE, "node-a", &node.hostname(), node.advertised_ips(), node.port, node.txt_properties()).unwrap();
+        let parsed = NodeInfo::from_service_info(&info).unwrap();
+        assert_eq!(parsed.labels, node.labels);
+        assert_eq!(parsed.capabilities, node.capabilities);
+    }
+
+    #[test]
+    fn test_interface_advertising() {
+        let interface = |name: &str, ip: &str, interface_type| NetworkInterface::new(name.to_string(), ip.parse().unwrap(), interface_type);
+        let mut interfaces = vec![
+            interface("en1", "10.0.0.5", InterfaceType::Wifi),
+            interface("en0", "192.168.1.5", InterfaceType::Ethernet),
+            interface("lo0", "127.0.0.1", InterfaceType::Loopback),
+        ];
+        let macs: HashMap<String, String> = [("en0", "a4:83:e7:12:34:56"), ("lo0", "00:00:00:00:00:01")]
+            .map(|(name, mac)| (name.to_string(), mac.to_string()))
+            .into();
+        let mut node = NodeInfo::new("node-a".to_string(), "node-a".to_string(), &interfaces[0], DISCOVERY_PORT);
+        assert!(node.set_interfaces(&interfaces, &macs).unwrap());
+        assert!(!node.set_interfaces(&interfaces, &macs).unwrap());
+        assert_eq!(node.addresses, ["10.0.0.5", "192.168.1.5"]);
+        assert_eq!(node.address_type(&"192.168.1.5".parse().unwrap()), Some(InterfaceType::Ethernet));
+
+        // A Thunderbolt bridge coming up is advertised with its type
+        interfaces.push(interface("bridge0", "169.254.3.4", InterfaceType::Thunderbolt));
+        assert!(node.set_interfaces(&interfaces, &macs).unwrap());
+        let info = ServiceInfo::new(SERVICE_TYPE, "node-a", &node.hostname(), node.advertised_ips(), node.port, node.txt_properties()).unwrap();
+        let parsed = NodeInfo::from_service_info(&info).unwrap();
+        assert_eq!(parsed.address_types, node.address_types);
+        assert_eq!(parsed.mac_addresses, [("192.168.1.5".to_string(), "a4:83:e7:12:34:56".to_string())].into());
+        assert_eq!(parsed.address_type(&"169.254.3.4".parse().unwrap()), Some(InterfaceType::Thunderbolt));
+        assert_eq!(parsed.address_type(&"10.0.0.9".parse().unwrap()), None);
+    }
+}
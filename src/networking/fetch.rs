@@ -0,0 +1,230 @@
+// src/networking/fetch.rs
+//
+// Serving file fetches
+// Transfers are pushed by the sender. To let node A pull a path from node B,
+// A asks B over gRPC; B checks the path lies inside one of its export roots
+// and pushes it back to A's file transfer server. A can browse what B exports
+// first: B lists the directories inside its roots, with file sizes and,
+// on request, SHA-256 hashes.
+
+use anyhow::{anyhow, Context, Result};
+use log::{error, info, warn};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use super::file_transfer::FileTransferManager;
+
+/// Most entries one listing returns
+pub const MAX_LISTED_FILES: usize = 10_000;
+
+/// A file or directory in an export listing
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedFile {
+    /// Relative to the listed directory; an export root's absolute path when
+    /// listing the roots
+    pub path: String,
+    pub directory: bool,
+    /// Size in bytes; 0 for directories
+    pub size: u64,
+    /// Hex SHA-256 of a file, when hashes were asked for
+    pub hash: Option<String>,
+    /// Modification time in seconds since the Unix epoch
+    pub modified: u64,
+}
+
+impl ListedFile {
+    fn new(path: String, metadata: &fs::Metadata) -> Self {
+        Self {
+            path,
+            directory: metadata.is_dir(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            hash: None,
+            modified: metadata.modified().ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_secs()),
+        }
+    }
+}
+
+/// Directories peers may fetch from, and the manager that sends them
+#[derive(Clone)]
+pub struct FileExports {
+    roots: Vec<PathBuf>,
+    manager: Arc<FileTransferManager>,
+}
+
+impl FileExports {
+    /// Export `roots`; roots that don't exist are skipped
+    pub fn new(roots: Vec<PathBuf>, manager: Arc<FileTransferManager>) -> Self {
+        let roots = roots
+            .into_iter()
+            .filter_map(|root| match root.canonicalize() {
+                Ok(root) => Some(root),
+                Err(e) => {
+                    warn!("Not exporting {}: {}", root.display(), e);
+                    None
+                }
+            })
+            .collect();
+        Self { roots, manager }
+    }
+
+    /// Resolve a requested path, absolute or relative to an export root, to a
+    /// file or directory inside one of the roots. Symlinks and `..` are resolved
+    /// first, so neither can reach outside.
+    pub fn resolve(&self, requested: &str) -> Result<PathBuf> {
+        let requested = Path::new(requested);
+        let candidates: Vec<PathBuf> = if requested.is_absolute() {
+            vec![requested.to_path_buf()]
+        } else {
+            self.roots.iter().map(|root| root.join(requested)).collect()
+        };
+
+        let mut found = false;
+        for candidate in candidates {
+            let Ok(path) = candidate.canonicalize() else { continue };
+            found = true;
+            if self.roots.iter().any(|root| path.starts_with(root)) {
+                return Ok(path);
+            }
+        }
+        if found {
+            Err(anyhow!("{} is not exported", requested.display()))
+        } else {
+            Err(anyhow!("{} does not exist", requested.display()))
+        }
+    }
+
+    /// Send `path` to the file transfer server at `target` in the background
+    pub fn send(&self, path: PathBuf, target: SocketAddr) {
+        let manager = self.manager.clone();
+        tokio::spawn(async move {
+            info!("Sending fetched {} to {}", path.display(), target);
+            if let Err(e) = manager.send_file(&path, target).await {
+                error!("Failed to send fetched {} to {}: {}", path.display(), target, e);
+            }
+        });
+    }
+
+    /// List the directory at `requested` (see `resolve`), everything under it
+    /// when `recursive`, or the export roots themselves when `requested` is
+    /// empty. Entries leading outside the roots through symlinks are left out
+    /// and symlinked directories are not descended into. At most
+    /// `MAX_LISTED_FILES` entries are returned; the flag tells whether there
+    /// were more. Hashing reads every file, so this blocks for a while.
+    pub fn list(&self, requested: &str, recursive: bool, hashes: bool) -> Result<(Vec<ListedFile>, bool)> {
+        if requested.is_empty() {
+            let roots = self.roots.iter()
+                .filter_map(|root| Some(ListedFile::new(root.display().to_string(), &fs::metadata(root).ok()?)))
+                .collect();
+            return Ok((roots, false));
+        }
+        let dir = self.resolve(requested)?;
+        if !dir.is_dir() {
+            return Err(anyhow!("{} is not a directory", requested));
+        }
+
+        let mut listed = Vec::new();
+        let mut pending = vec![PathBuf::new()];
+        while let Some(relative) = pending.pop() {
+            let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir.join(&relative))
+                .with_context(|| format!("Failed to list {}", relative.display()))?
+                .filter_map(|entry| entry.ok())
+                .collect();
+            entries.sort_by_key(fs::DirEntry::file_name);
+            for entry in entries {
+                let Ok(path) = entry.path().canonicalize() else { continue };
+                if !self.roots.iter().any(|root| path.starts_with(root)) {
+                    continue;
+                }
+                let Ok(metadata) = fs::metadata(&path) else { continue };
+                if listed.len() == MAX_LISTED_FILES {
+                    return Ok((listed, true));
+                }
+                let name = relative.join(entry.file_name());
+                let mut file = ListedFile::new(name.to_string_lossy().to_string(), &metadata);
+                if hashes && !file.directory {
+                    file.hash = FileTransferManager::calculate_file_hash(&path).ok();
+                }
+                let symlink = entry.file_type().is_ok_and(|kind| kind.is_symlink());
+                if recursive && file.directory && !symlink {
+                    pending.push(name);
+                }
+                listed.push(file);
+            }
+        }
+        Ok((listed, false))
+    }
+}
+
+/// Name a fetched path is received under
+pub fn received_name(path: &Path) -> Result<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .context("Cannot fetch a filesystem root")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::FileTransferConfig;
+
+    #[test]
+    fn test_resolve_exports() {
+        let exported = tempfile::tempdir().unwrap();
+        let private = tempfile::tempdir().unwrap();
+        fs::create_dir(exported.path().join("models")).unwrap();
+        fs::write(exported.path().join("models/a.bin"), "a").unwrap();
+        fs::write(private.path().join("secret"), "s").unwrap();
+        std::os::unix::fs::symlink(private.path().join("secret"), exported.path().join("link")).unwrap();
+
+        let manager = Arc::new(FileTransferManager::new(FileTransferConfig::default()));
+        let exports = FileExports::new(vec![exported.path().to_path_buf(), PathBuf::from("/nonexistent/export")], manager);
+        let root = exported.path().canonicalize().unwrap();
+
+        assert_eq!(exports.resolve("models/a.bin").unwrap(), root.join("models/a.bin"));
+        assert_eq!(exports.resolve(root.join("models").to_str().unwrap()).unwrap(), root.join("models"));
+        assert_eq!(
+            exports.resolve("../secret").unwrap_err().to_string(),
+            "../secret does not exist"
+        );
+        let outside = private.path().join("secret");
+        assert!(exports.resolve(outside.to_str().unwrap()).unwrap_err().to_string().ends_with("is not exported"));
+        assert!(exports.resolve("link").unwrap_err().to_string().ends_with("is not exported"));
+        assert!(exports.resolve("models/../../").is_err());
+    }
+
+    #[test]
+    fn test_list_exports() {
+        let exported = tempfile::tempdir().unwrap();
+        let private = tempfile::tempdir().unwrap();
+        fs::create_dir_all(exported.path().join("models/llama")).unwrap();
+        fs::write(exported.path().join("models/a.bin"), "a").unwrap();
+        fs::write(exported.path().join("models/llama/weights.bin"), "weights").unwrap();
+        fs::write(private.path().join("secret"), "s").unwrap();
+        std::os::unix::fs::symlink(private.path().join("secret"), exported.path().join("models/link")).unwrap();
+
+        let manager = Arc::new(FileTransferManager::new(FileTransferConfig::default()));
+        let exports = FileExports::new(vec![exported.path().to_path_buf()], manager);
+
+        let (roots, _) = exports.list("", false, false).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].path, exported.path().canonicalize().unwrap().display().to_string());
+
+        let (files, truncated) = exports.list("models", false, true).unwrap();
+        assert!(!truncated);
+        let names: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(names, ["a.bin", "llama"]);
+        assert_eq!((files[0].size, files[1].directory), (1, true));
+        assert_eq!(files[0].hash.as_deref(), Some("ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb"));
+        assert_eq!(files[1].hash, None);
+
+        let (files, _) = exports.list("models", true, false).unwrap();
+        assert!(files.iter().any(|file| file.path == "llama/weights.bin" && file.size == 7));
+        assert!(exports.list("models/a.bin", false, false).is_err());
+        assert!(exports.list("..", false, false).is_err());
+    }
+}
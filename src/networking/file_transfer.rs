@@ -3,23 +3,83 @@ use futures_util::StreamExt;
 use log::{debug, error, info, warn};
 use std::fs::{self, File};
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::net::SocketAddr;
+use std::net::{Ipv6Addr, SocketAddr};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpStream;
 use tokio::sync::{mpsc, Mutex};
 use uuid::Uuid;
 use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
 use serde_json;
 use std::io::BufReader;
 use sha2::{Sha256, Digest};
+use std::os::unix::fs::{FileExt, PermissionsExt};
+use crate::updater::{hmac_sha256, RateLimit};
+use super::bandwidth::{StreamThrottle, Throttles};
+use super::control::{TransferControls, TransferState, TransferWatch};
+use super::offers::TransferOffers;
+use super::peer_auth::{PeerAuth, TRANSFER_PROOF};
+use super::receive_policy::{Admissions, IncomingFile, ReceivePolicy};
+use super::content_store::{self, ContentStore};
+use super::history::{TransferHistory, TransferRecord};
+use super::zero_copy;
+use super::benchmark::{self, BenchmarkLatency, BenchmarkPlan, BenchmarkReport, BenchmarkRun};
+use super::capabilities;
+use super::rdma::{self, RdmaReceiver, RdmaSender};
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use super::uring::{self, RangeStream};
+use super::fetch::received_name;
+use super::{NodeClient, NodeInfo};
+use crate::updater::free_space;
+use super::delta_sync::{self, DeltaOp, Signatures, BlockSignature, STRONG_HASH_LEN};
+use super::manifest::{apply_metadata, mtime_of, safe_relative_path, TransferManifest};
 
 // Constants for file transfer
 const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024; // 1MB chunks
 const DEFAULT_PORT: u16 = 7879;
 const BUFFER_POOL_SIZE: usize = 8; // Number of reusable buffers
 
+// Handshake before each transfer stream
+const CHALLENGE_LEN: usize = 32;
+const MAX_NODE_ID_LEN: usize = 256;
+const MAX_MAC_LEN: usize = 64;
+const MAX_KEY_LEN: usize = 128;
+const HANDSHAKE_ACCEPTED: u8 = 0;
+const HANDSHAKE_REJECTED: u8 = 1;
+
+// Frame types sent after the handshake
+const FRAME_FILE_RANGE: u8 = 0;
+const FRAME_MANIFEST: u8 = 1;
+const FRAME_MANIFEST_COMPLETE: u8 = 2;
+const FRAME_CANCEL: u8 = 3;
+const FRAME_DELTA_SYNC: u8 = 4;
+/// A file range with an RDMA offer after the header, see `rdma`
+const FRAME_RDMA_RANGE: u8 = 5;
+/// Pings and data from memory, see `benchmark`
+const FRAME_BENCHMARK: u8 = 6;
+/// A delta sync the receiver skips when its copy is as new or newer, see `folder_sync`
+const FRAME_FOLDER_SYNC: u8 = 7;
+/// Removal of a file deleted from a synchronized folder, see `folder_sync`
+const FRAME_FOLDER_DELETE: u8 = 8;
+
+// Delta sync ops, see `FileTransferManager::sync_file`
+const OP_COPY: u8 = 0;
+const OP_LITERAL: u8 = 1;
+const OP_END: u8 = 2;
+const MAX_LITERAL_LEN: usize = 64 * 1024 * 1024;
+const MAX_FILE_NAME_LEN: usize = 4096;
+const MAX_MANIFEST_LEN: usize = 64 * 1024 * 1024;
+const TRANSFER_ACK: u8 = 0;
+/// Sent instead of an ACK with a reason when the receive policy refuses a file
+const TRANSFER_REFUSED: u8 = 1;
+/// Sent instead of an ACK for a folder sync when the receiver's copy is as new or newer
+const TRANSFER_CURRENT: u8 = 2;
+/// SHA256 digest the sender appends to every range
+const RANGE_DIGEST_LEN: usize = 32;
+
 /// Status of a file transfer, reported via progress callback
 #[derive(Debug, Clone)]
 pub enum TransferStatus {
@@ -48,10 +108,14 @@ pub enum TransferStatus {
         file_id: String,
         error: String,
     },
+    /// Transfer cancelled and its partial data removed
+    Cancelled {
+        file_id: String,
+    },
 }
 
 /// Direction of file transfer
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TransferDirection {
     /// Sending a file
     Send,
@@ -59,6 +123,16 @@ pub enum TransferDirection {
     Receive,
 }
 
+/// Outcome of a delta sync
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncStats {
+    pub file_size: u64,
+    /// Bytes the receiver copied from its existing copy
+    pub matched_bytes: u64,
+    /// Bytes sent over the network
+    pub literal_bytes: u64,
+}
+
 /// Type of progress callback for file transfers
 pub type ProgressCallback = Arc<dyn Fn(TransferStatus) + Send + Sync>;
 
@@ -75,6 +149,27 @@ pub struct FileTransferConfig {
     pub progress_callback: Option<ProgressCallback>,
     /// Number of concurrent transfer streams
     pub concurrent_streams: usize,
+    /// This node's discovery ID, presented to receivers when sending
+    pub node_id: Option<String>,
+    /// Secret shared by the nodes allowed to exchange files; incoming transfers
+    /// are not authenticated when None
+    pub shared_secret: Option<String>,
+    /// Node IDs allowed to send files to this node; any authenticated sender when None
+    pub allowed_senders: Option<Vec<String>>,
+    /// Identity key senders prove they hold in the handshake, and receivers
+    /// check the proofs of; senders are not asked for one when None
+    pub peer_auth: Option<PeerAuth>,
+    /// Only accept transfers offered over gRPC beforehand, see `accept_offer`
+    pub require_offer: bool,
+    /// Limits on the files this node accepts
+    pub receive_policy: ReceivePolicy,
+    /// Bandwidth cap for each transfer; unlimited when None
+    pub transfer_limit: Option<RateLimit>,
+    /// Bandwidth cap across all transfers, applied to sending and receiving
+    /// separately; unlimited when None
+    pub total_limit: Option<RateLimit>,
+    /// File the transfer history is kept in; in memory only when None
+    pub history_file: Option<PathBuf>,
 }
 
 impl Default for FileTransferConfig {
@@ -85,6 +180,15 @@ impl Default for FileTransferConfig {
             receive_dir: std::env::temp_dir().join("node_controller_files"),
             progress_callback: None,
             concurrent_streams: 4, // Default to 4 concurrent streams
+            node_id: None,
+            shared_secret: None,
+            allowed_senders: None,
+            peer_auth: None,
+            require_offer: false,
+            receive_policy: ReceivePolicy::default(),
+            transfer_limit: None,
+            total_limit: None,
+            history_file: None,
         }
     }
 }
@@ -93,8 +197,15 @@ impl Default for FileTransferConfig {
 pub struct FileTransferManager {
     config: FileTransferConfig,
     server_address: Arc<Mutex<Option<SocketAddr>>>,
-    shutdown_sender: Option<mpsc::Sender<()>>,
+    shutdown_sender: Mutex<Option<mpsc::Sender<()>>>,
     buffer_pool: Arc<Mutex<Vec<Vec<u8>>>>,
+    send_throttles: Throttles,
+    receive_throttles: Throttles,
+    controls: TransferControls,
+    offers: TransferOffers,
+    admissions: Admissions,
+    store: ContentStore,
+    history: TransferHistory,
 }
 
 impl FileTransferManager {
@@ -118,22 +229,38 @@ impl FileTransferManager {
         }
 
         Self {
+            send_throttles: Throttles::new(config.transfer_limit, config.total_limit),
+            receive_throttles: Throttles::new(config.transfer_limit, config.total_limit),
+            controls: TransferControls::default(),
+            offers: TransferOffers::default(),
+            admissions: Admissions::default(),
+            store: ContentStore::open(&config.receive_dir),
+            history: TransferHistory::open(config.history_file.as_deref()),
             config,
             server_address: Arc::new(Mutex::new(None)),
-            shutdown_sender: None,
+            shutdown_sender: Mutex::new(None),
             buffer_pool: Arc::new(Mutex::new(buffer_pool)),
         }
     }
 
     /// Start the file transfer server
     pub async fn start_server(&mut self) -> Result<SocketAddr> {
+        // Without a secret or a required key the sender's node ID is just a claim
+        let keys_required = self.config.peer_auth.as_ref().is_some_and(PeerAuth::is_required);
+        if self.config.allowed_senders.is_some() && self.config.shared_secret.is_none() && !keys_required {
+            return Err(anyhow!("A sender allowlist requires a shared secret or required peer keys"));
+        }
+        if self.config.shared_secret.is_none() && !keys_required {
+            warn!("File transfer server accepts files from any host: no shared secret configured");
+        }
+
         // Create a channel to signal shutdown
         let (tx, mut rx) = mpsc::channel(1);
-        self.shutdown_sender = Some(tx);
+        *self.shutdown_sender.get_mut() = Some(tx);
 
         // Attempt to bind to the configured port
-        let addr = SocketAddr::from(([0, 0, 0, 0], self.config.port));
-        let listener = TcpListener::bind(addr).await?;
+        let addr = SocketAddr::from((Ipv6Addr::UNSPECIFIED, self.config.port));
+        let listener = zero_copy::listen(addr)?;
         let server_addr = listener.local_addr()?;
         
         // Store the server address
@@ -147,6 +274,15 @@ impl FileTransferManager {
         // Clone necessary items for the server task
         let config = self.config.clone();
         let buffer_pool = self.buffer_pool.clone();
+        let receiver = Receiver {
+            sender: String::new(),
+            throttles: self.receive_throttles.clone(),
+            controls: self.controls.clone(),
+            offers: self.offers.clone(),
+            admissions: self.admissions.clone(),
+            store: self.store.clone(),
+            history: self.history.clone(),
+        };
 
         // Spawn the server task
         tokio::spawn(async move {
@@ -157,14 +293,18 @@ impl FileTransferManager {
                         match conn_result {
                             Ok((socket, addr)) => {
                                 info!("New file transfer connection from {}", addr);
+                                if let Err(e) = socket.set_nodelay(true) {
+                                    warn!("Failed to set TCP_NODELAY for {}: {}", addr, e);
+                                }
                                 
                                 // Clone items needed for the handler
                                 let handler_config = config.clone();
                                 let handler_pool = buffer_pool.clone();
+                                let handler_receiver = receiver.clone();
                                 
                                 // Spawn a task to handle this connection
                                 tokio::spawn(async move {
-                                    if let Err(e) = handle_incoming_file(socket, handler_config, handler_pool).await {
+                                    if let Err(e) = handle_connection(socket, handler_config, handler_pool, handler_receiver).await {
                                         error!("Error handling file transfer from {}: {}", addr, e);
                                     }
                                 });
@@ -188,15 +328,16 @@ impl FileTransferManager {
     }
 
     /// Stop the file transfer server
-    pub async fn stop_server(&mut self) {
-        if let Some(tx) = self.shutdown_sender.take() {
+    pub async fn stop_server(&self) {
+        let sender = self.shutdown_sender.lock().await.take();
+        if let Some(tx) = sender {
             let _ = tx.send(()).await;
             info!("Sent shutdown signal to file transfer server");
         }
     }
 
     /// Calculate SHA256 hash of a file
-    fn calculate_file_hash(path: &Path) -> Result<String> {
+    pub(crate) fn calculate_file_hash(path: &Path) -> Result<String> {
         let file = File::open(path)?;
         let mut reader = BufReader::new(file);
         let mut hasher = Sha256::new();
@@ -214,10 +355,152 @@ impl FileTransferManager {
         Ok(format!("{:x}", hash))
     }
 
-    /// Send a file to a remote node
+    /// Send a file, or a directory with everything under it, to a remote node
     pub async fn send_file<P: AsRef<Path>>(&self, path: P, target_addr: SocketAddr) -> Result<String> {
+        self.send_to(path.as_ref(), &Destination { addr: target_addr, token: None, node_id: None, rdma: false }).await
+    }
+
+    /// Offer a file or directory to `node` over gRPC and send it to the port
+    /// the node returns, with the token it issued
+    pub async fn send_to_node<P: AsRef<Path>>(
+        &self,
+        client: &NodeClient,
+        node: &NodeInfo,
+        local_node: &NodeInfo,
+        path: P,
+    ) -> Result<String> {
         let path = path.as_ref();
-        
+        let target = offer_to(client, node, local_node, path).await?;
+        self.send_to(path, &target).await
+    }
+
+    /// Offer a file to `node` over gRPC and synchronize it with the node's copy
+    pub async fn sync_to_node<P: AsRef<Path>>(
+        &self,
+        client: &NodeClient,
+        node: &NodeInfo,
+        local_node: &NodeInfo,
+        path: P,
+    ) -> Result<SyncStats> {
+        let path = path.as_ref();
+        let target = offer_to(client, node, local_node, path).await?;
+        self.sync_to(path, &target).await
+    }
+
+    /// Offer a file of a synchronized folder to `node` and bring its copy at
+    /// `name` up to date, unless that copy is as new or newer (newest wins);
+    /// None when the node kept it
+    pub async fn sync_newest_to_node(
+        &self,
+        client: &NodeClient,
+        node: &NodeInfo,
+        local_node: &NodeInfo,
+        path: &Path,
+        name: &str,
+    ) -> Result<Option<SyncStats>> {
+        if node.capability(capabilities::FOLDER_SYNC).is_none() {
+            return Err(anyhow!("{} does not accept folder sync", node.name));
+        }
+        let size = fs::metadata(path)
+            .with_context(|| format!("Failed to get metadata for file {}", path.display()))?
+            .len();
+        let target = offer(client, node, local_node, name, size, false).await?;
+        self.sync_as(path, name, &target, FRAME_FOLDER_SYNC).await
+    }
+
+    /// Delete the copy of `name` on `node`, a file deleted from a synchronized
+    /// folder at `deleted_at`, unless the node modified it since; whether the
+    /// node no longer has it
+    pub async fn delete_on_node(
+        &self,
+        client: &NodeClient,
+        node: &NodeInfo,
+        local_node: &NodeInfo,
+        name: &str,
+        deleted_at: SystemTime,
+    ) -> Result<bool> {
+        if node.capability(capabilities::FOLDER_SYNC).is_none() {
+            return Err(anyhow!("{} does not accept folder sync", node.name));
+        }
+        let target = offer(client, node, local_node, name, 0, false).await?;
+        self.delete_on(name, deleted_at, &target).await
+    }
+
+    async fn delete_on(&self, name: &str, deleted_at: SystemTime, target: &Destination) -> Result<bool> {
+        let deleted_at = deleted_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut socket = open_stream(target, &self.config, FRAME_FOLDER_DELETE).await?;
+        write_field(&mut socket, name.as_bytes()).await?;
+        socket.write_all(&deleted_at.as_secs().to_be_bytes()).await?;
+        socket.write_all(&deleted_at.subsec_nanos().to_be_bytes()).await?;
+        expect_admission(&mut socket, "deletion").await
+    }
+
+    /// Accept or refuse a transfer of `size` bytes offered by `sender_id`,
+    /// returning the port of the transfer server and the token to present
+    pub async fn accept_offer(&self, sender_id: &str, size: u64) -> Result<(u16, String)> {
+        let port = self.server_address().await
+            .ok_or_else(|| anyhow!("File transfer server is not running"))?
+            .port();
+        if !self.config.allowed_senders.as_ref().is_none_or(|allowed| allowed.iter().any(|id| id == sender_id)) {
+            return Err(anyhow!("Node {} is not in the sender allowlist", sender_id));
+        }
+        match free_space(&self.config.receive_dir).await {
+            Ok((_, available)) if available < size => {
+                return Err(anyhow!(
+                    "Not enough free space: {} MiB offered, {} MiB available",
+                    size / (1024 * 1024), available / (1024 * 1024)
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Could not check free space for an offered transfer: {}", e),
+        }
+        Ok((port, self.offers.issue(sender_id)))
+    }
+
+    async fn send_to(&self, path: &Path, target: &Destination) -> Result<String> {
+        let metadata = fs::metadata(path)
+            .with_context(|| format!("Failed to get metadata for file {}", path.display()))?;
+        if metadata.is_dir() {
+            return self.send_directory(path, target).await;
+        }
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid file path"))?
+            .to_string_lossy()
+            .to_string();
+        self.send_single_file(path, file_name, target).await
+    }
+
+    /// Send a directory: its manifest first, then every file, then a frame
+    /// telling the receiver to verify the tree and restore directory metadata
+    async fn send_directory(&self, root: &Path, target: &Destination) -> Result<String> {
+        let manifest = TransferManifest::from_directory(Uuid::new_v4().to_string(), root)?;
+        let file_count = manifest.files(root).count();
+        info!(
+            "Sending directory {} ({} entries, {} files)",
+            root.display(), manifest.entries.len(), file_count
+        );
+
+        let mut socket = open_stream(target, &self.config, FRAME_MANIFEST).await?;
+        write_field(&mut socket, &serde_json::to_vec(&manifest)?).await?;
+        expect_ack(&mut socket, "manifest").await?;
+
+        for (entry, path) in manifest.files(root) {
+            self.send_single_file(&path, entry.path.clone(), target).await
+                .with_context(|| format!("Failed to send {}", entry.path))?;
+        }
+
+        let mut socket = open_stream(target, &self.config, FRAME_MANIFEST_COMPLETE).await?;
+        write_field(&mut socket, manifest.id.as_bytes()).await?;
+        expect_ack(&mut socket, "directory").await?;
+
+        info!("Directory transfer complete: {} ({} files)", root.display(), file_count);
+        Ok(manifest.id)
+    }
+
+    /// Send one file, stored as `file_name` relative to the receive directory
+    async fn send_single_file(&self, path: &Path, file_name: String, target: &Destination) -> Result<String> {
         // Generate a unique ID for this transfer
         let file_id = Uuid::new_v4().to_string();
         
@@ -226,16 +509,9 @@ impl FileTransferManager {
             .with_context(|| format!("Failed to get metadata for file {}", path.display()))?;
         
         let file_size = metadata.len();
-        let file_name = path
-            .file_name()
-            .ok_or_else(|| anyhow!("Invalid file path"))?
-            .to_string_lossy()
-            .to_string();
 
-        // Calculate file hash (for integrity verification)
-        let file_hash = Self::calculate_file_hash(path)
-            .with_context(|| format!("Failed to calculate hash for file {}", path.display()))?;
-        info!("File hash (SHA256): {}", file_hash);
+        // Registered before the start is reported so the callback can already cancel
+        let control = self.controls.watch(&file_id);
 
         // Notify of transfer start
         if let Some(callback) = &self.config.progress_callback {
@@ -251,7 +527,8 @@ impl FileTransferManager {
 
         // Open connections for transfer (multiple streams for parallelism)
         let mut handles = vec![];
-        let chunk_count = (file_size as f64 / self.config.chunk_size as f64).ceil() as u64;
+        // Empty files still need one (empty) range so the receiver creates them
+        let chunk_count = ((file_size as f64 / self.config.chunk_size as f64).ceil() as u64).max(1);
         let chunks_per_stream = (chunk_count as f64 / self.config.concurrent_streams as f64).ceil() as u64;
 
         // Track total bytes sent for progress updates
@@ -305,12 +582,17 @@ impl FileTransferManager {
             
             // Clone required values
             let path = path.to_path_buf();
-            let target = target_addr;
-            let chunk_size = self.config.chunk_size;
-            let file_id = file_id.clone();
-            let file_name = file_name.clone();
+            let target = target.clone();
+            let config = self.config.clone();
+            let throttle = self.send_throttles.stream(&file_id);
+            let stream_control = self.controls.watch(&file_id);
+            let range = FileRange {
+                file_id: file_id.clone(),
+                file_name: file_name.clone(),
+                start_pos,
+                end_pos,
+            };
             let bytes_sent = total_bytes_sent.clone();
-            let file_hash_clone = file_hash.clone();
             
             // Spawn a task for this stream
             let handle = tokio::spawn(async move {
@@ -319,14 +601,12 @@ impl FileTransferManager {
                 
                 let result = send_file_range(
                     &path,
-                    target,
-                    file_id,
-                    file_name,
-                    start_pos,
-                    end_pos,
-                    chunk_size,
+                    &target,
+                    &config,
+                    range,
                     bytes_sent,
-                    file_hash_clone,
+                    throttle,
+                    stream_control,
                 ).await;
                 
                 if let Err(e) = &result {
@@ -345,10 +625,12 @@ impl FileTransferManager {
         let mut success = true;
         let mut errors = Vec::new();
         
+        let mut range_digests = Vec::new();
         for (i, handle) in handles.into_iter().enumerate() {
             match handle.await {
-                Ok(Ok(_)) => {
+                Ok(Ok(digest)) => {
                     // This stream completed successfully
+                    range_digests.push(digest);
                 }
                 Ok(Err(e)) => {
                     success = false;
@@ -366,6 +648,23 @@ impl FileTransferManager {
             handle.abort();
         }
         
+        if control.is_cancelled() {
+            // Have the receiver drop what it got so far
+            if let Err(e) = send_cancel(target, &self.config, &file_id, &file_name).await {
+                warn!("Failed to notify receiver of cancelled transfer {}: {}", file_id, e);
+            }
+            if let Some(callback) = &self.config.progress_callback {
+                callback(TransferStatus::Cancelled { file_id: file_id.clone() });
+            }
+            info!("File transfer cancelled: {}", path.display());
+            let error = anyhow!("Transfer {} was cancelled", file_id);
+            self.history.record(TransferRecord::new(
+                &file_id, &file_name, TransferDirection::Send, &target.peer(), file_size,
+                start_time.elapsed(), Err(error.to_string()),
+            ));
+            return Err(error);
+        }
+        
         // Calculate final statistics
         let elapsed = start_time.elapsed();
         let elapsed_secs = elapsed.as_secs_f32();
@@ -393,16 +692,341 @@ impl FileTransferManager {
             }
         }
 
-        if success {
-            info!(
-                "File transfer complete: {} ({:.2} MB/s)",
-                path.display(),
-                throughput
-            );
-            Ok(file_id)
+        let outcome = if success {
+            Ok(combine_digests(&range_digests))
         } else {
-            Err(anyhow!("File transfer failed"))
+            Err(format!("File transfer failed: {}", errors.join(", ")))
+        };
+        self.history.record(TransferRecord::new(
+            &file_id, &file_name, TransferDirection::Send, &target.peer(), file_size, elapsed, outcome.clone(),
+        ));
+
+        match outcome {
+            Ok(digest) => {
+                info!("File transfer complete: {} ({:.2} MB/s), digest {}", path.display(), throughput, digest);
+                Ok(file_id)
+            }
+            Err(e) => Err(anyhow!(e)),
+        }
+    }
+
+    /// Send a file or directory to every destination in `targets` at once,
+    /// reading each chunk from disk once for all of them; one outcome per
+    /// destination, in order. Fails as a whole only when `path` can't be read.
+    pub(super) async fn send_to_all(&self, path: &Path, targets: &[Destination]) -> Result<Vec<Result<String>>> {
+        let metadata = fs::metadata(path)
+            .with_context(|| format!("Failed to get metadata for file {}", path.display()))?;
+        if metadata.is_dir() {
+            return self.broadcast_directory(path, targets).await;
+        }
+        let targets: Vec<&Destination> = targets.iter().collect();
+        self.broadcast_single_file(path, received_name(path)?, &targets).await
+    }
+
+    /// Send a directory to every destination in `targets`: the manifest to
+    /// each, then every file to those still taking part, then the frame that
+    /// completes the directory
+    async fn broadcast_directory(&self, root: &Path, targets: &[Destination]) -> Result<Vec<Result<String>>> {
+        let manifest = TransferManifest::from_directory(Uuid::new_v4().to_string(), root)?;
+        let manifest_json = serde_json::to_vec(&manifest)?;
+        info!("Broadcasting directory {} ({} entries) to {} nodes", root.display(), manifest.entries.len(), targets.len());
+
+        let mut outcomes = futures_util::future::join_all(targets.iter().map(|target| async {
+            let mut socket = open_stream(target, &self.config, FRAME_MANIFEST).await?;
+            write_field(&mut socket, &manifest_json).await?;
+            expect_ack(&mut socket, "manifest").await?;
+            Ok(manifest.id.clone())
+        })).await;
+
+        for (entry, path) in manifest.files(root) {
+            let live: Vec<usize> = (0..targets.len()).filter(|&i| outcomes[i].is_ok()).collect();
+            if live.is_empty() {
+                break;
+            }
+            let live_targets: Vec<&Destination> = live.iter().map(|&i| &targets[i]).collect();
+            let sent = self.broadcast_single_file(&path, entry.path.clone(), &live_targets).await?;
+            for (i, result) in live.into_iter().zip(sent) {
+                if let Err(e) = result {
+                    outcomes[i] = Err(e.context(format!("Failed to send {}", entry.path)));
+                }
+            }
+        }
+
+        let completions = futures_util::future::join_all(targets.iter().zip(&outcomes).map(|(target, outcome)| async {
+            outcome.as_ref().map_err(|e| anyhow!("{:#}", e))?;
+            let mut socket = open_stream(target, &self.config, FRAME_MANIFEST_COMPLETE).await?;
+            write_field(&mut socket, manifest.id.as_bytes()).await?;
+            expect_ack(&mut socket, "directory").await?;
+            Ok(manifest.id.clone())
+        })).await;
+        Ok(completions)
+    }
+
+    /// Send one file to every destination in `targets`, each over a single
+    /// stream: every chunk is read from disk and hashed once, then written to
+    /// all streams still open. A destination that fails drops out without
+    /// stopping the others.
+    async fn broadcast_single_file(&self, path: &Path, file_name: String, targets: &[&Destination]) -> Result<Vec<Result<String>>> {
+        let file_id = Uuid::new_v4().to_string();
+        let file = tokio::fs::File::open(path).await
+            .with_context(|| format!("Failed to open {}", path.display()))?
+            .into_std().await;
+        let file_size = file.metadata()?.len();
+
+        let mut control = self.controls.watch(&file_id);
+        let throttle = self.send_throttles.stream(&file_id);
+        if let Some(callback) = &self.config.progress_callback {
+            callback(TransferStatus::Started {
+                file_id: file_id.clone(),
+                file_name: file_name.clone(),
+                file_size,
+            });
+        }
+        let start_time = std::time::Instant::now();
+
+        let range = FileRange { file_id: file_id.clone(), file_name: file_name.clone(), start_pos: 0, end_pos: file_size };
+        let mut streams = futures_util::future::join_all(
+            targets.iter().map(|target| open_range(target, &self.config, &file, &range, FRAME_FILE_RANGE))
+        ).await;
+
+        let file = Arc::new(file);
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; self.config.chunk_size];
+        let mut position = 0;
+        let mut cancelled = false;
+        while position < file_size && streams.iter().any(Result::is_ok) {
+            if control.proceed().await.is_err() {
+                cancelled = true;
+                break;
+            }
+
+            let n = std::cmp::min(self.config.chunk_size as u64, file_size - position) as usize;
+            let read_file = file.clone();
+            let (read, returned) = tokio::task::spawn_blocking(move || {
+                let read = read_file.read_exact_at(&mut buffer[..n], position);
+                (read, buffer)
+            }).await?;
+            buffer = returned;
+            read.with_context(|| format!("Failed to read {}", path.display()))?;
+            hasher.update(&buffer[..n]);
+
+            let chunk = &buffer[..n];
+            futures_util::future::join_all(streams.iter_mut().map(|stream| async move {
+                let written = match stream {
+                    Ok(socket) => socket.write_all(chunk).await,
+                    Err(_) => return,
+                };
+                if let Err(e) = written {
+                    *stream = Err(anyhow!(e).context("Failed to send file data"));
+                }
+            })).await;
+            position += n as u64;
+            throttle.consume(n * streams.iter().filter(|stream| stream.is_ok()).count()).await;
+
+            if let Some(callback) = &self.config.progress_callback {
+                callback(TransferStatus::Progress {
+                    file_id: file_id.clone(),
+                    bytes_transferred: position,
+                    total_bytes: file_size,
+                    percent_complete: (position as f32 / file_size as f32) * 100.0,
+                });
+            }
+        }
+
+        let digest = hasher.finalize().to_vec();
+        let outcomes: Vec<Result<String>> = futures_util::future::join_all(targets.iter().zip(streams).map(|(target, stream)| async {
+            let mut socket = stream?;
+            if cancelled {
+                // Have the receiver drop what it got so far
+                drop(socket);
+                if let Err(e) = send_cancel(target, &self.config, &file_id, &file_name).await {
+                    warn!("Failed to notify receiver of cancelled transfer {}: {}", file_id, e);
+                }
+                return Err(anyhow!("Transfer {} was cancelled", file_id));
+            }
+            socket.write_all(&digest).await?;
+            expect_ack(&mut socket, "range").await?;
+            Ok(file_id.clone())
+        })).await;
+
+        let elapsed = start_time.elapsed();
+        let combined = combine_digests(std::slice::from_ref(&digest));
+        for (target, outcome) in targets.iter().zip(&outcomes) {
+            self.history.record(TransferRecord::new(
+                &file_id, &file_name, TransferDirection::Send, &target.peer(), file_size, elapsed,
+                outcome.as_ref().map(|_| combined.clone()).map_err(|e| format!("{:#}", e)),
+            ));
+        }
+
+        let errors: Vec<String> = outcomes.iter().filter_map(|outcome| outcome.as_ref().err()).map(|e| format!("{:#}", e)).collect();
+        if let Some(callback) = &self.config.progress_callback {
+            if cancelled {
+                callback(TransferStatus::Cancelled { file_id: file_id.clone() });
+            } else if errors.is_empty() {
+                let elapsed_secs = elapsed.as_secs_f32();
+                callback(TransferStatus::Completed {
+                    file_id: file_id.clone(),
+                    bytes_transferred: file_size,
+                    elapsed_seconds: elapsed_secs,
+                    throughput_mbps: if elapsed_secs > 0.0 { (file_size as f32 / elapsed_secs) / (1024.0 * 1024.0) } else { 0.0 },
+                });
+            } else {
+                callback(TransferStatus::Failed { file_id: file_id.clone(), error: errors.join(", ") });
+            }
+        }
+        info!(
+            "Broadcast of {} reached {} of {} nodes, digest {}",
+            path.display(), targets.len() - errors.len(), targets.len(), combined
+        );
+        Ok(outcomes)
+    }
+
+    /// Bring the receiver's copy of a file up to date, sending only the blocks
+    /// that differ from it. Without a copy on the receiver the whole file is sent.
+    ///
+    /// The receiver answers the header with signatures of the blocks of its
+    /// copy; the sender replies with ops that copy one of those blocks or carry
+    /// literal data, and the receiver rebuilds the file next to the old one,
+    /// checks its hash and swaps it in.
+    pub async fn sync_file<P: AsRef<Path>>(&self, path: P, target_addr: SocketAddr) -> Result<SyncStats> {
+        self.sync_to(path.as_ref(), &Destination { addr: target_addr, token: None, node_id: None, rdma: false }).await
+    }
+
+    async fn sync_to(&self, path: &Path, target: &Destination) -> Result<SyncStats> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid file path"))?
+            .to_string_lossy()
+            .to_string();
+        self.sync_as(path, &file_name, target, FRAME_DELTA_SYNC).await?
+            .ok_or_else(|| anyhow!("Receiver did not accept the file"))
+    }
+
+    /// Delta sync `path` to the receiver's copy at `file_name`, opening the
+    /// stream with `frame`; None when a folder sync receiver kept its copy
+    async fn sync_as(&self, path: &Path, file_name: &str, target: &Destination, frame: u8) -> Result<Option<SyncStats>> {
+        let metadata = fs::metadata(path)
+            .with_context(|| format!("Failed to get metadata for file {}", path.display()))?;
+        if !metadata.is_file() {
+            return Err(anyhow!("Delta sync only supports files, not {}", path.display()));
+        }
+        let file_size = metadata.len();
+        let file_name = file_name.to_string();
+        let file_id = Uuid::new_v4().to_string();
+
+        // Header, answered by the signatures of the receiver's copy
+        let mut control = self.controls.watch(&file_id);
+        let mut socket = open_stream(target, &self.config, frame).await?;
+        write_field(&mut socket, file_id.as_bytes()).await?;
+        write_field(&mut socket, file_name.as_bytes()).await?;
+        socket.write_all(&file_size.to_be_bytes()).await?;
+        let (mtime_secs, mtime_nanos) = mtime_of(&metadata);
+        socket.write_all(&(metadata.permissions().mode() & 0o777).to_be_bytes()).await?;
+        socket.write_all(&mtime_secs.to_be_bytes()).await?;
+        socket.write_all(&mtime_nanos.to_be_bytes()).await?;
+        if !expect_admission(&mut socket, "file").await? {
+            debug!("{} keeps its copy of {}", target.peer(), file_name);
+            return Ok(None);
+        }
+
+        if let Some(callback) = &self.config.progress_callback {
+            callback(TransferStatus::Started {
+                file_id: file_id.clone(),
+                file_name: file_name.clone(),
+                file_size,
+            });
+        }
+        let start_time = std::time::Instant::now();
+        let signatures = read_signatures(&mut socket).await?;
+
+        let delta_path = path.to_path_buf();
+        let block_count = signatures.blocks.len();
+        // Hashed in the same pass, and sent once the receiver has rebuilt the file
+        let (ops, file_hash) = tokio::task::spawn_blocking(move || delta_sync::compute_delta(&delta_path, &signatures))
+            .await??;
+
+        // Stream the ops, reading literal data from the file as it goes
+        let throttle = self.send_throttles.stream(&file_id);
+        let mut file = File::open(path)?;
+        let mut buffer = vec![0u8; self.config.chunk_size];
+        let mut literal_bytes = 0u64;
+        let result: Result<()> = async {
+            for op in &ops {
+                control.proceed().await?;
+                match *op {
+                    DeltaOp::Copy(index) => {
+                        socket.write_all(&[OP_COPY]).await?;
+                        socket.write_all(&index.to_be_bytes()).await?;
+                    }
+                    DeltaOp::Literal { offset, len } => {
+                        file.seek(SeekFrom::Start(offset))?;
+                        let mut remaining = len;
+                        while remaining > 0 {
+                            let n = std::cmp::min(buffer.len() as u64, remaining) as usize;
+                            file.read_exact(&mut buffer[..n])?;
+                            socket.write_all(&[OP_LITERAL]).await?;
+                            socket.write_all(&(n as u32).to_be_bytes()).await?;
+                            socket.write_all(&buffer[..n]).await?;
+                            throttle.consume(n).await;
+                            remaining -= n as u64;
+                            literal_bytes += n as u64;
+                        }
+                    }
+                }
+            }
+            socket.write_all(&[OP_END]).await?;
+            write_field(&mut socket, file_hash.as_bytes()).await?;
+            expect_ack(&mut socket, "synchronized file").await
+        }.await;
+
+        let outcome = result.as_ref().map(|_| file_hash.clone()).map_err(|e| e.to_string());
+        self.history.record(TransferRecord::new(
+            &file_id, &file_name, TransferDirection::Send, &target.peer(), file_size, start_time.elapsed(), outcome,
+        ));
+        if let Err(e) = result {
+            if let Some(callback) = &self.config.progress_callback {
+                callback(if control.is_cancelled() {
+                    TransferStatus::Cancelled { file_id: file_id.clone() }
+                } else {
+                    TransferStatus::Failed { file_id: file_id.clone(), error: e.to_string() }
+                });
+            }
+            return Err(e);
+        }
+
+        let elapsed_secs = start_time.elapsed().as_secs_f32();
+        if let Some(callback) = &self.config.progress_callback {
+            callback(TransferStatus::Completed {
+                file_id,
+                bytes_transferred: literal_bytes,
+                elapsed_seconds: elapsed_secs,
+                throughput_mbps: if elapsed_secs > 0.0 { (literal_bytes as f32 / elapsed_secs) / (1024.0 * 1024.0) } else { 0.0 },
+            });
         }
+        info!(
+            "Delta sync complete: {} ({} of {} bytes sent, {} blocks on receiver)",
+            path.display(), literal_bytes, file_size, block_count
+        );
+        Ok(Some(SyncStats {
+            file_size,
+            matched_bytes: file_size - literal_bytes,
+            literal_bytes,
+        }))
+    }
+
+    /// Stop a transfer, sending or receiving, and remove its partial data
+    pub fn cancel_transfer(&self, file_id: &str) -> Result<()> {
+        self.controls.set(file_id, TransferState::Cancelled)
+    }
+
+    /// Suspend a transfer until `resume_transfer` is called
+    pub fn pause_transfer(&self, file_id: &str) -> Result<()> {
+        self.controls.set(file_id, TransferState::Paused)
+    }
+
+    /// Continue a paused transfer
+    pub fn resume_transfer(&self, file_id: &str) -> Result<()> {
+        self.controls.set(file_id, TransferState::Running)
     }
 
     /// Get a buffer from the pool or create a new one if none are available
@@ -436,59 +1060,194 @@ impl FileTransferManager {
     pub fn receive_directory(&self) -> PathBuf {
         self.config.receive_dir.clone()
     }
+
+    /// A received file with SHA256 `hash`, if this node has one
+    pub fn find_by_hash(&self, hash: &str) -> Option<PathBuf> {
+        self.store.lookup(hash)
+    }
+
+    /// Completed and failed transfers, sent and received
+    pub fn history(&self) -> &TransferHistory {
+        &self.history
+    }
+
+    /// Measure round trips and throughput to the transfer server at
+    /// `target_addr`, see `benchmark`
+    pub async fn benchmark(&self, target_addr: SocketAddr, plan: &BenchmarkPlan) -> Result<BenchmarkReport> {
+        self.benchmark_to(&Destination { addr: target_addr, token: None, node_id: None, rdma: false }, plan).await
+    }
+
+    /// Measure round trips and throughput to `node` over the path a transfer
+    /// to it takes, once it accepted an empty offer
+    pub async fn benchmark_node(
+        &self,
+        client: &NodeClient,
+        node: &NodeInfo,
+        local_node: &NodeInfo,
+        plan: &BenchmarkPlan,
+    ) -> Result<BenchmarkReport> {
+        if node.capability(capabilities::BENCHMARK).is_none() {
+            return Err(anyhow!("{} does not accept benchmarks", node.name));
+        }
+        let target = offer(client, node, local_node, "benchmark", 0, false).await?;
+        self.benchmark_to(&target, plan).await
+    }
+
+    async fn benchmark_to(&self, target: &Destination, plan: &BenchmarkPlan) -> Result<BenchmarkReport> {
+        info!("Benchmarking the link to {}", target.peer());
+
+        // Round trips first, while the link carries nothing else
+        let mut socket = open_benchmark(target, &self.config, plan.pings, 0).await?;
+        let mut round_trips = Vec::with_capacity(plan.pings as usize);
+        for ping in 0..plan.pings {
+            let started = std::time::Instant::now();
+            socket.write_all(&[ping as u8]).await?;
+            let mut echo = [0u8; 1];
+            socket.read_exact(&mut echo).await?;
+            if echo[0] != ping as u8 {
+                return Err(anyhow!("Receiver answered ping {} with {}", ping as u8, echo[0]));
+            }
+            round_trips.push(started.elapsed());
+        }
+        expect_ack(&mut socket, "benchmark").await?;
+
+        let data = Arc::new(vec![0u8; self.config.chunk_size]);
+        let mut runs = Vec::with_capacity(plan.stream_counts.len());
+        for streams in plan.stream_counts.iter().map(|&streams| streams.max(1)) {
+            // Connected and authenticated before the clock starts
+            let shares = benchmark::split(plan.run_bytes, streams);
+            let mut sockets = Vec::with_capacity(shares.len());
+            for &share in &shares {
+                sockets.push(open_benchmark(target, &self.config, 0, share).await?);
+            }
+
+            let started = std::time::Instant::now();
+            let sends: Vec<_> = sockets.into_iter().zip(shares)
+                .map(|(socket, share)| tokio::spawn(send_benchmark_data(socket, data.clone(), share)))
+                .collect();
+            for send in sends {
+                send.await??;
+            }
+            let run = BenchmarkRun::new(streams, plan.run_bytes, started.elapsed());
+            info!("{} stream(s) to {}: {:.2} MB/s", run.streams, target.peer(), run.throughput_mbps);
+            runs.push(run);
+        }
+
+        Ok(BenchmarkReport { peer: target.peer(), latency: BenchmarkLatency::of(&round_trips), runs })
+    }
 }
 
-/// Handle an incoming file transfer
-async fn handle_incoming_file(
+/// Authenticate an incoming connection and dispatch on the frame it opens with
+async fn handle_connection(
     mut socket: TcpStream,
     config: FileTransferConfig,
     buffer_pool: Arc<Mutex<Vec<Vec<u8>>>>,
+    mut receiver: Receiver,
 ) -> Result<()> {
-    // Read the header (file ID, file name, and file size)
-    let mut id_len_buf = [0u8; 4];
-    socket.read_exact(&mut id_len_buf).await?;
-    let id_len = u32::from_be_bytes(id_len_buf) as usize;
-    
-    let mut id_buf = vec![0u8; id_len];
-    socket.read_exact(&mut id_buf).await?;
-    let file_id = String::from_utf8(id_buf)?;
-    
-    let mut name_len_buf = [0u8; 4];
-    socket.read_exact(&mut name_len_buf).await?;
-    let name_len = u32::from_be_bytes(name_len_buf) as usize;
-    
-    let mut name_buf = vec![0u8; name_len];
-    socket.read_exact(&mut name_buf).await?;
-    let file_name = String::from_utf8(name_buf)?;
-    
+    receiver.sender = authenticate_sender(&mut socket, &config, &receiver.offers).await?;
+    debug!("Accepted file transfer from node {}", receiver.sender);
+
+    let mut frame = [0u8; 1];
+    socket.read_exact(&mut frame).await?;
+    match frame[0] {
+        FRAME_FILE_RANGE => handle_incoming_file(socket, config, buffer_pool, &receiver, false).await,
+        FRAME_RDMA_RANGE => handle_incoming_file(socket, config, buffer_pool, &receiver, true).await,
+        FRAME_CANCEL => handle_cancel(socket, &config, &receiver).await,
+        FRAME_DELTA_SYNC => handle_delta_sync(socket, &config, &receiver, false).await,
+        FRAME_FOLDER_SYNC => handle_delta_sync(socket, &config, &receiver, true).await,
+        FRAME_FOLDER_DELETE => handle_folder_delete(socket, &config, &receiver).await,
+        FRAME_MANIFEST => handle_manifest(socket, &config).await,
+        FRAME_MANIFEST_COMPLETE => handle_manifest_complete(socket, &config).await,
+        FRAME_BENCHMARK => handle_benchmark(socket, &config, &receiver).await,
+        other => Err(anyhow!("Unknown file transfer frame {}", other)),
+    }
+}
+
+/// Create the tree of an incoming directory transfer and keep its manifest
+/// until the transfer completes
+async fn handle_manifest(mut socket: TcpStream, config: &FileTransferConfig) -> Result<()> {
+    let manifest: TransferManifest = serde_json::from_slice(&read_field(&mut socket, MAX_MANIFEST_LEN).await?)?;
+    Uuid::parse_str(&manifest.id).context("Invalid manifest ID")?;
+    manifest.validate()?;
+    manifest.create_directories(&config.receive_dir)?;
+    fs::write(
+        config.receive_dir.join(format!("{}.manifest", manifest.id)),
+        serde_json::to_vec(&manifest)?,
+    )?;
+
+    info!("Receiving directory transfer {} ({} entries)", manifest.id, manifest.entries.len());
+    socket.write_all(&[TRANSFER_ACK]).await?;
+    Ok(())
+}
+
+/// Verify a directory transfer once all its files are in and restore the
+/// directory metadata
+async fn handle_manifest_complete(mut socket: TcpStream, config: &FileTransferConfig) -> Result<()> {
+    let id = String::from_utf8(read_field(&mut socket, MAX_NODE_ID_LEN).await?)?;
+    Uuid::parse_str(&id).context("Invalid manifest ID")?;
+    let manifest_path = config.receive_dir.join(format!("{}.manifest", id));
+    let manifest: TransferManifest = serde_json::from_slice(
+        &fs::read(&manifest_path).with_context(|| format!("Unknown directory transfer {}", id))?,
+    )?;
+
+    manifest.finish(&config.receive_dir)?;
+    let _ = fs::remove_file(&manifest_path);
+
+    info!("Directory transfer {} received successfully", id);
+    socket.write_all(&[TRANSFER_ACK]).await?;
+    Ok(())
+}
+
+/// Receiver side of `FileTransferManager::sync_file`, and of folder sync when
+/// `newest_wins`: a copy as new as the sender's, or newer, is then kept
+async fn handle_delta_sync(
+    mut socket: TcpStream,
+    config: &FileTransferConfig,
+    receiver: &Receiver,
+    newest_wins: bool,
+) -> Result<()> {
+    let file_id = String::from_utf8(read_field(&mut socket, MAX_NODE_ID_LEN).await?)?;
+    Uuid::parse_str(&file_id).context("Invalid file ID")?;
+    let file_name = String::from_utf8(read_field(&mut socket, MAX_FILE_NAME_LEN).await?)?;
     let mut size_buf = [0u8; 8];
     socket.read_exact(&mut size_buf).await?;
     let file_size = u64::from_be_bytes(size_buf);
-    
-    // Additional fields for partial transfers
-    let mut start_pos_buf = [0u8; 8];
-    socket.read_exact(&mut start_pos_buf).await?;
-    let start_pos = u64::from_be_bytes(start_pos_buf);
-    
-    let mut end_pos_buf = [0u8; 8];
-    socket.read_exact(&mut end_pos_buf).await?;
-    let end_pos = u64::from_be_bytes(end_pos_buf);
-    
-    // Read file hash
-    let mut hash_len_buf = [0u8; 4];
-    socket.read_exact(&mut hash_len_buf).await?;
-    let hash_len = u32::from_be_bytes(hash_len_buf) as usize;
-    
-    let mut hash_buf = vec![0u8; hash_len];
-    socket.read_exact(&mut hash_buf).await?;
-    let expected_hash = String::from_utf8(hash_buf)?;
-    
+    let mut mode_buf = [0u8; 4];
+    socket.read_exact(&mut mode_buf).await?;
+    let mut mtime_secs_buf = [0u8; 8];
+    socket.read_exact(&mut mtime_secs_buf).await?;
+    let mut mtime_nanos_buf = [0u8; 4];
+    socket.read_exact(&mut mtime_nanos_buf).await?;
+
+    let file_path = config.receive_dir.join(safe_relative_path(&file_name)?);
+    let mtime = (u64::from_be_bytes(mtime_secs_buf), u32::from_be_bytes(mtime_nanos_buf));
+    if newest_wins && is_as_new(&file_path, file_size, mtime) {
+        debug!("Keeping {}, as new as the copy of {}", file_name, receiver.peer(&socket));
+        socket.write_all(&[TRANSFER_CURRENT]).await?;
+        return Ok(());
+    }
+    let incoming = IncomingFile { file_name: file_name.clone(), file_size };
+    // The old copy is only replaced once the new one is complete
+    receiver.admit(&mut socket, config, &file_id, incoming, || Ok(())).await?;
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Signatures of the copy we have, if any
+    let signatures = match fs::metadata(&file_path) {
+        Ok(existing) if existing.is_file() => {
+            let block_size = delta_sync::block_size_for(existing.len());
+            let signature_path = file_path.clone();
+            tokio::task::spawn_blocking(move || Signatures::of_file(&signature_path, block_size)).await??
+        }
+        _ => Signatures { block_size: delta_sync::block_size_for(0), blocks: Vec::new() },
+    };
     info!(
-        "Receiving file: {} (ID: {}), size: {}B, range: {}-{}, expected hash: {}",
-        file_name, file_id, file_size, start_pos, end_pos, expected_hash
+        "Delta sync of {} (ID: {}), size: {}B, {} existing blocks",
+        file_name, file_id, file_size, signatures.blocks.len()
     );
-    
-    // Notify of transfer start
+    write_signatures(&mut socket, &signatures).await?;
+
     if let Some(callback) = &config.progress_callback {
         callback(TransferStatus::Started {
             file_id: file_id.clone(),
@@ -496,21 +1255,399 @@ async fn handle_incoming_file(
             file_size,
         });
     }
-    
-    // Prepare output file
-    let file_path = config.receive_dir.join(&file_name);
-    
-    // Use a file tracking mechanism for multi-part transfers
-    let tracking_path = config.receive_dir.join(format!("{}.parts", file_id));
-    let range_key = format!("{}-{}", start_pos, end_pos);
-    
-    // Store hash in hash tracking file
-    let hash_tracking_path = config.receive_dir.join(format!("{}.hash", file_id));
-    if !hash_tracking_path.exists() {
-        fs::write(&hash_tracking_path, &expected_hash)?;
-    }
-    
-    // Create or update the tracking file to indicate this part is being transferred
+    let start_time = std::time::Instant::now();
+
+    // Rebuild next to the old copy, which the copy ops read from
+    let sync_path = config.receive_dir.join(format!("{}.sync", file_id));
+    let throttle = receiver.throttles.stream(&file_id);
+    let mut control = receiver.controls.watch(&file_id);
+    let result = async {
+        let (size, hash) = rebuild_from_delta(&mut socket, &file_path, &sync_path, &signatures, &throttle, &mut control).await?;
+        // The sender hashes its file while computing the delta and sends the hash last
+        let expected_hash = String::from_utf8(read_field(&mut socket, MAX_NODE_ID_LEN).await?)?;
+        if size != file_size || hash != expected_hash {
+            return Err(anyhow!("Synchronized file {} does not match the sender's (hash {})", file_name, hash));
+        }
+        Ok((size, hash))
+    }.await;
+    receiver.admissions.forget(&file_id);
+    let (received, file_hash) = match result {
+        Ok(received) => received,
+        Err(e) => {
+            let _ = fs::remove_file(&sync_path);
+            if let Some(callback) = &config.progress_callback {
+                callback(if control.is_cancelled() {
+                    TransferStatus::Cancelled { file_id: file_id.clone() }
+                } else {
+                    TransferStatus::Failed { file_id: file_id.clone(), error: e.to_string() }
+                });
+            }
+            return Err(e);
+        }
+    };
+
+    fs::rename(&sync_path, &file_path)
+        .with_context(|| format!("Failed to replace {}", file_path.display()))?;
+    if let Err(e) = apply_metadata(
+        &file_path,
+        u32::from_be_bytes(mode_buf),
+        u64::from_be_bytes(mtime_secs_buf),
+        u32::from_be_bytes(mtime_nanos_buf),
+    ) {
+        warn!("Failed to restore metadata of {}: {}", file_name, e);
+    }
+    if let Err(e) = receiver.store.add(&file_path, &file_hash) {
+        warn!("Failed to index {}: {}", file_name, e);
+    }
+    receiver.history.record(TransferRecord::new(
+        &file_id, &file_name, TransferDirection::Receive, &receiver.peer(&socket), file_size,
+        start_time.elapsed(), Ok(file_hash),
+    ));
+
+    if let Some(callback) = &config.progress_callback {
+        let elapsed_secs = start_time.elapsed().as_secs_f32();
+        callback(TransferStatus::Completed {
+            file_id,
+            bytes_transferred: received,
+            elapsed_seconds: elapsed_secs,
+            throughput_mbps: if elapsed_secs > 0.0 { (received as f32 / elapsed_secs) / (1024.0 * 1024.0) } else { 0.0 },
+        });
+    }
+    info!("✅ Delta sync of {} complete, hash verified", file_name);
+    socket.write_all(&[TRANSFER_ACK]).await?;
+    Ok(())
+}
+
+/// Whether the file at `path` is modified after `mtime`, or at it with `size`
+/// bytes: the copy a newest-wins sync keeps
+fn is_as_new(path: &Path, size: u64, mtime: (u64, u32)) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_file() => {
+            let local = mtime_of(&metadata);
+            local > mtime || (local == mtime && metadata.len() == size)
+        }
+        _ => false,
+    }
+}
+
+/// Remove a file the sender deleted from a synchronized folder, unless our
+/// copy was modified after the deletion
+async fn handle_folder_delete(mut socket: TcpStream, config: &FileTransferConfig, receiver: &Receiver) -> Result<()> {
+    let file_name = String::from_utf8(read_field(&mut socket, MAX_FILE_NAME_LEN).await?)?;
+    let mut deleted_secs_buf = [0u8; 8];
+    socket.read_exact(&mut deleted_secs_buf).await?;
+    let mut deleted_nanos_buf = [0u8; 4];
+    socket.read_exact(&mut deleted_nanos_buf).await?;
+    let deleted_at = (u64::from_be_bytes(deleted_secs_buf), u32::from_be_bytes(deleted_nanos_buf));
+
+    let file_path = config.receive_dir.join(safe_relative_path(&file_name)?);
+    match fs::symlink_metadata(&file_path) {
+        Ok(metadata) if metadata.is_file() && mtime_of(&metadata) > deleted_at => {
+            info!("Keeping {}, modified after {} deleted it", file_name, receiver.peer(&socket));
+            socket.write_all(&[TRANSFER_CURRENT]).await?;
+            return Ok(());
+        }
+        Ok(metadata) if metadata.is_file() => {
+            fs::remove_file(&file_path)
+                .with_context(|| format!("Failed to remove {}", file_path.display()))?;
+            info!("Removed {}, deleted by {}", file_name, receiver.peer(&socket));
+        }
+        _ => debug!("{} deleted by {} is already gone", file_name, receiver.peer(&socket)),
+    }
+    socket.write_all(&[TRANSFER_ACK]).await?;
+    Ok(())
+}
+
+/// Apply the sender's ops, writing the new file to `sync_path`; returns its
+/// size and SHA256 hash
+async fn rebuild_from_delta(
+    socket: &mut TcpStream,
+    old_path: &Path,
+    sync_path: &Path,
+    signatures: &Signatures,
+    throttle: &StreamThrottle,
+    control: &mut TransferWatch,
+) -> Result<(u64, String)> {
+    let mut old = File::open(old_path).ok();
+    let mut out = File::create(sync_path)?;
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    let mut buffer = vec![0u8; signatures.block_size.max(64 * 1024)];
+
+    loop {
+        control.proceed().await?;
+        let mut op = [0u8; 1];
+        socket.read_exact(&mut op).await?;
+        let data = match op[0] {
+            OP_COPY => {
+                let mut index_buf = [0u8; 4];
+                socket.read_exact(&mut index_buf).await?;
+                let index = u32::from_be_bytes(index_buf) as usize;
+                let old = old.as_mut().filter(|_| index < signatures.blocks.len())
+                    .ok_or_else(|| anyhow!("Delta sync copies unknown block {}", index))?;
+                old.seek(SeekFrom::Start((index * signatures.block_size) as u64))?;
+                let mut n = 0;
+                while n < signatures.block_size {
+                    let read = old.read(&mut buffer[n..signatures.block_size])?;
+                    if read == 0 {
+                        break;
+                    }
+                    n += read;
+                }
+                &buffer[..n]
+            }
+            OP_LITERAL => {
+                let mut len_buf = [0u8; 4];
+                socket.read_exact(&mut len_buf).await?;
+                let len = u32::from_be_bytes(len_buf) as usize;
+                if len > MAX_LITERAL_LEN {
+                    return Err(anyhow!("Delta sync literal too long ({} bytes)", len));
+                }
+                if buffer.len() < len {
+                    buffer.resize(len, 0);
+                }
+                socket.read_exact(&mut buffer[..len]).await?;
+                throttle.consume(len).await;
+                &buffer[..len]
+            }
+            OP_END => break,
+            other => return Err(anyhow!("Unknown delta sync op {}", other)),
+        };
+        out.write_all(data)?;
+        hasher.update(data);
+        size += data.len() as u64;
+    }
+
+    out.sync_all()?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+async fn write_signatures(socket: &mut TcpStream, signatures: &Signatures) -> Result<()> {
+    let mut encoded = Vec::with_capacity(8 + signatures.blocks.len() * (4 + STRONG_HASH_LEN));
+    encoded.extend_from_slice(&(signatures.block_size as u32).to_be_bytes());
+    encoded.extend_from_slice(&(signatures.blocks.len() as u32).to_be_bytes());
+    for block in &signatures.blocks {
+        encoded.extend_from_slice(&block.weak.to_be_bytes());
+        encoded.extend_from_slice(&block.strong);
+    }
+    socket.write_all(&encoded).await?;
+    Ok(())
+}
+
+async fn read_signatures(socket: &mut TcpStream) -> Result<Signatures> {
+    let mut header = [0u8; 8];
+    socket.read_exact(&mut header).await?;
+    let block_size = u32::from_be_bytes(header[..4].try_into()?) as usize;
+    let count = u32::from_be_bytes(header[4..].try_into()?) as usize;
+    delta_sync::check_block_size(block_size)?;
+
+    let mut blocks = Vec::with_capacity(count.min(1 << 20));
+    let mut entry = [0u8; 4 + STRONG_HASH_LEN];
+    for _ in 0..count {
+        socket.read_exact(&mut entry).await?;
+        let mut strong = [0u8; STRONG_HASH_LEN];
+        strong.copy_from_slice(&entry[4..]);
+        blocks.push(BlockSignature { weak: u32::from_be_bytes(entry[..4].try_into()?), strong });
+    }
+    Ok(Signatures { block_size, blocks })
+}
+
+/// Stop an incoming transfer the sender cancelled and remove its partial data
+async fn handle_cancel(mut socket: TcpStream, config: &FileTransferConfig, receiver: &Receiver) -> Result<()> {
+    let file_id = String::from_utf8(read_field(&mut socket, MAX_NODE_ID_LEN).await?)?;
+    Uuid::parse_str(&file_id).context("Invalid file ID")?;
+    let file_name = String::from_utf8(read_field(&mut socket, MAX_FILE_NAME_LEN).await?)?;
+    let file_path = config.receive_dir.join(safe_relative_path(&file_name)?);
+
+    // Streams still running stop at their next chunk
+    let _ = receiver.controls.set(&file_id, TransferState::Cancelled);
+    remove_partial_transfer(config, &file_id, &file_path);
+    receiver.admissions.forget(&file_id);
+
+    info!("Sender cancelled transfer of {} (ID: {})", file_name, file_id);
+    if let Some(callback) = &config.progress_callback {
+        callback(TransferStatus::Cancelled { file_id });
+    }
+    socket.write_all(&[TRANSFER_ACK]).await?;
+    Ok(())
+}
+
+/// Echo the pings of a benchmark stream, then read and discard its data
+async fn handle_benchmark(mut socket: TcpStream, config: &FileTransferConfig, receiver: &Receiver) -> Result<()> {
+    let mut pings_buf = [0u8; 4];
+    socket.read_exact(&mut pings_buf).await?;
+    let pings = u32::from_be_bytes(pings_buf);
+    let mut bytes_buf = [0u8; 8];
+    socket.read_exact(&mut bytes_buf).await?;
+    let bytes = u64::from_be_bytes(bytes_buf);
+    if bytes > benchmark::MAX_STREAM_BYTES {
+        let reason = format!("{} bytes exceed the benchmark limit of {}", bytes, benchmark::MAX_STREAM_BYTES);
+        socket.write_all(&[TRANSFER_REFUSED]).await?;
+        write_field(&mut socket, reason.as_bytes()).await?;
+        return Err(anyhow!(reason));
+    }
+    socket.write_all(&[TRANSFER_ACK]).await?;
+
+    let mut ping = [0u8; 1];
+    for _ in 0..pings {
+        socket.read_exact(&mut ping).await?;
+        socket.write_all(&ping).await?;
+    }
+    let mut buffer = vec![0u8; config.chunk_size];
+    let mut remaining = bytes;
+    while remaining > 0 {
+        let max_bytes = std::cmp::min(buffer.len() as u64, remaining) as usize;
+        let n = socket.read(&mut buffer[..max_bytes]).await?;
+        if n == 0 {
+            return Err(anyhow!("Connection closed with {} benchmark bytes outstanding", remaining));
+        }
+        remaining -= n as u64;
+    }
+
+    debug!("Benchmark stream from {}: {} pings, {} bytes", receiver.peer(&socket), pings, bytes);
+    socket.write_all(&[TRANSFER_ACK]).await?;
+    Ok(())
+}
+
+/// State shared by the streams a node receives
+#[derive(Clone)]
+struct Receiver {
+    /// Node ID the sender authenticated as, set by the handshake
+    sender: String,
+    throttles: Throttles,
+    controls: TransferControls,
+    offers: TransferOffers,
+    admissions: Admissions,
+    store: ContentStore,
+    history: TransferHistory,
+}
+
+impl Receiver {
+    /// The sender as recorded in the transfer history: its node ID, or its
+    /// address when it did not present one
+    fn peer(&self, socket: &TcpStream) -> String {
+        if !self.sender.is_empty() {
+            return self.sender.clone();
+        }
+        socket.peer_addr().map(|addr| addr.ip().to_canonical().to_string()).unwrap_or_default()
+    }
+
+    /// Check an incoming file against the receive policy before any of it is
+    /// written, and tell the sender whether it may go ahead. `prepare` runs
+    /// once the file is first admitted.
+    async fn admit(
+        &self,
+        socket: &mut TcpStream,
+        config: &FileTransferConfig,
+        file_id: &str,
+        file: IncomingFile,
+        prepare: impl FnOnce() -> Result<()>,
+    ) -> Result<()> {
+        let file_name = file.file_name.clone();
+        if let Err(e) = self.admissions.admit(&config.receive_policy, &config.receive_dir, file_id, file, prepare).await {
+            warn!("Refused {} from node {}: {}", file_name, self.sender, e);
+            socket.write_all(&[TRANSFER_REFUSED]).await?;
+            write_field(socket, e.to_string().as_bytes()).await?;
+            return Err(e);
+        }
+        socket.write_all(&[TRANSFER_ACK]).await?;
+        Ok(())
+    }
+}
+
+/// Remove a partially received file and its tracking files
+fn remove_partial_transfer(config: &FileTransferConfig, file_id: &str, file_path: &Path) {
+    let _ = fs::remove_file(file_path);
+    let _ = fs::remove_file(config.receive_dir.join(format!("{}.parts", file_id)));
+}
+
+/// Handle an incoming file transfer, taking the data over RDMA when the
+/// sender offers it (`rdma`) and this node can
+async fn handle_incoming_file(
+    mut socket: TcpStream,
+    config: FileTransferConfig,
+    buffer_pool: Arc<Mutex<Vec<Vec<u8>>>>,
+    receiver: &Receiver,
+    rdma: bool,
+) -> Result<()> {
+    // Read the header (file ID, file name, and file size)
+    let mut id_len_buf = [0u8; 4];
+    socket.read_exact(&mut id_len_buf).await?;
+    let id_len = u32::from_be_bytes(id_len_buf) as usize;
+    
+    let mut id_buf = vec![0u8; id_len];
+    socket.read_exact(&mut id_buf).await?;
+    let file_id = String::from_utf8(id_buf)?;
+    
+    let mut name_len_buf = [0u8; 4];
+    socket.read_exact(&mut name_len_buf).await?;
+    let name_len = u32::from_be_bytes(name_len_buf) as usize;
+    
+    let mut name_buf = vec![0u8; name_len];
+    socket.read_exact(&mut name_buf).await?;
+    let file_name = String::from_utf8(name_buf)?;
+    
+    let mut size_buf = [0u8; 8];
+    socket.read_exact(&mut size_buf).await?;
+    let file_size = u64::from_be_bytes(size_buf);
+    
+    // Additional fields for partial transfers
+    let mut start_pos_buf = [0u8; 8];
+    socket.read_exact(&mut start_pos_buf).await?;
+    let start_pos = u64::from_be_bytes(start_pos_buf);
+    
+    let mut end_pos_buf = [0u8; 8];
+    socket.read_exact(&mut end_pos_buf).await?;
+    let end_pos = u64::from_be_bytes(end_pos_buf);
+    if start_pos > end_pos || end_pos > file_size {
+        return Err(anyhow!("Invalid range {}-{} of {} byte file", start_pos, end_pos, file_size));
+    }
+    
+    // Permissions and modification time, applied once all parts are in
+    let mut mode_buf = [0u8; 4];
+    socket.read_exact(&mut mode_buf).await?;
+    let mode = u32::from_be_bytes(mode_buf);
+    
+    let mut mtime_secs_buf = [0u8; 8];
+    socket.read_exact(&mut mtime_secs_buf).await?;
+    let mtime_secs = u64::from_be_bytes(mtime_secs_buf);
+    
+    let mut mtime_nanos_buf = [0u8; 4];
+    socket.read_exact(&mut mtime_nanos_buf).await?;
+    let mtime_nanos = u32::from_be_bytes(mtime_nanos_buf);
+    
+    info!(
+        "Receiving file: {} (ID: {}), size: {}B, range: {}-{}",
+        file_name, file_id, file_size, start_pos, end_pos
+    );
+    let file_path = config.receive_dir.join(safe_relative_path(&file_name)?);
+    let incoming = IncomingFile { file_name: file_name.clone(), file_size };
+    // Ranges are written in place, so never into a name sharing its content
+    receiver.admit(&mut socket, &config, &file_id, incoming, || content_store::detach(&file_path)).await?;
+    let mut rdma = if rdma { RdmaReceiver::accept(&mut socket, config.chunk_size).await? } else { None };
+    if rdma.is_some() {
+        debug!("Receiving range {}-{} of {} over RDMA", start_pos, end_pos, file_name);
+    }
+    
+    // Notify of transfer start
+    if let Some(callback) = &config.progress_callback {
+        callback(TransferStatus::Started {
+            file_id: file_id.clone(),
+            file_name: file_name.clone(),
+            file_size,
+        });
+    }
+    
+    // Prepare output file; names may include subdirectories for directory transfers
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    
+    // Use a file tracking mechanism for multi-part transfers
+    let tracking_path = config.receive_dir.join(format!("{}.parts", file_id));
+    let range_key = format!("{}-{}", start_pos, end_pos);
+    
+    // Create or update the tracking file to indicate this part is being transferred
     {
         let mut parts = if tracking_path.exists() {
             let content = fs::read_to_string(&tracking_path)?;
@@ -525,67 +1662,121 @@ async fn handle_incoming_file(
         fs::write(&tracking_path, serde_json::to_string(&parts)?)?;
     }
     
-    let file = Arc::new(Mutex::new({
-        // Ensure the file exists and has the right size
-        if !file_path.exists() {
-            // Create new file and set its size
-            let mut file = File::create(&file_path)?;
-            file.set_len(file_size)?;
-            file
-        } else {
-            // File exists, open for writing
-            let mut file = File::options()
-                .read(true)
-                .write(true)
-                .open(&file_path)?;
-            
-            // If file size is wrong, fix it
+    // Streams of the same file write at their own offsets, so they share no cursor
+    let file = {
+        let file_path = file_path.clone();
+        Arc::new(tokio::task::spawn_blocking(move || -> Result<File> {
+            // Ensure the file exists and has the right size
+            let file = File::options().write(true).create(true).truncate(false).open(&file_path)?;
             if file.metadata()?.len() != file_size {
                 file.set_len(file_size)?;
             }
-            file
-        }
-    }));
-    
-    // Seek to the correct position for this part
-    {
-        let mut file_guard = file.lock().await;
-        file_guard.seek(SeekFrom::Start(start_pos))?;
-    }
+            Ok(file)
+        }).await??)
+    };
     
     // Start time for throughput calculation
     let start_time = std::time::Instant::now();
+    let throttle = receiver.throttles.stream(&file_id);
+    let mut control = receiver.controls.watch(&file_id);
+    
+    let cancelled = || {
+        remove_partial_transfer(&config, &file_id, &file_path);
+        receiver.admissions.forget(&file_id);
+        if let Some(callback) = &config.progress_callback {
+            callback(TransferStatus::Cancelled { file_id: file_id.clone() });
+        }
+    };
     
-    // Read and process data
+    // Read and process data, hashing it for comparison with the sender's digest
     let mut bytes_received = 0;
+    let mut hasher = Sha256::new();
     let mut buffer = if let Ok(mut pool) = buffer_pool.try_lock() {
         pool.pop().unwrap_or_else(|| vec![0u8; config.chunk_size])
     } else {
         vec![0u8; config.chunk_size]
     };
     
+    // The ring takes the data phase over a duplicate of the socket
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    let range_digest = if rdma.is_none() {
+        let std_socket = socket.into_std()?;
+        let stream = RangeStream {
+            socket: std_socket.try_clone()?,
+            file: file.try_clone()?,
+            start_pos,
+            end_pos,
+            chunk_size: config.chunk_size,
+            throttle: receiver.throttles.stream(&file_id),
+            control: receiver.controls.watch(&file_id),
+        };
+        let (progress_callback, progress_id) = (config.progress_callback.clone(), file_id.clone());
+        let progress = move |total_received: u64| {
+            if let Some(callback) = &progress_callback {
+                callback(TransferStatus::Progress {
+                    file_id: progress_id.clone(),
+                    bytes_transferred: total_received,
+                    total_bytes: file_size,
+                    percent_complete: (total_received as f32 / file_size as f32) * 100.0,
+                });
+            }
+        };
+        let received = uring::receive_range(stream, progress).await;
+        socket = TcpStream::from_std(std_socket)?;
+        match received {
+            Ok(digest) => {
+                bytes_received = end_pos - start_pos;
+                Some(digest)
+            }
+            Err(e) => {
+                if control.is_cancelled() {
+                    cancelled();
+                }
+                return Err(e);
+            }
+        }
+    } else {
+        None
+    };
+    #[cfg(not(all(target_os = "linux", feature = "io-uring")))]
+    let range_digest: Option<Vec<u8>> = None;
+
     while bytes_received < (end_pos - start_pos) {
+        if let Err(e) = control.proceed().await {
+            cancelled();
+            return Err(e);
+        }
+    
         let max_bytes = std::cmp::min(
             buffer.len() as u64,
             (end_pos - start_pos) - bytes_received,
         ) as usize;
-        
+    
         let read_buf = &mut buffer[..max_bytes];
-        let n = socket.read(read_buf).await?;
-        
+        let n = match &mut rdma {
+            Some(rdma) => rdma.receive_chunk(&mut socket, read_buf).await?,
+            None => socket.read(read_buf).await?,
+        };
+    
         if n == 0 {
             // EOF before expected end
             return Err(anyhow!("Connection closed prematurely"));
         }
-        
-        // Write to file
-        {
-            let mut file_guard = file.lock().await;
-            file_guard.write_all(&buffer[..n])?;
-        }
-        
+    
+        // Write to file off the runtime, handing the buffer back afterwards
+        let offset = start_pos + bytes_received;
+        let write_file = file.clone();
+        let (written, returned) = tokio::task::spawn_blocking(move || {
+            let written = write_file.write_all_at(&buffer[..n], offset);
+            (written, buffer)
+        }).await?;
+        buffer = returned;
+        written?;
+        hasher.update(&buffer[..n]);
+    
         bytes_received += n as u64;
-        
+        throttle.consume(n).await;
+    
         // Report progress
         if let Some(callback) = &config.progress_callback {
             let total_received = start_pos + bytes_received;
@@ -599,6 +1790,15 @@ async fn handle_incoming_file(
         }
     }
     
+    // The sender's digest of the range follows the data
+    let mut expected_digest = [0u8; RANGE_DIGEST_LEN];
+    socket.read_exact(&mut expected_digest).await?;
+    let digest = range_digest.unwrap_or_else(|| hasher.finalize().to_vec());
+    if digest != expected_digest {
+        error!("❌ Range {} of {} does not match the sender's digest", range_key, file_name);
+        return Err(anyhow!("Range {} of {} was corrupted in transit", range_key, file_name));
+    }
+    
     // Calculate throughput
     let elapsed = start_time.elapsed();
     let elapsed_secs = elapsed.as_secs_f32();
@@ -647,35 +1847,36 @@ async fn handle_incoming_file(
         
         fs::write(&tracking_path, serde_json::to_string(&parts)?)?;
         
-        // If all parts are complete, we can verify the hash and clean up
+        // Every range was verified against the sender's digest as it arrived
         if all_complete {
-            info!("All parts of file {} received successfully", file_name);
+            info!("✅ All parts of file {} received and verified", file_name);
             
-            // Verify file integrity with hash
-            let hash_tracking_path = config.receive_dir.join(format!("{}.hash", file_id));
-            if hash_tracking_path.exists() {
-                let expected_hash = fs::read_to_string(&hash_tracking_path)?;
-                
-                // Calculate actual hash of the complete file
-                match FileTransferManager::calculate_file_hash(&file_path) {
-                    Ok(actual_hash) => {
-                        if actual_hash == expected_hash {
-                            info!("✅ Hash verification successful: File integrity confirmed");
-                        } else {
-                            error!("❌ Hash verification failed: File may be corrupted");
-                            error!("Expected: {}", expected_hash);
-                            error!("Actual:   {}", actual_hash);
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to calculate hash for verification: {}", e);
+            if let Err(e) = apply_metadata(&file_path, mode, mtime_secs, mtime_nanos) {
+                warn!("Failed to restore metadata of {}: {}", file_name, e);
+            }
+            
+            // Store content received before only once
+            let hash = match FileTransferManager::calculate_file_hash(&file_path) {
+                Ok(hash) => {
+                    if let Err(e) = receiver.store.add(&file_path, &hash) {
+                        warn!("Failed to index {}: {}", file_name, e);
                     }
+                    hash
                 }
-            }
+                Err(e) => {
+                    warn!("Failed to hash {} for the content index: {}", file_name, e);
+                    String::new()
+                }
+            };
+            // Timed from when the file's first range was admitted
+            let duration = receiver.admissions.admitted_at(&file_id).map_or(elapsed, |at| at.elapsed());
+            receiver.history.record(TransferRecord::new(
+                &file_id, &file_name, TransferDirection::Receive, &receiver.peer(&socket), file_size, duration, Ok(hash),
+            ));
             
             // Clean up tracking files
             let _ = fs::remove_file(&tracking_path);
-            let _ = fs::remove_file(&hash_tracking_path);
+            receiver.admissions.forget(&file_id);
         } else {
             info!("Partial transfer of {}: {}/{} parts complete", 
                    file_name, 
@@ -684,26 +1885,298 @@ async fn handle_incoming_file(
         }
     }
     
+    // Tell the sender this range is written
+    socket.write_all(&[TRANSFER_ACK]).await?;
     Ok(())
 }
 
-/// Send a range of a file over a TCP connection
-async fn send_file_range(
-    path: &Path,
-    target_addr: SocketAddr,
+/// Receiver side of the handshake that opens every connection. The receiver
+/// sends a random challenge; the sender answers with its node ID,
+/// HMAC-SHA256(secret, challenge || node ID), the token of an accepted offer,
+/// its public key and its signature of the challenge (all but the node ID
+/// possibly empty), and the receiver replies with a single status byte before
+/// any header is read.
+async fn authenticate_sender(socket: &mut TcpStream, config: &FileTransferConfig, offers: &TransferOffers) -> Result<String> {
+    let challenge: [u8; CHALLENGE_LEN] = rand::random();
+    socket.write_all(&challenge).await?;
+
+    let node_id = String::from_utf8(read_field(socket, MAX_NODE_ID_LEN).await?)?;
+    let mac = read_field(socket, MAX_MAC_LEN).await?;
+    let token = String::from_utf8(read_field(socket, MAX_NODE_ID_LEN).await?)?;
+    let public_key = String::from_utf8(read_field(socket, MAX_KEY_LEN).await?)?;
+    let signature = read_field(socket, MAX_KEY_LEN).await?;
+
+    let rejection = config.shared_secret.as_ref()
+        .filter(|secret| !constant_time_eq(&mac, &handshake_mac(secret, &challenge, &node_id)))
+        .map(|_| format!("Rejected file transfer from node {:?}: authentication failed", node_id));
+    let rejection = rejection.or_else(|| {
+        let auth = config.peer_auth.as_ref()?;
+        if public_key.is_empty() {
+            return auth.is_required().then(|| format!("Rejected file transfer from node {:?}: no identity key presented", node_id));
+        }
+        auth.check_proof(TRANSFER_PROOF, &node_id, &public_key, &challenge, &signature).err()
+            .map(|e| format!("Rejected file transfer from node {:?}: {}", node_id, e))
+    });
+    let rejection = rejection.or_else(|| {
+        (!config.allowed_senders.as_ref().is_none_or(|allowed| allowed.contains(&node_id)))
+            .then(|| format!("Rejected file transfer from node {}: not in the sender allowlist", node_id))
+    });
+    let rejection = rejection.or_else(|| {
+        if !config.require_offer {
+            return None;
+        }
+        offers.redeem(&token, &node_id).err()
+            .map(|e| format!("Rejected file transfer from node {}: {}", node_id, e))
+    });
+
+    match rejection {
+        Some(reason) => {
+            socket.write_all(&[HANDSHAKE_REJECTED]).await?;
+            Err(anyhow!(reason))
+        }
+        None => {
+            socket.write_all(&[HANDSHAKE_ACCEPTED]).await?;
+            Ok(node_id)
+        }
+    }
+}
+
+/// Sender side of the handshake, see `authenticate_sender`
+async fn authenticate_to_receiver(socket: &mut TcpStream, config: &FileTransferConfig, token: Option<&str>) -> Result<()> {
+    let mut challenge = [0u8; CHALLENGE_LEN];
+    socket.read_exact(&mut challenge).await?;
+
+    // The key proves the ID it is held under
+    let auth = config.peer_auth.as_ref();
+    let node_id = auth.map(PeerAuth::node_id).or(config.node_id.as_deref()).unwrap_or_default();
+    let mac = config.shared_secret.as_deref()
+        .map(|secret| handshake_mac(secret, &challenge, node_id))
+        .unwrap_or_default();
+    write_field(socket, node_id.as_bytes()).await?;
+    write_field(socket, &mac).await?;
+    write_field(socket, token.unwrap_or_default().as_bytes()).await?;
+    write_field(socket, auth.map(|auth| auth.key().public_key()).unwrap_or_default().as_bytes()).await?;
+    write_field(socket, &auth.map(|auth| auth.prove(TRANSFER_PROOF, &challenge)).unwrap_or_default()).await?;
+
+    let mut status = [0u8; 1];
+    socket.read_exact(&mut status).await?;
+    if status[0] != HANDSHAKE_ACCEPTED {
+        return Err(anyhow!("Receiver rejected the transfer: check the shared secret, node key, sender allowlist and transfer offer"));
+    }
+    Ok(())
+}
+
+fn handshake_mac(secret: &str, challenge: &[u8], node_id: &str) -> Vec<u8> {
+    let message: Vec<u8> = challenge.iter().chain(node_id.as_bytes()).copied().collect();
+    hmac_sha256(secret.as_bytes(), &message)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn read_field(socket: &mut TcpStream, max_len: usize) -> Result<Vec<u8>> {
+    let mut len_buf = [0u8; 4];
+    socket.read_exact(&mut len_buf).await?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > max_len {
+        return Err(anyhow!("Handshake field too long ({} bytes)", len));
+    }
+    let mut buf = vec![0u8; len];
+    socket.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+async fn write_field(socket: &mut TcpStream, bytes: &[u8]) -> Result<()> {
+    socket.write_all(&(bytes.len() as u32).to_be_bytes()).await?;
+    socket.write_all(bytes).await?;
+    Ok(())
+}
+
+/// Offer `path` to `node` and return where its transfer goes once accepted
+pub(super) async fn offer_to(client: &NodeClient, node: &NodeInfo, local_node: &NodeInfo, path: &Path) -> Result<Destination> {
+    let metadata = fs::metadata(path)
+        .with_context(|| format!("Failed to get metadata for file {}", path.display()))?;
+    let name = received_name(path)?;
+    let size = if metadata.is_dir() {
+        TransferManifest::from_directory(String::new(), path)?.files(path).map(|(entry, _)| entry.size).sum()
+    } else {
+        metadata.len()
+    };
+
+    offer(client, node, local_node, &name, size, metadata.is_dir()).await
+}
+
+/// Offer `size` bytes stored as `name` to `node` and return where they go once accepted
+async fn offer(client: &NodeClient, node: &NodeInfo, local_node: &NodeInfo, name: &str, size: u64, directory: bool) -> Result<Destination> {
+    let offer = client.offer_transfer(node, local_node, name, size, directory).await?;
+    if !offer.accepted {
+        return Err(anyhow!("{} rejected the transfer of {}: {}", node.name, name, offer.reason));
+    }
+    // The data takes a direct Thunderbolt link to the node where there is
+    // one, while control stays on the address gRPC answered on
+    let ip = match node.thunderbolt_links().first() {
+        Some((_, ip)) => *ip,
+        None => client.address_of(node).await?,
+    };
+    let port = u16::try_from(offer.port).context("Invalid transfer port")?;
+    info!("{} accepted {} on {}", node.name, name, SocketAddr::new(ip, port));
+    let rdma = rdma::SUPPORTED
+        && node.capability(capabilities::RDMA).is_some()
+        && local_node.capability(capabilities::RDMA).is_some();
+    Ok(Destination { addr: SocketAddr::new(ip, port), token: Some(offer.token), node_id: Some(node.id.clone()), rdma })
+}
+
+/// Where a transfer goes, with the token its receiver issued when the transfer was offered
+#[derive(Debug, Clone)]
+pub(super) struct Destination {
+    addr: SocketAddr,
+    token: Option<String>,
+    /// The receiving node, when the transfer was offered to one
+    node_id: Option<String>,
+    /// Both nodes have RDMA devices, so ranges are offered over RDMA first
+    rdma: bool,
+}
+
+impl Destination {
+    /// The receiver as recorded in the transfer history
+    fn peer(&self) -> String {
+        self.node_id.clone().unwrap_or_else(|| self.addr.to_string())
+    }
+}
+
+/// One byte range of a file, sent over its own connection
+struct FileRange {
     file_id: String,
     file_name: String,
     start_pos: u64,
     end_pos: u64,
-    chunk_size: usize,
+}
+
+/// Send a range of a file over a TCP connection, or over RDMA when both nodes
+/// can; returns the SHA256 digest of the range, which is also sent after the
+/// data for the receiver to verify
+async fn send_file_range(
+    path: &Path,
+    target: &Destination,
+    config: &FileTransferConfig,
+    range: FileRange,
     bytes_sent_counter: Arc<Mutex<u64>>,
-    file_hash: String,
-) -> Result<()> {
-    // Connect to target
-    let mut socket = TcpStream::connect(target_addr).await?;
+    throttle: StreamThrottle,
+    mut control: TransferWatch,
+) -> Result<Vec<u8>> {
+    let FileRange { start_pos, end_pos, .. } = range;
+    let chunk_size = config.chunk_size;
     
     // Open the file
-    let mut file = File::open(path)?;
+    let file = Arc::new(tokio::fs::File::open(path).await?.into_std().await);
+    
+    // Connect to target, over RDMA where both nodes offer it; receivers that
+    // don't know the RDMA frame close the connection, so retry over TCP
+    let (mut socket, mut rdma) = if target.rdma {
+        match open_rdma_range(target, config, &file, &range).await {
+            Ok(opened) => opened,
+            Err(e) => {
+                warn!("Sending range {}-{} over TCP, RDMA failed: {}", start_pos, end_pos, e);
+                (open_range(target, config, &file, &range, FRAME_FILE_RANGE).await?, None)
+            }
+        }
+    } else {
+        (open_range(target, config, &file, &range, FRAME_FILE_RANGE).await?, None)
+    };
+    
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    if rdma.is_none() {
+        // The ring takes the data phase over a duplicate of the socket
+        let std_socket = socket.into_std()?;
+        let stream = RangeStream {
+            socket: std_socket.try_clone()?,
+            file: file.try_clone()?,
+            start_pos,
+            end_pos,
+            chunk_size,
+            throttle,
+            control,
+        };
+        let sent = uring::send_range(stream, bytes_sent_counter).await;
+        let mut socket = TcpStream::from_std(std_socket)?;
+        let digest = sent.with_context(|| format!("Failed to send {}", path.display()))?;
+        return finish_range(&mut socket, digest, start_pos, end_pos).await;
+    }
+
+    // Send file data straight from the page cache, hashing what was sent while
+    // it is still cached instead of reading the whole file beforehand
+    let mut position = start_pos;
+    let mut hasher = Sha256::new();
+    let mut hash_buffer = vec![0u8; chunk_size];
+
+    while position < end_pos {
+        control.proceed().await?;
+    
+        let max_bytes = std::cmp::min(chunk_size as u64, end_pos - position) as usize;
+        let n = match &mut rdma {
+            Some(rdma) => rdma.send_chunk(&mut socket, &file, position, max_bytes).await?,
+            None => zero_copy::send_chunk(&socket, &file, position, max_bytes).await?,
+        };
+    
+        if n == 0 {
+            break; // EOF
+        }
+    
+        let read_file = file.clone();
+        let (read, returned) = tokio::task::spawn_blocking(move || {
+            let read = read_file.read_exact_at(&mut hash_buffer[..n], position);
+            (read, hash_buffer)
+        }).await?;
+        hash_buffer = returned;
+        read?;
+        hasher.update(&hash_buffer[..n]);
+        position += n as u64;
+        throttle.consume(n).await;
+    
+        // Update the shared counter
+        {
+            let mut counter = bytes_sent_counter.lock().await;
+            *counter += n as u64;
+        }
+    }
+
+    if position < end_pos {
+        return Err(anyhow!("{} ended at {} while sending range {}-{}", path.display(), position, start_pos, end_pos));
+    }
+    let digest = hasher.finalize().to_vec();
+    finish_range(&mut socket, digest, start_pos, end_pos).await
+}
+
+/// Send the digest of a range after its data and wait for the receiver to confirm it
+async fn finish_range(socket: &mut TcpStream, digest: Vec<u8>, start_pos: u64, end_pos: u64) -> Result<Vec<u8>> {
+    socket.write_all(&digest).await?;
+    expect_ack(socket, "range").await?;
+    debug!("Completed sending range {}-{}", start_pos, end_pos);
+    Ok(digest)
+}
+
+/// Open a stream for `range` with an RDMA offer; the RDMA link unless the
+/// receiver declined it
+async fn open_rdma_range(
+    target: &Destination,
+    config: &FileTransferConfig,
+    file: &File,
+    range: &FileRange,
+) -> Result<(TcpStream, Option<RdmaSender>)> {
+    let mut socket = open_range(target, config, file, range, FRAME_RDMA_RANGE).await?;
+    let rdma = RdmaSender::offer(&mut socket, config.chunk_size).await?;
+    if rdma.is_some() {
+        debug!("Sending range {}-{} over RDMA", range.start_pos, range.end_pos);
+    }
+    Ok((socket, rdma))
+}
+
+/// Open a stream with frame type `frame` and send the header of `range` of
+/// `file`, once the receiver admitted it
+async fn open_range(target: &Destination, config: &FileTransferConfig, file: &File, range: &FileRange, frame: u8) -> Result<TcpStream> {
+    let FileRange { file_id, file_name, start_pos, end_pos } = range;
+    let mut socket = open_stream(target, config, frame).await?;
     
     // Send header
     let id_bytes = file_id.as_bytes();
@@ -717,45 +2190,98 @@ async fn send_file_range(
     socket.write_all(name_bytes).await?;
     
     // Get total file size
-    let file_size = file.metadata()?.len();
+    let metadata = file.metadata()?;
+    let file_size = metadata.len();
     socket.write_all(&file_size.to_be_bytes()).await?;
     
     // Send range information
     socket.write_all(&start_pos.to_be_bytes()).await?;
     socket.write_all(&end_pos.to_be_bytes()).await?;
     
-    // Send file hash for integrity verification
-    let hash_bytes = file_hash.as_bytes();
-    let hash_len = hash_bytes.len() as u32;
-    socket.write_all(&hash_len.to_be_bytes()).await?;
-    socket.write_all(hash_bytes).await?;
-    
-    // Seek to start position
-    file.seek(SeekFrom::Start(start_pos))?;
-    
-    // Send file data
-    let mut buffer = vec![0u8; chunk_size];
-    let mut position = start_pos;
-    
-    while position < end_pos {
-        let max_bytes = std::cmp::min(chunk_size as u64, end_pos - position) as usize;
-        let n = file.read(&mut buffer[..max_bytes])?;
-        
-        if n == 0 {
-            break; // EOF
+    // Send permissions and modification time
+    let (mtime_secs, mtime_nanos) = mtime_of(&metadata);
+    socket.write_all(&(metadata.permissions().mode() & 0o777).to_be_bytes()).await?;
+    socket.write_all(&mtime_secs.to_be_bytes()).await?;
+    socket.write_all(&mtime_nanos.to_be_bytes()).await?;
+    expect_ack(&mut socket, "file").await?;
+    Ok(socket)
+}
+
+/// Digest of a whole file sent in ranges: SHA256 over the range digests in order
+fn combine_digests(range_digests: &[Vec<u8>]) -> String {
+    let mut hasher = Sha256::new();
+    for digest in range_digests {
+        hasher.update(digest);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Tell the receiver a transfer was cancelled so it removes the partial file
+async fn send_cancel(target: &Destination, config: &FileTransferConfig, file_id: &str, file_name: &str) -> Result<()> {
+    let mut socket = open_stream(target, config, FRAME_CANCEL).await?;
+    write_field(&mut socket, file_id.as_bytes()).await?;
+    write_field(&mut socket, file_name.as_bytes()).await?;
+    expect_ack(&mut socket, "cancellation").await
+}
+
+/// Open a benchmark stream announcing `pings` pings and `bytes` bytes of data
+async fn open_benchmark(target: &Destination, config: &FileTransferConfig, pings: u32, bytes: u64) -> Result<TcpStream> {
+    let mut socket = open_stream(target, config, FRAME_BENCHMARK).await?;
+    socket.write_all(&pings.to_be_bytes()).await?;
+    socket.write_all(&bytes.to_be_bytes()).await?;
+    expect_ack(&mut socket, "benchmark").await?;
+    Ok(socket)
+}
+
+/// Send `bytes` bytes of `data`, repeated, over a benchmark stream
+async fn send_benchmark_data(mut socket: TcpStream, data: Arc<Vec<u8>>, bytes: u64) -> Result<()> {
+    let mut remaining = bytes;
+    while remaining > 0 {
+        let n = std::cmp::min(data.len() as u64, remaining) as usize;
+        socket.write_all(&data[..n]).await?;
+        remaining -= n as u64;
+    }
+    expect_ack(&mut socket, "benchmark data").await
+}
+
+/// Connect, authenticate and open a stream with the given frame type
+async fn open_stream(target: &Destination, config: &FileTransferConfig, frame: u8) -> Result<TcpStream> {
+    let mut socket = zero_copy::connect(target.addr).await?;
+    authenticate_to_receiver(&mut socket, config, target.token.as_deref()).await?;
+    socket.write_all(&[frame]).await?;
+    Ok(socket)
+}
+
+/// Wait for the receiver to admit a folder sync; false when it answers that
+/// its copy is as new or newer
+async fn expect_admission(socket: &mut TcpStream, what: &str) -> Result<bool> {
+    let mut status = [0u8; 1];
+    socket.read_exact(&mut status).await
+        .with_context(|| format!("Receiver closed the connection before confirming the {}", what))?;
+    match status[0] {
+        TRANSFER_ACK => Ok(true),
+        TRANSFER_CURRENT => Ok(false),
+        TRANSFER_REFUSED => {
+            let reason = read_field(socket, MAX_FILE_NAME_LEN).await?;
+            Err(anyhow!("Receiver refused the {}: {}", what, String::from_utf8_lossy(&reason)))
         }
-        
-        socket.write_all(&buffer[..n]).await?;
-        position += n as u64;
-        
-        // Update the shared counter
-        {
-            let mut counter = bytes_sent_counter.lock().await;
-            *counter += n as u64;
+        _ => Err(anyhow!("Receiver did not accept the {}", what)),
+    }
+}
+
+/// Wait for the receiver to confirm it processed what was sent
+async fn expect_ack(socket: &mut TcpStream, what: &str) -> Result<()> {
+    let mut status = [0u8; 1];
+    socket.read_exact(&mut status).await
+        .with_context(|| format!("Receiver closed the connection before confirming the {}", what))?;
+    match status[0] {
+        TRANSFER_ACK => {}
+        TRANSFER_REFUSED => {
+            let reason = read_field(socket, MAX_FILE_NAME_LEN).await?;
+            return Err(anyhow!("Receiver refused the {}: {}", what, String::from_utf8_lossy(&reason)));
         }
+        _ => return Err(anyhow!("Receiver did not accept the {}", what)),
     }
-    
-    debug!("Completed sending range {}-{}", start_pos, end_pos);
     Ok(())
 }
 
@@ -763,9 +2289,21 @@ async fn send_file_range(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
     use tempfile::tempdir;
     
+    /// Names in a receive directory besides its content index
+    fn received_entries(dir: &Path) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if name != content_store::INDEX_FILE {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
     #[tokio::test]
     async fn test_loopback_transfer() -> Result<()> {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -789,7 +2327,7 @@ mod tests {
         // Set up progress tracking
         let received_started = Arc::new(AtomicBool::new(false));
         let received_completed = Arc::new(AtomicBool::new(false));
-        let received_bytes = Arc::new(Mutex::new(0u64));
+        let received_bytes = Arc::new(AtomicU64::new(0));
         
         let r_started = received_started.clone();
         let r_completed = received_completed.clone();
@@ -801,8 +2339,7 @@ mod tests {
                     r_started.store(true, Ordering::SeqCst);
                 }
                 TransferStatus::Progress { bytes_transferred, .. } => {
-                    let mut b = r_bytes.blocking_lock();
-                    *b = bytes_transferred;
+                    r_bytes.store(bytes_transferred, Ordering::SeqCst);
                 }
                 TransferStatus::Completed { .. } => {
                     r_completed.store(true, Ordering::SeqCst);
@@ -810,6 +2347,9 @@ mod tests {
                 TransferStatus::Failed { error, .. } => {
                     panic!("Transfer failed: {}", error);
                 }
+                TransferStatus::Cancelled { .. } => {
+                    panic!("Transfer cancelled");
+                }
             }
         });
         
@@ -820,6 +2360,7 @@ mod tests {
             receive_dir: receive_dir.path().to_path_buf(),
             progress_callback: Some(progress_callback),
             concurrent_streams: 2,
+            ..FileTransferConfig::default()
         };
         
         let mut manager = FileTransferManager::new(config);
@@ -832,10 +2373,7 @@ mod tests {
         assert!(received_started.load(Ordering::SeqCst), "Transfer never started");
         assert!(received_completed.load(Ordering::SeqCst), "Transfer never completed");
         
-        {
-            let bytes = received_bytes.lock().await;
-            assert_eq!(*bytes, file_size as u64, "Incorrect number of bytes transferred");
-        }
+        assert_eq!(received_bytes.load(Ordering::SeqCst), file_size as u64, "Incorrect number of bytes transferred");
         
         // Verify the received file
         let received_file_path = receive_dir.path().join("test_file.dat");
@@ -849,4 +2387,386 @@ mod tests {
         
         Ok(())
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_authenticated_transfer() -> Result<()> {
+        let send_dir = tempdir()?;
+        let receive_dir = tempdir()?;
+        let test_file_path = send_dir.path().join("payload.bin");
+        fs::write(&test_file_path, vec![0xAAu8; 256 * 1024])?;
+
+        let mut receiver = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.path().to_path_buf(),
+            shared_secret: Some("lab-secret".to_string()),
+            allowed_senders: Some(vec!["node-a".to_string()]),
+            ..FileTransferConfig::default()
+        });
+        let server_addr = receiver.start_server().await?;
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], server_addr.port()));
+
+        let sender = |node_id: &str, secret: Option<&str>| FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            chunk_size: 64 * 1024,
+            concurrent_streams: 1,
+            node_id: Some(node_id.to_string()),
+            shared_secret: secret.map(str::to_string),
+            ..FileTransferConfig::default()
+        });
+
+        assert!(sender("node-a", None).send_file(&test_file_path, server_addr).await.is_err());
+        assert!(sender("node-a", Some("wrong")).send_file(&test_file_path, server_addr).await.is_err());
+        assert!(sender("node-b", Some("lab-secret")).send_file(&test_file_path, server_addr).await.is_err());
+        assert!(!receive_dir.path().join("payload.bin").exists());
+
+        sender("node-a", Some("lab-secret")).send_file(&test_file_path, server_addr).await?;
+        assert_eq!(fs::metadata(receive_dir.path().join("payload.bin"))?.len(), 256 * 1024);
+
+        receiver.stop_server().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_key_authenticated_transfer() -> Result<()> {
+        use crate::networking::NodeKey;
+
+        let send_dir = tempdir()?;
+        let receive_dir = tempdir()?;
+        let test_file_path = send_dir.path().join("payload.bin");
+        fs::write(&test_file_path, vec![0x55u8; 128 * 1024])?;
+
+        // Keys replace the shared secret for the allowlist
+        let mut receiver = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.path().to_path_buf(),
+            allowed_senders: Some(vec!["node-a".to_string()]),
+            peer_auth: Some(PeerAuth::new("node-r", NodeKey::generate()?, true)),
+            ..FileTransferConfig::default()
+        });
+        let server_addr = receiver.start_server().await?;
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], server_addr.port()));
+
+        let sender = |auth: Option<PeerAuth>| FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            concurrent_streams: 1,
+            node_id: Some("node-a".to_string()),
+            peer_auth: auth,
+            ..FileTransferConfig::default()
+        });
+        let key_a = NodeKey::generate()?;
+
+        assert!(sender(None).send_file(&test_file_path, server_addr).await.is_err());
+        sender(Some(PeerAuth::new("node-a", key_a.clone(), true))).send_file(&test_file_path, server_addr).await?;
+        assert_eq!(fs::metadata(receive_dir.path().join("payload.bin"))?.len(), 128 * 1024);
+
+        // Node A's key is pinned now, so another key claiming its ID is refused
+        let impostor = PeerAuth::new("node-a", NodeKey::generate()?, true);
+        let err = sender(Some(impostor)).send_file(&test_file_path, server_addr).await.unwrap_err();
+        assert!(err.to_string().contains("node key"), "{}", err);
+
+        receiver.stop_server().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_policy() -> Result<()> {
+        let send_dir = tempdir()?;
+        let receive_dir = tempdir()?;
+        fs::write(send_dir.path().join("weights.bin"), vec![1u8; 300 * 1024])?;
+        fs::write(send_dir.path().join("big.bin"), vec![2u8; 600 * 1024])?;
+        fs::write(send_dir.path().join("notes.txt"), "hello")?;
+
+        let mut receiver = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.path().to_path_buf(),
+            receive_policy: ReceivePolicy {
+                max_file_size: Some(512 * 1024),
+                allowed_extensions: Some(vec!["bin".to_string()]),
+                ..ReceivePolicy::default()
+            },
+            ..FileTransferConfig::default()
+        });
+        let server_addr = receiver.start_server().await?;
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], server_addr.port()));
+        let sender = FileTransferManager::new(FileTransferConfig {
+            chunk_size: 64 * 1024,
+            ..FileTransferConfig::default()
+        });
+
+        let err = sender.send_file(send_dir.path().join("notes.txt"), server_addr).await.unwrap_err();
+        assert!(err.to_string().contains("Files like notes.txt are not accepted"), "{}", err);
+        let err = sender.sync_file(send_dir.path().join("big.bin"), server_addr).await.unwrap_err();
+        assert!(err.to_string().contains("larger than the 524288 byte limit"), "{}", err);
+        assert!(!receive_dir.path().join("notes.txt").exists());
+        assert!(!receive_dir.path().join("big.bin").exists());
+
+        sender.send_file(send_dir.path().join("weights.bin"), server_addr).await?;
+        assert_eq!(fs::metadata(receive_dir.path().join("weights.bin"))?.len(), 300 * 1024);
+
+        receiver.stop_server().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_duplicates_stored_once() -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let send_dir = tempdir()?;
+        let receive_dir = tempdir()?;
+        let data: Vec<u8> = (0..300 * 1024u32).map(|i| (i % 253) as u8).collect();
+        fs::write(send_dir.path().join("model.bin"), &data)?;
+        fs::write(send_dir.path().join("model-copy.bin"), &data)?;
+        let hash = FileTransferManager::calculate_file_hash(&send_dir.path().join("model.bin"))?;
+
+        let mut receiver = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.path().to_path_buf(),
+            ..FileTransferConfig::default()
+        });
+        let server_addr = receiver.start_server().await?;
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], server_addr.port()));
+        let sender = FileTransferManager::new(FileTransferConfig {
+            chunk_size: 64 * 1024,
+            ..FileTransferConfig::default()
+        });
+
+        sender.send_file(send_dir.path().join("model.bin"), server_addr).await?;
+        sender.send_file(send_dir.path().join("model-copy.bin"), server_addr).await?;
+        let received = fs::metadata(receive_dir.path().join("model.bin"))?;
+        assert_eq!(received.nlink(), 2);
+        assert_eq!(received.ino(), fs::metadata(receive_dir.path().join("model-copy.bin"))?.ino());
+        assert_eq!(receiver.find_by_hash(&hash), Some(receive_dir.path().join("model.bin")));
+
+        // Receiving new content under a shared name leaves the other name intact
+        fs::write(send_dir.path().join("model.bin"), b"retrained")?;
+        sender.send_file(send_dir.path().join("model.bin"), server_addr).await?;
+        assert_eq!(fs::read(receive_dir.path().join("model.bin"))?, b"retrained");
+        assert_eq!(fs::read(receive_dir.path().join("model-copy.bin"))?, data);
+        assert_eq!(receiver.find_by_hash(&hash), Some(receive_dir.path().join("model-copy.bin")));
+
+        receiver.stop_server().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_allowlist_requires_secret() {
+        let mut manager = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            allowed_senders: Some(vec!["node-a".to_string()]),
+            ..FileTransferConfig::default()
+        });
+        let err = manager.start_server().await.unwrap_err();
+        assert_eq!(err.to_string(), "A sender allowlist requires a shared secret or required peer keys");
+    }
+
+    #[tokio::test]
+    async fn test_directory_transfer() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let send_dir = tempdir()?;
+        let receive_dir = tempdir()?;
+        let root = send_dir.path().join("models");
+        fs::create_dir_all(root.join("weights/empty"))?;
+        fs::write(root.join("weights/layer0.bin"), vec![7u8; 300 * 1024])?;
+        fs::write(root.join("config.json"), "{}")?;
+        fs::write(root.join("run.sh"), "#!/bin/sh\n")?;
+        fs::set_permissions(root.join("run.sh"), fs::Permissions::from_mode(0o755))?;
+
+        let mut manager = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            chunk_size: 64 * 1024,
+            receive_dir: receive_dir.path().to_path_buf(),
+            ..FileTransferConfig::default()
+        });
+        let server_addr = manager.start_server().await?;
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], server_addr.port()));
+        manager.send_file(&root, server_addr).await?;
+
+        let received = receive_dir.path().join("models");
+        assert_eq!(fs::read(received.join("weights/layer0.bin"))?, vec![7u8; 300 * 1024]);
+        assert_eq!(fs::read_to_string(received.join("config.json"))?, "{}");
+        assert!(received.join("weights/empty").is_dir());
+
+        let script = fs::metadata(received.join("run.sh"))?;
+        assert_eq!(script.permissions().mode() & 0o777, 0o755);
+        assert_eq!(mtime_of(&script), mtime_of(&fs::metadata(root.join("run.sh"))?));
+        assert_eq!(
+            mtime_of(&fs::metadata(received.join("weights"))?),
+            mtime_of(&fs::metadata(root.join("weights"))?)
+        );
+
+        // Only the tree and the content index are left behind, no tracking files or manifest
+        assert_eq!(received_entries(receive_dir.path())?, ["models"]);
+
+        manager.stop_server().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancel_transfer() -> Result<()> {
+        let send_dir = tempdir()?;
+        let receive_dir = tempdir()?;
+        let test_file_path = send_dir.path().join("large.bin");
+        fs::write(&test_file_path, vec![0x11u8; 1024 * 1024])?;
+
+        let mut receiver = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.path().to_path_buf(),
+            ..FileTransferConfig::default()
+        });
+        let server_addr = receiver.start_server().await?;
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], server_addr.port()));
+
+        // Slow enough to be caught in flight: 1 MiB at 256 KiB/s
+        let (status_tx, mut status_rx) = mpsc::unbounded_channel();
+        let sender = Arc::new(FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            chunk_size: 16 * 1024,
+            concurrent_streams: 2,
+            progress_callback: Some(Arc::new(move |status| { let _ = status_tx.send(status); })),
+            transfer_limit: RateLimit::from_kib(256, Some(16)),
+            ..FileTransferConfig::default()
+        }));
+        let sending = {
+            let sender = sender.clone();
+            let path = test_file_path.clone();
+            tokio::spawn(async move { sender.send_file(&path, server_addr).await })
+        };
+
+        let file_id = match status_rx.recv().await {
+            Some(TransferStatus::Started { file_id, .. }) => file_id,
+            other => panic!("Unexpected status {:?}", other),
+        };
+        sender.pause_transfer(&file_id)?;
+        sender.resume_transfer(&file_id)?;
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        sender.cancel_transfer(&file_id)?;
+        assert!(sender.resume_transfer(&file_id).is_err());
+
+        let err = sending.await?.unwrap_err();
+        assert_eq!(err.to_string(), format!("Transfer {} was cancelled", file_id));
+        let mut cancelled = false;
+        while let Ok(status) = status_rx.try_recv() {
+            cancelled |= matches!(status, TransferStatus::Cancelled { .. });
+        }
+        assert!(cancelled, "Cancellation was not reported");
+
+        // The receiver dropped the partial file and its tracking files
+        assert_eq!(fs::read_dir(receive_dir.path())?.count(), 0);
+        assert!(sender.cancel_transfer(&file_id).is_err());
+
+        receiver.stop_server().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_file() -> Result<()> {
+        let send_dir = tempdir()?;
+        let receive_dir = tempdir()?;
+        let old: Vec<u8> = (0..1_000_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 11) as u8).collect();
+        let mut new = old.clone();
+        new[400_000..400_100].fill(0);
+        new.extend_from_slice(b"appended");
+        let path = send_dir.path().join("model.bin");
+        fs::write(&path, &new)?;
+
+        let mut manager = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.path().to_path_buf(),
+            ..FileTransferConfig::default()
+        });
+        let server_addr = manager.start_server().await?;
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], server_addr.port()));
+
+        // No copy yet: everything is sent
+        let stats = manager.sync_file(&path, server_addr).await?;
+        assert_eq!((stats.literal_bytes, stats.matched_bytes), (new.len() as u64, 0));
+        assert_eq!(fs::read(receive_dir.path().join("model.bin"))?, new);
+
+        // An older copy: only the changed blocks travel
+        fs::write(receive_dir.path().join("model.bin"), &old)?;
+        let stats = manager.sync_file(&path, server_addr).await?;
+        assert_eq!(fs::read(receive_dir.path().join("model.bin"))?, new);
+        assert!(stats.literal_bytes < 8 * 1024, "sent {} bytes", stats.literal_bytes);
+        assert_eq!(stats.matched_bytes + stats.literal_bytes, new.len() as u64);
+
+        // No leftovers next to the file
+        assert_eq!(received_entries(receive_dir.path())?, ["model.bin"]);
+        assert!(manager.sync_file(send_dir.path(), server_addr).await.is_err());
+
+        manager.stop_server().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_folder_sync_newest_wins() -> Result<()> {
+        let send_dir = tempdir()?;
+        let receive_dir = tempdir()?;
+        let path = send_dir.path().join("notes.txt");
+        let copy = receive_dir.path().join("shared/notes.txt");
+        let hour_ago = SystemTime::now() - std::time::Duration::from_secs(3600);
+        let set_mtime = |path: &Path, mtime: SystemTime| File::options().write(true).open(path)?.set_modified(mtime);
+
+        let mut manager = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.path().to_path_buf(),
+            ..FileTransferConfig::default()
+        });
+        let server_addr = manager.start_server().await?;
+        let target = Destination {
+            addr: SocketAddr::from(([127, 0, 0, 1], server_addr.port())),
+            token: None,
+            node_id: None,
+            rdma: false,
+        };
+
+        // Sent under the folder's name, then kept once the same
+        fs::write(&path, b"ours")?;
+        assert!(manager.sync_as(&path, "shared/notes.txt", &target, FRAME_FOLDER_SYNC).await?.is_some());
+        assert_eq!(fs::read(&copy)?, b"ours");
+        assert!(manager.sync_as(&path, "shared/notes.txt", &target, FRAME_FOLDER_SYNC).await?.is_none());
+
+        // A newer copy on the receiver wins over an older file
+        fs::write(&copy, b"theirs, newer")?;
+        set_mtime(&path, hour_ago)?;
+        assert!(manager.sync_as(&path, "shared/notes.txt", &target, FRAME_FOLDER_SYNC).await?.is_none());
+        assert_eq!(fs::read(&copy)?, b"theirs, newer");
+
+        // A deletion only removes copies not modified after it
+        assert!(!manager.delete_on("shared/notes.txt", hour_ago, &target).await?);
+        assert!(copy.exists());
+        assert!(manager.delete_on("shared/notes.txt", SystemTime::now(), &target).await?);
+        assert!(!copy.exists());
+        assert!(manager.delete_on("shared/notes.txt", SystemTime::now(), &target).await?);
+
+        manager.stop_server().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_benchmark() -> Result<()> {
+        let receive_dir = tempdir()?;
+        let mut receiver = FileTransferManager::new(FileTransferConfig {
+            port: 0,
+            receive_dir: receive_dir.path().to_path_buf(),
+            ..FileTransferConfig::default()
+        });
+        let server_addr = receiver.start_server().await?;
+        let server_addr = SocketAddr::from(([127, 0, 0, 1], server_addr.port()));
+        let sender = FileTransferManager::new(FileTransferConfig {
+            chunk_size: 64 * 1024,
+            ..FileTransferConfig::default()
+        });
+
+        let plan = BenchmarkPlan { pings: 5, stream_counts: vec![1, 3], run_bytes: 4 * 1024 * 1024 + 1 };
+        let report = sender.benchmark(server_addr, &plan).await?;
+        assert_eq!(report.latency.as_ref().map(|latency| latency.samples), Some(5));
+        assert_eq!(report.runs.iter().map(|run| run.streams).collect::<Vec<_>>(), [1, 3]);
+        assert!(report.runs.iter().all(|run| run.bytes == plan.run_bytes && run.throughput_mbps > 0.0));
+        // Nothing was written to the receiver's disk
+        assert!(received_entries(receive_dir.path())?.is_empty());
+
+        receiver.stop_server().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,401 @@
+// src/networking/folder_sync.rs
+//
+// Folder sync
+// A node keeps a directory in sync on chosen peers. Changes under it are
+// picked up as they happen, from `fs_watch`, and by a periodic rescan that
+// catches whatever a watcher missed or can't see. Each changed file goes to
+// every peer as a delta sync, so only the blocks that differ cross the link,
+// and lands in the peer's receive directory under the folder's name.
+// Conflicts resolve by modification time, newest wins: a peer keeps its copy
+// when it was modified after ours (or is the same), and a deleted file is
+// only removed where it wasn't modified after the deletion. Syncing the
+// peer's <receive dir>/<folder name> back makes the sync two-way; files that
+// come back unchanged are kept after one round trip. Changes a peer misses
+// while unreachable stay queued for it.
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use super::communication::NodeClient;
+use super::discovery::{NodeDiscovery, NodeInfo};
+use super::file_transfer::FileTransferManager;
+use super::fs_watch;
+use super::manifest::mtime_of;
+
+/// Time between full rescans of the folder
+pub const DEFAULT_RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+/// Time to wait for more changes after the watcher reports one
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// A folder to keep in sync, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FolderSyncConfig {
+    pub root: PathBuf,
+    /// Directory the files go to in the peers' receive directories
+    pub name: String,
+    /// IDs, ID prefixes or names of the peers
+    pub peers: Vec<String>,
+    pub rescan_interval: Duration,
+}
+
+impl FolderSyncConfig {
+    /// Sync `root` to `peers` under its own name
+    pub fn new(root: impl Into<PathBuf>, peers: Vec<String>) -> Self {
+        let root = root.into();
+        let name = root.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        Self { root, name, peers, rescan_interval: DEFAULT_RESCAN_INTERVAL }
+    }
+}
+
+/// Where the sync of a folder stands
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FolderSyncStatus {
+    pub root: String,
+    pub name: String,
+    /// Whether changes are picked up as they happen, not only by rescans
+    pub watching: bool,
+    /// Files in the folder
+    pub files: usize,
+    /// Unix time in seconds of the last scan
+    pub last_scan: Option<u64>,
+    pub peers: Vec<PeerSyncStatus>,
+}
+
+/// Where the sync of a folder to one peer stands
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PeerSyncStatus {
+    /// The peer as configured
+    pub peer: String,
+    /// Its node ID, once discovered
+    pub node_id: Option<String>,
+    /// Files brought up to date
+    pub synced: u64,
+    /// Files the peer kept, its copy being as new or newer
+    pub kept: u64,
+    /// Files removed from the peer
+    pub deleted: u64,
+    pub failed: u64,
+    /// Bytes sent that the peer's copies lacked
+    pub bytes_sent: u64,
+    /// Changes not yet on the peer
+    pub pending: usize,
+    /// Unix time in seconds of the last change the peer took or kept
+    pub last_sync: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+/// Size and modification time of a file as last scanned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileState {
+    size: u64,
+    mtime: (u64, u32),
+}
+
+/// What happened to a file since the last scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Modified,
+    Deleted(SystemTime),
+}
+
+/// Files in a folder by their path relative to it, separated by '/'
+type Index = BTreeMap<String, FileState>;
+
+/// Keeps a folder in sync on its peers; clones share the sync
+#[derive(Clone)]
+pub struct FolderSync {
+    config: FolderSyncConfig,
+    transfers: Arc<FileTransferManager>,
+    client: Arc<NodeClient>,
+    discovery: Arc<NodeDiscovery>,
+    status: Arc<StdMutex<FolderSyncStatus>>,
+}
+
+impl FolderSync {
+    pub fn new(
+        config: FolderSyncConfig,
+        transfers: Arc<FileTransferManager>,
+        client: Arc<NodeClient>,
+        discovery: Arc<NodeDiscovery>,
+    ) -> Result<Self> {
+        if !config.root.is_dir() {
+            return Err(anyhow!("Sync folder {} is not a directory", config.root.display()));
+        }
+        if config.name.is_empty() || config.peers.is_empty() {
+            return Err(anyhow!("Folder sync of {} needs a name and at least one peer", config.root.display()));
+        }
+        let status = FolderSyncStatus {
+            root: config.root.display().to_string(),
+            name: config.name.clone(),
+            watching: false,
+            files: 0,
+            last_scan: None,
+            peers: config.peers.iter()
+                .map(|peer| PeerSyncStatus { peer: peer.clone(), ..Default::default() })
+                .collect(),
+        };
+        Ok(Self { config, transfers, client, discovery, status: Arc::new(StdMutex::new(status)) })
+    }
+
+    pub fn status(&self) -> FolderSyncStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Watch and rescan the folder, sending its changes to the peers
+    pub fn spawn(&self) -> JoinHandle<()> {
+        let sync = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = sync.run().await {
+                warn!("Folder sync of {} stopped: {}", sync.config.root.display(), e);
+            }
+        })
+    }
+
+    async fn run(&self) -> Result<()> {
+        let root = fs::canonicalize(&self.config.root)
+            .with_context(|| format!("Failed to resolve {}", self.config.root.display()))?;
+        let mut events = if !fs_watch::SUPPORTED {
+            info!("This platform has no directory watcher, changes to {} are picked up by rescans only", root.display());
+            None
+        } else {
+            match fs_watch::watch(&root) {
+                Ok(events) => Some(events),
+                Err(e) => {
+                    warn!("Not watching {}, changes are picked up by rescans only: {}", root.display(), e);
+                    None
+                }
+            }
+        };
+        self.status.lock().unwrap().watching = events.is_some();
+        info!("Syncing {} to {} as {}", root.display(), self.config.peers.join(", "), self.config.name);
+
+        // Starting from an empty index offers every file once, which the
+        // peers keep where they already have it
+        let mut index = Index::new();
+        let mut pending: BTreeMap<String, BTreeMap<String, Change>> =
+            self.config.peers.iter().map(|peer| (peer.clone(), BTreeMap::new())).collect();
+        let mut rescan = tokio::time::interval(self.config.rescan_interval);
+        loop {
+            let mut changed = BTreeSet::new();
+            tokio::select! {
+                _ = rescan.tick() => {
+                    changed.insert(root.clone());
+                }
+                event = next_event(&mut events) => match event {
+                    Some(path) => {
+                        // Take in the rest of a burst of changes
+                        tokio::time::sleep(DEBOUNCE).await;
+                        changed.insert(path);
+                        while let Some(path) = events.as_mut().and_then(|events| events.try_recv().ok()) {
+                            changed.insert(path);
+                        }
+                    }
+                    None => {
+                        warn!("Stopped watching {}, changes are picked up by rescans only", root.display());
+                        events = None;
+                        self.status.lock().unwrap().watching = false;
+                        continue;
+                    }
+                },
+            }
+
+            let scan_root = root.clone();
+            let (scanned, changes) = tokio::task::spawn_blocking(move || {
+                let changes = rescan_paths(&scan_root, &mut index, &changed, SystemTime::now());
+                (index, changes)
+            })
+            .await?;
+            index = scanned;
+            if !changes.is_empty() {
+                debug!("{} changes under {}", changes.len(), root.display());
+            }
+            for queue in pending.values_mut() {
+                queue.extend(changes.iter().map(|(name, change)| (name.clone(), *change)));
+            }
+            {
+                let mut status = self.status.lock().unwrap();
+                status.files = index.len();
+                status.last_scan = Some(unix_now());
+            }
+            self.push(&root, &mut pending).await;
+        }
+    }
+
+    /// Send the changes queued for each peer, keeping those that failed
+    async fn push(&self, root: &Path, pending: &mut BTreeMap<String, BTreeMap<String, Change>>) {
+        let nodes = self.discovery.get_discovered_nodes();
+        let local_node = self.discovery.get_local_node();
+        for (peer, queue) in pending.iter_mut() {
+            let Some(node) = find_node(&nodes, peer) else {
+                if !queue.is_empty() {
+                    self.update_peer(peer, |status| status.last_error = Some("Not discovered".to_string()));
+                }
+                self.update_peer(peer, |status| status.pending = queue.len());
+                continue;
+            };
+            self.update_peer(peer, |status| status.node_id = Some(node.id.clone()));
+
+            let changes: Vec<(String, Change)> = queue.iter().map(|(name, change)| (name.clone(), *change)).collect();
+            for (name, change) in changes {
+                let remote = format!("{}/{}", self.config.name, name);
+                let result = match change {
+                    Change::Modified => self.transfers
+                        .sync_newest_to_node(&self.client, node, &local_node, &root.join(&name), &remote).await
+                        .map(|stats| stats.map(|stats| stats.literal_bytes)),
+                    Change::Deleted(deleted_at) => self.transfers
+                        .delete_on_node(&self.client, node, &local_node, &remote, deleted_at).await
+                        .map(|deleted| deleted.then_some(0)),
+                };
+                match result {
+                    Ok(sent) => {
+                        queue.remove(&name);
+                        self.update_peer(peer, |status| {
+                            match (change, sent) {
+                                (_, None) => status.kept += 1,
+                                (Change::Modified, Some(bytes)) => {
+                                    status.synced += 1;
+                                    status.bytes_sent += bytes;
+                                }
+                                (Change::Deleted(_), Some(_)) => status.deleted += 1,
+                            }
+                            status.last_sync = Some(unix_now());
+                        });
+                    }
+                    Err(e) => {
+                        warn!("Failed to sync {} to {}: {}", remote, node.name, e);
+                        self.update_peer(peer, |status| {
+                            status.failed += 1;
+                            status.last_error = Some(format!("{}: {}", name, e));
+                        });
+                    }
+                }
+            }
+            self.update_peer(peer, |status| status.pending = queue.len());
+        }
+    }
+
+    fn update_peer(&self, peer: &str, update: impl FnOnce(&mut PeerSyncStatus)) {
+        let mut status = self.status.lock().unwrap();
+        if let Some(status) = status.peers.iter_mut().find(|status| status.peer == peer) {
+            update(status);
+        }
+    }
+}
+
+/// The next path the watcher reports; never resolves without a watcher
+async fn next_event(events: &mut Option<mpsc::UnboundedReceiver<PathBuf>>) -> Option<PathBuf> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The node with ID, ID prefix or name `peer`
+fn find_node<'a>(nodes: &'a [NodeInfo], peer: &str) -> Option<&'a NodeInfo> {
+    nodes.iter()
+        .find(|node| node.id == peer || node.name == peer)
+        .or_else(|| nodes.iter().find(|node| node.id.starts_with(peer)))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Rescan `paths` under `root`, updating `index`; returns what changed, with
+/// deletions dated `now`
+fn rescan_paths(root: &Path, index: &mut Index, paths: &BTreeSet<PathBuf>, now: SystemTime) -> BTreeMap<String, Change> {
+    let mut changes = BTreeMap::new();
+    for path in paths {
+        let Some(prefix) = path.strip_prefix(root).ok().and_then(relative_name) else {
+            continue;
+        };
+        let old: Index = index.iter()
+            .filter(|(name, _)| prefix.is_empty() || **name == prefix || name.starts_with(&format!("{}/", prefix)))
+            .map(|(name, state)| (name.clone(), *state))
+            .collect();
+        let mut new = Index::new();
+        scan(path, &prefix, &mut new);
+
+        changes.extend(diff(&old, &new, now));
+        for name in old.keys() {
+            index.remove(name);
+        }
+        index.extend(new);
+    }
+    changes
+}
+
+/// Add the file at `path`, or every file under it, named from `name`
+fn scan(path: &Path, name: &str, index: &mut Index) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return;
+    };
+    if metadata.is_file() {
+        index.insert(name.to_string(), FileState { size: metadata.len(), mtime: mtime_of(&metadata) });
+    } else if metadata.is_dir() {
+        for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+            let entry_name = entry.file_name().to_string_lossy().to_string();
+            let name = if name.is_empty() { entry_name } else { format!("{}/{}", name, entry_name) };
+            scan(&entry.path(), &name, index);
+        }
+    }
+}
+
+/// Changes turning `old` into `new`, with deletions dated `now`
+fn diff(old: &Index, new: &Index, now: SystemTime) -> BTreeMap<String, Change> {
+    let modified = new.iter()
+        .filter(|(name, state)| old.get(*name) != Some(state))
+        .map(|(name, _)| (name.clone(), Change::Modified));
+    let deleted = old.keys()
+        .filter(|name| !new.contains_key(*name))
+        .map(|name| (name.clone(), Change::Deleted(now)));
+    modified.chain(deleted).collect()
+}
+
+/// `path` as a '/' separated name, None unless it is plain UTF-8
+fn relative_name(path: &Path) -> Option<String> {
+    path.components()
+        .map(|component| component.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()
+        .map(|parts| parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rescan_finds_changes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+        fs::create_dir_all(root.join("a/b"))?;
+        fs::write(root.join("top"), b"1")?;
+        fs::write(root.join("a/b/deep"), b"2")?;
+        fs::write(root.join("a/other"), b"3")?;
+
+        let now = SystemTime::now();
+        let mut index = Index::new();
+        let everything = BTreeSet::from([root.to_path_buf()]);
+        let changes = rescan_paths(root, &mut index, &everything, now);
+        assert_eq!(changes.keys().collect::<Vec<_>>(), ["a/b/deep", "a/other", "top"]);
+        assert!(rescan_paths(root, &mut index, &everything, now).is_empty());
+
+        // A removed directory deletes what was under it, and only that
+        fs::remove_dir_all(root.join("a/b"))?;
+        fs::write(root.join("top"), b"longer")?;
+        let changes = rescan_paths(root, &mut index, &BTreeSet::from([root.join("a/b")]), now);
+        assert_eq!(changes, BTreeMap::from([("a/b/deep".to_string(), Change::Deleted(now))]));
+        let changes = rescan_paths(root, &mut index, &everything, now);
+        assert_eq!(changes, BTreeMap::from([("top".to_string(), Change::Modified)]));
+        assert_eq!(index.keys().collect::<Vec<_>>(), ["a/other", "top"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+// src/networking/fs_watch.rs
+//
+// Directory watching
+// Reports the paths that change under a directory, to be rescanned: inotify
+// on Linux, with a watch on every directory, added as new ones appear, and
+// FSEvents on macOS, which watches the whole tree by itself. Both run on a
+// thread of their own. A reported path may be a file or a directory, and may
+// no longer exist; when inotify drops events it reports the root. Other
+// platforms have no watcher, and callers fall back to rescanning.
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+/// Whether this platform can watch directories
+pub const SUPPORTED: bool = cfg!(any(target_os = "linux", target_os = "macos"));
+
+/// Watch everything under `root`; the paths that change arrive on the
+/// returned channel, which closes if the watcher fails
+pub fn watch(root: &Path) -> Result<mpsc::UnboundedReceiver<PathBuf>> {
+    imp::watch(root)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use anyhow::{Context, Result};
+    use log::{debug, warn};
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::fs;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use tokio::sync::mpsc;
+
+    /// Events of a watched directory and its entries worth a rescan
+    const MASK: u32 = libc::IN_CREATE | libc::IN_MODIFY | libc::IN_CLOSE_WRITE | libc::IN_ATTRIB
+        | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_ONLYDIR;
+
+    struct Watcher {
+        fd: OwnedFd,
+        root: PathBuf,
+        /// Directory of each watch descriptor
+        dirs: HashMap<i32, PathBuf>,
+    }
+
+    pub fn watch(root: &Path) -> Result<mpsc::UnboundedReceiver<PathBuf>> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to start inotify");
+        }
+        let mut watcher = Watcher { fd: unsafe { OwnedFd::from_raw_fd(fd) }, root: root.to_path_buf(), dirs: HashMap::new() };
+        watcher.add_tree(root);
+        if watcher.dirs.is_empty() {
+            return Err(anyhow::anyhow!("Failed to watch {}", root.display()));
+        }
+
+        let (changes, receiver) = mpsc::unbounded_channel();
+        std::thread::Builder::new()
+            .name("fs-watch".to_string())
+            .spawn(move || watcher.run(changes))?;
+        Ok(receiver)
+    }
+
+    impl Watcher {
+        /// Watch `dir` and every directory under it
+        fn add_tree(&mut self, dir: &Path) {
+            let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+                return;
+            };
+            let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), MASK) };
+            if wd < 0 {
+                warn!("Failed to watch {}: {}", dir.display(), io::Error::last_os_error());
+                return;
+            }
+            self.dirs.insert(wd, dir.to_path_buf());
+            for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+                if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                    self.add_tree(&entry.path());
+                }
+            }
+        }
+
+        /// Read events until the receiver of `changes` is gone
+        fn run(mut self, changes: mpsc::UnboundedSender<PathBuf>) {
+            let mut buffer = vec![0u8; 64 * 1024];
+            loop {
+                let n = unsafe { libc::read(self.fd.as_raw_fd(), buffer.as_mut_ptr().cast(), buffer.len()) };
+                if n < 0 {
+                    let e = io::Error::last_os_error();
+                    if e.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    warn!("Stopped watching {}: {}", self.root.display(), e);
+                    return;
+                }
+
+                let mut offset = 0;
+                while offset + size_of::<libc::inotify_event>() <= n as usize {
+                    let event = unsafe {
+                        std::ptr::read_unaligned(buffer.as_ptr().add(offset).cast::<libc::inotify_event>())
+                    };
+                    let name_start = offset + size_of::<libc::inotify_event>();
+                    offset = name_start + event.len as usize;
+                    let name = buffer[name_start..offset.min(n as usize)].split(|byte| *byte == 0).next().unwrap_or_default();
+
+                    if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                        debug!("inotify queue overflowed, rescanning {}", self.root.display());
+                        if changes.send(self.root.clone()).is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    if event.mask & libc::IN_IGNORED != 0 {
+                        self.dirs.remove(&event.wd);
+                        continue;
+                    }
+                    let Some(dir) = self.dirs.get(&event.wd) else {
+                        continue;
+                    };
+                    let path = if name.is_empty() { dir.clone() } else { dir.join(OsStr::from_bytes(name)) };
+                    if event.mask & libc::IN_ISDIR != 0 && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                        self.add_tree(&path);
+                    }
+                    if changes.send(path).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use anyhow::{anyhow, Result};
+    use std::ffi::{c_char, c_void, CStr, CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc as std_mpsc;
+    use tokio::sync::mpsc;
+
+    const UTF8_ENCODING: u32 = 0x0800_0100;
+    const SINCE_NOW: u64 = u64::MAX;
+    const CREATE_FLAG_NO_DEFER: u32 = 0x02;
+    const CREATE_FLAG_FILE_EVENTS: u32 = 0x10;
+    /// Seconds FSEvents coalesces events for
+    const LATENCY: f64 = 0.2;
+
+    #[repr(C)]
+    struct FSEventStreamContext {
+        version: isize,
+        info: *mut c_void,
+        retain: *const c_void,
+        release: *const c_void,
+        copy_description: *const c_void,
+    }
+
+    #[repr(C)]
+    struct CFArrayCallBacks {
+        version: isize,
+        retain: *const c_void,
+        release: *const c_void,
+        copy_description: *const c_void,
+        equal: *const c_void,
+    }
+
+    type Callback = extern "C" fn(*const c_void, *mut c_void, usize, *mut c_void, *const u32, *const u64);
+
+    #[link(name = "CoreServices", kind = "framework")]
+    extern "C" {
+        fn FSEventStreamCreate(
+            allocator: *const c_void,
+            callback: Callback,
+            context: *const FSEventStreamContext,
+            paths: *const c_void,
+            since_when: u64,
+            latency: f64,
+            flags: u32,
+        ) -> *mut c_void;
+        fn FSEventStreamScheduleWithRunLoop(stream: *mut c_void, run_loop: *mut c_void, mode: *const c_void);
+        fn FSEventStreamStart(stream: *mut c_void) -> u8;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        static kCFRunLoopDefaultMode: *const c_void;
+        static kCFTypeArrayCallBacks: CFArrayCallBacks;
+        fn CFRunLoopGetCurrent() -> *mut c_void;
+        fn CFRunLoopRun();
+        fn CFStringCreateWithCString(allocator: *const c_void, string: *const c_char, encoding: u32) -> *const c_void;
+        fn CFArrayCreate(
+            allocator: *const c_void,
+            values: *const *const c_void,
+            count: isize,
+            callbacks: *const CFArrayCallBacks,
+        ) -> *const c_void;
+    }
+
+    extern "C" fn on_events(
+        _stream: *const c_void,
+        info: *mut c_void,
+        count: usize,
+        paths: *mut c_void,
+        _flags: *const u32,
+        _ids: *const u64,
+    ) {
+        let changes = unsafe { &*(info as *const mpsc::UnboundedSender<PathBuf>) };
+        let paths = paths as *const *const c_char;
+        for index in 0..count {
+            let path = unsafe { CStr::from_ptr(*paths.add(index)) };
+            let _ = changes.send(PathBuf::from(OsStr::from_bytes(path.to_bytes())));
+        }
+    }
+
+    pub fn watch(root: &Path) -> Result<mpsc::UnboundedReceiver<PathBuf>> {
+        let root = CString::new(root.as_os_str().as_bytes())?;
+        let (changes, receiver) = mpsc::unbounded_channel();
+        let (started, outcome) = std_mpsc::channel();
+        std::thread::Builder::new()
+            .name("fs-watch".to_string())
+            .spawn(move || unsafe {
+                // The sender lives as long as the thread's run loop, for good
+                let info = Box::into_raw(Box::new(changes)) as *mut c_void;
+                let context = FSEventStreamContext {
+                    version: 0,
+                    info,
+                    retain: std::ptr::null(),
+                    release: std::ptr::null(),
+                    copy_description: std::ptr::null(),
+                };
+                let path = CFStringCreateWithCString(std::ptr::null(), root.as_ptr(), UTF8_ENCODING);
+                let paths = CFArrayCreate(std::ptr::null(), &path, 1, &kCFTypeArrayCallBacks);
+                let stream = FSEventStreamCreate(
+                    std::ptr::null(),
+                    on_events,
+                    &context,
+                    paths,
+                    SINCE_NOW,
+                    LATENCY,
+                    CREATE_FLAG_FILE_EVENTS | CREATE_FLAG_NO_DEFER,
+                );
+                if stream.is_null() {
+                    let _ = started.send(Err(anyhow!("Failed to create an FSEvents stream")));
+                    return;
+                }
+                FSEventStreamScheduleWithRunLoop(stream, CFRunLoopGetCurrent(), kCFRunLoopDefaultMode);
+                if FSEventStreamStart(stream) == 0 {
+                    let _ = started.send(Err(anyhow!("Failed to start the FSEvents stream")));
+                    return;
+                }
+                let _ = started.send(Ok(()));
+                CFRunLoopRun();
+            })?;
+        outcome.recv().map_err(|_| anyhow!("FSEvents thread stopped"))??;
+        Ok(receiver)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod imp {
+    use anyhow::{anyhow, Result};
+    use std::path::{Path, PathBuf};
+    use tokio::sync::mpsc;
+
+    pub fn watch(_root: &Path) -> Result<mpsc::UnboundedReceiver<PathBuf>> {
+        Err(anyhow!("Watching directories is not supported on this platform"))
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_reports_changes_in_new_directories() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut changes = watch(dir.path())?;
+
+        std::fs::create_dir(dir.path().join("sub"))?;
+        assert_eq!(tokio::time::timeout(Duration::from_secs(5), changes.recv()).await?, Some(dir.path().join("sub")));
+        // Watched as soon as it is reported
+        std::fs::write(dir.path().join("sub/file"), b"data")?;
+        loop {
+            let path = tokio::time::timeout(Duration::from_secs(5), changes.recv()).await?.unwrap();
+            if path == dir.path().join("sub/file") {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
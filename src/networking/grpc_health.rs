@@ -0,0 +1,128 @@
+// src/networking/grpc_health.rs
+//
+// Standard gRPC health checking
+// Serves grpc.health.v1.Health next to the node service so generic tooling
+// (grpcurl, load balancers, Kubernetes gRPC probes) can probe a node without
+// knowing its protocol. The status follows the node's own health status:
+// healthy and degraded nodes are serving, unhealthy ones aren't. Like the
+// probes it is meant for, it doesn't need peer authentication.
+
+use futures_util::Stream;
+use std::pin::Pin;
+use tokio::sync::watch;
+use tonic::server::NamedService;
+use tonic::{Request, Response, Status};
+
+use super::communication::node::health_check_response::Status as NodeStatus;
+use super::communication::node::node_service_server::NodeServiceServer;
+use super::communication::NodeCommunicationService;
+
+pub mod proto {
+    tonic::include_proto!("grpc.health.v1");
+}
+
+use proto::health_check_response::ServingStatus;
+use proto::health_server::Health;
+use proto::{HealthCheckRequest, HealthCheckResponse};
+
+/// Health service reporting the status of the node service
+pub struct HealthService {
+    status: watch::Receiver<NodeStatus>,
+}
+
+impl HealthService {
+    pub fn new(status: watch::Receiver<NodeStatus>) -> Self {
+        Self { status }
+    }
+
+    /// Status of `service`, where the empty name stands for the whole server;
+    /// None for services this server doesn't have
+    fn serving_status(service: &str, status: NodeStatus) -> Option<ServingStatus> {
+        if !service.is_empty() && service != NodeServiceServer::<NodeCommunicationService>::NAME {
+            return None;
+        }
+        Some(match status {
+            NodeStatus::Healthy | NodeStatus::Degraded => ServingStatus::Serving,
+            NodeStatus::Unhealthy => ServingStatus::NotServing,
+            NodeStatus::Unknown => ServingStatus::Unknown,
+        })
+    }
+}
+
+fn response(status: ServingStatus) -> HealthCheckResponse {
+    HealthCheckResponse { status: status as i32 }
+}
+
+#[tonic::async_trait]
+impl Health for HealthService {
+    async fn check(&self, request: Request<HealthCheckRequest>) -> Result<Response<HealthCheckResponse>, Status> {
+        let service = request.into_inner().service;
+        match Self::serving_status(&service, *self.status.borrow()) {
+            Some(status) => Ok(Response::new(response(status))),
+            None => Err(Status::not_found(format!("Unknown service {:?}", service))),
+        }
+    }
+
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, Status>> + Send>>;
+
+    /// Send the current status, then every change of it until the client
+    /// goes away
+    async fn watch(&self, request: Request<HealthCheckRequest>) -> Result<Response<Self::WatchStream>, Status> {
+        let service = request.into_inner().service;
+        let mut status = self.status.clone();
+        status.mark_changed();
+        let updates = futures_util::stream::unfold((status, None), move |(mut status, last)| {
+            let service = service.clone();
+            async move {
+                loop {
+                    if status.changed().await.is_err() {
+                        return None;
+                    }
+                    let current = Self::serving_status(&service, *status.borrow_and_update())
+                        .unwrap_or(ServingStatus::ServiceUnknown);
+                    if last != Some(current) {
+                        return Some((Ok(response(current)), (status, Some(current))));
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(updates)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    fn request(service: &str) -> Request<HealthCheckRequest> {
+        Request::new(HealthCheckRequest { service: service.to_string() })
+    }
+
+    async fn check(health: &HealthService, service: &str) -> Result<ServingStatus, Status> {
+        health.check(request(service)).await.map(|response| response.into_inner().status())
+    }
+
+    #[tokio::test]
+    async fn test_health_service() {
+        let node = NodeCommunicationService::new("node-1".to_string(), "node".to_string());
+        let health = HealthService::new(node.health());
+        assert_eq!(check(&health, "").await.unwrap(), ServingStatus::Serving);
+        assert_eq!(check(&health, "node.NodeService").await.unwrap(), ServingStatus::Serving);
+        assert_eq!(check(&health, "other.Service").await.unwrap_err().code(), tonic::Code::NotFound);
+
+        let mut updates = health.watch(request("node.NodeService")).await.unwrap().into_inner();
+        let mut unknown = health.watch(request("other.Service")).await.unwrap().into_inner();
+        assert_eq!(updates.next().await.unwrap().unwrap().status(), ServingStatus::Serving);
+        assert_eq!(unknown.next().await.unwrap().unwrap().status(), ServingStatus::ServiceUnknown);
+
+        // Degraded is still serving, so only the change to unhealthy is sent
+        node.update_health_status(NodeStatus::Degraded).await;
+        node.update_health_status(NodeStatus::Unhealthy).await;
+        assert_eq!(updates.next().await.unwrap().unwrap().status(), ServingStatus::NotServing);
+        assert_eq!(check(&health, "").await.unwrap(), ServingStatus::NotServing);
+
+        drop(node);
+        assert!(updates.next().await.is_none());
+    }
+}
@@ -0,0 +1,166 @@
+// src/networking/grpc_tls.rs
+//
+// Mutual TLS for the node gRPC service
+// Each node serves and calls gRPC with a certificate naming its node ID as a
+// DNS subject alternative name, issued by a cluster CA or self-signed. Both
+// ends only accept certificates that chain to the configured trust file (the
+// cluster CA, or every node's own certificate): clients check that the
+// server's certificate names the node ID they meant to reach, and the server
+// checks that a client's certificate names the node ID its requests claim,
+// so a host can't pose as another node in either direction.
+
+use anyhow::{bail, Context, Result};
+use openssl::pkey::PKey;
+use openssl::x509::X509;
+use std::fs;
+use std::path::Path;
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+use tonic::Request;
+
+/// Certificate and trust settings of a node's gRPC server and client
+#[derive(Clone)]
+pub struct GrpcTls {
+    identity: Identity,
+    trusted: Certificate,
+}
+
+impl GrpcTls {
+    /// Load the certificate of `node_id` from `cert` with its private key from
+    /// `key`, and trust the certificates in `trusted`, all PEM files
+    pub fn load(node_id: &str, cert: &Path, key: &Path, trusted: &Path) -> Result<Self> {
+        let read = |path: &Path| fs::read(path).with_context(|| format!("Failed to read {}", path.display()));
+        Self::from_pem(node_id, &read(cert)?, &read(key)?, &read(trusted)?)
+    }
+
+    /// Serve and call with the certificate chain `cert_pem`, which must name
+    /// `node_id`, and its private key `key_pem`, trusting the certificates in
+    /// `trusted_pem`
+    pub fn from_pem(node_id: &str, cert_pem: &[u8], key_pem: &[u8], trusted_pem: &[u8]) -> Result<Self> {
+        let names = certificate_names(cert_pem)?;
+        if !names.iter().any(|name| name == node_id) {
+            bail!("Certificate is for {:?}, not node {}", names, node_id);
+        }
+        // Any key format openssl reads, handed on as PKCS#8
+        let key = PKey::private_key_from_pem(key_pem).context("Invalid private key")?;
+        if !X509::from_pem(cert_pem)?.public_key()?.public_eq(&key) {
+            bail!("Certificate doesn't match the private key");
+        }
+        if X509::stack_from_pem(trusted_pem).context("Invalid trusted certificates")?.is_empty() {
+            bail!("No trusted certificates");
+        }
+        Ok(Self {
+            identity: Identity::from_pem(cert_pem, key.private_key_to_pem_pkcs8()?),
+            trusted: Certificate::from_pem(trusted_pem),
+        })
+    }
+
+    /// Server settings that require clients to present a trusted certificate
+    pub(crate) fn server_config(&self) -> ServerTlsConfig {
+        ServerTlsConfig::new()
+            .identity(self.identity.clone())
+            .client_ca_root(self.trusted.clone())
+    }
+
+    /// Client settings for a channel to `node_id`, whose certificate must be
+    /// trusted and name it
+    pub(crate) fn client_config(&self, node_id: &str) -> ClientTlsConfig {
+        ClientTlsConfig::new()
+            .identity(self.identity.clone())
+            .ca_certificate(self.trusted.clone())
+            .domain_name(node_id)
+    }
+}
+
+/// DNS names in the subject alternative names of the first certificate in `pem`
+pub fn certificate_names(pem: &[u8]) -> Result<Vec<String>> {
+    Ok(names(&X509::from_pem(pem).context("Invalid certificate")?))
+}
+
+/// DNS names of the certificate the client of `request` presented, empty
+/// without TLS
+pub fn client_names<T>(request: &Request<T>) -> Vec<String> {
+    request.peer_certs()
+        .and_then(|certificates| X509::from_der(certificates.first()?.get_ref()).ok())
+        .map(|certificate| names(&certificate))
+        .unwrap_or_default()
+}
+
+fn names(certificate: &X509) -> Vec<String> {
+    certificate.subject_alt_names()
+        .map(|names| names.iter().filter_map(|name| name.dnsname().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
+/// Certificates for tests: a cluster CA and node certificates it issued
+#[cfg(test)]
+pub(crate) mod test_certs {
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+    use openssl::x509::{X509NameBuilder, X509};
+
+    fn key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    /// Certificate for `name`, signed by `issuer` or self-signed
+    fn certificate(name: &str, key: &PKey<Private>, issuer: Option<(&X509, &PKey<Private>)>, ca: bool) -> X509 {
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_nid(Nid::COMMONNAME, name).unwrap();
+        let subject = subject.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_serial_number(&BigNum::from_u32(rand::random::<u16>().into()).unwrap().to_asn1_integer().unwrap()).unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder.set_issuer_name(issuer.map_or(&subject, |(issuer, _)| issuer.subject_name())).unwrap();
+        builder.set_pubkey(key).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        if ca {
+            builder.append_extension(BasicConstraints::new().critical().ca().build().unwrap()).unwrap();
+        } else {
+            let names = SubjectAlternativeName::new().dns(name).build(&builder.x509v3_context(issuer.map(|(issuer, _)| &**issuer), None)).unwrap();
+            builder.append_extension(names).unwrap();
+        }
+        builder.sign(issuer.map_or(key, |(_, key)| key), MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    /// PEM certificate and key of a new CA
+    pub fn ca() -> (Vec<u8>, Vec<u8>) {
+        let key = key();
+        let certificate = certificate("node-controller test CA", &key, None, true);
+        (certificate.to_pem().unwrap(), key.private_key_to_pem_pkcs8().unwrap())
+    }
+
+    /// PEM certificate and key for `node_id`, issued by the CA
+    pub fn node(node_id: &str, ca: &(Vec<u8>, Vec<u8>)) -> (Vec<u8>, Vec<u8>) {
+        let ca_certificate = X509::from_pem(&ca.0).unwrap();
+        let ca_key = PKey::private_key_from_pem(&ca.1).unwrap();
+        let key = key();
+        let certificate = certificate(node_id, &key, Some((&ca_certificate, &ca_key)), false);
+        (certificate.to_pem().unwrap(), key.private_key_to_pem_pkcs8().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certificate_must_name_node() {
+        let ca = test_certs::ca();
+        let (cert, key) = test_certs::node("node-a", &ca);
+        assert_eq!(certificate_names(&cert).unwrap(), ["node-a"]);
+        assert!(GrpcTls::from_pem("node-a", &cert, &key, &ca.0).is_ok());
+        assert!(GrpcTls::from_pem("node-b", &cert, &key, &ca.0).is_err());
+        assert!(GrpcTls::from_pem("node-a", &cert, &key, b"").is_err());
+        let (_, other_key) = test_certs::node("node-a", &ca);
+        assert!(GrpcTls::from_pem("node-a", &cert, &other_key, &ca.0).is_err());
+    }
+}
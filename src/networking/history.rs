@@ -0,0 +1,245 @@
+// src/networking/history.rs
+//
+// Transfer history
+// Every completed or failed transfer is recorded with its peer, size,
+// duration, throughput and hash, appended to a JSON lines file so the record
+// outlives restarts. Per-peer statistics over the records make throughput
+// regressions between specific node pairs visible; both are served by the
+// GetTransferHistory RPC.
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::file_transfer::TransferDirection;
+
+/// Records kept in memory and read back on start
+pub const HISTORY_LIMIT: usize = 1000;
+
+/// One finished transfer of a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub file_id: String,
+    pub file_name: String,
+    pub direction: TransferDirection,
+    /// Node ID of the other side, or its address when it has none
+    pub peer: String,
+    /// File size in bytes
+    pub size: u64,
+    pub duration_secs: f64,
+    /// MiB per second
+    pub throughput_mbps: f64,
+    /// SHA256 of the file when received, of its range digests when sent;
+    /// empty when the transfer failed
+    pub hash: String,
+    /// Why the transfer failed; None when it succeeded
+    pub error: Option<String>,
+    /// Unix time in seconds the transfer finished
+    pub finished_at: u64,
+}
+
+impl TransferRecord {
+    /// A record of a transfer finishing now after `duration`
+    pub fn new(
+        file_id: &str,
+        file_name: &str,
+        direction: TransferDirection,
+        peer: &str,
+        size: u64,
+        duration: Duration,
+        outcome: Result<String, String>,
+    ) -> Self {
+        let duration_secs = duration.as_secs_f64();
+        let (hash, error) = match outcome {
+            Ok(hash) => (hash, None),
+            Err(error) => (String::new(), Some(error)),
+        };
+        Self {
+            file_id: file_id.to_string(),
+            file_name: file_name.to_string(),
+            direction,
+            peer: peer.to_string(),
+            size,
+            duration_secs,
+            throughput_mbps: if duration_secs > 0.0 && error.is_none() {
+                size as f64 / duration_secs / (1024.0 * 1024.0)
+            } else {
+                0.0
+            },
+            hash,
+            error,
+            finished_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        }
+    }
+}
+
+/// Transfers with one peer in one direction, summarized
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerStats {
+    pub peer: String,
+    pub direction: TransferDirection,
+    pub transfers: u64,
+    pub failures: u64,
+    /// Bytes of the successful transfers
+    pub bytes: u64,
+    /// Throughput over all successful transfers, in MiB per second
+    pub mean_throughput_mbps: f64,
+    /// Throughput of the latest successful transfer, in MiB per second
+    pub last_throughput_mbps: f64,
+}
+
+/// Recent transfers, persisted to a file when there is one
+#[derive(Clone, Default)]
+pub struct TransferHistory {
+    path: Option<PathBuf>,
+    records: Arc<StdMutex<VecDeque<TransferRecord>>>,
+}
+
+impl TransferHistory {
+    /// Open the history stored at `path`, or keep it in memory only when None
+    pub fn open(path: Option<&Path>) -> Self {
+        let mut records = VecDeque::new();
+        if let Some(path) = path {
+            if let Ok(data) = fs::read_to_string(path) {
+                for line in data.lines().filter(|line| !line.trim().is_empty()) {
+                    match serde_json::from_str(line) {
+                        Ok(record) => records.push_back(record),
+                        Err(e) => warn!("Skipping unreadable transfer record in {}: {}", path.display(), e),
+                    }
+                }
+                let excess = records.len().saturating_sub(HISTORY_LIMIT);
+                records.drain(..excess);
+                // Compact the file once it holds much more than is kept
+                if excess > HISTORY_LIMIT {
+                    Self::rewrite(path, &records);
+                }
+            }
+        }
+        Self { path: path.map(Path::to_path_buf), records: Arc::new(StdMutex::new(records)) }
+    }
+
+    /// Add a finished transfer
+    pub fn record(&self, record: TransferRecord) {
+        if let Some(path) = &self.path {
+            let result = serde_json::to_string(&record)
+                .map_err(std::io::Error::from)
+                .and_then(|line| {
+                    OpenOptions::new().create(true).append(true).open(path)?.write_all(format!("{}\n", line).as_bytes())
+                });
+            if let Err(e) = result {
+                warn!("Failed to save transfer record to {}: {}", path.display(), e);
+            }
+        }
+        let mut records = self.records.lock().unwrap();
+        records.push_back(record);
+        if records.len() > HISTORY_LIMIT {
+            records.pop_front();
+        }
+    }
+
+    /// The latest `limit` records with `peer` (any peer when empty), oldest first
+    pub fn records(&self, peer: &str, limit: usize) -> Vec<TransferRecord> {
+        let records = self.records.lock().unwrap();
+        let matching: Vec<_> = records.iter().filter(|record| peer.is_empty() || record.peer == peer).collect();
+        matching[matching.len().saturating_sub(limit)..].iter().map(|record| (*record).clone()).collect()
+    }
+
+    /// Statistics for every peer and direction in the history
+    pub fn stats(&self) -> Vec<PeerStats> {
+        let records = self.records.lock().unwrap();
+        let mut stats: BTreeMap<(String, bool), (PeerStats, f64)> = BTreeMap::new();
+        for record in records.iter() {
+            let key = (record.peer.clone(), record.direction == TransferDirection::Send);
+            let (peer, seconds) = stats.entry(key).or_insert_with(|| (
+                PeerStats {
+                    peer: record.peer.clone(),
+                    direction: record.direction,
+                    transfers: 0,
+                    failures: 0,
+                    bytes: 0,
+                    mean_throughput_mbps: 0.0,
+                    last_throughput_mbps: 0.0,
+                },
+                0.0,
+            ));
+            peer.transfers += 1;
+            if record.error.is_some() {
+                peer.failures += 1;
+                continue;
+            }
+            peer.bytes += record.size;
+            *seconds += record.duration_secs;
+            peer.last_throughput_mbps = record.throughput_mbps;
+        }
+        stats.into_values()
+            .map(|(mut peer, seconds)| {
+                if seconds > 0.0 {
+                    peer.mean_throughput_mbps = peer.bytes as f64 / seconds / (1024.0 * 1024.0);
+                }
+                peer
+            })
+            .collect()
+    }
+
+    fn rewrite(path: &Path, records: &VecDeque<TransferRecord>) {
+        let data: String = records.iter()
+            .filter_map(|record| serde_json::to_string(record).ok())
+            .map(|line| line + "\n")
+            .collect();
+        if let Err(e) = fs::write(path, data) {
+            warn!("Failed to compact transfer history {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(peer: &str, direction: TransferDirection, secs: u64, outcome: Result<String, String>) -> TransferRecord {
+        TransferRecord::new("id", "model.bin", direction, peer, 100 * 1024 * 1024, Duration::from_secs(secs), outcome)
+    }
+
+    #[test]
+    fn test_history_and_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let history = TransferHistory::open(Some(&path));
+        history.record(transfer("node-b", TransferDirection::Send, 1, Ok("h1".to_string())));
+        history.record(transfer("node-b", TransferDirection::Send, 4, Ok("h2".to_string())));
+        history.record(transfer("node-b", TransferDirection::Send, 2, Err("refused".to_string())));
+        history.record(transfer("node-c", TransferDirection::Receive, 10, Ok("h3".to_string())));
+
+        // Read back after a restart
+        let history = TransferHistory::open(Some(&path));
+        assert_eq!(history.records("", 10).len(), 4);
+        let latest = history.records("node-b", 2);
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].hash, "h2");
+        assert_eq!(latest[1].error.as_deref(), Some("refused"));
+
+        let stats = history.stats();
+        assert_eq!(stats.len(), 2);
+        let sent = stats.iter().find(|stats| stats.peer == "node-b").unwrap();
+        assert_eq!((sent.transfers, sent.failures, sent.bytes), (3, 1, 200 * 1024 * 1024));
+        assert_eq!(sent.mean_throughput_mbps, 40.0);
+        assert_eq!(sent.last_throughput_mbps, 25.0);
+        let received = stats.iter().find(|stats| stats.peer == "node-c").unwrap();
+        assert_eq!(received.direction, TransferDirection::Receive);
+        assert_eq!(received.mean_throughput_mbps, 10.0);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let history = TransferHistory::open(None);
+        for _ in 0..HISTORY_LIMIT + 5 {
+            history.record(transfer("node-b", TransferDirection::Send, 1, Ok(String::new())));
+        }
+        assert_eq!(history.records("", usize::MAX).len(), HISTORY_LIMIT);
+    }
+}
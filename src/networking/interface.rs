@@ -3,7 +3,12 @@ use if_addrs::{IfAddr, Interface, get_if_addrs};
 use local_ip_address::{list_afinet_netifas, local_ip};
 use log::{debug, info, warn, error};
 use std::collections::HashMap;
-use std::net::IpAddr;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use tokio::net::TcpSocket;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InterfaceType {
@@ -14,12 +19,44 @@ pub enum InterfaceType {
     Other,
 }
 
+impl InterfaceType {
+    /// Rank of a path over this interface between two nodes, lower is
+    /// better: wired LAN first, then Thunderbolt, then Wi-Fi
+    pub fn path_rank(&self) -> u8 {
+        match self {
+            InterfaceType::Ethernet => 0,
+            InterfaceType::Thunderbolt => 1,
+            InterfaceType::Wifi => 2,
+            InterfaceType::Other => 3,
+            InterfaceType::Loopback => 4,
+        }
+    }
+}
+
+impl FromStr for InterfaceType {
+    type Err = anyhow::Error;
+
+    /// Parse the name a node advertises an interface type by
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "Thunderbolt" => Ok(InterfaceType::Thunderbolt),
+            "Ethernet" => Ok(InterfaceType::Ethernet),
+            "Wifi" => Ok(InterfaceType::Wifi),
+            "Loopback" => Ok(InterfaceType::Loopback),
+            "Other" => Ok(InterfaceType::Other),
+            other => Err(anyhow!("Unknown interface type '{}'", other)),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct NetworkInterface {
     pub name: String,
     pub ip: IpAddr,
     pub interface_type: InterfaceType,
     pub priority: u8, // Higher number = higher priority
+    /// Netmask of the interface's subnet, when known
+    pub netmask: Option<IpAddr>,
 }
 
 impl NetworkInterface {
@@ -38,16 +75,41 @@ impl NetworkInterface {
             ip,
             interface_type,
             priority,
+            netmask: None,
+        }
+    }
+
+    pub fn with_netmask(mut self, netmask: IpAddr) -> Self {
+        self.netmask = Some(netmask);
+        self
+    }
+
+    /// Whether `ip` is on this interface's subnet
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.ip, self.netmask, ip) {
+            (IpAddr::V4(own), Some(IpAddr::V4(mask)), IpAddr::V4(ip)) => {
+                u32::from(own) & u32::from(mask) == u32::from(*ip) & u32::from(mask)
+            }
+            (IpAddr::V6(own), Some(IpAddr::V6(mask)), IpAddr::V6(ip)) => {
+                u128::from(own) & u128::from(mask) == u128::from(*ip) & u128::from(mask)
+            }
+            _ => false,
         }
     }
 
-    /// Determines if this is likely a Thunderbolt interface
+    /// Directed broadcast address of the interface's IPv4 subnet
+    pub fn broadcast(&self) -> Option<Ipv4Addr> {
+        match (self.ip, self.netmask) {
+            (IpAddr::V4(own), Some(IpAddr::V4(mask))) => Some(Ipv4Addr::from(u32::from(own) | !u32::from(mask))),
+            _ => None,
+        }
+    }
+
+    /// Determines if this is a Thunderbolt interface: one macOS lists as a
+    /// Thunderbolt hardware port (the Thunderbolt Bridge and its member
+    /// ports), or a Linux thunderbolt-net interface
     fn is_thunderbolt(name: &str) -> bool {
-        // Common patterns for Thunderbolt interfaces
-        name.contains("thunderbolt") || 
-        name.contains("tb") || 
-        name.contains("bridge") ||
-        name.starts_with("en") && (name.contains("5") || name.contains("6"))
+        name.starts_with("thunderbolt") || thunderbolt_ports().iter().any(|port| port == name)
     }
 
     /// Determines if this is likely an Ethernet interface
@@ -90,17 +152,129 @@ impl NetworkInterface {
     }
 }
 
+/// Devices of the Thunderbolt hardware ports macOS lists, looked up once
+fn thunderbolt_ports() -> &'static [String] {
+    static PORTS: OnceLock<Vec<String>> = OnceLock::new();
+    PORTS.get_or_init(|| {
+        if !cfg!(target_os = "macos") {
+            return Vec::new();
+        }
+        match Command::new("networksetup").arg("-listallhardwareports").output() {
+            Ok(output) if output.status.success() => parse_thunderbolt_ports(&String::from_utf8_lossy(&output.stdout)),
+            _ => {
+                debug!("Hardware ports unavailable; Thunderbolt interfaces aren't recognized");
+                Vec::new()
+            }
+        }
+    })
+}
+
+/// Devices of the Thunderbolt ports in `networksetup -listallhardwareports` output
+fn parse_thunderbolt_ports(output: &str) -> Vec<String> {
+    let mut ports = Vec::new();
+    let mut thunderbolt = false;
+    for line in output.lines() {
+        if let Some(port) = line.strip_prefix("Hardware Port:") {
+            thunderbolt = port.trim().starts_with("Thunderbolt");
+        } else if let Some(device) = line.strip_prefix("Device:").filter(|_| thunderbolt) {
+            ports.push(device.trim().to_string());
+        }
+    }
+    ports
+}
+
+/// Hardware addresses of the interfaces that have one, by interface name,
+/// formatted as `a4:83:e7:12:34:56`
+pub fn mac_addresses() -> HashMap<String, String> {
+    let mut macs = HashMap::new();
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs fills `addrs` with a list we walk read-only and free once
+    unsafe {
+        if libc::getifaddrs(&mut addrs) != 0 {
+            debug!("Failed to list hardware addresses: {}", io::Error::last_os_error());
+            return macs;
+        }
+        let mut cursor = addrs;
+        while let Some(entry) = cursor.as_ref() {
+            cursor = entry.ifa_next;
+            if entry.ifa_addr.is_null() {
+                continue;
+            }
+            if let Some(mac) = link_address(entry.ifa_addr).filter(|mac| *mac != [0; 6]) {
+                let name = std::ffi::CStr::from_ptr(entry.ifa_name).to_string_lossy().to_string();
+                macs.insert(name, format_mac(&mac));
+            }
+        }
+        libc::freeifaddrs(addrs);
+    }
+    macs
+}
+
+/// The Ethernet address of a link-layer `addr` from getifaddrs
+#[cfg(target_os = "linux")]
+unsafe fn link_address(addr: *const libc::sockaddr) -> Option<[u8; 6]> {
+    if i32::from((*addr).sa_family) != libc::AF_PACKET {
+        return None;
+    }
+    let link = &*(addr as *const libc::sockaddr_ll);
+    if link.sll_halen != 6 {
+        return None;
+    }
+    link.sll_addr[..6].try_into().ok()
+}
+
+/// The Ethernet address of a link-layer `addr` from getifaddrs
+#[cfg(target_os = "macos")]
+unsafe fn link_address(addr: *const libc::sockaddr) -> Option<[u8; 6]> {
+    if i32::from((*addr).sa_family) != libc::AF_LINK {
+        return None;
+    }
+    let link = &*(addr as *const libc::sockaddr_dl);
+    if link.sdl_alen != 6 {
+        return None;
+    }
+    // The address follows the interface name, which may run past sdl_data
+    let data = std::ptr::addr_of!(link.sdl_data) as *const u8;
+    std::slice::from_raw_parts(data.add(link.sdl_nlen as usize), 6).try_into().ok()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+unsafe fn link_address(_addr: *const libc::sockaddr) -> Option<[u8; 6]> {
+    None
+}
+
+/// `mac` as six colon-separated hex bytes
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":")
+}
+
 /// Discover all network interfaces on the system
 pub fn discover_interfaces() -> Result<Vec<NetworkInterface>> {
+    let interfaces = list_interfaces()?;
+    
+    for (idx, interface) in interfaces.iter().enumerate() {
+        info!("Interface #{}: {} ({:?}) - {}", 
+              idx + 1, interface.name, interface.interface_type, interface.ip);
+    }
+    
+    if interfaces.is_empty() {
+        warn!("No usable network interfaces found!");
+    }
+    
+    Ok(interfaces)
+}
+
+/// The network interfaces of the system, by priority, without logging them
+pub fn list_interfaces() -> Result<Vec<NetworkInterface>> {
     let mut interfaces = Vec::new();
     
     // Get all network interfaces
     match get_if_addrs() {
         Ok(if_addrs) => {
             for interface in if_addrs {
-                let ip = match interface.addr {
-                    IfAddr::V4(addr) => IpAddr::V4(addr.ip),
-                    IfAddr::V6(addr) => IpAddr::V6(addr.ip),
+                let (ip, netmask) = match interface.addr {
+                    IfAddr::V4(addr) => (IpAddr::V4(addr.ip), IpAddr::V4(addr.netmask)),
+                    IfAddr::V6(addr) => (IpAddr::V6(addr.ip), IpAddr::V6(addr.netmask)),
                 };
                 
                 // Skip interfaces without a valid IP
@@ -117,7 +291,7 @@ pub fn discover_interfaces() -> Result<Vec<NetworkInterface>> {
                     interface.name.clone(),
                     ip,
                     interface_type,
-                ));
+                ).with_netmask(netmask));
             }
         },
         Err(err) => {
@@ -129,24 +303,30 @@ pub fn discover_interfaces() -> Result<Vec<NetworkInterface>> {
     // Sort interfaces by priority (highest first)
     interfaces.sort_by(|a, b| b.priority.cmp(&a.priority));
     
-    for (idx, interface) in interfaces.iter().enumerate() {
-        info!("Interface #{}: {} ({:?}) - {}", 
-              idx + 1, interface.name, interface.interface_type, interface.ip);
-    }
-    
-    if interfaces.is_empty() {
-        warn!("No usable network interfaces found!");
-    }
-    
     Ok(interfaces)
 }
 
 /// Get the best interface for node-to-node communication
 pub fn get_best_interface() -> Result<NetworkInterface> {
-    let interfaces = discover_interfaces()?;
-    
-    // Get the highest priority non-loopback interface
-    for interface in &interfaces {
+    best_interface(&discover_interfaces()?)
+}
+
+/// The best of `interfaces`, as sorted by `discover_interfaces`. Thunderbolt
+/// links only reach the Macs plugged into this one, so discovery and control
+/// stay on the LAN and only fall back to Thunderbolt without one; transfers
+/// use Thunderbolt where it reaches the peer (`thunderbolt_links`).
+pub fn best_interface(interfaces: &[NetworkInterface]) -> Result<NetworkInterface> {
+    let lan_first = || interfaces.iter()
+        .filter(|interface| interface.interface_type != InterfaceType::Thunderbolt)
+        .chain(interfaces.iter().filter(|interface| interface.interface_type == InterfaceType::Thunderbolt));
+    // Get the highest priority non-loopback interface, IPv4 first since every
+    // peer can reach it
+    for interface in lan_first().filter(|interface| interface.ip.is_ipv4()) {
+        if interface.interface_type != InterfaceType::Loopback {
+            return Ok(interface.clone());
+        }
+    }
+    for interface in lan_first().filter(|interface| is_routable(&interface.ip)) {
         if interface.interface_type != InterfaceType::Loopback {
             return Ok(interface.clone());
         }
@@ -164,4 +344,149 @@ pub fn get_local_ip() -> Result<IpAddr> {
             Err(anyhow!("Failed to determine local IP: {}", err))
         }
     }
-} 
\ No newline at end of file
+}
+
+/// Whether peers can connect to `ip`: not loopback, and not a link-local IPv6
+/// address, which needs an interface scope to be used
+pub fn is_routable(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !ip.is_loopback() && !ip.is_unspecified(),
+        IpAddr::V6(ip) => !ip.is_loopback() && !ip.is_unspecified() && (ip.segments()[0] & 0xffc0) != 0xfe80,
+    }
+}
+
+/// Direct Thunderbolt links to a peer advertising `peer_addresses`: pairs of
+/// the address of one of our Thunderbolt `interfaces` and the peer's address
+/// on the same subnet, best interface first
+pub fn thunderbolt_links(interfaces: &[NetworkInterface], peer_addresses: &[IpAddr]) -> Vec<(IpAddr, IpAddr)> {
+    interfaces.iter()
+        .filter(|interface| interface.interface_type == InterfaceType::Thunderbolt)
+        .flat_map(|interface| peer_addresses.iter()
+            .filter(|peer| **peer != interface.ip && interface.contains(peer))
+            .map(|peer| (interface.ip, *peer)))
+        .collect()
+}
+
+/// Direct Thunderbolt links from this host to a peer advertising `peer_addresses`
+pub fn links_to(peer_addresses: &[IpAddr]) -> Vec<(IpAddr, IpAddr)> {
+    match list_interfaces() {
+        Ok(interfaces) => thunderbolt_links(&interfaces, peer_addresses),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Interfaces whose addresses this node advertises, one per address: `best`
+/// first, then the other routable addresses of `interfaces` of both families
+pub fn advertised_interfaces<'a>(best: &'a NetworkInterface, interfaces: &'a [NetworkInterface]) -> Vec<&'a NetworkInterface> {
+    let mut advertised = vec![best];
+    for interface in interfaces {
+        if is_routable(&interface.ip) && !advertised.iter().any(|a| a.ip == interface.ip) {
+            advertised.push(interface);
+        }
+    }
+    advertised
+}
+
+/// Bind a TCP socket to `addr` for listening. The IPv6 unspecified address
+/// takes connections of both families, IPv4 ones as IPv4-mapped addresses;
+/// hosts without IPv6 fall back to every IPv4 address.
+pub fn bind_tcp(addr: SocketAddr) -> io::Result<TcpSocket> {
+    if addr.ip() != IpAddr::V6(Ipv6Addr::UNSPECIFIED) {
+        let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+        socket.set_reuseaddr(true)?;
+        socket.bind(addr)?;
+        return Ok(socket);
+    }
+
+    let dual_stack = TcpSocket::new_v6().and_then(|socket| {
+        set_v6_only(&socket, false)?;
+        socket.set_reuseaddr(true)?;
+        socket.bind(addr)?;
+        Ok(socket)
+    });
+    match dual_stack {
+        Ok(socket) => Ok(socket),
+        Err(e) => {
+            debug!("IPv6 listening on port {} unavailable ({}), using IPv4 only", addr.port(), e);
+            bind_tcp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, addr.port())))
+        }
+    }
+}
+
+fn set_v6_only(socket: &TcpSocket, v6_only: bool) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let value = libc::c_int::from(v6_only);
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_V6ONLY,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routable_addresses() {
+        let interface = |ip: &str| NetworkInterface::new("en0".to_string(), ip.parse().unwrap(), InterfaceType::Ethernet);
+        let interfaces = [interface("192.168.1.5"), interface("fe80::1"), interface("2001:db8::5"), interface("::1")];
+        assert_eq!(best_interface(&interfaces).unwrap().ip.to_string(), "192.168.1.5");
+        assert_eq!(best_interface(&interfaces[1..]).unwrap().ip.to_string(), "2001:db8::5");
+        assert_eq!(
+            advertised_interfaces(&interfaces[0], &interfaces).iter().map(|interface| interface.ip).collect::<Vec<_>>(),
+            ["192.168.1.5".parse::<IpAddr>().unwrap(), "2001:db8::5".parse().unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_thunderbolt_links() {
+        let ports = "Hardware Port: Ethernet\nDevice: en0\nEthernet Address: 00:00:00:00:00:01\n\n\
+            Hardware Port: Thunderbolt Bridge\nDevice: bridge0\nEthernet Address: N/A\n\n\
+            Hardware Port: Thunderbolt 1\nDevice: en1\nEthernet Address: 00:00:00:00:00:02\n\n\
+            Hardware Port: Wi-Fi\nDevice: en2\nEthernet Address: 00:00:00:00:00:03\n";
+        assert_eq!(parse_thunderbolt_ports(ports), ["bridge0", "en1"]);
+
+        let interface = |name: &str, ip: &str, mask: &str, interface_type| {
+            NetworkInterface::new(name.to_string(), ip.parse().unwrap(), interface_type).with_netmask(mask.parse().unwrap())
+        };
+        let interfaces = [
+            interface("bridge0", "169.254.10.1", "255.255.0.0", InterfaceType::Thunderbolt),
+            interface("en0", "192.168.1.5", "255.255.255.0", InterfaceType::Ethernet),
+        ];
+        // Discovery and control stay on the LAN
+        assert_eq!(best_interface(&interfaces).unwrap().name, "en0");
+        assert_eq!(best_interface(&interfaces[..1]).unwrap().name, "bridge0");
+
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        assert!(interfaces[0].contains(&ip("169.254.200.7")));
+        assert!(!interfaces[0].contains(&ip("169.253.200.7")));
+        assert!(!interfaces[0].contains(&ip("2001:db8::7")));
+        let peer = [ip("192.168.1.7"), ip("169.254.200.7")];
+        assert_eq!(thunderbolt_links(&interfaces, &peer), [(ip("169.254.10.1"), ip("169.254.200.7"))]);
+        assert!(thunderbolt_links(&interfaces, &peer[..1]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dual_stack_listener() {
+        let listener = bind_tcp(SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))).unwrap().listen(16).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let v4 = tokio::net::TcpStream::connect((Ipv4Addr::LOCALHOST, port)).await.unwrap();
+        let (_, peer) = listener.accept().await.unwrap();
+        assert_eq!(peer.ip().to_canonical(), v4.local_addr().unwrap().ip());
+        // Only where the host has IPv6 loopback
+        if let Ok(v6) = tokio::net::TcpStream::connect((Ipv6Addr::LOCALHOST, port)).await {
+            let (_, peer) = listener.accept().await.unwrap();
+            assert_eq!(peer.ip(), v6.local_addr().unwrap().ip());
+        }
+    }
+}
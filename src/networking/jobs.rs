@@ -0,0 +1,530 @@
+// src/networking/jobs.rs
+//
+// Distributed jobs
+// A scheduler (any node, driven by the monitoring API or a caller of the
+// library) picks the discovered nodes a job targets by capability or ID and
+// runs it on all of them at once: shell commands and benchmarks through the
+// RunJob RPC, file distribution by broadcasting the file to them from one
+// read of each chunk. Every node's outcome is recorded as it arrives, and the
+// job's state aggregates them.
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use super::broadcast::DEFAULT_FAN_OUT;
+use super::communication::NodeClient;
+use super::discovery::NodeInfo;
+use super::file_transfer::FileTransferManager;
+
+/// Time a shell job may run unless it sets its own limit
+const DEFAULT_SHELL_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Output kept of a shell job
+const MAX_OUTPUT_LEN: usize = 64 * 1024;
+
+/// Data a benchmark hashes and writes unless it asks for a different size
+const DEFAULT_BENCHMARK_MB: u64 = 256;
+const MAX_BENCHMARK_MB: u64 = 4096;
+
+/// Jobs the scheduler keeps reports of
+const JOB_HISTORY: usize = 100;
+
+/// What a job does on each node it targets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum JobSpec {
+    /// Run `command` with `sh -c` and return its output
+    Shell {
+        command: String,
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+    /// Send a file or directory of the scheduling node to every target
+    Distribute { path: PathBuf },
+    /// Measure SHA256 and disk write throughput over `size_mb` MiB
+    Benchmark {
+        #[serde(default)]
+        size_mb: Option<u64>,
+    },
+}
+
+/// The nodes a job runs on: those with every capability and label listed and,
+/// if any nodes are listed, one of them (by ID or name). A capability matches
+/// by name (`gpu_cores`), or exactly when it has a value (`grpc:54321`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobTarget {
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl JobTarget {
+    pub fn matches(&self, node: &NodeInfo) -> bool {
+        self.capabilities.iter().all(|wanted| match wanted.contains(':') {
+            true => node.capabilities.contains(wanted),
+            false => node.capability(wanted).is_some(),
+        })
+            && self.labels.iter().all(|(key, value)| node.labels.get(key) == Some(value))
+            && (self.nodes.is_empty() || self.nodes.iter().any(|n| *n == node.id || *n == node.name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// Outcome of a job on one node
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeJobResult {
+    pub node_id: String,
+    pub node_name: String,
+    pub state: JobState,
+    pub output: String,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// A job and what became of it on each node
+#[derive(Debug, Clone, Serialize)]
+pub struct JobReport {
+    pub id: String,
+    pub spec: JobSpec,
+    pub target: JobTarget,
+    /// Running until every node finished, then succeeded if all of them did
+    pub state: JobState,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub nodes: Vec<NodeJobResult>,
+}
+
+impl JobReport {
+    fn aggregate(&mut self) {
+        let count = |state| self.nodes.iter().filter(|node| node.state == state).count();
+        self.succeeded = count(JobState::Succeeded);
+        self.failed = count(JobState::Failed);
+        self.state = if self.succeeded + self.failed < self.nodes.len() {
+            JobState::Running
+        } else if self.failed == 0 {
+            JobState::Succeeded
+        } else {
+            JobState::Failed
+        };
+    }
+}
+
+/// Runs jobs other nodes send this node over RunJob
+#[derive(Debug, Clone, Default)]
+pub struct JobRunner {
+    allow_shell: bool,
+    /// Nodes whose shell jobs RunJob accepts, once they proved their key
+    shell_senders: Vec<String>,
+}
+
+impl JobRunner {
+    /// A runner of benchmarks, and of shell commands if `allow_shell`
+    pub fn new(allow_shell: bool) -> Self {
+        Self { allow_shell, shell_senders: Vec::new() }
+    }
+
+    /// Accept shell jobs over RunJob from the nodes with these IDs only
+    pub fn with_shell_senders(mut self, senders: Vec<String>) -> Self {
+        self.shell_senders = senders;
+        self
+    }
+
+    pub fn runs_shell(&self) -> bool {
+        self.allow_shell
+    }
+
+    /// Whether node `node_id` may send this node shell jobs
+    pub fn allows_shell_from(&self, node_id: &str) -> bool {
+        self.allow_shell && self.shell_senders.iter().any(|sender| sender == node_id)
+    }
+
+    /// Run `spec`, returning its output
+    pub async fn run(&self, spec: &JobSpec) -> Result<String> {
+        match spec {
+            JobSpec::Shell { command, timeout_secs } => {
+                if !self.allow_shell {
+                    return Err(anyhow!("Shell jobs are disabled on this node"));
+                }
+                let limit = timeout_secs.map(Duration::from_secs).unwrap_or(DEFAULT_SHELL_TIMEOUT);
+                run_shell(command, limit).await
+            }
+            JobSpec::Benchmark { size_mb } => {
+                let size_mb = size_mb.unwrap_or(DEFAULT_BENCHMARK_MB).clamp(1, MAX_BENCHMARK_MB);
+                tokio::task::spawn_blocking(move || benchmark(size_mb)).await?
+            }
+            JobSpec::Distribute { .. } => Err(anyhow!("File distribution is sent by the scheduler, not run")),
+        }
+    }
+}
+
+async fn run_shell(command: &str, limit: Duration) -> Result<String> {
+    let child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(limit, child).await
+        .map_err(|_| anyhow!("Timed out after {}s", limit.as_secs()))?
+        .context("Failed to start the command")?;
+
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    if text.len() > MAX_OUTPUT_LEN {
+        let mut end = MAX_OUTPUT_LEN;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    if output.status.success() {
+        Ok(text)
+    } else {
+        Err(anyhow!("Command {}: {}", output.status, text.trim_end()))
+    }
+}
+
+/// SHA256 and disk write throughput in MiB/s over `size_mb` MiB
+fn benchmark(size_mb: u64) -> Result<String> {
+    let block = vec![0x5au8; 1024 * 1024];
+
+    let started = Instant::now();
+    let mut hasher = Sha256::new();
+    for _ in 0..size_mb {
+        hasher.update(&block);
+    }
+    hasher.finalize();
+    let hash_secs = started.elapsed().as_secs_f64();
+
+    let mut file = tempfile::tempfile().context("Failed to create the benchmark file")?;
+    let started = Instant::now();
+    for _ in 0..size_mb {
+        file.write_all(&block)?;
+    }
+    file.sync_all()?;
+    let write_secs = started.elapsed().as_secs_f64();
+
+    let throughput = |secs: f64| size_mb as f64 / secs.max(f64::EPSILON);
+    Ok(serde_json::json!({
+        "size_mb": size_mb,
+        "sha256_mb_per_sec": throughput(hash_secs),
+        "disk_write_mb_per_sec": throughput(write_secs),
+    }).to_string())
+}
+
+/// Sends jobs to the nodes they target and tracks their outcome
+#[derive(Clone)]
+pub struct JobScheduler {
+    local_node: NodeInfo,
+    client: Arc<NodeClient>,
+    /// Sends distribution jobs; they are refused without one
+    transfers: Option<Arc<FileTransferManager>>,
+    /// Most recent jobs, oldest first
+    jobs: Arc<StdMutex<VecDeque<JobReport>>>,
+}
+
+impl JobScheduler {
+    pub fn new(local_node: NodeInfo, client: Arc<NodeClient>) -> Self {
+        Self {
+            local_node,
+            client,
+            transfers: None,
+            jobs: Arc::default(),
+        }
+    }
+
+    /// Send distribution jobs with `transfers`
+    pub fn with_transfers(mut self, transfers: Arc<FileTransferManager>) -> Self {
+        self.transfers = Some(transfers);
+        self
+    }
+
+    /// Start `spec` on the nodes of `nodes` that `target` matches, returning
+    /// the job ID to follow it with `report`
+    pub fn submit(&self, spec: JobSpec, target: JobTarget, nodes: &[NodeInfo]) -> Result<String> {
+        let (id, targets) = self.prepare(spec.clone(), target, nodes)?;
+        let scheduler = self.clone();
+        let job_id = id.clone();
+        tokio::spawn(async move { scheduler.dispatch(&job_id, spec, targets).await });
+        Ok(id)
+    }
+
+    /// Run `spec` on the nodes of `nodes` that `target` matches and wait for
+    /// all of them
+    pub async fn run(&self, spec: JobSpec, target: JobTarget, nodes: &[NodeInfo]) -> Result<JobReport> {
+        let (id, targets) = self.prepare(spec.clone(), target, nodes)?;
+        self.dispatch(&id, spec, targets).await;
+        self.report(&id).ok_or_else(|| anyhow!("Job {} was dropped from the history", id))
+    }
+
+    /// The current state of job `id`
+    pub fn report(&self, id: &str) -> Option<JobReport> {
+        self.jobs.lock().unwrap().iter().find(|job| job.id == id).cloned()
+    }
+
+    /// Reports of the most recent jobs, oldest first
+    pub fn reports(&self) -> Vec<JobReport> {
+        self.jobs.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn prepare(&self, spec: JobSpec, target: JobTarget, nodes: &[NodeInfo]) -> Result<(String, Vec<NodeInfo>)> {
+        match &spec {
+            JobSpec::Shell { command, .. } if command.trim().is_empty() => return Err(anyhow!("Shell job without a command")),
+            JobSpec::Distribute { path } => {
+                if self.transfers.is_none() {
+                    return Err(anyhow!("This node has no file transfer manager to distribute files with"));
+                }
+                if !path.exists() {
+                    return Err(anyhow!("{} does not exist", path.display()));
+                }
+            }
+            _ => {}
+        }
+        let targets: Vec<NodeInfo> = nodes.iter()
+            .filter(|node| node.id != self.local_node.id && target.matches(node))
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            return Err(anyhow!("No discovered node matches the job's target"));
+        }
+
+        let id = Uuid::new_v4().to_string();
+        info!("Job {}: {:?} on {} nodes", id, spec, targets.len());
+        let mut report = JobReport {
+            id: id.clone(),
+            spec,
+            target,
+            state: JobState::Pending,
+            succeeded: 0,
+            failed: 0,
+            nodes: targets.iter().map(|node| NodeJobResult {
+                node_id: node.id.clone(),
+                node_name: node.name.clone(),
+                state: JobState::Pending,
+                output: String::new(),
+                error: None,
+                duration_ms: 0,
+            }).collect(),
+        };
+        report.aggregate();
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.len() >= JOB_HISTORY {
+            jobs.pop_front();
+        }
+        jobs.push_back(report);
+        Ok((id, targets))
+    }
+
+    async fn dispatch(&self, id: &str, spec: JobSpec, targets: Vec<NodeInfo>) {
+        for node in &targets {
+            self.record(id, &node.id, |result| result.state = JobState::Running);
+        }
+        if let JobSpec::Distribute { path } = &spec {
+            self.distribute(id, path, &targets).await;
+            return;
+        }
+        let runs: Vec<_> = targets.iter().map(|node| self.run_on(id, &spec, node)).collect();
+        futures_util::future::join_all(runs).await;
+    }
+
+    /// Broadcast `path` to the targets of job `id`, a few nodes at a time from
+    /// the same reads, and record each node's outcome
+    async fn distribute(&self, id: &str, path: &Path, targets: &[NodeInfo]) {
+        let Some(transfers) = &self.transfers else { return };
+        let started = Instant::now();
+        let report = transfers.broadcast_to_nodes(&self.client, targets, &self.local_node, path, DEFAULT_FAN_OUT).await;
+        let duration_ms = started.elapsed().as_millis() as u64;
+        for (node_id, transfer_id) in report.delivered {
+            self.finish(id, &node_id, Ok(format!("Transfer {}", transfer_id)), duration_ms);
+        }
+        for (node_id, error) in report.failed {
+            self.finish(id, &node_id, Err(error), duration_ms);
+        }
+    }
+
+    /// Run job `id` on `node` and record the outcome
+    async fn run_on(&self, id: &str, spec: &JobSpec, node: &NodeInfo) {
+        let started = Instant::now();
+        match self.client.run_job(node, &self.local_node, id, spec).await {
+            Ok(response) if response.succeeded => self.finish(id, &node.id, Ok(response.output), response.duration_ms),
+            Ok(response) => self.finish(id, &node.id, Err(response.error), response.duration_ms),
+            Err(e) => self.finish(id, &node.id, Err(e.to_string()), started.elapsed().as_millis() as u64),
+        }
+    }
+
+    fn finish(&self, id: &str, node_id: &str, result: Result<String, String>, duration_ms: u64) {
+        self.record(id, node_id, |node| {
+            node.duration_ms = duration_ms;
+            match result {
+                Ok(output) => {
+                    node.state = JobState::Succeeded;
+                    node.output = output;
+                }
+                Err(error) => {
+                    warn!("Job {} failed on {}: {}", id, node.node_name, error);
+                    node.state = JobState::Failed;
+                    node.error = Some(error);
+                }
+            }
+        });
+    }
+
+    /// Change the result of job `id` on `node_id`
+    fn record(&self, id: &str, node_id: &str, change: impl FnOnce(&mut NodeJobResult)) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(job) = jobs.iter_mut().find(|job| job.id == id) else { return };
+        if let Some(node) = job.nodes.iter_mut().find(|node| node.node_id == node_id) {
+            change(node);
+        }
+        job.aggregate();
+        if job.state != JobState::Running {
+            info!("Job {} {:?}: {} of {} nodes succeeded", id, job.state, job.succeeded, job.nodes.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::communication::node::node_service_server::NodeServiceServer;
+    use crate::networking::communication::NodeCommunicationService;
+    use crate::networking::{NodeKey, PeerAuth};
+    use tonic::transport::Server;
+
+    fn node(id: &str, port: u16, capabilities: &[&str]) -> NodeInfo {
+        NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port,
+            interface_type: "Loopback".to_string(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+            version: String::new(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        }
+    }
+
+    /// A node answering RunJob with `runner`, checking the keys of its callers
+    async fn job_node(id: &str, runner: JobRunner, capabilities: &[&str]) -> NodeInfo {
+        let key = NodeKey::generate().unwrap();
+        let service = NodeCommunicationService::new(id.to_string(), id.to_string())
+            .with_jobs(runner)
+            .with_auth(PeerAuth::new(id, key.clone(), false));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(Server::builder()
+            .add_service(NodeServiceServer::new(service))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)));
+        NodeInfo { public_key: key.public_key(), ..node(id, port, capabilities) }
+    }
+
+    /// A client proving the key of node `id`
+    fn client_of(id: &str) -> NodeClient {
+        NodeClient::new().with_auth(PeerAuth::new(id, NodeKey::generate().unwrap(), false))
+    }
+
+    #[test]
+    fn test_job_target() {
+        let gpu = node("node-a", 0, &["discovery", "gpu_cores:38"]);
+        let plain = node("node-b", 0, &["discovery"]);
+        let target = JobTarget { capabilities: vec!["gpu_cores".to_string()], nodes: vec![], ..Default::default() };
+        assert!(target.matches(&gpu) && !target.matches(&plain));
+        let target = JobTarget { capabilities: vec!["gpu_cores:10".to_string()], nodes: vec![], ..Default::default() };
+        assert!(!target.matches(&gpu));
+        let target = JobTarget { capabilities: vec![], nodes: vec!["node-b".to_string()], ..Default::default() };
+        assert!(!target.matches(&gpu) && target.matches(&plain));
+        let mut rack = gpu.clone();
+        rack.labels.insert("rack".to_string(), "r1".to_string());
+        let target: JobTarget = serde_json::from_str(r#"{"labels": {"rack": "r1"}}"#).unwrap();
+        assert!(target.matches(&rack) && !target.matches(&gpu));
+        assert!(JobTarget::default().matches(&gpu));
+
+        let spec: JobSpec = serde_json::from_str(r#"{"kind": "shell", "command": "uptime"}"#).unwrap();
+        assert_eq!(spec, JobSpec::Shell { command: "uptime".to_string(), timeout_secs: None });
+    }
+
+    #[tokio::test]
+    async fn test_shell_jobs() {
+        let shell = |command: &str| JobSpec::Shell { command: command.to_string(), timeout_secs: Some(5) };
+        let runner = JobRunner::new(true);
+        assert_eq!(runner.run(&shell("echo hello")).await.unwrap(), "hello\n");
+        let err = runner.run(&shell("echo broken >&2; exit 3")).await.unwrap_err();
+        assert!(err.to_string().contains("broken"));
+        let err = JobRunner::new(false).run(&shell("echo hello")).await.unwrap_err();
+        assert!(err.to_string().contains("disabled"));
+    }
+
+    #[tokio::test]
+    async fn test_shell_jobs_need_allowed_sender() {
+        let runner = JobRunner::new(true).with_shell_senders(vec!["node-a".to_string()]);
+        let target = job_node("node-1", runner, &[]).await;
+        let shell = JobSpec::Shell { command: "echo hello".to_string(), timeout_secs: Some(5) };
+
+        // Without a session, and from a node that is not allowed
+        let err = NodeClient::new().run_job(&target, &node("node-a", 0, &[]), "job-1", &shell).await.unwrap_err();
+        assert!(err.to_string().contains("authenticated with the node key"), "{}", err);
+        let err = client_of("node-b").run_job(&target, &node("node-b", 0, &[]), "job-2", &shell).await.unwrap_err();
+        assert!(err.to_string().contains("may not send shell jobs"), "{}", err);
+
+        // Benchmarks need no allowlist
+        let benchmark = JobSpec::Benchmark { size_mb: Some(1) };
+        let response = NodeClient::new().run_job(&target, &node("node-a", 0, &[]), "job-3", &benchmark).await.unwrap();
+        assert!(response.succeeded, "{}", response.error);
+
+        let response = client_of("node-a").run_job(&target, &node("node-a", 0, &[]), "job-4", &shell).await.unwrap();
+        assert_eq!(response.output, "hello\n");
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_job() {
+        let nodes = vec![
+            job_node("node-1", JobRunner::new(true).with_shell_senders(vec!["node-a".to_string()]), &["linux"]).await,
+            job_node("node-2", JobRunner::new(false), &["linux"]).await,
+            job_node("node-3", JobRunner::new(true), &[]).await,
+        ];
+        let scheduler = JobScheduler::new(node("node-a", 0, &[]), Arc::new(client_of("node-a")));
+        let target = JobTarget { capabilities: vec!["linux".to_string()], nodes: vec![], ..Default::default() };
+
+        let spec = JobSpec::Shell { command: "echo done".to_string(), timeout_secs: None };
+        let report = scheduler.run(spec.clone(), target.clone(), &nodes).await.unwrap();
+        assert_eq!(report.nodes.len(), 2);
+        assert_eq!((report.state, report.succeeded, report.failed), (JobState::Failed, 1, 1));
+        let result = |id: &str| report.nodes.iter().find(|node| node.node_id == id).unwrap().clone();
+        assert_eq!(result("node-1").output, "done\n");
+        assert!(result("node-2").error.unwrap().contains("disabled"));
+
+        let id = scheduler.submit(JobSpec::Benchmark { size_mb: Some(1) }, target, &nodes).unwrap();
+        for _ in 0..100 {
+            if scheduler.report(&id).unwrap().state != JobState::Running {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(scheduler.report(&id).unwrap().state, JobState::Succeeded);
+
+        let err = scheduler.submit(spec, JobTarget { capabilities: vec!["gpu".to_string()], nodes: vec![], ..Default::default() }, &nodes).unwrap_err();
+        assert!(err.to_string().contains("No discovered node"));
+    }
+}
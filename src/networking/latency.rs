@@ -0,0 +1,326 @@
+// src/networking/latency.rs
+//
+// Latency and clock offsets between nodes
+// Every discovered node serving gRPC is pinged periodically. The round trip
+// time of each ping, and the node's clock compared to ours (its response
+// timestamp against the midpoint of the round trip), are kept over a window
+// of recent pings. The offset is taken from the fastest ping of the window,
+// whose midpoint is the most exact. Nodes much slower than the others, or
+// than they usually are, are flagged, which tells a Thunderbolt link from
+// Wi-Fi and shows when a path degrades.
+
+use anyhow::Result;
+use log::debug;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+
+use super::capabilities;
+use super::communication::NodeClient;
+use super::discovery::{NodeDiscovery, NodeInfo};
+
+/// Time between pings of each node
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+/// Pings each node's statistics are computed over
+const WINDOW: usize = 20;
+/// Round trips this many times longer than the reference are abnormal...
+const ABNORMAL_FACTOR: f64 = 3.0;
+/// ...once longer than this, as fast links differ by large factors in
+/// fractions of a millisecond
+const ABNORMAL_FLOOR_MS: f64 = 2.0;
+
+/// Why a node's latency is abnormal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyFlag {
+    /// Its median round trip is far above the median over all nodes
+    SlowerThanPeers,
+    /// Its last round trip is far above its fastest recent one
+    AboveBaseline,
+    /// Its last ping failed
+    Unreachable,
+}
+
+/// Latency statistics of one node, over its recent pings
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeerLatency {
+    pub node_id: String,
+    pub node_name: String,
+    /// Interface the node advertises itself on
+    pub interface_type: String,
+    /// Address its gRPC service answered on
+    pub address: Option<IpAddr>,
+    pub samples: usize,
+    /// Round trip of the last successful ping
+    pub rtt_ms: Option<f64>,
+    pub rtt_median_ms: Option<f64>,
+    pub rtt_min_ms: Option<f64>,
+    /// Mean difference between consecutive round trips
+    pub jitter_ms: Option<f64>,
+    /// How far the node's clock is ahead of ours, negative when behind;
+    /// exact to half the round trip it was measured with plus a millisecond
+    pub clock_offset_ms: Option<f64>,
+    /// Unix time in seconds of the last ping
+    pub measured_at: u64,
+    pub last_error: Option<String>,
+    pub flags: Vec<LatencyFlag>,
+}
+
+/// This node's row of the latency matrix
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyReport {
+    pub node_id: String,
+    pub peers: Vec<PeerLatency>,
+}
+
+/// A successful ping
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    rtt_ms: f64,
+    offset_ms: f64,
+}
+
+struct Peer {
+    node_name: String,
+    interface_type: String,
+    address: Option<IpAddr>,
+    samples: VecDeque<Sample>,
+    measured_at: u64,
+    last_error: Option<String>,
+}
+
+/// Latency of the nodes pinged, by node ID
+#[derive(Clone, Default)]
+pub struct LatencyTracker {
+    peers: Arc<StdMutex<BTreeMap<String, Peer>>>,
+}
+
+impl LatencyTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a ping of `node` at `address` that we sent at `request_ms` and
+    /// that it answered at `response_ms` by its clock, both Unix milliseconds,
+    /// after a round trip of `rtt`
+    pub fn record(&self, node: &NodeInfo, address: IpAddr, rtt: Duration, request_ms: i64, response_ms: i64) {
+        let rtt_ms = rtt.as_micros() as f64 / 1000.0;
+        let sample = Sample { rtt_ms, offset_ms: response_ms as f64 - (request_ms as f64 + rtt_ms / 2.0) };
+        self.update(node, |peer| {
+            peer.address = Some(address);
+            peer.last_error = None;
+            if peer.samples.len() == WINDOW {
+                peer.samples.pop_front();
+            }
+            peer.samples.push_back(sample);
+        });
+    }
+
+    /// Record a ping of `node` that failed
+    pub fn record_failure(&self, node: &NodeInfo, error: &anyhow::Error) {
+        self.update(node, |peer| peer.last_error = Some(error.to_string()));
+    }
+
+    fn update(&self, node: &NodeInfo, update: impl FnOnce(&mut Peer)) {
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.entry(node.id.clone()).or_insert_with(|| Peer {
+            node_name: String::new(),
+            interface_type: String::new(),
+            address: None,
+            samples: VecDeque::new(),
+            measured_at: 0,
+            last_error: None,
+        });
+        peer.node_name = node.name.clone();
+        peer.interface_type = node.interface_type.clone();
+        peer.measured_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        update(peer);
+    }
+
+    /// Forget the nodes other than `node_ids`
+    pub fn retain(&self, node_ids: &[&str]) {
+        self.peers.lock().unwrap().retain(|id, _| node_ids.contains(&id.as_str()));
+    }
+
+    /// Statistics of every node pinged, by node ID
+    pub fn peers(&self) -> Vec<PeerLatency> {
+        let peers = self.peers.lock().unwrap();
+        let mut latencies: Vec<PeerLatency> = peers.iter().map(|(id, peer)| peer.latency(id)).collect();
+        let medians: Vec<f64> = latencies.iter().filter_map(|latency| latency.rtt_median_ms).collect();
+        if let Some(cluster_median) = median(medians) {
+            for latency in &mut latencies {
+                if latency.rtt_median_ms.is_some_and(|rtt| abnormal(rtt, cluster_median)) {
+                    latency.flags.push(LatencyFlag::SlowerThanPeers);
+                }
+            }
+        }
+        latencies
+    }
+
+    /// Statistics of every node pinged from `node_id`, this node
+    pub fn report(&self, node_id: &str) -> LatencyReport {
+        LatencyReport { node_id: node_id.to_string(), peers: self.peers() }
+    }
+
+    /// Ping `node` once and record the outcome
+    pub async fn measure(&self, client: &NodeClient, node: &NodeInfo, local_node: &NodeInfo) -> Result<()> {
+        let result = async {
+            // Connect first so the round trip is that of the ping alone
+            let address = client.address_of(node).await?;
+            let started = Instant::now();
+            let pong = client.ping(node, "latency", local_node).await?;
+            Ok((address, started.elapsed(), pong))
+        }.await;
+        match result {
+            Ok((address, rtt, pong)) => {
+                self.record(node, address, rtt, pong.request_timestamp, pong.response_timestamp);
+                Ok(())
+            }
+            Err(e) => {
+                self.record_failure(node, &e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Ping every discovered node serving gRPC every `interval`
+    pub fn spawn(&self, discovery: Arc<NodeDiscovery>, client: Arc<NodeClient>, interval: Duration) -> JoinHandle<()> {
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let local_node = discovery.get_local_node();
+                let nodes: Vec<NodeInfo> = discovery.get_discovered_nodes().into_iter()
+                    .filter(|node| node.id != local_node.id && node.capability(capabilities::GRPC).is_some())
+                    .collect();
+                tracker.retain(&nodes.iter().map(|node| node.id.as_str()).collect::<Vec<_>>());
+                // Nodes the client backs off from keep the error that opened
+                // their circuit until it lets a ping through again
+                let mut reachable = Vec::new();
+                for node in &nodes {
+                    if !client.is_backing_off(node).await {
+                        reachable.push(node);
+                    }
+                }
+                let pings = reachable.iter().map(|node| tracker.measure(&client, node, &local_node));
+                for (node, result) in reachable.iter().zip(futures_util::future::join_all(pings).await) {
+                    if let Err(e) = result {
+                        debug!("Latency measurement of {} failed: {}", node.name, e);
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl Peer {
+    fn latency(&self, node_id: &str) -> PeerLatency {
+        let rtts: Vec<f64> = self.samples.iter().map(|sample| sample.rtt_ms).collect();
+        let fastest = self.samples.iter().min_by(|a, b| a.rtt_ms.total_cmp(&b.rtt_ms));
+        let last = self.samples.back().map(|sample| sample.rtt_ms);
+        let mut flags = Vec::new();
+        if self.last_error.is_some() {
+            flags.push(LatencyFlag::Unreachable);
+        } else if let (Some(last), Some(fastest)) = (last, fastest) {
+            if abnormal(last, fastest.rtt_ms) {
+                flags.push(LatencyFlag::AboveBaseline);
+            }
+        }
+        PeerLatency {
+            node_id: node_id.to_string(),
+            node_name: self.node_name.clone(),
+            interface_type: self.interface_type.clone(),
+            address: self.address,
+            samples: self.samples.len(),
+            rtt_ms: last,
+            rtt_median_ms: median(rtts.clone()),
+            rtt_min_ms: fastest.map(|sample| sample.rtt_ms),
+            jitter_ms: (rtts.len() > 1).then(|| {
+                rtts.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum::<f64>() / (rtts.len() - 1) as f64
+            }),
+            clock_offset_ms: fastest.map(|sample| sample.offset_ms),
+            measured_at: self.measured_at,
+            last_error: self.last_error.clone(),
+            flags,
+        }
+    }
+}
+
+fn abnormal(rtt_ms: f64, reference_ms: f64) -> bool {
+    rtt_ms > ABNORMAL_FLOOR_MS && rtt_ms > reference_ms * ABNORMAL_FACTOR
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) { (values[middle - 1] + values[middle]) / 2.0 } else { values[middle] })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    fn node(id: &str, interface_type: &str) -> NodeInfo {
+        NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port: 0,
+            interface_type: interface_type.to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_latency_statistics() {
+        let tracker = LatencyTracker::new();
+        let (thunderbolt, wifi, lan) = (node("tb", "Thunderbolt"), node("wifi", "WiFi"), node("lan", "Ethernet"));
+        let address = "127.0.0.1".parse().unwrap();
+        let ms = |ms: f64| Duration::from_micros((ms * 1000.0) as u64);
+        // A clock 250ms ahead; the fastest ping gives the best estimate
+        tracker.record(&thunderbolt, address, ms(4.0), 1_000, 1_253);
+        tracker.record(&thunderbolt, address, ms(0.5), 2_000, 2_250);
+        tracker.record(&thunderbolt, address, ms(1.0), 3_000, 3_251);
+        for (i, rtt) in [40.0, 35.0, 45.0].into_iter().enumerate() {
+            tracker.record(&wifi, address, ms(rtt), i as i64 * 1000, i as i64 * 1000 - 80);
+        }
+        tracker.record(&lan, address, ms(2.0), 0, 1);
+        tracker.record(&lan, address, ms(3.0), 1000, 1001);
+
+        let peers = tracker.peers();
+        assert_eq!(peers.iter().map(|p| p.node_id.as_str()).collect::<Vec<_>>(), ["lan", "tb", "wifi"]);
+        let tb = &peers[1];
+        assert_eq!((tb.samples, tb.rtt_ms, tb.rtt_median_ms, tb.rtt_min_ms), (3, Some(1.0), Some(1.0), Some(0.5)));
+        assert_eq!(tb.jitter_ms, Some(2.0));
+        assert_eq!(tb.clock_offset_ms, Some(249.75));
+        assert!(tb.flags.is_empty());
+        let wifi_latency = &peers[2];
+        assert_eq!(wifi_latency.clock_offset_ms, Some(-97.5));
+        assert_eq!(wifi_latency.flags, [LatencyFlag::SlowerThanPeers]);
+
+        // A spike above the node's own baseline, then an outage
+        tracker.record(&lan, address, ms(12.0), 2000, 2001);
+        assert_eq!(tracker.peers()[0].flags, [LatencyFlag::AboveBaseline]);
+        tracker.record_failure(&lan, &anyhow!("connection refused"));
+        let lan_latency = &tracker.peers()[0];
+        assert_eq!(lan_latency.flags, [LatencyFlag::Unreachable]);
+        assert_eq!(lan_latency.samples, 3);
+
+        tracker.retain(&["tb"]);
+        assert_eq!(tracker.peers().len(), 1);
+    }
+}
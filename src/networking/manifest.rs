@@ -0,0 +1,238 @@
+// src/networking/manifest.rs
+//
+// Directory transfer manifest
+// Sending a directory first streams a manifest of every entry under it (relative
+// path, size, permissions, mtime). The receiver creates the tree from it, the
+// files then travel as ordinary transfers, and once they are all in place the
+// directory permissions and mtimes are restored, deepest first, so a read-only
+// directory is only locked after its contents were written.
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, Metadata};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Kind of a manifest entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Directory,
+    File,
+}
+
+/// One file or directory of a directory transfer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path relative to the receive directory, starting with the transferred directory's name
+    pub path: String,
+    pub kind: EntryKind,
+    /// Size in bytes; 0 for directories
+    pub size: u64,
+    /// Unix permission bits, without setuid, setgid and sticky
+    pub mode: u32,
+    /// Modification time as seconds and nanoseconds since the Unix epoch
+    pub mtime_secs: u64,
+    pub mtime_nanos: u32,
+}
+
+impl ManifestEntry {
+    fn new(path: String, kind: EntryKind, metadata: &Metadata) -> Self {
+        let (mtime_secs, mtime_nanos) = mtime_of(metadata);
+        Self {
+            path,
+            kind,
+            size: if kind == EntryKind::File { metadata.len() } else { 0 },
+            mode: metadata.permissions().mode() & 0o777,
+            mtime_secs,
+            mtime_nanos,
+        }
+    }
+}
+
+/// Everything under a transferred directory, the directory itself first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferManifest {
+    pub id: String,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl TransferManifest {
+    /// Walk `root`, naming entries relative to its parent. Symlinks are skipped.
+    pub fn from_directory(id: String, root: &Path) -> Result<Self> {
+        let name = root
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid directory path {}", root.display()))?
+            .to_string_lossy()
+            .to_string();
+        let mut entries = Vec::new();
+        walk(root, name, &mut entries)?;
+        Ok(Self { id, entries })
+    }
+
+    /// Files in the manifest with their local paths, given the directory it was built from
+    pub fn files<'a>(&'a self, root: &'a Path) -> impl Iterator<Item = (&'a ManifestEntry, PathBuf)> + 'a {
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind == EntryKind::File)
+            .map(move |entry| {
+                // Entry paths start with the root's own name
+                let relative: PathBuf = Path::new(&entry.path).components().skip(1).collect();
+                (entry, root.join(relative))
+            })
+    }
+
+    /// Refuse manifests that would write outside the receive directory
+    pub fn validate(&self) -> Result<()> {
+        for entry in &self.entries {
+            safe_relative_path(&entry.path)?;
+        }
+        Ok(())
+    }
+
+    /// Create the directory tree under `receive_dir`
+    pub fn create_directories(&self, receive_dir: &Path) -> Result<()> {
+        for entry in self.entries.iter().filter(|entry| entry.kind == EntryKind::Directory) {
+            let path = receive_dir.join(safe_relative_path(&entry.path)?);
+            fs::create_dir_all(&path)
+                .with_context(|| format!("Failed to create directory {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Check every file arrived with its expected size, then restore directory
+    /// permissions and mtimes, deepest first
+    pub fn finish(&self, receive_dir: &Path) -> Result<()> {
+        for entry in self.entries.iter().filter(|entry| entry.kind == EntryKind::File) {
+            let path = receive_dir.join(safe_relative_path(&entry.path)?);
+            let size = fs::metadata(&path)
+                .with_context(|| format!("Missing file {}", entry.path))?
+                .len();
+            if size != entry.size {
+                return Err(anyhow!("File {} is {} bytes, expected {}", entry.path, size, entry.size));
+            }
+        }
+
+        let mut directories: Vec<_> = self.entries.iter().filter(|entry| entry.kind == EntryKind::Directory).collect();
+        directories.sort_by_key(|entry| std::cmp::Reverse(Path::new(&entry.path).components().count()));
+        for entry in directories {
+            apply_metadata(&receive_dir.join(safe_relative_path(&entry.path)?), entry.mode, entry.mtime_secs, entry.mtime_nanos)?;
+        }
+        Ok(())
+    }
+}
+
+fn walk(path: &Path, name: String, entries: &mut Vec<ManifestEntry>) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)
+        .with_context(|| format!("Failed to get metadata for {}", path.display()))?;
+    if metadata.is_file() {
+        entries.push(ManifestEntry::new(name, EntryKind::File, &metadata));
+    } else if metadata.is_dir() {
+        entries.push(ManifestEntry::new(name.clone(), EntryKind::Directory, &metadata));
+        let mut children: Vec<_> = fs::read_dir(path)
+            .with_context(|| format!("Failed to read directory {}", path.display()))?
+            .collect::<std::io::Result<_>>()?;
+        children.sort_by_key(|child| child.file_name());
+        for child in children {
+            let child_name = format!("{}/{}", name, child.file_name().to_string_lossy());
+            walk(&child.path(), child_name, entries)?;
+        }
+    } else {
+        warn!("Skipping {}: not a regular file or directory", path.display());
+    }
+    Ok(())
+}
+
+/// Modification time as seconds and nanoseconds since the Unix epoch
+pub fn mtime_of(metadata: &Metadata) -> (u64, u32) {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    (mtime.as_secs(), mtime.subsec_nanos())
+}
+
+/// Set permissions and modification time of a received file or directory;
+/// setuid, setgid and sticky bits from the sender are dropped
+pub fn apply_metadata(path: &Path, mode: u32, mtime_secs: u64, mtime_nanos: u32) -> Result<()> {
+    let mtime = UNIX_EPOCH + Duration::new(mtime_secs, mtime_nanos);
+    if mtime > UNIX_EPOCH && mtime <= SystemTime::now() + Duration::from_secs(86400) {
+        File::open(path)
+            .and_then(|file| file.set_modified(mtime))
+            .with_context(|| format!("Failed to set modification time of {}", path.display()))?;
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+        .with_context(|| format!("Failed to set permissions of {}", path.display()))
+}
+
+/// Check a sender-supplied name only contains plain components, so it stays
+/// inside the receive directory
+pub fn safe_relative_path(name: &str) -> Result<PathBuf> {
+    let path = Path::new(name);
+    if name.is_empty() || !path.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(anyhow!("Refusing unsafe transfer path {:?}", name));
+    }
+    Ok(path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_relative_path() {
+        assert!(safe_relative_path("photos/2024/a.jpg").is_ok());
+        assert!(safe_relative_path("../etc/passwd").is_err());
+        assert!(safe_relative_path("photos/../../x").is_err());
+        assert!(safe_relative_path("/etc/passwd").is_err());
+        assert!(safe_relative_path("").is_err());
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let source = tempfile::tempdir().unwrap();
+        let root = source.path().join("dataset");
+        fs::create_dir_all(root.join("raw/empty")).unwrap();
+        fs::write(root.join("raw/a.bin"), vec![1u8; 10]).unwrap();
+        fs::write(root.join("readme.txt"), "hello").unwrap();
+        fs::set_permissions(root.join("raw"), fs::Permissions::from_mode(0o750)).unwrap();
+
+        let manifest = TransferManifest::from_directory("id".to_string(), &root).unwrap();
+        let paths: Vec<_> = manifest.entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["dataset", "dataset/raw", "dataset/raw/a.bin", "dataset/raw/empty", "dataset/readme.txt"]);
+        let files: Vec<_> = manifest.files(&root).map(|(_, path)| path).collect();
+        assert_eq!(files, [root.join("raw/a.bin"), root.join("readme.txt")]);
+
+        // Reassemble elsewhere
+        let receive = tempfile::tempdir().unwrap();
+        manifest.validate().unwrap();
+        manifest.create_directories(receive.path()).unwrap();
+        assert!(manifest.finish(receive.path()).is_err());
+        fs::write(receive.path().join("dataset/raw/a.bin"), vec![1u8; 10]).unwrap();
+        fs::write(receive.path().join("dataset/readme.txt"), "hello").unwrap();
+        manifest.finish(receive.path()).unwrap();
+
+        let raw = fs::metadata(receive.path().join("dataset/raw")).unwrap();
+        assert_eq!(raw.permissions().mode() & 0o7777, 0o750);
+        assert_eq!(mtime_of(&raw), mtime_of(&fs::metadata(root.join("raw")).unwrap()));
+        assert!(receive.path().join("dataset/raw/empty").is_dir());
+
+        let hostile = TransferManifest {
+            id: "id".to_string(),
+            entries: vec![ManifestEntry { path: "../escape".to_string(), ..manifest.entries[0].clone() }],
+        };
+        assert!(hostile.validate().is_err());
+    }
+
+    #[test]
+    fn test_special_bits_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tool");
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        apply_metadata(&path, 0o4755, 0, 0).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o7777, 0o755);
+    }
+}
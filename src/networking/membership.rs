@@ -0,0 +1,581 @@
+// src/networking/membership.rs
+//
+// SWIM membership
+// Discovery expiry notices a node is gone minutes after it stops announcing
+// itself. Instead every node probes one member per interval over UDP; a
+// member that doesn't acknowledge is probed indirectly through a few others
+// (so a lossy link between two nodes doesn't condemn either), and only then
+// suspected. A suspect that doesn't refute the suspicion with a higher
+// incarnation within the suspect timeout is declared dead. State changes are
+// piggybacked on the probes, so every node learns of them within a few
+// intervals.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+
+use super::discovery::NodeInfo;
+
+/// Port membership messages are exchanged on
+pub const DEFAULT_GOSSIP_PORT: u16 = 54323;
+
+/// Prefix of every message, so stray datagrams are ignored
+const MAGIC: &[u8] = b"NCSWIM1\n";
+
+/// Largest message accepted
+const MAX_MESSAGE_LEN: usize = 65536;
+
+/// State changes piggybacked on one message
+const MAX_PIGGYBACK: usize = 6;
+
+/// Each state change is piggybacked this many times the log2 of the member count
+const RETRANSMIT_MULT: usize = 3;
+
+/// Time dead members are remembered, so stale news of them is ignored
+const DEAD_RETENTION: Duration = Duration::from_secs(300);
+
+/// Timing of the failure detector
+#[derive(Debug, Clone, Copy)]
+pub struct MembershipConfig {
+    /// Time between probes; each probes one member
+    pub probe_interval: Duration,
+    /// Time a direct probe waits for its acknowledgement
+    pub probe_timeout: Duration,
+    /// Members asked to probe an unresponsive member for us
+    pub indirect_probes: usize,
+    /// Time a suspect has to refute the suspicion before it is declared dead
+    pub suspect_timeout: Duration,
+}
+
+impl Default for MembershipConfig {
+    fn default() -> Self {
+        Self {
+            probe_interval: Duration::from_secs(1),
+            probe_timeout: Duration::from_millis(300),
+            indirect_probes: 3,
+            suspect_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// What the cluster believes about a member
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemberState {
+    Alive,
+    /// Missed its probes; dead unless it refutes in time
+    Suspect,
+    Dead,
+}
+
+/// A node in the membership list
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub node: NodeInfo,
+    /// Where it receives membership messages
+    pub addr: SocketAddr,
+    pub state: MemberState,
+    /// Raised by the member to refute suspicion of it
+    pub incarnation: u64,
+    /// When the state last changed
+    pub since: Instant,
+}
+
+/// A state change of a member, as gossiped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Update {
+    node: NodeInfo,
+    addr: SocketAddr,
+    state: MemberState,
+    incarnation: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Message {
+    Ping { seq: u64 },
+    /// Probe `target` and acknowledge `seq` if it answers
+    PingReq { seq: u64, target: SocketAddr },
+    Ack { seq: u64 },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    message: Message,
+    /// The sender, alive at its current incarnation
+    sender: Update,
+    updates: Vec<Update>,
+}
+
+/// SWIM membership of this node, shared with its tasks
+#[derive(Clone)]
+pub struct Membership {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    config: MembershipConfig,
+    socket: UdpSocket,
+    local_id: String,
+    /// This node as currently advertised
+    local_node: watch::Receiver<NodeInfo>,
+    local_addr: SocketAddr,
+    incarnation: AtomicU64,
+    seq: AtomicU64,
+    members: StdMutex<HashMap<String, Member>>,
+    /// Acknowledgements waited for, by sequence number
+    pending: StdMutex<HashMap<u64, oneshot::Sender<()>>>,
+    /// State changes still to piggyback, with the times left to send each
+    gossip: StdMutex<VecDeque<(Update, usize)>>,
+    /// Members left to probe in this round
+    probe_order: StdMutex<Vec<String>>,
+}
+
+impl Membership {
+    /// Receive membership messages for `local_node` on `addr`
+    pub async fn bind(local_node: watch::Receiver<NodeInfo>, addr: SocketAddr, config: MembershipConfig) -> Result<Self> {
+        let socket = UdpSocket::bind(addr).await
+            .with_context(|| format!("Failed to bind membership port {}", addr.port()))?;
+        let local_addr = socket.local_addr()?;
+        // A restarted node outranks what the cluster remembers of its last run
+        let incarnation = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let local_id = local_node.borrow().id.clone();
+        info!("Membership of node '{}' on {}", local_node.borrow().name, local_addr);
+        Ok(Self {
+            inner: Arc::new(Inner {
+                config,
+                socket,
+                local_id,
+                local_node,
+                local_addr,
+                incarnation: AtomicU64::new(incarnation),
+                seq: AtomicU64::new(0),
+                members: StdMutex::new(HashMap::new()),
+                pending: StdMutex::new(HashMap::new()),
+                gossip: StdMutex::new(VecDeque::new()),
+                probe_order: StdMutex::new(Vec::new()),
+            }),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.inner.local_addr
+    }
+
+    /// Run the receiver and the failure detector
+    pub fn spawn(&self) -> Vec<JoinHandle<()>> {
+        let receiver = {
+            let membership = self.clone();
+            tokio::spawn(async move { membership.receive().await })
+        };
+        let detector = {
+            let membership = self.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(membership.inner.config.probe_interval);
+                loop {
+                    ticker.tick().await;
+                    membership.expire_suspects();
+                    if let Some(member) = membership.next_target() {
+                        let membership = membership.clone();
+                        tokio::spawn(async move { membership.probe(member).await });
+                    }
+                }
+            })
+        };
+        vec![receiver, detector]
+    }
+
+    /// Keep joining the nodes of `discovered_nodes`, at `port` of their first
+    /// IPv4 address
+    pub fn follow(
+        &self,
+        discovered_nodes: Arc<StdMutex<HashMap<String, (NodeInfo, Instant)>>>,
+        port: u16,
+    ) -> JoinHandle<()> {
+        let membership = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(membership.inner.config.probe_interval);
+            loop {
+                ticker.tick().await;
+                let nodes: Vec<NodeInfo> = discovered_nodes.lock().unwrap().values().map(|(node, _)| node.clone()).collect();
+                for node in nodes {
+                    if let Some(ip) = node.candidate_ips().into_iter().find(IpAddr::is_ipv4) {
+                        membership.join(&node, SocketAddr::new(ip, port));
+                    }
+                }
+            }
+        })
+    }
+
+    /// Add `node`, reachable at `addr`, unless it is already a member; a
+    /// member's record is replaced by `node`
+    pub fn join(&self, node: &NodeInfo, addr: SocketAddr) {
+        if node.id == self.inner.local_id {
+            return;
+        }
+        let mut members = self.inner.members.lock().unwrap();
+        match members.get_mut(&node.id) {
+            Some(member) => member.node = node.clone(),
+            None => {
+                debug!("Node {} joins the membership at {}", node.name, addr);
+                members.insert(node.id.clone(), Member {
+                    node: node.clone(),
+                    addr,
+                    state: MemberState::Alive,
+                    incarnation: 0,
+                    since: Instant::now(),
+                });
+            }
+        }
+    }
+
+    /// Every member but this node, dead ones included
+    pub fn members(&self) -> Vec<Member> {
+        self.inner.members.lock().unwrap().values().cloned().collect()
+    }
+
+    async fn receive(&self) {
+        let mut buffer = vec![0u8; MAX_MESSAGE_LEN];
+        loop {
+            let (len, source) = match self.inner.socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("Membership receive failed: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            let Some(envelope) = buffer[..len].strip_prefix(MAGIC).and_then(|json| serde_json::from_slice::<Envelope>(json).ok()) else {
+                continue;
+            };
+            self.handle(envelope, source).await;
+        }
+    }
+
+    async fn handle(&self, envelope: Envelope, source: SocketAddr) {
+        let mut sender = envelope.sender;
+        // The address the message came from is one we can reach
+        sender.addr = SocketAddr::new(source.ip().to_canonical(), source.port());
+        self.apply(sender.clone());
+        // The sender's own record is its current one, even at the same incarnation
+        if let Some(member) = self.inner.members.lock().unwrap().get_mut(&sender.node.id) {
+            member.node = sender.node;
+        }
+        for update in envelope.updates {
+            self.apply(update);
+        }
+
+        match envelope.message {
+            Message::Ping { seq } => self.send(source, Message::Ack { seq }).await,
+            Message::Ack { seq } => {
+                if let Some(waiter) = self.inner.pending.lock().unwrap().remove(&seq) {
+                    let _ = waiter.send(());
+                }
+            }
+            Message::PingReq { seq, target } => {
+                let membership = self.clone();
+                tokio::spawn(async move {
+                    if membership.ping(target, membership.inner.config.probe_timeout).await {
+                        membership.send(source, Message::Ack { seq }).await;
+                    }
+                });
+            }
+        }
+    }
+
+    /// Probe `member` directly, then through others, and suspect it if
+    /// nobody reaches it
+    async fn probe(&self, member: Member) {
+        let config = self.inner.config;
+        if self.ping(member.addr, config.probe_timeout).await {
+            return;
+        }
+
+        let (seq, acked) = self.expect_ack();
+        let helpers: Vec<SocketAddr> = {
+            let members = self.inner.members.lock().unwrap();
+            let candidates: Vec<SocketAddr> = members.values()
+                .filter(|other| other.state == MemberState::Alive && other.node.id != member.node.id)
+                .map(|other| other.addr)
+                .collect();
+            candidates.choose_multiple(&mut rand::thread_rng(), config.indirect_probes).copied().collect()
+        };
+        debug!("No ack from {}, probing it through {} members", member.node.name, helpers.len());
+        for helper in helpers {
+            self.send(helper, Message::PingReq { seq, target: member.addr }).await;
+        }
+
+        let wait = config.probe_interval.saturating_sub(config.probe_timeout).max(config.probe_timeout);
+        if !self.wait_for_ack(seq, acked, wait).await {
+            self.apply(Update {
+                node: member.node,
+                addr: member.addr,
+                state: MemberState::Suspect,
+                incarnation: member.incarnation,
+            });
+        }
+    }
+
+    /// Ping `addr`; whether it acknowledged within `timeout`
+    async fn ping(&self, addr: SocketAddr, timeout: Duration) -> bool {
+        let (seq, acked) = self.expect_ack();
+        self.send(addr, Message::Ping { seq }).await;
+        self.wait_for_ack(seq, acked, timeout).await
+    }
+
+    fn expect_ack(&self) -> (u64, oneshot::Receiver<()>) {
+        let seq = self.inner.seq.fetch_add(1, Ordering::Relaxed);
+        let (waiter, acked) = oneshot::channel();
+        self.inner.pending.lock().unwrap().insert(seq, waiter);
+        (seq, acked)
+    }
+
+    async fn wait_for_ack(&self, seq: u64, acked: oneshot::Receiver<()>, timeout: Duration) -> bool {
+        let result = tokio::time::timeout(timeout, acked).await;
+        self.inner.pending.lock().unwrap().remove(&seq);
+        result.is_ok_and(|received| received.is_ok())
+    }
+
+    async fn send(&self, addr: SocketAddr, message: Message) {
+        let envelope = Envelope { message, sender: self.self_update(), updates: self.take_gossip() };
+        let mut datagram = MAGIC.to_vec();
+        datagram.extend(serde_json::to_vec(&envelope).unwrap_or_default());
+        if let Err(e) = self.inner.socket.send_to(&datagram, addr).await {
+            debug!("Failed to send membership message to {}: {}", addr, e);
+        }
+    }
+
+    fn self_update(&self) -> Update {
+        Update {
+            node: self.inner.local_node.borrow().clone(),
+            addr: self.inner.local_addr,
+            state: MemberState::Alive,
+            incarnation: self.inner.incarnation.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Apply news about a member, passing it on if it changed what we know
+    fn apply(&self, update: Update) {
+        if update.node.id == self.inner.local_id {
+            // Refute suspicion of ourselves by outranking it
+            let incarnation = self.inner.incarnation.load(Ordering::Relaxed);
+            if update.state != MemberState::Alive && update.incarnation >= incarnation {
+                self.inner.incarnation.store(update.incarnation + 1, Ordering::Relaxed);
+                info!("Refuting suspicion of this node with incarnation {}", update.incarnation + 1);
+                self.queue(self.self_update());
+            }
+            return;
+        }
+
+        {
+            let mut members = self.inner.members.lock().unwrap();
+            match members.get_mut(&update.node.id) {
+                Some(member) => {
+                    let newer = match (update.state, member.state) {
+                        (MemberState::Alive, _) => update.incarnation > member.incarnation,
+                        (MemberState::Suspect, MemberState::Alive) => update.incarnation >= member.incarnation,
+                        (MemberState::Suspect, _) => update.incarnation > member.incarnation,
+                        (MemberState::Dead, MemberState::Dead) => false,
+                        (MemberState::Dead, _) => update.incarnation >= member.incarnation,
+                    };
+                    if !newer {
+                        return;
+                    }
+                    if update.state != member.state {
+                        log_transition(&update.node, member.state, update.state);
+                        member.since = Instant::now();
+                    }
+                    member.node = update.node.clone();
+                    member.addr = update.addr;
+                    member.state = update.state;
+                    member.incarnation = update.incarnation;
+                }
+                None => {
+                    info!("✅ Node {} ({}) joined the membership as {:?}", update.node.name, update.node.id, update.state);
+                    members.insert(update.node.id.clone(), Member {
+                        node: update.node.clone(),
+                        addr: update.addr,
+                        state: update.state,
+                        incarnation: update.incarnation,
+                        since: Instant::now(),
+                    });
+                }
+            }
+        }
+        self.queue(update);
+    }
+
+    /// Gossip `update`, replacing older news of the same member
+    fn queue(&self, update: Update) {
+        let members = self.inner.members.lock().unwrap().len() + 1;
+        let transmissions = RETRANSMIT_MULT * (usize::BITS - members.leading_zeros()) as usize;
+        let mut gossip = self.inner.gossip.lock().unwrap();
+        gossip.retain(|(queued, _)| queued.node.id != update.node.id);
+        gossip.push_front((update, transmissions));
+    }
+
+    /// The updates to piggyback on the next message, least sent first
+    fn take_gossip(&self) -> Vec<Update> {
+        let mut gossip = self.inner.gossip.lock().unwrap();
+        let mut updates = Vec::new();
+        for _ in 0..MAX_PIGGYBACK.min(gossip.len()) {
+            let Some((update, left)) = gossip.pop_front() else { break };
+            updates.push(update.clone());
+            if left > 1 {
+                gossip.push_back((update, left - 1));
+            }
+        }
+        updates
+    }
+
+    /// The next live member to probe, going round the members in random order
+    fn next_target(&self) -> Option<Member> {
+        let members = self.inner.members.lock().unwrap();
+        let mut order = self.inner.probe_order.lock().unwrap();
+        loop {
+            if order.is_empty() {
+                order.extend(members.values().filter(|member| member.state != MemberState::Dead).map(|member| member.node.id.clone()));
+                if order.is_empty() {
+                    return None;
+                }
+                order.shuffle(&mut rand::thread_rng());
+            }
+            let id = order.pop()?;
+            if let Some(member) = members.get(&id).filter(|member| member.state != MemberState::Dead) {
+                return Some(member.clone());
+            }
+        }
+    }
+
+    /// Declare suspects dead once their time to refute is up, and forget
+    /// members dead for long
+    fn expire_suspects(&self) {
+        let expired: Vec<Update> = {
+            let mut members = self.inner.members.lock().unwrap();
+            members.retain(|_, member| member.state != MemberState::Dead || member.since.elapsed() < DEAD_RETENTION);
+            members.values()
+                .filter(|member| member.state == MemberState::Suspect && member.since.elapsed() >= self.inner.config.suspect_timeout)
+                .map(|member| Update {
+                    node: member.node.clone(),
+                    addr: member.addr,
+                    state: MemberState::Dead,
+                    incarnation: member.incarnation,
+                })
+                .collect()
+        };
+        for update in expired {
+            self.apply(update);
+        }
+    }
+}
+
+fn log_transition(node: &NodeInfo, from: MemberState, to: MemberState) {
+    match to {
+        MemberState::Alive => info!("✅ Node {} ({}) is alive again", node.name, node.id),
+        MemberState::Suspect => warn!("Node {} ({}) is suspected to have failed", node.name, node.id),
+        MemberState::Dead if from == MemberState::Suspect => warn!("👋 Node {} ({}) failed", node.name, node.id),
+        MemberState::Dead => warn!("👋 Node {} ({}) is reported dead", node.name, node.id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str) -> NodeInfo {
+        NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: "127.0.0.1".to_string(),
+            port: 54321,
+            interface_type: "Loopback".to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        }
+    }
+
+    fn state_of(membership: &Membership, id: &str) -> Option<MemberState> {
+        membership.members().into_iter().find(|member| member.node.id == id).map(|member| member.state)
+    }
+
+    async fn wait_for(what: impl Fn() -> bool) -> bool {
+        for _ in 0..100 {
+            if what() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn test_failure_detection() {
+        let config = MembershipConfig {
+            probe_interval: Duration::from_millis(100),
+            probe_timeout: Duration::from_millis(40),
+            indirect_probes: 2,
+            suspect_timeout: Duration::from_millis(500),
+        };
+        let localhost = SocketAddr::from(([127, 0, 0, 1], 0));
+        let mut nodes = Vec::new();
+        for id in ["node-a", "node-b", "node-c"] {
+            nodes.push(Membership::bind(watch::channel(node(id)).1, localhost, config).await.unwrap());
+        }
+        let (a, b, c) = (&nodes[0], &nodes[1], &nodes[2]);
+
+        // A and C only know B; they learn of each other through gossip
+        a.join(&node("node-b"), b.local_addr());
+        c.join(&node("node-b"), b.local_addr());
+        let tasks: Vec<Vec<JoinHandle<()>>> = nodes.iter().map(Membership::spawn).collect();
+        assert!(wait_for(|| state_of(a, "node-c") == Some(MemberState::Alive) && state_of(c, "node-a") == Some(MemberState::Alive)).await);
+        assert_eq!(state_of(b, "node-a"), Some(MemberState::Alive));
+
+        // C fails; A and B suspect it, then declare it dead
+        for task in &tasks[2] {
+            task.abort();
+        }
+        assert!(wait_for(|| state_of(a, "node-c") == Some(MemberState::Dead) && state_of(b, "node-c") == Some(MemberState::Dead)).await);
+        assert_eq!(state_of(a, "node-b"), Some(MemberState::Alive));
+
+        for task in tasks.into_iter().flatten() {
+            task.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_suspicion_is_refuted() {
+        let membership = Membership::bind(watch::channel(node("node-a")).1, SocketAddr::from(([127, 0, 0, 1], 0)), MembershipConfig::default()).await.unwrap();
+        let incarnation = membership.self_update().incarnation;
+        membership.apply(Update {
+            node: node("node-a"),
+            addr: membership.local_addr(),
+            state: MemberState::Suspect,
+            incarnation,
+        });
+        assert_eq!(membership.self_update().incarnation, incarnation + 1);
+        let gossip = membership.take_gossip();
+        assert_eq!((gossip[0].state, gossip[0].incarnation), (MemberState::Alive, incarnation + 1));
+
+        // Stale news doesn't override newer
+        let peer = |state, incarnation| Update { node: node("node-b"), addr: membership.local_addr(), state, incarnation };
+        membership.apply(peer(MemberState::Alive, 5));
+        membership.apply(peer(MemberState::Suspect, 4));
+        assert_eq!(state_of(&membership, "node-b"), Some(MemberState::Alive));
+        membership.apply(peer(MemberState::Suspect, 5));
+        assert_eq!(state_of(&membership, "node-b"), Some(MemberState::Suspect));
+        membership.apply(peer(MemberState::Alive, 6));
+        assert_eq!(state_of(&membership, "node-b"), Some(MemberState::Alive));
+    }
+}
@@ -2,11 +2,57 @@ pub mod discovery;
 pub mod interface;
 pub mod communication;
 pub mod file_transfer;
+pub mod bandwidth;
+pub mod benchmark;
+pub mod broadcast;
+pub mod capabilities;
+pub mod connections;
+pub mod content_store;
+pub mod control;
+pub mod delta_sync;
+pub mod fetch;
+pub mod folder_sync;
+pub mod fs_watch;
+pub mod grpc_health;
+pub mod grpc_tls;
+pub mod history;
+pub mod jobs;
+pub mod latency;
+pub mod manifest;
+pub mod membership;
+pub mod offers;
+pub mod paths;
+pub mod peer_auth;
+pub mod peer_metrics;
+pub mod rdma;
+pub mod receive_policy;
+pub mod reflection;
+pub mod rendezvous;
+pub mod udp_discovery;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod uring;
+pub mod wake;
+pub mod zero_copy;
 
 // Re-export key components for easier access
 pub use discovery::{NodeDiscovery, NodeInfo};
+pub use udp_discovery::UdpMode;
 pub use interface::NetworkInterface;
 pub use interface::InterfaceType;
 pub use communication::NodeClient;
-pub use communication::start_grpc_server;
-pub use file_transfer::{FileTransferManager, FileTransferConfig, TransferStatus}; 
\ No newline at end of file
+pub use connections::ConnectionPolicy;
+pub use communication::{start_grpc_server_until, NodeCommunicationService};
+pub use file_transfer::{FileTransferManager, FileTransferConfig, SyncStats, TransferStatus};
+pub use benchmark::{BenchmarkPlan, BenchmarkReport};
+pub use broadcast::BroadcastReport;
+pub use fetch::FileExports;
+pub use folder_sync::{FolderSync, FolderSyncConfig, FolderSyncStatus};
+pub use grpc_tls::GrpcTls;
+pub use history::{PeerStats, TransferHistory, TransferRecord};
+pub use jobs::{JobReport, JobRunner, JobScheduler, JobSpec, JobTarget};
+pub use latency::LatencyTracker;
+pub use membership::{Member, MemberState};
+pub use peer_auth::{NodeKey, PeerAuth};
+pub use peer_metrics::MetricsSummary;
+pub use receive_policy::{ReceivePolicy, WhenFull};
+pub use wake::WakeTable;
@@ -0,0 +1,83 @@
+// src/networking/offers.rs
+//
+// Transfer offers
+// Instead of assuming the receiver's transfer port, a sender can first offer a
+// transfer over gRPC. The receiver checks it and answers with the port of its
+// file transfer server and a token the transfer's streams present in their
+// handshake. Receivers with `require_offer` set refuse streams without a valid
+// token, so every incoming transfer was announced and accepted beforehand.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long a token stays valid after it was issued or last used
+pub const OFFER_TTL: Duration = Duration::from_secs(600);
+
+struct IssuedOffer {
+    sender_id: String,
+    expires_at: Instant,
+}
+
+/// Tokens of accepted offers
+#[derive(Clone, Default)]
+pub struct TransferOffers {
+    issued: Arc<StdMutex<HashMap<String, IssuedOffer>>>,
+}
+
+impl TransferOffers {
+    /// Issue a token for a transfer from `sender_id`
+    pub fn issue(&self, sender_id: &str) -> String {
+        let token = Uuid::new_v4().to_string();
+        let now = Instant::now();
+        let mut issued = self.issued.lock().unwrap();
+        issued.retain(|_, offer| offer.expires_at > now);
+        issued.insert(token.clone(), IssuedOffer {
+            sender_id: sender_id.to_string(),
+            expires_at: now + OFFER_TTL,
+        });
+        token
+    }
+
+    /// Check a token presented by `sender_id`. Every stream of the transfer
+    /// presents it, so each use extends its life.
+    pub fn redeem(&self, token: &str, sender_id: &str) -> Result<()> {
+        self.redeem_at(token, sender_id, Instant::now())
+    }
+
+    fn redeem_at(&self, token: &str, sender_id: &str, now: Instant) -> Result<()> {
+        let mut issued = self.issued.lock().unwrap();
+        let offer = issued
+            .get_mut(token)
+            .filter(|offer| offer.expires_at > now)
+            .ok_or_else(|| anyhow!("unknown or expired transfer token"))?;
+        if offer.sender_id != sender_id {
+            return Err(anyhow!("transfer token was issued to another node"));
+        }
+        offer.expires_at = now + OFFER_TTL;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redeem_offer_tokens() {
+        let offers = TransferOffers::default();
+        let token = offers.issue("node-a");
+        assert!(offers.redeem(&token, "node-a").is_ok());
+        assert!(offers.redeem(&token, "node-a").is_ok());
+        assert_eq!(offers.redeem(&token, "node-b").unwrap_err().to_string(), "transfer token was issued to another node");
+        assert!(offers.redeem("made-up", "node-a").is_err());
+
+        // Use keeps the token alive, idleness expires it
+        let later = Instant::now() + OFFER_TTL - Duration::from_secs(1);
+        assert!(offers.redeem_at(&token, "node-a", later).is_ok());
+        assert!(offers.redeem_at(&token, "node-a", later + OFFER_TTL - Duration::from_secs(1)).is_ok());
+        assert!(offers.redeem_at(&token, "node-a", later + 3 * OFFER_TTL).is_err());
+    }
+}
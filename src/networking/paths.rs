@@ -0,0 +1,138 @@
+// src/networking/paths.rs
+//
+// Path selection between nodes
+// Nodes advertise every usable address with its interface type. Before
+// connecting to a node with several addresses, every address is probed with a
+// TCP connect to the node's port, and the reachable ones are tried best path
+// first: wired LAN, then Thunderbolt, then Wi-Fi. The ranking is kept for a
+// while, and dropped when the node's addresses change or it stops answering,
+// so a node that moves networks is probed again instead of being pinned to a
+// path chosen once.
+
+use futures_util::future::join_all;
+use log::debug;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex as StdMutex;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+use super::discovery::NodeInfo;
+
+/// Time each address has to accept the probe connection
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Time a ranking is used before the node is probed again
+const RANKING_TTL: Duration = Duration::from_secs(300);
+
+struct Ranking {
+    /// Candidates of the node when it was probed
+    candidates: Vec<IpAddr>,
+    ranked: Vec<IpAddr>,
+    probed_at: Instant,
+}
+
+/// Rankings of the paths to each node, by node ID
+#[derive(Default)]
+pub(crate) struct PathSelector {
+    rankings: StdMutex<HashMap<String, Ranking>>,
+}
+
+impl PathSelector {
+    /// Addresses of `node` to connect to, best first, probing them unless a
+    /// recent ranking is known
+    pub async fn candidates(&self, node: &NodeInfo) -> Vec<IpAddr> {
+        let candidates = node.candidate_ips();
+        if candidates.len() < 2 {
+            return candidates;
+        }
+        if let Some(ranking) = self.rankings.lock().unwrap().get(&node.id) {
+            if ranking.candidates == candidates && ranking.probed_at.elapsed() < RANKING_TTL {
+                return ranking.ranked.clone();
+            }
+        }
+
+        let probes = candidates.iter().map(|ip| async move {
+            let probe = tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(SocketAddr::new(*ip, node.port))).await;
+            matches!(probe, Ok(Ok(_)))
+        });
+        let reachable = join_all(probes).await;
+        let ranked = rank(node, &candidates, &reachable);
+        debug!("Paths to {}: {:?} (reachable: {:?})", node.name, ranked, reachable);
+        self.rankings.lock().unwrap().insert(node.id.clone(), Ranking {
+            candidates,
+            ranked: ranked.clone(),
+            probed_at: Instant::now(),
+        });
+        ranked
+    }
+
+    /// Probe `node_id` again before its next connection
+    pub fn forget(&self, node_id: &str) {
+        self.rankings.lock().unwrap().remove(node_id);
+    }
+}
+
+/// `candidates` of `node` with the `reachable` ones first, by the rank of
+/// their interface type, and the others after in their original order
+fn rank(node: &NodeInfo, candidates: &[IpAddr], reachable: &[bool]) -> Vec<IpAddr> {
+    let mut ranked: Vec<(usize, &IpAddr)> = candidates.iter().enumerate().collect();
+    ranked.sort_by_key(|(index, ip)| {
+        let path_rank = node.address_type(ip).map_or(u8::MAX, |interface_type| interface_type.path_rank());
+        (!reachable[*index], if reachable[*index] { path_rank } else { 0 }, *index)
+    });
+    ranked.into_iter().map(|(_, ip)| *ip).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(port: u16) -> NodeInfo {
+        let types = [("10.0.0.5", "Wifi"), ("169.254.3.4", "Thunderbolt"), ("127.0.0.1", "Ethernet")];
+        NodeInfo {
+            id: "node-a".to_string(),
+            name: "node-a".to_string(),
+            ip: "10.0.0.5".to_string(),
+            port,
+            interface_type: "Wifi".to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: ["10.0.0.5", "169.254.3.4", "192.168.7.7", "127.0.0.1"].map(String::from).to_vec(),
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: types.map(|(ip, t)| (ip.to_string(), t.to_string())).into(),
+            mac_addresses: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_path_ranking() {
+        let node = node(54321);
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        let candidates = [ip("10.0.0.5"), ip("169.254.3.4"), ip("192.168.7.7")];
+        // Thunderbolt before Wi-Fi, then addresses of unknown type, unreachable ones last
+        assert_eq!(rank(&node, &candidates, &[true, true, true]), [ip("169.254.3.4"), ip("10.0.0.5"), ip("192.168.7.7")]);
+        assert_eq!(rank(&node, &candidates, &[true, false, true]), [ip("10.0.0.5"), ip("192.168.7.7"), ip("169.254.3.4")]);
+        assert_eq!(rank(&node, &candidates, &[false, false, false]), candidates);
+    }
+
+    #[tokio::test]
+    async fn test_probed_paths() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut node = node(listener.local_addr().unwrap().port());
+        // The loopback address answers, the documentation one doesn't
+        node.ip = "127.0.0.1".to_string();
+        node.addresses = vec!["127.0.0.1".to_string(), "192.0.2.1".to_string()];
+        node.address_types = [("127.0.0.1", "Wifi"), ("192.0.2.1", "Ethernet")].map(|(ip, t)| (ip.to_string(), t.to_string())).into();
+        let paths = PathSelector::default();
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        assert_eq!(paths.candidates(&node).await, [ip("127.0.0.1"), ip("192.0.2.1")]);
+        assert!(paths.rankings.lock().unwrap().contains_key("node-a"));
+
+        // Probed again once forgotten or when the node's addresses change
+        paths.forget(&node.id);
+        assert!(paths.rankings.lock().unwrap().is_empty());
+        node.addresses.pop();
+        assert_eq!(paths.candidates(&node).await, [ip("127.0.0.1")]);
+    }
+}
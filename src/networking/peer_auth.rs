@@ -0,0 +1,321 @@
+// src/networking/peer_auth.rs
+//
+// Peer authentication
+// Every node holds a persistent Ed25519 keypair and advertises the public key
+// and its fingerprint in discovery. Advertising a key proves nothing, so peers
+// prove they hold the private key: gRPC clients and servers sign each other's
+// challenges before any other call, and file transfer senders sign the
+// challenge of the connection handshake. The first key a node ID proves is
+// pinned, so a host advertising a known node's ID with another key is refused.
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use openssl::pkey::{Id, PKey, Private};
+use openssl::sign::{Signer, Verifier};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+/// What a signature proves, so one made for a purpose can't be replayed for another
+pub const GRPC_SERVER_PROOF: &str = "node-controller grpc server";
+pub const GRPC_CLIENT_PROOF: &str = "node-controller grpc client";
+pub const TRANSFER_PROOF: &str = "node-controller file transfer";
+
+/// gRPC metadata carrying the session a client authenticated
+pub const SESSION_HEADER: &str = "x-node-session";
+
+/// Random bytes in a challenge
+pub const CHALLENGE_LEN: usize = 32;
+
+/// Time a gRPC challenge may be answered in
+const CHALLENGE_TTL: Duration = Duration::from_secs(30);
+
+/// Time a gRPC session is accepted for; clients authenticate again after it
+const SESSION_TTL: Duration = Duration::from_secs(3600);
+
+/// Bytes of the SHA256 of a public key shown as its fingerprint
+const FINGERPRINT_LEN: usize = 16;
+
+/// Ed25519 keypair identifying this node
+#[derive(Clone)]
+pub struct NodeKey {
+    key: PKey<Private>,
+    public_key: Vec<u8>,
+}
+
+impl NodeKey {
+    /// A new random keypair
+    pub fn generate() -> Result<Self> {
+        Self::from_key(PKey::generate_ed25519()?)
+    }
+
+    /// Load the keypair stored at `path`, generating and persisting a new one
+    /// if the file is missing or unreadable
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        if let Ok(pem) = fs::read(path) {
+            match PKey::private_key_from_pem(&pem).map_err(anyhow::Error::from).and_then(Self::from_key) {
+                Ok(key) => return Ok(key),
+                Err(e) => warn!("Ignoring invalid node key in {} ({}), generating a new one", path.display(), e),
+            }
+        }
+
+        let key = Self::generate()?;
+        persist(path, &key.key.private_key_to_pem_pkcs8()?)?;
+        info!("Generated new node key {} ({})", key.fingerprint(), path.display());
+        Ok(key)
+    }
+
+    fn from_key(key: PKey<Private>) -> Result<Self> {
+        if key.id() != Id::ED25519 {
+            return Err(anyhow!("Not an Ed25519 key"));
+        }
+        let public_key = key.raw_public_key()?;
+        Ok(Self { key, public_key })
+    }
+
+    /// Public key, hex encoded as advertised in discovery
+    pub fn public_key(&self) -> String {
+        hex(&self.public_key)
+    }
+
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.public_key())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        Signer::new_without_digest(&self.key)
+            .and_then(|mut signer| signer.sign_oneshot_to_vec(message))
+            .expect("Ed25519 signing does not fail")
+    }
+}
+
+/// Default location of the node key, next to the persisted node ID
+pub fn default_key_path(identity_path: &Path) -> PathBuf {
+    identity_path.with_file_name("node_key.pem")
+}
+
+/// Short form of a hex public key for TXT records and logs
+pub fn fingerprint(public_key: &str) -> String {
+    let raw = unhex(public_key).unwrap_or_else(|| public_key.as_bytes().to_vec());
+    hex(&Sha256::digest(&raw)[..FINGERPRINT_LEN])
+}
+
+/// Whether `signature` is the signature of `message` by the hex `public_key`
+pub fn verify(public_key: &str, message: &[u8], signature: &[u8]) -> bool {
+    let Some(raw) = unhex(public_key) else { return false };
+    PKey::public_key_from_raw_bytes(&raw, Id::ED25519)
+        .and_then(|key| Verifier::new_without_digest(&key)?.verify_oneshot(signature, message))
+        .unwrap_or(false)
+}
+
+/// The message signed to prove `node_id` holds its key when answering `challenge`
+fn proof_message(purpose: &str, challenge: &[u8], node_id: &str) -> Vec<u8> {
+    [purpose.as_bytes(), &[0], challenge, node_id.as_bytes()].concat()
+}
+
+/// This node's key, the keys its peers proved, and the challenges and sessions
+/// of the gRPC authentication
+#[derive(Clone)]
+pub struct PeerAuth {
+    node_id: String,
+    key: NodeKey,
+    required: bool,
+    /// Key pinned for each node ID, saved to `known_peers_file` when set
+    known_peers: Arc<StdMutex<HashMap<String, String>>>,
+    known_peers_file: Option<PathBuf>,
+    challenges: Arc<StdMutex<HashMap<Vec<u8>, Instant>>>,
+    /// Node ID each session was issued to
+    sessions: Arc<StdMutex<HashMap<String, (String, Instant)>>>,
+}
+
+impl PeerAuth {
+    /// Authenticate as `node_id` with `key`. Peers that don't prove a key are
+    /// still served unless `required` is set.
+    pub fn new(node_id: &str, key: NodeKey, required: bool) -> Self {
+        Self {
+            node_id: node_id.to_string(),
+            key,
+            required,
+            known_peers: Arc::new(StdMutex::new(HashMap::new())),
+            known_peers_file: None,
+            challenges: Arc::new(StdMutex::new(HashMap::new())),
+            sessions: Arc::new(StdMutex::new(HashMap::new())),
+        }
+    }
+
+    /// Keep the pinned keys in `path` (a JSON object of node IDs to keys), so
+    /// they survive restarts
+    pub fn with_known_peers(mut self, path: &Path) -> Self {
+        if let Ok(data) = fs::read_to_string(path) {
+            match serde_json::from_str(&data) {
+                Ok(known) => self.known_peers = Arc::new(StdMutex::new(known)),
+                Err(e) => warn!("Ignoring unreadable known peers file {}: {}", path.display(), e),
+            }
+        }
+        self.known_peers_file = Some(path.to_path_buf());
+        self
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub fn key(&self) -> &NodeKey {
+        &self.key
+    }
+
+    /// Whether peers must prove a key to be served
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// Our answer to `challenge` for `purpose`
+    pub fn prove(&self, purpose: &str, challenge: &[u8]) -> Vec<u8> {
+        self.key.sign(&proof_message(purpose, challenge, &self.node_id))
+    }
+
+    /// Check that `node_id` answered `challenge` with the key `public_key`, and
+    /// that it is the key pinned for the node. The first key a node proves is pinned.
+    pub fn check_proof(&self, purpose: &str, node_id: &str, public_key: &str, challenge: &[u8], signature: &[u8]) -> Result<()> {
+        if !verify(public_key, &proof_message(purpose, challenge, node_id), signature) {
+            return Err(anyhow!("Node {:?} failed to prove it holds key {}", node_id, fingerprint(public_key)));
+        }
+
+        let mut known_peers = self.known_peers.lock().unwrap();
+        match known_peers.get(node_id) {
+            Some(pinned) if pinned == public_key => return Ok(()),
+            Some(pinned) => {
+                return Err(anyhow!(
+                    "Node {} presented key {} but key {} is pinned for it",
+                    node_id, fingerprint(public_key), fingerprint(pinned)
+                ));
+            }
+            None => {}
+        }
+        info!("Pinned key {} for node {}", fingerprint(public_key), node_id);
+        known_peers.insert(node_id.to_string(), public_key.to_string());
+        if let Some(path) = &self.known_peers_file {
+            let saved = serde_json::to_vec_pretty(&*known_peers)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| persist(path, &data));
+            if let Err(e) = saved {
+                warn!("Failed to save known peers to {}: {:#}", path.display(), e);
+            }
+        }
+        Ok(())
+    }
+
+    /// A fresh challenge for a gRPC client to sign
+    pub fn issue_challenge(&self) -> Vec<u8> {
+        let challenge = rand::random::<[u8; CHALLENGE_LEN]>().to_vec();
+        let mut challenges = self.challenges.lock().unwrap();
+        challenges.retain(|_, issued| issued.elapsed() < CHALLENGE_TTL);
+        challenges.insert(challenge.clone(), Instant::now());
+        challenge
+    }
+
+    /// Check a client's answer to a challenge we issued, and open a session for it
+    pub fn authenticate(&self, node_id: &str, public_key: &str, challenge: &[u8], signature: &[u8]) -> Result<String> {
+        let issued = self.challenges.lock().unwrap().remove(challenge);
+        if issued.is_none_or(|issued| issued.elapsed() >= CHALLENGE_TTL) {
+            return Err(anyhow!("Unknown or expired challenge"));
+        }
+        self.check_proof(GRPC_CLIENT_PROOF, node_id, public_key, challenge, signature)?;
+
+        let session = hex(&rand::random::<[u8; 32]>());
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, (_, opened)| opened.elapsed() < SESSION_TTL);
+        sessions.insert(session.clone(), (node_id.to_string(), Instant::now()));
+        Ok(session)
+    }
+
+    /// The node ID `session` was opened for, if it is still valid
+    pub fn session_peer(&self, session: &str) -> Option<String> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(session)
+            .filter(|(_, opened)| opened.elapsed() < SESSION_TTL)
+            .map(|(node_id, _)| node_id.clone())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// Write via a temporary file readable by the owner only
+fn persist(path: &Path, data: &[u8]) -> Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::io::Write;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&tmp)
+        .and_then(|mut file| file.write_all(data))
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to persist {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_is_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = default_key_path(&dir.path().join("state/node_id"));
+        let first = NodeKey::load_or_create(&path).unwrap();
+        let second = NodeKey::load_or_create(&path).unwrap();
+        assert_eq!(first.public_key(), second.public_key());
+        assert_eq!(first.public_key().len(), 64);
+        assert_eq!(first.fingerprint().len(), 2 * FINGERPRINT_LEN);
+
+        let signature = first.sign(b"message");
+        assert!(verify(&second.public_key(), b"message", &signature));
+        assert!(!verify(&second.public_key(), b"other message", &signature));
+        assert!(!verify("not hex", b"message", &signature));
+    }
+
+    #[test]
+    fn test_proofs_and_pinning() {
+        let dir = tempfile::tempdir().unwrap();
+        let known_peers = dir.path().join("known_peers.json");
+        let server = PeerAuth::new("node-a", NodeKey::generate().unwrap(), true).with_known_peers(&known_peers);
+        let client = PeerAuth::new("node-b", NodeKey::generate().unwrap(), true);
+        let impostor = PeerAuth::new("node-b", NodeKey::generate().unwrap(), true);
+
+        let challenge = server.issue_challenge();
+        let session = server.authenticate("node-b", &client.key().public_key(), &challenge, &client.prove(GRPC_CLIENT_PROOF, &challenge)).unwrap();
+        assert_eq!(server.session_peer(&session).as_deref(), Some("node-b"));
+        assert!(server.session_peer("forged").is_none());
+
+        // Challenges are single use
+        let replayed = server.authenticate("node-b", &client.key().public_key(), &challenge, &client.prove(GRPC_CLIENT_PROOF, &challenge));
+        assert!(replayed.is_err());
+
+        // A proof for another purpose or node ID is not accepted
+        let challenge = server.issue_challenge();
+        let transfer_proof = client.prove(TRANSFER_PROOF, &challenge);
+        assert!(server.authenticate("node-b", &client.key().public_key(), &challenge, &transfer_proof).is_err());
+        assert!(server.check_proof(TRANSFER_PROOF, "node-c", &client.key().public_key(), &challenge, &transfer_proof).is_err());
+
+        // Another key for a pinned node ID is refused, also after a restart
+        let proof = impostor.prove(TRANSFER_PROOF, &challenge);
+        let err = server.check_proof(TRANSFER_PROOF, "node-b", &impostor.key().public_key(), &challenge, &proof).unwrap_err();
+        assert!(err.to_string().contains("is pinned"));
+        let restarted = PeerAuth::new("node-a", NodeKey::generate().unwrap(), true).with_known_peers(&known_peers);
+        assert!(restarted.check_proof(TRANSFER_PROOF, "node-b", &impostor.key().public_key(), &challenge, &proof).is_err());
+    }
+}
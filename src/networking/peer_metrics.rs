@@ -0,0 +1,164 @@
+// src/networking/peer_metrics.rs
+//
+// Metrics summaries between nodes
+// A node keeps a compact snapshot of its latest metrics (CPU load, memory,
+// free disk and network rates), updated as its collectors run, and serves it
+// with the GetMetricsSummary RPC. Peers use it for local scheduling decisions
+// without a round trip through the monitoring API.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::Path;
+
+use super::communication::node::MetricsSummaryResponse;
+use crate::metrics::cpu::types::CpuMetrics;
+use crate::metrics::network::types::NetworkSnapshot;
+use crate::metrics::storage::types::StorageMetrics;
+use crate::metrics::system::types::SystemInfo;
+
+/// Latest resource usage of a node; fields are None until their collector
+/// has run
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MetricsSummary {
+    pub cpu_load_percent: Option<f64>,
+    pub load_average_1m: Option<f64>,
+    pub free_memory_bytes: Option<u64>,
+    pub total_memory_bytes: Option<u64>,
+    /// Filesystem holding the agent's data directory
+    pub free_disk_bytes: Option<u64>,
+    pub total_disk_bytes: Option<u64>,
+    /// Summed over all interfaces
+    pub rx_bytes_per_sec: Option<f64>,
+    pub tx_bytes_per_sec: Option<f64>,
+    /// When any field was last updated
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl MetricsSummary {
+    pub fn record_cpu(&mut self, cpu: &CpuMetrics) {
+        self.cpu_load_percent = Some(cpu.current_load);
+        self.updated_at = Some(cpu.collected_at);
+    }
+
+    pub fn record_system(&mut self, system: &SystemInfo) {
+        self.load_average_1m = Some(system.platform.load_average.0);
+        self.free_memory_bytes = Some(system.platform.available_memory);
+        self.total_memory_bytes = Some(system.platform.total_memory);
+        self.updated_at = Some(Utc::now());
+    }
+
+    pub fn record_network(&mut self, network: &NetworkSnapshot) {
+        self.rx_bytes_per_sec = Some(network.interfaces.iter().map(|i| i.rx_bytes_per_sec).sum());
+        self.tx_bytes_per_sec = Some(network.interfaces.iter().map(|i| i.tx_bytes_per_sec).sum());
+        self.updated_at = Some(Utc::now());
+    }
+
+    /// Record the space of the filesystem `data_dir` is on, the one with the
+    /// longest mount point containing it
+    pub fn record_storage(&mut self, storage: &StorageMetrics, data_dir: &Path) {
+        let filesystem = storage.filesystem_metrics.iter()
+            .filter(|fs| data_dir.starts_with(&fs.mount))
+            .max_by_key(|fs| fs.mount.len());
+        if let Some(fs) = filesystem {
+            self.free_disk_bytes = Some(fs.available);
+            self.total_disk_bytes = Some(fs.size);
+            self.updated_at = Some(storage.collected_at);
+        }
+    }
+
+    pub fn to_response(&self, responder_id: String) -> MetricsSummaryResponse {
+        MetricsSummaryResponse {
+            responder_id,
+            cpu_load_percent: self.cpu_load_percent,
+            load_average_1m: self.load_average_1m,
+            free_memory_bytes: self.free_memory_bytes,
+            total_memory_bytes: self.total_memory_bytes,
+            free_disk_bytes: self.free_disk_bytes,
+            total_disk_bytes: self.total_disk_bytes,
+            rx_bytes_per_sec: self.rx_bytes_per_sec,
+            tx_bytes_per_sec: self.tx_bytes_per_sec,
+            updated_at_ms: self.updated_at.map_or(0, |at| at.timestamp_millis()),
+        }
+    }
+}
+
+impl From<MetricsSummaryResponse> for MetricsSummary {
+    fn from(response: MetricsSummaryResponse) -> Self {
+        Self {
+            cpu_load_percent: response.cpu_load_percent,
+            load_average_1m: response.load_average_1m,
+            free_memory_bytes: response.free_memory_bytes,
+            total_memory_bytes: response.total_memory_bytes,
+            free_disk_bytes: response.free_disk_bytes,
+            total_disk_bytes: response.total_disk_bytes,
+            rx_bytes_per_sec: response.rx_bytes_per_sec,
+            tx_bytes_per_sec: response.tx_bytes_per_sec,
+            updated_at: DateTime::from_timestamp_millis(response.updated_at_ms).filter(|_| response.updated_at_ms > 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::storage::types::{FilesystemMetric, IoMetrics};
+    use crate::networking::communication::node::node_service_server::NodeService;
+    use crate::networking::communication::node::MetricsSummaryRequest;
+    use crate::networking::NodeCommunicationService;
+    use tokio::sync::watch;
+    use tonic::Request;
+
+    fn filesystem(mount: &str, size: u64, available: u64) -> FilesystemMetric {
+        FilesystemMetric {
+            fs: format!("/dev/{}", mount.len()),
+            mount: mount.to_string(),
+            fs_type: "apfs".to_string(),
+            size,
+            used: size - available,
+            available,
+            used_percent: 0.0,
+            encrypted: false,
+            encryption_type: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metrics_summary() {
+        let storage = StorageMetrics {
+            node_id: "node-1".to_string(),
+            collected_at: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            filesystem_metrics: vec![filesystem("/", 500, 100), filesystem("/System/Volumes/Data", 2000, 700)],
+            io_metrics: IoMetrics {
+                total_read: 0,
+                total_write: 0,
+                read_bytes_per_sec: 0.0,
+                write_bytes_per_sec: 0.0,
+                read_rate_human: String::new(),
+                write_rate_human: String::new(),
+                devices: vec![],
+            },
+            backup: None,
+            mount_events: vec![],
+        };
+        let mut summary = MetricsSummary::default();
+        summary.record_storage(&storage, Path::new("/System/Volumes/Data/Users/admin"));
+        assert_eq!((summary.free_disk_bytes, summary.total_disk_bytes), (Some(700), Some(2000)));
+        summary.record_storage(&storage, Path::new("/opt/node"));
+        assert_eq!(summary.free_disk_bytes, Some(100));
+
+        let request = || Request::new(MetricsSummaryRequest { sender_id: "node-2".to_string() });
+        let service = NodeCommunicationService::new("node-1".to_string(), "node".to_string());
+        assert_eq!(service.get_metrics_summary(request()).await.unwrap_err().code(), tonic::Code::Unimplemented);
+
+        let (metrics, receiver) = watch::channel(MetricsSummary::default());
+        let service = service.with_metrics(receiver);
+        let empty = service.get_metrics_summary(request()).await.unwrap().into_inner();
+        assert_eq!((empty.free_disk_bytes, empty.updated_at_ms), (None, 0));
+        assert_eq!(MetricsSummary::from(empty), MetricsSummary::default());
+
+        metrics.send_replace(summary.clone());
+        let response = service.get_metrics_summary(request()).await.unwrap().into_inner();
+        assert_eq!(response.responder_id, "node-1");
+        assert_eq!(MetricsSummary::from(response), summary);
+    }
+}
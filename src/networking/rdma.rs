@@ -0,0 +1,527 @@
+// src/networking/rdma.rs
+//
+// RDMA transfers
+// Between two nodes that both advertise the `rdma` capability, file ranges
+// are written straight into the receiver's memory over a reliable-connected
+// (RC) queue pair instead of through the TCP stream. The transfer connection
+// still carries the handshake, the range header, the digest and every
+// acknowledgement: after the header the sender offers the endpoint of its
+// queue pair, the receiver answers with its own and the address and key of a
+// registered buffer, and then for each chunk the sender RDMA-writes into that
+// buffer and announces the length, and the receiver acknowledges once it has
+// taken the chunk out. Either side may decline, and the range then goes over
+// the same TCP connection. Only Linux builds with the `rdma` feature use
+// ibverbs; every other build declines.
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use std::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Whether this build can transfer over RDMA
+pub const SUPPORTED: bool = cfg!(all(target_os = "linux", feature = "rdma"));
+
+const DECLINED: u8 = 0;
+const OFFERED: u8 = 1;
+const CHUNK_TAKEN: u8 = 0;
+
+/// Where the other side of a queue pair reaches this one, swapped over the
+/// transfer connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Endpoint {
+    /// Port LID; 0 on RoCE, where the GID routes instead
+    pub lid: u16,
+    pub qp_num: u32,
+    /// First packet sequence number
+    pub psn: u32,
+    pub gid: [u8; 16],
+    /// Registered buffer the other side may write into, and its key
+    pub addr: u64,
+    pub rkey: u32,
+    pub len: u32,
+}
+
+impl Endpoint {
+    const LEN: usize = 2 + 4 + 4 + 16 + 8 + 4 + 4;
+
+    fn to_bytes(self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[0..2].copy_from_slice(&self.lid.to_be_bytes());
+        bytes[2..6].copy_from_slice(&self.qp_num.to_be_bytes());
+        bytes[6..10].copy_from_slice(&self.psn.to_be_bytes());
+        bytes[10..26].copy_from_slice(&self.gid);
+        bytes[26..34].copy_from_slice(&self.addr.to_be_bytes());
+        bytes[34..38].copy_from_slice(&self.rkey.to_be_bytes());
+        bytes[38..42].copy_from_slice(&self.len.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; Self::LEN]) -> Self {
+        Self {
+            lid: u16::from_be_bytes([bytes[0], bytes[1]]),
+            qp_num: u32::from_be_bytes(bytes[2..6].try_into().unwrap()),
+            psn: u32::from_be_bytes(bytes[6..10].try_into().unwrap()),
+            gid: bytes[10..26].try_into().unwrap(),
+            addr: u64::from_be_bytes(bytes[26..34].try_into().unwrap()),
+            rkey: u32::from_be_bytes(bytes[34..38].try_into().unwrap()),
+            len: u32::from_be_bytes(bytes[38..42].try_into().unwrap()),
+        }
+    }
+
+    async fn write(&self, socket: &mut TcpStream) -> Result<()> {
+        socket.write_all(&self.to_bytes()).await?;
+        Ok(())
+    }
+
+    async fn read(socket: &mut TcpStream) -> Result<Self> {
+        let mut bytes = [0u8; Self::LEN];
+        socket.read_exact(&mut bytes).await?;
+        Ok(Self::from_bytes(&bytes))
+    }
+}
+
+/// Names of the RDMA devices of this host
+pub fn devices() -> Result<Vec<String>> {
+    imp::devices()
+}
+
+/// Sending end of a range over RDMA
+pub struct RdmaSender {
+    link: imp::Link,
+    remote: Endpoint,
+}
+
+impl RdmaSender {
+    /// Offer to send the range over RDMA in chunks of up to `chunk_size`
+    /// bytes; None when either side declines
+    pub async fn offer(socket: &mut TcpStream, chunk_size: usize) -> Result<Option<Self>> {
+        let mut link = match imp::Link::open(chunk_size, false) {
+            Ok(link) => link,
+            Err(e) => {
+                debug!("Not offering RDMA: {}", e);
+                socket.write_all(&[DECLINED]).await?;
+                return Ok(None);
+            }
+        };
+        socket.write_all(&[OFFERED]).await?;
+        link.endpoint().write(socket).await?;
+
+        let mut answer = [0u8; 1];
+        socket.read_exact(&mut answer).await?;
+        if answer[0] != OFFERED {
+            debug!("Receiver declined RDMA");
+            return Ok(None);
+        }
+        let remote = Endpoint::read(socket).await?;
+        link.connect(&remote)?;
+        Ok(Some(Self { link, remote }))
+    }
+
+    /// Write up to `len` bytes of `file` from `offset` into the receiver's
+    /// buffer and wait until it has taken them; returns the bytes sent, 0 at
+    /// the end of the file
+    pub async fn send_chunk(&mut self, socket: &mut TcpStream, file: &File, offset: u64, len: usize) -> Result<usize> {
+        let len = len.min(self.remote.len as usize);
+        let n = self.link.read_file(file, offset, len)?;
+        if n == 0 {
+            return Ok(0);
+        }
+        self.link.write_remote(&self.remote, n).await?;
+
+        socket.write_all(&(n as u32).to_be_bytes()).await?;
+        let mut status = [0u8; 1];
+        socket.read_exact(&mut status).await?;
+        if status[0] != CHUNK_TAKEN {
+            return Err(anyhow!("Receiver did not take the RDMA chunk"));
+        }
+        Ok(n)
+    }
+}
+
+/// Receiving end of a range over RDMA
+pub struct RdmaReceiver {
+    link: imp::Link,
+}
+
+impl RdmaReceiver {
+    /// Answer the sender's offer with a buffer of `chunk_size` bytes; None
+    /// when either side declines
+    pub async fn accept(socket: &mut TcpStream, chunk_size: usize) -> Result<Option<Self>> {
+        let mut offer = [0u8; 1];
+        socket.read_exact(&mut offer).await?;
+        if offer[0] != OFFERED {
+            return Ok(None);
+        }
+        let remote = Endpoint::read(socket).await?;
+
+        let mut link = match imp::Link::open(chunk_size, true) {
+            Ok(link) => link,
+            Err(e) => {
+                debug!("Declining RDMA: {}", e);
+                socket.write_all(&[DECLINED]).await?;
+                return Ok(None);
+            }
+        };
+        // Ready to receive before the sender learns where to write
+        link.connect(&remote)?;
+        socket.write_all(&[OFFERED]).await?;
+        link.endpoint().write(socket).await?;
+        Ok(Some(Self { link }))
+    }
+
+    /// Wait for the sender's next chunk and copy it into `buffer`; returns
+    /// its length, 0 when the sender closed the connection
+    pub async fn receive_chunk(&mut self, socket: &mut TcpStream, buffer: &mut [u8]) -> Result<usize> {
+        let mut len = [0u8; 4];
+        match socket.read_exact(&mut len).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(0),
+            Err(e) => return Err(e.into()),
+        }
+        let n = u32::from_be_bytes(len) as usize;
+        if n > buffer.len() || n > self.link.buffer().len() {
+            return Err(anyhow!("RDMA chunk of {} bytes exceeds the {} byte buffer", n, buffer.len()));
+        }
+        buffer[..n].copy_from_slice(&self.link.buffer()[..n]);
+        socket.write_all(&[CHUNK_TAKEN]).await?;
+        Ok(n)
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "rdma"))]
+mod imp {
+    use anyhow::{anyhow, Result};
+    use rdma_sys::*;
+    use std::ffi::CStr;
+    use std::fs::File;
+    use std::os::unix::fs::FileExt;
+    use std::{mem, ptr};
+
+    use super::Endpoint;
+
+    /// Physical port of the device used
+    const PORT: u8 = 1;
+    /// GID table entry used on RoCE ports
+    const GID_INDEX: i32 = 0;
+
+    pub fn devices() -> Result<Vec<String>> {
+        let mut devices = Vec::new();
+        unsafe {
+            let mut count = 0;
+            let list = ibv_get_device_list(&mut count);
+            if list.is_null() {
+                return Err(anyhow!("Failed to list RDMA devices"));
+            }
+            for i in 0..count as usize {
+                let device = *list.add(i);
+                let name = ibv_get_device_name(device);
+                if !name.is_null() {
+                    devices.push(CStr::from_ptr(name).to_string_lossy().into_owned());
+                }
+            }
+            ibv_free_device_list(list);
+        }
+        Ok(devices)
+    }
+
+    /// An RC queue pair on the first RDMA device with one registered buffer
+    pub struct Link {
+        context: *mut ibv_context,
+        pd: *mut ibv_pd,
+        cq: *mut ibv_cq,
+        qp: *mut ibv_qp,
+        mr: *mut ibv_mr,
+        buffer: Vec<u8>,
+        port: ibv_port_attr,
+        gid: ibv_gid,
+        psn: u32,
+    }
+
+    // The verbs objects are only used through `&mut self`
+    unsafe impl Send for Link {}
+
+    impl Link {
+        /// Open the first device and register a buffer of `len` bytes, which
+        /// the other side may write into when `remote_write`
+        pub fn open(len: usize, remote_write: bool) -> Result<Self> {
+            let mut link = Link {
+                context: ptr::null_mut(),
+                pd: ptr::null_mut(),
+                cq: ptr::null_mut(),
+                qp: ptr::null_mut(),
+                mr: ptr::null_mut(),
+                buffer: vec![0u8; len],
+                port: unsafe { mem::zeroed() },
+                gid: unsafe { mem::zeroed() },
+                psn: rand::random::<u32>() & 0xff_ffff,
+            };
+            unsafe {
+                let mut count = 0;
+                let list = ibv_get_device_list(&mut count);
+                if list.is_null() || count == 0 {
+                    if !list.is_null() {
+                        ibv_free_device_list(list);
+                    }
+                    return Err(anyhow!("No RDMA device"));
+                }
+                link.context = ibv_open_device(*list);
+                ibv_free_device_list(list);
+                if link.context.is_null() {
+                    return Err(anyhow!("Failed to open the RDMA device"));
+                }
+                if ibv_query_port(link.context, PORT, &mut link.port) != 0 {
+                    return Err(anyhow!("Failed to query RDMA port {}", PORT));
+                }
+                if ibv_query_gid(link.context, PORT, GID_INDEX, &mut link.gid) != 0 {
+                    return Err(anyhow!("Failed to query the GID of RDMA port {}", PORT));
+                }
+
+                link.pd = ibv_alloc_pd(link.context);
+                if link.pd.is_null() {
+                    return Err(anyhow!("Failed to allocate an RDMA protection domain"));
+                }
+                let mut access = ibv_access_flags::IBV_ACCESS_LOCAL_WRITE;
+                if remote_write {
+                    access |= ibv_access_flags::IBV_ACCESS_REMOTE_WRITE;
+                }
+                link.mr = ibv_reg_mr(link.pd, link.buffer.as_mut_ptr().cast(), len, access as i32);
+                if link.mr.is_null() {
+                    return Err(anyhow!("Failed to register a {} byte RDMA buffer", len));
+                }
+                link.cq = ibv_create_cq(link.context, 16, ptr::null_mut(), ptr::null_mut(), 0);
+                if link.cq.is_null() {
+                    return Err(anyhow!("Failed to create an RDMA completion queue"));
+                }
+
+                let mut init: ibv_qp_init_attr = mem::zeroed();
+                init.send_cq = link.cq;
+                init.recv_cq = link.cq;
+                init.qp_type = ibv_qp_type::IBV_QPT_RC;
+                init.cap.max_send_wr = 1;
+                init.cap.max_recv_wr = 1;
+                init.cap.max_send_sge = 1;
+                init.cap.max_recv_sge = 1;
+                link.qp = ibv_create_qp(link.pd, &mut init);
+                if link.qp.is_null() {
+                    return Err(anyhow!("Failed to create an RDMA queue pair"));
+                }
+
+                let mut attr: ibv_qp_attr = mem::zeroed();
+                attr.qp_state = ibv_qp_state::IBV_QPS_INIT;
+                attr.pkey_index = 0;
+                attr.port_num = PORT;
+                attr.qp_access_flags = access;
+                let mask = ibv_qp_attr_mask::IBV_QP_STATE
+                    | ibv_qp_attr_mask::IBV_QP_PKEY_INDEX
+                    | ibv_qp_attr_mask::IBV_QP_PORT
+                    | ibv_qp_attr_mask::IBV_QP_ACCESS_FLAGS;
+                if ibv_modify_qp(link.qp, &mut attr, mask as i32) != 0 {
+                    return Err(anyhow!("Failed to initialize the RDMA queue pair"));
+                }
+            }
+            Ok(link)
+        }
+
+        pub fn endpoint(&self) -> Endpoint {
+            unsafe {
+                Endpoint {
+                    lid: self.port.lid,
+                    qp_num: (*self.qp).qp_num,
+                    psn: self.psn,
+                    gid: self.gid.raw,
+                    addr: self.buffer.as_ptr() as u64,
+                    rkey: (*self.mr).rkey,
+                    len: self.buffer.len() as u32,
+                }
+            }
+        }
+
+        /// Move the queue pair to ready-to-send, connected to `remote`
+        pub fn connect(&mut self, remote: &Endpoint) -> Result<()> {
+            unsafe {
+                let mut attr: ibv_qp_attr = mem::zeroed();
+                attr.qp_state = ibv_qp_state::IBV_QPS_RTR;
+                attr.path_mtu = self.port.active_mtu;
+                attr.dest_qp_num = remote.qp_num;
+                attr.rq_psn = remote.psn;
+                attr.max_dest_rd_atomic = 1;
+                attr.min_rnr_timer = 12;
+                attr.ah_attr.dlid = remote.lid;
+                attr.ah_attr.port_num = PORT;
+                if remote.lid == 0 {
+                    attr.ah_attr.is_global = 1;
+                    attr.ah_attr.grh.dgid.raw = remote.gid;
+                    attr.ah_attr.grh.sgid_index = GID_INDEX as u8;
+                    attr.ah_attr.grh.hop_limit = 1;
+                }
+                let mask = ibv_qp_attr_mask::IBV_QP_STATE
+                    | ibv_qp_attr_mask::IBV_QP_AV
+                    | ibv_qp_attr_mask::IBV_QP_PATH_MTU
+                    | ibv_qp_attr_mask::IBV_QP_DEST_QPN
+                    | ibv_qp_attr_mask::IBV_QP_RQ_PSN
+                    | ibv_qp_attr_mask::IBV_QP_MAX_DEST_RD_ATOMIC
+                    | ibv_qp_attr_mask::IBV_QP_MIN_RNR_TIMER;
+                if ibv_modify_qp(self.qp, &mut attr, mask as i32) != 0 {
+                    return Err(anyhow!("Failed to move the RDMA queue pair to ready-to-receive"));
+                }
+
+                let mut attr: ibv_qp_attr = mem::zeroed();
+                attr.qp_state = ibv_qp_state::IBV_QPS_RTS;
+                attr.sq_psn = self.psn;
+                attr.timeout = 14;
+                attr.retry_cnt = 7;
+                attr.rnr_retry = 7;
+                attr.max_rd_atomic = 1;
+                let mask = ibv_qp_attr_mask::IBV_QP_STATE
+                    | ibv_qp_attr_mask::IBV_QP_SQ_PSN
+                    | ibv_qp_attr_mask::IBV_QP_TIMEOUT
+                    | ibv_qp_attr_mask::IBV_QP_RETRY_CNT
+                    | ibv_qp_attr_mask::IBV_QP_RNR_RETRY
+                    | ibv_qp_attr_mask::IBV_QP_MAX_QP_RD_ATOMIC;
+                if ibv_modify_qp(self.qp, &mut attr, mask as i32) != 0 {
+                    return Err(anyhow!("Failed to move the RDMA queue pair to ready-to-send"));
+                }
+            }
+            Ok(())
+        }
+
+        pub fn buffer(&self) -> &[u8] {
+            &self.buffer
+        }
+
+        /// Read up to `len` bytes of `file` from `offset` into the buffer
+        pub fn read_file(&mut self, file: &File, offset: u64, len: usize) -> Result<usize> {
+            Ok(file.read_at(&mut self.buffer[..len], offset)?)
+        }
+
+        /// Write the first `len` bytes of the buffer into `remote`'s and wait
+        /// for the completion
+        pub async fn write_remote(&mut self, remote: &Endpoint, len: usize) -> Result<()> {
+            unsafe {
+                let mut sge = ibv_sge {
+                    addr: self.buffer.as_ptr() as u64,
+                    length: len as u32,
+                    lkey: (*self.mr).lkey,
+                };
+                let mut wr: ibv_send_wr = mem::zeroed();
+                wr.sg_list = &mut sge;
+                wr.num_sge = 1;
+                wr.opcode = ibv_wr_opcode::IBV_WR_RDMA_WRITE;
+                wr.send_flags = ibv_send_flags::IBV_SEND_SIGNALED;
+                wr.wr.rdma.remote_addr = remote.addr;
+                wr.wr.rdma.rkey = remote.rkey;
+                let mut bad_wr = ptr::null_mut();
+                if ibv_post_send(self.qp, &mut wr, &mut bad_wr) != 0 {
+                    return Err(anyhow!("Failed to post an RDMA write"));
+                }
+            }
+            loop {
+                let mut wc: ibv_wc = unsafe { mem::zeroed() };
+                match unsafe { ibv_poll_cq(self.cq, 1, &mut wc) } {
+                    0 => tokio::task::yield_now().await,
+                    1 if wc.status == ibv_wc_status::IBV_WC_SUCCESS => return Ok(()),
+                    1 => return Err(anyhow!("RDMA write failed with status {}", wc.status)),
+                    _ => return Err(anyhow!("Failed to poll the RDMA completion queue")),
+                }
+            }
+        }
+    }
+
+    impl Drop for Link {
+        fn drop(&mut self) {
+            unsafe {
+                if !self.qp.is_null() {
+                    ibv_destroy_qp(self.qp);
+                }
+                if !self.cq.is_null() {
+                    ibv_destroy_cq(self.cq);
+                }
+                if !self.mr.is_null() {
+                    ibv_dereg_mr(self.mr);
+                }
+                if !self.pd.is_null() {
+                    ibv_dealloc_pd(self.pd);
+                }
+                if !self.context.is_null() {
+                    ibv_close_device(self.context);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "rdma")))]
+mod imp {
+    use anyhow::{anyhow, Result};
+    use std::fs::File;
+
+    use super::Endpoint;
+
+    pub fn devices() -> Result<Vec<String>> {
+        Err(anyhow!("RDMA support is not built in"))
+    }
+
+    pub enum Link {}
+
+    impl Link {
+        pub fn open(_len: usize, _remote_write: bool) -> Result<Self> {
+            Err(anyhow!("RDMA support is not built in"))
+        }
+
+        pub fn endpoint(&self) -> Endpoint {
+            match *self {}
+        }
+
+        pub fn connect(&mut self, _remote: &Endpoint) -> Result<()> {
+            match *self {}
+        }
+
+        pub fn buffer(&self) -> &[u8] {
+            match *self {}
+        }
+
+        pub fn read_file(&mut self, _file: &File, _offset: u64, _len: usize) -> Result<usize> {
+            match *self {}
+        }
+
+        pub async fn write_remote(&mut self, _remote: &Endpoint, _len: usize) -> Result<()> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_bytes() {
+        let endpoint = Endpoint {
+            lid: 7,
+            qp_num: 0x1234,
+            psn: 0xabcdef,
+            gid: [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8],
+            addr: 0x7f00_dead_beef,
+            rkey: 42,
+            len: 1024 * 1024,
+        };
+        assert_eq!(Endpoint::from_bytes(&endpoint.to_bytes()), endpoint);
+    }
+
+    #[tokio::test]
+    async fn test_declined_without_devices() {
+        if SUPPORTED && devices().is_ok_and(|devices| !devices.is_empty()) {
+            return;
+        }
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let receiver = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            RdmaReceiver::accept(&mut socket, 4096).await.unwrap().is_none()
+        });
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        assert!(RdmaSender::offer(&mut socket, 4096).await.unwrap().is_none());
+        assert!(receiver.await.unwrap());
+    }
+}
@@ -0,0 +1,308 @@
+// src/networking/receive_policy.rs
+//
+// Receive policies
+// Before the first byte of an incoming file is written the receiver checks it
+// against its policy: a size cap, allowed extensions, an optional accept
+// callback and a quota on the receive directory. When the quota is full the
+// file is rejected, or the oldest received files make room for it. Directory
+// transfers are checked file by file.
+
+use anyhow::{anyhow, Result};
+use log::info;
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use super::content_store::{INDEX_FILE, LINK_SUFFIX};
+
+/// How long a decision is kept for ranges of the same file still arriving
+const DECISION_TTL: Duration = Duration::from_secs(600);
+
+/// Bookkeeping files of transfers in progress, never counted or evicted
+const BOOKKEEPING_SUFFIXES: [&str; 5] = [".parts", ".hash", ".manifest", ".sync", LINK_SUFFIX];
+
+/// A file about to be received
+#[derive(Debug, Clone)]
+pub struct IncomingFile {
+    /// Path relative to the receive directory
+    pub file_name: String,
+    pub file_size: u64,
+}
+
+/// Decides whether an incoming file is accepted; may block, e.g. to ask a user
+pub type AcceptCallback = Arc<dyn Fn(&IncomingFile) -> bool + Send + Sync>;
+
+/// What to do with a file that doesn't fit in the quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhenFull {
+    #[default]
+    Reject,
+    /// Delete the least recently received files until it fits
+    EvictOldest,
+}
+
+/// Limits on what a node receives; everything is accepted by default
+#[derive(Clone, Default)]
+pub struct ReceivePolicy {
+    /// Largest file accepted, in bytes
+    pub max_file_size: Option<u64>,
+    /// Accepted file extensions without the dot, compared case-insensitively
+    pub allowed_extensions: Option<Vec<String>>,
+    /// Bytes the receive directory may hold
+    pub quota: Option<u64>,
+    pub when_full: WhenFull,
+    pub accept_callback: Option<AcceptCallback>,
+}
+
+impl ReceivePolicy {
+    /// Check `file` against the policy, evicting files from `receive_dir` if
+    /// the policy allows it. Files in `in_progress` are never evicted.
+    pub fn check(&self, receive_dir: &Path, file: &IncomingFile, in_progress: &[PathBuf]) -> Result<()> {
+        if let Some(max) = self.max_file_size.filter(|max| file.file_size > *max) {
+            return Err(anyhow!("{} is larger than the {} byte limit", file.file_name, max));
+        }
+        if let Some(allowed) = &self.allowed_extensions {
+            let extension = Path::new(&file.file_name).extension().map(|e| e.to_string_lossy().to_lowercase());
+            if !extension.is_some_and(|extension| allowed.iter().any(|a| a.trim_start_matches('.').eq_ignore_ascii_case(&extension))) {
+                return Err(anyhow!("Files like {} are not accepted", file.file_name));
+            }
+        }
+        if let Some(callback) = &self.accept_callback {
+            if !callback(file) {
+                return Err(anyhow!("{} was declined", file.file_name));
+            }
+        }
+        if let Some(quota) = self.quota {
+            self.make_room(receive_dir, file, quota, in_progress)?;
+        }
+        Ok(())
+    }
+
+    fn make_room(&self, receive_dir: &Path, file: &IncomingFile, quota: u64, in_progress: &[PathBuf]) -> Result<()> {
+        if file.file_size > quota {
+            return Err(anyhow!("{} is larger than the {} byte receive quota", file.file_name, quota));
+        }
+        let target = receive_dir.join(&file.file_name);
+        let mut received = received_files(receive_dir);
+        // A copy being replaced frees its space, unless other names share it
+        let used: u64 = received.iter().filter(|f| f.paths != [target.clone()]).map(|f| f.size).sum();
+        let mut over = (used + file.file_size).saturating_sub(quota);
+        if over == 0 {
+            return Ok(());
+        }
+        if self.when_full == WhenFull::Reject {
+            return Err(anyhow!(
+                "Receive quota full: {} needs {} bytes, {} of {} in use",
+                file.file_name, file.file_size, used, quota
+            ));
+        }
+
+        received.retain(|f| !f.paths.iter().any(|path| *path == target || in_progress.contains(path)));
+        received.sort_by_key(|f| f.received_at);
+        let mut evict = Vec::new();
+        for f in received {
+            if over == 0 {
+                break;
+            }
+            over = over.saturating_sub(f.size);
+            evict.push(f);
+        }
+        if over > 0 {
+            return Err(anyhow!("Receive quota full: not enough finished files to evict for {}", file.file_name));
+        }
+        // Content stored once only frees its space with its last name
+        for f in evict {
+            for path in &f.paths {
+                info!("Evicting {} to make room for {}", path.display(), file.file_name);
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A received file with all its names
+struct ReceivedFile {
+    paths: Vec<PathBuf>,
+    size: u64,
+    /// Status change time: mtime is restored to the sender's, ctime is when we wrote the file
+    received_at: (i64, i64),
+}
+
+/// Regular files under `dir` by inode, skipping transfer bookkeeping
+fn received_files(dir: &Path) -> Vec<ReceivedFile> {
+    let mut files: HashMap<(u64, u64), ReceivedFile> = HashMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else { continue };
+            let path = entry.path();
+            if metadata.is_dir() {
+                pending.push(path);
+            } else if metadata.is_file() && !is_bookkeeping(&path) {
+                files.entry((metadata.dev(), metadata.ino()))
+                    .or_insert_with(|| ReceivedFile {
+                        paths: Vec::new(),
+                        size: metadata.len(),
+                        received_at: (metadata.ctime(), metadata.ctime_nsec()),
+                    })
+                    .paths.push(path);
+            }
+        }
+    }
+    files.into_values().collect()
+}
+
+fn is_bookkeeping(path: &Path) -> bool {
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    name == INDEX_FILE || BOOKKEEPING_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+struct Decision {
+    outcome: Result<(), String>,
+    path: PathBuf,
+    decided_at: Instant,
+}
+
+/// Policy decisions of the files being received, keyed by file ID. Every range
+/// of a file gets the decision its first range got, and decisions are made one
+/// at a time so quota checks and accept prompts don't race.
+#[derive(Clone, Default)]
+pub struct Admissions {
+    decisions: Arc<StdMutex<HashMap<String, Decision>>>,
+    /// Held while a decision is made
+    deciding: Arc<Mutex<()>>,
+}
+
+impl Admissions {
+    /// Admit a range of `file` (transfer `file_id`) under `policy`. When the
+    /// file is first admitted `prepare` runs before any other range is let in.
+    pub async fn admit(
+        &self,
+        policy: &ReceivePolicy,
+        receive_dir: &Path,
+        file_id: &str,
+        file: IncomingFile,
+        prepare: impl FnOnce() -> Result<()>,
+    ) -> Result<()> {
+        let _deciding = self.deciding.lock().await;
+        let now = Instant::now();
+        let in_progress: Vec<PathBuf> = {
+            let mut decisions = self.decisions.lock().unwrap();
+            decisions.retain(|_, decision| now.duration_since(decision.decided_at) < DECISION_TTL);
+            if let Some(decision) = decisions.get(file_id) {
+                return decision.outcome.clone().map_err(|e| anyhow!(e));
+            }
+            decisions.values()
+                .filter(|decision| decision.outcome.is_ok())
+                .map(|decision| decision.path.clone())
+                .collect()
+        };
+
+        let path = receive_dir.join(&file.file_name);
+        let (policy, receive_dir) = (policy.clone(), receive_dir.to_path_buf());
+        // The accept callback may block
+        let outcome = tokio::task::spawn_blocking(move || policy.check(&receive_dir, &file, &in_progress))
+            .await?
+            .map_err(|e| e.to_string())
+            .and_then(|_| prepare().map_err(|e| e.to_string()));
+
+        self.decisions.lock().unwrap()
+            .insert(file_id.to_string(), Decision { outcome: outcome.clone(), path, decided_at: now });
+        outcome.map_err(|e| anyhow!(e))
+    }
+
+    /// When transfer `file_id` was first admitted
+    pub fn admitted_at(&self, file_id: &str) -> Option<Instant> {
+        self.decisions.lock().unwrap().get(file_id).map(|decision| decision.decided_at)
+    }
+
+    /// Forget transfer `file_id` once it is complete or removed
+    pub fn forget(&self, file_id: &str) {
+        self.decisions.lock().unwrap().remove(file_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn incoming(name: &str, size: u64) -> IncomingFile {
+        IncomingFile { file_name: name.to_string(), file_size: size }
+    }
+
+    #[test]
+    fn test_size_and_extension_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = ReceivePolicy {
+            max_file_size: Some(1000),
+            allowed_extensions: Some(vec!["bin".to_string(), ".safetensors".to_string()]),
+            accept_callback: Some(Arc::new(|file: &IncomingFile| !file.file_name.starts_with("spam"))),
+            ..ReceivePolicy::default()
+        };
+        assert!(policy.check(dir.path(), &incoming("models/a.BIN", 1000), &[]).is_ok());
+        assert!(policy.check(dir.path(), &incoming("b.safetensors", 10), &[]).is_ok());
+        assert!(policy.check(dir.path(), &incoming("a.bin", 1001), &[]).is_err());
+        assert!(policy.check(dir.path(), &incoming("run.sh", 10), &[]).is_err());
+        assert!(policy.check(dir.path(), &incoming("Makefile", 10), &[]).is_err());
+        assert_eq!(policy.check(dir.path(), &incoming("spam.bin", 10), &[]).unwrap_err().to_string(), "spam.bin was declined");
+    }
+
+    #[test]
+    fn test_quota_rejects_or_evicts_oldest() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["old.bin", "newer.bin", "busy.bin"] {
+            fs::write(dir.path().join(name), vec![0u8; 400]).unwrap();
+            if name == "newer.bin" {
+                // A second name for the same content takes no space
+                fs::hard_link(dir.path().join(name), dir.path().join("newer-copy.bin")).unwrap();
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        fs::write(dir.path().join("0d6e.parts"), "{}").unwrap();
+
+        let mut policy = ReceivePolicy { quota: Some(1500), ..ReceivePolicy::default() };
+        assert!(policy.check(dir.path(), &incoming("new.bin", 300), &[]).is_ok());
+        // Replacing a copy only needs the difference
+        assert!(policy.check(dir.path(), &incoming("old.bin", 700), &[]).is_ok());
+        assert!(policy.check(dir.path(), &incoming("new.bin", 400), &[]).is_err());
+        assert!(policy.check(dir.path(), &incoming("huge.bin", 2000), &[]).is_err());
+
+        policy.when_full = WhenFull::EvictOldest;
+        let busy = vec![dir.path().join("old.bin")];
+        policy.check(dir.path(), &incoming("new.bin", 700), &busy).unwrap();
+        assert!(dir.path().join("old.bin").exists());
+        assert!(!dir.path().join("newer.bin").exists());
+        assert!(!dir.path().join("newer-copy.bin").exists());
+        assert!(dir.path().join("busy.bin").exists());
+        assert!(dir.path().join("0d6e.parts").exists());
+    }
+
+    #[tokio::test]
+    async fn test_ranges_share_a_decision() {
+        let dir = tempfile::tempdir().unwrap();
+        let asked = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = asked.clone();
+        let policy = ReceivePolicy {
+            accept_callback: Some(Arc::new(move |_: &IncomingFile| {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0
+            })),
+            ..ReceivePolicy::default()
+        };
+        let admissions = Admissions::default();
+        for _ in 0..3 {
+            admissions.admit(&policy, dir.path(), "t1", incoming("a.bin", 10), || Ok(())).await.unwrap();
+        }
+        assert!(admissions.admit(&policy, dir.path(), "t2", incoming("b.bin", 10), || Ok(())).await.is_err());
+        assert!(admissions.admit(&policy, dir.path(), "t2", incoming("b.bin", 10), || Ok(())).await.is_err());
+        assert_eq!(asked.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        admissions.forget("t1");
+        assert!(admissions.admit(&policy, dir.path(), "t1", incoming("a.bin", 10), || Ok(())).await.is_err());
+    }
+}
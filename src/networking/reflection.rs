@@ -0,0 +1,182 @@
+// src/networking/reflection.rs
+//
+// gRPC server reflection
+// Serves grpc.reflection.v1alpha.ServerReflection so tools like grpcurl can
+// list the services of a node and fetch their descriptors instead of needing
+// the .proto files. The descriptors are the ones compiled into the agent, so
+// they always match what it serves.
+
+use futures_util::{Stream, TryStreamExt};
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::Arc;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+pub mod proto {
+    tonic::include_proto!("grpc.reflection.v1alpha");
+}
+
+use proto::server_reflection_request::MessageRequest;
+use proto::server_reflection_response::MessageResponse;
+use proto::server_reflection_server::ServerReflection;
+use proto::{ErrorResponse, FileDescriptorResponse, ListServiceResponse, ServiceResponse};
+use proto::{ServerReflectionRequest, ServerReflectionResponse};
+
+/// Descriptors of node_service.proto and the standard services served with it
+const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("node_descriptor");
+
+/// Reflection service answering from the compiled descriptors
+#[derive(Clone)]
+pub struct ReflectionService {
+    index: Arc<DescriptorIndex>,
+}
+
+struct DescriptorIndex {
+    /// Fully qualified names of the services
+    services: Vec<String>,
+    files: HashMap<String, FileDescriptorProto>,
+    /// File defining each fully qualified symbol
+    symbols: HashMap<String, String>,
+}
+
+impl ReflectionService {
+    pub fn new() -> Self {
+        let set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET).expect("Invalid compiled file descriptors");
+        let mut index = DescriptorIndex { services: Vec::new(), files: HashMap::new(), symbols: HashMap::new() };
+        for file in set.file {
+            let name = file.name().to_string();
+            let prefix = if file.package().is_empty() { String::new() } else { format!("{}.", file.package()) };
+            let mut symbols = Vec::new();
+            for service in &file.service {
+                let service_name = format!("{}{}", prefix, service.name());
+                symbols.extend(service.method.iter().map(|method| format!("{}.{}", service_name, method.name())));
+                index.services.push(service_name.clone());
+                symbols.push(service_name);
+            }
+            symbols.extend(file.enum_type.iter().map(|e| format!("{}{}", prefix, e.name())));
+            for message in &file.message_type {
+                message_symbols(&prefix, message, &mut symbols);
+            }
+            index.symbols.extend(symbols.into_iter().map(|symbol| (symbol, name.clone())));
+            index.files.insert(name, file);
+        }
+        Self { index: Arc::new(index) }
+    }
+
+    fn respond(&self, request: ServerReflectionRequest) -> ServerReflectionResponse {
+        let response = match &request.message_request {
+            Some(MessageRequest::ListServices(_)) => MessageResponse::ListServicesResponse(ListServiceResponse {
+                service: self.index.services.iter().map(|name| ServiceResponse { name: name.clone() }).collect(),
+            }),
+            Some(MessageRequest::FileByFilename(name)) => self.file_response(name)
+                .unwrap_or_else(|| error(Code::NotFound, format!("Unknown file {}", name))),
+            Some(MessageRequest::FileContainingSymbol(symbol)) => self.index.symbols.get(symbol)
+                .and_then(|name| self.file_response(name))
+                .unwrap_or_else(|| error(Code::NotFound, format!("Unknown symbol {}", symbol))),
+            // None of the served files declare extensions
+            Some(MessageRequest::FileContainingExtension(_)) | Some(MessageRequest::AllExtensionNumbersOfType(_)) => {
+                error(Code::Unimplemented, "Extensions aren't supported".to_string())
+            }
+            None => error(Code::InvalidArgument, "Empty request".to_string()),
+        };
+        ServerReflectionResponse {
+            valid_host: request.host.clone(),
+            original_request: Some(request),
+            message_response: Some(response),
+        }
+    }
+
+    /// The file `name` followed by everything it imports, directly or not
+    fn file_response(&self, name: &str) -> Option<MessageResponse> {
+        let mut pending = vec![self.index.files.get(name)?];
+        let mut seen = HashSet::from([name]);
+        let mut files = Vec::new();
+        while let Some(file) = pending.pop() {
+            files.push(file.encode_to_vec());
+            for dependency in &file.dependency {
+                if seen.insert(dependency.as_str()) {
+                    pending.extend(self.index.files.get(dependency));
+                }
+            }
+        }
+        Some(MessageResponse::FileDescriptorResponse(FileDescriptorResponse { file_descriptor_proto: files }))
+    }
+}
+
+impl Default for ReflectionService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Add the names of `message`, its nested messages and enums to `symbols`
+fn message_symbols(prefix: &str, message: &DescriptorProto, symbols: &mut Vec<String>) {
+    let name = format!("{}{}", prefix, message.name());
+    let nested = format!("{}.", name);
+    symbols.extend(message.enum_type.iter().map(|e| format!("{}{}", nested, e.name())));
+    for child in &message.nested_type {
+        message_symbols(&nested, child, symbols);
+    }
+    symbols.push(name);
+}
+
+fn error(code: Code, message: String) -> MessageResponse {
+    MessageResponse::ErrorResponse(ErrorResponse { error_code: code as i32, error_message: message })
+}
+
+#[tonic::async_trait]
+impl ServerReflection for ReflectionService {
+    type ServerReflectionInfoStream = Pin<Box<dyn Stream<Item = Result<ServerReflectionResponse, Status>> + Send>>;
+
+    async fn server_reflection_info(
+        &self,
+        request: Request<Streaming<ServerReflectionRequest>>,
+    ) -> Result<Response<Self::ServerReflectionInfoStream>, Status> {
+        let reflection = self.clone();
+        let responses = request.into_inner().map_ok(move |request| reflection.respond(request));
+        Ok(Response::new(Box::pin(responses)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(message: MessageRequest) -> ServerReflectionRequest {
+        ServerReflectionRequest { host: String::new(), message_request: Some(message) }
+    }
+
+    fn files(response: ServerReflectionResponse) -> Vec<FileDescriptorProto> {
+        match response.message_response {
+            Some(MessageResponse::FileDescriptorResponse(response)) => response.file_descriptor_proto.iter()
+                .map(|bytes| FileDescriptorProto::decode(bytes.as_slice()).unwrap())
+                .collect(),
+            other => panic!("Expected file descriptors, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reflection() {
+        let reflection = ReflectionService::new();
+        let services = match reflection.respond(request(MessageRequest::ListServices(String::new()))).message_response {
+            Some(MessageResponse::ListServicesResponse(response)) => response.service.into_iter().map(|s| s.name).collect::<Vec<_>>(),
+            other => panic!("Expected services, got {:?}", other),
+        };
+        assert!(services.contains(&"node.NodeService".to_string()));
+        assert!(services.contains(&"grpc.health.v1.Health".to_string()));
+        assert!(services.contains(&"grpc.reflection.v1alpha.ServerReflection".to_string()));
+
+        for symbol in ["node.NodeService", "node.NodeService.Ping", "node.PingRequest", "node.HealthCheckResponse.Status"] {
+            let files = files(reflection.respond(request(MessageRequest::FileContainingSymbol(symbol.to_string()))));
+            assert_eq!(files[0].name(), "node_service.proto", "{}", symbol);
+        }
+        let files = files(reflection.respond(request(MessageRequest::FileByFilename("health.proto".to_string()))));
+        assert_eq!(files[0].package(), "grpc.health.v1");
+
+        let missing = reflection.respond(request(MessageRequest::FileContainingSymbol("node.Missing".to_string())));
+        assert!(matches!(missing.message_response, Some(MessageResponse::ErrorResponse(ErrorResponse { error_code: 5, .. }))));
+        assert!(missing.original_request.is_some());
+    }
+}
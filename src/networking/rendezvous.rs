@@ -0,0 +1,150 @@
+// src/networking/rendezvous.rs
+//
+// Rendezvous discovery
+// mDNS and UDP broadcasts stay within one network. For nodes on other subnets
+// or sites, every node registers its NodeInfo with the monitoring API (or a
+// dedicated rendezvous endpoint) and fetches the records of its peers from it,
+// adding them to the same discovered node list. Registrations are refreshed
+// every interval, so a node that stops registering drops out of its peers'
+// lists when the discovery expiry passes.
+
+use anyhow::Result;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use super::discovery::NodeInfo;
+use crate::api::client::ApiClient;
+
+/// Time between registrations unless configured otherwise
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Register `local_node` and fetch its peers every `interval`, and right away
+/// when the node changes
+pub fn spawn(
+    client: Arc<ApiClient>,
+    mut local_node: watch::Receiver<NodeInfo>,
+    discovered_nodes: Arc<Mutex<HashMap<String, (NodeInfo, Instant)>>>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    info!("Registering node '{}' for rendezvous discovery every {:?}", local_node.borrow().name, interval);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                Ok(()) = local_node.changed() => {}
+            }
+            let node = local_node.borrow_and_update().clone();
+            match sync(&client, &node, &discovered_nodes).await {
+                Ok(count) => debug!("Rendezvous discovery listed {} peers", count),
+                Err(e) => warn!("Rendezvous discovery failed: {:#}", e),
+            }
+        }
+    })
+}
+
+/// Register once and add the listed peers; returns how many were listed
+pub async fn sync(
+    client: &ApiClient,
+    local_node: &NodeInfo,
+    discovered_nodes: &Mutex<HashMap<String, (NodeInfo, Instant)>>,
+) -> Result<usize> {
+    client.register_peer(local_node).await?;
+    let peers: Vec<NodeInfo> = client.fetch_peers().await?
+        .into_iter()
+        .filter(|peer| peer.id != local_node.id)
+        .collect();
+
+    let now = Instant::now();
+    let mut nodes = discovered_nodes.lock().unwrap();
+    for peer in &peers {
+        if nodes.insert(peer.id.clone(), (peer.clone(), now)).is_none() {
+            info!("✅ Discovered node through rendezvous: {} ({}) at {}", peer.name, peer.id, peer.ip);
+        }
+    }
+    Ok(peers.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn node(id: &str, ip: &str) -> NodeInfo {
+        NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: ip.to_string(),
+            port: 54321,
+            interface_type: "Ethernet".to_string(),
+            capabilities: vec!["discovery".to_string()],
+            version: "0.1.0".to_string(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        }
+    }
+
+    /// Serve two requests: accept the registration and list the peers
+    async fn rendezvous_server(peers: Vec<NodeInfo>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let length = text.lines()
+                            .find_map(|line| line.to_lowercase().strip_prefix("content-length: ").map(str::to_string))
+                            .and_then(|length| length.trim().parse::<usize>().ok())
+                            .unwrap_or(0);
+                        if request.len() >= end + 4 + length || n == 0 {
+                            break;
+                        }
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).to_string();
+                let body = if request.starts_with("GET") { serde_json::to_string(&peers).unwrap() } else { String::new() };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(), body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn test_register_and_fetch_peers() {
+        let local = node("node-a", "10.0.0.1");
+        let (url, server) = rendezvous_server(vec![local.clone(), node("node-b", "192.168.7.20")]).await;
+        let client = ApiClient::new(url, "key".to_string(), "node-a".to_string()).unwrap();
+        let nodes = Mutex::new(HashMap::new());
+
+        assert_eq!(sync(&client, &local, &nodes).await.unwrap(), 1);
+        let nodes = nodes.into_inner().unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes["node-b"].0.ip, "192.168.7.20");
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("PUT /api/v1/nodes/node-a/peer "));
+        assert!(requests[0].contains("\"id\":\"node-a\""));
+        assert!(requests[1].starts_with("GET /api/v1/nodes/node-a/peers "));
+    }
+}
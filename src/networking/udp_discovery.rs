@@ -0,0 +1,221 @@
+// src/networking/udp_discovery.rs
+//
+// UDP broadcast discovery
+// A fallback for networks where mDNS doesn't work: the daemon fails to start,
+// or multicast DNS is filtered. Every node broadcasts its NodeInfo to a
+// well-known UDP port and listens there for the broadcasts of others, filling
+// the same discovered node list mDNS does. A node answers the first
+// announcement it hears from a peer directly, so new nodes are found without
+// waiting a full interval.
+
+use anyhow::{Context, Result};
+use if_addrs::{get_if_addrs, IfAddr};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use super::discovery::NodeInfo;
+
+/// Port nodes announce themselves on
+pub const DEFAULT_UDP_PORT: u16 = 54322;
+
+/// Time between announcements, well within the discovery expiry
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Prefix of every announcement, so stray datagrams are ignored
+const MAGIC: &[u8] = b"NCDISC1\n";
+
+/// Largest announcement accepted
+const MAX_ANNOUNCEMENT_LEN: usize = 8192;
+
+/// When UDP discovery runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UdpMode {
+    /// Only when mDNS can't be started
+    #[default]
+    Fallback,
+    /// Alongside mDNS, for networks that filter multicast DNS
+    Always,
+    /// Never
+    Off,
+}
+
+impl UdpMode {
+    /// Parse `fallback`, `always` or `off`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "fallback" | "" => Some(Self::Fallback),
+            "always" | "true" => Some(Self::Always),
+            "off" | "false" => Some(Self::Off),
+            _ => None,
+        }
+    }
+}
+
+/// Announcement of `node`
+pub fn encode(node: &NodeInfo) -> Vec<u8> {
+    let mut message = MAGIC.to_vec();
+    message.extend(serde_json::to_vec(node).unwrap_or_default());
+    message
+}
+
+/// The node announced by `message`, if it is an announcement
+pub fn decode(message: &[u8]) -> Option<NodeInfo> {
+    serde_json::from_slice(message.strip_prefix(MAGIC)?).ok()
+}
+
+/// Listen on `port` and announce `local_node` to the broadcast address of
+/// every interface, adding the nodes heard from to `discovered_nodes`
+pub async fn start(
+    local_node: watch::Receiver<NodeInfo>,
+    port: u16,
+    discovered_nodes: Arc<Mutex<HashMap<String, (NodeInfo, Instant)>>>,
+) -> Result<Vec<JoinHandle<()>>> {
+    let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))).await
+        .with_context(|| format!("Failed to bind UDP discovery port {}", port))?;
+    socket.set_broadcast(true)?;
+    let targets = broadcast_addresses().into_iter().map(|ip| SocketAddr::from((ip, port))).collect();
+    info!("UDP discovery announcing node '{}' on port {}", local_node.borrow().name, port);
+    Ok(spawn(Arc::new(socket), local_node, targets, discovered_nodes))
+}
+
+/// Run announcing and listening on `socket`; announcements go to `targets`,
+/// and right away when `local_node` changes
+pub fn spawn(
+    socket: Arc<UdpSocket>,
+    local_node: watch::Receiver<NodeInfo>,
+    targets: Vec<SocketAddr>,
+    discovered_nodes: Arc<Mutex<HashMap<String, (NodeInfo, Instant)>>>,
+) -> Vec<JoinHandle<()>> {
+    let announcer = {
+        let (socket, mut local_node) = (socket.clone(), local_node.clone());
+        tokio::spawn(async move {
+            loop {
+                let announcement = encode(&local_node.borrow_and_update());
+                for target in &targets {
+                    if let Err(e) = socket.send_to(&announcement, target).await {
+                        debug!("Failed to announce to {}: {}", target, e);
+                    }
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(ANNOUNCE_INTERVAL) => {}
+                    Ok(()) = local_node.changed() => {}
+                }
+            }
+        })
+    };
+
+    let listener = tokio::spawn(async move {
+        let mut buffer = vec![0u8; MAX_ANNOUNCEMENT_LEN];
+        loop {
+            let (len, source) = match socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("UDP discovery receive failed: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            let Some(mut node) = decode(&buffer[..len]) else { continue };
+            if node.id == local_node.borrow().id {
+                continue;
+            }
+            // The address the announcement came from is one we can reach
+            node.ip = source.ip().to_canonical().to_string();
+
+            let is_new = {
+                let mut nodes = discovered_nodes.lock().unwrap();
+                nodes.insert(node.id.clone(), (node.clone(), Instant::now())).is_none()
+            };
+            if is_new {
+                info!("✅ Discovered node over UDP: {} ({})", node.name, node.id);
+                let announcement = encode(&local_node.borrow());
+                if let Err(e) = socket.send_to(&announcement, source).await {
+                    debug!("Failed to answer {}: {}", source, e);
+                }
+            }
+        }
+    });
+
+    vec![announcer, listener]
+}
+
+/// The limited broadcast address and the broadcast address of every IPv4 interface
+fn broadcast_addresses() -> Vec<Ipv4Addr> {
+    let mut addresses = vec![Ipv4Addr::BROADCAST];
+    for interface in get_if_addrs().unwrap_or_default() {
+        if let IfAddr::V4(v4) = interface.addr {
+            if let Some(broadcast) = v4.broadcast.filter(|_| !v4.ip.is_loopback()) {
+                if !addresses.contains(&broadcast) {
+                    addresses.push(broadcast);
+                }
+            }
+        }
+    }
+    addresses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str) -> NodeInfo {
+        NodeInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            ip: "10.0.0.1".to_string(),
+            port: 54321,
+            interface_type: "Ethernet".to_string(),
+            capabilities: vec!["discovery".to_string()],
+            version: "0.1.0".to_string(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_announcement_round_trip() {
+        let announced = decode(&encode(&node("node-a"))).unwrap();
+        assert_eq!((announced.id.as_str(), announced.port), ("node-a", 54321));
+        assert!(decode(b"{\"id\":\"node-a\"}").is_none());
+        assert!(decode(b"NCDISC1\nnot json").is_none());
+        assert_eq!(UdpMode::parse("Always"), Some(UdpMode::Always));
+        assert_eq!(UdpMode::parse("sometimes"), None);
+    }
+
+    #[tokio::test]
+    async fn test_nodes_find_each_other() {
+        let bind = || UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)));
+        let (socket_a, socket_b) = (Arc::new(bind().await.unwrap()), Arc::new(bind().await.unwrap()));
+        let addr_b = socket_b.local_addr().unwrap();
+        let nodes_a = Arc::new(Mutex::new(HashMap::new()));
+        let nodes_b = Arc::new(Mutex::new(HashMap::new()));
+
+        // B only announces to itself, so A learns of it from B's answer to A's announcement
+        let announce = |id| watch::channel(node(id)).1;
+        let tasks_b = spawn(socket_b, announce("node-b"), vec![addr_b], nodes_b.clone());
+        let tasks_a = spawn(socket_a, announce("node-a"), vec![addr_b], nodes_a.clone());
+
+        for _ in 0..50 {
+            if nodes_a.lock().unwrap().contains_key("node-b") {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let (seen_by_a, _) = nodes_a.lock().unwrap()["node-b"].clone();
+        assert_eq!(seen_by_a.ip, "127.0.0.1");
+        assert!(nodes_b.lock().unwrap().contains_key("node-a"));
+        assert!(!nodes_b.lock().unwrap().contains_key("node-b"));
+        for task in tasks_a.into_iter().chain(tasks_b) {
+            task.abort();
+        }
+    }
+}
@@ -0,0 +1,251 @@
+// src/networking/wake.rs
+//
+// Wake-on-LAN
+// Nodes advertise the hardware address of each IPv4 address in discovery.
+// Every node remembers the addresses of the peers it has seen, on disk, since
+// a node that went to sleep stops announcing itself. Waking one broadcasts
+// the magic packet (6 bytes of 0xff, then its MAC 16 times) on the subnet of
+// each address it had, through our interface on that subnet, and on the
+// local broadcast address. Broadcasts don't cross routers: a node on another
+// subnet is woken by asking a peer on its subnet over the WakeNode RPC.
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+use super::discovery::{NodeDiscovery, NodeInfo};
+use super::interface;
+
+/// Port magic packets are sent to (discard)
+pub const WAKE_PORT: u16 = 9;
+
+/// Time between recording the MACs of the discovered nodes
+pub const RECORD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A node that can be woken, as last seen in discovery
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WakeTarget {
+    pub node_id: String,
+    pub name: String,
+    /// Hardware address of each of its IPv4 addresses
+    pub mac_addresses: BTreeMap<String, String>,
+    /// Unix time in seconds it was last discovered
+    pub last_seen: u64,
+}
+
+/// Magic packets sent to wake a node
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WakeReport {
+    pub node_id: String,
+    pub name: String,
+    /// Broadcast addresses a packet went to
+    pub sent_to: Vec<String>,
+}
+
+/// The MACs of every node seen in discovery, persisted to a file when there is one
+#[derive(Clone, Default)]
+pub struct WakeTable {
+    path: Option<PathBuf>,
+    targets: Arc<StdMutex<BTreeMap<String, WakeTarget>>>,
+}
+
+impl WakeTable {
+    /// Open the table stored at `path`, or keep it in memory only when None
+    pub fn open(path: Option<&Path>) -> Self {
+        let targets = path
+            .and_then(|path| fs::read(path).ok().map(|data| (path, data)))
+            .and_then(|(path, data)| match serde_json::from_slice::<Vec<WakeTarget>>(&data) {
+                Ok(targets) => Some(targets),
+                Err(e) => {
+                    warn!("Ignoring unreadable Wake-on-LAN table {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .map(|target| (target.node_id.clone(), target))
+            .collect();
+        Self { path: path.map(Path::to_path_buf), targets: Arc::new(StdMutex::new(targets)) }
+    }
+
+    /// Remember the MACs `nodes` advertise; saved when any changed
+    pub fn record(&self, nodes: &[NodeInfo]) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let mut targets = self.targets.lock().unwrap();
+        let mut changed = false;
+        for node in nodes.iter().filter(|node| !node.mac_addresses.is_empty()) {
+            let target = targets.entry(node.id.clone()).or_insert_with(|| WakeTarget {
+                node_id: node.id.clone(),
+                name: String::new(),
+                mac_addresses: BTreeMap::new(),
+                last_seen: 0,
+            });
+            if target.name != node.name || target.mac_addresses != node.mac_addresses {
+                debug!("Recording hardware addresses of {}: {:?}", node.name, node.mac_addresses);
+                (target.name, target.mac_addresses) = (node.name.clone(), node.mac_addresses.clone());
+                changed = true;
+            }
+            target.last_seen = now;
+        }
+        if changed {
+            self.save(&targets);
+        }
+    }
+
+    /// Record the MACs of the nodes `discovery` finds every `RECORD_INTERVAL`
+    pub fn spawn(&self, discovery: Arc<NodeDiscovery>) -> JoinHandle<()> {
+        let table = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(RECORD_INTERVAL);
+            loop {
+                ticker.tick().await;
+                table.record(&discovery.get_discovered_nodes());
+            }
+        })
+    }
+
+    /// The node with ID, ID prefix or name `node`
+    pub fn find(&self, node: &str) -> Option<WakeTarget> {
+        let targets = self.targets.lock().unwrap();
+        targets.get(node)
+            .or_else(|| targets.values().find(|target| target.name == node))
+            .or_else(|| targets.values().find(|target| target.node_id.starts_with(node)))
+            .cloned()
+    }
+
+    /// Every node that can be woken
+    pub fn targets(&self) -> Vec<WakeTarget> {
+        self.targets.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Send magic packets to the node with ID, ID prefix or name `node`
+    pub async fn wake(&self, node: &str) -> Result<WakeReport> {
+        let target = self.find(node)
+            .ok_or_else(|| anyhow!("No hardware address known for node {}", node))?;
+        let sent_to = send_magic_packets(&target.mac_addresses).await?;
+        info!("Sent Wake-on-LAN packets for {} to {}", target.name, sent_to.join(", "));
+        Ok(WakeReport { node_id: target.node_id, name: target.name, sent_to })
+    }
+
+    fn save(&self, targets: &BTreeMap<String, WakeTarget>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_vec_pretty(&targets.values().collect::<Vec<_>>())
+            .map_err(std::io::Error::from)
+            .and_then(|data| fs::write(path, data));
+        if let Err(e) = result {
+            warn!("Failed to save the Wake-on-LAN table to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Parse a MAC written as six hex bytes separated by colons or dashes
+pub fn parse_mac(mac: &str) -> Result<[u8; 6]> {
+    let bytes = mac.split([':', '-'])
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .with_context(|| format!("Invalid MAC address '{}'", mac))?;
+    bytes.try_into().map_err(|_| anyhow!("Invalid MAC address '{}'", mac))
+}
+
+/// The magic packet waking the interface with hardware address `mac`
+pub fn magic_packet(mac: &[u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xff; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(mac);
+    }
+    packet
+}
+
+/// Broadcast the magic packet of every MAC in `mac_addresses` (IPv4 address
+/// to MAC) on the subnet of its address, where one of our interfaces is on
+/// it, and on the local broadcast address; returns where packets went
+pub async fn send_magic_packets(mac_addresses: &BTreeMap<String, String>) -> Result<Vec<String>> {
+    if mac_addresses.is_empty() {
+        return Err(anyhow!("No hardware addresses to wake"));
+    }
+    let interfaces = interface::list_interfaces().unwrap_or_default();
+    let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))).await?;
+    socket.set_broadcast(true)?;
+
+    let mut sent_to = Vec::new();
+    for (address, mac) in mac_addresses {
+        let packet = magic_packet(&parse_mac(mac)?);
+        let subnet = address.parse::<IpAddr>().ok()
+            .and_then(|ip| interfaces.iter().find(|interface| interface.contains(&ip)))
+            .and_then(|interface| interface.broadcast());
+        for broadcast in subnet.into_iter().chain([Ipv4Addr::BROADCAST]) {
+            let destination = SocketAddr::from((broadcast, WAKE_PORT));
+            match socket.send_to(&packet, destination).await {
+                Ok(_) => sent_to.push(format!("{} ({})", destination, mac)),
+                Err(e) => debug!("Failed to send a magic packet to {}: {}", destination, e),
+            }
+        }
+    }
+    if sent_to.is_empty() {
+        return Err(anyhow!("No magic packet could be sent"));
+    }
+    Ok(sent_to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, name: &str, macs: &[(&str, &str)]) -> NodeInfo {
+        NodeInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            ip: "10.0.0.5".to_string(),
+            port: 54321,
+            interface_type: "Ethernet".to_string(),
+            capabilities: vec![],
+            version: String::new(),
+            addresses: vec![],
+            public_key: String::new(),
+            labels: Default::default(),
+            address_types: Default::default(),
+            mac_addresses: macs.iter().map(|(ip, mac)| (ip.to_string(), mac.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_magic_packet() {
+        let mac = parse_mac("a4:83:e7:12:34:56").unwrap();
+        assert_eq!(parse_mac("A4-83-E7-12-34-56").unwrap(), mac);
+        assert!(parse_mac("a4:83:e7:12:34").is_err());
+        assert!(parse_mac("a4:83:e7:12:34:zz").is_err());
+
+        let packet = magic_packet(&mac);
+        assert_eq!(packet.len(), 102);
+        assert_eq!(packet[..6], [0xff; 6]);
+        assert!(packet[6..].chunks(6).all(|chunk| chunk == mac));
+    }
+
+    #[test]
+    fn test_table_outlives_discovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wake.json");
+        let table = WakeTable::open(Some(&path));
+        table.record(&[
+            node("3f2a0c1e", "render-1", &[("10.0.0.5", "a4:83:e7:12:34:56")]),
+            node("77b1d9e0", "laptop", &[]),
+        ]);
+        assert_eq!(table.targets().len(), 1);
+
+        // Read back after a restart, by ID prefix or name
+        let table = WakeTable::open(Some(&path));
+        assert_eq!(table.find("3f2a").unwrap().name, "render-1");
+        assert_eq!(table.find("render-1").unwrap().mac_addresses["10.0.0.5"], "a4:83:e7:12:34:56");
+        assert!(table.find("laptop").is_none());
+    }
+}
@@ -0,0 +1,127 @@
+// src/networking/zero_copy.rs
+//
+// Zero-copy sending
+// File ranges go from the page cache straight to the socket with sendfile(2)
+// instead of through a userspace buffer, which saves a copy per byte and most
+// of the CPU a transfer costs on 10GbE and Thunderbolt links. Platforms
+// without a usable sendfile fall back to pread and write.
+
+use std::fs::File;
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::Interest;
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+
+/// Socket buffer size for transfer connections; the defaults are tuned for
+/// latency, not for keeping a fast link busy
+pub const SOCKET_BUFFER_SIZE: u32 = 4 * 1024 * 1024;
+
+/// Connect to `addr` with a large send buffer and Nagle's algorithm disabled,
+/// so the small handshake and header writes go out immediately
+pub async fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    socket.set_send_buffer_size(SOCKET_BUFFER_SIZE)?;
+    let stream = socket.connect(addr).await?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// Listen on `addr` with a large receive buffer, inherited by accepted
+/// connections; the IPv6 unspecified address listens on both families
+pub fn listen(addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = super::interface::bind_tcp(addr)?;
+    socket.set_recv_buffer_size(SOCKET_BUFFER_SIZE)?;
+    socket.listen(1024)
+}
+
+/// Send up to `len` bytes of `file` from `offset`; returns the bytes sent,
+/// 0 at the end of the file
+pub async fn send_chunk(socket: &TcpStream, file: &File, offset: u64, len: usize) -> io::Result<usize> {
+    loop {
+        socket.writable().await?;
+        match socket.try_io(Interest::WRITABLE, || sendfile(file, socket, offset, len)) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            result => return result,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn sendfile(file: &File, socket: &TcpStream, offset: u64, len: usize) -> io::Result<usize> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut offset = offset as libc::off_t;
+    let sent = unsafe { libc::sendfile(socket.as_raw_fd(), file.as_raw_fd(), &mut offset, len) };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(sent as usize)
+}
+
+#[cfg(target_os = "macos")]
+fn sendfile(file: &File, socket: &TcpStream, offset: u64, len: usize) -> io::Result<usize> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut sent = len as libc::off_t;
+    let result = unsafe {
+        libc::sendfile(file.as_raw_fd(), socket.as_raw_fd(), offset as libc::off_t, &mut sent, std::ptr::null_mut(), 0)
+    };
+    if result < 0 {
+        let error = io::Error::last_os_error();
+        // A non-blocking socket can take part of the range before it fills up
+        if error.kind() == io::ErrorKind::WouldBlock && sent > 0 {
+            return Ok(sent as usize);
+        }
+        return Err(error);
+    }
+    Ok(sent as usize)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn sendfile(file: &File, socket: &TcpStream, offset: u64, len: usize) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+
+    let mut buffer = vec![0u8; len.min(256 * 1024)];
+    let n = file.read_at(&mut buffer, offset)?;
+    if n == 0 {
+        return Ok(0);
+    }
+    socket.try_write(&buffer[..n])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_send_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(dir.path().join("data.bin"), &data).unwrap();
+
+        let listener = listen(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let reader = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            socket.read_to_end(&mut received).await.unwrap();
+            received
+        });
+
+        // Send the second half in chunks, past the end of the file
+        let socket = connect(addr).await.unwrap();
+        let file = File::open(dir.path().join("data.bin")).unwrap();
+        let mut offset = 1_500_000u64;
+        loop {
+            let n = send_chunk(&socket, &file, offset, 256 * 1024).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            offset += n as u64;
+        }
+        assert_eq!(offset, data.len() as u64);
+        drop(socket);
+        assert_eq!(reader.await.unwrap(), data[1_500_000..]);
+    }
+}
@@ -0,0 +1,139 @@
+// src/node_identity.rs
+//
+// Stable node identity
+// The node ID is generated once, persisted next to the other NodeController
+// state, and shared by the collectors, the API client and node discovery so
+// that a node keeps the same identity across restarts and hostname changes.
+// User-defined labels (rack, role, environment) travel with the identity so
+// peers and the backend can filter and group nodes.
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Identity of this node as seen by the monitoring API and other nodes
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeIdentity {
+    /// Persistent UUID, stable across restarts
+    pub node_id: String,
+    /// Human-readable name (NODE_NAME or hostname), may change
+    pub node_name: String,
+    /// User-defined labels, e.g. rack=r1
+    pub labels: BTreeMap<String, String>,
+}
+
+impl NodeIdentity {
+    /// Load the node ID from `path`, generating and persisting a new one if the
+    /// file is missing or does not contain a valid UUID
+    pub fn load_or_create(path: &Path, node_name: String) -> Result<Self> {
+        if let Ok(contents) = fs::read_to_string(path) {
+            match Uuid::parse_str(contents.trim()) {
+                Ok(id) => {
+                    return Ok(Self {
+                        node_id: id.to_string(),
+                        node_name,
+                        labels: BTreeMap::new(),
+                    });
+                }
+                Err(_) => warn!("Ignoring invalid node ID in {}, generating a new one", path.display()),
+            }
+        }
+
+        let node_id = Uuid::new_v4().to_string();
+        persist(path, &node_id)?;
+        info!("Generated new node ID {} ({})", node_id, path.display());
+
+        Ok(Self { node_id, node_name, labels: BTreeMap::new() })
+    }
+
+    pub fn with_labels(mut self, labels: BTreeMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+}
+
+/// Longest label key; keys are also used in DNS TXT record names
+const MAX_LABEL_KEY: usize = 63;
+/// Longest label value
+const MAX_LABEL_VALUE: usize = 128;
+
+/// Parse labels given as `key=value` pairs separated by commas, e.g.
+/// `rack=r1,role=worker`. Keys are letters, digits, `_`, `.` and `-`.
+pub fn parse_labels(spec: &str) -> Result<BTreeMap<String, String>> {
+    let mut labels = BTreeMap::new();
+    for pair in spec.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let Some((key, value)) = pair.split_once('=') else {
+            bail!("Label '{}' is not key=value", pair);
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if key.is_empty()
+            || key.len() > MAX_LABEL_KEY
+            || !key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        {
+            bail!("Invalid label key '{}'", key);
+        }
+        if value.len() > MAX_LABEL_VALUE || value.contains('=') {
+            bail!("Invalid value for label '{}'", key);
+        }
+        labels.insert(key.to_string(), value.to_string());
+    }
+    Ok(labels)
+}
+
+/// Default location of the persisted node ID, alongside the update directory
+pub fn default_identity_path() -> PathBuf {
+    dirs::home_dir()
+        .map(|home| home.join("Library/Application Support/NodeController/node_id"))
+        .unwrap_or_else(|| PathBuf::from("./node_id"))
+}
+
+/// Write via a temporary file so a crash never leaves a truncated ID behind
+fn persist(path: &Path, node_id: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, format!("{}\n", node_id))
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to persist node ID to {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_node_id_is_persisted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state/node_id");
+
+        let first = NodeIdentity::load_or_create(&path, "node-a".to_string()).unwrap();
+        let second = NodeIdentity::load_or_create(&path, "renamed".to_string()).unwrap();
+        assert_eq!(first.node_id, second.node_id);
+        assert_eq!(second.node_name, "renamed");
+
+        fs::write(&path, "not-a-uuid").unwrap();
+        let regenerated = NodeIdentity::load_or_create(&path, "node-a".to_string()).unwrap();
+        assert_ne!(regenerated.node_id, first.node_id);
+        assert_eq!(fs::read_to_string(&path).unwrap().trim(), regenerated.node_id);
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        let labels = parse_labels("rack=r1, role=worker,environment=prod,").unwrap();
+        assert_eq!(labels.len(), 3);
+        assert_eq!(labels["role"], "worker");
+        assert!(parse_labels("").unwrap().is_empty());
+        assert!(parse_labels("rack").is_err());
+        assert!(parse_labels("=r1").is_err());
+        assert!(parse_labels("rack id=r1").is_err());
+        assert!(parse_labels("rack=r1=r2").is_err());
+    }
+}
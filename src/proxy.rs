@@ -0,0 +1,247 @@
+// src/proxy.rs
+//
+// Outbound proxy configuration
+// Every HTTP client (monitoring API, metric sinks, updater, network probes)
+// is built through `client_builder` so that managed networks that only allow
+// egress through a proxy work everywhere. The WebSocket and MQTT transports
+// tunnel through the same proxy with HTTP CONNECT.
+
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{debug, info, warn};
+use reqwest::{ClientBuilder, Proxy, Url};
+use std::env;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Longest CONNECT response header accepted from a proxy
+const MAX_CONNECT_RESPONSE: usize = 8 * 1024;
+
+/// Proxies for outbound HTTP and HTTPS traffic
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyConfig {
+    pub http: Option<Url>,
+    pub https: Option<Url>,
+    /// Hosts reached directly: exact names, domain suffixes (`.corp` or `corp`),
+    /// IP addresses, or `*` for everything
+    pub no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// OUTBOUND_PROXY / OUTBOUND_NO_PROXY take precedence over the conventional
+    /// HTTP_PROXY, HTTPS_PROXY and NO_PROXY variables (upper or lower case)
+    pub fn from_env() -> Self {
+        let var = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| env::var(name).ok().filter(|v| !v.trim().is_empty()))
+        };
+        let parse = |name: &str, value: Option<String>| {
+            value.and_then(|v| match parse_proxy_url(&v) {
+                Ok(url) => Some(url),
+                Err(e) => {
+                    warn!("Ignoring invalid {} '{}': {}", name, v, e);
+                    None
+                }
+            })
+        };
+
+        let explicit = parse("OUTBOUND_PROXY", var(&["OUTBOUND_PROXY"]));
+        let http = explicit.clone().or_else(|| parse("HTTP_PROXY", var(&["HTTP_PROXY", "http_proxy"])));
+        let https = explicit.or_else(|| parse("HTTPS_PROXY", var(&["HTTPS_PROXY", "https_proxy"])));
+        let no_proxy = [var(&["OUTBOUND_NO_PROXY"]), var(&["NO_PROXY", "no_proxy"])]
+            .into_iter()
+            .flatten()
+            .flat_map(|list| list.split(',').map(|h| h.trim().to_lowercase()).collect::<Vec<_>>())
+            .filter(|h| !h.is_empty())
+            .collect();
+
+        Self { http, https, no_proxy }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.http.is_none() && self.https.is_none()
+    }
+
+    /// Proxy to use for `url`, if any. `wss` and `mqtts` URLs use the HTTPS proxy and
+    /// `ws` and `mqtt` the HTTP one.
+    pub fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let proxy = match url.scheme() {
+            "https" | "wss" | "mqtts" | "ssl" => self.https.as_ref(),
+            "http" | "ws" | "mqtt" | "tcp" => self.http.as_ref(),
+            _ => None,
+        }?;
+        if self.bypass(url.host_str()?) {
+            return None;
+        }
+        Some(proxy.clone())
+    }
+
+    fn bypass(&self, host: &str) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']').to_lowercase();
+        self.no_proxy.iter().any(|entry| {
+            // Entries may carry a port, which is ignored
+            let entry = match entry.rsplit_once(':') {
+                Some((name, port)) if !name.contains(':') && port.parse::<u16>().is_ok() => name,
+                _ => entry.as_str(),
+            };
+            let entry = entry.trim_start_matches('[').trim_end_matches(']');
+            if entry == "*" {
+                return true;
+            }
+            if entry.parse::<IpAddr>().is_ok() || host.parse::<IpAddr>().is_ok() {
+                return entry == host;
+            }
+            let domain = entry.trim_start_matches('.');
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+    }
+
+    /// Route a client through these proxies instead of reqwest's own environment lookup
+    pub fn apply(&self, builder: ClientBuilder) -> ClientBuilder {
+        let builder = builder.no_proxy();
+        if self.is_empty() {
+            return builder;
+        }
+        let config = self.clone();
+        builder.proxy(Proxy::custom(move |url| config.proxy_for(url)))
+    }
+}
+
+/// Accept `host:port` without a scheme, as many tools do
+fn parse_proxy_url(value: &str) -> Result<Url> {
+    let value = value.trim();
+    let url = if value.contains("://") {
+        Url::parse(value)?
+    } else {
+        Url::parse(&format!("http://{}", value))?
+    };
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(anyhow!("unsupported proxy scheme '{}'", url.scheme()));
+    }
+    if url.host_str().is_none() {
+        return Err(anyhow!("proxy URL has no host"));
+    }
+    Ok(url)
+}
+
+/// Process-wide proxy configuration, read from the environment once
+pub fn global() -> &'static ProxyConfig {
+    static CONFIG: OnceLock<ProxyConfig> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let config = ProxyConfig::from_env();
+        if !config.is_empty() {
+            info!(
+                "Outbound proxy: http={} https={} no_proxy=[{}]",
+                config.http.as_ref().map_or("direct".to_string(), redact),
+                config.https.as_ref().map_or("direct".to_string(), redact),
+                config.no_proxy.join(",")
+            );
+        }
+        config
+    })
+}
+
+/// Proxy URL without credentials, for logs
+fn redact(url: &Url) -> String {
+    let mut url = url.clone();
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.to_string()
+}
+
+/// HTTP client builder that honors the outbound proxy configuration
+pub fn client_builder() -> ClientBuilder {
+    global().apply(reqwest::Client::builder())
+}
+
+/// Open a TCP connection to `host:port` for `url`, tunnelling through the proxy
+/// with HTTP CONNECT when one applies
+pub async fn connect_tcp(url: &Url, host: &str, port: u16) -> Result<TcpStream> {
+    let Some(proxy) = global().proxy_for(url) else {
+        return Ok(TcpStream::connect((host, port)).await?);
+    };
+    let proxy_host = proxy.host_str().ok_or_else(|| anyhow!("proxy URL has no host"))?;
+    let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+    if proxy.scheme() == "https" {
+        return Err(anyhow!("TLS connections to the proxy are not supported for tunnelling"));
+    }
+    debug!("Tunnelling to {}:{} through proxy {}:{}", host, port, proxy_host, proxy_port);
+
+    let mut stream = TcpStream::connect((proxy_host, proxy_port))
+        .await
+        .with_context(|| format!("Failed to connect to proxy {}:{}", proxy_host, proxy_port))?;
+    stream.write_all(connect_request(&proxy, host, port).as_bytes()).await?;
+
+    // Read the response header byte by byte so nothing after it is consumed
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_CONNECT_RESPONSE {
+            return Err(anyhow!("Proxy CONNECT response too large"));
+        }
+        let byte = stream.read_u8().await.context("Proxy closed the connection")?;
+        response.push(byte);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(stream),
+        _ => Err(anyhow!("Proxy refused CONNECT to {}:{}: {}", host, port, status_line)),
+    }
+}
+
+fn connect_request(proxy: &Url, host: &str, port: u16) -> String {
+    let authority = if host.contains(':') { format!("[{}]:{}", host, port) } else { format!("{}:{}", host, port) };
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if !proxy.username().is_empty() {
+        let credentials = format!("{}:{}", proxy.username(), proxy.password().unwrap_or_default());
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", BASE64.encode(credentials)));
+    }
+    request.push_str("\r\n");
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(no_proxy: &[&str]) -> ProxyConfig {
+        ProxyConfig {
+            http: Some(Url::parse("http://proxy.corp:3128").unwrap()),
+            https: Some(Url::parse("http://proxy.corp:3129").unwrap()),
+            no_proxy: no_proxy.iter().map(|h| h.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_proxy_selection_and_no_proxy() {
+        let config = config(&[".internal", "metrics.local:8080", "10.0.0.5"]);
+        let proxy = |url: &str| config.proxy_for(&Url::parse(url).unwrap()).map(|p| p.port().unwrap());
+
+        assert_eq!(proxy("http://api.github.com"), Some(3128));
+        assert_eq!(proxy("https://api.github.com"), Some(3129));
+        assert_eq!(proxy("wss://api.github.com/ws"), Some(3129));
+        assert_eq!(proxy("mqtts://broker.example.com:8883"), Some(3129));
+        assert_eq!(proxy("mqtt://broker.example.com"), Some(3128));
+        assert_eq!(proxy("https://node.internal/api"), None);
+        assert_eq!(proxy("https://internal/api"), None);
+        assert_eq!(proxy("https://notinternal/api"), Some(3129));
+        assert_eq!(proxy("http://metrics.local"), None);
+        assert_eq!(proxy("http://10.0.0.5:3000"), None);
+        assert_eq!(proxy("http://10.0.0.6:3000"), Some(3128));
+        assert_eq!(ProxyConfig { no_proxy: vec!["*".to_string()], ..config }.proxy_for(&Url::parse("http://a.b").unwrap()), None);
+    }
+
+    #[test]
+    fn test_connect_request() {
+        let proxy = parse_proxy_url("user:secret@proxy.corp:3128").unwrap();
+        let request = connect_request(&proxy, "api.a14a.org", 443);
+        assert!(request.starts_with("CONNECT api.a14a.org:443 HTTP/1.1\r\nHost: api.a14a.org:443\r\n"));
+        assert!(request.contains(&format!("Proxy-Authorization: Basic {}\r\n", BASE64.encode("user:secret"))));
+        assert!(request.ends_with("\r\n\r\n"));
+        assert!(parse_proxy_url("socks5://proxy:1080").is_err());
+    }
+}
@@ -6,28 +6,25 @@ use anyhow::{Result, Context, anyhow};
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::process::Command;
-use log::{debug, info, warn};
+use log::{debug, error, info, warn};
 use chrono::Utc;
-use crate::updater::UpdateConfig;
+use crate::updater::codesign;
+use crate::updater::service::{self, ApplyMode, InstallLayout, ServiceManager};
+use crate::updater::slots::SlotLayout;
+use crate::updater::{UpdateConfig, Version};
 use std::os::unix::fs::PermissionsExt;
 
-/// Location of the current application binary
-// For testing, use a local binary path
-const APP_BINARY_PATH: &str = "./target/debug/node-controller-rust";
-//const APP_BINARY_PATH: &str = "/Applications/NodeController/bin/node-controller";
-
-/// Location of the restore script that will be created
-// For testing, use a local path
-const RESTORE_SCRIPT_PATH: &str = "./temp-updates/restore.sh";
-//const RESTORE_SCRIPT_PATH: &str = "/Library/NodeController/updates/restore.sh";
+/// File in a backup holding the version that was backed up
+const VERSION_FILE: &str = "VERSION";
 
 /// Create a backup of the current installation
-pub async fn create_backup(update_dir: &Path) -> Result<PathBuf> {
+pub async fn create_backup(config: &UpdateConfig, current_version: &Version) -> Result<PathBuf> {
     info!("Creating backup of current installation");
+    let layout = &config.install;
     
     // Create backup directory
     let timestamp = Utc::now().format("%Y%m%d_%H%M%S").to_string();
-    let backup_dir = update_dir.join(format!("backup_{}", timestamp));
+    let backup_dir = config.update_dir.join(format!("backup_{}", timestamp));
     
     fs::create_dir_all(&backup_dir).await
         .context("Failed to create backup directory")?;
@@ -40,33 +37,33 @@ pub async fn create_backup(update_dir: &Path) -> Result<PathBuf> {
         .context("Failed to create bin directory in backup")?;
     
     // Check if the application binary exists
-    if !Path::new(APP_BINARY_PATH).exists() {
-        return Err(anyhow!("Application binary not found at {}", APP_BINARY_PATH));
+    if !layout.binary_path.exists() {
+        return Err(anyhow!("Application binary not found at {}", layout.binary_path.display()));
     }
     
     // Copy application binary
-    fs::copy(APP_BINARY_PATH, bin_dir.join("node-controller")).await
+    fs::copy(&layout.binary_path, bin_dir.join("node-controller")).await
         .context("Failed to copy application binary to backup")?;
     
     info!("Application binary backed up successfully");
     
+    // Remember which version this is for manual rollbacks
+    fs::write(backup_dir.join(VERSION_FILE), current_version.to_string()).await
+        .context("Failed to record backup version")?;
+    
     // Backup configuration files
-    backup_config_files(&backup_dir).await?;
+    backup_config_files(&layout.config_dir, &backup_dir).await?;
     
     // Create restore script
-    create_restore_script(&backup_dir).await?;
+    create_restore_script(layout, &backup_dir).await?;
     
     Ok(backup_dir)
 }
 
 /// Backup configuration files
-async fn backup_config_files(backup_dir: &Path) -> Result<()> {
+async fn backup_config_files(config_dir: &Path, backup_dir: &Path) -> Result<()> {
     info!("Backing up configuration files");
     
-    // For testing, use a local config dir
-    let config_dir = Path::new("./temp-updates/config");
-    //let config_dir = Path::new("/Library/NodeController/config");
-    
     if !config_dir.exists() {
         warn!("Config directory not found, skipping config backup");
         return Ok(());
@@ -133,9 +130,17 @@ async fn copy_directory_contents(from: &Path, to: &Path) -> Result<()> {
 }
 
 /// Create a restore script that can recover from a failed update
-async fn create_restore_script(backup_dir: &Path) -> Result<()> {
+async fn create_restore_script(layout: &InstallLayout, backup_dir: &Path) -> Result<()> {
     info!("Creating restore script");
     
+    let (stop_service, start_service) = layout.service.script_commands();
+    let (chown_binary, chown_config) = match layout.service.owner() {
+        Some(owner) => (
+            format!(r#"chown {} "$BINARY_PATH""#, owner),
+            format!(r#"chown -R {} "$CONFIG_DIR""#, owner),
+        ),
+        None => (":".to_string(), ":".to_string()),
+    };
     let script_content = format!(
         r#"#!/bin/bash
 # Auto-generated restore script for node-controller
@@ -145,86 +150,107 @@ async fn create_restore_script(backup_dir: &Path) -> Result<()> {
 set -e
 
 BACKUP_DIR="{}"
-APP_DIR="/Applications/NodeController"
-CONFIG_DIR="/Library/NodeController/config"
+BINARY_PATH="{}"
+CONFIG_DIR="{}"
 
 echo "Restoring node-controller from backup..."
 
 # Stop the service
-if launchctl list | grep -q "org.a14a.node-controller"; then
-    echo "Stopping node-controller service..."
-    launchctl unload /Library/LaunchDaemons/org.a14a.node-controller.plist || true
-fi
+echo "Stopping node-controller service..."
+{}
 
 # Restore application binary
 echo "Restoring application binary..."
-mkdir -p "$APP_DIR/bin"
-cp "$BACKUP_DIR/bin/node-controller" "$APP_DIR/bin/node-controller"
-chmod 755 "$APP_DIR/bin/node-controller"
-chown root:wheel "$APP_DIR/bin/node-controller"
+mkdir -p "$(dirname "$BINARY_PATH")"
+cp "$BACKUP_DIR/bin/node-controller" "$BINARY_PATH"
+chmod 755 "$BINARY_PATH"
+{}
 
 # Restore configuration files
 if [ -d "$BACKUP_DIR/config" ]; then
     echo "Restoring configuration files..."
     mkdir -p "$CONFIG_DIR"
-    cp -R "$BACKUP_DIR/config/"* "$CONFIG_DIR/"
-    chown -R root:wheel "$CONFIG_DIR"
+    cp -R "$BACKUP_DIR/config/". "$CONFIG_DIR/"
+    {}
 fi
 
 # Restart the service
 echo "Restarting node-controller service..."
-launchctl load /Library/LaunchDaemons/org.a14a.node-controller.plist
+{}
 
 echo "Restore completed successfully!"
 "#,
         Utc::now().to_rfc3339(),
-        backup_dir.display()
+        backup_dir.display(),
+        layout.binary_path.display(),
+        layout.config_dir.display(),
+        stop_service,
+        chown_binary,
+        chown_config,
+        start_service,
     );
     
     // Write the restore script
-    fs::write(RESTORE_SCRIPT_PATH, script_content).await
+    let script_path = &layout.restore_script;
+    if let Some(parent) = script_path.parent() {
+        fs::create_dir_all(parent).await
+            .context("Failed to create restore script directory")?;
+    }
+    fs::write(script_path, script_content).await
         .context("Failed to write restore script")?;
     
     // Make the script executable
-    let mut perms = fs::metadata(RESTORE_SCRIPT_PATH).await?.permissions();
+    let mut perms = fs::metadata(script_path).await?.permissions();
     perms.set_mode(0o755); // rwxr-xr-x
-    fs::set_permissions(RESTORE_SCRIPT_PATH, perms).await
+    fs::set_permissions(script_path, perms).await
         .context("Failed to set permissions on restore script")?;
     
-    info!("Restore script created at {}", RESTORE_SCRIPT_PATH);
+    info!("Restore script created at {}", script_path.display());
     Ok(())
 }
 
 /// Restore from a backup after a failed update
-pub async fn restore_from_backup(backup_dir: &Path) -> Result<()> {
+pub async fn restore_from_backup(backup_dir: &Path, layout: &InstallLayout) -> Result<()> {
     info!("Restoring from backup at {}", backup_dir.display());
     
-    // For testing, we'll just log instead of actually restoring
-    info!("TEST MODE: Restore from backup simulated successfully");
-    
-    /*
-    // Execute the restore script
-    let output = Command::new("sudo")
-        .arg(RESTORE_SCRIPT_PATH)
-        .output()
-        .await
-        .context("Failed to execute restore script")?;
-    
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        error!("Restore script failed: {}", stderr);
-        return Err(anyhow!("Restore script failed: {}", stderr));
+    // Execute the restore script written with the backup; it only needs root to manage a service
+    let command = ["/bin/bash".to_string(), layout.restore_script.display().to_string()];
+    if let Err(e) = service::run(&command, layout.service != ServiceManager::None).await {
+        error!("Restore script failed: {:#}", e);
+        return Err(e.context("Restore script failed"));
     }
     
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    info!("Restore completed: {}", stdout);
-    */
-    
+    info!("Restore completed");
     Ok(())
 }
 
+/// Restore `backup_dir` on request, rewriting the restore script for it first
+pub async fn rollback(backup_dir: &Path, layout: &InstallLayout) -> Result<()> {
+    create_restore_script(layout, backup_dir).await?;
+    restore_from_backup(backup_dir, layout).await
+}
+
+/// Switch A/B installs back to the previous slot, restarting the service around it.
+/// Returns the version switched to.
+pub async fn switch_to_previous_slot(slots: &SlotLayout, layout: &InstallLayout) -> Result<String> {
+    if layout.apply_mode == ApplyMode::Service {
+        layout.service.stop().await?;
+        let version = slots.switch_back().await?;
+        layout.service.start().await?;
+        Ok(version)
+    } else {
+        slots.switch_back().await
+    }
+}
+
+/// Version recorded in a backup, for backups that have one
+pub async fn backup_version(backup_dir: &Path) -> Option<String> {
+    fs::read_to_string(backup_dir.join(VERSION_FILE)).await.ok()
+        .map(|version| version.trim().to_string())
+}
+
 /// Install an update from a downloaded file
-pub async fn install_update(download_path: &Path, config: &UpdateConfig) -> Result<()> {
+pub async fn install_update(download_path: &Path, config: &UpdateConfig, version: &str) -> Result<()> {
     info!("Installing update from {}", download_path.display());
     
     // Create a temporary directory for extraction
@@ -237,38 +263,62 @@ pub async fn install_update(download_path: &Path, config: &UpdateConfig) -> Resu
     fs::create_dir_all(&extract_dir).await
         .context("Failed to create temporary extraction directory")?;
     
-    // Extract the archive
-    if download_path.extension().map_or(false, |ext| ext == "zip") {
-        extract_zip(download_path, &extract_dir).await?;
-    } else if download_path.to_string_lossy().ends_with(".tar.gz") || 
-              download_path.extension().map_or(false, |ext| ext == "gz") 
-    {
-        extract_tar(download_path, &extract_dir).await?;
+    // Extract the archive; binaries produced from a patch are installed as they are
+    let binary_path = if download_path.extension().is_some_and(|ext| ext == "patched") {
+        download_path.to_path_buf()
     } else {
-        return Err(anyhow!("Unknown archive format for {}", download_path.display()));
-    }
-    
-    // Find the binary in the extracted files
-    let binary_path = find_binary_in_directory(&extract_dir).await?;
-    
-    // Stop the service
-    stop_service().await?;
-    
-    // Install the new binary
-    fs::copy(&binary_path, APP_BINARY_PATH).await
-        .context("Failed to copy new binary to installation directory")?;
+        if download_path.extension().is_some_and(|ext| ext == "zip") {
+            extract_zip(download_path, &extract_dir).await?;
+        } else if download_path.to_string_lossy().ends_with(".tar.gz") || 
+                  download_path.extension().is_some_and(|ext| ext == "gz") 
+        {
+            extract_tar(download_path, &extract_dir).await?;
+        } else {
+            return Err(anyhow!("Unknown archive format for {}", download_path.display()));
+        }
+        
+        // Find the binary in the extracted files
+        find_binary_in_directory(&extract_dir).await?
+    };
     
-    // Set proper permissions
-    let mut perms = fs::metadata(APP_BINARY_PATH).await?.permissions();
-    perms.set_mode(0o755); // rwxr-xr-x
-    fs::set_permissions(APP_BINARY_PATH, perms).await
-        .context("Failed to set permissions on new binary")?;
+    // The checksum covers the archive; the binary itself must be signed by us
+    if let Some(policy) = &config.codesign {
+        codesign::verify(&binary_path, policy).await?;
+    }
     
-    // Set ownership
-    set_ownership(APP_BINARY_PATH, "root", "wheel").await?;
+    let layout = &config.install;
     
-    // Start the service
-    start_service().await?;
+    if let Some(slots) = &layout.slots {
+        // Stage into the inactive slot and switch over to it
+        let slot = slots.stage(&binary_path, version).await?;
+        set_binary_attributes(&slots.slot_binary(slot), &layout.service).await?;
+        if layout.apply_mode == ApplyMode::Service {
+            layout.service.stop().await?;
+            slots.activate(slot).await?;
+            layout.service.start().await?;
+        } else {
+            slots.activate(slot).await?;
+        }
+    } else if layout.apply_mode == ApplyMode::Service {
+        // Stop the service
+        layout.service.stop().await?;
+        
+        // Install the new binary
+        fs::copy(&binary_path, &layout.binary_path).await
+            .context("Failed to copy new binary to installation directory")?;
+        set_binary_attributes(&layout.binary_path, &layout.service).await?;
+        
+        // Start the service
+        layout.service.start().await?;
+    } else {
+        // Swap the binary under the running agent, which restarts itself afterwards
+        let staged_path = layout.binary_path.with_extension("new");
+        fs::copy(&binary_path, &staged_path).await
+            .context("Failed to copy new binary to installation directory")?;
+        set_binary_attributes(&staged_path, &layout.service).await?;
+        fs::rename(&staged_path, &layout.binary_path).await
+            .context("Failed to replace the running binary")?;
+    }
     
     // Execute any post-update commands
     for cmd in &config.post_update_commands {
@@ -283,6 +333,21 @@ pub async fn install_update(download_path: &Path, config: &UpdateConfig) -> Resu
     Ok(())
 }
 
+/// Make an installed binary executable and owned like the rest of the installation
+async fn set_binary_attributes(path: &Path, service: &ServiceManager) -> Result<()> {
+    // Set proper permissions
+    let mut perms = fs::metadata(path).await?.permissions();
+    perms.set_mode(0o755); // rwxr-xr-x
+    fs::set_permissions(path, perms).await
+        .context("Failed to set permissions on new binary")?;
+    
+    // Set ownership
+    if let Some(owner) = service.owner() {
+        set_ownership(path, owner).await?;
+    }
+    Ok(())
+}
+
 /// Extract a zip archive
 async fn extract_zip(zip_path: &Path, target_dir: &Path) -> Result<()> {
     debug!("Extracting zip archive: {} to {}", zip_path.display(), target_dir.display());
@@ -394,81 +459,13 @@ async fn find_executable_file(dir: &Path) -> Result<PathBuf> {
     Err(anyhow!("No executable file found in {}", dir.display()))
 }
 
-/// Stop the node-controller service
-async fn stop_service() -> Result<()> {
-    info!("Stopping node-controller service");
-    
-    // For testing, we'll just log instead of actually stopping a service
-    info!("TEST MODE: Service stop simulated successfully");
-    
-    /*
-    let output = Command::new("sudo")
-        .arg("launchctl")
-        .arg("unload")
-        .arg("/Library/LaunchDaemons/org.a14a.node-controller.plist")
-        .output()
-        .await
-        .context("Failed to execute launchctl unload command")?;
-    
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        warn!("Warning when stopping service: {}", stderr);
-        // Don't return an error, as the service might not be running
-    }
-    
-    // Add a small delay to ensure the service is stopped
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-    */
-    
-    Ok(())
-}
-
-/// Start the node-controller service
-async fn start_service() -> Result<()> {
-    info!("Starting node-controller service");
-    
-    // For testing, we'll just log instead of actually starting a service
-    info!("TEST MODE: Service start simulated successfully");
-    
-    /*
-    let output = Command::new("sudo")
-        .arg("launchctl")
-        .arg("load")
-        .arg("/Library/LaunchDaemons/org.a14a.node-controller.plist")
-        .output()
-        .await
-        .context("Failed to execute launchctl load command")?;
-    
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("Failed to start service: {}", stderr));
-    }
-    */
-    
-    Ok(())
-}
-
-/// Set ownership of a file
-async fn set_ownership(path: &str, user: &str, group: &str) -> Result<()> {
-    debug!("Setting ownership of {} to {}:{}", path, user, group);
-    
-    // For testing, we'll just log instead of actually changing ownership
-    debug!("TEST MODE: Ownership set simulated for {}:{}", user, group);
-    
-    /*
-    let output = Command::new("sudo")
-        .arg("chown")
-        .arg(format!("{}:{}", user, group))
-        .arg(path)
-        .output()
-        .await
-        .context("Failed to execute chown command")?;
+/// Set ownership of a file to `owner` (`user:group`)
+async fn set_ownership(path: &Path, owner: &str) -> Result<()> {
+    debug!("Setting ownership of {} to {}", path.display(), owner);
     
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(anyhow!("Failed to set ownership: {}", stderr));
-    }
-    */
+    let command = ["chown".to_string(), owner.to_string(), path.display().to_string()];
+    service::run(&command, true).await
+        .context("Failed to set ownership")?;
     
     debug!("Ownership set successfully");
     Ok(())
@@ -497,10 +494,8 @@ async fn execute_post_update_command(command: &str) -> Result<()> {
     Ok(())
 }
 
-/// Clean up old backups, keeping only the most recent ones
-pub async fn cleanup_old_backups(update_dir: &Path, max_backups: usize) -> Result<()> {
-    info!("Cleaning up old backups, keeping {} most recent", max_backups);
-    
+/// Backup directories in `update_dir`, newest first
+pub async fn list_backups(update_dir: &Path) -> Result<Vec<PathBuf>> {
     // Find all backup directories
     let mut backups = Vec::new();
     let mut entries = fs::read_dir(update_dir).await?;
@@ -526,6 +521,15 @@ pub async fn cleanup_old_backups(update_dir: &Path, max_backups: usize) -> Resul
         b_name.cmp(a_name)  // Reverse order
     });
     
+    Ok(backups)
+}
+
+/// Clean up old backups, keeping only the most recent ones
+pub async fn cleanup_old_backups(update_dir: &Path, max_backups: usize) -> Result<()> {
+    info!("Cleaning up old backups, keeping {} most recent", max_backups);
+    
+    let backups = list_backups(update_dir).await?;
+    
     // Remove old backups
     if backups.len() > max_backups {
         for old_backup in backups.iter().skip(max_backups) {
@@ -537,4 +541,4 @@ pub async fn cleanup_old_backups(update_dir: &Path, max_backups: usize) -> Resul
     
     info!("Backup cleanup completed");
     Ok(())
-} 
\ No newline at end of file
+}
@@ -0,0 +1,61 @@
+// src/updater/checksums.rs
+//
+// Release checksums
+// Releases publish a `SHA256SUMS` asset in the format written by `shasum -a 256`
+// or `sha256sum`, optionally signed like any other asset. The entry for the
+// downloaded asset is what the download is verified against.
+
+use serde::{Deserialize, Serialize};
+
+/// Name of the checksums asset of a release
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// The checksums asset of a release
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ChecksumsAsset {
+    pub download_url: String,
+    /// Detached minisign signature of the checksums file
+    pub signature_url: Option<String>,
+}
+
+/// Checksum of `file_name` in the contents of a checksums file. Accepts the
+/// `<hash>  <name>` / `<hash> *<name>` and BSD `SHA256 (<name>) = <hash>` formats.
+pub fn find_checksum(contents: &str, file_name: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let line = line.trim();
+        let (hash, name) = match line.strip_prefix("SHA256 (") {
+            Some(rest) => {
+                let (name, hash) = rest.rsplit_once(") = ")?;
+                (hash, name)
+            }
+            None => {
+                let (hash, name) = line.split_once(char::is_whitespace)?;
+                (hash, name.trim_start().trim_start_matches('*'))
+            }
+        };
+        let is_sha256 = hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit());
+        // Entries may carry a directory, e.g. `./dist/<name>`
+        let matches = name == file_name || name.rsplit('/').next() == Some(file_name);
+        (is_sha256 && matches).then(|| hash.to_ascii_lowercase())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_checksum() {
+        let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        let contents = format!(
+            "{}  node-controller-linux.tar.gz\n{} *dist/node-controller-macos.zip\n",
+            "0".repeat(64), hash.to_uppercase()
+        );
+        assert_eq!(find_checksum(&contents, "node-controller-macos.zip").as_deref(), Some(hash));
+        assert_eq!(find_checksum(&contents, "node-controller-macos.zip.minisig"), None);
+
+        let bsd = format!("SHA256 (node-controller-macos.zip) = {}\n", hash);
+        assert_eq!(find_checksum(&bsd, "node-controller-macos.zip").as_deref(), Some(hash));
+        assert_eq!(find_checksum("not-a-hash  node-controller-macos.zip", "node-controller-macos.zip"), None);
+    }
+}
@@ -0,0 +1,161 @@
+// src/updater/codesign.rs
+//
+// Code signature verification on macOS
+// A matching checksum only shows the download is what the release page lists.
+// Before a new binary is installed on macOS it must also carry a valid
+// Developer ID signature from the expected team, and optionally a notarization
+// ticket, so a binary swapped in upstream of the checksums is still refused.
+
+use anyhow::{Result, Context, anyhow};
+use std::path::Path;
+use tokio::process::Command;
+use log::{debug, info};
+
+/// Requirements for the signature of new binaries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodesignPolicy {
+    /// Team identifier the binary must be signed by; any Developer ID team when None
+    pub team_id: Option<String>,
+    /// Also require the binary to be notarized, as assessed by Gatekeeper
+    pub require_notarization: bool,
+}
+
+/// Signing details reported by `codesign -dv`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SigningInfo {
+    adhoc: bool,
+    team_id: Option<String>,
+    /// Leaf certificate, e.g. `Developer ID Application: Example Inc (ABCDE12345)`
+    authority: Option<String>,
+}
+
+/// Refuse `binary` unless its signature satisfies `policy`. Only checked on macOS.
+pub async fn verify(binary: &Path, policy: &CodesignPolicy) -> Result<()> {
+    if !cfg!(target_os = "macos") {
+        debug!("Code signatures are only verified on macOS");
+        return Ok(());
+    }
+
+    // Signature must be intact and cover the whole binary
+    let output = Command::new("codesign")
+        .args(["--verify", "--strict", "--verbose=2"])
+        .arg(binary)
+        .output()
+        .await
+        .context("Failed to execute codesign --verify")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Code signature of {} is invalid: {}",
+            binary.display(), String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // codesign prints the signing details to stderr
+    let output = Command::new("codesign")
+        .args(["--display", "--verbose=2"])
+        .arg(binary)
+        .output()
+        .await
+        .context("Failed to execute codesign --display")?;
+    let info = parse_signing_info(&String::from_utf8_lossy(&output.stderr));
+    check_signer(&info, policy)?;
+
+    if policy.require_notarization {
+        check_notarization(binary).await?;
+    }
+
+    info!("Code signature verified: {}", info.authority.as_deref().unwrap_or("unknown authority"));
+    Ok(())
+}
+
+fn parse_signing_info(display: &str) -> SigningInfo {
+    let mut info = SigningInfo::default();
+    for line in display.lines() {
+        if line.trim() == "Signature=adhoc" {
+            info.adhoc = true;
+        } else if let Some(team_id) = line.strip_prefix("TeamIdentifier=") {
+            info.team_id = Some(team_id.trim()).filter(|id| *id != "not set").map(str::to_string);
+        } else if let Some(authority) = line.strip_prefix("Authority=") {
+            // The leaf certificate comes first
+            info.authority.get_or_insert_with(|| authority.trim().to_string());
+        }
+    }
+    info
+}
+
+fn check_signer(info: &SigningInfo, policy: &CodesignPolicy) -> Result<()> {
+    if info.adhoc {
+        return Err(anyhow!("New binary is only ad-hoc signed"));
+    }
+    let team_id = info.team_id.as_deref()
+        .ok_or_else(|| anyhow!("New binary is not signed by a Developer ID team"))?;
+    if !info.authority.as_deref().is_some_and(|authority| authority.starts_with("Developer ID Application")) {
+        return Err(anyhow!(
+            "New binary is signed by {}, not a Developer ID Application certificate",
+            info.authority.as_deref().unwrap_or("an unknown authority")
+        ));
+    }
+    if let Some(expected) = policy.team_id.as_deref().filter(|expected| *expected != team_id) {
+        return Err(anyhow!("New binary is signed by team {}, expected {}", team_id, expected));
+    }
+    Ok(())
+}
+
+/// Gatekeeper assessment; `--context context:primary-signature` lets spctl assess
+/// a bare executable rather than an app bundle
+async fn check_notarization(binary: &Path) -> Result<()> {
+    let output = Command::new("spctl")
+        .args(["--assess", "--type", "open", "--context", "context:primary-signature", "-vv"])
+        .arg(binary)
+        .output()
+        .await
+        .context("Failed to execute spctl")?;
+    let assessment = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !is_notarized(&assessment) {
+        return Err(anyhow!("New binary is not notarized: {}", assessment.trim()));
+    }
+    Ok(())
+}
+
+fn is_notarized(assessment: &str) -> bool {
+    assessment.lines().any(|line| line.trim() == "source=Notarized Developer ID")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_signer() {
+        let display = "Executable=/tmp/node-controller\n\
+                       Identifier=org.a14a.node-controller\n\
+                       Format=Mach-O thin (arm64)\n\
+                       Authority=Developer ID Application: A14A B.V. (ABCDE12345)\n\
+                       Authority=Developer ID Certification Authority\n\
+                       Authority=Apple Root CA\n\
+                       TeamIdentifier=ABCDE12345\n";
+        let info = parse_signing_info(display);
+        assert_eq!(info.team_id.as_deref(), Some("ABCDE12345"));
+        assert!(check_signer(&info, &CodesignPolicy::default()).is_ok());
+        let policy = CodesignPolicy { team_id: Some("ABCDE12345".to_string()), ..CodesignPolicy::default() };
+        assert!(check_signer(&info, &policy).is_ok());
+        let policy = CodesignPolicy { team_id: Some("ZZZZZ99999".to_string()), ..CodesignPolicy::default() };
+        assert_eq!(
+            check_signer(&info, &policy).unwrap_err().to_string(),
+            "New binary is signed by team ABCDE12345, expected ZZZZZ99999"
+        );
+
+        let adhoc = parse_signing_info("Executable=/tmp/node-controller\nSignature=adhoc\nTeamIdentifier=not set\n");
+        assert!(adhoc.adhoc && adhoc.team_id.is_none());
+        assert!(check_signer(&adhoc, &CodesignPolicy::default()).is_err());
+
+        let development = parse_signing_info("Authority=Apple Development: dev@example.com (XYZ)\nTeamIdentifier=ABCDE12345\n");
+        assert!(check_signer(&development, &CodesignPolicy::default()).is_err());
+    }
+
+    #[test]
+    fn test_is_notarized() {
+        assert!(is_notarized("/tmp/node-controller: accepted\nsource=Notarized Developer ID\norigin=Developer ID Application: A14A B.V. (ABCDE12345)\n"));
+        assert!(!is_notarized("/tmp/node-controller: accepted\nsource=Developer ID\n"));
+    }
+}
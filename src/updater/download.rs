@@ -7,31 +7,52 @@ use std::path::{Path, PathBuf};
 use tokio::fs::{self, File};
 use tokio::io::AsyncWriteExt;
 use log::{debug, info, warn};
-use crate::updater::github::GithubReleaseInfo;
+use crate::updater::github::{self, GithubReleaseInfo};
+use crate::updater::checksums::{self, ChecksumsAsset, CHECKSUMS_FILE};
+use crate::updater::patch::{self, PatchAsset};
+use crate::updater::signature::{self, SIGNATURE_EXTENSION};
+use crate::updater::throttle::Throttle;
+use crate::updater::UpdateConfig;
 use tokio::process::Command as TokioCommand;
 
 /// Download a release asset to the specified directory
 pub async fn download_release(
     release: &GithubReleaseInfo,
-    update_dir: &Path,
+    config: &UpdateConfig,
 ) -> Result<PathBuf> {
+    download_asset(&release.download_url, config).await
+}
+
+/// Download the file at `url` into the update directory, at most at the configured rate
+async fn download_asset(url: &str, config: &UpdateConfig) -> Result<PathBuf> {
+    let update_dir = &config.update_dir;
+    let limit = config.effective_download_limit();
+    
     // Create the update directory if it doesn't exist
     fs::create_dir_all(update_dir).await
         .context("Failed to create update directory")?;
         
     // Determine file name from download URL
-    let file_name = extract_filename_from_url(&release.download_url)?;
+    let file_name = extract_filename_from_url(url)?;
     let download_path = update_dir.join(file_name);
     
-    info!("Downloading update from {} to {}", release.download_url, download_path.display());
+    // Bundles on local or removable media are copied
+    if let Some(source) = local_path(url)? {
+        info!("Copying update from {} to {}", source.display(), download_path.display());
+        fs::copy(&source, &download_path).await
+            .context(format!("Failed to copy {}", source.display()))?;
+        return Ok(download_path);
+    }
+    
+    info!("Downloading update from {} to {}", url, download_path.display());
     
     // Create the HTTP client
-    let client = reqwest::Client::builder()
+    let client = crate::proxy::client_builder()
         .user_agent("node-controller-updater")
         .build()?;
         
     // Download the file with progress tracking
-    let response = client.get(&release.download_url)
+    let response = github::asset_request(&client, url, config.github_token.as_ref()).await?
         .send()
         .await
         .context("Failed to start download")?;
@@ -50,12 +71,19 @@ pub async fn download_release(
     // Download the file in chunks
     let mut stream = response.bytes_stream();
     let mut downloaded: u64 = 0;
+    let mut throttle = limit.map(Throttle::new);
+    if let Some(limit) = limit {
+        debug!("Limiting download to {} KiB/s", limit.bytes_per_sec / 1024);
+    }
     
     use futures_util::StreamExt;
     while let Some(item) = stream.next().await {
         let chunk = item.context("Error while downloading file")?;
         file.write_all(&chunk).await
             .context("Error while writing to file")?;
+        if let Some(throttle) = &mut throttle {
+            throttle.consume(chunk.len()).await;
+        }
             
         // Update progress
         downloaded += chunk.len() as u64;
@@ -74,7 +102,7 @@ pub async fn download_release(
 }
 
 /// Verify the integrity of a downloaded release
-pub async fn verify_release(download_path: &Path, release: &GithubReleaseInfo) -> Result<()> {
+pub async fn verify_release(download_path: &Path, release: &GithubReleaseInfo, config: &UpdateConfig) -> Result<()> {
     info!("Verifying downloaded update: {}", download_path.display());
     
     // Verify file exists
@@ -95,7 +123,7 @@ pub async fn verify_release(download_path: &Path, release: &GithubReleaseInfo) -
     }
     
     // Verify checksum if available
-    if let Some(expected_sha256) = &release.sha256 {
+    if let Some(expected_sha256) = &expected_sha256(release, config).await? {
         let calculated_sha256 = calculate_sha256(download_path).await
             .context("Failed to calculate SHA256 checksum")?;
             
@@ -112,11 +140,11 @@ pub async fn verify_release(download_path: &Path, release: &GithubReleaseInfo) -
     }
     
     // If it's a zip or tar.gz file, verify it can be extracted
-    if download_path.extension().map_or(false, |ext| ext == "zip") {
+    if download_path.extension().is_some_and(|ext| ext == "zip") {
         verify_zip_archive(download_path).await?;
     } else if download_path
         .to_string_lossy()
-        .ends_with(".tar.gz") || download_path.extension().map_or(false, |ext| ext == "gz") 
+        .ends_with(".tar.gz") || download_path.extension().is_some_and(|ext| ext == "gz") 
     {
         verify_tar_archive(download_path).await?;
     }
@@ -125,8 +153,148 @@ pub async fn verify_release(download_path: &Path, release: &GithubReleaseInfo) -
     Ok(())
 }
 
-/// Extract filename from download URL
+/// SHA256 the download must match: its entry in the release's `SHA256SUMS` asset, or the
+/// checksum listed in a manifest. Channels that require a checksum refuse releases without one.
+async fn expected_sha256(release: &GithubReleaseInfo, config: &UpdateConfig) -> Result<Option<String>> {
+    let Some(checksums) = &release.checksums else {
+        if release.sha256.is_none() && config.requires_checksum() {
+            return Err(anyhow!(
+                "Release {} has no {} asset, refusing to install on the {} channel",
+                release.tag_name, CHECKSUMS_FILE, config.channel.as_tag_prefix()
+            ));
+        }
+        return Ok(release.sha256.clone());
+    };
+    
+    let file_name = extract_filename_from_url(&release.download_url)?;
+    release_checksum(checksums, &file_name, release, config).await.map(Some)
+}
+
+/// Checksum of `file_name` in the release's `SHA256SUMS` asset
+async fn release_checksum(checksums: &ChecksumsAsset, file_name: &str, release: &GithubReleaseInfo, config: &UpdateConfig) -> Result<String> {
+    let sums_path = download_asset(&checksums.download_url, config).await?;
+    // The asset carries its own signature, so an unsigned checksums file is still worth checking
+    if checksums.signature_url.is_some() {
+        verify_signature(&sums_path, checksums.signature_url.as_deref(), &release.tag_name, config).await?;
+    }
+    let contents = fs::read_to_string(&sums_path).await
+        .context(format!("Failed to read {}", sums_path.display()))?;
+    checksums::find_checksum(&contents, file_name)
+        .ok_or_else(|| anyhow!("{} of release {} has no entry for {}", CHECKSUMS_FILE, release.tag_name, file_name))
+}
+
+/// Download the binary patch for the release and apply it to the installed binary.
+/// Returns None, after logging why, when the full archive has to be downloaded instead.
+pub async fn fetch_patched_binary(release: &GithubReleaseInfo, config: &UpdateConfig) -> Option<PathBuf> {
+    let patch = release.patch.as_ref().filter(|_| config.delta_updates)?;
+    match apply_release_patch(patch, release, config).await {
+        Ok(binary) => Some(binary),
+        Err(e) => {
+            warn!("Binary patch for {} failed, downloading the full release: {:#}", release.tag_name, e);
+            None
+        }
+    }
+}
+
+async fn apply_release_patch(patch: &PatchAsset, release: &GithubReleaseInfo, config: &UpdateConfig) -> Result<PathBuf> {
+    let patch_path = download_asset(&patch.download_url, config).await?;
+    let size = fs::metadata(&patch_path).await?.len();
+    if patch.size > 0 && size != patch.size {
+        return Err(anyhow!("Patch size mismatch: expected {}, got {}", patch.size, size));
+    }
+    verify_signature(&patch_path, patch.signature_url.as_deref(), &release.tag_name, config).await?;
+    // Patching a binary other than the one the patch was made from gives garbage,
+    // so the result must match the release's checksum of the new binary
+    let expected_sha256 = patch_target_sha256(patch, release, config).await?;
+    let binary = patch::apply_patch(patch.format, &config.install.binary_path, &patch_path).await?;
+    let calculated_sha256 = calculate_sha256(&binary).await
+        .context("Failed to calculate SHA256 checksum of the patched binary")?;
+    if !calculated_sha256.eq_ignore_ascii_case(&expected_sha256) {
+        let _ = fs::remove_file(&binary).await;
+        return Err(anyhow!(
+            "Patched binary SHA256 mismatch: expected {}, got {}",
+            expected_sha256, calculated_sha256
+        ));
+    }
+    Ok(binary)
+}
+
+/// SHA256 the binary produced by `patch` must match
+async fn patch_target_sha256(patch: &PatchAsset, release: &GithubReleaseInfo, config: &UpdateConfig) -> Result<String> {
+    if let Some(sha256) = &patch.target_sha256 {
+        return Ok(sha256.clone());
+    }
+    let patch_name = extract_filename_from_url(&patch.download_url)?;
+    let (Some(checksums), Some(target_name)) = (&release.checksums, patch::target_name(&patch_name)) else {
+        return Err(anyhow!("Release {} has no checksum of the binary {} produces", release.tag_name, patch_name));
+    };
+    release_checksum(checksums, target_name, release, config).await
+}
+
+/// Download the detached signature at `signature_url` and check `download_path` against it.
+/// Unsigned or badly signed releases are refused unless signatures are optional.
+pub async fn verify_signature(
+    download_path: &Path,
+    signature_url: Option<&str>,
+    tag_name: &str,
+    config: &UpdateConfig,
+) -> Result<()> {
+    let Some(signature_url) = signature_url else {
+        if config.require_signature {
+            return Err(anyhow!("Release {} has no signature, refusing to install", tag_name));
+        }
+        warn!("Release {} is not signed, installing anyway", tag_name);
+        return Ok(());
+    };
+    
+    let signature_path = PathBuf::from(format!("{}.{}", download_path.display(), SIGNATURE_EXTENSION));
+    let body = match local_path(signature_url)? {
+        Some(source) => fs::read(&source).await
+            .context(format!("Failed to read signature {}", source.display()))?,
+        None => {
+            let client = crate::proxy::client_builder()
+                .user_agent("node-controller-updater")
+                .build()?;
+            let response = github::asset_request(&client, signature_url, config.github_token.as_ref()).await?
+                .send()
+                .await
+                .context("Failed to download release signature")?;
+            if !response.status().is_success() {
+                return Err(anyhow!("Signature download failed with status: {}", response.status()));
+            }
+            response.bytes().await.context("Error while downloading signature")?.to_vec()
+        }
+    };
+    fs::write(&signature_path, &body).await
+        .context(format!("Failed to write {}", signature_path.display()))?;
+    
+    let result = match signature::trusted_keys() {
+        Ok(keys) => signature::verify(download_path, &signature_path, &keys).await,
+        Err(e) => Err(e.context("Invalid embedded signing key")),
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) if config.require_signature => Err(e.context(format!("Refusing to install {}", tag_name))),
+        Err(e) => {
+            warn!("Could not verify signature of {}, installing anyway: {:#}", tag_name, e);
+            Ok(())
+        }
+    }
+}
+
+/// Path of a `file://` URL
+fn local_path(url: &str) -> Result<Option<PathBuf>> {
+    if !url.starts_with("file://") {
+        return Ok(None);
+    }
+    let url = reqwest::Url::parse(url).context("Invalid file URL")?;
+    let path = url.to_file_path().map_err(|_| anyhow!("Invalid file URL: {}", url))?;
+    Ok(Some(path))
+}
+
+/// Extract filename from download URL, without any query string
 fn extract_filename_from_url(url: &str) -> Result<String> {
+    let url = url.split(['?', '#']).next().unwrap_or(url);
     url.split('/')
         .last()
         .map(|s| s.to_string())
@@ -208,6 +376,57 @@ async fn verify_tar_archive(file_path: &Path) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::updater::patch::PatchFormat;
+    
+    #[tokio::test]
+    async fn test_patch_of_other_base_falls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let (base, new, patch_path) = (dir.path().join("base"), dir.path().join("new"), dir.path().join("node-controller.patch-from-0.1.0.zst"));
+        std::fs::write(&base, b"node-controller 0.1.0 ".repeat(512)).unwrap();
+        std::fs::write(&new, b"node-controller 0.2.0 ".repeat(512)).unwrap();
+        // Without zstd's own checksum the wrong base goes unnoticed by zstd
+        let made = std::process::Command::new("zstd")
+            .args(["-q", "--no-check", "--long=31"])
+            .arg(format!("--patch-from={}", base.display()))
+            .arg(&new).arg("-o").arg(&patch_path)
+            .status().unwrap();
+        assert!(made.success());
+        
+        let mut config = UpdateConfig { update_dir: dir.path().join("updates"), require_signature: false, ..Default::default() };
+        let target_sha256 = calculate_sha256(&new).await.unwrap();
+        let release = GithubReleaseInfo {
+            version: "0.2.0".to_string(),
+            name: "stable-0.2.0".to_string(),
+            tag_name: "stable-0.2.0".to_string(),
+            body: String::new(),
+            prerelease: false,
+            published_at: String::new(),
+            download_url: "https://example.com/node-controller-macos.zip".to_string(),
+            size: 0,
+            sha256: None,
+            checksums: None,
+            signature_url: None,
+            patch: Some(Box::new(PatchAsset {
+                format: PatchFormat::Zstd,
+                download_url: reqwest::Url::from_file_path(&patch_path).unwrap().to_string(),
+                size: 0,
+                signature_url: None,
+                target_sha256: Some(target_sha256.clone()),
+            })),
+            rollout_percent: None,
+            critical: false,
+        };
+        
+        config.install.binary_path = base.clone();
+        let patched = fetch_patched_binary(&release, &config).await.unwrap();
+        assert_eq!(calculate_sha256(&patched).await.unwrap(), target_sha256);
+        
+        // An installed binary other than the one the patch was made from
+        let other = dir.path().join("other");
+        std::fs::write(&other, b"node-controller 0.1.1 ".repeat(512)).unwrap();
+        config.install.binary_path = other;
+        assert_eq!(fetch_patched_binary(&release, &config).await, None);
+    }
     
     #[test]
     fn test_extract_filename_from_url() {
@@ -220,5 +439,10 @@ mod tests {
             extract_filename_from_url("https://example.com/path/to/file.tar.gz").unwrap(),
             "file.tar.gz"
         );
+        
+        assert_eq!(
+            extract_filename_from_url("https://releases.s3.amazonaws.com/a.zip?X-Amz-Signature=abc").unwrap(),
+            "a.zip"
+        );
     }
 } 
\ No newline at end of file
@@ -4,9 +4,15 @@
 // Handles checking for updates and retrieving release information
 
 use anyhow::{Result, Context, anyhow};
+use reqwest::header::ACCEPT;
+use reqwest::{StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use crate::updater::Version;
+use crate::updater::version::{self, VersionPin};
+use crate::updater::checksums::{ChecksumsAsset, CHECKSUMS_FILE};
+use crate::updater::patch::{self, PatchAsset};
+use crate::updater::signature::SIGNATURE_EXTENSION;
 use log::{debug, error, info};
 
 /// Information about a GitHub release
@@ -38,6 +44,39 @@ pub struct GithubReleaseInfo {
     
     /// SHA256 checksum for verification
     pub sha256: Option<String>,
+    
+    /// `SHA256SUMS` asset listing the checksum of the download
+    #[serde(default)]
+    pub checksums: Option<Box<ChecksumsAsset>>,
+    
+    /// Download URL of the detached minisign signature of the asset
+    #[serde(default)]
+    pub signature_url: Option<String>,
+    
+    /// Binary patch from the running version, when the release ships one
+    #[serde(default)]
+    pub patch: Option<Box<PatchAsset>>,
+    
+    /// Share of nodes (0-100) the release is offered to; all nodes when None
+    #[serde(default)]
+    pub rollout_percent: Option<u8>,
+    
+    /// Install right away, even outside the maintenance window
+    #[serde(default)]
+    pub critical: bool,
+}
+
+/// Media type of GitHub API responses
+const GITHUB_JSON: &str = "application/vnd.github+json";
+
+/// GitHub access token for private repositories and the higher authenticated rate limit
+#[derive(Clone, PartialEq, Eq)]
+pub struct GithubToken(pub String);
+
+impl std::fmt::Debug for GithubToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("GithubToken(..)")
+    }
 }
 
 /// Check for updates from GitHub releases
@@ -45,34 +84,48 @@ pub async fn check_for_updates(
     repository: &str,
     tag_prefix: &str,
     current_version: &Version,
+    pin: Option<&VersionPin>,
+    token: Option<&GithubToken>,
 ) -> Result<Option<GithubReleaseInfo>> {
     debug!("Checking for updates in repository {} with tag prefix {}", repository, tag_prefix);
     
-    let github_releases = fetch_github_releases(repository).await
+    let github_releases = fetch_github_releases(repository, token).await
         .context("Failed to fetch GitHub releases")?;
     
     // Find the latest matching release
-    let latest_release = find_latest_release(&github_releases, tag_prefix, current_version)?;
+    let latest_release = find_latest_release(&github_releases, tag_prefix, current_version, pin)?;
     
     Ok(latest_release)
 }
 
 /// Fetch releases from GitHub API
-async fn fetch_github_releases(repository: &str) -> Result<Vec<serde_json::Value>> {
-    let client = reqwest::Client::builder()
+async fn fetch_github_releases(repository: &str, token: Option<&GithubToken>) -> Result<Vec<serde_json::Value>> {
+    let client = crate::proxy::client_builder()
         .user_agent("node-controller-updater")
         .build()?;
     
     let url = format!("https://api.github.com/repos/{}/releases", repository);
     debug!("Fetching releases from GitHub API: {}", url);
     
-    let response = client.get(&url)
+    let mut request = client.get(&url).header(ACCEPT, GITHUB_JSON);
+    if let Some(token) = token {
+        request = request.bearer_auth(&token.0);
+    }
+    let response = request
         .send()
         .await
         .context("Failed to send request to GitHub API")?;
     
     if !response.status().is_success() {
         let status = response.status();
+        let rate_limited = matches!(status, StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS)
+            && response.headers().get("x-ratelimit-remaining").is_some_and(|remaining| remaining == "0");
+        if rate_limited && token.is_none() {
+            return Err(anyhow!("GitHub API rate limit exceeded; set UPDATE_GITHUB_TOKEN to use the authenticated limit"));
+        }
+        if status == StatusCode::NOT_FOUND && token.is_none() {
+            return Err(anyhow!("Repository {} not found; private repositories need UPDATE_GITHUB_TOKEN", repository));
+        }
         let body = response.text().await.unwrap_or_default();
         error!("GitHub API returned error status {}: {}", status, body);
         return Err(anyhow!("GitHub API returned error status {}", status));
@@ -84,11 +137,63 @@ async fn fetch_github_releases(repository: &str) -> Result<Vec<serde_json::Value
     Ok(releases)
 }
 
+/// Request for the release asset at `url`. With a token, assets of GitHub releases are
+/// fetched through the API, which unlike browser download URLs also serves private
+/// repositories. Other URLs are requested as they are.
+pub async fn asset_request(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&GithubToken>,
+) -> Result<reqwest::RequestBuilder> {
+    let (Some(token), Some((repository, tag, name))) = (token, parse_release_download_url(url)) else {
+        return Ok(client.get(url));
+    };
+    
+    let release_url = format!("https://api.github.com/repos/{}/releases/tags/{}", repository, tag);
+    debug!("Looking up asset {} of release {} through the GitHub API", name, tag);
+    let release: serde_json::Value = client.get(&release_url)
+        .header(ACCEPT, GITHUB_JSON)
+        .bearer_auth(&token.0)
+        .send()
+        .await
+        .context("Failed to send request to GitHub API")?
+        .error_for_status()
+        .context(format!("Failed to look up release {}", tag))?
+        .json()
+        .await
+        .context("Failed to parse GitHub API response")?;
+    
+    let asset_url = release["assets"].as_array()
+        .and_then(|assets| assets.iter().find(|asset| asset["name"].as_str() == Some(name.as_str())))
+        .and_then(|asset| asset["url"].as_str())
+        .ok_or_else(|| anyhow!("Release {} has no asset {}", tag, name))?;
+    
+    // The API redirects to storage; reqwest drops the token when following it to another host
+    Ok(client.get(asset_url)
+        .header(ACCEPT, "application/octet-stream")
+        .bearer_auth(&token.0))
+}
+
+/// Repository, tag and asset name of a `https://github.com/{owner}/{repo}/releases/download/{tag}/{name}` URL
+fn parse_release_download_url(url: &str) -> Option<(String, String, String)> {
+    let url = Url::parse(url).ok()?;
+    if url.host_str()? != "github.com" {
+        return None;
+    }
+    match url.path_segments()?.collect::<Vec<_>>().as_slice() {
+        [owner, repo, "releases", "download", tag, name] => {
+            Some((format!("{}/{}", owner, repo), tag.to_string(), name.to_string()))
+        }
+        _ => None,
+    }
+}
+
 /// Find the latest release that matches our criteria
 fn find_latest_release(
     releases: &[serde_json::Value],
     tag_prefix: &str,
     current_version: &Version,
+    pin: Option<&VersionPin>,
 ) -> Result<Option<GithubReleaseInfo>> {
     debug!("Looking for releases with tag prefix {}", tag_prefix);
     
@@ -117,9 +222,9 @@ fn find_latest_release(
             }
         };
         
-        // Skip versions that are not newer than current
-        if version <= *current_version {
-            debug!("Skipping release {}: not newer than current version {}", version, current_version);
+        // Skip versions that are not newer than current, or outside the pin
+        if !version::is_candidate(&version, current_version, pin) {
+            debug!("Skipping release {}: not a candidate to replace version {}", version, current_version);
             continue;
         }
         
@@ -143,6 +248,8 @@ fn find_latest_release(
         }
         
         let (download_url, size) = mac_asset.unwrap();
+        let signature_url = find_signature_asset(assets, &download_url);
+        let patch = find_patch_asset(assets, &current_version.to_string());
         
         // If we found a newer version, update our "latest"
         if latest_version.is_none() || version > *latest_version.as_ref().unwrap() {
@@ -161,8 +268,8 @@ fn find_latest_release(
                 .unwrap_or("")
                 .to_string();
                 
-            // Look for SHA256 checksum in release notes
-            let sha256 = extract_sha256_from_body(&body);
+            // Releases marked "[critical]" in their notes skip the maintenance window
+            let critical = body.to_lowercase().contains("[critical]");
             
             // Create release info
             let release_info = GithubReleaseInfo {
@@ -174,7 +281,12 @@ fn find_latest_release(
                 published_at,
                 download_url,
                 size,
-                sha256,
+                sha256: None,
+                checksums: find_checksums_asset(assets),
+                signature_url,
+                patch,
+                rollout_percent: None,
+                critical,
             };
             
             latest_release = Some(release_info);
@@ -236,30 +348,64 @@ fn find_mac_asset(assets: &[serde_json::Value]) -> Result<Option<(String, u64)>>
     Ok(None)
 }
 
-/// Extract SHA256 checksum from release notes
-fn extract_sha256_from_body(body: &str) -> Option<String> {
-    // Look for common formats of SHA256 checksums in release notes
-    for line in body.lines() {
-        // Look for SHA256 keyword followed by a hash
-        if line.to_lowercase().contains("sha256") || line.to_lowercase().contains("checksum") {
-            let hash_pattern = line.split_whitespace()
-                .find(|word| {
-                    word.len() == 64 && word.chars().all(|c| c.is_ascii_hexdigit())
-                });
-                
-            if let Some(hash) = hash_pattern {
-                return Some(hash.to_string());
-            }
-        }
-    }
-    
-    None
+/// Find a binary patch from `from_version`
+fn find_patch_asset(assets: &[serde_json::Value], from_version: &str) -> Option<Box<PatchAsset>> {
+    assets.iter().find_map(|asset| {
+        let format = patch::patch_format(asset["name"].as_str()?, from_version)?;
+        let download_url = asset["browser_download_url"].as_str()?.to_string();
+        Some(Box::new(PatchAsset {
+            format,
+            size: asset["size"].as_u64().unwrap_or(0),
+            signature_url: find_signature_asset(assets, &download_url),
+            download_url,
+            target_sha256: None,
+        }))
+    })
+}
+
+/// Find the detached signature published next to the asset at `download_url`
+fn find_signature_asset(assets: &[serde_json::Value], download_url: &str) -> Option<String> {
+    let asset_name = download_url.rsplit('/').next()?;
+    let signature_name = format!("{}.{}", asset_name, SIGNATURE_EXTENSION);
+    assets.iter()
+        .find(|asset| asset["name"].as_str() == Some(signature_name.as_str()))
+        .and_then(|asset| asset["browser_download_url"].as_str())
+        .map(ToString::to_string)
+}
+
+/// Find the `SHA256SUMS` asset and its signature
+fn find_checksums_asset(assets: &[serde_json::Value]) -> Option<Box<ChecksumsAsset>> {
+    let download_url = assets.iter()
+        .find(|asset| asset["name"].as_str() == Some(CHECKSUMS_FILE))
+        .and_then(|asset| asset["browser_download_url"].as_str())?
+        .to_string();
+    let signature_url = find_signature_asset(assets, &download_url).or_else(|| {
+        let signature_name = format!("{}.sig", CHECKSUMS_FILE);
+        assets.iter()
+            .find(|asset| asset["name"].as_str() == Some(signature_name.as_str()))
+            .and_then(|asset| asset["browser_download_url"].as_str())
+            .map(ToString::to_string)
+    });
+    Some(Box::new(ChecksumsAsset { download_url, signature_url }))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     
+    #[test]
+    fn test_parse_release_download_url() {
+        assert_eq!(
+            parse_release_download_url("https://github.com/a14a-org/node-controller-rust/releases/download/stable-0.2.0/node-controller-macos.zip"),
+            Some((
+                "a14a-org/node-controller-rust".to_string(),
+                "stable-0.2.0".to_string(),
+                "node-controller-macos.zip".to_string(),
+            ))
+        );
+        assert_eq!(parse_release_download_url("https://github.com/a14a-org/node-controller-rust/archive/main.zip"), None);
+        assert_eq!(parse_release_download_url("https://updates.example.com/releases/download/v1/a.zip"), None);
+    }
     
     #[test]
     fn test_extract_version_from_tag() {
@@ -289,23 +435,46 @@ mod tests {
     }
     
     #[test]
-    fn test_extract_sha256_from_body() {
-        // Test with SHA256 label
-        let body = "Release notes\n\nSHA256: 1a2b3c4d5e6f7g8h9i0j1k2l3m4n5o6p7q8r9s0t1u2v3w4x5y6z7a8b9c0d\nMore text";
+    fn test_find_signature_asset() {
+        let assets = serde_json::json!([
+            { "name": "node-controller-macos.zip", "browser_download_url": "https://example.com/node-controller-macos.zip" },
+            { "name": "node-controller-macos.zip.minisig", "browser_download_url": "https://example.com/node-controller-macos.zip.minisig" },
+        ]);
+        let assets = assets.as_array().unwrap();
         assert_eq!(
-            extract_sha256_from_body(body),
-            Some("1a2b3c4d5e6f7g8h9i0j1k2l3m4n5o6p7q8r9s0t1u2v3w4x5y6z7a8b9c0d".to_string())
+            find_signature_asset(assets, "https://example.com/node-controller-macos.zip").as_deref(),
+            Some("https://example.com/node-controller-macos.zip.minisig")
         );
-        
-        // Test with checksum label
-        let body = "Release notes\n\nChecksum: 1a2b3c4d5e6f7g8h9i0j1k2l3m4n5o6p7q8r9s0t1u2v3w4x5y6z7a8b9c0d\nMore text";
+        assert_eq!(find_signature_asset(assets, "https://example.com/node-controller-linux.tar.gz"), None);
+    }
+    
+    #[test]
+    fn test_find_checksums_asset() {
+        let assets = serde_json::json!([
+            { "name": "node-controller-macos.zip", "browser_download_url": "https://example.com/node-controller-macos.zip" },
+            { "name": "SHA256SUMS", "browser_download_url": "https://example.com/SHA256SUMS" },
+            { "name": "SHA256SUMS.sig", "browser_download_url": "https://example.com/SHA256SUMS.sig" },
+        ]);
         assert_eq!(
-            extract_sha256_from_body(body),
-            Some("1a2b3c4d5e6f7g8h9i0j1k2l3m4n5o6p7q8r9s0t1u2v3w4x5y6z7a8b9c0d".to_string())
+            find_checksums_asset(assets.as_array().unwrap()),
+            Some(Box::new(ChecksumsAsset {
+                download_url: "https://example.com/SHA256SUMS".to_string(),
+                signature_url: Some("https://example.com/SHA256SUMS.sig".to_string()),
+            }))
         );
-        
-        // Test with no checksum
-        let body = "Release notes\n\nNo checksum here\nMore text";
-        assert_eq!(extract_sha256_from_body(body), None);
+        assert_eq!(find_checksums_asset(&assets.as_array().unwrap()[..1]), None);
+    }
+    
+    #[test]
+    fn test_find_patch_asset() {
+        let assets = serde_json::json!([
+            { "name": "node-controller.patch-from-0.1.0.zst", "browser_download_url": "https://example.com/a.zst", "size": 10 },
+            { "name": "node-controller.patch-from-0.2.0.zst", "browser_download_url": "https://example.com/node-controller.patch-from-0.2.0.zst", "size": 20 },
+            { "name": "node-controller.patch-from-0.2.0.zst.minisig", "browser_download_url": "https://example.com/node-controller.patch-from-0.2.0.zst.minisig" },
+        ]);
+        let patch = find_patch_asset(assets.as_array().unwrap(), "0.2.0").unwrap();
+        assert_eq!(patch.size, 20);
+        assert_eq!(patch.signature_url.as_deref(), Some("https://example.com/node-controller.patch-from-0.2.0.zst.minisig"));
+        assert_eq!(find_patch_asset(assets.as_array().unwrap(), "0.3.0"), None);
     }
 } 
\ No newline at end of file
@@ -1,25 +1,142 @@
 // src/updater/health.rs
 //
 // Health check functionality to verify successful updates
+// After an update the new version can be required to serve its HTTP health
+// endpoint, answer a gRPC ping and deliver metrics, and to keep doing so for a
+// minimum uptime before the update is declared successful.
 
 use anyhow::{Result, Context, anyhow};
 use tokio::process::Command;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use log::{debug, info, warn};
 use tokio::time;
+use crate::networking::communication::node::node_service_client::NodeServiceClient;
+use crate::networking::communication::node::PingRequest;
+
+/// Pause between attempts while a check is still failing
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Timeout of a single probe request
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the new version has to pass before an update counts as successful
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthChecks {
+    /// HTTP endpoint of the agent that must answer with a 2xx status
+    pub http_url: Option<String>,
+    /// gRPC address of the agent's NodeService that must answer a ping
+    pub grpc_addr: Option<String>,
+    /// Marker the API client updates after each acknowledged upload; it must
+    /// change after the update was installed
+    pub delivery_marker: Option<PathBuf>,
+    /// How long the checks must keep passing after they first do
+    pub min_uptime: Duration,
+}
+
+impl HealthChecks {
+    fn is_empty(&self) -> bool {
+        self.http_url.is_none() && self.grpc_addr.is_none() && self.delivery_marker.is_none()
+    }
+}
 
 /// Verify the installation is working correctly
-/// This is called after an update is applied to ensure the system is still functioning
-pub async fn verify_installation(_timeout: Duration) -> Result<()> {
-    // In test mode, just log and return success
-    info!("Health verification simulated successfully in test mode");
-    return Ok(());
-    
-    // The real health verification would be implemented here:
-    // - Check system functionality
-    // - Verify critical services
-    // - Implement timeout mechanism with the provided duration
-    // - etc.
+/// This is called after an update is applied to ensure the system is still functioning.
+/// Each configured check is retried until `timeout`; once all pass they are run again
+/// after the `min_uptime` soak period.
+pub async fn verify_installation(checks: &HealthChecks, timeout: Duration, installed_at: SystemTime) -> Result<()> {
+    if checks.is_empty() {
+        info!("No post-update health checks configured");
+        return Ok(());
+    }
+
+    run_checks(checks, timeout, installed_at).await?;
+    if !checks.min_uptime.is_zero() {
+        info!("Health checks passed, waiting {}s before confirming the update", checks.min_uptime.as_secs());
+        time::sleep(checks.min_uptime).await;
+        run_checks(checks, timeout, installed_at).await
+            .context("Health check failed during the soak period")?;
+    }
+
+    info!("Post-update health checks passed");
+    Ok(())
+}
+
+/// Run every configured check, retrying each until `timeout` has passed
+async fn run_checks(checks: &HealthChecks, timeout: Duration, installed_at: SystemTime) -> Result<()> {
+    let deadline = time::Instant::now() + timeout;
+    if let Some(url) = &checks.http_url {
+        retry_until(deadline, || check_http(url)).await
+            .context(format!("HTTP health check of {} failed", url))?;
+    }
+    if let Some(addr) = &checks.grpc_addr {
+        retry_until(deadline, || check_grpc(addr)).await
+            .context(format!("gRPC ping of {} failed", addr))?;
+    }
+    if let Some(marker) = &checks.delivery_marker {
+        retry_until(deadline, || check_delivery(marker, installed_at)).await
+            .context("No metrics delivered since the update")?;
+    }
+    Ok(())
+}
+
+/// Run `check` until it succeeds, returning its last error once `deadline` has passed
+async fn retry_until<F, Fut>(deadline: time::Instant, mut check: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    loop {
+        match check().await {
+            Ok(()) => return Ok(()),
+            Err(e) if time::Instant::now() + RETRY_INTERVAL >= deadline => return Err(e),
+            Err(e) => {
+                debug!("Health check not passing yet: {:#}", e);
+                time::sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// The agent's health endpoint answers with a 2xx status
+async fn check_http(url: &str) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(PROBE_TIMEOUT)
+        .build()?;
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Health endpoint returned {}", response.status()));
+    }
+    Ok(())
+}
+
+/// The agent's NodeService answers a ping
+async fn check_grpc(addr: &str) -> Result<()> {
+    let endpoint = if addr.contains("://") { addr.to_string() } else { format!("http://{}", addr) };
+    let ping = async {
+        let mut client = NodeServiceClient::connect(endpoint).await?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+        client.ping(PingRequest {
+            sender_id: "updater".to_string(),
+            sender_name: "updater".to_string(),
+            message: "post-update health check".to_string(),
+            timestamp,
+        }).await?;
+        Ok::<_, anyhow::Error>(())
+    };
+    time::timeout(PROBE_TIMEOUT, ping).await
+        .map_err(|_| anyhow!("Ping timed out"))?
+}
+
+/// Metrics were delivered after the update was installed
+async fn check_delivery(marker: &Path, installed_at: SystemTime) -> Result<()> {
+    let modified = tokio::fs::metadata(marker).await
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| anyhow!("Cannot read delivery marker {}: {}", marker.display(), e))?;
+    if modified < installed_at {
+        return Err(anyhow!("Last delivery predates the update"));
+    }
+    Ok(())
 }
 
 /// Run a series of health checks to verify the installation
@@ -234,4 +351,31 @@ async fn get_file_size(path: &str) -> Result<u64> {
 async fn check_api_connectivity() -> Result<()> {
     // Implementation
 }
-*/ 
\ No newline at end of file
+*/ 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_delivery_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("last_delivery");
+        let installed_at = SystemTime::now() - Duration::from_secs(60);
+        assert!(check_delivery(&marker, installed_at).await.is_err());
+
+        std::fs::write(&marker, "2026-01-01T00:00:00Z").unwrap();
+        assert!(check_delivery(&marker, installed_at).await.is_ok());
+        assert!(check_delivery(&marker, SystemTime::now() + Duration::from_secs(60)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_checks_time_out() {
+        let checks = HealthChecks {
+            grpc_addr: Some("127.0.0.1:1".to_string()),
+            ..HealthChecks::default()
+        };
+        let err = verify_installation(&checks, Duration::from_millis(100), SystemTime::now()).await.unwrap_err();
+        assert!(err.to_string().starts_with("gRPC ping of 127.0.0.1:1 failed"));
+        assert!(verify_installation(&HealthChecks::default(), Duration::ZERO, SystemTime::now()).await.is_ok());
+    }
+}
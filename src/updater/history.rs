@@ -0,0 +1,118 @@
+// src/updater/history.rs
+//
+// Update history
+// Every update attempt is appended as one JSON line to `history.jsonl` in the
+// update directory. The file is only ever appended to, so it doubles as an
+// audit log of what was installed on a node and why updates were rolled back.
+
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// File name of the history log inside the update directory
+const HISTORY_FILE: &str = "history.jsonl";
+
+/// How an update attempt ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateOutcome {
+    Succeeded,
+    Failed,
+    /// Installed but failed verification, previous version restored
+    RolledBack,
+}
+
+/// One update attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateRecord {
+    pub from_version: String,
+    pub to_version: String,
+    pub tag_name: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub outcome: UpdateOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Why the installation was rolled back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollback_reason: Option<String>,
+}
+
+fn history_path(update_dir: &Path) -> PathBuf {
+    update_dir.join(HISTORY_FILE)
+}
+
+/// Append `record` to the history log in `update_dir`
+pub fn append(update_dir: &Path, record: &UpdateRecord) -> Result<()> {
+    std::fs::create_dir_all(update_dir)
+        .with_context(|| format!("Failed to create update directory {}", update_dir.display()))?;
+    let path = history_path(update_dir);
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open update history {}", path.display()))?;
+    file.write_all(line.as_bytes())?;
+    file.sync_data()?;
+    Ok(())
+}
+
+/// The last `limit` attempts, oldest first. Lines that don't parse (e.g. one
+/// cut short by a crash) are skipped.
+pub fn read(update_dir: &Path, limit: usize) -> Result<Vec<UpdateRecord>> {
+    let path = history_path(update_dir);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read update history {}", path.display())),
+    };
+    let records: Vec<UpdateRecord> = contents.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let skip = records.len().saturating_sub(limit);
+    Ok(records.into_iter().skip(skip).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(to_version: &str, outcome: UpdateOutcome) -> UpdateRecord {
+        UpdateRecord {
+            from_version: "0.1.0".to_string(),
+            to_version: to_version.to_string(),
+            tag_name: format!("stable-v{}", to_version),
+            started_at: Utc::now(),
+            finished_at: Utc::now(),
+            outcome,
+            error: None,
+            rollback_reason: None,
+        }
+    }
+
+    #[test]
+    fn test_history_append_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(read(dir.path(), 10).unwrap().is_empty());
+
+        let rolled_back = UpdateRecord {
+            rollback_reason: Some("Health check timed out".to_string()),
+            ..record("0.2.0", UpdateOutcome::RolledBack)
+        };
+        append(dir.path(), &rolled_back).unwrap();
+        // A truncated line from an interrupted write doesn't hide later entries
+        std::fs::OpenOptions::new().append(true).open(history_path(dir.path())).unwrap()
+            .write_all(b"{\"fromVersion\":\n").unwrap();
+        append(dir.path(), &record("0.2.1", UpdateOutcome::Succeeded)).unwrap();
+
+        let history = read(dir.path(), 10).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0], rolled_back);
+        assert_eq!(read(dir.path(), 1).unwrap()[0].to_version, "0.2.1");
+    }
+}
@@ -0,0 +1,86 @@
+// src/updater/lock.rs
+//
+// Update lock
+// Installing and rolling back replace the binary and the backups, so only one
+// may run at a time: not two agent instances sharing an install, nor a
+// requested update racing a scheduled one. An advisory flock on
+// `update.lock` in the update directory is held for the duration; the kernel
+// releases it if the holder dies, so a crash never leaves a stale lock.
+
+use anyhow::{Result, Context, anyhow};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use log::debug;
+
+/// File name of the lock inside the update directory
+const LOCK_FILE: &str = "update.lock";
+
+/// Held while an update or rollback runs; released on drop
+#[derive(Debug)]
+pub struct UpdateLock {
+    file: File,
+}
+
+impl UpdateLock {
+    /// Take the lock in `update_dir`, failing right away if someone else holds it
+    pub fn try_acquire(update_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(update_dir)
+            .with_context(|| format!("Failed to create update directory {}", update_dir.display()))?;
+        let path = update_dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open update lock {}", path.display()))?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let error = std::io::Error::last_os_error();
+            if error.raw_os_error() != Some(libc::EWOULDBLOCK) {
+                return Err(error).with_context(|| format!("Failed to lock {}", path.display()));
+            }
+            let mut holder = String::new();
+            let _ = file.read_to_string(&mut holder);
+            return Err(match holder.trim() {
+                "" => anyhow!("Another update is already in progress"),
+                pid => anyhow!("Another update is already in progress (pid {})", pid),
+            });
+        }
+
+        // Record the holder for the error message above
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        debug!("Acquired update lock {}", path.display());
+        Ok(Self { file })
+    }
+}
+
+impl Drop for UpdateLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = UpdateLock::try_acquire(dir.path()).unwrap();
+        let err = UpdateLock::try_acquire(dir.path()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Another update is already in progress (pid {})", std::process::id())
+        );
+
+        drop(lock);
+        assert!(UpdateLock::try_acquire(dir.path()).is_ok());
+    }
+}
@@ -9,17 +9,47 @@ mod download;
 mod backup;
 mod health;
 mod version;
+mod signature;
+mod patch;
+mod throttle;
+mod source;
+mod s3;
+mod rollout;
+mod window;
+mod history;
+mod checksums;
+mod service;
+mod preflight;
+mod slots;
+mod codesign;
+mod lock;
 
-pub use self::github::GithubReleaseInfo;
-pub use self::version::Version;
+pub use self::github::{GithubReleaseInfo, GithubToken};
+pub use self::version::{Version, VersionPin};
+pub use self::throttle::{RateLimit, Throttle};
+pub use self::source::{SourceConfig, UpdateSource};
+pub use self::s3::S3Config;
+pub(crate) use self::s3::hmac_sha256;
+pub(crate) use self::preflight::free_space;
+pub use self::window::MaintenanceWindow;
+pub use self::history::{UpdateOutcome, UpdateRecord};
+pub use self::service::{ApplyMode, InstallLayout, ServiceManager};
+pub use self::slots::SlotLayout;
+pub use self::health::HealthChecks;
+pub use self::codesign::CodesignPolicy;
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{mpsc, Mutex};
 use std::time::Duration;
-use log::{info, error, debug};
-use anyhow::{Result, Context};
+use log::{info, error, debug, warn};
+use anyhow::{Result, Context, anyhow};
 use dirs;
+use self::lock::UpdateLock;
+
+/// How often to check whether the maintenance window has opened
+const WINDOW_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Configuration for the update system
 #[derive(Debug, Clone)]
@@ -30,15 +60,27 @@ pub struct UpdateConfig {
     /// Which update channel to use (stable, beta, etc.)
     pub channel: UpdateChannel,
     
+    /// Highest version to install (None for the newest release)
+    pub version_pin: Option<VersionPin>,
+    
     /// Whether to apply updates automatically or just notify
     pub auto_update: bool,
     
+    /// Where to look for releases
+    pub source: SourceConfig,
+    
     /// Repository owner/name on GitHub
     pub repository: String,
     
+    /// Token for GitHub API requests and asset downloads (anonymous when None)
+    pub github_token: Option<GithubToken>,
+    
     /// Directory to store backups and downloaded updates
     pub update_dir: PathBuf,
     
+    /// Installed binary, configuration, restore script and service manager
+    pub install: InstallLayout,
+    
     /// Maximum number of backups to keep
     pub max_backups: usize,
     
@@ -47,6 +89,48 @@ pub struct UpdateConfig {
     
     /// Timeout for health checks after an update
     pub health_check_timeout: Duration,
+    
+    /// Checks the new version must pass before the update counts as successful
+    pub health_checks: HealthChecks,
+    
+    /// Refuse releases without a valid signature from an embedded key
+    pub require_signature: bool,
+    
+    /// Channels (tag prefixes) whose releases must come with a SHA256 checksum
+    pub checksum_channels: Vec<String>,
+    
+    /// Signature new binaries must carry on macOS (None to skip the check)
+    pub codesign: Option<CodesignPolicy>,
+    
+    /// Download a binary patch instead of the full archive when the release has one
+    pub delta_updates: bool,
+    
+    /// Maximum download rate for updates (None for unlimited)
+    pub download_limit: Option<RateLimit>,
+    
+    /// Download rate overrides per channel tag prefix (None for unlimited)
+    pub channel_download_limits: HashMap<String, Option<RateLimit>>,
+    
+    /// Node ID that places this node in a staged rollout cohort
+    pub node_id: Option<String>,
+    
+    /// Local times automatic updates may be installed; any time when None
+    pub maintenance_window: Option<MaintenanceWindow>,
+}
+
+impl UpdateConfig {
+    /// Download rate limit for the current channel
+    pub fn effective_download_limit(&self) -> Option<RateLimit> {
+        self.channel_download_limits
+            .get(&self.channel.as_tag_prefix())
+            .copied()
+            .unwrap_or(self.download_limit)
+    }
+    
+    /// Whether releases on the current channel must be verified against a checksum
+    pub fn requires_checksum(&self) -> bool {
+        self.checksum_channels.contains(&self.channel.as_tag_prefix())
+    }
 }
 
 impl Default for UpdateConfig {
@@ -59,12 +143,25 @@ impl Default for UpdateConfig {
         Self {
             check_interval_mins: 60, // Check every hour by default
             channel: UpdateChannel::Stable,
+            version_pin: None,
             auto_update: false,      // Default to notify-only for safety
+            source: SourceConfig::GitHub,
             repository: "a14a-org/node-controller-rust".to_string(),
+            github_token: None,
+            install: InstallLayout::platform_default(ServiceManager::None, &default_update_dir),
             update_dir: default_update_dir,
             max_backups: 3,
             post_update_commands: vec![],
             health_check_timeout: Duration::from_secs(30),
+            health_checks: HealthChecks::default(),
+            require_signature: true,
+            checksum_channels: vec!["stable".to_string()],
+            codesign: cfg!(target_os = "macos").then(CodesignPolicy::default),
+            delta_updates: true,
+            download_limit: None,
+            channel_download_limits: HashMap::new(),
+            node_id: None,
+            maintenance_window: None,
         }
     }
 }
@@ -90,24 +187,103 @@ impl UpdateChannel {
     }
 }
 
+impl std::str::FromStr for UpdateChannel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stable" => Ok(Self::Stable),
+            "beta" => Ok(Self::Beta),
+            "nightly" => Ok(Self::Nightly),
+            "" => Err(anyhow::anyhow!("Update channel must not be empty")),
+            custom => Ok(Self::Custom(custom.to_string())),
+        }
+    }
+}
+
 /// Status of the update process
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum UpdateStatus {
     Idle,
     Checking,
     UpdateAvailable(GithubReleaseInfo),
+    /// Waiting for the maintenance window to install
+    UpdateDeferred(GithubReleaseInfo),
+    /// A newer release is being rolled out, but not to this node yet
+    RolloutPending { version: String, percent: u8 },
+    /// Not enough disk space or write access to install; nothing was changed
+    PreflightFailed { version: String, reason: String },
     Downloading { version: String, progress: u8 },
     Verifying { version: String },
     BackingUp { version: String },
     Installing { version: String },
     VerifyingInstallation { version: String },
     UpdateSuccess { version: String, timestamp: chrono::DateTime<chrono::Utc> },
+    /// Installed in place; the agent has to restart to run the new version
+    RestartPending { version: String },
     UpdateFailed { version: String, error: String },
     RollingBack { version: String, reason: String },
+    /// Restored a backup on request
+    RolledBack { version: String, timestamp: chrono::DateTime<chrono::Utc> },
     NoUpdateAvailable,
     Error(String),
 }
 
+impl UpdateStatus {
+    /// Short snake_case name of the state, e.g. for remote status queries
+    pub fn state(&self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Checking => "checking",
+            Self::UpdateAvailable(_) => "update_available",
+            Self::UpdateDeferred(_) => "update_deferred",
+            Self::RolloutPending { .. } => "rollout_pending",
+            Self::PreflightFailed { .. } => "preflight_failed",
+            Self::Downloading { .. } => "downloading",
+            Self::Verifying { .. } => "verifying",
+            Self::BackingUp { .. } => "backing_up",
+            Self::Installing { .. } => "installing",
+            Self::VerifyingInstallation { .. } => "verifying_installation",
+            Self::UpdateSuccess { .. } => "update_success",
+            Self::RestartPending { .. } => "restart_pending",
+            Self::UpdateFailed { .. } => "update_failed",
+            Self::RollingBack { .. } => "rolling_back",
+            Self::RolledBack { .. } => "rolled_back",
+            Self::NoUpdateAvailable => "no_update_available",
+            Self::Error(_) => "error",
+        }
+    }
+    
+    /// Version the state refers to, if any
+    pub fn version(&self) -> Option<&str> {
+        match self {
+            Self::UpdateAvailable(release) | Self::UpdateDeferred(release) => Some(&release.version),
+            Self::RolloutPending { version, .. }
+            | Self::PreflightFailed { version, .. }
+            | Self::Downloading { version, .. }
+            | Self::Verifying { version }
+            | Self::BackingUp { version }
+            | Self::Installing { version }
+            | Self::VerifyingInstallation { version }
+            | Self::UpdateSuccess { version, .. }
+            | Self::RestartPending { version }
+            | Self::UpdateFailed { version, .. }
+            | Self::RollingBack { version, .. }
+            | Self::RolledBack { version, .. } => Some(version),
+            Self::Idle | Self::Checking | Self::NoUpdateAvailable | Self::Error(_) => None,
+        }
+    }
+    
+    /// Error or rollback reason, if any
+    pub fn detail(&self) -> Option<&str> {
+        match self {
+            Self::PreflightFailed { reason, .. } | Self::RollingBack { reason, .. } => Some(reason),
+            Self::UpdateFailed { error, .. } | Self::Error(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
 /// The Update Manager handles the update workflow
 pub struct UpdateManager {
     config: UpdateConfig,
@@ -127,6 +303,11 @@ enum UpdateCommand {
     CheckForUpdates,
     ApplyUpdate(GithubReleaseInfo),
     CancelUpdate,
+    Rollback,
+    SetChannel(UpdateChannel),
+    SetVersionPin(Option<VersionPin>),
+    /// A release was announced by a push notification, with its tag if known
+    ReleasePublished(Option<String>),
     Shutdown,
 }
 
@@ -154,6 +335,20 @@ impl UpdateManager {
         let config = self.config.clone();
         let current_version = self.current_version.clone();
         let tx = self.update_tx.clone();
+
+        if config.require_signature {
+            match signature::trusted_keys() {
+                Ok(keys) if keys.is_empty() => error!(
+                    "Update signatures are required but this build embeds no signing keys: \
+                     every update will be refused. Rebuild with UPDATE_SIGNING_KEYS set"
+                ),
+                Err(e) => error!(
+                    "Update signatures are required but the embedded signing keys are invalid ({}): \
+                     every update will be refused", e
+                ),
+                Ok(_) => {}
+            }
+        }
         
         // Spawn the background update task
         tokio::spawn(async move {
@@ -169,17 +364,25 @@ impl UpdateManager {
     /// The main update loop that handles update commands
     async fn update_loop(
         status: Arc<Mutex<UpdateStatus>>,
-        config: UpdateConfig,
+        mut config: UpdateConfig,
         current_version: Version,
         mut rx: mpsc::Receiver<UpdateCommand>,
         _tx: mpsc::Sender<UpdateCommand>,
     ) {
+        let check_interval = Duration::from_secs(config.check_interval_mins * 60);
         let mut update_interval = tokio::time::interval(
-            Duration::from_secs(config.check_interval_mins * 60)
+            source::from_config(&config).poll_interval().map_or(check_interval, |poll| poll.min(check_interval))
         );
         
+        let mut window_check = tokio::time::interval(WINDOW_CHECK_INTERVAL);
+        
         loop {
             tokio::select! {
+                // Install deferred updates when the maintenance window opens
+                _ = window_check.tick(), if config.maintenance_window.is_some() => {
+                    Self::apply_deferred(&status, &config, &current_version).await;
+                }
+                
                 // Handle scheduled update checks
                 _ = update_interval.tick() => {
                     debug!("Scheduled update check triggered");
@@ -205,13 +408,9 @@ impl UpdateManager {
                         UpdateCommand::ApplyUpdate(release) => {
                             info!("Applying update to version {}", release.version);
                             let version_str = release.version.clone();
-                            if let Err(e) = Self::apply_update(&status, &config, release).await {
+                            if let Err(e) = Self::apply_update(&status, &config, &current_version, release).await {
                                 error!("Update failed: {}", e);
-                                let mut s = status.lock().await;
-                                *s = UpdateStatus::UpdateFailed {
-                                    version: version_str,
-                                    error: e.to_string(),
-                                };
+                                Self::set_failed(&status, version_str, &e).await;
                             }
                         }
                         
@@ -221,6 +420,51 @@ impl UpdateManager {
                             *s = UpdateStatus::Idle;
                         }
                         
+                        UpdateCommand::Rollback => {
+                            if let Err(e) = Self::rollback(&status, &config, &current_version).await {
+                                error!("Rollback failed: {}", e);
+                                let mut s = status.lock().await;
+                                *s = UpdateStatus::Error(format!("Rollback failed: {}", e));
+                            }
+                        }
+                        
+                        UpdateCommand::SetChannel(channel) => {
+                            if channel != config.channel {
+                                info!("Switching update channel from {:?} to {:?}", config.channel, channel);
+                                config.channel = channel;
+                                update_interval.reset_immediately();
+                            }
+                        }
+                        
+                        UpdateCommand::ReleasePublished(tag_name) => {
+                            let prefix = config.channel.as_tag_prefix();
+                            if tag_name.as_deref().is_none_or(|tag| tag.starts_with(&prefix)) {
+                                info!("Release {} published, checking for updates",
+                                      tag_name.as_deref().unwrap_or("notification"));
+                                // The next scheduled check is a full interval away again
+                                update_interval.reset();
+                                if let Err(e) = Self::check_updates(&status, &config, &current_version).await {
+                                    error!("Update check after release notification failed: {}", e);
+                                    let mut s = status.lock().await;
+                                    *s = UpdateStatus::Error(format!("Update check failed: {}", e));
+                                }
+                            } else {
+                                debug!("Ignoring release {} outside the {} channel", tag_name.unwrap_or_default(), prefix);
+                            }
+                        }
+                        
+                        UpdateCommand::SetVersionPin(pin) => {
+                            if pin != config.version_pin {
+                                match &pin {
+                                    Some(pin) => info!("Pinning updates to version {} (downgrade allowed: {})",
+                                                       pin.max_version, pin.allow_downgrade),
+                                    None => info!("Removing update version pin"),
+                                }
+                                config.version_pin = pin;
+                                update_interval.reset_immediately();
+                            }
+                        }
+
                         UpdateCommand::Shutdown => {
                             info!("Update manager shutting down");
                             break;
@@ -231,6 +475,93 @@ impl UpdateManager {
         }
     }
     
+    /// Whether automatic updates may be installed now
+    fn in_maintenance_window(config: &UpdateConfig) -> bool {
+        config.maintenance_window.as_ref()
+            .is_none_or(|window| window.contains(chrono::Local::now().naive_local()))
+    }
+    
+    /// Install a deferred update once the maintenance window opens
+    async fn apply_deferred(status: &Arc<Mutex<UpdateStatus>>, config: &UpdateConfig, current_version: &Version) {
+        if !Self::in_maintenance_window(config) {
+            return;
+        }
+        let release = match &*status.lock().await {
+            UpdateStatus::UpdateDeferred(release) => release.clone(),
+            _ => return,
+        };
+        info!("Maintenance window open, applying deferred update to version {}", release.version);
+        let version = release.version.clone();
+        if let Err(e) = Self::apply_update(status, config, current_version, release).await {
+            error!("Deferred update failed: {}", e);
+            Self::set_failed(status, version, &e).await;
+        }
+    }
+    
+    /// Report a failed update, keeping the more specific preflight failure
+    async fn set_failed(status: &Arc<Mutex<UpdateStatus>>, version: String, error: &anyhow::Error) {
+        let mut s = status.lock().await;
+        if !matches!(*s, UpdateStatus::PreflightFailed { .. }) {
+            *s = UpdateStatus::UpdateFailed { version, error: error.to_string() };
+        }
+    }
+    
+    /// Restore the newest backup on request
+    async fn rollback(
+        status: &Arc<Mutex<UpdateStatus>>,
+        config: &UpdateConfig,
+        current_version: &Version,
+    ) -> Result<()> {
+        let _lock = UpdateLock::try_acquire(&config.update_dir)?;
+        let started_at = chrono::Utc::now();
+        {
+            let mut s = status.lock().await;
+            *s = UpdateStatus::RollingBack {
+                version: current_version.to_string(),
+                reason: "Requested".to_string(),
+            };
+        }
+        
+        // A/B installs switch back to the other slot, others restore the newest backup
+        let (source, result) = match &config.install.slots {
+            Some(slots) => {
+                info!("Rolling back by switching install slots in {}", slots.root.display());
+                ("previous slot".to_string(), backup::switch_to_previous_slot(slots, &config.install).await)
+            }
+            None => match backup::list_backups(&config.update_dir).await?.into_iter().next() {
+                Some(backup_path) => {
+                    let version = backup::backup_version(&backup_path).await.unwrap_or_else(|| "unknown".to_string());
+                    info!("Rolling back to version {} from {}", version, backup_path.display());
+                    let name = backup_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                    (name, backup::rollback(&backup_path, &config.install).await.map(|_| version))
+                }
+                None => return Err(anyhow!("No backup to roll back to in {}", config.update_dir.display())),
+            },
+        };
+        let version = result.as_ref().map_or_else(|_| "unknown".to_string(), Clone::clone);
+        let record = UpdateRecord {
+            from_version: current_version.to_string(),
+            to_version: version.clone(),
+            tag_name: source,
+            started_at,
+            finished_at: chrono::Utc::now(),
+            outcome: if result.is_ok() { UpdateOutcome::RolledBack } else { UpdateOutcome::Failed },
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            rollback_reason: Some("Requested".to_string()),
+        };
+        if let Err(e) = history::append(&config.update_dir, &record) {
+            warn!("Failed to record update history: {}", e);
+        }
+        result?;
+        
+        let mut s = status.lock().await;
+        *s = match config.install.apply_mode {
+            ApplyMode::Service => UpdateStatus::RolledBack { version, timestamp: chrono::Utc::now() },
+            ApplyMode::Exec | ApplyMode::Supervisor => UpdateStatus::RestartPending { version },
+        };
+        Ok(())
+    }
+    
     /// Check for available updates
     async fn check_updates(
         status: &Arc<Mutex<UpdateStatus>>,
@@ -239,26 +570,45 @@ impl UpdateManager {
     ) -> Result<()> {
         {
             let mut s = status.lock().await;
+            if matches!(*s, UpdateStatus::RestartPending { .. }) {
+                debug!("Not checking for updates before restarting into the installed one");
+                return Ok(());
+            }
             *s = UpdateStatus::Checking;
         }
         
-        let release = github::check_for_updates(
-            &config.repository, 
+        let source = source::from_config(config);
+        debug!("Checking {} for updates", source.name());
+        let release = source.latest_release(
             &config.channel.as_tag_prefix(),
-            current_version
+            current_version,
+            config.version_pin.as_ref(),
         ).await?;
         
         let mut s = status.lock().await;
+        if let Some(release) = &release {
+            if let Some(percent) = release.rollout_percent {
+                if !rollout::in_rollout(config.node_id.as_deref(), percent) {
+                    info!("Release {} is rolled out to {}% of nodes, not including this one yet",
+                          release.version, percent);
+                    *s = UpdateStatus::RolloutPending { version: release.version.clone(), percent };
+                    return Ok(());
+                }
+            }
+        }
         if let Some(release) = release {
             info!("Update available: {} -> {}", current_version, release.version);
             *s = UpdateStatus::UpdateAvailable(release.clone());
             
             // Auto-apply the update if auto_update is enabled
-            if config.auto_update {
+            if config.auto_update && !release.critical && !Self::in_maintenance_window(config) {
+                info!("Deferring update to version {} until the maintenance window", release.version);
+                *s = UpdateStatus::UpdateDeferred(release);
+            } else if config.auto_update {
                 info!("Auto-update is enabled, applying update to version {}", release.version);
                 // Drop the mutex lock before applying update
                 drop(s);
-                if let Err(e) = Self::apply_update(status, config, release).await {
+                if let Err(e) = Self::apply_update(status, config, current_version, release).await {
                     error!("Automatic update failed: {}", e);
                 }
             }
@@ -270,45 +620,108 @@ impl UpdateManager {
         Ok(())
     }
     
-    /// Apply an update
+    /// Apply an update and record the attempt in the update history
     async fn apply_update(
         status: &Arc<Mutex<UpdateStatus>>,
         config: &UpdateConfig,
+        current_version: &Version,
         release: GithubReleaseInfo,
     ) -> Result<()> {
-        // 1. Download update
-        {
+        // Held until the update is installed or rolled back
+        let _lock = UpdateLock::try_acquire(&config.update_dir)?;
+        let started_at = chrono::Utc::now();
+        let mut rollback_reason = None;
+        let result = Self::install_release(status, config, current_version, &release, &mut rollback_reason).await;
+        
+        let outcome = match (&result, &rollback_reason) {
+            (Ok(()), _) => UpdateOutcome::Succeeded,
+            (Err(_), Some(_)) => UpdateOutcome::RolledBack,
+            (Err(_), None) => UpdateOutcome::Failed,
+        };
+        let record = UpdateRecord {
+            from_version: current_version.to_string(),
+            to_version: release.version,
+            tag_name: release.tag_name,
+            started_at,
+            finished_at: chrono::Utc::now(),
+            outcome,
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            rollback_reason,
+        };
+        if let Err(e) = history::append(&config.update_dir, &record) {
+            warn!("Failed to record update history: {}", e);
+        }
+        result
+    }
+    
+    /// Download, verify and install a release, rolling back if the new version is unhealthy
+    async fn install_release(
+        status: &Arc<Mutex<UpdateStatus>>,
+        config: &UpdateConfig,
+        current_version: &Version,
+        release: &GithubReleaseInfo,
+        rollback_reason: &mut Option<String>,
+    ) -> Result<()> {
+        // With A/B installs the running version becomes the first slot
+        if let Some(slots) = &config.install.slots {
+            let running_binary = std::env::current_exe().context("Failed to locate the running binary")?;
+            slots.ensure_initialized(&running_binary, &current_version.to_string()).await?;
+        }
+        
+        // 0. Make sure the update can be installed before touching anything
+        if let Err(e) = preflight::check(release, config).await {
+            error!("Preflight checks for {} failed: {:#}", release.version, e);
             let mut s = status.lock().await;
-            *s = UpdateStatus::Downloading {
+            *s = UpdateStatus::PreflightFailed {
                 version: release.version.to_string(),
-                progress: 0,
+                reason: format!("{:#}", e),
             };
+            return Err(e);
         }
         
-        let download_path = download::download_release(
-            &release,
-            &config.update_dir
-        ).await?;
-        
-        // 2. Verify download
+        // 1. Download update
         {
             let mut s = status.lock().await;
-            *s = UpdateStatus::Verifying {
+            *s = UpdateStatus::Downloading {
                 version: release.version.to_string(),
+                progress: 0,
             };
         }
         
-        download::verify_release(&download_path, &release).await?;
+        // A patch against the installed binary is much smaller; fall back to the archive
+        let download_path = match download::fetch_patched_binary(release, config).await {
+            Some(binary) => binary,
+            None => {
+                let download_path = download::download_release(
+                    release,
+                    config
+                ).await?;
+                
+                // 2. Verify download
+                {
+                    let mut s = status.lock().await;
+                    *s = UpdateStatus::Verifying {
+                        version: release.version.to_string(),
+                    };
+                }
+                
+                download::verify_release(&download_path, release, config).await?;
+                download::verify_signature(&download_path, release.signature_url.as_deref(), &release.tag_name, config).await?;
+                download_path
+            }
+        };
         
-        // 3. Create backup
-        {
+        // 3. Create backup; with A/B installs the previous slot is the backup
+        let backup_path = if config.install.slots.is_none() {
             let mut s = status.lock().await;
             *s = UpdateStatus::BackingUp {
                 version: release.version.to_string(),
             };
-        }
-        
-        let backup_path = backup::create_backup(&config.update_dir).await?;
+            drop(s);
+            Some(backup::create_backup(config, current_version).await?)
+        } else {
+            None
+        };
         
         // 4. Install update
         {
@@ -318,7 +731,8 @@ impl UpdateManager {
             };
         }
         
-        backup::install_update(&download_path, config).await?;
+        let installed_at = std::time::SystemTime::now();
+        backup::install_update(&download_path, config, &release.version).await?;
         
         // 5. Verify installation
         {
@@ -328,7 +742,12 @@ impl UpdateManager {
             };
         }
         
-        if let Err(e) = health::verify_installation(config.health_check_timeout).await {
+        // Until the restart the checks would only see the running version
+        let health_checks = match config.install.apply_mode {
+            ApplyMode::Service => config.health_checks.clone(),
+            ApplyMode::Exec | ApplyMode::Supervisor => HealthChecks::default(),
+        };
+        if let Err(e) = health::verify_installation(&health_checks, config.health_check_timeout, installed_at).await {
             error!("Installation verification failed: {}", e);
             
             // Rollback to previous version
@@ -340,7 +759,12 @@ impl UpdateManager {
                 };
             }
             
-            backup::restore_from_backup(&backup_path).await?;
+            *rollback_reason = Some(e.to_string());
+            if let Some(slots) = &config.install.slots {
+                backup::switch_to_previous_slot(slots, &config.install).await?;
+            } else if let Some(backup_path) = &backup_path {
+                backup::restore_from_backup(backup_path, &config.install).await?;
+            }
             return Err(e.into());
         }
         
@@ -350,9 +774,14 @@ impl UpdateManager {
         // 7. Update success
         {
             let mut s = status.lock().await;
-            *s = UpdateStatus::UpdateSuccess {
-                version: release.version.to_string(),
-                timestamp: chrono::Utc::now(),
+            *s = match config.install.apply_mode {
+                ApplyMode::Service => UpdateStatus::UpdateSuccess {
+                    version: release.version.to_string(),
+                    timestamp: chrono::Utc::now(),
+                },
+                ApplyMode::Exec | ApplyMode::Supervisor => UpdateStatus::RestartPending {
+                    version: release.version.to_string(),
+                },
             };
         }
         
@@ -367,13 +796,102 @@ impl UpdateManager {
         Ok(())
     }
     
+    /// The last `limit` recorded update attempts, oldest first
+    pub fn history(&self, limit: usize) -> Result<Vec<UpdateRecord>> {
+        history::read(&self.config.update_dir, limit)
+    }
+    
+    /// Check for updates right away when a pushed notification announces a release
+    /// on this node's channel; `tag_name` is None when the notification has no tag
+    pub async fn release_published(&self, tag_name: Option<String>) -> Result<()> {
+        self.update_tx.send(UpdateCommand::ReleasePublished(tag_name)).await
+            .context("Failed to send release notification")?;
+        Ok(())
+    }
+    
+    /// Follow a different update channel from the next check on
+    pub async fn set_channel(&self, channel: UpdateChannel) -> Result<()> {
+        self.update_tx.send(UpdateCommand::SetChannel(channel)).await
+            .context("Failed to send set channel command")?;
+        Ok(())
+    }
+    
+    /// Install at most `max_version` from the next check on, or remove the pin with None.
+    /// Pins below the running version are refused unless `force` allows the downgrade.
+    pub async fn pin_version(&self, max_version: Option<Version>, force: bool) -> Result<()> {
+        let pin = max_version
+            .map(|max_version| VersionPin::new(max_version, &self.current_version, force))
+            .transpose()?;
+        self.update_tx.send(UpdateCommand::SetVersionPin(pin)).await
+            .context("Failed to send version pin command")?;
+        Ok(())
+    }
+
+    /// Restore the newest backup, e.g. when a release misbehaves after passing its health check
+    pub async fn rollback_to_previous(&self) -> Result<()> {
+        self.update_tx.send(UpdateCommand::Rollback).await
+            .context("Failed to send rollback command")?;
+        Ok(())
+    }
+
+    /// Check for updates in the foreground, without the background task (e.g. from
+    /// the command line); the status the check ended in
+    pub async fn check_now(&self) -> Result<UpdateStatus> {
+        Self::check_updates(&self.status, &self.config, &self.current_version).await?;
+        Ok(self.status().await)
+    }
+    
+    /// Install the update found by `check_now` in the foreground. With `version`,
+    /// only if that is the version available.
+    pub async fn apply_now(&self, version: Option<&str>) -> Result<UpdateStatus> {
+        let release = available_release(self.status().await, version)?;
+        let version = release.version.clone();
+        if let Err(e) = Self::apply_update(&self.status, &self.config, &self.current_version, release).await {
+            Self::set_failed(&self.status, version, &e).await;
+            return Err(e);
+        }
+        Ok(self.status().await)
+    }
+    
+    /// Restore the newest backup in the foreground
+    pub async fn rollback_now(&self) -> Result<UpdateStatus> {
+        Self::rollback(&self.status, &self.config, &self.current_version).await?;
+        Ok(self.status().await)
+    }
+
+    /// Cloneable handle for driving updates from elsewhere, e.g. the node gRPC service
+    pub fn handle(&self) -> UpdateHandle {
+        UpdateHandle {
+            current_version: self.current_version.clone(),
+            status: self.status.clone(),
+            update_tx: self.update_tx.clone(),
+        }
+    }
+
     /// Gets the current update status
-    /// This is currently unused but part of the public API
-    #[allow(dead_code)]
     pub async fn status(&self) -> UpdateStatus {
         self.status.lock().await.clone()
     }
     
+    /// Whether an update was installed in place and the agent should shut down to
+    /// run it, see `restart_into_update`
+    pub async fn restart_pending(&self) -> bool {
+        matches!(*self.status.lock().await, UpdateStatus::RestartPending { .. })
+    }
+    
+    /// Start the installed update after the agent has shut down. Re-execs the new
+    /// binary in exec mode and returns to let the process exit for a supervisor.
+    pub fn restart_into_update(&self) -> Result<()> {
+        match self.config.install.apply_mode {
+            ApplyMode::Exec => Err(service::exec_binary(&self.config.install.binary_path)),
+            ApplyMode::Supervisor => {
+                info!("Exiting for the supervisor to start the updated agent");
+                Ok(())
+            }
+            ApplyMode::Service => Ok(()),
+        }
+    }
+    
     /// Manually triggers an update process with the provided release info
     /// This is currently unused but part of the public API
     #[allow(dead_code)]
@@ -407,4 +925,59 @@ impl UpdateManager {
     pub fn set_health_check_timeout(&mut self, timeout: Duration) {
         self.health_check_timeout = timeout;
     }
-} 
\ No newline at end of file
+}
+
+/// Shares an update manager's command channel and status, see `UpdateManager::handle`
+#[derive(Clone)]
+pub struct UpdateHandle {
+    current_version: Version,
+    status: Arc<Mutex<UpdateStatus>>,
+    update_tx: mpsc::Sender<UpdateCommand>,
+}
+
+impl UpdateHandle {
+    /// Version of the running agent
+    pub fn current_version(&self) -> &Version {
+        &self.current_version
+    }
+    
+    pub async fn status(&self) -> UpdateStatus {
+        self.status.lock().await.clone()
+    }
+    
+    /// Queue an update check
+    pub async fn check_for_updates(&self) -> Result<()> {
+        self.update_tx.send(UpdateCommand::CheckForUpdates).await
+            .context("Failed to send update check command")?;
+        Ok(())
+    }
+    
+    /// Install the update found by the last check, including one waiting for the
+    /// maintenance window. With `version`, only if that is the version available.
+    pub async fn apply_available(&self, version: Option<&str>) -> Result<()> {
+        let release = available_release(self.status().await, version)?;
+        self.update_tx.send(UpdateCommand::ApplyUpdate(release)).await
+            .context("Failed to send apply update command")?;
+        Ok(())
+    }
+    
+    /// Queue a rollback to the previous version
+    pub async fn rollback(&self) -> Result<()> {
+        self.update_tx.send(UpdateCommand::Rollback).await
+            .context("Failed to send rollback command")?;
+        Ok(())
+    }
+}
+
+/// The release an update check found, available or waiting for the maintenance
+/// window; with `version`, only if that is its version
+fn available_release(status: UpdateStatus, version: Option<&str>) -> Result<GithubReleaseInfo> {
+    let release = match status {
+        UpdateStatus::UpdateAvailable(release) | UpdateStatus::UpdateDeferred(release) => release,
+        _ => return Err(anyhow!("No update available to apply")),
+    };
+    if let Some(version) = version.filter(|version| *version != release.version) {
+        return Err(anyhow!("Version {} is available, not {}", release.version, version));
+    }
+    Ok(release)
+}
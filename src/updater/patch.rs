@@ -0,0 +1,119 @@
+// src/updater/patch.rs
+//
+// Binary patch updates
+// Releases may ship patches that turn the binary of an older version into the
+// new one (`<name>.patch-from-<version>.zst` made with `zstd --patch-from`, or
+// `.bsdiff`). They are a fraction of the full archive; any failure falls back
+// to downloading the archive.
+
+use anyhow::{Result, Context, anyhow};
+use serde::{Deserialize, Serialize};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::process::Command;
+use log::{debug, info};
+
+/// Marker in patch asset names, followed by the version the patch applies to
+const PATCH_MARKER: &str = ".patch-from-";
+
+/// Largest binary zstd patches are applied to; matches `--long=31` at creation
+const ZSTD_WINDOW_LOG: u32 = 31;
+
+/// Tool that created a patch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchFormat {
+    Zstd,
+    Bsdiff,
+}
+
+/// A patch from the running version to a release
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PatchAsset {
+    pub format: PatchFormat,
+    pub download_url: String,
+    pub size: u64,
+    pub signature_url: Option<String>,
+    /// SHA256 of the binary the patch produces; looked up in the release's
+    /// `SHA256SUMS` under the name the patch is for when not given
+    #[serde(default)]
+    pub target_sha256: Option<String>,
+}
+
+/// Format of a patch asset named for `from_version`, if `name` is one
+pub fn patch_format(name: &str, from_version: &str) -> Option<PatchFormat> {
+    let (_, rest) = name.split_once(PATCH_MARKER)?;
+    if let Some(version) = rest.strip_suffix(".zst") {
+        return (version == from_version).then_some(PatchFormat::Zstd);
+    }
+    if let Some(version) = rest.strip_suffix(".bsdiff") {
+        return (version == from_version).then_some(PatchFormat::Bsdiff);
+    }
+    None
+}
+
+/// Name of the binary the patch asset `name` produces, e.g. `node-controller`
+/// for `node-controller.patch-from-0.2.0.zst`
+pub fn target_name(name: &str) -> Option<&str> {
+    name.split_once(PATCH_MARKER).map(|(target, _)| target)
+}
+
+/// Apply `patch` to the binary at `base`, writing an executable next to the patch
+pub async fn apply_patch(format: PatchFormat, base: &Path, patch: &Path) -> Result<PathBuf> {
+    let output_path = patch.with_extension("patched");
+    if output_path.exists() {
+        fs::remove_file(&output_path).await
+            .context("Failed to remove previous patch output")?;
+    }
+    debug!("Applying {:?} patch {} to {}", format, patch.display(), base.display());
+
+    let output = match format {
+        PatchFormat::Zstd => Command::new("zstd")
+            .arg("-d")
+            .arg("-q")
+            .arg(format!("--long={}", ZSTD_WINDOW_LOG))
+            .arg(format!("--patch-from={}", base.display()))
+            .arg(patch)
+            .arg("-o")
+            .arg(&output_path)
+            .output()
+            .await
+            .context("Failed to execute zstd command")?,
+        PatchFormat::Bsdiff => Command::new("bspatch")
+            .arg(base)
+            .arg(&output_path)
+            .arg(patch)
+            .output()
+            .await
+            .context("Failed to execute bspatch command")?,
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!("Failed to apply patch: {}", stderr.trim()));
+    }
+
+    let mut perms = fs::metadata(&output_path).await?.permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&output_path, perms).await
+        .context("Failed to set permissions on patched binary")?;
+
+    info!("Patched binary written to {}", output_path.display());
+    Ok(output_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_format() {
+        assert_eq!(patch_format("node-controller.patch-from-0.2.0.zst", "0.2.0"), Some(PatchFormat::Zstd));
+        assert_eq!(patch_format("node-controller.patch-from-0.2.0.bsdiff", "0.2.0"), Some(PatchFormat::Bsdiff));
+        assert_eq!(patch_format("node-controller.patch-from-0.1.9.zst", "0.2.0"), None);
+        assert_eq!(patch_format("node-controller.patch-from-0.2.0.zst.minisig", "0.2.0"), None);
+        assert_eq!(patch_format("node-controller-macos.zip", "0.2.0"), None);
+        assert_eq!(target_name("node-controller.patch-from-0.2.0.zst"), Some("node-controller"));
+        assert_eq!(target_name("node-controller-macos.zip"), None);
+    }
+}
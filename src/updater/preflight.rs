@@ -0,0 +1,111 @@
+// src/updater/preflight.rs
+//
+// Preflight checks
+// Before anything is downloaded, make sure the update directory and the
+// install location have room for the download, the extracted binary and the
+// backup, and that the agent may write to them. Failing here leaves the
+// installation untouched instead of failing halfway through.
+
+use anyhow::{Result, Context, anyhow};
+use std::path::Path;
+use tokio::fs;
+use tokio::process::Command;
+use log::debug;
+use crate::updater::github::GithubReleaseInfo;
+use crate::updater::UpdateConfig;
+
+/// Space to leave free on top of what the update needs
+const HEADROOM_BYTES: u64 = 50 * 1024 * 1024;
+
+/// Check free space and write access for installing `release`
+pub async fn check(release: &GithubReleaseInfo, config: &UpdateConfig) -> Result<()> {
+    let layout = &config.install;
+    let install_dir = layout.binary_path.parent()
+        .ok_or_else(|| anyhow!("Install path {} has no directory", layout.binary_path.display()))?;
+    fs::create_dir_all(&config.update_dir).await
+        .context(format!("Cannot create update directory {}", config.update_dir.display()))?;
+
+    check_writable(&config.update_dir).await?;
+    check_writable(install_dir).await?;
+
+    // The archive is compressed, so assume the new binary is at least as large as the installed one
+    let installed_size = fs::metadata(&layout.binary_path).await.map(|m| m.len()).unwrap_or(0);
+    let binary_size = release.size.max(installed_size);
+    // Download, extracted binary and backup of the installed binary
+    let update_needed = release.size + binary_size + installed_size + HEADROOM_BYTES;
+    // Copy of the new binary next to the installed one
+    let install_needed = binary_size + HEADROOM_BYTES;
+
+    let (update_fs, update_available) = free_space(&config.update_dir).await?;
+    let (install_fs, install_available) = free_space(install_dir).await?;
+    if update_fs == install_fs {
+        require_space(&config.update_dir, update_available, update_needed + binary_size)?;
+    } else {
+        require_space(&config.update_dir, update_available, update_needed)?;
+        require_space(install_dir, install_available, install_needed)?;
+    }
+
+    debug!("Preflight checks passed for {}", release.tag_name);
+    Ok(())
+}
+
+fn require_space(path: &Path, available: u64, needed: u64) -> Result<()> {
+    if available < needed {
+        return Err(anyhow!(
+            "Not enough free space for {}: {} MiB needed, {} MiB available",
+            path.display(), needed / (1024 * 1024), available / (1024 * 1024)
+        ));
+    }
+    Ok(())
+}
+
+/// Fail unless a file can be created in `dir`
+async fn check_writable(dir: &Path) -> Result<()> {
+    let probe = dir.join(format!(".node-controller-preflight-{}", std::process::id()));
+    fs::write(&probe, b"").await
+        .map_err(|e| anyhow!("No write access to {}: {}", dir.display(), e))?;
+    let _ = fs::remove_file(&probe).await;
+    Ok(())
+}
+
+/// Filesystem holding `path` and its free space in bytes, from `df`
+pub(crate) async fn free_space(path: &Path) -> Result<(String, u64)> {
+    let output = Command::new("df")
+        .arg("-Pk") // POSIX format, 1K blocks
+        .arg(path)
+        .output()
+        .await
+        .context("Failed to execute df")?;
+    if !output.status.success() {
+        return Err(anyhow!("df failed for {}: {}", path.display(), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    parse_df(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| anyhow!("Unexpected df output for {}", path.display()))
+}
+
+fn parse_df(output: &str) -> Option<(String, u64)> {
+    let parts: Vec<&str> = output.lines().nth(1)?.split_whitespace().collect();
+    let available_kib = parts.get(3)?.parse::<u64>().ok()?;
+    Some((parts[0].to_string(), available_kib * 1024))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_df() {
+        let output = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n\
+                      /dev/disk3s5     971350180 512345678 459004502      53% /System/Volumes/Data\n";
+        assert_eq!(parse_df(output), Some(("/dev/disk3s5".to_string(), 459004502 * 1024)));
+        assert_eq!(parse_df("Filesystem 1024-blocks Used Available Capacity Mounted on\n"), None);
+    }
+
+    #[test]
+    fn test_require_space() {
+        let path = Path::new("/tmp");
+        assert!(require_space(path, 200 * 1024 * 1024, 100 * 1024 * 1024).is_ok());
+        let err = require_space(path, 10 * 1024 * 1024, 100 * 1024 * 1024).unwrap_err();
+        assert_eq!(err.to_string(), "Not enough free space for /tmp: 100 MiB needed, 10 MiB available");
+    }
+}
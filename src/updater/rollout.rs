@@ -0,0 +1,45 @@
+// src/updater/rollout.rs
+//
+// Staged rollouts
+// Each node hashes its node ID into one of 100 buckets. A release that
+// advertises a rollout percentage is only installed by nodes whose bucket
+// is below it, so raising the percentage gradually widens the cohort.
+
+use sha2::{Digest, Sha256};
+
+/// Rollout bucket of a node, 0 to 99. Stable for a node ID.
+pub fn rollout_bucket(node_id: &str) -> u8 {
+    let digest = Sha256::digest(node_id.as_bytes());
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(prefix) % 100) as u8
+}
+
+/// Whether a node is part of a rollout to `percent` of the fleet.
+/// Nodes without an ID only take fully rolled out releases.
+pub fn in_rollout(node_id: Option<&str>, percent: u8) -> bool {
+    if percent >= 100 {
+        return true;
+    }
+    node_id.is_some_and(|id| rollout_bucket(id) < percent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollout_cohorts() {
+        assert_eq!(rollout_bucket("node-1"), rollout_bucket("node-1"));
+        assert!(in_rollout(None, 100));
+        assert!(!in_rollout(None, 99));
+        assert!(!in_rollout(Some("node-1"), 0));
+
+        // Roughly the advertised share of nodes takes a release
+        let ids: Vec<String> = (0..1000).map(|i| format!("node-{}", i)).collect();
+        let included = ids.iter().filter(|id| in_rollout(Some(id), 25)).count();
+        assert!((180..320).contains(&included), "{} of 1000 nodes in a 25% rollout", included);
+        // Widening a rollout keeps the nodes that already had it
+        assert!(ids.iter().filter(|id| in_rollout(Some(id), 25)).all(|id| in_rollout(Some(id), 50)));
+    }
+}
@@ -0,0 +1,314 @@
+// src/updater/service.rs
+//
+// Installation layout and service managers
+// Where the agent binary, its configuration and the restore script live, and
+// how its service is stopped and started while the binary is replaced: launchd
+// on macOS, systemd on Linux. Without a service manager (e.g. in development)
+// restarting the agent is left to whoever runs it.
+
+use anyhow::{Result, Context, anyhow};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::process::Command;
+use log::{debug, info, warn};
+use crate::updater::slots::SlotLayout;
+
+/// launchd job installed by install.sh
+const DEFAULT_LAUNCHD_LABEL: &str = "com.nodecontroller.daemon";
+
+/// systemd unit shipped as node-controller.service
+const DEFAULT_SYSTEMD_UNIT: &str = "node-controller.service";
+
+/// How the agent's service is stopped and started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceManager {
+    /// launchd job with this label, loaded from /Library/LaunchDaemons/<label>.plist
+    Launchd { label: String },
+    /// systemd unit
+    Systemd { unit: String },
+    /// No service manager; the new binary is picked up on the next start
+    None,
+}
+
+impl ServiceManager {
+    /// The service manager the agent is running under, if any
+    pub fn detect() -> Self {
+        // systemd sets INVOCATION_ID for the processes of a unit
+        if cfg!(target_os = "linux") && std::env::var_os("INVOCATION_ID").is_some() {
+            return Self::Systemd { unit: DEFAULT_SYSTEMD_UNIT.to_string() };
+        }
+        // launchd sets XPC_SERVICE_NAME to the job label
+        if cfg!(target_os = "macos") {
+            if let Ok(label) = std::env::var("XPC_SERVICE_NAME") {
+                if label != "0" && !label.is_empty() {
+                    return Self::Launchd { label };
+                }
+            }
+        }
+        Self::None
+    }
+
+    fn plist_path(label: &str) -> String {
+        format!("/Library/LaunchDaemons/{}.plist", label)
+    }
+
+    /// Command that stops the service
+    fn stop_command(&self) -> Option<Vec<String>> {
+        match self {
+            Self::Launchd { label } => Some(vec!["launchctl".into(), "unload".into(), Self::plist_path(label)]),
+            Self::Systemd { unit } => Some(vec!["systemctl".into(), "stop".into(), unit.clone()]),
+            Self::None => None,
+        }
+    }
+
+    /// Command that starts the service
+    fn start_command(&self) -> Option<Vec<String>> {
+        match self {
+            Self::Launchd { label } => Some(vec!["launchctl".into(), "load".into(), Self::plist_path(label)]),
+            Self::Systemd { unit } => Some(vec!["systemctl".into(), "start".into(), unit.clone()]),
+            Self::None => None,
+        }
+    }
+
+    /// Owner of installed files, as `user:group`
+    pub fn owner(&self) -> Option<&'static str> {
+        match self {
+            Self::Launchd { .. } => Some("root:wheel"),
+            Self::Systemd { .. } => Some("root:root"),
+            Self::None => None,
+        }
+    }
+
+    /// Stop the service. Failures are only logged since it might not be running.
+    pub async fn stop(&self) -> Result<()> {
+        let Some(command) = self.stop_command() else {
+            debug!("No service manager, not stopping the service");
+            return Ok(());
+        };
+        info!("Stopping node-controller service");
+        if let Err(e) = run(&command, true).await {
+            warn!("Warning when stopping service: {:#}", e);
+        }
+        // Give the service a moment to exit
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        Ok(())
+    }
+
+    /// Start the service
+    pub async fn start(&self) -> Result<()> {
+        let Some(command) = self.start_command() else {
+            info!("No service manager, the update takes effect on the next start");
+            return Ok(());
+        };
+        info!("Starting node-controller service");
+        run(&command, true).await.context("Failed to start service")
+    }
+
+    /// Shell lines that stop and start the service, for the restore script
+    pub fn script_commands(&self) -> (String, String) {
+        let line = |command: Option<Vec<String>>| command.map(|c| c.join(" ")).unwrap_or_else(|| ":".to_string());
+        (format!("{} || true", line(self.stop_command())), line(self.start_command()))
+    }
+}
+
+impl FromStr for ServiceManager {
+    type Err = anyhow::Error;
+
+    /// `launchd`, `systemd` or `none`, optionally followed by `:<label or unit>`
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, name) = s.split_once(':').map_or((s, None), |(kind, name)| (kind, Some(name.to_string())));
+        match kind {
+            "launchd" => Ok(Self::Launchd { label: name.unwrap_or_else(|| DEFAULT_LAUNCHD_LABEL.to_string()) }),
+            "systemd" => Ok(Self::Systemd { unit: name.unwrap_or_else(|| DEFAULT_SYSTEMD_UNIT.to_string()) }),
+            "none" => Ok(Self::None),
+            other => Err(anyhow!("Unknown service manager '{}'", other)),
+        }
+    }
+}
+
+/// How a new binary takes effect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyMode {
+    /// Stop the service, replace the binary and start the service again
+    Service,
+    /// Replace the binary and re-exec it in place of the running agent
+    Exec,
+    /// Replace the binary and exit, for a supervisor (launchd KeepAlive, systemd
+    /// Restart=always) to start the new one
+    Supervisor,
+}
+
+impl ApplyMode {
+    /// Restart through the service manager when there is one, otherwise re-exec
+    pub fn default_for(service: &ServiceManager) -> Self {
+        match service {
+            ServiceManager::None => Self::Exec,
+            _ => Self::Service,
+        }
+    }
+}
+
+impl FromStr for ApplyMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "service" => Ok(Self::Service),
+            "exec" => Ok(Self::Exec),
+            "supervisor" => Ok(Self::Supervisor),
+            other => Err(anyhow!("Unknown update apply mode '{}'", other)),
+        }
+    }
+}
+
+/// Where the agent is installed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallLayout {
+    /// The binary replaced by updates
+    pub binary_path: PathBuf,
+    /// Configuration backed up before updating
+    pub config_dir: PathBuf,
+    /// Script that restores the latest backup
+    pub restore_script: PathBuf,
+    pub service: ServiceManager,
+    pub apply_mode: ApplyMode,
+    /// A/B install slots; `binary_path` is the binary behind their `current` link
+    pub slots: Option<SlotLayout>,
+}
+
+impl InstallLayout {
+    /// Default layout for `service` on this platform. A systemd unit's ExecStart and
+    /// WorkingDirectory give the binary and configuration; the running binary is
+    /// replaced when there is no service manager.
+    pub async fn for_service(service: ServiceManager, update_dir: &Path) -> Self {
+        let mut layout = Self::platform_default(service, update_dir);
+        if let ServiceManager::Systemd { unit } = &layout.service {
+            match systemd_unit_paths(unit).await {
+                Ok((binary, working_dir)) => {
+                    layout.binary_path = binary.unwrap_or(layout.binary_path);
+                    layout.config_dir = working_dir.unwrap_or(layout.config_dir);
+                }
+                Err(e) => warn!("Failed to read paths of {}, using defaults: {:#}", unit, e),
+            }
+        }
+        layout
+    }
+
+    pub fn platform_default(service: ServiceManager, update_dir: &Path) -> Self {
+        let (binary_path, config_dir) = if cfg!(target_os = "macos") {
+            ("/Applications/NodeController/bin/node-controller", "/Library/NodeController/config")
+        } else {
+            ("/usr/local/bin/node-controller-rust", "/etc/node-controller")
+        };
+        let binary_path = match service {
+            ServiceManager::None => std::env::current_exe().unwrap_or_else(|_| PathBuf::from(binary_path)),
+            _ => PathBuf::from(binary_path),
+        };
+        Self {
+            binary_path,
+            config_dir: PathBuf::from(config_dir),
+            restore_script: update_dir.join("restore.sh"),
+            apply_mode: ApplyMode::default_for(&service),
+            service,
+            slots: None,
+        }
+    }
+}
+
+/// Replace the running process with `binary`, keeping the arguments and environment.
+/// Only returns if the exec failed.
+pub fn exec_binary(binary: &Path) -> anyhow::Error {
+    use std::os::unix::process::CommandExt;
+    info!("Restarting as {}", binary.display());
+    let error = std::process::Command::new(binary)
+        .args(std::env::args_os().skip(1))
+        .exec();
+    anyhow!("Failed to exec {}: {}", binary.display(), error)
+}
+
+/// Binary (from ExecStart) and working directory of a systemd unit
+async fn systemd_unit_paths(unit: &str) -> Result<(Option<PathBuf>, Option<PathBuf>)> {
+    let output = Command::new("systemctl")
+        .args(["show", "--property=ExecStart", "--property=WorkingDirectory", unit])
+        .output()
+        .await
+        .context("Failed to execute systemctl show")?;
+    if !output.status.success() {
+        return Err(anyhow!("systemctl show failed: {}", String::from_utf8_lossy(&output.stderr)));
+    }
+    Ok(parse_unit_paths(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse `systemctl show` output such as
+/// `ExecStart={ path=/opt/nc/node-controller ; argv[]=... }` and `WorkingDirectory=/opt/nc`
+fn parse_unit_paths(show: &str) -> (Option<PathBuf>, Option<PathBuf>) {
+    let mut binary = None;
+    let mut working_dir = None;
+    for line in show.lines() {
+        if let Some(exec) = line.strip_prefix("ExecStart=") {
+            binary = exec.split(';')
+                .find_map(|field| field.trim().trim_start_matches('{').trim().strip_prefix("path="))
+                .map(|path| PathBuf::from(path.trim()));
+        } else if let Some(dir) = line.strip_prefix("WorkingDirectory=") {
+            // `!` means "home directory of the user", which is no use here
+            working_dir = Some(dir.trim()).filter(|dir| dir.starts_with('/')).map(PathBuf::from);
+        }
+    }
+    (binary, working_dir)
+}
+
+/// Run `command`; `privileged` commands go through `sudo -n` unless already running as root
+pub async fn run(command: &[String], privileged: bool) -> Result<()> {
+    let (program, args) = command.split_first().ok_or_else(|| anyhow!("Empty command"))?;
+    let is_root = unsafe { libc::geteuid() } == 0;
+    let mut cmd = if is_root || !privileged {
+        Command::new(program)
+    } else {
+        let mut sudo = Command::new("sudo");
+        sudo.arg("-n").arg(program);
+        sudo
+    };
+    let output = cmd.args(args)
+        .output()
+        .await
+        .context(format!("Failed to execute {}", program))?;
+    if !output.status.success() {
+        return Err(anyhow!("{} failed: {}", command.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_service_manager() {
+        assert_eq!("systemd".parse::<ServiceManager>().unwrap(), ServiceManager::Systemd { unit: DEFAULT_SYSTEMD_UNIT.to_string() });
+        assert_eq!(
+            "launchd:org.a14a.node-controller".parse::<ServiceManager>().unwrap(),
+            ServiceManager::Launchd { label: "org.a14a.node-controller".to_string() }
+        );
+        assert_eq!("none".parse::<ServiceManager>().unwrap(), ServiceManager::None);
+        assert!("upstart".parse::<ServiceManager>().is_err());
+
+        assert_eq!(ApplyMode::default_for(&ServiceManager::None), ApplyMode::Exec);
+        assert_eq!(ApplyMode::default_for(&"systemd".parse().unwrap()), ApplyMode::Service);
+        assert_eq!("supervisor".parse::<ApplyMode>().unwrap(), ApplyMode::Supervisor);
+    }
+
+    #[test]
+    fn test_parse_unit_paths() {
+        let show = "ExecStart={ path=/opt/node-controller-rust/target/release/node-controller-rust ; \
+                    argv[]=/opt/node-controller-rust/target/release/node-controller-rust ; ignore_errors=no }\n\
+                    WorkingDirectory=/opt/node-controller-rust\n";
+        assert_eq!(
+            parse_unit_paths(show),
+            (
+                Some(PathBuf::from("/opt/node-controller-rust/target/release/node-controller-rust")),
+                Some(PathBuf::from("/opt/node-controller-rust")),
+            )
+        );
+        assert_eq!(parse_unit_paths("ExecStart=\nWorkingDirectory=!\n"), (None, None));
+    }
+}
@@ -0,0 +1,240 @@
+// src/updater/signature.rs
+//
+// Signature verification of release assets
+// Releases ship a detached minisign signature (`<asset>.minisig`) made with
+// the release signing key. The public keys are embedded at build time, so a
+// compromised release page or mirror cannot swap in its own key. Signatures
+// are checked in-process with openssl's Ed25519, without the minisign tool.
+
+use anyhow::{Result, Context, anyhow, bail};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::{Id, PKey};
+use openssl::sign::Verifier;
+use std::path::Path;
+use log::{debug, info};
+
+/// Extension of detached signature assets
+pub const SIGNATURE_EXTENSION: &str = "minisig";
+
+/// Public keys trusted to sign releases, as printed by `minisign -G`, separated by
+/// commas. Set `UPDATE_SIGNING_KEYS` when building release binaries.
+const EMBEDDED_KEYS: Option<&str> = option_env!("UPDATE_SIGNING_KEYS");
+
+/// A minisign Ed25519 public key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    pub key_id: [u8; 8],
+    pub key: [u8; 32],
+}
+
+impl PublicKey {
+    pub fn parse(encoded: &str) -> Result<Self> {
+        let bytes = BASE64.decode(encoded.trim()).context("Public key is not valid base64")?;
+        if bytes.len() != 42 || &bytes[..2] != b"Ed" {
+            return Err(anyhow!("Not a minisign Ed25519 public key"));
+        }
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&bytes[2..10]);
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&bytes[10..]);
+        Ok(Self { key_id, key })
+    }
+
+    /// Whether `signature` is this key's Ed25519 signature of `message`
+    fn verifies(&self, message: &[u8], signature: &[u8]) -> Result<bool> {
+        let key = PKey::public_key_from_raw_bytes(&self.key, Id::ED25519)?;
+        let mut verifier = Verifier::new_without_digest(&key)?;
+        Ok(verifier.verify_oneshot(signature, message)?)
+    }
+}
+
+/// Keys embedded in this build
+pub fn trusted_keys() -> Result<Vec<PublicKey>> {
+    EMBEDDED_KEYS
+        .unwrap_or_default()
+        .split(',')
+        .filter(|key| !key.trim().is_empty())
+        .map(PublicKey::parse)
+        .collect()
+}
+
+/// A minisign signature file
+struct Signature {
+    /// Whether the file's BLAKE2b-512 hash was signed ("ED") rather than the file ("Ed")
+    prehashed: bool,
+    key_id: [u8; 8],
+    signature: Vec<u8>,
+    trusted_comment: String,
+    /// Signature of `signature` followed by the trusted comment
+    global_signature: Vec<u8>,
+}
+
+impl Signature {
+    fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("untrusted comment:"));
+        let line = lines.next().ok_or_else(|| anyhow!("Signature file is empty"))?;
+        let bytes = BASE64.decode(line).context("Signature is not valid base64")?;
+        if bytes.len() != 74 || (&bytes[..2] != b"Ed" && &bytes[..2] != b"ED") {
+            bail!("Not a minisign Ed25519 signature");
+        }
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&bytes[2..10]);
+        let trusted_comment = lines.next()
+            .and_then(|line| line.strip_prefix("trusted comment: "))
+            .ok_or_else(|| anyhow!("Signature has no trusted comment"))?;
+        let global_signature = lines.next()
+            .map(|line| BASE64.decode(line))
+            .transpose()
+            .context("Global signature is not valid base64")?
+            .filter(|signature| signature.len() == 64)
+            .ok_or_else(|| anyhow!("Signature has no global signature"))?;
+        Ok(Self {
+            prehashed: &bytes[..2] == b"ED",
+            key_id,
+            signature: bytes[10..].to_vec(),
+            trusted_comment: trusted_comment.to_string(),
+            global_signature,
+        })
+    }
+}
+
+/// Verify `file` against the detached signature at `signature_path` with one of `keys`
+pub async fn verify(file: &Path, signature_path: &Path, keys: &[PublicKey]) -> Result<()> {
+    if keys.is_empty() {
+        return Err(anyhow!("This build has no embedded update signing keys"));
+    }
+    let signature = tokio::fs::read_to_string(signature_path).await
+        .with_context(|| format!("Failed to read signature {}", signature_path.display()))?;
+    let signature = Signature::parse(&signature)?;
+    let key = keys.iter()
+        .find(|key| key.key_id == signature.key_id)
+        .ok_or_else(|| anyhow!("Release was signed with an untrusted key ({})", hex(&signature.key_id)))?;
+    debug!("Verifying {} with key {}", file.display(), hex(&key.key_id));
+
+    let contents = tokio::fs::read(file).await
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    verify_contents(&contents, &signature, key)?;
+    info!("Release signature verified with key {}", hex(&key.key_id));
+    Ok(())
+}
+
+/// Check the signature of `contents` and the signature of the trusted comment
+fn verify_contents(contents: &[u8], signature: &Signature, key: &PublicKey) -> Result<()> {
+    let message = if signature.prehashed {
+        let blake2b = MessageDigest::from_name("BLAKE2b512")
+            .ok_or_else(|| anyhow!("This openssl has no BLAKE2b-512"))?;
+        hash(blake2b, contents)?.to_vec()
+    } else {
+        contents.to_vec()
+    };
+    if !key.verifies(&message, &signature.signature)? {
+        bail!("Signature verification failed: the file doesn't match its signature");
+    }
+    let comment = [signature.signature.as_slice(), signature.trusted_comment.as_bytes()].concat();
+    if !key.verifies(&comment, &signature.global_signature)? {
+        bail!("Signature verification failed: the trusted comment was altered");
+    }
+    Ok(())
+}
+
+/// Key IDs are shown in upper-case hex, least significant byte first, like minisign does
+fn hex(key_id: &[u8; 8]) -> String {
+    key_id.iter().rev().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(prefix: &[u8], key_id: [u8; 8], len: usize) -> String {
+        let mut bytes = prefix.to_vec();
+        bytes.extend_from_slice(&key_id);
+        bytes.resize(len, 7);
+        BASE64.encode(bytes)
+    }
+
+    #[test]
+    fn test_parse_key_and_signature() {
+        let key_id = [1, 2, 3, 4, 5, 6, 7, 8];
+        let key = PublicKey::parse(&encode(b"Ed", key_id, 42)).unwrap();
+        assert_eq!(key.key_id, key_id);
+        assert_eq!(hex(&key.key_id), "0807060504030201");
+        assert!(PublicKey::parse(&encode(b"Ed", key_id, 40)).is_err());
+
+        let signature = format!(
+            "untrusted comment: signature from minisign secret key\n{}\ntrusted comment: timestamp:1700000000\n{}\n",
+            encode(b"ED", key_id, 74),
+            BASE64.encode([0u8; 64])
+        );
+        let parsed = Signature::parse(&signature).unwrap();
+        assert_eq!(parsed.key_id, key_id);
+        assert!(parsed.prehashed);
+        assert_eq!(parsed.trusted_comment, "timestamp:1700000000");
+        assert!(Signature::parse("untrusted comment: x\n").is_err());
+        assert!(Signature::parse(&signature.replace("trusted comment: ", "comment: ")).is_err());
+    }
+
+    /// Sign `contents` the way `minisign -S` does, returning the public key and signature file
+    fn sign(contents: &[u8], prehashed: bool, comment: &str) -> (PublicKey, String) {
+        let secret = PKey::generate_ed25519().unwrap();
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&secret.raw_public_key().unwrap());
+        let public = PublicKey { key_id: [9, 8, 7, 6, 5, 4, 3, 2], key };
+
+        let sign = |message: &[u8]| {
+            openssl::sign::Signer::new_without_digest(&secret).unwrap().sign_oneshot_to_vec(message).unwrap()
+        };
+        let message = if prehashed {
+            hash(MessageDigest::from_name("BLAKE2b512").unwrap(), contents).unwrap().to_vec()
+        } else {
+            contents.to_vec()
+        };
+        let signature = sign(&message);
+        let global = sign(&[signature.as_slice(), comment.as_bytes()].concat());
+        let line = [if prehashed { b"ED" } else { b"Ed" }.as_slice(), &public.key_id, &signature].concat();
+        let file = format!(
+            "untrusted comment: signature from minisign secret key\n{}\ntrusted comment: {}\n{}\n",
+            BASE64.encode(line), comment, BASE64.encode(global)
+        );
+        (public, file)
+    }
+
+    #[tokio::test]
+    async fn test_verify_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("agent");
+        let signature_path = dir.path().join("agent.minisig");
+        std::fs::write(&file, b"release binary").unwrap();
+
+        for prehashed in [true, false] {
+            let (key, signature) = sign(b"release binary", prehashed, "timestamp:1700000000");
+            std::fs::write(&signature_path, &signature).unwrap();
+            let keys = [key];
+            verify(&file, &signature_path, &keys).await.unwrap();
+
+            let other = PublicKey { key: sign(b"", false, "").0.key, ..keys[0].clone() };
+            assert!(verify(&file, &signature_path, &[other]).await.is_err());
+
+            let altered = signature.replace("timestamp:1700000000", "timestamp:1800000000");
+            std::fs::write(&signature_path, altered).unwrap();
+            let err = verify(&file, &signature_path, &keys).await.unwrap_err();
+            assert!(err.to_string().contains("trusted comment"));
+
+            std::fs::write(&signature_path, &signature).unwrap();
+            std::fs::write(&file, b"tampered binary").unwrap();
+            let err = verify(&file, &signature_path, &keys).await.unwrap_err();
+            assert!(err.to_string().contains("doesn't match"));
+            std::fs::write(&file, b"release binary").unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_requires_keys() {
+        let err = verify(Path::new("missing"), Path::new("missing.minisig"), &[]).await.unwrap_err();
+        assert!(err.to_string().contains("no embedded update signing keys"));
+    }
+}
@@ -0,0 +1,148 @@
+// src/updater/slots.rs
+//
+// A/B installs
+// Two install directories, `a` and `b`, under a common root, and a `current`
+// symlink pointing at the active one. The service runs `current/<binary>`.
+// Updates are staged into the inactive slot and activated by swapping the
+// symlink, so rolling back is swapping it back rather than restoring a backup.
+
+use anyhow::{Result, Context, anyhow};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use log::info;
+
+const SLOTS: [&str; 2] = ["a", "b"];
+const CURRENT_LINK: &str = "current";
+const BINARY_NAME: &str = "node-controller";
+/// File in a slot holding the version installed there
+const VERSION_FILE: &str = "VERSION";
+
+/// Root directory holding the two install slots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotLayout {
+    pub root: PathBuf,
+}
+
+impl SlotLayout {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Binary installed in `slot`
+    pub fn slot_binary(&self, slot: &str) -> PathBuf {
+        self.root.join(slot).join(BINARY_NAME)
+    }
+
+    /// Path the service runs, through the `current` symlink
+    pub fn binary_path(&self) -> PathBuf {
+        self.root.join(CURRENT_LINK).join(BINARY_NAME)
+    }
+
+    /// Slot the `current` symlink points at
+    pub async fn active_slot(&self) -> Result<Option<&'static str>> {
+        match fs::read_link(self.root.join(CURRENT_LINK)).await {
+            Ok(target) => Ok(SLOTS.iter().copied().find(|slot| target == Path::new(slot))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read the current install slot"),
+        }
+    }
+
+    /// The slot that is not active
+    async fn inactive_slot(&self) -> Result<&'static str> {
+        Ok(match self.active_slot().await? {
+            Some(active) if active == SLOTS[0] => SLOTS[1],
+            _ => SLOTS[0],
+        })
+    }
+
+    /// Version installed in `slot`
+    pub async fn slot_version(&self, slot: &str) -> Option<String> {
+        fs::read_to_string(self.root.join(slot).join(VERSION_FILE)).await.ok()
+            .map(|version| version.trim().to_string())
+    }
+
+    /// Put the running binary into the first slot when the layout has not been set up yet
+    pub async fn ensure_initialized(&self, running_binary: &Path, version: &str) -> Result<()> {
+        if self.active_slot().await?.is_some() {
+            return Ok(());
+        }
+        info!("Setting up A/B install slots in {}", self.root.display());
+        self.stage_into(SLOTS[0], running_binary, version).await?;
+        self.activate(SLOTS[0]).await
+    }
+
+    /// Copy `binary` into the inactive slot, replacing what was there. Returns the slot.
+    pub async fn stage(&self, binary: &Path, version: &str) -> Result<&'static str> {
+        let slot = self.inactive_slot().await?;
+        self.stage_into(slot, binary, version).await?;
+        Ok(slot)
+    }
+
+    async fn stage_into(&self, slot: &str, binary: &Path, version: &str) -> Result<()> {
+        let dir = self.root.join(slot);
+        if dir.exists() {
+            fs::remove_dir_all(&dir).await
+                .context(format!("Failed to clear install slot {}", dir.display()))?;
+        }
+        fs::create_dir_all(&dir).await
+            .context(format!("Failed to create install slot {}", dir.display()))?;
+        fs::copy(binary, self.slot_binary(slot)).await
+            .context(format!("Failed to copy new binary to install slot {}", dir.display()))?;
+        fs::write(dir.join(VERSION_FILE), version).await?;
+        Ok(())
+    }
+
+    /// Point `current` at `slot`, atomically replacing the previous link
+    pub async fn activate(&self, slot: &str) -> Result<()> {
+        if !self.slot_binary(slot).exists() {
+            return Err(anyhow!("Install slot {} has no binary", slot));
+        }
+        let staged_link = self.root.join(format!("{}.new", CURRENT_LINK));
+        let _ = fs::remove_file(&staged_link).await;
+        fs::symlink(slot, &staged_link).await
+            .context("Failed to create install slot link")?;
+        fs::rename(&staged_link, self.root.join(CURRENT_LINK)).await
+            .context("Failed to switch install slot")?;
+        info!("Activated install slot {}", slot);
+        Ok(())
+    }
+
+    /// Switch back to the other slot. Returns the version it holds.
+    pub async fn switch_back(&self) -> Result<String> {
+        let previous = self.inactive_slot().await?;
+        self.activate(previous).await
+            .context("No previous version to switch back to")?;
+        Ok(self.slot_version(previous).await.unwrap_or_else(|| "unknown".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_slots_switch() {
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("binary");
+        std::fs::write(&binary, b"v1").unwrap();
+        let slots = SlotLayout::new(dir.path().join("slots"));
+        std::fs::create_dir_all(&slots.root).unwrap();
+
+        slots.ensure_initialized(&binary, "0.1.0").await.unwrap();
+        assert_eq!(slots.active_slot().await.unwrap(), Some("a"));
+        // Switching back needs something in the other slot
+        assert!(slots.switch_back().await.is_err());
+
+        std::fs::write(&binary, b"v2").unwrap();
+        let staged = slots.stage(&binary, "0.2.0").await.unwrap();
+        assert_eq!(staged, "b");
+        slots.activate(staged).await.unwrap();
+        assert_eq!(std::fs::read(slots.binary_path()).unwrap(), b"v2");
+
+        assert_eq!(slots.switch_back().await.unwrap(), "0.1.0");
+        assert_eq!(std::fs::read(slots.binary_path()).unwrap(), b"v1");
+        // Already set up, so the active slot is left alone
+        slots.ensure_initialized(&binary, "0.2.0").await.unwrap();
+        assert_eq!(slots.active_slot().await.unwrap(), Some("a"));
+    }
+}
@@ -0,0 +1,372 @@
+// src/updater/source.rs
+//
+// Where releases come from
+// GitHub releases are the default; deployments without GitHub access can
+// publish a static JSON manifest over HTTPS or in an S3-compatible bucket.
+
+use anyhow::{Result, Context, anyhow};
+use async_trait::async_trait;
+use reqwest::Url;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use log::{debug, info, warn};
+use crate::updater::github::{self, GithubReleaseInfo, GithubToken};
+use crate::updater::patch::{PatchAsset, PatchFormat};
+use crate::updater::s3::S3Config;
+use crate::updater::{UpdateConfig, Version};
+use crate::updater::version::{self, VersionPin};
+
+/// A place to look for new releases
+#[async_trait]
+pub trait UpdateSource: Send + Sync {
+    /// Short description used in logs
+    fn name(&self) -> String;
+
+    /// Newest release on the channel with `tag_prefix` that should replace `current_version`,
+    /// within `pin` when the version is pinned
+    async fn latest_release(&self, tag_prefix: &str, current_version: &Version, pin: Option<&VersionPin>) -> Result<Option<GithubReleaseInfo>>;
+
+    /// How often to check, for sources that are cheaper to poll than the configured interval
+    fn poll_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Configured release source
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceConfig {
+    /// GitHub releases of `UpdateConfig::repository`
+    GitHub,
+    /// JSON manifest at a URL
+    Manifest(Url),
+    /// JSON manifest and assets in an S3-compatible bucket
+    S3(S3Config),
+    /// Release bundles in a local directory, e.g. a USB drive or network share
+    LocalDir(PathBuf),
+}
+
+/// Build the source selected in `config`
+pub fn from_config(config: &UpdateConfig) -> Box<dyn UpdateSource> {
+    match &config.source {
+        SourceConfig::GitHub => Box::new(GithubSource {
+            repository: config.repository.clone(),
+            token: config.github_token.clone(),
+        }),
+        SourceConfig::Manifest(url) => Box::new(ManifestSource { url: url.clone() }),
+        SourceConfig::S3(s3) => Box::new(s3.clone()),
+        SourceConfig::LocalDir(dir) => Box::new(LocalDirSource { dir: dir.clone() }),
+    }
+}
+
+/// GitHub releases tagged `{channel}-{version}`
+pub struct GithubSource {
+    pub repository: String,
+    pub token: Option<GithubToken>,
+}
+
+#[async_trait]
+impl UpdateSource for GithubSource {
+    fn name(&self) -> String {
+        format!("GitHub repository {}", self.repository)
+    }
+
+    async fn latest_release(&self, tag_prefix: &str, current_version: &Version, pin: Option<&VersionPin>) -> Result<Option<GithubReleaseInfo>> {
+        github::check_for_updates(&self.repository, tag_prefix, current_version, pin, self.token.as_ref()).await
+    }
+}
+
+/// Static release index served over HTTP(S)
+pub struct ManifestSource {
+    pub url: Url,
+}
+
+#[async_trait]
+impl UpdateSource for ManifestSource {
+    fn name(&self) -> String {
+        format!("manifest {}", self.url)
+    }
+
+    async fn latest_release(&self, tag_prefix: &str, current_version: &Version, pin: Option<&VersionPin>) -> Result<Option<GithubReleaseInfo>> {
+        let manifest = fetch_manifest(&self.url).await?;
+        manifest.latest_release(tag_prefix, current_version, pin, &|location: &str| {
+            self.url.join(location).map(String::from).map_err(Into::into)
+        })
+    }
+}
+
+/// Release bundles in a directory: a `manifest.json` at the top and/or one in each
+/// subdirectory, with asset locations relative to the manifest. Checked every
+/// `LOCAL_POLL_INTERVAL` so media is picked up soon after it is mounted.
+pub struct LocalDirSource {
+    pub dir: PathBuf,
+}
+
+/// How often a local directory is checked for new bundles
+const LOCAL_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+impl LocalDirSource {
+    /// Manifest files in the directory; a missing directory (media not mounted) has none
+    fn manifest_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.dir.join("manifest.json")];
+        if let Ok(entries) = std::fs::read_dir(&self.dir) {
+            let mut bundles: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path().join("manifest.json"))
+                .collect();
+            bundles.sort();
+            paths.extend(bundles);
+        }
+        paths.retain(|path| path.is_file());
+        paths
+    }
+}
+
+#[async_trait]
+impl UpdateSource for LocalDirSource {
+    fn name(&self) -> String {
+        format!("directory {}", self.dir.display())
+    }
+
+    async fn latest_release(&self, tag_prefix: &str, current_version: &Version, pin: Option<&VersionPin>) -> Result<Option<GithubReleaseInfo>> {
+        let mut latest: Option<GithubReleaseInfo> = None;
+        for path in self.manifest_paths() {
+            let manifest: Manifest = match std::fs::read(&path).map_err(anyhow::Error::from)
+                .and_then(|data| serde_json::from_slice(&data).map_err(Into::into))
+            {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    warn!("Skipping release bundle {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let base = Url::from_file_path(&path).map_err(|_| anyhow!("Invalid bundle path {}", path.display()))?;
+            let release = manifest.latest_release(tag_prefix, current_version, pin, &|location: &str| {
+                base.join(location).map(String::from).map_err(Into::into)
+            })?;
+            let newer = |release: &GithubReleaseInfo| -> bool {
+                let Some(latest) = &latest else { return true };
+                match (Version::from_str(&release.version), Version::from_str(&latest.version)) {
+                    (Ok(version), Ok(latest)) => version > latest,
+                    _ => false,
+                }
+            };
+            if let Some(release) = release.filter(|release| newer(release)) {
+                latest = Some(release);
+            }
+        }
+        Ok(latest)
+    }
+
+    fn poll_interval(&self) -> Option<Duration> {
+        Some(LOCAL_POLL_INTERVAL)
+    }
+}
+
+/// Download and parse a release manifest
+pub async fn fetch_manifest(url: &Url) -> Result<Manifest> {
+    let client = crate::proxy::client_builder()
+        .user_agent("node-controller-updater")
+        .build()?;
+    debug!("Fetching release manifest from {}", url);
+
+    let response = client.get(url.clone())
+        .send()
+        .await
+        .context("Failed to fetch release manifest")?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Release manifest returned error status {}", response.status()));
+    }
+    response.json().await.context("Failed to parse release manifest")
+}
+
+/// Release index, e.g.
+/// `{"releases": [{"version": "0.3.0", "channel": "stable", "assets": [{"url": "node-controller-macos.zip", ...}]}]}`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manifest {
+    pub releases: Vec<ManifestRelease>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestRelease {
+    pub version: String,
+    pub channel: String,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub published_at: String,
+    #[serde(default)]
+    pub notes: String,
+    /// Staged rollout: share of nodes (0-100) that should install the release
+    #[serde(default)]
+    pub rollout_percent: Option<u8>,
+    /// Install outside the maintenance window
+    #[serde(default)]
+    pub critical: bool,
+    pub assets: Vec<ManifestAsset>,
+    #[serde(default)]
+    pub patches: Vec<ManifestPatch>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestAsset {
+    /// Operating system as in `std::env::consts::OS`; assets without one fit every platform
+    #[serde(default)]
+    pub os: Option<String>,
+    /// Absolute URL, or a location relative to the manifest
+    pub url: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestPatch {
+    /// Version the patch applies to
+    pub from: String,
+    pub format: PatchFormat,
+    #[serde(default)]
+    pub os: Option<String>,
+    pub url: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// SHA256 of the binary the patch produces
+    #[serde(default)]
+    pub target_sha256: Option<String>,
+}
+
+impl Manifest {
+    /// Newest release for this platform on the channel, with asset locations
+    /// turned into download URLs by `resolve`
+    pub fn latest_release(
+        &self,
+        tag_prefix: &str,
+        current_version: &Version,
+        pin: Option<&VersionPin>,
+        resolve: &(dyn Fn(&str) -> Result<String> + Sync),
+    ) -> Result<Option<GithubReleaseInfo>> {
+        let os = std::env::consts::OS;
+        let fits = |asset_os: &Option<String>| asset_os.as_deref().is_none_or(|asset_os| asset_os == os);
+        let current = current_version.to_string();
+
+        let latest = self.releases.iter()
+            .filter(|release| release.channel == tag_prefix)
+            .filter_map(|release| {
+                let version = match Version::from_str(&release.version) {
+                    Ok(version) => version,
+                    Err(e) => {
+                        debug!("Skipping manifest release {}: invalid version format: {}", release.version, e);
+                        return None;
+                    }
+                };
+                version::is_candidate(&version, current_version, pin).then_some((version, release))
+            })
+            .filter(|(_, release)| release.assets.iter().any(|asset| fits(&asset.os)))
+            .max_by(|(a, _), (b, _)| a.cmp(b));
+        let Some((_, release)) = latest else {
+            debug!("No newer version found");
+            return Ok(None);
+        };
+
+        let asset = release.assets.iter().find(|asset| fits(&asset.os))
+            .ok_or_else(|| anyhow!("Release {} has no asset for {}", release.version, os))?;
+        let patch = release.patches.iter()
+            .find(|patch| patch.from == current && fits(&patch.os))
+            .map(|patch| -> Result<Box<PatchAsset>> {
+                Ok(Box::new(PatchAsset {
+                    format: patch.format,
+                    download_url: resolve(&patch.url)?,
+                    size: patch.size,
+                    signature_url: patch.signature.as_deref().map(resolve).transpose()?,
+                    target_sha256: patch.target_sha256.clone(),
+                }))
+            })
+            .transpose()?;
+
+        let tag_name = format!("{}-{}", release.channel, release.version);
+        info!("Found update: {} ({})", release.version, tag_name);
+        Ok(Some(GithubReleaseInfo {
+            version: release.version.clone(),
+            name: tag_name.clone(),
+            tag_name,
+            body: release.notes.clone(),
+            prerelease: release.prerelease,
+            published_at: release.published_at.clone(),
+            download_url: resolve(&asset.url)?,
+            size: asset.size,
+            sha256: asset.sha256.clone(),
+            checksums: None,
+            signature_url: asset.signature.as_deref().map(resolve).transpose()?,
+            patch,
+            rollout_percent: release.rollout_percent.map(|percent| percent.min(100)),
+            critical: release.critical,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_latest_release() {
+        let manifest: Manifest = serde_json::from_value(serde_json::json!({
+            "releases": [
+                { "version": "0.3.0", "channel": "stable", "assets": [
+                    { "url": "stable/0.3.0/node-controller.zip", "size": 100, "signature": "stable/0.3.0/node-controller.zip.minisig" }
+                ], "patches": [
+                    { "from": "0.2.0", "format": "zstd", "url": "stable/0.3.0/from-0.2.0.zst", "size": 10 }
+                ] },
+                { "version": "0.4.0", "channel": "beta", "assets": [{ "url": "beta/0.4.0/node-controller.zip" }] },
+                { "version": "0.5.0", "channel": "stable", "assets": [{ "os": "windows", "url": "node-controller.exe" }] },
+                { "version": "0.1.0", "channel": "stable", "assets": [{ "url": "old.zip" }] }
+            ]
+        })).unwrap();
+        let base = Url::parse("https://updates.example.com/manifest.json").unwrap();
+        let resolve = |location: &str| base.join(location).map(String::from).map_err(Into::into);
+
+        let current = Version::from_str("0.2.0").unwrap();
+        let release = manifest.latest_release("stable", &current, None, &resolve).unwrap().unwrap();
+        assert_eq!(release.version, "0.3.0");
+        assert_eq!(release.tag_name, "stable-0.3.0");
+        assert_eq!(release.download_url, "https://updates.example.com/stable/0.3.0/node-controller.zip");
+        assert_eq!(release.signature_url.as_deref(), Some("https://updates.example.com/stable/0.3.0/node-controller.zip.minisig"));
+        assert_eq!(release.patch.unwrap().download_url, "https://updates.example.com/stable/0.3.0/from-0.2.0.zst");
+
+        let current = Version::from_str("0.3.0").unwrap();
+        assert!(manifest.latest_release("stable", &current, None, &resolve).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_local_dir_bundles() {
+        let dir = tempfile::tempdir().unwrap();
+        for version in ["0.3.0", "0.4.0"] {
+            let bundle = dir.path().join(format!("stable-{}", version));
+            std::fs::create_dir_all(&bundle).unwrap();
+            std::fs::write(bundle.join("manifest.json"), serde_json::json!({
+                "releases": [{ "version": version, "channel": "stable", "assets": [{ "url": "node-controller.zip" }] }]
+            }).to_string()).unwrap();
+        }
+        let source = LocalDirSource { dir: dir.path().to_path_buf() };
+
+        let current = Version::from_str("0.2.0").unwrap();
+        let release = source.latest_release("stable", &current, None).await.unwrap().unwrap();
+        assert_eq!(release.version, "0.4.0");
+        assert_eq!(
+            release.download_url,
+            Url::from_file_path(dir.path().join("stable-0.4.0/node-controller.zip")).unwrap().as_str()
+        );
+
+        let missing = LocalDirSource { dir: dir.path().join("not-mounted") };
+        assert!(missing.latest_release("stable", &current, None).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,88 @@
+// src/updater/throttle.rs
+//
+// Download rate limiting
+// A token bucket keeps update downloads below a configured rate so they
+// don't saturate the link used for file transfers and metrics.
+
+use std::time::{Duration, Instant};
+
+/// Maximum download rate with an allowance for short bursts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Sustained rate in bytes per second
+    pub bytes_per_sec: u64,
+    /// Bytes that may be read at full speed before the rate applies
+    pub burst_bytes: u64,
+}
+
+impl RateLimit {
+    /// Limit to `kib_per_sec` KiB/s with a burst of `burst_kib` KiB (one second's worth if None)
+    pub fn from_kib(kib_per_sec: u64, burst_kib: Option<u64>) -> Option<Self> {
+        if kib_per_sec == 0 {
+            return None;
+        }
+        Some(Self {
+            bytes_per_sec: kib_per_sec * 1024,
+            burst_bytes: burst_kib.unwrap_or(kib_per_sec) * 1024,
+        })
+    }
+}
+
+/// Token bucket that tells a reader how long to pause after each chunk
+#[derive(Debug)]
+pub struct Throttle {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Throttle {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst_bytes as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Account for `bytes` read at `now` and return how long to wait before reading more
+    fn reserve(&mut self, bytes: usize, now: Instant) -> Duration {
+        let rate = self.limit.bytes_per_sec as f64;
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * rate).min(self.limit.burst_bytes as f64);
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-self.tokens / rate)
+    }
+
+    /// Wait as long as needed after reading `bytes`
+    pub async fn consume(&mut self, bytes: usize) {
+        let delay = self.reserve(bytes, Instant::now());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle_allows_burst_then_limits() {
+        let limit = RateLimit::from_kib(100, Some(200)).unwrap();
+        let mut throttle = Throttle::new(limit);
+        let start = throttle.last_refill;
+
+        // The burst goes through without waiting
+        assert_eq!(throttle.reserve(200 * 1024, start), Duration::ZERO);
+        // Then 100 KiB take a second
+        assert_eq!(throttle.reserve(100 * 1024, start), Duration::from_secs(1));
+        // Having waited that second, the bucket is empty again but not in debt
+        assert_eq!(throttle.reserve(0, start + Duration::from_secs(1)), Duration::ZERO);
+        assert_eq!(RateLimit::from_kib(0, None), None);
+    }
+}
@@ -187,6 +187,40 @@ impl Ord for Version {
     }
 }
 
+/// Highest version that may be installed, e.g. to hold nodes back from a bad release
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionPin {
+    pub max_version: Version,
+    /// Whether the pin may take the node below the installed version
+    pub allow_downgrade: bool,
+}
+
+impl VersionPin {
+    /// Pin to `max_version`. A pin below `current` is refused unless `force` is set,
+    /// in which case the newest release at or below the pin is installed.
+    pub fn new(max_version: Version, current: &Version, force: bool) -> Result<Self> {
+        if max_version < *current && !force {
+            return Err(anyhow!(
+                "Pinning to {} would downgrade from {}; set force to allow downgrades",
+                max_version, current
+            ));
+        }
+        Ok(Self { max_version, allow_downgrade: force })
+    }
+}
+
+/// Whether a release of `version` should replace `current`: any newer version, or
+/// with a pin, versions up to the pin and older ones when the pin forces a downgrade
+pub fn is_candidate(version: &Version, current: &Version, pin: Option<&VersionPin>) -> bool {
+    match pin {
+        None => version > current,
+        Some(pin) => {
+            let downgrade = pin.allow_downgrade && *current > pin.max_version;
+            *version <= pin.max_version && (version > current || downgrade)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -257,4 +291,26 @@ mod tests {
         let v = Version::new(1, 2, 3, Some("alpha.1".to_string()), Some("20230101".to_string()));
         assert_eq!(v.to_string(), "1.2.3-alpha.1+20230101");
     }
-} 
\ No newline at end of file
+    
+    #[test]
+    fn test_version_pin() {
+        let v = |s: &str| Version::from_str(s).unwrap();
+        let current = v("1.2.0");
+        assert!(is_candidate(&v("1.3.0"), &current, None));
+        assert!(!is_candidate(&v("1.1.0"), &current, None));
+
+        let pin = VersionPin::new(v("1.2.5"), &current, false).unwrap();
+        assert!(is_candidate(&v("1.2.5"), &current, Some(&pin)));
+        assert!(!is_candidate(&v("1.3.0"), &current, Some(&pin)));
+        assert!(!is_candidate(&v("1.1.0"), &current, Some(&pin)));
+
+        // Pins below the installed version need force, and then allow downgrades to the pin
+        assert!(VersionPin::new(v("1.1.0"), &current, false).is_err());
+        let pin = VersionPin::new(v("1.1.0"), &current, true).unwrap();
+        assert!(is_candidate(&v("1.1.0"), &current, Some(&pin)));
+        assert!(is_candidate(&v("1.0.9"), &current, Some(&pin)));
+        assert!(!is_candidate(&v("1.1.1"), &current, Some(&pin)));
+        // Once at or below the pin, forcing doesn't keep downgrading
+        assert!(!is_candidate(&v("1.0.9"), &v("1.1.0"), Some(&pin)));
+    }
+}
@@ -0,0 +1,111 @@
+// src/updater/window.rs
+//
+// Maintenance windows
+// Automatic updates are only installed inside the configured window, e.g.
+// "02:00-05:00 weekdays" in local time. Windows may wrap past midnight.
+
+use anyhow::{Result, anyhow};
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+use std::str::FromStr;
+
+/// Times of day (and optionally days of the week) when updates may be installed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Days the window opens on; every day when empty
+    pub days: Vec<Weekday>,
+}
+
+impl MaintenanceWindow {
+    /// Whether `now` (local time) falls inside the window. A window that wraps past
+    /// midnight belongs to the day it opened on.
+    pub fn contains(&self, now: NaiveDateTime) -> bool {
+        let time = now.time();
+        let opens_on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        if self.start <= self.end {
+            time >= self.start && time < self.end && opens_on(now.weekday())
+        } else {
+            (time >= self.start && opens_on(now.weekday())) || (time < self.end && opens_on(now.weekday().pred()))
+        }
+    }
+}
+
+impl FromStr for MaintenanceWindow {
+    type Err = anyhow::Error;
+
+    /// `HH:MM-HH:MM [days]`, where days is `daily`, `weekdays`, `weekends`, a range
+    /// like `mon-fri` or a list like `sat,sun`
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().replace(['–', '—'], "-");
+        let (times, days) = s.split_once(char::is_whitespace).unwrap_or((s.as_str(), ""));
+        let (start, end) = times.split_once('-')
+            .ok_or_else(|| anyhow!("Maintenance window '{}' must look like 02:00-05:00", s))?;
+        let time = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M")
+            .map_err(|_| anyhow!("Invalid time '{}' in maintenance window", t));
+        let window = Self { start: time(start)?, end: time(end)?, days: parse_days(days.trim())? };
+        if window.start == window.end {
+            return Err(anyhow!("Maintenance window '{}' is empty", s));
+        }
+        Ok(window)
+    }
+}
+
+fn parse_days(spec: &str) -> Result<Vec<Weekday>> {
+    use Weekday::*;
+    let day = |name: &str| Weekday::from_str(name.trim()).map_err(|_| anyhow!("Invalid day '{}' in maintenance window", name));
+    match spec.to_ascii_lowercase().as_str() {
+        "" | "daily" => Ok(Vec::new()),
+        "weekdays" => Ok(vec![Mon, Tue, Wed, Thu, Fri]),
+        "weekends" => Ok(vec![Sat, Sun]),
+        spec => {
+            let mut days = Vec::new();
+            for part in spec.split(',') {
+                match part.split_once('-') {
+                    Some((first, last)) => {
+                        let (mut current, last) = (day(first)?, day(last)?);
+                        days.push(current);
+                        while current != last {
+                            current = current.succ();
+                            days.push(current);
+                        }
+                    }
+                    None => days.push(day(part)?),
+                }
+            }
+            Ok(days)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 2024-01-01 was a Monday
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_window_contains() {
+        let window: MaintenanceWindow = "02:00–05:00 weekdays".parse().unwrap();
+        assert!(window.contains(at(1, 2, 0)));
+        assert!(window.contains(at(5, 4, 59)));
+        assert!(!window.contains(at(1, 5, 0)));
+        assert!(!window.contains(at(6, 3, 0))); // Saturday
+
+        // Friday night into Saturday morning belongs to Friday
+        let window: MaintenanceWindow = "22:00-02:00 fri".parse().unwrap();
+        assert!(window.contains(at(5, 23, 0)));
+        assert!(window.contains(at(6, 1, 0)));
+        assert!(!window.contains(at(5, 1, 0)));
+
+        let window: MaintenanceWindow = "01:00-03:00 sat-mon".parse().unwrap();
+        assert_eq!(window.days, vec![Weekday::Sat, Weekday::Sun, Weekday::Mon]);
+        assert!("02:00".parse::<MaintenanceWindow>().is_err());
+        assert!("02:00-02:00".parse::<MaintenanceWindow>().is_err());
+        assert!("02:00-05:00 someday".parse::<MaintenanceWindow>().is_err());
+    }
+}